@@ -0,0 +1,76 @@
+//! 比较哈希连接和嵌套循环连接的性能
+//!
+//! 两张表均有 `n` 行，`b.a_id` 是 `a.id` 的一个排列，连接结果也是 `n` 行。
+//! 嵌套循环连接的代价为 O(n²)，`n` 为 100k 时需要比较 10^10 次，因此只在较小的规模上运行，
+//! 对比相同规模下两者的耗时即可看出数量级的差异。
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use sqldb::{
+    executor::{Executor, ResultSet},
+    parser::Parser,
+    storage::MemoryStorage,
+    Engine,
+};
+
+/// 每条 INSERT 语句插入的行数
+const BATCH_SIZE: usize = 1000;
+
+/// 创建 `a` 和 `b` 两张表，并各插入 `n` 行数据
+fn setup(n: usize) -> Executor<MemoryStorage> {
+    let engine = Engine::new(MemoryStorage::new());
+    let executor = Executor::from_engine(&engine).unwrap();
+    let execute = |sql: &str| executor.execute(Parser::new(sql).parse().unwrap()).unwrap();
+
+    execute("CREATE TABLE a (id INT PRIMARY KEY, v INT);");
+    execute("CREATE TABLE b (id INT PRIMARY KEY, a_id INT);");
+    for start in (0..n).step_by(BATCH_SIZE) {
+        let end = (start + BATCH_SIZE).min(n);
+        let values = |f: &dyn Fn(usize) -> usize| {
+            (start..end)
+                .map(|i| format!("({}, {})", i, f(i)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        execute(&format!("INSERT INTO a VALUES {};", values(&|i| i * 2)));
+        // 7919 是质数，和 n 互质时 i * 7919 % n 是 0..n 的一个排列
+        execute(&format!(
+            "INSERT INTO b VALUES {};",
+            values(&|i| i * 7919 % n)
+        ));
+    }
+    executor
+}
+
+/// 执行查询，返回结果的行数
+fn query(executor: &Executor<MemoryStorage>, sql: &str) -> usize {
+    match executor.execute(Parser::new(sql).parse().unwrap()).unwrap() {
+        ResultSet::Query { rows, .. } => rows.len(),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+fn bench_join(c: &mut Criterion) {
+    let mut group = c.benchmark_group("join");
+    group.sample_size(10);
+
+    // `NOT NOT` 使计划器无法识别等值条件，从而使用嵌套循环连接
+    let hash_join = "SELECT a.id, b.id FROM a JOIN b ON a.id = b.a_id;";
+    let nested_loop_join = "SELECT a.id, b.id FROM a JOIN b ON NOT NOT (a.id = b.a_id);";
+
+    for n in [1_000, 2_000, 100_000] {
+        let executor = setup(n);
+        group.bench_with_input(BenchmarkId::new("hash", n), &n, |b, &n| {
+            b.iter(|| assert_eq!(query(&executor, hash_join), n))
+        });
+        if n <= 2_000 {
+            group.bench_with_input(BenchmarkId::new("nested_loop", n), &n, |b, &n| {
+                b.iter(|| assert_eq!(query(&executor, nested_loop_join), n))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_join);
+criterion_main!(benches);
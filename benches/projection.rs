@@ -0,0 +1,83 @@
+//! 比较宽表上只选择少数列和选择所有列的扫描性能
+//!
+//! 表有 30 列，其中 28 列是字符串。只选择 2 列时，列裁剪使扫描跳过其余列的解码，
+//! 两者的差距即为裁剪节省的解码开销。
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use sqldb::{
+    executor::{Executor, ResultSet},
+    parser::Parser,
+    storage::MemoryStorage,
+    Engine,
+};
+
+/// 表的列数
+const COLUMNS: usize = 30;
+
+/// 每条 INSERT 语句插入的行数
+const BATCH_SIZE: usize = 1000;
+
+/// 创建 `wide` 表并插入 `n` 行数据，`c0` 为主键，`c1` 为整数，其余列为字符串
+fn setup(n: usize) -> Executor<MemoryStorage> {
+    let engine = Engine::new(MemoryStorage::new());
+    let executor = Executor::from_engine(&engine).unwrap();
+    let execute = |sql: &str| executor.execute(Parser::new(sql).parse().unwrap()).unwrap();
+
+    let columns = (2..COLUMNS)
+        .map(|i| format!("c{i} STRING"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    execute(&format!(
+        "CREATE TABLE wide (c0 INT PRIMARY KEY, c1 INT, {columns});"
+    ));
+    for start in (0..n).step_by(BATCH_SIZE) {
+        let end = (start + BATCH_SIZE).min(n);
+        let values = (start..end)
+            .map(|i| {
+                let strings = (2..COLUMNS)
+                    .map(|j| format!("'value of column {j} in row {i}'"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("({i}, {}, {strings})", i % 100)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        execute(&format!("INSERT INTO wide VALUES {values};"));
+    }
+    executor
+}
+
+/// 执行查询，返回结果的行数
+fn query(executor: &Executor<MemoryStorage>, sql: &str) -> usize {
+    match executor.execute(Parser::new(sql).parse().unwrap()).unwrap() {
+        ResultSet::Query { rows, .. } => rows.len(),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+fn bench_projection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("projection");
+    group.sample_size(10);
+
+    let all_columns = (0..COLUMNS)
+        .map(|i| format!("c{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let all = format!("SELECT {all_columns} FROM wide WHERE c1 < 50;");
+    let two = "SELECT c0, c2 FROM wide WHERE c1 < 50;";
+
+    for n in [10_000, 50_000] {
+        let executor = setup(n);
+        group.bench_with_input(BenchmarkId::new("all_columns", n), &n, |b, &n| {
+            b.iter(|| assert_eq!(query(&executor, &all), n / 2))
+        });
+        group.bench_with_input(BenchmarkId::new("two_columns", n), &n, |b, &n| {
+            b.iter(|| assert_eq!(query(&executor, two), n / 2))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_projection);
+criterion_main!(benches);
@@ -0,0 +1,189 @@
+use std::sync::{mpsc, Arc};
+
+use tokio::{sync::oneshot, task};
+
+use crate::{
+    executor::{CancellationToken, ResultSet},
+    storage::Storage,
+    Database, Error, Result,
+};
+
+/// 交给会话线程执行的语句
+struct Command {
+    sql: String,
+    token: CancellationToken,
+    reply: oneshot::Sender<Result<ResultSet>>,
+}
+
+/// 异步的会话，语句在 tokio 的阻塞线程池中执行，不会阻塞调用方的异步任务
+///
+/// 每个会话占用阻塞线程池中的一个线程，线程中的 [`Session`](crate::Session) 按照提交的顺序执行语句，
+/// 因此显式事务和同步的会话一样跨越多次调用。会话销毁时线程结束，回滚尚未结束的显式事务。
+///
+/// 丢弃 [`AsyncSession::execute`] 返回的 future 会取消语句：还没有开始的语句不会执行，
+/// 正在执行的语句在下一次检查取消标记时停止，自动提交的语句回滚，显式事务中的语句回滚整个事务，
+/// 和 [`Session::execute_with_token`](crate::Session::execute_with_token) 相同。
+/// 已经提交的语句不受影响。
+///
+/// ```
+/// use std::sync::Arc;
+/// use sqldb::{storage::MemoryStorage, AsyncSession, Database, Engine, ResultSet};
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+/// let mut session = AsyncSession::new(db);
+/// session.execute("CREATE TABLE t (id INT PRIMARY KEY);").await?;
+/// let result = session.execute("INSERT INTO t VALUES (1), (2);").await?;
+/// assert_eq!(result, ResultSet::modified(2));
+/// # Ok::<(), sqldb::Error>(())
+/// # }).unwrap();
+/// ```
+pub struct AsyncSession {
+    commands: mpsc::Sender<Command>,
+}
+
+impl AsyncSession {
+    /// 在 `database` 上创建会话，需要在 tokio 运行时中调用
+    pub fn new<S: Storage + Send + 'static>(database: Arc<Database<S>>) -> Self {
+        let (commands, receiver) = mpsc::channel::<Command>();
+        task::spawn_blocking(move || {
+            let mut session = database.session();
+            for command in receiver {
+                let result = session.execute_with_token(&command.sql, &command.token);
+                // 调用方已经丢弃 future 时没有人接收结果
+                let _ = command.reply.send(result);
+            }
+        });
+        Self { commands }
+    }
+
+    /// 解析并执行一条 SQL 语句
+    pub async fn execute(&mut self, sql: &str) -> Result<ResultSet> {
+        let token = CancellationToken::new();
+        let (reply, result) = oneshot::channel();
+        self.commands
+            .send(Command {
+                sql: sql.to_string(),
+                token: token.clone(),
+                reply,
+            })
+            .map_err(|_| session_stopped())?;
+
+        let guard = CancelOnDrop(Some(token));
+        let result = result.await.map_err(|_| session_stopped())?;
+        guard.disarm();
+        result
+    }
+}
+
+/// 会话线程在执行语句时 panic 后，之后的语句都返回这个错误
+fn session_stopped() -> Error {
+    Error::Internal("session thread stopped".to_string())
+}
+
+/// future 被丢弃时取消语句，得到结果后解除
+struct CancelOnDrop(Option<CancellationToken>);
+
+impl CancelOnDrop {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.0.take() {
+            token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::{storage::MemoryStorage, Engine, ErrorCode, Value};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_sessions() -> Result<()> {
+        let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+        let mut session = AsyncSession::new(Arc::clone(&db));
+        session
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")
+            .await?;
+
+        // 并发的会话交替读写，全部在时限内完成说明没有在存储的锁上死锁
+        let tasks = (0..16)
+            .map(|i| {
+                let db = Arc::clone(&db);
+                tokio::spawn(async move {
+                    let mut session = AsyncSession::new(db);
+                    for j in 0..20 {
+                        let id = i * 100 + j;
+                        session.execute("BEGIN;").await?;
+                        session
+                            .execute(&format!("INSERT INTO t VALUES ({id}, {j});"))
+                            .await?;
+                        session.execute("SELECT COUNT(*) FROM t;").await?;
+                        session.execute("COMMIT;").await?;
+                    }
+                    Ok::<_, Error>(())
+                })
+            })
+            .collect::<Vec<_>>();
+        timeout(Duration::from_secs(30), async {
+            for task in tasks {
+                task.await.expect("task panicked")?;
+            }
+            Ok::<_, Error>(())
+        })
+        .await
+        .expect("sessions deadlocked")?;
+
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM t;").await?.rows()[0][0],
+            Value::Integer(16 * 20)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_drop_cancels() -> Result<()> {
+        let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+        let mut session = AsyncSession::new(db);
+        let values = (0..1000)
+            .map(|i| format!("({i})"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        for table in ["t", "u", "v"] {
+            session
+                .execute(&format!("CREATE TABLE {table} (id INT PRIMARY KEY);"))
+                .await?;
+            session
+                .execute(&format!("INSERT INTO {table} VALUES {values};"))
+                .await?;
+        }
+
+        // 超时丢弃 future 后语句被取消，显式事务回滚
+        session.execute("BEGIN;").await?;
+        session.execute("DELETE FROM t WHERE id < 10;").await?;
+        let huge = "SELECT COUNT(*) FROM t CROSS JOIN u CROSS JOIN v;";
+        assert!(timeout(Duration::from_millis(100), session.execute(huge))
+            .await
+            .is_err());
+        assert_eq!(
+            session.execute("COMMIT;").await.unwrap_err().code(),
+            ErrorCode::NoActiveTransaction
+        );
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM t;").await?.rows()[0][0],
+            Value::Integer(1000)
+        );
+
+        Ok(())
+    }
+}
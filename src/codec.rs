@@ -0,0 +1,91 @@
+use crate::{
+    error::StorageError::Decode,
+    schema::{read_u64, skip_value, Row, Value},
+    Result,
+};
+
+/// 行数据的编码方式，保存在 [`Engine`](crate::Engine) 中，在运行时选择
+///
+/// 只负责行数据的编码，存储中的 key 始终使用固定的保序编码，表定义、索引项等元数据始终使用 bincode。
+/// 解码的行由调用方按照表定义检查，实现不需要关心表的结构。
+pub trait ValueCodec: Send + Sync {
+    /// 编码一行数据
+    fn encode_row(&self, row: &Row) -> Result<Vec<u8>>;
+
+    /// 解码 [`ValueCodec::encode_row`] 编码的行
+    fn decode_row(&self, bytes: &[u8]) -> Result<Row>;
+
+    /// 只解码下标在 `needed` 中的列，其他列在结果中为 NULL，结果的列数和编码的行相同
+    ///
+    /// 默认解码整行后丢弃不需要的列，编码支持跳过列时可以覆盖这个方法避免反序列化不需要的列。
+    fn decode_columns(&self, bytes: &[u8], needed: &[usize]) -> Result<Row> {
+        let mut row = self.decode_row(bytes)?;
+        for (col_idx, value) in row.iter_mut().enumerate() {
+            if !needed.contains(&col_idx) {
+                *value = Value::Null;
+            }
+        }
+        Ok(row)
+    }
+}
+
+/// 默认的编码方式，使用 `bincode::serialize` 编码行
+///
+/// 行的编码为：[列数 u64, 每一列的值]，值的编码为：[枚举索引 u32, 数据]，整数都是小端编码。
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BincodeCodec;
+
+impl ValueCodec for BincodeCodec {
+    fn encode_row(&self, row: &Row) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(row)?)
+    }
+
+    fn decode_row(&self, bytes: &[u8]) -> Result<Row> {
+        Ok(bincode::deserialize(bytes).map_err(|_| Decode {
+            context: "decoding row",
+            bytes: bytes.to_vec(),
+        })?)
+    }
+
+    /// 不需要的列直接跳过而不反序列化
+    fn decode_columns(&self, bytes: &[u8], needed: &[usize]) -> Result<Row> {
+        let decode_error = || Decode {
+            context: "decoding columns",
+            bytes: bytes.to_vec(),
+        };
+
+        let mut rest = bytes;
+        let len = read_u64(&mut rest)
+            .and_then(|len| usize::try_from(len).ok())
+            .ok_or_else(decode_error)?;
+        // 每一列至少占用枚举索引的 4 个字节，避免按照损坏的列数分配过大的内存
+        if len > rest.len() / 4 {
+            return Err(decode_error().into());
+        }
+        let mut row = vec![Value::Null; len];
+        for (col_idx, value) in row.iter_mut().enumerate() {
+            let start = rest;
+            skip_value(&mut rest).ok_or_else(decode_error)?;
+            if needed.contains(&col_idx) {
+                let encoded = &start[..start.len() - rest.len()];
+                *value = bincode::deserialize(encoded).map_err(|_| decode_error())?;
+            }
+        }
+        Ok(row)
+    }
+}
+
+/// 使用 JSON 编码行，用于测试确认行数据的读写都经过指定的编码，而不是默认的 bincode
+#[cfg(test)]
+pub(crate) struct JsonCodec;
+
+#[cfg(test)]
+impl ValueCodec for JsonCodec {
+    fn encode_row(&self, row: &Row) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(row)?)
+    }
+
+    fn decode_row(&self, bytes: &[u8]) -> Result<Row> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
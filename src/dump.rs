@@ -0,0 +1,282 @@
+//! 数据库的逻辑备份：将所有表的定义和数据导出为 SQL 脚本，执行脚本即可恢复出相同的数据库
+//!
+//! 脚本包含在一个显式事务中，依次为每个表生成 `CREATE TABLE`、分批的 `INSERT` 和 `CREATE INDEX`。
+//! 被外键引用的表先于引用它的表创建，引用自身的表中被引用的行先于引用它的行插入。
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::Write,
+};
+
+use crate::{
+    engine::Transaction,
+    error::{ExecutionError::InvalidCast, Result},
+    schema::{DataType, OnDelete, Row, Table, Value},
+    storage::Storage,
+};
+
+/// 每条 INSERT 语句最多包含的行数
+const INSERT_BATCH_SIZE: usize = 500;
+
+/// 将事务 `txn` 中可见的所有表导出为 SQL 脚本，写入 `writer`
+pub(crate) fn dump<S: Storage>(txn: &Transaction<S>, writer: &mut impl Write) -> Result<()> {
+    writeln!(writer, "BEGIN;")?;
+    for table in dependency_order(txn.list_tables()?) {
+        writeln!(writer, "{};", create_table(&table)?)?;
+
+        // 不引用自身的表按照主键的顺序流式导出，引用自身的表需要先读取所有行再排序
+        let rows = txn.scan_table_iter(&table, None);
+        match self_references(&table).is_empty() {
+            true => {
+                let mut batch = Vec::with_capacity(INSERT_BATCH_SIZE);
+                for row in rows {
+                    batch.push(row?);
+                    if batch.len() == INSERT_BATCH_SIZE {
+                        write_insert(writer, &table, &batch)?;
+                        batch.clear();
+                    }
+                }
+                write_insert(writer, &table, &batch)?;
+            }
+            false => {
+                let (ordered, cyclic) = order_by_references(&table, rows.collect::<Result<_>>()?);
+                for batch in ordered.chunks(INSERT_BATCH_SIZE) {
+                    write_insert(writer, &table, batch)?;
+                }
+                // 互相引用的行无法排序，放在同一条语句中，外键在整条语句写入之后才检查
+                write_insert(writer, &table, &cyclic)?;
+            }
+        }
+
+        for index in &table.indexes {
+            write!(
+                writer,
+                "CREATE {}INDEX {} ON {} ({})",
+                if index.unique { "UNIQUE " } else { "" },
+                index.name,
+                table.name,
+                index.columns.join(", ")
+            )?;
+            if let Some(predicate) = &index.predicate {
+                write!(writer, " WHERE {predicate}")?;
+            }
+            writeln!(writer, ";")?;
+        }
+    }
+    writeln!(writer, "COMMIT;")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// 按照外键的依赖关系排列表，被引用的表在前，没有依赖关系的表保持按照表名排序
+fn dependency_order(tables: Vec<Table>) -> Vec<Table> {
+    fn visit(
+        name: &str,
+        tables: &mut HashMap<String, Table>,
+        visited: &mut HashSet<String>,
+        ordered: &mut Vec<Table>,
+    ) {
+        if !visited.insert(name.to_string()) {
+            return;
+        }
+        let Some(table) = tables.remove(name) else {
+            return;
+        };
+        for foreign_key in &table.foreign_keys {
+            visit(&foreign_key.parent_table, tables, visited, ordered);
+        }
+        ordered.push(table);
+    }
+
+    let names = tables
+        .iter()
+        .map(|table| table.name.clone())
+        .collect::<Vec<_>>();
+    let mut tables = tables
+        .into_iter()
+        .map(|table| (table.name.clone(), table))
+        .collect();
+    let (mut visited, mut ordered) = (HashSet::new(), Vec::new());
+    for name in names {
+        visit(&name, &mut tables, &mut visited, &mut ordered);
+    }
+    ordered
+}
+
+/// 表中引用自身的外键列的位置
+fn self_references(table: &Table) -> Vec<usize> {
+    table
+        .foreign_keys
+        .iter()
+        .filter(|foreign_key| foreign_key.parent_table == table.name)
+        .filter_map(|foreign_key| table.get_col_idx(&foreign_key.column))
+        .collect()
+}
+
+/// 将引用自身的表的行排序，使得被引用的行在引用它的行之前，返回排序后的行和无法排序的行
+///
+/// 无法排序的行之间存在循环引用，或者引用了循环中的行。
+fn order_by_references(table: &Table, rows: Vec<Row>) -> (Vec<Row>, Vec<Row>) {
+    let columns = self_references(table);
+    let positions = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (table.get_primary_key(row), i))
+        .collect::<HashMap<_, _>>();
+
+    // 每一行尚未插入的被引用行的数量，以及每一行被哪些行引用
+    let mut waiting = vec![0; rows.len()];
+    let mut referenced_by = vec![Vec::new(); rows.len()];
+    for (i, row) in rows.iter().enumerate() {
+        let pk = table.get_primary_key(row);
+        for &col in &columns {
+            if row[col] == Value::Null || &row[col] == pk {
+                continue;
+            }
+            if let Some(&parent) = positions.get(&row[col]) {
+                waiting[i] += 1;
+                referenced_by[parent].push(i);
+            }
+        }
+    }
+
+    let mut queue = (0..rows.len())
+        .filter(|&i| waiting[i] == 0)
+        .collect::<VecDeque<_>>();
+    let mut order = Vec::with_capacity(rows.len());
+    while let Some(i) = queue.pop_front() {
+        order.push(i);
+        for &child in &referenced_by[i] {
+            waiting[child] -= 1;
+            if waiting[child] == 0 {
+                queue.push_back(child);
+            }
+        }
+    }
+
+    let mut rows = rows.into_iter().map(Some).collect::<Vec<_>>();
+    let ordered = order.iter().filter_map(|&i| rows[i].take()).collect();
+    let cyclic = rows.into_iter().flatten().collect();
+    (ordered, cyclic)
+}
+
+/// 根据表定义生成 CREATE TABLE 语句，不包含结尾的分号
+fn create_table(table: &Table) -> Result<String> {
+    let columns = table
+        .columns
+        .iter()
+        .map(|column| {
+            let mut definition = format!("{} {}", column.name, sql_type(column.data_type));
+            if column.primary_key {
+                definition.push_str(" PRIMARY KEY");
+                // 恢复时显式插入的 ID 会推进计数器，不需要单独导出计数器的值
+                if column.auto_increment {
+                    definition.push_str(" AUTO_INCREMENT");
+                }
+            } else if column.nullable {
+                definition.push_str(" NULL");
+            } else {
+                definition.push_str(" NOT NULL");
+            }
+            for foreign_key in &table.foreign_keys {
+                if foreign_key.column == column.name {
+                    definition.push_str(&format!(" REFERENCES {}", foreign_key.parent_table));
+                    if foreign_key.on_delete == OnDelete::Cascade {
+                        definition.push_str(" ON DELETE CASCADE");
+                    }
+                }
+            }
+            // DEFAULT 之后是表达式，放在最后，避免之后的关键字被解析为表达式的一部分
+            if let Some(default) = &column.default {
+                definition.push_str(&format!(" DEFAULT {}", literal(default)?));
+            }
+            Ok(definition)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(format!(
+        "CREATE TABLE {} (\n    {}\n)",
+        table.name,
+        columns.join(",\n    ")
+    ))
+}
+
+/// 写入一条插入 `rows` 的 INSERT 语句，`rows` 为空时不写入
+fn write_insert(writer: &mut impl Write, table: &Table, rows: &[Row]) -> Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    write!(writer, "INSERT INTO {} VALUES", table.name)?;
+    for (i, row) in rows.iter().enumerate() {
+        let values = row.iter().map(literal).collect::<Result<Vec<_>>>()?;
+        let separator = if i == 0 { "" } else { "," };
+        write!(writer, "{separator}\n    ({})", values.join(", "))?;
+    }
+    writeln!(writer, ";")?;
+    Ok(())
+}
+
+/// 列的数据类型在 SQL 中的名称
+fn sql_type(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::Boolean => "BOOLEAN",
+        DataType::Integer => "INTEGER",
+        DataType::Float => "FLOAT",
+        DataType::String => "STRING",
+        DataType::Json => "JSON",
+    }
+}
+
+/// 将值转换为 SQL 中的字面量，解析后得到相同的值
+///
+/// 字符串中的单引号写为两个单引号，JSON 值写为字符串，插入 JSON 列时重新解析。
+/// 浮点数总是包含小数点，不使用指数表示；NaN 和无穷大没有对应的字面量，返回错误。
+fn literal(value: &Value) -> Result<String> {
+    match value {
+        Value::Float(f) if !f.is_finite() => Err(InvalidCast {
+            value: value.clone(),
+            target: "SQL literal",
+        }
+        .into()),
+        Value::Float(f) => {
+            let text = f.to_string();
+            Ok(match text.contains('.') {
+                true => text,
+                false => format!("{text}.0"),
+            })
+        }
+        Value::Json(json) => Ok(Value::String(json.to_string()).to_string()),
+        value => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::ErrorCode;
+
+    #[test]
+    fn test_literal() -> Result<()> {
+        let cases = [
+            (Value::Null, "NULL"),
+            (Value::Boolean(true), "TRUE"),
+            (Value::Integer(-42), "-42"),
+            (Value::Float(3.0), "3.0"),
+            (Value::Float(-0.25), "-0.25"),
+            (Value::Float(1e20), "100000000000000000000.0"),
+            (Value::String("it's\n'ok'".to_string()), "'it''s\n''ok'''"),
+            (Value::Json(json!({"a": "b'c"})), r#"'{"a":"b''c"}'"#),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(literal(&value)?, expected);
+        }
+
+        // NaN 和无穷大没有对应的字面量
+        for f in [f64::NAN, f64::INFINITY] {
+            let e = literal(&Value::Float(f)).unwrap_err();
+            assert_eq!(e.code(), ErrorCode::InvalidCast);
+        }
+        Ok(())
+    }
+}
@@ -1,30 +1,126 @@
-use serde::{Deserialize, Serialize};
+use std::{
+    ops::{Bound, Range, RangeBounds},
+    sync::{Arc, Mutex},
+};
 
 use crate::{
+    codec::{BincodeCodec, ValueCodec},
+    error::{
+        ErrorContext,
+        ExecutionError::{DuplicateKey, TypeMismatch, UniqueViolation},
+        SchemaError::{
+            ColumnNotFound, IndexExists, InvalidDefinition, NoIndexOnColumn, TableExists,
+            TableNotFound,
+        },
+    },
+    executor::expression::{evaluate, predicate_passes},
+    function::FunctionRegistry,
+    keycode,
     parser::ast::Expression,
-    schema::{Row, Table, Value},
-    storage::{Mvcc, MvccTxn, Storage},
-    Error::InternalError,
-    Result,
+    schema::{IndexDef, Namespace, Row, Table, Value},
+    stats::TableStats,
+    storage::{prefix_end, Mvcc, MvccTxn, Storage, Version},
+    Result, ResultExt,
 };
 
 /// 数据库引擎，负责管理事务，执行事务操作
 pub struct Engine<S: Storage> {
     mvcc: Mvcc<S>,
+    /// 语句中可以调用的标量函数
+    functions: Arc<FunctionRegistry>,
+    /// 行数据的编码方式
+    codec: Arc<dyn ValueCodec>,
+    /// key 的命名空间
+    namespace: Namespace,
 }
 
 impl<S: Storage> Engine<S> {
-    /// 创建一个新的数据库引擎
+    /// 创建一个新的数据库引擎，行数据使用 [`BincodeCodec`] 编码
     pub fn new(storage: S) -> Self {
+        Self::with_codec(storage, Arc::new(BincodeCodec))
+    }
+
+    /// 创建一个新的数据库引擎，行数据使用 `codec` 编码
+    pub fn with_codec(storage: S, codec: Arc<dyn ValueCodec>) -> Self {
         Self {
             mvcc: Mvcc::new(storage),
+            functions: Arc::new(FunctionRegistry::new()),
+            codec,
+            namespace: Namespace::default(),
+        }
+    }
+
+    /// 行数据的编码方式
+    pub fn codec(&self) -> &Arc<dyn ValueCodec> {
+        &self.codec
+    }
+
+    /// 替换行数据的编码方式，之后开启的事务使用新的编码
+    ///
+    /// 已经存储的行不会重新编码，新的编码无法解码这些行时读取会返回错误。
+    pub fn set_codec(&mut self, codec: Arc<dyn ValueCodec>) {
+        self.codec = codec;
+    }
+
+    /// key 的命名空间
+    pub fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
+    /// 替换 key 的命名空间，之后开启的事务使用新的分隔符编码 key
+    ///
+    /// 已经存储的数据不会重新编码，只应该在写入任何数据之前设置。
+    pub fn set_namespace(&mut self, namespace: Namespace) {
+        self.namespace = namespace;
+    }
+
+    /// 标量函数的注册表
+    pub fn functions(&self) -> &Arc<FunctionRegistry> {
+        &self.functions
+    }
+
+    /// 在一个新事务中向表 `table_name` 批量写入按主键排好序的行，返回写入的行数，见 [`Mvcc::bulk_insert`]
+    ///
+    /// 行和 key 使用引擎的编码方式和命名空间。表有二级索引时返回错误；表有自增列时，
+    /// 写入成功之后计数器推进到写入的最大 ID。
+    pub fn bulk_insert(
+        &self,
+        table_name: &str,
+        rows: impl IntoIterator<Item = Row>,
+        verify: bool,
+    ) -> Result<usize> {
+        let txn = self.start_txn()?;
+        let table = txn
+            .get_table(table_name)?
+            .ok_or(TableNotFound(table_name.to_string()))?;
+        txn.commit()?;
+
+        let auto_increment_idx = table.auto_increment_idx();
+        let mut max_id = None;
+        let rows = rows.into_iter().inspect(|row| {
+            if let Some(Value::Integer(id)) = auto_increment_idx.and_then(|idx| row.get(idx)) {
+                max_id = max_id.max(Some(*id));
+            }
+        });
+        let count =
+            self.mvcc
+                .bulk_insert(&table, rows, self.codec.as_ref(), &self.namespace, verify)?;
+
+        if let Some(id) = max_id {
+            let txn = self.mvcc.start_txn()?;
+            txn.advance_sequence(table_name.as_bytes(), id)?;
+            txn.commit()?;
         }
+        Ok(count)
     }
 
     /// 开启一个新的事务
     pub fn start_txn(&self) -> Result<Transaction<S>> {
         Ok(Transaction {
             txn: self.mvcc.start_txn()?,
+            codec: self.codec.clone(),
+            namespace: self.namespace,
+            sequence_updates: Mutex::new(Vec::new()),
         })
     }
 }
@@ -33,39 +129,164 @@ impl<S: Storage> Engine<S> {
 ///
 /// - `Table(String)`：标识存储表信息
 /// - `Row(String, Value)`：标识存储行数据
-#[derive(Debug, Serialize, Deserialize)]
+/// - `Index(String, String, Vec<Value>, Value)`：标识二级索引项，依次为表名、索引名、索引列的值和主键值，
+///   存储的值为主键值
+/// - `UniqueIndex(String, String, Vec<Value>)`：标识唯一索引中索引列的值均不为 NULL 的索引项，
+///   依次为表名、索引名和索引列的值，存储的值为主键值
+/// - `Stats(String)`：标识存储表的统计信息
+///
+/// 使用 `keycode` 进行保序编码，同一张表的行按照主键值的顺序存储，从而支持主键的范围扫描；
+/// 同一个索引的索引项按照索引列的值排序，主键值放在最后，保证索引列的值重复时索引项也不会冲突。
+/// 唯一索引的索引项不包含主键值，同一个值只对应一个 key，并发插入相同值的事务会产生写冲突。
+#[derive(Debug)]
 enum Key {
     Table(String),
     Row(String, Value),
+    Index(String, String, Vec<Value>, Value),
+    UniqueIndex(String, String, Vec<Value>),
+    Stats(String),
+}
+
+impl Key {
+    /// 行在索引上对应的索引项，唯一索引中索引列的值均不为 NULL 时不包含主键值
+    fn index(table: &Table, index: &IndexDef, values: Vec<Value>, pk: &Value) -> Self {
+        if index.unique && !values.contains(&Value::Null) {
+            Key::UniqueIndex(table.name.clone(), index.name.clone(), values)
+        } else {
+            Key::Index(table.name.clone(), index.name.clone(), values, pk.clone())
+        }
+    }
+
+    /// 在命名空间 `namespace` 中编码 key
+    fn encode(&self, namespace: &Namespace) -> Vec<u8> {
+        match self {
+            Key::Table(name) => {
+                let mut bytes = KeyPrefix::Table.encode(namespace);
+                namespace.encode_name(name, &mut bytes);
+                bytes
+            }
+            Key::Row(table_name, pk) => {
+                let mut bytes = KeyPrefix::Row(table_name.clone()).encode(namespace);
+                keycode::encode_value(pk, &mut bytes);
+                bytes
+            }
+            Key::Index(table_name, index_name, values, pk) => {
+                let mut bytes =
+                    KeyPrefix::Index(table_name.clone(), index_name.clone(), values.clone())
+                        .encode(namespace);
+                keycode::encode_value(pk, &mut bytes);
+                bytes
+            }
+            Key::UniqueIndex(table_name, index_name, values) => {
+                KeyPrefix::Index(table_name.clone(), index_name.clone(), values.clone())
+                    .encode(namespace)
+            }
+            Key::Stats(table_name) => {
+                let mut bytes = KeyPrefix::Stats.encode(namespace);
+                namespace.encode_name(table_name, &mut bytes);
+                bytes
+            }
+        }
+    }
+}
+
+/// 命名空间 `namespace` 中，表中主键为 `pk` 的行在存储中的 key
+pub(crate) fn row_key(namespace: &Namespace, table_name: &str, pk: &Value) -> Vec<u8> {
+    Key::Row(table_name.to_string(), pk.clone()).encode(namespace)
 }
 
 /// 数据库引擎内部的键前缀
 ///
 /// - `Table`：标识表信息的前缀
 /// - `Row(String)`：标识行数据的前缀
+/// - `Index(String, String, Vec<Value>)`：标识索引项的前缀，`Vec<Value>` 为索引列中前若干列的值
+/// - `Stats`：标识表的统计信息的前缀
 ///
-/// 注：和 `storage::mvcc::MvccKey` 不同，虽然前缀中也使用了字符串，但字符串长度和 Key 中的 `String` 长度相同，
-/// 因此不需要删除前缀中的长度信息，直接使用 bincode 序列化即可。
-#[derive(Debug, Serialize, Deserialize)]
+/// 表名和索引名以命名空间的分隔符结尾，名称中不能包含分隔符，因此一张表的行前缀不会是另一张表的行前缀。
+#[derive(Debug)]
 enum KeyPrefix {
     Table,
     Row(String),
+    Index(String, String, Vec<Value>),
+    Stats,
+}
+
+impl KeyPrefix {
+    /// 在命名空间 `namespace` 中编码 key 前缀
+    fn encode(&self, namespace: &Namespace) -> Vec<u8> {
+        match self {
+            KeyPrefix::Table => vec![0x01],
+            KeyPrefix::Row(table_name) => {
+                let mut bytes = vec![0x02];
+                namespace.encode_name(table_name, &mut bytes);
+                bytes
+            }
+            KeyPrefix::Index(table_name, index_name, values) => {
+                let mut bytes = vec![0x03];
+                namespace.encode_name(table_name, &mut bytes);
+                namespace.encode_name(index_name, &mut bytes);
+                for value in values {
+                    keycode::encode_value(value, &mut bytes);
+                }
+                bytes
+            }
+            KeyPrefix::Stats => vec![0x04],
+        }
+    }
+}
+
+/// [`Transaction::check_foreign_keys`] 发现的引用了不存在的行的外键值
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferenceViolation {
+    /// 外键的约束名
+    pub constraint: String,
+    /// 子表名
+    pub table: String,
+    /// 外键列
+    pub column: String,
+    /// 被引用的父表名
+    pub parent_table: String,
+    /// 子表中违反约束的行的主键
+    pub primary_key: Value,
+    /// 父表中不存在的主键值
+    pub key: Value,
+}
+
+/// 事务提交之后才应用到表的自增计数器上的修改
+#[derive(Debug)]
+enum SequenceUpdate {
+    /// 显式写入了自增列的值，将计数器推进到至少这个值
+    Advance(String, i64),
+    /// 删除了表，重置计数器
+    Reset(String),
 }
 
 /// 数据库事务，对 `MvccTxn` 进行了封装，提供了更高级别的操作
 pub struct Transaction<S: Storage> {
     txn: MvccTxn<S>,
+    /// 行数据的编码方式，在事务开启时确定
+    codec: Arc<dyn ValueCodec>,
+    /// key 的命名空间，在事务开启时确定
+    namespace: Namespace,
+    /// 按照发生的顺序记录的计数器修改，提交时应用，回滚时丢弃
+    sequence_updates: Mutex<Vec<SequenceUpdate>>,
 }
 
 impl<S: Storage> Transaction<S> {
+    /// key 的命名空间
+    pub fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
     /// 获取表信息
     pub fn get_table(&self, table_name: &str) -> Result<Option<Table>> {
         let key = Key::Table(table_name.to_string());
         let table = self
             .txn
-            .get(&bincode::serialize(&key)?)?
+            .get(&key.encode(&self.namespace))?
             .map(|data| bincode::deserialize(&data))
-            .transpose()?;
+            .transpose()
+            .with_context(|| ErrorContext::new("reading definition of").table(table_name))?;
         Ok(table)
     }
 
@@ -74,137 +295,902 @@ impl<S: Storage> Transaction<S> {
         // 如果表不存在，返回错误
         let table = self
             .get_table(table_name)?
-            .ok_or(InternalError(format!("Table {table_name} not found")))?;
+            .ok_or(TableNotFound(table_name.to_string()))?;
 
         // 检查行数据是否符合表定义
-        for (column, row) in table.columns.iter().zip(row.iter()) {
-            match row.data_type() {
-                None if !column.nullable => {
-                    return Err(InternalError(format!(
-                        "Column {} cannot be null",
-                        column.name
-                    )));
-                }
-                Some(data_type) if data_type != column.data_type => {
-                    return Err(InternalError(format!(
-                        "Column {} expect {:?}, got {:?}",
-                        column.name, column.data_type, data_type
-                    )));
-                }
-                _ => {}
-            }
-        }
+        table.validate_row(row)?;
 
         // 将行数据序列化后存储，键为表名和主键值
         let key = Key::Row(table_name.to_string(), table.get_primary_key(row).clone());
 
         // 如果主键已经存在，返回错误
-        if self.txn.get(&bincode::serialize(&key)?)?.is_some() {
-            return Err(InternalError(format!(
-                "Primary key {:?} in table {} already exists",
-                table.get_primary_key(row),
-                table_name
-            )));
+        if self.txn.get(&key.encode(&self.namespace))?.is_some() {
+            return Err(DuplicateKey {
+                table: table_name.to_string(),
+                key: table.get_primary_key(row).clone(),
+            }
+            .into());
         }
 
         // 存储行数据
-        let value = bincode::serialize(row)?;
-        self.txn.set(&bincode::serialize(&key)?, &value)?;
+        let value = self.codec.encode_row(row)?;
+        self.txn.set(&key.encode(&self.namespace), &value)?;
+
+        // 写入所有二级索引的索引项
+        for index in &table.indexes {
+            self.index_put(&table, index, row)?;
+        }
+
+        self.advance_id(&table, row)
+    }
+
+    /// 为表的自增列分配下一个 ID，表没有自增列时返回错误
+    ///
+    /// 计数器保存在 MVCC 的序列中，不受事务快照和回滚的影响：并发事务分配的 ID 不会重复，
+    /// 也不会互相产生写冲突；回滚的事务分配过的 ID 不会再被分配，因此 ID 可能不连续。
+    pub fn allocate_id(&self, table_name: &str) -> Result<i64> {
+        let table = self
+            .get_table(table_name)?
+            .ok_or(TableNotFound(table_name.to_string()))?;
+        if table.auto_increment_idx().is_none() {
+            return Err(InvalidDefinition(format!(
+                "Table {table_name} has no auto-increment column"
+            ))
+            .into());
+        }
+
+        // 本事务显式写入的 ID 在提交之前不会推进计数器，分配时先跳过这些 ID，
+        // 和分配的 ID 一样，这次推进不会因为事务回滚而撤销
+        let pending = self
+            .sequence_updates
+            .lock()?
+            .iter()
+            .filter_map(|update| match update {
+                SequenceUpdate::Advance(name, id) if name == table_name => Some(*id),
+                _ => None,
+            })
+            .max();
+        if let Some(id) = pending {
+            self.txn.advance_sequence(table_name.as_bytes(), id)?;
+        }
+        self.txn.next_sequence(table_name.as_bytes())
+    }
 
+    /// 行的自增列为 NULL 时为其分配 ID，返回分配的 ID；表没有自增列或者值已经给出时不做修改
+    pub fn fill_auto_increment(&self, table: &Table, row: &mut Row) -> Result<Option<i64>> {
+        match table.auto_increment_idx() {
+            Some(idx) if row.get(idx) == Some(&Value::Null) => {
+                let id = self.allocate_id(&table.name)?;
+                row[idx] = Value::Integer(id);
+                Ok(Some(id))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// 记录自增列中显式写入的值，事务提交时计数器推进到至少这个值，避免之后分配的 ID 和它重复
+    ///
+    /// 只有自增列的值会推进计数器；回滚的事务写入的值不会推进计数器。
+    fn advance_id(&self, table: &Table, row: &Row) -> Result<()> {
+        let Some(Value::Integer(id)) = table.auto_increment_idx().map(|idx| &row[idx]) else {
+            return Ok(());
+        };
+        let mut updates = self.sequence_updates.lock()?;
+        // 连续写入同一个表时只保留最大的值
+        match updates.last_mut() {
+            Some(SequenceUpdate::Advance(name, max)) if *name == table.name => {
+                *max = (*max).max(*id);
+            }
+            _ => updates.push(SequenceUpdate::Advance(table.name.clone(), *id)),
+        }
         Ok(())
     }
 
+    /// 获取所有表的信息，按照表名排序
+    pub fn list_tables(&self) -> Result<Vec<Table>> {
+        // 分隔符不一定小于名称中的字节，key 的顺序不一定是表名的顺序
+        let mut tables = self
+            .txn
+            .scan_prefix(&KeyPrefix::Table.encode(&self.namespace))?
+            .into_iter()
+            .map(|(_, data)| Ok(bincode::deserialize(&data)?))
+            .collect::<Result<Vec<Table>>>()?;
+        tables.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tables)
+    }
+
+    /// 获取表的统计信息，没有执行过 `ANALYZE` 时为 `None`
+    pub fn get_stats(&self, table_name: &str) -> Result<Option<TableStats>> {
+        let key = Key::Stats(table_name.to_string());
+        let stats = self
+            .txn
+            .get(&key.encode(&self.namespace))?
+            .map(|data| bincode::deserialize(&data))
+            .transpose()?;
+        Ok(stats)
+    }
+
+    /// 保存表的统计信息，覆盖之前的统计信息
+    pub fn set_stats(&self, table_name: &str, stats: &TableStats) -> Result<()> {
+        if self.get_table(table_name)?.is_none() {
+            return Err(TableNotFound(table_name.to_string()).into());
+        }
+        let key = Key::Stats(table_name.to_string()).encode(&self.namespace);
+        self.txn.set(&key, &bincode::serialize(stats)?)
+    }
+
     /// 创建表
+    ///
+    /// 表名和列名中不能包含命名空间的分隔符，
+    /// 外键引用的父表必须已经存在或者是表自身，外键列的类型必须和父表主键的类型一致。
     pub fn create_table(&self, table: Table) -> Result<()> {
+        self.namespace.check_name("Table", &table.name)?;
+        for column in &table.columns {
+            self.namespace.check_name("Column", &column.name)?;
+        }
+
         // 检查表是否已经存在，如果存在则返回错误
         if self.get_table(&table.name)?.is_some() {
-            return Err(InternalError(format!(
-                "Table {} already exists",
-                table.name
-            )));
+            return Err(TableExists(table.name.clone()).into());
         }
 
-        let key = bincode::serialize(&Key::Table(table.name.clone()))?;
+        for foreign_key in &table.foreign_keys {
+            let parent = match foreign_key.parent_table == table.name {
+                true => None,
+                false => Some(
+                    self.get_table(&foreign_key.parent_table)?
+                        .ok_or(TableNotFound(foreign_key.parent_table.clone()))?,
+                ),
+            };
+            let parent_key = parent.as_ref().unwrap_or(&table).get_primary_key_column();
+            let column = table
+                .get_col_idx(&foreign_key.column)
+                .map(|col_idx| &table.columns[col_idx])
+                .ok_or(ColumnNotFound {
+                    table: table.name.clone(),
+                    column: foreign_key.column.clone(),
+                })?;
+            if column.data_type != parent_key.data_type {
+                return Err(InvalidDefinition(format!(
+                    "Foreign key {} has type {:?}, but the referenced key {} has type {:?}",
+                    foreign_key.name, column.data_type, parent_key.name, parent_key.data_type
+                ))
+                .into());
+            }
+        }
+
+        let key = Key::Table(table.name.clone()).encode(&self.namespace);
         let value = bincode::serialize(&table)?;
         self.txn.set(&key, &value)?;
 
         Ok(())
     }
 
-    /// 扫描表
-    pub fn scan_table(
-        &self,
-        table: &Table,
-        filter: Option<(String, Expression)>,
-    ) -> Result<Vec<Row>> {
-        let prefix = KeyPrefix::Row(table.name.clone());
-        let result = self.txn.scan_prefix(&bincode::serialize(&prefix)?)?;
-
-        let mut rows = Vec::new();
-        for (_, value) in result {
-            let row: Row = bincode::deserialize(&value)?;
-            // 如果有过滤条件，检查是否符合条件
-            if let Some((col, expr)) = &filter {
-                let col_idx = table.get_col_idx(col).ok_or(InternalError(format!(
-                    "Column {} not found in table {}",
-                    col, table.name
-                )))?;
-                if Value::from(expr.clone()) != row[col_idx] {
-                    continue;
+    /// 删除表，以及表的所有行、索引项和统计信息
+    ///
+    /// 被其他表的外键引用的表不能删除。表的自增计数器在事务提交时重置，
+    /// 之后创建的同名表重新从 1 开始分配 ID。
+    pub fn drop_table(&self, table_name: &str) -> Result<()> {
+        let table = self
+            .get_table(table_name)?
+            .ok_or(TableNotFound(table_name.to_string()))?;
+        for other in self.list_tables()? {
+            if other.name == table.name {
+                continue;
+            }
+            if let Some(foreign_key) = other
+                .foreign_keys
+                .iter()
+                .find(|foreign_key| foreign_key.parent_table == table.name)
+            {
+                return Err(InvalidDefinition(format!(
+                    "Table {} is referenced by foreign key {} of table {}",
+                    table.name, foreign_key.name, other.name
+                ))
+                .into());
+            }
+        }
+
+        let mut prefixes = vec![KeyPrefix::Row(table.name.clone())];
+        for index in &table.indexes {
+            prefixes.push(KeyPrefix::Index(
+                table.name.clone(),
+                index.name.clone(),
+                Vec::new(),
+            ));
+        }
+        for prefix in prefixes {
+            for key in self.txn.scan_prefix_keys(&prefix.encode(&self.namespace))? {
+                self.txn.delete(&key)?;
+            }
+        }
+        self.txn
+            .delete(&Key::Stats(table.name.clone()).encode(&self.namespace))?;
+        self.txn
+            .delete(&Key::Table(table.name.clone()).encode(&self.namespace))?;
+
+        self.sequence_updates
+            .lock()?
+            .push(SequenceUpdate::Reset(table.name));
+        Ok(())
+    }
+
+    /// 检查所有表的外键，返回引用了不存在的父表行的所有行
+    ///
+    /// 外键在写入时由执行器检查，这里用于检查直接通过事务写入的数据或者损坏的数据。
+    /// 只读取当前事务可见的行，外键列为 NULL 的行不检查，父表不存在时所有引用都被报告。
+    pub fn check_foreign_keys(&self) -> Result<Vec<ReferenceViolation>> {
+        let tables = self.list_tables()?;
+        let mut violations = Vec::new();
+        for table in tables.iter().filter(|table| !table.foreign_keys.is_empty()) {
+            for foreign_key in &table.foreign_keys {
+                let col_idx = table
+                    .get_col_idx(&foreign_key.column)
+                    .ok_or(ColumnNotFound {
+                        table: table.name.clone(),
+                        column: foreign_key.column.clone(),
+                    })?;
+                let parent = tables
+                    .iter()
+                    .find(|parent| parent.name == foreign_key.parent_table);
+                for row in self.scan_table_iter(table, None) {
+                    let row = row?;
+                    let key = &row[col_idx];
+                    if *key == Value::Null {
+                        continue;
+                    }
+                    let exists = match parent {
+                        Some(parent) => self.get_row(parent, key)?.is_some(),
+                        None => false,
+                    };
+                    if !exists {
+                        violations.push(ReferenceViolation {
+                            constraint: foreign_key.name.clone(),
+                            table: table.name.clone(),
+                            column: foreign_key.column.clone(),
+                            parent_table: foreign_key.parent_table.clone(),
+                            primary_key: table.get_primary_key(&row).clone(),
+                            key: key.clone(),
+                        });
+                    }
                 }
             }
-            rows.push(row);
         }
+        Ok(violations)
+    }
+
+    /// 根据主键获取行数据
+    pub fn get_row(&self, table: &Table, pk: &Value) -> Result<Option<Row>> {
+        let key = Key::Row(table.name.clone(), pk.clone()).encode(&self.namespace);
+        let row = self
+            .txn
+            .get(&key)?
+            .map(|data| table.decode_row_with(self.codec.as_ref(), &data))
+            .transpose()
+            .with_context(|| ErrorContext::new("reading").table(&table.name).key(&key))?;
+        Ok(row)
+    }
+
+    /// 扫描表，返回满足 `filter` 的行
+    ///
+    /// `filter` 中的字段可以为 `col_name` 或 `table_name.col_name` 的形式，
+    /// 只有计算结果为 `TRUE` 的行会被保留。
+    pub fn scan_table(&self, table: &Table, filter: Option<Expression>) -> Result<Vec<Row>> {
+        self.scan_table_iter(table, filter).collect()
+    }
 
-        Ok(rows)
+    /// 扫描表中主键在 `range` 范围内的行，结果按主键升序排列
+    pub fn scan_table_range<R>(&self, table: &Table, range: R) -> Result<Vec<Row>>
+    where
+        R: RangeBounds<Value>,
+    {
+        self.scan_table_range_iter(table, range).collect()
+    }
+
+    /// 和 [`Transaction::scan_table`] 相同，但以迭代器的形式按需分批读取行
+    pub fn scan_table_iter(&self, table: &Table, filter: Option<Expression>) -> RowScan<'_, S> {
+        RowScan::new(
+            &self.txn,
+            self.codec.as_ref(),
+            &self.namespace,
+            table,
+            (Bound::Unbounded, Bound::Unbounded),
+            filter,
+        )
+    }
+
+    /// 和 [`Transaction::scan_table_range`] 相同，但以迭代器的形式按需分批读取行
+    pub fn scan_table_range_iter<R>(&self, table: &Table, range: R) -> RowScan<'_, S>
+    where
+        R: RangeBounds<Value>,
+    {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        RowScan::new(
+            &self.txn,
+            self.codec.as_ref(),
+            &self.namespace,
+            table,
+            range,
+            None,
+        )
     }
 
     /// 更新行数据
     ///
     /// `pk` 为要更新的行的主键值，`row` 为新的行数据，`row` 的主键值不一定和 `pk` 相同。
     pub fn update_row(&self, table: &Table, pk: &Value, row: &Row) -> Result<()> {
+        // 删除旧行的索引项，之后写入新行的索引项
+        if !table.indexes.is_empty() {
+            if let Some(old_row) = self.get_row(table, pk)? {
+                for index in &table.indexes {
+                    self.index_delete(table, index, &old_row)?;
+                }
+            }
+        }
+
         // 如果更新了主键，则需要删除原来的数据
         let row_pk = table.get_primary_key(row);
         if row_pk != pk {
             let key = Key::Row(table.name.clone(), pk.clone());
-            self.txn.delete(&bincode::serialize(&key)?)?;
+            self.txn.delete(&key.encode(&self.namespace))?;
         }
 
         // 更新行数据
         let key = Key::Row(table.name.clone(), row_pk.clone());
-        let value = bincode::serialize(row)?;
-        self.txn.set(&bincode::serialize(&key)?, &value)?;
+        let value = self.codec.encode_row(row)?;
+        self.txn.set(&key.encode(&self.namespace), &value)?;
 
-        Ok(())
+        for index in &table.indexes {
+            self.index_put(table, index, row)?;
+        }
+
+        self.advance_id(table, row)
     }
 
     /// 删除行数据
     pub fn delete_row(&self, table: &Table, pk: &Value) -> Result<()> {
+        // 删除行的所有索引项
+        if !table.indexes.is_empty() {
+            if let Some(old_row) = self.get_row(table, pk)? {
+                for index in &table.indexes {
+                    self.index_delete(table, index, &old_row)?;
+                }
+            }
+        }
+
         let key = Key::Row(table.name.clone(), pk.clone());
-        self.txn.delete(&bincode::serialize(&key)?)?;
+        self.txn.delete(&key.encode(&self.namespace))?;
+
+        Ok(())
+    }
+
+    /// 创建二级索引，并为表中已有的行写入索引项
+    pub fn create_index(&self, table_name: &str, index: IndexDef) -> Result<()> {
+        let mut table = self
+            .get_table(table_name)?
+            .ok_or(TableNotFound(table_name.to_string()))?;
+
+        // 检查索引名是否合法、是否重复，索引列是否存在
+        self.namespace.check_name("Index", &index.name)?;
+        if table.get_index(&index.name).is_some() {
+            return Err(IndexExists {
+                table: table_name.to_string(),
+                index: index.name.clone(),
+            }
+            .into());
+        }
+        if index.columns.is_empty() {
+            return Err(InvalidDefinition(format!("Index {} has no columns", index.name)).into());
+        }
+        for col_name in &index.columns {
+            if table.get_col_idx(col_name).is_none() {
+                return Err(ColumnNotFound {
+                    table: table_name.to_string(),
+                    column: col_name.to_string(),
+                }
+                .into());
+            }
+        }
+        // 部分索引的条件以文本的形式保存在表定义中，只能引用表中的字段，不能包含子查询和函数
+        if let Some(predicate) = &index.predicate {
+            if !predicate.is_row_expression() {
+                return Err(InvalidDefinition(format!(
+                    "Index {} has an unsupported predicate {}",
+                    index.name, predicate
+                ))
+                .into());
+            }
+            let mut fields = Vec::new();
+            predicate.collect_fields(&mut fields);
+            if let Some(field) = fields.iter().find(|f| table.get_col_idx(f).is_none()) {
+                return Err(ColumnNotFound {
+                    table: table_name.to_string(),
+                    column: field.to_string(),
+                }
+                .into());
+            }
+        }
+
+        // 为已有的行写入索引项，唯一索引会在写入时检查重复值
+        for row in self.scan_table(&table, None)? {
+            self.index_put(&table, &index, &row)?;
+        }
+
+        table.add_index(index);
+        let key = Key::Table(table.name.clone()).encode(&self.namespace);
+        self.txn.set(&key, &bincode::serialize(&table)?)?;
 
         Ok(())
     }
 
-    /// 提交事务
+    /// 获取行在索引列上的值
+    fn index_values(table: &Table, index: &IndexDef, row: &Row) -> Result<Vec<Value>> {
+        index
+            .columns
+            .iter()
+            .map(|col_name| table.value_of(row, col_name).cloned())
+            .collect()
+    }
+
+    /// 行是否满足部分索引的条件，不是部分索引时总是满足
+    fn index_covers(table: &Table, index: &IndexDef, row: &Row) -> Result<bool> {
+        match &index.predicate {
+            Some(predicate) => {
+                let columns = table
+                    .columns
+                    .iter()
+                    .map(|col| col.name.clone())
+                    .collect::<Vec<_>>();
+                predicate_passes(evaluate(predicate, &columns, row)?)
+            }
+            None => Ok(true),
+        }
+    }
+
+    /// 写入行对应的索引项
+    ///
+    /// 行不满足部分索引的条件时不写入。
+    /// 对于唯一索引，先通过 [`Transaction::check_unique`] 检查索引列的值是否已经被其他行使用。
+    pub fn index_put(&self, table: &Table, index: &IndexDef, row: &Row) -> Result<()> {
+        if !Self::index_covers(table, index, row)? {
+            return Ok(());
+        }
+        let pk = table.get_primary_key(row);
+        if index.unique {
+            self.check_unique(table, index, row, pk)?;
+        }
+
+        let key = Key::index(table, index, Self::index_values(table, index, row)?, pk);
+        self.txn
+            .set(&key.encode(&self.namespace), &bincode::serialize(pk)?)
+    }
+
+    /// 检查 `row` 在唯一索引上的值是否已经被主键不为 `pk` 的行使用，是则返回 [`UniqueViolation`]
+    ///
+    /// 只读取值对应的一个索引项，不扫描表。索引列的值含有 NULL 时不检查。
+    /// 其他未提交的事务写入的相同的值在这里不可见，但之后写入同一个索引项时会产生写冲突。
+    pub fn check_unique(
+        &self,
+        table: &Table,
+        index: &IndexDef,
+        row: &Row,
+        pk: &Value,
+    ) -> Result<()> {
+        match self.unique_lookup_pk(table, index, row)? {
+            Some(existing_pk) if existing_pk != *pk => Err(UniqueViolation {
+                constraint: index.name.clone(),
+                table: table.name.clone(),
+                values: Self::index_values(table, index, row)?,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// 在唯一索引上查找索引列的值和 `row` 相同的行，`row` 本身不需要已经写入
+    ///
+    /// 索引列的值含有 NULL 或者 `row` 不满足部分索引的条件时不会和其他行冲突，返回 `None`。
+    pub fn unique_lookup(&self, table: &Table, index: &IndexDef, row: &Row) -> Result<Option<Row>> {
+        match self.unique_lookup_pk(table, index, row)? {
+            Some(pk) => self.get_row(table, &pk),
+            None => Ok(None),
+        }
+    }
+
+    /// 和 [`Transaction::unique_lookup`] 相同，但只返回主键值
+    fn unique_lookup_pk(
+        &self,
+        table: &Table,
+        index: &IndexDef,
+        row: &Row,
+    ) -> Result<Option<Value>> {
+        let values = Self::index_values(table, index, row)?;
+        if values.contains(&Value::Null) || !Self::index_covers(table, index, row)? {
+            return Ok(None);
+        }
+        let key = Key::UniqueIndex(table.name.clone(), index.name.clone(), values);
+        self.txn
+            .get(&key.encode(&self.namespace))?
+            .map(|pk| bincode::deserialize(&pk))
+            .transpose()
+            .map_err(|e| e.into())
+    }
+
+    /// 删除行对应的索引项
+    ///
+    /// 不检查部分索引的条件，行不在索引中时删除不存在的索引项没有影响。
+    pub fn index_delete(&self, table: &Table, index: &IndexDef, row: &Row) -> Result<()> {
+        let values = Self::index_values(table, index, row)?;
+        let key = Key::index(table, index, values, table.get_primary_key(row));
+        self.txn.delete(&key.encode(&self.namespace))
+    }
+
+    /// 通过索引扫描行
+    ///
+    /// `prefix` 为索引前若干列的值，`range` 为下一列的范围，结果按照索引的顺序排列。
+    /// 指定了 `range` 的上界或下界时，下一列为 NULL 的行不会出现在结果中。
+    pub fn scan_index(
+        &self,
+        table: &Table,
+        index: &IndexDef,
+        prefix: &[Value],
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Result<Vec<Row>> {
+        self.scan_index_iter(table, index, prefix, range)?.collect()
+    }
+
+    /// 和 [`Transaction::scan_index`] 相同，但以迭代器的形式按需读取行，见 [`IndexScan`]
+    pub fn scan_index_iter(
+        &self,
+        table: &Table,
+        index: &IndexDef,
+        prefix: &[Value],
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Result<IndexScan<'_, S>> {
+        let range = match range {
+            (Bound::Unbounded, Bound::Unbounded) => {
+                let prefix =
+                    KeyPrefix::Index(table.name.clone(), index.name.clone(), prefix.to_vec())
+                        .encode(&self.namespace);
+                (Bound::Included(prefix.clone()), prefix_end(&prefix))
+            }
+            range => {
+                let range = self.index_key_range(table, index, prefix, range);
+                (Bound::Included(range.start), Bound::Excluded(range.end))
+            }
+        };
+        Ok(IndexScan {
+            txn: &self.txn,
+            codec: self.codec.as_ref(),
+            namespace: self.namespace,
+            table: table.clone(),
+            start: range.0,
+            end: range.1,
+            batch: Vec::new().into_iter(),
+            is_exhausted: false,
+        })
+    }
+
+    /// 将索引上的范围转换为编码后的索引项范围 `[start, end)`
+    ///
+    /// `prefix` 为索引前若干列的值，`range` 为下一列的范围，下一列为 NULL 的索引项不在范围内。
+    fn index_key_range(
+        &self,
+        table: &Table,
+        index: &IndexDef,
+        prefix: &[Value],
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Range<Vec<u8>> {
+        let prefix = KeyPrefix::Index(table.name.clone(), index.name.clone(), prefix.to_vec())
+            .encode(&self.namespace);
+        let with_value = |value: &Value| {
+            let mut bytes = prefix.clone();
+            keycode::encode_value(value, &mut bytes);
+            bytes
+        };
+        // 一个值对应的所有索引项都以 `prefix + value` 开头，之后是主键的编码（唯一索引没有主键）或下一列的值，
+        // 编码的第一个字节是类型标签，一定小于 0xFF，因此 `prefix + value + 0xFF` 大于这些索引项
+        let after_value = |value: &Value| [with_value(value), vec![0xFF]].concat();
+
+        let start = match range.0 {
+            Bound::Included(value) => with_value(&value),
+            Bound::Excluded(value) => after_value(&value),
+            // NULL 的编码最小，从 NULL 之后开始扫描
+            Bound::Unbounded => after_value(&Value::Null),
+        };
+        let end = match range.1 {
+            Bound::Included(value) => after_value(&value),
+            Bound::Excluded(value) => with_value(&value),
+            // 前缀以非 0xFF 的标签开头，一定有上界
+            Bound::Unbounded => match prefix_end(&prefix) {
+                Bound::Excluded(end) => end,
+                _ => unreachable!("key prefix {:?} has no upper bound", prefix),
+            },
+        };
+        start..end
+    }
+
+    /// 编码 `column BETWEEN low AND high` 在二级索引上对应的索引项范围，用于 `scan_range`
+    ///
+    /// 使用第一列为 `column` 的索引（不使用部分索引），返回编码后的起始 key（包含）和结束 key（不包含）。
+    /// `low` 或 `high` 为 `Value::Null` 时表示该侧无界，索引值为 NULL 的行不在范围内。
+    pub fn index_range_bounds(
+        &self,
+        table: &Table,
+        column: &str,
+        low: &Value,
+        high: &Value,
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let col_idx = table.get_col_idx(column).ok_or(ColumnNotFound {
+            table: table.name.clone(),
+            column: column.to_string(),
+        })?;
+        let index = table
+            .indexes
+            .iter()
+            .find(|index| {
+                index.predicate.is_none() && index.columns.first().is_some_and(|col| col == column)
+            })
+            .ok_or(NoIndexOnColumn {
+                table: table.name.clone(),
+                column: column.to_string(),
+            })?;
+
+        // 边界的类型必须和列相同，否则编码的顺序和比较的语义不一致
+        let data_type = table.columns[col_idx].data_type;
+        let bound = |value: &Value| -> Result<Bound<Value>> {
+            match value.data_type() {
+                None => Ok(Bound::Unbounded),
+                Some(t) if t == data_type => Ok(Bound::Included(value.clone())),
+                Some(_) => Err(TypeMismatch(format!(
+                    "Value {:?} does not match column {}'s data type",
+                    value, column
+                ))
+                .into()),
+            }
+        };
+
+        let range = self.index_key_range(table, index, &[], (bound(low)?, bound(high)?));
+        Ok((range.start, range.end))
+    }
+
+    /// 事务的版本
     #[inline]
+    pub fn version(&self) -> Version {
+        self.txn.version()
+    }
+
+    /// 提交事务，之后按照顺序应用事务中对自增计数器的修改
+    ///
+    /// 计数器在提交成功之后才修改，提交和修改之间崩溃时计数器可能落后于已经写入的 ID，
+    /// 之后分配到这些 ID 的插入会返回 [`DuplicateKey`]，而不会覆盖已有的行。
     pub fn commit(&self) -> Result<()> {
-        self.txn.commit()
+        self.txn.commit()?;
+        for update in self.sequence_updates.lock()?.drain(..) {
+            match update {
+                SequenceUpdate::Advance(name, id) => {
+                    self.txn.advance_sequence(name.as_bytes(), id)?
+                }
+                SequenceUpdate::Reset(name) => self.txn.reset_sequence(name.as_bytes())?,
+            }
+        }
+        Ok(())
     }
 
-    /// 回滚事务
-    #[inline]
+    /// 回滚事务，丢弃事务中对自增计数器的修改
     pub fn rollback(&self) -> Result<()> {
+        self.sequence_updates.lock()?.clear();
         self.txn.rollback()
     }
 }
 
+/// 表扫描的迭代器
+///
+/// 每次从存储引擎中读取至多 [`RowScan::BATCH_SIZE`] 行，当前批次消费完后再读取下一批。
+/// 调用方提前停止迭代时（例如满足了 LIMIT），剩余的行不会从存储引擎中读取。
+pub struct RowScan<'a, S: Storage> {
+    txn: &'a MvccTxn<S>,
+    codec: &'a dyn ValueCodec,
+    table: Table,
+    columns: Vec<String>,
+    filter: Option<Expression>,
+    /// 需要解码的列的下标，为 `None` 时解码所有列
+    needed: Option<Vec<usize>>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    batch: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+    is_exhausted: bool,
+}
+
+impl<'a, S: Storage> RowScan<'a, S> {
+    /// 每批读取的行数
+    pub const BATCH_SIZE: usize = 128;
+
+    fn new(
+        txn: &'a MvccTxn<S>,
+        codec: &'a dyn ValueCodec,
+        namespace: &Namespace,
+        table: &Table,
+        range: (Bound<Value>, Bound<Value>),
+        filter: Option<Expression>,
+    ) -> Self {
+        let prefix = KeyPrefix::Row(table.name.clone()).encode(namespace);
+        let encode = |pk: Value| Key::Row(table.name.clone(), pk).encode(namespace);
+        let start = match range.0 {
+            Bound::Included(pk) => Bound::Included(encode(pk)),
+            Bound::Excluded(pk) => Bound::Excluded(encode(pk)),
+            Bound::Unbounded => Bound::Included(prefix.clone()),
+        };
+        let end = match range.1 {
+            Bound::Included(pk) => Bound::Included(encode(pk)),
+            Bound::Excluded(pk) => Bound::Excluded(encode(pk)),
+            Bound::Unbounded => prefix_end(&prefix),
+        };
+        let columns = table
+            .columns
+            .iter()
+            .map(|col| format!("{}.{}", table.name, col.name))
+            .collect();
+
+        Self {
+            txn,
+            codec,
+            table: table.clone(),
+            columns,
+            filter,
+            needed: None,
+            start,
+            end,
+            batch: Vec::new().into_iter(),
+            is_exhausted: false,
+        }
+    }
+
+    /// 只解码下标在 `needed` 中的列，其他列为 NULL，`filter` 引用的列必须包含在内
+    ///
+    /// `needed` 为 `None` 时解码所有列。
+    pub fn decode_only(mut self, needed: Option<Vec<usize>>) -> Self {
+        self.needed = needed;
+        self
+    }
+
+    /// 读取下一批行，并将扫描的起点移动到这一批的最后一个 key 之后
+    fn next_batch(&mut self) -> Result<()> {
+        let batch = self
+            .txn
+            .scan_range_limit((self.start.clone(), self.end.clone()), Self::BATCH_SIZE)
+            .with_context(|| ErrorContext::new("scanning").table(&self.table.name))?;
+        if batch.len() < Self::BATCH_SIZE {
+            self.is_exhausted = true;
+        }
+        if let Some((key, _)) = batch.last() {
+            self.start = Bound::Excluded(key.clone());
+        }
+        self.batch = batch.into_iter();
+        Ok(())
+    }
+
+    /// 解码行，并使用 `filter` 进行过滤，不满足条件时返回 `None`
+    fn decode_row(&self, key: &[u8], value: &[u8]) -> Result<Option<Row>> {
+        let row = match &self.needed {
+            Some(needed) => self.table.decode_columns_with(self.codec, value, needed),
+            None => self.table.decode_row_with(self.codec, value),
+        }
+        .with_context(|| {
+            ErrorContext::new("scanning")
+                .table(&self.table.name)
+                .key(key)
+        })?;
+        if let Some(filter) = &self.filter {
+            if !predicate_passes(evaluate(filter, &self.columns, &row)?)? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(row))
+    }
+}
+
+impl<S: Storage> Iterator for RowScan<'_, S> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, value)) = self.batch.next() {
+                match self.decode_row(&key, &value).transpose() {
+                    Some(row) => return Some(row),
+                    None => continue,
+                }
+            }
+            if self.is_exhausted {
+                return None;
+            }
+            if let Err(e) = self.next_batch() {
+                // 出错后不再继续扫描
+                self.is_exhausted = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// 索引扫描的迭代器
+///
+/// 和 [`RowScan`] 相同，每次从存储引擎中读取至多 [`IndexScan::BATCH_SIZE`] 个索引项，当前批次消费完后再读取下一批，
+/// 迭代时根据索引项中的主键读取对应的行，跳过已经被删除的行。调用方提前停止迭代时，剩余的索引项不会被读取。
+pub struct IndexScan<'a, S: Storage> {
+    txn: &'a MvccTxn<S>,
+    codec: &'a dyn ValueCodec,
+    namespace: Namespace,
+    table: Table,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    batch: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+    is_exhausted: bool,
+}
+
+impl<S: Storage> IndexScan<'_, S> {
+    /// 每批读取的索引项数
+    pub const BATCH_SIZE: usize = 128;
+
+    /// 读取下一批索引项，并将扫描的起点移动到这一批的最后一个 key 之后
+    fn next_batch(&mut self) -> Result<()> {
+        let batch = self
+            .txn
+            .scan_range_limit((self.start.clone(), self.end.clone()), Self::BATCH_SIZE)
+            .with_context(|| ErrorContext::new("scanning").table(&self.table.name))?;
+        if batch.len() < Self::BATCH_SIZE {
+            self.is_exhausted = true;
+        }
+        if let Some((key, _)) = batch.last() {
+            self.start = Bound::Excluded(key.clone());
+        }
+        self.batch = batch.into_iter();
+        Ok(())
+    }
+
+    /// 根据索引项中的主键读取行，行已经被删除时返回 `None`
+    fn fetch_row(&self, pk: &[u8]) -> Result<Option<Row>> {
+        let key =
+            Key::Row(self.table.name.clone(), bincode::deserialize(pk)?).encode(&self.namespace);
+        self.txn
+            .get(&key)?
+            .map(|data| self.table.decode_row_with(self.codec, &data))
+            .transpose()
+    }
+}
+
+impl<S: Storage> Iterator for IndexScan<'_, S> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((_, pk)) = self.batch.next() {
+                match self.fetch_row(&pk).transpose() {
+                    Some(row) => return Some(row),
+                    None => continue,
+                }
+            }
+            if self.is_exhausted {
+                return None;
+            }
+            if let Err(e) = self.next_batch() {
+                // 出错后不再继续扫描
+                self.is_exhausted = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use super::*;
     use crate::{
-        parser::ast::Constant,
-        schema::{Column, DataType},
+        codec::JsonCodec,
+        parser::ast::{Constant, Operation},
+        schema::{Column, DataType, ForeignKey},
         storage::MemoryStorage,
     };
 
@@ -221,6 +1207,7 @@ mod tests {
                 nullable: false,
                 default: None,
                 primary_key: true,
+                auto_increment: false,
             },
             Column {
                 name: "name".to_string(),
@@ -228,6 +1215,7 @@ mod tests {
                 nullable: true,
                 default: Some(Value::String("".to_string())),
                 primary_key: false,
+                auto_increment: false,
             },
         ];
         let table = Table::new("users", columns).unwrap();
@@ -266,14 +1254,28 @@ mod tests {
         let rows_scan = txn
             .scan_table(
                 &table,
-                Some((
-                    "id".to_string(),
-                    Expression::Constant(Constant::Integer(42)),
-                )),
+                Some(Expression::Operation(Operation::Equal(
+                    Box::new(Expression::Field("id".to_string())),
+                    Box::new(Expression::Constant(Constant::Integer(42))),
+                ))),
             )
             .unwrap();
         assert_eq!(rows_scan.len(), 1);
 
+        assert_eq!(
+            txn.get_row(&table, &Value::Integer(42)).unwrap(),
+            Some(rows[0].clone())
+        );
+        assert_eq!(txn.get_row(&table, &Value::Integer(43)).unwrap(), None);
+        let rows_scan = txn
+            .scan_table_range(&table, Value::Integer(43)..=Value::Integer(114514))
+            .unwrap();
+        assert_eq!(rows_scan, vec![rows[1].clone()]);
+        let rows_scan = txn
+            .scan_table_range(&table, ..Value::Integer(114514))
+            .unwrap();
+        assert_eq!(rows_scan, vec![rows[0].clone()]);
+
         txn.update_row(
             &table,
             &Value::Integer(42),
@@ -302,4 +1304,592 @@ mod tests {
             ]]
         );
     }
+
+    #[test]
+    fn test_index_range_bounds() -> Result<()> {
+        let engine = Engine::new(MemoryStorage::new());
+        let txn = engine.start_txn()?;
+
+        let columns = vec![
+            Column {
+                name: "id".to_string(),
+                data_type: DataType::Integer,
+                nullable: false,
+                default: None,
+                primary_key: true,
+                auto_increment: false,
+            },
+            Column {
+                name: "score".to_string(),
+                data_type: DataType::Integer,
+                nullable: true,
+                default: None,
+                primary_key: false,
+                auto_increment: false,
+            },
+        ];
+        txn.create_table(Table::new("scores", columns)?)?;
+        txn.create_index(
+            "scores",
+            IndexDef {
+                name: "idx_score".to_string(),
+                columns: vec!["score".to_string()],
+                unique: false,
+                predicate: None,
+            },
+        )?;
+        let table = txn.get_table("scores")?.unwrap();
+
+        // 包含重复值、负数和 NULL
+        let scores = [5, -3, 0, 5, 12, -7, 8, 0, 3, 100];
+        for (id, score) in scores.iter().enumerate() {
+            txn.create_row(
+                "scores",
+                &vec![Value::Integer(id as i64), Value::Integer(*score)],
+            )?;
+        }
+        txn.create_row("scores", &vec![Value::Integer(10), Value::Null])?;
+
+        // 扫描范围，返回索引项中的主键
+        let scan = |low: Value, high: Value| -> Result<Vec<i64>> {
+            let (start, end) = txn.index_range_bounds(&table, "score", &low, &high)?;
+            let mut ids = txn
+                .txn
+                .scan_range(start..end)?
+                .into_iter()
+                .map(|(_, pk)| bincode::deserialize::<Value>(&pk)?.as_i64())
+                .collect::<Result<Vec<_>>>()?;
+            ids.sort();
+            Ok(ids)
+        };
+        let expected = |low: Option<i64>, high: Option<i64>| {
+            scores
+                .iter()
+                .enumerate()
+                .filter(|(_, score)| low.is_none_or(|low| **score >= low))
+                .filter(|(_, score)| high.is_none_or(|high| **score <= high))
+                .map(|(id, _)| id as i64)
+                .collect::<Vec<_>>()
+        };
+
+        let bounds = [
+            None,
+            Some(-7),
+            Some(-1),
+            Some(0),
+            Some(5),
+            Some(6),
+            Some(100),
+            Some(200),
+        ];
+        for low in bounds {
+            for high in bounds {
+                let value = |bound: Option<i64>| bound.map(Value::Integer).unwrap_or(Value::Null);
+                assert_eq!(
+                    scan(value(low), value(high))?,
+                    expected(low, high),
+                    "[{:?}, {:?}]",
+                    low,
+                    high
+                );
+            }
+        }
+
+        // 列不存在、列上没有索引或者边界类型不匹配时返回错误
+        let bounds =
+            |column: &str, low: Value| txn.index_range_bounds(&table, column, &low, &Value::Null);
+        assert!(bounds("missing", Value::Integer(1)).is_err());
+        assert!(bounds("id", Value::Integer(1)).is_err());
+        assert!(bounds("score", Value::String("1".to_string())).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_context() -> Result<()> {
+        let engine = Engine::new(MemoryStorage::new());
+        let txn = engine.start_txn()?;
+        let table = Table::new(
+            "accounts",
+            vec![Column {
+                name: "id".to_string(),
+                data_type: DataType::Integer,
+                nullable: false,
+                default: None,
+                primary_key: true,
+                auto_increment: false,
+            }],
+        )?;
+        txn.create_table(table.clone())?;
+        txn.create_row("accounts", &vec![Value::Integer(1)])?;
+
+        // 写入无法解码的行和表定义，行的 key 超过了显示的长度
+        let key = Key::Row("accounts".to_string(), Value::Integer(2)).encode(&txn.namespace);
+        txn.txn.set(&key, b"bad")?;
+        txn.txn.set(
+            &Key::Table("broken".to_string()).encode(&txn.namespace),
+            b"bad",
+        )?;
+
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .take(ErrorContext::MAX_KEY_BYTES)
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        };
+        let message = txn.scan_table(&table, None).unwrap_err().to_string();
+        assert_eq!(
+            message,
+            format!(
+                "while scanning table 'accounts' at key 0x{}…: Decode error when decoding row: 0x{}",
+                hex(&key),
+                hex(b"bad")
+            )
+        );
+
+        let err = txn.get_row(&table, &Value::Integer(2)).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("while reading table 'accounts' at key 0x"));
+        assert_eq!(err.code(), crate::ErrorCode::DataCorrupted);
+
+        let message = txn.get_table("broken").unwrap_err().to_string();
+        assert!(message.starts_with("while reading definition of table 'broken': Encoding error: "));
+
+        // 正常的行不受影响
+        assert_eq!(
+            txn.get_row(&table, &Value::Integer(1))?,
+            Some(vec![Value::Integer(1)])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_codec() -> Result<()> {
+        let mut engine = Engine::new(MemoryStorage::new());
+        engine.set_codec(Arc::new(JsonCodec));
+        let txn = engine.start_txn()?;
+        let table = Table::new(
+            "users",
+            vec![
+                Column {
+                    name: "id".to_string(),
+                    data_type: DataType::Integer,
+                    nullable: false,
+                    default: None,
+                    primary_key: true,
+                    auto_increment: false,
+                },
+                Column {
+                    name: "name".to_string(),
+                    data_type: DataType::String,
+                    nullable: true,
+                    default: None,
+                    primary_key: false,
+                    auto_increment: false,
+                },
+            ],
+        )?;
+        txn.create_table(table.clone())?;
+        let row = |id, name: &str| vec![Value::Integer(id), Value::String(name.to_string())];
+        txn.create_row("users", &row(1, "alice"))?;
+        txn.create_row("users", &row(2, "bob"))?;
+        txn.update_row(&table, &Value::Integer(2), &row(2, "carol"))?;
+
+        // 存储中的行是 JSON 编码的，表定义等元数据不受影响
+        let raw = txn
+            .txn
+            .get(&Key::Row("users".to_string(), Value::Integer(1)).encode(&txn.namespace))?
+            .unwrap();
+        assert_eq!(serde_json::from_slice::<Row>(&raw)?, row(1, "alice"));
+        assert_eq!(txn.get_table("users")?.unwrap().columns.len(), 2);
+
+        assert_eq!(
+            txn.get_row(&table, &Value::Integer(2))?,
+            Some(row(2, "carol"))
+        );
+        assert_eq!(
+            txn.scan_table(&table, None)?,
+            vec![row(1, "alice"), row(2, "carol")]
+        );
+        assert_eq!(
+            txn.scan_table_iter(&table, None)
+                .decode_only(Some(vec![1]))
+                .collect::<Result<Vec<_>>>()?,
+            vec![
+                vec![Value::Null, Value::String("alice".to_string())],
+                vec![Value::Null, Value::String("carol".to_string())],
+            ]
+        );
+        txn.commit()?;
+
+        // 换回 bincode 后无法读取 JSON 编码的行
+        engine.set_codec(Arc::new(BincodeCodec));
+        let txn = engine.start_txn()?;
+        assert!(txn.scan_table(&table, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_key_namespace() -> Result<()> {
+        let column = |name: &str| Column {
+            name: name.to_string(),
+            data_type: DataType::String,
+            nullable: false,
+            default: None,
+            primary_key: true,
+            auto_increment: false,
+        };
+        let namespace = Namespace { separator: b'.' };
+
+        // 表名、列名和索引名中不能包含分隔符
+        assert!(Table::new("t\0x", vec![column("id")]).is_err());
+        assert!(Table::with_namespace("a.b", vec![column("id")], &namespace).is_err());
+        assert!(Table::with_namespace("t", vec![column("i.d")], &namespace).is_err());
+
+        let mut engine = Engine::new(MemoryStorage::new());
+        engine.set_namespace(namespace);
+        let txn = engine.start_txn()?;
+        assert!(txn
+            .create_table(Table::new("a.b", vec![column("id")])?)
+            .is_err());
+
+        // 一张表的名称是另一张表的名称的前缀时，两张表的行、索引和统计信息互不影响
+        let names = ["t", "tx", "t-"];
+        for name in names {
+            txn.create_table(Table::with_namespace(name, vec![column("id")], &namespace)?)?;
+        }
+        let index = |name: &str| IndexDef {
+            name: name.to_string(),
+            columns: vec!["id".to_string()],
+            unique: true,
+            predicate: None,
+        };
+        assert!(txn.create_index("t", index("by.id")).is_err());
+        txn.create_index("t", index("by_id"))?;
+        txn.create_index("tx", index("by_id"))?;
+        txn.create_row("t", &vec![Value::String("x".to_string())])?;
+        txn.create_row("tx", &vec![Value::String("".to_string())])?;
+        txn.create_row("tx", &vec![Value::String("x".to_string())])?;
+
+        let count = |name: &str| -> Result<usize> {
+            let table = txn.get_table(name)?.unwrap();
+            Ok(txn.scan_table(&table, None)?.len())
+        };
+        assert_eq!(
+            names
+                .iter()
+                .map(|name| count(name))
+                .collect::<Result<Vec<_>>>()?,
+            vec![1, 2, 0]
+        );
+        let table = txn.get_table("t")?.unwrap();
+        assert_eq!(txn.get_row(&table, &Value::String("".to_string()))?, None);
+        assert_eq!(
+            txn.list_tables()?
+                .iter()
+                .map(|table| table.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["t", "t-", "tx"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_index() -> Result<()> {
+        let engine = Engine::new(MemoryStorage::new());
+        let columns = vec![
+            Column {
+                name: "id".to_string(),
+                data_type: DataType::Integer,
+                nullable: false,
+                default: None,
+                primary_key: true,
+                auto_increment: false,
+            },
+            Column {
+                name: "email".to_string(),
+                data_type: DataType::String,
+                nullable: true,
+                default: None,
+                primary_key: false,
+                auto_increment: false,
+            },
+        ];
+        let row = |id: i64, email: Option<&str>| {
+            vec![
+                Value::Integer(id),
+                email.map_or(Value::Null, |email| Value::String(email.to_string())),
+            ]
+        };
+
+        let txn = engine.start_txn()?;
+        txn.create_table(Table::new("users", columns)?)?;
+        txn.create_index(
+            "users",
+            IndexDef {
+                name: "idx_email".to_string(),
+                columns: vec!["email".to_string()],
+                unique: true,
+                predicate: None,
+            },
+        )?;
+        assert_eq!(txn.get_table("users")?.map(|t| t.schema_version()), Some(1));
+        txn.create_row("users", &row(1, Some("a")))?;
+        // 错误指明约束名和重复的值，NULL 不受唯一约束限制
+        assert_eq!(
+            txn.create_row("users", &row(2, Some("a"))),
+            Err(UniqueViolation {
+                constraint: "idx_email".to_string(),
+                table: "users".to_string(),
+                values: vec![Value::String("a".to_string())],
+            }
+            .into())
+        );
+        txn.create_row("users", &row(3, None))?;
+        txn.create_row("users", &row(4, None))?;
+        txn.commit()?;
+
+        // 两个并发事务插入相同的值，彼此都看不到对方的写入，后写入的事务产生写冲突
+        let txn1 = engine.start_txn()?;
+        let txn2 = engine.start_txn()?;
+        txn1.create_row("users", &row(5, Some("b")))?;
+        assert_eq!(
+            txn2.create_row("users", &row(6, Some("b"))),
+            Err(crate::TransactionError::WriteConflict.into())
+        );
+        txn2.rollback()?;
+        txn1.commit()?;
+
+        // 删除后值可以被其他行使用
+        let txn = engine.start_txn()?;
+        let table = txn.get_table("users")?.unwrap();
+        let index = table.get_index("idx_email").unwrap();
+        assert_eq!(
+            txn.unique_lookup(&table, index, &row(0, Some("b")))?,
+            Some(row(5, Some("b")))
+        );
+        txn.delete_row(&table, &Value::Integer(1))?;
+        txn.create_row("users", &row(7, Some("a")))?;
+        assert_eq!(
+            txn.scan_index(&table, index, &[], (Bound::Unbounded, Bound::Unbounded))?,
+            vec![
+                row(3, None),
+                row(4, None),
+                row(7, Some("a")),
+                row(5, Some("b"))
+            ]
+        );
+        txn.commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_foreign_keys() -> Result<()> {
+        let engine = Engine::new(MemoryStorage::new());
+        let column = |name: &str, nullable: bool, primary_key: bool| Column {
+            name: name.to_string(),
+            data_type: DataType::Integer,
+            nullable,
+            default: None,
+            primary_key,
+            auto_increment: false,
+        };
+        let int = Value::Integer;
+
+        let txn = engine.start_txn()?;
+        txn.create_table(Table::new("parent", vec![column("id", false, true)])?)?;
+        let mut child = Table::new(
+            "child",
+            vec![column("id", false, true), column("parent_id", true, false)],
+        )?;
+        child.foreign_keys.push(ForeignKey {
+            name: "fk_parent".to_string(),
+            column: "parent_id".to_string(),
+            parent_table: "parent".to_string(),
+            on_delete: Default::default(),
+        });
+        txn.create_table(child)?;
+
+        // 直接通过事务写入的行不检查外键，NULL 不引用任何行
+        txn.create_row("parent", &vec![int(1)])?;
+        for (id, parent_id) in [(1, int(1)), (2, Value::Null), (3, int(9)), (4, int(7))] {
+            txn.create_row("child", &vec![int(id), parent_id])?;
+        }
+        let violation = |id: i64, key: i64| ReferenceViolation {
+            constraint: "fk_parent".to_string(),
+            table: "child".to_string(),
+            column: "parent_id".to_string(),
+            parent_table: "parent".to_string(),
+            primary_key: int(id),
+            key: int(key),
+        };
+        // 报告所有违反约束的行，而不是只报告第一个
+        assert_eq!(
+            txn.check_foreign_keys()?,
+            vec![violation(3, 9), violation(4, 7)]
+        );
+        txn.commit()?;
+
+        // 只检查事务可见的数据
+        let txn1 = engine.start_txn()?;
+        let txn2 = engine.start_txn()?;
+        let parent = txn1.get_table("parent")?.unwrap();
+        txn1.delete_row(&parent, &int(1))?;
+        txn1.create_row("parent", &vec![int(9)])?;
+        assert_eq!(
+            txn1.check_foreign_keys()?,
+            vec![violation(1, 1), violation(4, 7)]
+        );
+        assert_eq!(
+            txn2.check_foreign_keys()?,
+            vec![violation(3, 9), violation(4, 7)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allocate_id() -> Result<()> {
+        let engine = Engine::new(MemoryStorage::new());
+        let column = |name: &str, auto_increment: bool| Column {
+            name: name.to_string(),
+            data_type: DataType::Integer,
+            nullable: false,
+            default: None,
+            primary_key: true,
+            auto_increment,
+        };
+        let table = Table::new("t", vec![column("id", true)])?;
+        let txn = engine.start_txn()?;
+        txn.create_table(table.clone())?;
+        txn.create_table(Table::new("plain", vec![column("id", false)])?)?;
+        assert!(txn.allocate_id("missing").is_err());
+        // 没有自增列的表没有计数器，显式写入的 ID 也不会推进其他表的计数器
+        assert_eq!(
+            txn.allocate_id("plain").unwrap_err().code(),
+            crate::ErrorCode::InvalidDefinition
+        );
+        txn.create_row("plain", &vec![Value::Integer(1000)])?;
+        txn.commit()?;
+
+        // 并发事务分配的 ID 互不重复，也不会产生写冲突
+        let ids = std::thread::scope(|scope| {
+            let handles = (0..16)
+                .map(|_| {
+                    scope.spawn(|| -> Result<i64> {
+                        let txn = engine.start_txn()?;
+                        let mut row = vec![Value::Null];
+                        let id = txn.fill_auto_increment(&table, &mut row)?.unwrap();
+                        txn.create_row("t", &row)?;
+                        txn.commit()?;
+                        Ok(id)
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Result<HashSet<_>>>()
+        })?;
+        assert_eq!(ids, (1..=16).collect());
+
+        // 回滚的事务分配的 ID 留下空洞，但不会再被分配
+        let txn = engine.start_txn()?;
+        assert_eq!(txn.allocate_id("t")?, 17);
+        txn.rollback()?;
+        let txn = engine.start_txn()?;
+        assert_eq!(txn.allocate_id("t")?, 18);
+
+        // 显式插入的 ID 在提交之后才推进计数器，回滚的事务不会推进计数器
+        txn.create_row("t", &vec![Value::Integer(100)])?;
+        txn.rollback()?;
+        let txn = engine.start_txn()?;
+        assert_eq!(txn.allocate_id("t")?, 19);
+        txn.create_row("t", &vec![Value::Integer(50)])?;
+        txn.commit()?;
+        let txn = engine.start_txn()?;
+        assert_eq!(txn.allocate_id("t")?, 51);
+
+        // 同一个事务中分配 ID 时跳过事务中显式写入的 ID
+        txn.create_row("t", &vec![Value::Integer(100)])?;
+        assert_eq!(txn.allocate_id("t")?, 101);
+        assert_eq!(txn.scan_table(&table, None)?.len(), 18);
+
+        // 删除表时重置计数器，重新创建的同名表从 1 开始分配
+        txn.drop_table("t")?;
+        assert!(txn.get_table("t")?.is_none());
+        txn.create_table(table.clone())?;
+        assert_eq!(txn.scan_table(&table, None)?, Vec::<Row>::new());
+        txn.commit()?;
+        let txn = engine.start_txn()?;
+        assert_eq!(txn.allocate_id("t")?, 1);
+        txn.commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bulk_insert() -> Result<()> {
+        let mut engine = Engine::new(MemoryStorage::new());
+        engine.set_codec(Arc::new(JsonCodec));
+        engine.set_namespace(Namespace { separator: b'.' });
+        let column = |name: &str, data_type, primary_key| Column {
+            name: name.to_string(),
+            data_type,
+            nullable: false,
+            default: None,
+            primary_key,
+            auto_increment: primary_key,
+        };
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("name", DataType::String, false),
+            ],
+        )?;
+        let txn = engine.start_txn()?;
+        txn.create_table(table.clone())?;
+        txn.commit()?;
+        let row = |id: i64| vec![Value::Integer(id), Value::String(format!("user{id}"))];
+
+        // 写入的行使用引擎的编码和命名空间，可以通过事务读取
+        assert_eq!(engine.bulk_insert("users", (1..=3).map(row), true)?, 3);
+        let txn = engine.start_txn()?;
+        assert_eq!(
+            txn.scan_table(&table, None)?,
+            (1..=3).map(row).collect::<Vec<_>>()
+        );
+        // 计数器推进到写入的最大 ID
+        assert_eq!(txn.allocate_id("users")?, 4);
+        txn.commit()?;
+
+        // 表不存在或者有二级索引时返回错误
+        assert!(engine.bulk_insert("missing", [row(1)], true).is_err());
+        let txn = engine.start_txn()?;
+        txn.create_index(
+            "users",
+            IndexDef {
+                name: "idx_name".to_string(),
+                columns: vec!["name".to_string()],
+                unique: false,
+                predicate: None,
+            },
+        )?;
+        txn.commit()?;
+        let e = engine.bulk_insert("users", [row(10)], true).unwrap_err();
+        assert_eq!(e.code(), crate::ErrorCode::InvalidArgument);
+        let txn = engine.start_txn()?;
+        assert_eq!(txn.get_row(&table, &Value::Integer(10))?, None);
+        assert_eq!(txn.allocate_id("users")?, 5);
+        txn.commit()?;
+
+        Ok(())
+    }
 }
@@ -1,48 +1,1039 @@
 use std::{
+    fmt,
     num::{ParseFloatError, ParseIntError},
-    sync::PoisonError,
+    sync::{Arc, PoisonError},
+    time::Duration,
 };
 
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 use thiserror::Error;
 
+use crate::{
+    schema::{DataType, Value},
+    storage::Version,
+};
+
+/// 数据库的错误，按照出错的阶段分类，每一类中的错误带有结构化的信息，便于调用方按照变体处理
 #[derive(Debug, Error, PartialEq)]
 pub enum Error {
+    /// SQL 文本的词法或语法错误
     #[error("Parse error: {0}")]
-    ParseError(String),
+    Parse(String),
+    /// 表、列、索引和函数等对象不存在、重复或者定义不合法
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+    /// 语句无法生成执行计划，例如列名有歧义、聚集函数出现在不允许的位置
+    #[error(transparent)]
+    Plan(#[from] PlanError),
+    /// 执行语句时计算失败、违反约束或者被取消
+    #[error(transparent)]
+    Execution(#[from] ExecutionError),
+    /// 存储引擎的读写或者编解码失败
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    /// 事务冲突或者事务的状态不允许当前操作
+    #[error(transparent)]
+    Transaction(#[from] TransactionError),
+    /// 附加了上下文的错误，`source` 为原始的错误，通过 [`ResultExt`] 添加
+    #[error("{context}: {source}")]
+    Context {
+        context: ErrorContext,
+        source: Box<Error>,
+    },
+    /// 多条语句组成的脚本中某一条语句出错，`position` 为出错的位置，`source` 为原始的错误
+    #[error("{position}: {source}\n{}", .position.snippet)]
+    Script {
+        position: ScriptPosition,
+        source: Box<Error>,
+    },
+    /// 和服务端的连接失败或者中断，请求可能已经执行，也可能没有执行
+    #[error("Connection error: {0}")]
+    Connection(String),
+    /// 服务端执行请求失败，编码、分类和错误信息由服务端返回，`position` 为脚本中出错的位置
+    #[error("{message}")]
+    Remote {
+        code: ErrorCode,
+        retryability: Retryability,
+        message: String,
+        position: Option<ScriptPosition>,
+    },
+    /// 其他线程在持有共享状态的锁时 panic，锁已经中毒，共享的状态可能只修改了一半
+    ///
+    /// 之后对同一个数据库的操作都会返回这个错误，需要重新打开数据库才能恢复。
+    #[error("Engine panicked while holding a lock ({0}); reopen the database to recover")]
+    EnginePanicked(String),
+    /// 违反了内部的不变量，说明存在 bug，不应该由用户的输入触发
     #[error("Internal error: {0}")]
-    InternalError(String),
+    Internal(String),
+}
+
+impl Error {
+    /// 原样重新执行整个事务是否可能成功，即 [`Error::retryability`] 为 [`Retryability::Retryable`]
+    pub fn is_retryable(&self) -> bool {
+        self.retryability() == Retryability::Retryable
+    }
+
+    /// 错误能否通过重试恢复，上下文不影响分类
+    ///
+    /// - [`Retryability::Retryable`]：写冲突（[`TransactionError::WriteConflict`]）和无法串行化
+    ///   （[`TransactionError::Serialization`]），冲突的事务结束后原样重试可能成功；
+    /// - [`Retryability::RetryableAfterReread`]：主键重复（[`ExecutionError::DuplicateKey`]）、
+    ///   违反唯一约束（[`ExecutionError::UniqueViolation`]）、违反外键约束
+    ///   （[`ExecutionError::ForeignKeyViolation`]），以及存储中 key 已经存在或者不存在
+    ///   （[`StorageError::KeyExists`]、[`StorageError::KeyNotFound`]），冲突的数据可能由并发的事务写入，
+    ///   重新读取后按照新的数据决定写入的内容可能成功，原样重试通常会再次失败；
+    /// - [`Retryability::Fatal`]：其他所有错误，包括语法、对象定义和计划错误，计算错误，
+    ///   语句被取消或者超时，数据损坏和 IO 错误，事务状态错误，连接错误以及内部错误。
+    ///
+    /// 服务端返回的错误（[`Error::Remote`]）使用服务端给出的分类。
+    pub fn retryability(&self) -> Retryability {
+        match self.root() {
+            Error::Transaction(
+                TransactionError::WriteConflict | TransactionError::Serialization,
+            ) => Retryability::Retryable,
+            Error::Execution(
+                ExecutionError::DuplicateKey { .. }
+                | ExecutionError::UniqueViolation { .. }
+                | ExecutionError::ForeignKeyViolation { .. },
+            )
+            | Error::Storage(StorageError::KeyExists(_) | StorageError::KeyNotFound(_)) => {
+                Retryability::RetryableAfterReread
+            }
+            Error::Remote { retryability, .. } => *retryability,
+            _ => Retryability::Fatal,
+        }
+    }
+
+    /// 去掉所有上下文和脚本中的位置后的原始错误
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } | Error::Script { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// 错误的稳定编码，供客户端按照编码而不是错误信息识别错误，上下文不影响编码
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::Parse(_) => ErrorCode::SyntaxError,
+            Error::Schema(err) => match err {
+                SchemaError::TableNotFound(_) => ErrorCode::UndefinedTable,
+                SchemaError::TableExists(_) => ErrorCode::DuplicateTable,
+                SchemaError::ColumnNotFound { .. } => ErrorCode::UndefinedColumn,
+                SchemaError::IndexNotFound { .. } | SchemaError::NoIndexOnColumn { .. } => {
+                    ErrorCode::UndefinedIndex
+                }
+                SchemaError::IndexExists { .. } => ErrorCode::DuplicateIndex,
+                SchemaError::FunctionNotFound(_) => ErrorCode::UndefinedFunction,
+                SchemaError::FunctionExists(_) => ErrorCode::DuplicateFunction,
+                SchemaError::ReservedFunctionName(_) => ErrorCode::ReservedName,
+                SchemaError::InvalidDefinition(_) => ErrorCode::InvalidDefinition,
+            },
+            Error::Plan(err) => match err {
+                PlanError::ColumnNotFound(_) => ErrorCode::UndefinedColumn,
+                PlanError::AmbiguousColumn(_) => ErrorCode::AmbiguousColumn,
+                PlanError::InvalidColumnName(_) => ErrorCode::InvalidName,
+                PlanError::ArgumentCount { .. } => ErrorCode::ArgumentCountMismatch,
+                PlanError::Unsupported(_) => ErrorCode::FeatureNotSupported,
+                PlanError::Invalid(_) => ErrorCode::InvalidStatement,
+            },
+            Error::Execution(err) => match err {
+                ExecutionError::TypeMismatch(_) => ErrorCode::DatatypeMismatch,
+                ExecutionError::InvalidColumnValue { found: None, .. }
+                | ExecutionError::MissingValue(_) => ErrorCode::NotNullViolation,
+                ExecutionError::InvalidColumnValue { .. } => ErrorCode::DatatypeMismatch,
+                ExecutionError::InvalidCast { .. } => ErrorCode::InvalidCast,
+                ExecutionError::DivisionByZero => ErrorCode::DivisionByZero,
+                ExecutionError::Overflow(_) => ErrorCode::NumericOverflow,
+                ExecutionError::DuplicateKey { .. } | ExecutionError::UniqueViolation { .. } => {
+                    ErrorCode::UniqueViolation
+                }
+                ExecutionError::ForeignKeyViolation { .. } => ErrorCode::ForeignKeyViolation,
+                ExecutionError::RowLength { .. } | ExecutionError::ValueCount { .. } => {
+                    ErrorCode::ValueCountMismatch
+                }
+                ExecutionError::SubqueryRows(_) => ErrorCode::CardinalityViolation,
+                ExecutionError::SubqueryColumns(_) => ErrorCode::InvalidStatement,
+                ExecutionError::PreparedStatementNotFound(_) => {
+                    ErrorCode::UndefinedPreparedStatement
+                }
+                ExecutionError::ParameterCount { .. } => ErrorCode::ValueCountMismatch,
+                ExecutionError::UnboundParameter(_) => ErrorCode::UndefinedParameter,
+                ExecutionError::ResultColumnNotFound(_) | ExecutionError::RowOutOfRange(_) => {
+                    ErrorCode::InvalidResultAccess
+                }
+                ExecutionError::Cancelled => ErrorCode::QueryCanceled,
+                ExecutionError::Timeout => ErrorCode::StatementTimeout,
+            },
+            Error::Storage(err) => match err {
+                StorageError::Decode { .. } | StorageError::Encoding(_) => ErrorCode::DataCorrupted,
+                StorageError::Io(_) => ErrorCode::IoError,
+                StorageError::KeyNotFound(_) => ErrorCode::KeyNotFound,
+                StorageError::KeyExists(_) => ErrorCode::KeyExists,
+                StorageError::SequenceOverflow => ErrorCode::SequenceOverflow,
+                StorageError::VersionExhausted => ErrorCode::VersionExhausted,
+                StorageError::InvalidArgument(_) => ErrorCode::InvalidArgument,
+            },
+            Error::Transaction(err) => match err {
+                TransactionError::WriteConflict => ErrorCode::WriteConflict,
+                TransactionError::Serialization => ErrorCode::SerializationFailure,
+                TransactionError::ReadOnly => ErrorCode::ReadOnlyTransaction,
+                TransactionError::Closed => ErrorCode::NoActiveTransaction,
+                TransactionError::AlreadyStarted => ErrorCode::ActiveTransaction,
+                TransactionError::Inactive(_) => ErrorCode::InvalidTransactionState,
+                TransactionError::Expired { .. } => ErrorCode::TransactionExpired,
+            },
+            Error::Context { source, .. } | Error::Script { source, .. } => source.code(),
+            Error::Connection(_) => ErrorCode::ConnectionFailure,
+            Error::Remote { code, .. } => *code,
+            Error::EnginePanicked(_) => ErrorCode::EnginePanicked,
+            Error::Internal(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+/// 错误能否通过重试恢复，由 [`Error::retryability`] 给出
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Retryability {
+    /// 原样重新执行整个事务可能成功
+    Retryable,
+    /// 重新读取数据、据此决定写入的内容后重新执行事务可能成功
+    RetryableAfterReread,
+    /// 重试不会改变结果
+    Fatal,
+}
+
+/// 错误发生时的上下文，包括正在进行的操作，以及涉及的表、key 和导入文件的行号
+///
+/// 显示为 `while scanning table 'users' at key 0x7573…`、`while reading version of key 0x01` 或者
+/// `while importing table 'users' at line 3`，key 只显示前 [`ErrorContext::MAX_KEY_BYTES`] 个字节的十六进制。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorContext {
+    operation: &'static str,
+    table: Option<String>,
+    key: Option<Vec<u8>>,
+    line: Option<u64>,
+}
+
+impl ErrorContext {
+    /// 显示 key 时最多显示的字节数
+    pub const MAX_KEY_BYTES: usize = 16;
+
+    /// 创建操作为 `operation` 的上下文，例如 `"scanning"`
+    pub fn new(operation: &'static str) -> Self {
+        Self {
+            operation,
+            table: None,
+            key: None,
+            line: None,
+        }
+    }
+
+    /// 设置涉及的表
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.table = Some(table.into());
+        self
+    }
+
+    /// 设置涉及的 key，只保留显示需要的前缀
+    pub fn key(mut self, key: &[u8]) -> Self {
+        // 多保留一个字节，用于判断显示时是否被截断
+        self.key = Some(key[..key.len().min(Self::MAX_KEY_BYTES + 1)].to_vec());
+        self
+    }
+
+    /// 设置出错的数据在导入的文件中的行号，从 1 开始
+    pub fn line(mut self, line: u64) -> Self {
+        self.line = Some(line);
+        self
+    }
+}
+
+impl From<&'static str> for ErrorContext {
+    fn from(operation: &'static str) -> Self {
+        Self::new(operation)
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "while {}", self.operation)?;
+        if let Some(table) = &self.table {
+            write!(f, " table '{}'", table)?;
+        }
+        if let Some(key) = &self.key {
+            let at = if self.table.is_some() { " at" } else { "" };
+            write!(f, "{} key ", at)?;
+            write_hex(f, key, Self::MAX_KEY_BYTES)?;
+        }
+        if let Some(line) = self.line {
+            write!(f, " at line {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// 以 `0x` 开头的十六进制写入 `bytes` 的前 `limit` 个字节，超出的部分显示为 `…`
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8], limit: usize) -> fmt::Result {
+    f.write_str("0x")?;
+    for byte in bytes.iter().take(limit) {
+        write!(f, "{:02x}", byte)?;
+    }
+    if bytes.len() > limit {
+        f.write_str("…")?;
+    }
+    Ok(())
+}
+
+/// 在错误信息中显示二进制数据，最多显示 [`Hex::LIMIT`] 个字节
+struct Hex<'a>(&'a [u8]);
+
+impl Hex<'_> {
+    const LIMIT: usize = 64;
+}
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, self.0, Self::LIMIT)?;
+        if self.0.len() > Self::LIMIT {
+            write!(f, " ({} bytes)", self.0.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// 错误在多条语句组成的脚本中的位置
+///
+/// 显示为 `statement 2 at line 3, column 8`，语句的序号和行列号都从 1 开始。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptPosition {
+    /// 出错的语句在脚本中的下标，从 0 开始
+    pub statement_index: usize,
+    /// 出错的位置所在的行，从 1 开始
+    pub line: usize,
+    /// 出错的位置在行中的列，按照字符计算，从 1 开始
+    pub column: usize,
+    /// 出错的位置所在的行，下一行用 `^` 指向出错的列
+    pub snippet: String,
+}
+
+impl ScriptPosition {
+    /// 根据出错的位置在脚本 `script` 中的字节偏移 `offset` 计算行列号和出错的行
+    pub fn new(script: &str, statement_index: usize, offset: usize) -> Self {
+        let offset = offset.min(script.len());
+        let line_start = script[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+        let line_end = script[offset..]
+            .find('\n')
+            .map_or(script.len(), |idx| offset + idx);
+        let line = script[..line_start].matches('\n').count() + 1;
+        let column = script[line_start..offset].chars().count() + 1;
+        let text = script[line_start..line_end].trim_end_matches('\r');
+        Self {
+            statement_index,
+            line,
+            column,
+            snippet: format!("{}\n{}^", text, " ".repeat(column - 1)),
+        }
+    }
+}
+
+impl fmt::Display for ScriptPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "statement {} at line {}, column {}",
+            self.statement_index + 1,
+            self.line,
+            self.column
+        )
+    }
+}
+
+/// 为 [`Result`] 的错误附加上下文
+pub trait ResultExt<T> {
+    /// 出错时附加上下文 `context`
+    fn context(self, context: impl Into<ErrorContext>) -> Result<T>;
+
+    /// 出错时附加 `f` 返回的上下文，只在出错时构造上下文
+    fn with_context(self, f: impl FnOnce() -> ErrorContext) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for std::result::Result<T, E> {
+    fn context(self, context: impl Into<ErrorContext>) -> Result<T> {
+        self.with_context(|| context.into())
+    }
+
+    fn with_context(self, f: impl FnOnce() -> ErrorContext) -> Result<T> {
+        self.map_err(|err| Error::Context {
+            context: f(),
+            source: Box::new(err.into()),
+        })
+    }
+}
+
+/// 序列化为包含编码、SQLSTATE、能否重试和错误信息的对象，例如
+/// `{"code":"unique_violation","sqlstate":"23505","retryability":"retryable_after_reread","message":"..."}`
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let code = self.code();
+        let mut state = serializer.serialize_struct("Error", 4)?;
+        state.serialize_field("code", &code)?;
+        state.serialize_field("sqlstate", &code.sqlstate())?;
+        state.serialize_field("retryability", &self.retryability())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// 错误的稳定编码
+///
+/// 错误信息的措辞可能随版本变化，编码不会：已有变体的字符串表示（[`ErrorCode::as_str`]）和
+/// SQLSTATE（[`ErrorCode::sqlstate`]）一经发布就不再修改，以后只会新增变体，
+/// 因此客户端匹配时需要处理未知的编码。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ErrorCode {
+    SyntaxError,
+    UndefinedTable,
+    DuplicateTable,
+    UndefinedColumn,
+    UndefinedIndex,
+    DuplicateIndex,
+    UndefinedFunction,
+    DuplicateFunction,
+    ReservedName,
+    InvalidDefinition,
+    AmbiguousColumn,
+    InvalidName,
+    ArgumentCountMismatch,
+    FeatureNotSupported,
+    InvalidStatement,
+    DatatypeMismatch,
+    InvalidCast,
+    DivisionByZero,
+    NumericOverflow,
+    NotNullViolation,
+    UniqueViolation,
+    ForeignKeyViolation,
+    ValueCountMismatch,
+    CardinalityViolation,
+    UndefinedPreparedStatement,
+    UndefinedParameter,
+    InvalidResultAccess,
+    QueryCanceled,
+    StatementTimeout,
+    DataCorrupted,
+    IoError,
+    KeyNotFound,
+    KeyExists,
+    SequenceOverflow,
+    VersionExhausted,
+    InvalidArgument,
+    WriteConflict,
+    SerializationFailure,
+    ReadOnlyTransaction,
+    NoActiveTransaction,
+    ActiveTransaction,
+    InvalidTransactionState,
+    TransactionExpired,
+    ConnectionFailure,
+    TooManyConnections,
+    EnginePanicked,
+    Internal,
+}
+
+impl ErrorCode {
+    /// 编码的字符串表示，和序列化的结果相同
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::SyntaxError => "syntax_error",
+            ErrorCode::UndefinedTable => "undefined_table",
+            ErrorCode::DuplicateTable => "duplicate_table",
+            ErrorCode::UndefinedColumn => "undefined_column",
+            ErrorCode::UndefinedIndex => "undefined_index",
+            ErrorCode::DuplicateIndex => "duplicate_index",
+            ErrorCode::UndefinedFunction => "undefined_function",
+            ErrorCode::DuplicateFunction => "duplicate_function",
+            ErrorCode::ReservedName => "reserved_name",
+            ErrorCode::InvalidDefinition => "invalid_definition",
+            ErrorCode::AmbiguousColumn => "ambiguous_column",
+            ErrorCode::InvalidName => "invalid_name",
+            ErrorCode::ArgumentCountMismatch => "argument_count_mismatch",
+            ErrorCode::FeatureNotSupported => "feature_not_supported",
+            ErrorCode::InvalidStatement => "invalid_statement",
+            ErrorCode::DatatypeMismatch => "datatype_mismatch",
+            ErrorCode::InvalidCast => "invalid_cast",
+            ErrorCode::DivisionByZero => "division_by_zero",
+            ErrorCode::NumericOverflow => "numeric_overflow",
+            ErrorCode::NotNullViolation => "not_null_violation",
+            ErrorCode::UniqueViolation => "unique_violation",
+            ErrorCode::ForeignKeyViolation => "foreign_key_violation",
+            ErrorCode::ValueCountMismatch => "value_count_mismatch",
+            ErrorCode::CardinalityViolation => "cardinality_violation",
+            ErrorCode::UndefinedPreparedStatement => "undefined_prepared_statement",
+            ErrorCode::UndefinedParameter => "undefined_parameter",
+            ErrorCode::InvalidResultAccess => "invalid_result_access",
+            ErrorCode::QueryCanceled => "query_canceled",
+            ErrorCode::StatementTimeout => "statement_timeout",
+            ErrorCode::DataCorrupted => "data_corrupted",
+            ErrorCode::IoError => "io_error",
+            ErrorCode::KeyNotFound => "key_not_found",
+            ErrorCode::KeyExists => "key_exists",
+            ErrorCode::SequenceOverflow => "sequence_overflow",
+            ErrorCode::VersionExhausted => "version_exhausted",
+            ErrorCode::InvalidArgument => "invalid_argument",
+            ErrorCode::WriteConflict => "write_conflict",
+            ErrorCode::SerializationFailure => "serialization_failure",
+            ErrorCode::ReadOnlyTransaction => "read_only_transaction",
+            ErrorCode::NoActiveTransaction => "no_active_transaction",
+            ErrorCode::ActiveTransaction => "active_transaction",
+            ErrorCode::InvalidTransactionState => "invalid_transaction_state",
+            ErrorCode::TransactionExpired => "transaction_expired",
+            ErrorCode::ConnectionFailure => "connection_failure",
+            ErrorCode::TooManyConnections => "too_many_connections",
+            ErrorCode::EnginePanicked => "engine_panicked",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    /// 对应的标准 SQLSTATE，没有合适的标准编码时返回 `None`
+    ///
+    /// 写冲突和串行化失败都对应 40001，客户端可以按照 SQLSTATE 统一重试。
+    pub fn sqlstate(&self) -> Option<&'static str> {
+        let sqlstate = match self {
+            ErrorCode::SyntaxError => "42601",
+            ErrorCode::UndefinedTable => "42P01",
+            ErrorCode::DuplicateTable => "42P07",
+            ErrorCode::UndefinedColumn => "42703",
+            ErrorCode::UndefinedIndex => "42704",
+            ErrorCode::DuplicateIndex => "42710",
+            ErrorCode::UndefinedFunction => "42883",
+            ErrorCode::DuplicateFunction => "42723",
+            ErrorCode::ReservedName => "42939",
+            ErrorCode::InvalidDefinition => "42P16",
+            ErrorCode::AmbiguousColumn => "42702",
+            ErrorCode::InvalidName => "42602",
+            ErrorCode::FeatureNotSupported => "0A000",
+            ErrorCode::InvalidStatement => "42000",
+            ErrorCode::DatatypeMismatch => "42804",
+            ErrorCode::InvalidCast => "22018",
+            ErrorCode::DivisionByZero => "22012",
+            ErrorCode::NumericOverflow => "22003",
+            ErrorCode::NotNullViolation => "23502",
+            ErrorCode::UniqueViolation | ErrorCode::KeyExists => "23505",
+            ErrorCode::ForeignKeyViolation => "23503",
+            ErrorCode::CardinalityViolation => "21000",
+            ErrorCode::UndefinedPreparedStatement => "26000",
+            ErrorCode::UndefinedParameter => "42P02",
+            ErrorCode::QueryCanceled | ErrorCode::StatementTimeout => "57014",
+            ErrorCode::DataCorrupted => "XX001",
+            ErrorCode::IoError => "58030",
+            ErrorCode::SequenceOverflow => "2200H",
+            ErrorCode::InvalidArgument => "22023",
+            ErrorCode::WriteConflict | ErrorCode::SerializationFailure => "40001",
+            ErrorCode::ReadOnlyTransaction => "25006",
+            ErrorCode::NoActiveTransaction => "25P01",
+            ErrorCode::ActiveTransaction => "25001",
+            ErrorCode::InvalidTransactionState => "25000",
+            ErrorCode::TransactionExpired => "25P04",
+            ErrorCode::ConnectionFailure => "08006",
+            ErrorCode::TooManyConnections => "53300",
+            ErrorCode::EnginePanicked | ErrorCode::Internal => "XX000",
+            ErrorCode::ArgumentCountMismatch
+            | ErrorCode::ValueCountMismatch
+            | ErrorCode::InvalidResultAccess
+            | ErrorCode::KeyNotFound
+            | ErrorCode::VersionExhausted => return None,
+        };
+        Some(sqlstate)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 表、列、索引和函数等对象的错误
+#[derive(Debug, Error, PartialEq)]
+pub enum SchemaError {
+    #[error("Table {0} not found")]
+    TableNotFound(String),
+    #[error("Table {0} already exists")]
+    TableExists(String),
+    #[error("Column {column} not found in table {table}")]
+    ColumnNotFound { table: String, column: String },
+    #[error("Index {index} not found in table {table}")]
+    IndexNotFound { table: String, index: String },
+    #[error("Index {index} already exists in table {table}")]
+    IndexExists { table: String, index: String },
+    #[error("No index on column {column} in table {table}")]
+    NoIndexOnColumn { table: String, column: String },
+    #[error("Function {0} not found")]
+    FunctionNotFound(String),
+    #[error("Function {0} already exists")]
+    FunctionExists(String),
+    #[error("Function name {0} is reserved")]
+    ReservedFunctionName(String),
+    /// 表、索引或者外键的定义不合法，例如没有主键、默认值和列的类型不符
+    #[error("{0}")]
+    InvalidDefinition(String),
+}
+
+/// 生成执行计划时的错误
+#[derive(Debug, Error, PartialEq)]
+pub enum PlanError {
+    #[error("Column {0} not found in table")]
+    ColumnNotFound(String),
+    #[error("Column {0} is ambiguous in table")]
+    AmbiguousColumn(String),
+    #[error("Invalid column name {0}")]
+    InvalidColumnName(String),
+    #[error("Function {function} expects {expected} arguments, got {got}")]
+    ArgumentCount {
+        function: String,
+        expected: usize,
+        got: usize,
+    },
+    /// 语法上合法但是尚未支持的用法，例如相关子查询
+    #[error("{0} is not yet supported")]
+    Unsupported(String),
+    /// 语句的结构不合法，例如 SELECT 的列没有出现在 GROUP BY 中
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// 执行语句时的错误
+#[derive(Debug, Error, PartialEq)]
+pub enum ExecutionError {
+    /// 运算的操作数类型不符合要求，例如字符串和数值比较、对字符串做算术运算
+    #[error("Type mismatch: {0}")]
+    TypeMismatch(String),
+    #[error("Cannot convert {value:?} to {target}")]
+    InvalidCast { value: Value, target: &'static str },
+    #[error("Division by zero")]
+    DivisionByZero,
+    /// 整数运算溢出，包含溢出的运算
+    #[error("Integer overflow when computing {0}")]
+    Overflow(String),
+    #[error("Primary key {key:?} in table {table} already exists")]
+    DuplicateKey { table: String, key: Value },
+    /// 违反外键约束，`table` 为定义约束的表，`key` 为引用的父表主键
+    #[error("Foreign key {constraint} on table {table} violated by key {key}")]
+    ForeignKeyViolation {
+        constraint: String,
+        table: String,
+        key: Value,
+    },
+    /// 违反唯一约束，`constraint` 为唯一索引名，`values` 为重复的索引列的值
+    #[error("Duplicate value {values:?} violates unique constraint {constraint} on table {table}")]
+    UniqueViolation {
+        constraint: String,
+        table: String,
+        values: Vec<Value>,
+    },
+    /// 行中第 `index` 列 `column` 的值不符合列的定义，`found` 为 `None` 时值为 NULL
+    #[error(
+        "column '{column}' (index {index}): expected {expected:?}, found {}",
+        .found.map_or("NULL".to_string(), |found| format!("{:?}", found))
+    )]
+    InvalidColumnValue {
+        column: String,
+        index: usize,
+        expected: DataType,
+        found: Option<DataType>,
+    },
+    #[error("Row has {found} values, but table {table} has {expected} columns")]
+    RowLength {
+        table: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Column count {columns} doesn't match value count {values}")]
+    ValueCount { columns: usize, values: usize },
+    /// 插入时没有给出值的列也没有默认值
+    #[error("Column {0} not found in value")]
+    MissingValue(String),
+    #[error("Scalar subquery returned {0} rows, expected at most one")]
+    SubqueryRows(usize),
+    #[error("Subquery must return exactly one column, got {0}")]
+    SubqueryColumns(usize),
+    #[error("Prepared statement {0} not found")]
+    PreparedStatementNotFound(String),
+    /// 执行预处理语句时给出的参数个数和语句中的占位符不符
+    #[error("Statement expects {expected} parameters, got {found}")]
+    ParameterCount { expected: usize, found: usize },
+    /// 参数占位符没有绑定值，例如直接执行包含占位符的语句
+    #[error("No value bound to parameter ${0}")]
+    UnboundParameter(usize),
+    /// 按照列名读取查询结果时列不存在
+    #[error("Column {0} not found")]
+    ResultColumnNotFound(String),
+    #[error("Row {0} out of range")]
+    RowOutOfRange(usize),
+    /// 语句被 [`CancellationToken`](crate::executor::CancellationToken) 取消
+    #[error("Statement cancelled")]
+    Cancelled,
+    /// 语句超过了截止时间
+    #[error("Statement timeout")]
+    Timeout,
+}
+
+/// 存储引擎的错误
+#[derive(Debug, Error, PartialEq)]
+pub enum StorageError {
+    #[error("Decode error when {context}: {}", Hex(.bytes))]
+    Decode {
+        context: &'static str,
+        bytes: Vec<u8>,
+    },
+    /// 序列化或者反序列化存储的值失败
+    #[error("Encoding error: {0}")]
+    Encoding(#[source] SourceError),
+    #[error("IO error: {0}")]
+    Io(#[source] SourceError),
+    #[error("Key {} not found", Hex(.0))]
+    KeyNotFound(Vec<u8>),
+    #[error("Key {} already exists", Hex(.0))]
+    KeyExists(Vec<u8>),
+    #[error("Sequence overflow")]
+    SequenceOverflow,
+    /// 事务的版本号已经用尽，无法再开启新的事务
+    #[error("Transaction versions exhausted")]
+    VersionExhausted,
+    /// 调用存储接口的参数不合法，例如批量导入的主键没有排序
+    #[error("{0}")]
+    InvalidArgument(String),
+}
+
+/// 事务的错误
+#[derive(Debug, Error, PartialEq)]
+pub enum TransactionError {
+    /// 写入的 key 被并发的事务修改，重新执行事务可能成功
     #[error("Write conflict")]
     WriteConflict,
+    /// 事务无法串行化，重新执行事务可能成功
+    #[error("Serialization failure")]
+    Serialization,
+    /// 在只读的事务中写入
+    #[error("Transaction is read-only")]
+    ReadOnly,
+    /// 没有正在进行的事务
+    #[error("No transaction in progress")]
+    Closed,
+    #[error("Transaction already started")]
+    AlreadyStarted,
+    #[error("Transaction {0:?} is not active")]
+    Inactive(Version),
+    /// 事务的存活时间超过了限制，已经不能写入和提交，需要重新开始事务
+    #[error("Transaction expired, exceeding the maximum age of {max_age:?}")]
+    Expired { max_age: Duration },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// 依赖的库返回的原始错误，保留在 [`std::error::Error::source`] 的错误链中
+///
+/// 显示和 `source` 都直接转发给原始错误。原始错误通常无法比较，因此按照错误信息判断是否相等。
+#[derive(Debug, Clone)]
+pub struct SourceError(Arc<dyn std::error::Error + Send + Sync>);
+
+impl SourceError {
+    pub fn new(err: impl std::error::Error + Send + Sync + 'static) -> Self {
+        Self(Arc::new(err))
+    }
+
+    /// 原始错误，可以通过 `downcast_ref` 转换为具体的类型
+    pub fn get_ref(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for SourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // bincode 只实现了已经废弃的 `cause`，需要手动取出其中包装的 io::Error
+        if let Some(bincode::ErrorKind::Io(err)) = self
+            .0
+            .downcast_ref::<bincode::Error>()
+            .map(|err| err.as_ref())
+        {
+            return Some(err);
+        }
+        self.0.source()
+    }
+}
+
+impl PartialEq for SourceError {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
 impl From<ParseIntError> for Error {
     fn from(err: ParseIntError) -> Self {
-        Error::ParseError(err.to_string())
+        Error::Parse(err.to_string())
     }
 }
 
 impl From<ParseFloatError> for Error {
     fn from(err: ParseFloatError) -> Self {
-        Error::ParseError(err.to_string())
+        Error::Parse(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        StorageError::Encoding(SourceError::new(err)).into()
     }
 }
 
 impl From<bincode::Error> for Error {
     fn from(err: bincode::Error) -> Self {
-        Error::InternalError(err.to_string())
+        StorageError::Encoding(SourceError::new(err)).into()
     }
 }
 
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
-        Error::InternalError(err.to_string())
+        StorageError::Io(SourceError::new(err)).into()
+    }
+}
+
+/// CSV 的读取错误转换为 [`StorageError::Io`]，格式错误转换为 [`Error::Parse`]，错误信息中包含出错的位置
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        let message = err.to_string();
+        match err.into_kind() {
+            csv::ErrorKind::Io(err) => err.into(),
+            _ => Error::Parse(message),
+        }
     }
 }
 
+/// 锁中毒说明持有锁的线程 panic 了，转换为 [`Error::EnginePanicked`]
+///
+/// 锁中毒的错误借用了锁的守卫，无法作为 `source` 保留，只保留错误信息。
 impl<T> From<PoisonError<T>> for Error {
     fn from(err: PoisonError<T>) -> Self {
-        Error::InternalError(err.to_string())
+        Error::EnginePanicked(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            Error::from(SchemaError::TableNotFound("t".to_string())).to_string(),
+            "Table t not found"
+        );
+        assert_eq!(
+            Error::from(ExecutionError::InvalidColumnValue {
+                column: "id".to_string(),
+                index: 0,
+                expected: DataType::Integer,
+                found: None,
+            })
+            .to_string(),
+            "column 'id' (index 0): expected Integer, found NULL"
+        );
+        assert!(Error::from(TransactionError::WriteConflict).is_retryable());
+        assert!(!Error::from(ExecutionError::Timeout).is_retryable());
+        assert!(!Error::Internal(String::new()).is_retryable());
+    }
+
+    #[test]
+    fn test_retryability() {
+        use Retryability::*;
+
+        let cases: Vec<(Error, Retryability)> = vec![
+            (TransactionError::WriteConflict.into(), Retryable),
+            (TransactionError::Serialization.into(), Retryable),
+            (
+                ExecutionError::DuplicateKey {
+                    table: "t".to_string(),
+                    key: Value::Integer(1),
+                }
+                .into(),
+                RetryableAfterReread,
+            ),
+            (
+                ExecutionError::UniqueViolation {
+                    constraint: "idx".to_string(),
+                    table: "t".to_string(),
+                    values: vec![Value::Integer(1)],
+                }
+                .into(),
+                RetryableAfterReread,
+            ),
+            (
+                ExecutionError::ForeignKeyViolation {
+                    constraint: "fk".to_string(),
+                    table: "t".to_string(),
+                    key: Value::Integer(1),
+                }
+                .into(),
+                RetryableAfterReread,
+            ),
+            (
+                StorageError::KeyExists(vec![1]).into(),
+                RetryableAfterReread,
+            ),
+            (
+                StorageError::KeyNotFound(vec![1]).into(),
+                RetryableAfterReread,
+            ),
+            (Error::Parse(String::new()), Fatal),
+            (SchemaError::TableNotFound("t".to_string()).into(), Fatal),
+            (PlanError::AmbiguousColumn("a".to_string()).into(), Fatal),
+            (ExecutionError::DivisionByZero.into(), Fatal),
+            (ExecutionError::Cancelled.into(), Fatal),
+            (ExecutionError::Timeout.into(), Fatal),
+            (
+                StorageError::Decode {
+                    context: "decoding row",
+                    bytes: vec![],
+                }
+                .into(),
+                Fatal,
+            ),
+            (StorageError::VersionExhausted.into(), Fatal),
+            (TransactionError::ReadOnly.into(), Fatal),
+            (TransactionError::Closed.into(), Fatal),
+            (TransactionError::AlreadyStarted.into(), Fatal),
+            (Error::Connection(String::new()), Fatal),
+            (
+                Error::Remote {
+                    code: ErrorCode::WriteConflict,
+                    retryability: Retryable,
+                    message: String::new(),
+                    position: None,
+                },
+                Retryable,
+            ),
+            (Error::EnginePanicked(String::new()), Fatal),
+            (Error::Internal(String::new()), Fatal),
+        ];
+        for (err, expected) in cases {
+            assert_eq!(err.retryability(), expected, "{err}");
+            assert_eq!(err.is_retryable(), expected == Retryable, "{err}");
+
+            // 上下文不影响分类
+            let err = Err::<(), _>(err).context("committing").unwrap_err();
+            assert_eq!(err.retryability(), expected, "{err}");
+        }
+    }
+
+    #[test]
+    fn test_error_code() -> Result<()> {
+        use crate::{storage::MemoryStorage, Database, Engine};
+
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")?;
+        session.execute("INSERT INTO t VALUES (1, 1);")?;
+        let mut code = |sql: &str| session.execute(sql).unwrap_err().code();
+
+        assert_eq!(code("SELEC 1;"), ErrorCode::SyntaxError);
+        assert_eq!(code("SELECT * FROM missing;"), ErrorCode::UndefinedTable);
+        assert_eq!(
+            code("CREATE TABLE t (id INT PRIMARY KEY);"),
+            ErrorCode::DuplicateTable
+        );
+        assert_eq!(
+            code("INSERT INTO t VALUES (1, 1);"),
+            ErrorCode::UniqueViolation
+        );
+        assert_eq!(code("SELECT v / 0 FROM t;"), ErrorCode::DivisionByZero);
+        assert_eq!(code("COMMIT;"), ErrorCode::NoActiveTransaction);
+
+        // 并发的事务修改同一行时发生写冲突
+        let mut session_1 = db.session();
+        let mut session_2 = db.session();
+        session_1.execute("BEGIN;")?;
+        session_2.execute("BEGIN;")?;
+        session_1.execute("UPDATE t SET v = 2 WHERE id = 1;")?;
+        let err = session_2
+            .execute("UPDATE t SET v = 3 WHERE id = 1;")
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::WriteConflict);
+
+        // 部分编码对应标准的 SQLSTATE
+        assert_eq!(err.code().sqlstate(), Some("40001"));
+        assert_eq!(ErrorCode::UniqueViolation.sqlstate(), Some("23505"));
+        assert_eq!(ErrorCode::UndefinedTable.sqlstate(), Some("42P01"));
+        assert_eq!(ErrorCode::InvalidResultAccess.sqlstate(), None);
+
+        // 序列化时同时输出编码和错误信息
+        assert_eq!(
+            serde_json::to_string(&err)?,
+            r#"{"code":"write_conflict","sqlstate":"40001","retryability":"retryable","message":"Write conflict"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&ErrorCode::UndefinedTable)?,
+            format!("\"{}\"", ErrorCode::UndefinedTable)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_context() {
+        let result: Result<()> = Err(TransactionError::WriteConflict.into());
+        let err = result
+            .context(
+                ErrorContext::new("updating")
+                    .table("users")
+                    .key(&[0xab; 20]),
+            )
+            .context("committing")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "while committing: while updating table 'users' at key 0x{}…: Write conflict",
+                "ab".repeat(ErrorContext::MAX_KEY_BYTES)
+            )
+        );
+
+        // 上下文不影响原始错误的编码和是否可以重试
+        assert_eq!(err.root(), &Error::from(TransactionError::WriteConflict));
+        assert_eq!(err.code(), ErrorCode::WriteConflict);
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn test_error_source() {
+        use std::error::Error as _;
+
+        // bincode 读取数据不足时返回包含 io::Error 的错误，错误链为 bincode::Error -> io::Error
+        let err = Error::from(bincode::deserialize::<String>(&[1]).unwrap_err());
+        assert!(matches!(err, Error::Storage(StorageError::Encoding(_))));
+        let bincode_err = err.source().unwrap();
+        assert!(bincode_err.to_string().contains("io error"));
+        let io_err = bincode_err.source().unwrap();
+        assert_eq!(
+            io_err.downcast_ref::<std::io::Error>().unwrap().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+        assert!(io_err.source().is_none());
+
+        let err = Error::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        let Error::Storage(StorageError::Io(source)) = &err else {
+            panic!("expected an IO error, got {err:?}");
+        };
+        assert!(source.get_ref().is::<std::io::Error>());
+        assert_eq!(err.source().unwrap().to_string(), source.to_string());
     }
 }
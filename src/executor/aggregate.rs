@@ -1,162 +1,355 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+use super::expression::{evaluate, get_column_index_by_name};
 use crate::{
-    error::Error::InternalError,
-    parser::ast::Aggregate,
+    error::{Error::Internal, ExecutionError::TypeMismatch},
+    parser::ast::{Aggregate, Expression},
     schema::{Row, Value},
     Result,
 };
 
-pub fn aggregate(col_name: &str, cols: &[String], rows: &[Row], agg: Aggregate) -> Result<Value> {
-    match agg {
-        Aggregate::Count => count(col_name, cols, rows),
-        Aggregate::Sum => sum(col_name, cols, rows),
-        Aggregate::Min => min(col_name, cols, rows),
-        Aggregate::Max => max(col_name, cols, rows),
-        Aggregate::Avg => avg(col_name, cols, rows),
-    }
-}
-
-fn find_column_index(col_name: &str, cols: &[String]) -> Result<usize> {
-    cols.iter()
-        .position(|col| col == col_name)
-        .ok_or(InternalError(format!("Column {} not found", col_name)))
-}
+/// 哈希聚集
+///
+/// 按照 `group_by` 的值将行分组，NULL 之间视为相等；每个分组为 `aggregates` 中的每个聚集函数维护一个累加器。
+/// 输出的每一行依次为分组的值和聚集函数的结果，分组按照第一次出现的顺序输出。
+/// 没有分组表达式时，即使输入为空也会输出一行。
+pub fn hash_aggregate<I>(
+    columns: &[String],
+    rows: I,
+    group_by: &[Expression],
+    aggregates: &[Expression],
+) -> Result<Vec<Row>>
+where
+    I: Iterator<Item = Result<Row>>,
+{
+    // 聚集函数的参数在输入行中的下标，COUNT(*) 对应 None
+    let args = aggregates
+        .iter()
+        .map(|expr| match expr {
+            Expression::Function(_, col_name, _) if col_name == "*" => Ok(None),
+            Expression::Function(_, col_name, _) => {
+                get_column_index_by_name(columns, col_name).map(Some)
+            }
+            expr => Err(Internal(format!("{} is not an aggregate function", expr))),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let new_accumulators = || {
+        aggregates
+            .iter()
+            .filter_map(|expr| expr.as_function())
+            .map(|(aggregate, _, distinct)| Accumulator::new(aggregate, distinct))
+            .collect::<Vec<_>>()
+    };
 
-fn count(col_name: &str, cols: &[String], rows: &[Row]) -> Result<Value> {
-    let count = if col_name == "*" {
-        rows.len()
-    } else {
-        let col_idx = find_column_index(col_name, cols)?;
-        rows.iter()
-            .filter(|row| row[col_idx] != Value::Null)
-            .count()
-    } as i64;
-
-    Ok(Value::Integer(count))
-}
+    let mut groups = Vec::new();
+    let mut group_indices = HashMap::new();
+    if group_by.is_empty() {
+        groups.push((Vec::new(), new_accumulators()));
+        group_indices.insert(Vec::new(), 0);
+    }
 
-fn sum(col_name: &str, cols: &[String], rows: &[Row]) -> Result<Value> {
-    let col_idx = find_column_index(col_name, cols)?;
-    let mut sum = Value::Null;
     for row in rows {
-        match &row[col_idx] {
-            Value::Integer(value) => {
-                if sum == Value::Null {
-                    sum = Value::Integer(0);
-                }
-                sum = Value::Integer(sum.as_i64()? + value);
-            }
-            Value::Float(value) => {
-                if sum == Value::Null {
-                    sum = Value::Float(0.0);
-                }
-                sum = Value::Float(sum.as_f64()? + value);
-            }
-            Value::Null => continue,
-            val => {
-                return Err(InternalError(format!(
-                    "Unsupported value {:?} for sum",
-                    val
-                )))
+        let row = row?;
+        let key = group_by
+            .iter()
+            .map(|expr| evaluate(expr, columns, &row))
+            .collect::<Result<Vec<_>>>()?;
+        let group_idx = match group_indices.entry(key) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                groups.push((entry.key().clone(), new_accumulators()));
+                *entry.insert(groups.len() - 1)
             }
+        };
+        for (accumulator, arg) in groups[group_idx].1.iter_mut().zip(&args) {
+            accumulator.add(arg.map(|idx| &row[idx]))?;
         }
     }
 
-    Ok(sum)
+    Ok(groups
+        .into_iter()
+        .map(|(mut row, accumulators)| {
+            row.extend(accumulators.into_iter().map(Accumulator::finish));
+            row
+        })
+        .collect())
 }
 
-fn min(col_name: &str, cols: &[String], rows: &[Row]) -> Result<Value> {
-    let col_idx = find_column_index(col_name, cols)?;
-    let mut min = Value::Null;
-    for row in rows {
-        match &row[col_idx] {
-            Value::Integer(value) => {
-                if min == Value::Null || *value < min.as_i64()? {
-                    min = Value::Integer(*value);
-                }
-            }
-            Value::Float(value) => {
-                if min == Value::Null || *value < min.as_f64()? {
-                    min = Value::Float(*value);
-                }
-            }
-            Value::Null => continue,
-            Value::String(value) => {
-                if min == Value::Null || value.as_str() < min.as_str()? {
-                    min = Value::String(value.clone());
-                }
-            }
-            val => {
-                return Err(InternalError(format!(
-                    "Unsupported value {:?} for min",
-                    val
-                )))
-            }
+/// 一个分组中一个聚集函数的累加器
+struct Accumulator {
+    aggregate: Aggregate,
+    /// DISTINCT 聚集中已经累加过的值
+    seen: Option<HashSet<Value>>,
+    /// 累加过的非 NULL 值的个数，COUNT(*) 时为行数
+    count: i64,
+    /// SUM 和 AVG 为当前的和，MIN 和 MAX 为当前的最值，还没有累加过值时为 NULL
+    value: Value,
+}
+
+impl Accumulator {
+    fn new(aggregate: Aggregate, distinct: bool) -> Self {
+        Self {
+            aggregate,
+            seen: distinct.then(HashSet::new),
+            count: 0,
+            value: Value::Null,
         }
     }
 
-    Ok(min)
-}
-
-fn max(col_name: &str, cols: &[String], rows: &[Row]) -> Result<Value> {
-    let col_idx = find_column_index(col_name, cols)?;
-    let mut max = Value::Null;
-    for row in rows {
-        match &row[col_idx] {
-            Value::Integer(value) => {
-                if max == Value::Null || *value > max.as_i64()? {
-                    max = Value::Integer(*value);
-                }
+    /// 累加一个值，NULL 会被忽略；`None` 表示 COUNT(*) 中的一行
+    fn add(&mut self, value: Option<&Value>) -> Result<()> {
+        let Some(value) = value else {
+            self.count += 1;
+            return Ok(());
+        };
+        if *value == Value::Null {
+            return Ok(());
+        }
+        if let Some(seen) = &mut self.seen {
+            if !seen.insert(value.clone()) {
+                return Ok(());
             }
-            Value::Float(value) => {
-                if max == Value::Null || *value > max.as_f64()? {
-                    max = Value::Float(*value);
+        }
+        self.count += 1;
+
+        let unsupported = || -> crate::Error {
+            TypeMismatch(format!(
+                "Unsupported value {:?} for {}",
+                value, self.aggregate
+            ))
+            .into()
+        };
+        self.value = match self.aggregate {
+            Aggregate::Count => return Ok(()),
+            // 整数求和溢出时返回错误
+            Aggregate::Sum => match value {
+                Value::Integer(_) | Value::Float(_) if self.value == Value::Null => value.clone(),
+                Value::Integer(_) | Value::Float(_) => self.value.checked_add(value)?,
+                _ => return Err(unsupported()),
+            },
+            // 平均值总是使用浮点数求和，避免整数溢出
+            Aggregate::Avg => {
+                let value = match value {
+                    Value::Integer(i) => *i as f64,
+                    Value::Float(f) => *f,
+                    _ => return Err(unsupported()),
+                };
+                match self.value {
+                    Value::Float(sum) => Value::Float(sum + value),
+                    _ => Value::Float(value),
                 }
             }
-            Value::Null => continue,
-            Value::String(value) => {
-                if max == Value::Null || value.as_str() > max.as_str()? {
-                    max = Value::String(value.clone());
+            Aggregate::Min | Aggregate::Max => {
+                if self.value == Value::Null {
+                    value.clone()
+                } else {
+                    let ordering = match (value, &self.value) {
+                        (
+                            Value::Integer(_) | Value::Float(_),
+                            Value::Integer(_) | Value::Float(_),
+                        ) => value.total_cmp(&self.value),
+                        _ => value.partial_cmp(&self.value).ok_or_else(unsupported)?,
+                    };
+                    let is_better = match self.aggregate {
+                        Aggregate::Min => ordering.is_lt(),
+                        _ => ordering.is_gt(),
+                    };
+                    if is_better {
+                        value.clone()
+                    } else {
+                        return Ok(());
+                    }
                 }
             }
-            val => {
-                return Err(InternalError(format!(
-                    "Unsupported value {:?} for max",
-                    val
-                )))
-            }
-        }
+        };
+        Ok(())
     }
 
-    Ok(max)
+    /// 计算聚集函数的结果，没有非 NULL 值时 COUNT 为 0，其余为 NULL
+    fn finish(self) -> Value {
+        match self.aggregate {
+            Aggregate::Count => Value::Integer(self.count),
+            Aggregate::Avg => match self.value {
+                Value::Float(sum) => Value::Float(sum / self.count as f64),
+                _ => Value::Null,
+            },
+            Aggregate::Sum | Aggregate::Min | Aggregate::Max => self.value,
+        }
+    }
 }
 
-fn avg(col_name: &str, cols: &[String], rows: &[Row]) -> Result<Value> {
-    let col_idx = find_column_index(col_name, cols)?;
-    let mut sum = 0.0;
-    let mut count = 0;
-    for row in rows {
-        match &row[col_idx] {
-            Value::Integer(value) => {
-                sum += *value as f64;
-                count += 1;
-            }
-            Value::Float(value) => {
-                sum += *value;
-                count += 1;
-            }
-            Value::Null => continue,
-            val => {
-                return Err(InternalError(format!(
-                    "Unsupported value {:?} for avg",
-                    val
-                )))
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(aggregate: Aggregate, col_name: &str, distinct: bool) -> Expression {
+        Expression::Function(aggregate, col_name.to_string(), distinct)
     }
 
-    if count == 0 {
-        return Ok(Value::Null);
+    fn aggregate_rows(
+        columns: &[String],
+        rows: &[Row],
+        group_by: &[Expression],
+        aggregates: &[Expression],
+    ) -> Result<Vec<Row>> {
+        hash_aggregate(columns, rows.iter().cloned().map(Ok), group_by, aggregates)
     }
 
-    Ok(Value::Float(sum / count as f64))
+    #[test]
+    fn test_hash_aggregate() -> Result<()> {
+        use Value::{Float, Integer, Null};
+
+        let columns = ["t.g", "t.a", "t.b", "t.c"].map(String::from).to_vec();
+        // c 列全部为 NULL
+        let rows = vec![
+            vec![Integer(1), Integer(1), Float(1.5), Null],
+            vec![Integer(1), Integer(2), Null, Null],
+            vec![Integer(1), Integer(2), Float(2.5), Null],
+            vec![Integer(2), Null, Null, Null],
+            vec![Null, Integer(5), Float(0.5), Null],
+            vec![Integer(2), Null, Float(4.0), Null],
+            vec![Null, Integer(5), Null, Null],
+            vec![Integer(1), Null, Float(1.0), Null],
+        ];
+        let aggregates = vec![
+            function(Aggregate::Count, "*", false),
+            function(Aggregate::Count, "a", false),
+            function(Aggregate::Count, "a", true),
+            function(Aggregate::Sum, "a", false),
+            function(Aggregate::Sum, "a", true),
+            function(Aggregate::Avg, "a", false),
+            function(Aggregate::Min, "a", false),
+            function(Aggregate::Max, "a", false),
+            function(Aggregate::Sum, "t.b", false),
+            function(Aggregate::Avg, "b", false),
+            function(Aggregate::Count, "c", false),
+            function(Aggregate::Sum, "c", false),
+            function(Aggregate::Avg, "c", false),
+            function(Aggregate::Max, "c", true),
+        ];
+        let all_null = [Integer(0), Null, Null, Null];
+
+        // 按照 g 分组，NULL 是一个单独的分组，分组按照第一次出现的顺序输出
+        let expected = vec![
+            [
+                vec![Integer(1)],
+                vec![Integer(4), Integer(3), Integer(2), Integer(5), Integer(3)],
+                vec![Float(5.0 / 3.0), Integer(1), Integer(2)],
+                vec![Float(5.0), Float(5.0 / 3.0)],
+                all_null.to_vec(),
+            ]
+            .concat(),
+            [
+                vec![Integer(2)],
+                vec![Integer(2), Integer(0), Integer(0), Null, Null],
+                vec![Null, Null, Null],
+                vec![Float(4.0), Float(4.0)],
+                all_null.to_vec(),
+            ]
+            .concat(),
+            [
+                vec![Null],
+                vec![Integer(2), Integer(2), Integer(1), Integer(10), Integer(5)],
+                vec![Float(5.0), Integer(5), Integer(5)],
+                vec![Float(0.5), Float(0.5)],
+                all_null.to_vec(),
+            ]
+            .concat(),
+        ];
+        let group_by = [Expression::Field("g".to_string())];
+        assert_eq!(
+            aggregate_rows(&columns, &rows, &group_by, &aggregates)?,
+            expected
+        );
+
+        // 没有分组时所有行为一组
+        let expected = [
+            vec![Integer(8), Integer(5), Integer(3), Integer(15), Integer(8)],
+            vec![Float(3.0), Integer(1), Integer(5)],
+            vec![Float(9.5), Float(9.5 / 5.0)],
+            all_null.to_vec(),
+        ]
+        .concat();
+        assert_eq!(
+            aggregate_rows(&columns, &rows, &[], &aggregates)?,
+            vec![expected]
+        );
+
+        // 输入为空时，没有分组则输出一行，有分组则没有输出
+        assert_eq!(
+            aggregate_rows(&columns, &[], &[], &aggregates)?,
+            vec![[
+                vec![Integer(0), Integer(0), Integer(0), Null, Null],
+                vec![Null, Null, Null],
+                vec![Null, Null],
+                all_null.to_vec(),
+            ]
+            .concat()]
+        );
+        assert!(aggregate_rows(&columns, &[], &group_by, &aggregates)?.is_empty());
+
+        // 分组表达式可以是任意表达式，只有分组没有聚集函数时相当于去重
+        let group_by = [Expression::Operation(
+            crate::parser::ast::Operation::IsNull(Box::new(Expression::Field("a".to_string()))),
+        )];
+        assert_eq!(
+            aggregate_rows(&columns, &rows, &group_by, &[])?,
+            vec![vec![Value::Boolean(false)], vec![Value::Boolean(true)]]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_values() -> Result<()> {
+        use Value::{Float, Integer, Null, String};
+
+        let columns = vec!["t.v".to_string()];
+        let aggregate_column = |values: Vec<Value>, aggregate: Aggregate| {
+            let rows = values.into_iter().map(|v| vec![v]).collect::<Vec<_>>();
+            aggregate_rows(&columns, &rows, &[], &[function(aggregate, "v", false)])
+                .map(|rows| rows[0][0].clone())
+        };
+
+        // 整数和浮点数混合
+        let mixed = vec![Integer(2), Float(0.5), Null, Integer(-1)];
+        assert_eq!(aggregate_column(mixed.clone(), Aggregate::Sum)?, Float(1.5));
+        assert_eq!(aggregate_column(mixed.clone(), Aggregate::Avg)?, Float(0.5));
+        assert_eq!(
+            aggregate_column(mixed.clone(), Aggregate::Min)?,
+            Integer(-1)
+        );
+        assert_eq!(aggregate_column(mixed, Aggregate::Max)?, Integer(2));
+
+        // 字符串可以求最值，不能求和
+        let strings = vec![String("b".into()), Null, String("a".into())];
+        assert_eq!(
+            aggregate_column(strings.clone(), Aggregate::Min)?,
+            String("a".into())
+        );
+        assert_eq!(
+            aggregate_column(strings.clone(), Aggregate::Max)?,
+            String("b".into())
+        );
+        assert!(aggregate_column(strings.clone(), Aggregate::Sum).is_err());
+        assert!(aggregate_column(strings, Aggregate::Avg).is_err());
+
+        // 不同类型之间不能比较
+        assert!(aggregate_column(vec![Integer(1), String("a".into())], Aggregate::Min).is_err());
+
+        // 整数求和溢出时返回错误，平均值使用浮点数计算不会溢出
+        let large = vec![Integer(i64::MAX), Integer(1)];
+        assert!(aggregate_column(large.clone(), Aggregate::Sum).is_err());
+        assert_eq!(
+            aggregate_column(large, Aggregate::Avg)?,
+            Float((i64::MAX as f64 + 1.0) / 2.0)
+        );
+
+        // 参数列不存在时返回错误
+        assert!(
+            aggregate_rows(&columns, &[], &[], &[function(Aggregate::Sum, "x", false)]).is_err()
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,132 @@
+use crate::{
+    error::{Result, SchemaError::TableNotFound},
+    executor::Executor,
+    stats::StatsCollector,
+    storage::Storage,
+};
+
+impl<S: Storage> Executor<S> {
+    /// 扫描表收集统计信息，保存在当前事务中，`table_name` 为 `None` 时收集所有表，返回收集了统计信息的表名
+    ///
+    /// 统计信息和表信息一样在事务中读写，事务回滚后恢复为之前的统计信息。之后修改表不会更新统计信息，
+    /// 计划器使用的估计可能和实际的数据不同，需要再次执行 `ANALYZE`。
+    pub(super) fn analyze(&self, table_name: Option<String>) -> Result<Vec<String>> {
+        let tables = match table_name {
+            Some(table_name) => vec![self
+                .transaction
+                .get_table(&table_name)?
+                .ok_or(TableNotFound(table_name.to_string()))?],
+            None => self.transaction.list_tables()?,
+        };
+        let mut names = Vec::new();
+        for table in tables {
+            let mut collector = StatsCollector::new(&table);
+            for row in self.transaction.scan_table_iter(&table, None) {
+                collector.add(&row?);
+            }
+            self.check_cancelled()?;
+            self.transaction
+                .set_stats(&table.name, &collector.finish())?;
+            names.push(table.name);
+        }
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{storage::MemoryStorage, Database, Engine, Result, ResultSet, Row, Session};
+
+    fn explain(session: &mut Session<MemoryStorage>, sql: &str) -> Result<String> {
+        match session.execute(&format!("EXPLAIN {sql}"))? {
+            ResultSet::Explain(plan) => Ok(plan),
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+
+    /// 按照主键排序的查询结果，访问路径不同时行的顺序可能不同
+    fn rows(session: &mut Session<MemoryStorage>, sql: &str) -> Result<Vec<Row>> {
+        let mut rows = session.execute(sql)?.rows().to_vec();
+        rows.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        Ok(rows)
+    }
+
+    #[test]
+    fn test_analyze() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        let values = (0..1000)
+            .map(|i| format!("({i}, {}, {})", i % 200, (i % 10 == 0) as i64))
+            .collect::<Vec<_>>()
+            .join(", ");
+        for sql in [
+            "CREATE TABLE t (id INT PRIMARY KEY, category INT, flag INT);".to_string(),
+            "CREATE INDEX idx_category ON t (category);".to_string(),
+            "CREATE INDEX idx_flag ON t (flag);".to_string(),
+            format!("INSERT INTO t VALUES {values};"),
+            "CREATE TABLE u (id INT PRIMARY KEY);".to_string(),
+        ] {
+            session.execute(&sql)?;
+        }
+        let most = "SELECT * FROM t WHERE flag = 0;";
+        let few = "SELECT * FROM t WHERE category < 10;";
+        let wide = "SELECT * FROM t WHERE category >= 10;";
+
+        // 没有统计信息时总是使用索引，也不输出估计
+        for sql in [most, few, wide] {
+            let plan = explain(&mut session, sql)?;
+            assert!(plan.starts_with("IndexScan"), "{plan}");
+            assert!(!plan.contains("estimated"), "{plan}");
+        }
+        let before = [most, few, wide].map(|sql| rows(&mut session, sql));
+
+        // 统计信息在事务中保存，回滚后不可见
+        session.execute("BEGIN;")?;
+        assert_eq!(
+            session.execute("ANALYZE t;")?,
+            ResultSet::Analyze {
+                tables: vec!["t".to_string()]
+            }
+        );
+        session.execute("ROLLBACK;")?;
+        assert!(!explain(&mut session, most)?.contains("estimated"));
+
+        // 条件匹配大部分行时使用全表扫描，选择率高时仍然使用索引
+        assert_eq!(
+            session.execute("ANALYZE;")?,
+            ResultSet::Analyze {
+                tables: vec!["t".to_string(), "u".to_string()]
+            }
+        );
+        assert_eq!(
+            explain(&mut session, most)?,
+            "Scan: t (flag = 0) [estimated rows=500]\n"
+        );
+        let plan = explain(&mut session, few)?;
+        assert!(
+            plan.starts_with("IndexScan: t USING idx_category (category < 10) [estimated rows=5"),
+            "{plan}"
+        );
+        assert!(explain(&mut session, wide)?.starts_with("Scan: t"));
+        assert!(
+            explain(&mut session, "SELECT * FROM t WHERE category = 7;")?.starts_with("IndexScan")
+        );
+        assert!(explain(&mut session, "SELECT * FROM t WHERE id = 7;")?.starts_with("KeyLookup"));
+        assert_eq!(
+            explain(&mut session, "SELECT * FROM t WHERE id > 899;")?,
+            "KeyRangeScan: t (id > 899) [estimated rows=100]\n"
+        );
+        assert_eq!(
+            explain(&mut session, "SELECT * FROM u;")?,
+            "Scan: u [estimated rows=0]\n"
+        );
+
+        // 访问路径改变后结果不变
+        let after = [most, few, wide].map(|sql| rows(&mut session, sql));
+        assert_eq!(before, after);
+
+        assert!(session.execute("ANALYZE missing;").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::{
+    error::{
+        ExecutionError::{Cancelled, Timeout},
+        Result,
+    },
+    executor::Rows,
+};
+
+/// 每产生多少行检查一次是否取消
+const CHECK_INTERVAL: usize = 256;
+
+/// 语句的取消标记，克隆后的标记共享同一个取消状态，可以在其他线程中取消正在执行的语句
+///
+/// 执行器在算子之间以及每产生 [`CHECK_INTERVAL`] 行检查一次标记，因此取消后语句不会立即停止，
+/// 而是在下一次检查时返回 [`Cancelled`]，超过截止时间时返回 [`Timeout`]。
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// 创建一个没有截止时间的取消标记
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 创建一个在 `deadline` 之后超时的取消标记
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// 取消使用这个标记的语句
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// 是否已经被取消
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// 已经取消时返回 [`Cancelled`]，超过截止时间时返回 [`Timeout`]
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(Timeout.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// 包装节点产生的行，每产生 [`CHECK_INTERVAL`] 行检查一次，取消后产生一个错误并结束
+    pub(super) fn guard<'a>(&self, rows: Rows<'a>) -> Rows<'a> {
+        let token = self.clone();
+        let mut count = 0;
+        let mut rows = Some(rows);
+        Box::new(std::iter::from_fn(move || {
+            count += 1;
+            if count % CHECK_INTERVAL == 0 {
+                if let Err(e) = token.check() {
+                    // 取消后不再从子节点拉取
+                    rows = None;
+                    return Some(Err(e));
+                }
+            }
+            rows.as_mut()?.next()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{storage::MemoryStorage, Database, Engine};
+
+    #[test]
+    fn test_cancellation() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        let values = (0..1000)
+            .map(|i| format!("({i})"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        for table in ["t", "u", "v"] {
+            session.execute(&format!("CREATE TABLE {table} (id INT PRIMARY KEY);"))?;
+            session.execute(&format!("INSERT INTO {table} VALUES {values};"))?;
+        }
+        // 10 亿行的笛卡尔积，不取消时无法在测试中执行完
+        let huge = "SELECT COUNT(*) FROM t CROSS JOIN u CROSS JOIN v;";
+
+        // 在另一个线程中取消
+        let token = CancellationToken::new();
+        let canceller = {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                token.cancel();
+            })
+        };
+        let start = Instant::now();
+        assert_eq!(
+            session.execute_with_token(huge, &token),
+            Err(Cancelled.into())
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+        canceller.join().unwrap();
+
+        // 超过截止时间
+        let start = Instant::now();
+        assert_eq!(
+            session.execute_with_deadline(huge, start + Duration::from_millis(100)),
+            Err(Timeout.into())
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        // 显式事务中取消的语句回滚整个事务
+        session.execute("BEGIN;")?;
+        session.execute("DELETE FROM t WHERE id < 10;")?;
+        let token = CancellationToken::new();
+        token.cancel();
+        assert_eq!(
+            session.execute_with_token("DELETE FROM t;", &token),
+            Err(Cancelled.into())
+        );
+        assert!(!session.in_transaction());
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM t;")?.rows()[0][0],
+            crate::Value::Integer(1000)
+        );
+
+        // 开始之前已经取消的自动提交语句不会执行
+        assert_eq!(
+            session.execute_with_token("INSERT INTO t VALUES (1000);", &token),
+            Err(Cancelled.into())
+        );
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM t;")?.rows()[0][0],
+            crate::Value::Integer(1000)
+        );
+
+        // 没有取消的语句正常执行
+        let token = CancellationToken::with_deadline(Instant::now() + Duration::from_secs(60));
+        assert_eq!(
+            session
+                .execute_with_token("SELECT COUNT(*) FROM t;", &token)?
+                .rows()[0][0],
+            crate::Value::Integer(1000)
+        );
+
+        Ok(())
+    }
+}
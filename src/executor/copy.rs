@@ -0,0 +1,606 @@
+use std::{
+    borrow::Cow,
+    io::{Read, Write},
+};
+
+use csv::{Position, ReaderBuilder, StringRecord, WriterBuilder};
+
+use crate::{
+    error::{
+        Error, ErrorContext,
+        ExecutionError::{DuplicateKey, InvalidCast, MissingValue, ValueCount},
+        Result, ResultExt,
+        SchemaError::{ColumnNotFound, TableNotFound},
+    },
+    executor::Executor,
+    parser::ast::Statement,
+    planner::Planner,
+    schema::{DataType, Row, Table, Value},
+    storage::Storage,
+};
+
+/// 导入的行出错时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnError {
+    /// 返回第一个出错的行的错误，已经写入的行随事务回滚
+    #[default]
+    Abort,
+    /// 跳过出错的行，行号和错误记录在 [`CopyResult::skipped`] 中
+    Skip,
+}
+
+/// CSV 导入和导出的选项，对应 `COPY ... WITH (...)` 中的同名选项
+///
+/// `empty_as_null`、`on_error` 和 `batch_size` 只用于导入。
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    /// 第一行是否为列名，导入时按照列名对应到表的列，否则按照位置对应；导出时写入结果的列名。默认为假
+    pub header: bool,
+    /// 字段的分隔符，默认为 `,`
+    pub delimiter: u8,
+    /// NULL 的文本，默认为空字符串；导出时 NULL 写为该文本，导入时和该文本相同的字段为 NULL
+    ///
+    /// 默认情况下空字符串和 NULL 都导出为空字段，需要区分两者时可以使用 `\N` 等不会出现在数据中的文本，
+    /// 并在导入时将 `empty_as_null` 设为假。
+    pub null: String,
+    /// 空字段是否导入为 NULL，默认为真；为假时字符串列的空字段导入为空字符串，其他列仍然为 NULL
+    pub empty_as_null: bool,
+    /// 行的值不合法、或者和已有的行冲突时的处理方式
+    pub on_error: OnError,
+    /// 每批读取的行数，一批行全部解析之后再写入
+    pub batch_size: usize,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            header: false,
+            delimiter: b',',
+            null: String::new(),
+            empty_as_null: true,
+            on_error: OnError::Abort,
+            batch_size: 1024,
+        }
+    }
+}
+
+/// CSV 导入的结果
+#[derive(Debug)]
+pub struct CopyResult {
+    /// 写入的行数
+    pub inserted: u64,
+    /// 跳过的行在文件中的行号和错误，行号从 1 开始，只在 [`OnError::Skip`] 时不为空
+    pub skipped: Vec<(u64, Error)>,
+}
+
+impl<S: Storage> Executor<S> {
+    /// 从 `reader` 中流式读取 CSV 格式的行，写入表 `table_name`
+    ///
+    /// 每次读取 `options.batch_size` 行，按照列的类型解析字段并检查是否符合表定义，之后逐行检查主键和唯一索引，
+    /// 没有冲突的行写入当前事务。没有对应字段的列使用默认值。行的错误按照 `options.on_error` 处理，
+    /// 附加的上下文中包含行号；CSV 格式本身的错误和外键的错误总是停止导入，外键在所有行写入之后检查。
+    pub fn copy_from(
+        &self,
+        table_name: &str,
+        reader: impl Read,
+        options: &CsvOptions,
+    ) -> Result<CopyResult> {
+        let table = self
+            .transaction
+            .get_table(table_name)?
+            .ok_or(TableNotFound(table_name.to_string()))?;
+        let context = |line: u64| ErrorContext::new("importing").table(table_name).line(line);
+
+        let mut reader = ReaderBuilder::new()
+            .has_headers(options.header)
+            .delimiter(options.delimiter)
+            .flexible(true)
+            .from_reader(reader);
+        let (positions, fields) = match options.header {
+            true => Self::header_positions(&table, reader.headers().context(context(1))?)?,
+            false => (
+                (0..table.columns.len()).map(Some).collect(),
+                table.columns.len(),
+            ),
+        };
+
+        let mut result = CopyResult {
+            inserted: 0,
+            skipped: Vec::new(),
+        };
+        // 外键在所有行写入之后再检查，和 INSERT 相同
+        let mut rows = Vec::new();
+        let batch_size = options.batch_size.max(1);
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut records = reader.into_records();
+        loop {
+            self.check_cancelled()?;
+            for record in records.by_ref().take(batch_size) {
+                let record = record?;
+                let line = record.position().map_or(0, Position::line);
+                let row = self.parse_record(&table, &positions, fields, &record, options);
+                batch.push((line, row));
+            }
+            if batch.is_empty() {
+                break;
+            }
+
+            for (line, row) in batch.drain(..) {
+                match row.and_then(|row| self.check_conflicts(&table, &row).map(|_| row)) {
+                    Ok(row) => {
+                        self.transaction
+                            .create_row(&table.name, &row)
+                            .with_context(|| context(line))?;
+                        result.inserted += 1;
+                        if !table.foreign_keys.is_empty() {
+                            rows.push(row);
+                        }
+                    }
+                    Err(e) => match options.on_error {
+                        OnError::Abort => return Err(e).with_context(|| context(line)),
+                        OnError::Skip => result.skipped.push((line, e)),
+                    },
+                }
+            }
+        }
+        self.check_references(&table, &rows)?;
+
+        Ok(result)
+    }
+
+    /// 执行查询 `query`，将结果以 CSV 格式流式写入 `writer`，返回写入的行数
+    ///
+    /// 结果逐行写入，不会在内存中保存整个结果。字段按照 RFC 4180 在需要时加上引号，NULL 写为 `options.null`，
+    /// 浮点数使用能够精确解析回原值的最短表示。`options.header` 为真时先写入结果的列名。
+    pub fn copy_to(
+        &self,
+        query: Statement,
+        writer: impl Write,
+        options: &CsvOptions,
+    ) -> Result<u64> {
+        let query = self.materialize_subqueries(query)?;
+        let mut leftmost = &query;
+        while let Statement::SetOperation { left, .. } = leftmost {
+            leftmost = left;
+        }
+        let is_projected =
+            !matches!(leftmost, Statement::Select { columns, .. } if columns.is_empty());
+
+        let plan = Planner::new(&self.transaction).build_query(query)?;
+        let (columns, rows) = self.execute_node(plan)?;
+        let mut writer = WriterBuilder::new()
+            .delimiter(options.delimiter)
+            .from_writer(writer);
+        if options.header {
+            writer.write_record(Self::output_columns(columns, is_projected))?;
+        }
+
+        let mut count = 0;
+        // 执行节点产生的行已经定期检查取消标记
+        for row in rows {
+            let row = row?;
+            for value in &row {
+                writer.write_field(format_field(value, &options.null).as_bytes())?;
+            }
+            // 空的记录结束当前行
+            writer.write_record(None::<&[u8]>)?;
+            count += 1;
+        }
+        writer.flush()?;
+
+        Ok(count)
+    }
+
+    /// 按照列名把表的每一列对应到 CSV 记录中的位置，同时返回每条记录应有的字段数
+    ///
+    /// 列名不区分大小写，重复的列名以最后一个为准，没有出现的列为 `None`，使用默认值。
+    fn header_positions(
+        table: &Table,
+        header: &StringRecord,
+    ) -> Result<(Vec<Option<usize>>, usize)> {
+        let names = header
+            .iter()
+            .map(|name| {
+                let name = name.trim().to_lowercase();
+                match table.get_col_idx(&name) {
+                    Some(_) => Ok(name),
+                    None => Err(ColumnNotFound {
+                        table: table.name.clone(),
+                        column: name,
+                    }
+                    .into()),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let positions = table
+            .columns
+            .iter()
+            .map(|column| names.iter().rposition(|name| *name == column.name))
+            .collect();
+        Ok((positions, names.len()))
+    }
+
+    /// 将一条 CSV 记录解析为表的一行，为省略或者为空的自增列分配 ID，并检查是否符合表定义
+    fn parse_record(
+        &self,
+        table: &Table,
+        positions: &[Option<usize>],
+        fields: usize,
+        record: &StringRecord,
+        options: &CsvOptions,
+    ) -> Result<Row> {
+        if record.len() != fields {
+            return Err(ValueCount {
+                columns: fields,
+                values: record.len(),
+            }
+            .into());
+        }
+        let mut row = table
+            .columns
+            .iter()
+            .zip(positions)
+            .map(|(column, position)| match position {
+                Some(idx) => parse_field(&record[*idx], column.data_type, options),
+                None if column.auto_increment => Ok(Value::Null),
+                None => column
+                    .default
+                    .clone()
+                    .ok_or(MissingValue(column.name.clone()).into()),
+            })
+            .collect::<Result<Row>>()?;
+        self.transaction.fill_auto_increment(table, &mut row)?;
+        table.validate_row(&row)?;
+        Ok(row)
+    }
+
+    /// 检查行的主键和唯一索引是否和已有的行冲突，在写入之前检查，冲突的行可以被跳过而不会留下部分写入的索引项
+    fn check_conflicts(&self, table: &Table, row: &Row) -> Result<()> {
+        let pk = table.get_primary_key(row);
+        if self.transaction.get_row(table, pk)?.is_some() {
+            return Err(DuplicateKey {
+                table: table.name.clone(),
+                key: pk.clone(),
+            }
+            .into());
+        }
+        for index in table.indexes.iter().filter(|index| index.unique) {
+            self.transaction.check_unique(table, index, row, pk)?;
+        }
+        Ok(())
+    }
+}
+
+/// 按照列的类型解析字段，空字段按照 `options.empty_as_null` 处理，和 `options.null` 相同的字段为 NULL
+///
+/// 布尔值接受 `true`/`t`/`yes`/`1` 和 `false`/`f`/`no`/`0`，不区分大小写。
+fn parse_field(field: &str, data_type: DataType, options: &CsvOptions) -> Result<Value> {
+    if field.is_empty() && (options.empty_as_null || data_type != DataType::String)
+        || !options.null.is_empty() && field == options.null
+    {
+        return Ok(Value::Null);
+    }
+    let invalid = |target: &'static str| -> Error {
+        InvalidCast {
+            value: Value::String(field.to_string()),
+            target,
+        }
+        .into()
+    };
+    match data_type {
+        DataType::Boolean => match field.trim().to_lowercase().as_str() {
+            "true" | "t" | "yes" | "1" => Ok(Value::Boolean(true)),
+            "false" | "f" | "no" | "0" => Ok(Value::Boolean(false)),
+            _ => Err(invalid("boolean")),
+        },
+        DataType::Integer => field
+            .trim()
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| invalid("integer")),
+        DataType::Float => field
+            .trim()
+            .parse()
+            .map(Value::Float)
+            .map_err(|_| invalid("float")),
+        DataType::String => Ok(Value::String(field.to_string())),
+        DataType::Json => serde_json::from_str(field)
+            .map(Value::Json)
+            .map_err(|_| invalid("json")),
+    }
+}
+
+/// 将值格式化为 CSV 字段，和 [`parse_field`] 互逆
+///
+/// 浮点数使用 `{:?}` 格式，总是包含小数点或者指数，解析后和原值相同，包括 `NaN` 和 `inf`。
+fn format_field<'a>(value: &'a Value, null: &'a str) -> Cow<'a, str> {
+    match value {
+        Value::Null => Cow::Borrowed(null),
+        Value::Boolean(b) => Cow::Borrowed(if *b { "true" } else { "false" }),
+        Value::Integer(i) => Cow::Owned(i.to_string()),
+        Value::Float(f) => Cow::Owned(format!("{f:?}")),
+        Value::String(s) => Cow::Borrowed(s),
+        Value::Json(j) => Cow::Owned(j.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{
+        executor::ResultSet, storage::MemoryStorage, Database, Engine, ErrorCode, Session,
+    };
+
+    #[test]
+    fn test_copy_from() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        session.execute(
+            "CREATE TABLE t (id INT PRIMARY KEY, name STRING NULL, score FLOAT NULL, active BOOL DEFAULT TRUE);",
+        )?;
+
+        // 列名的顺序和表不同，active 列使用默认值
+        let mut file = NamedTempFile::new()?;
+        writeln!(file, "score;ID;name")?;
+        for i in 0..100_000 {
+            match i % 10 {
+                0 => writeln!(file, "{};{};", i as f64 / 2.0, i)?,
+                _ => writeln!(file, "{};{};\"name {}; {}\"", i as f64 / 2.0, i, i, i)?,
+            }
+        }
+        file.flush()?;
+        let sql = format!(
+            "COPY t FROM '{}' WITH (HEADER true, DELIMITER ';', BATCH_SIZE 5000);",
+            file.path().display()
+        );
+        assert_eq!(session.execute(&sql)?, ResultSet::modified(100_000));
+        assert_eq!(
+            session
+                .execute("SELECT COUNT(*), COUNT(name), SUM(score) FROM t;")?
+                .rows()[0],
+            vec![
+                Value::Integer(100_000),
+                Value::Integer(90_000),
+                Value::Float((0..100_000).map(|i| i as f64 / 2.0).sum())
+            ]
+        );
+        assert_eq!(
+            session.execute("SELECT * FROM t WHERE id = 12345;")?.rows()[0],
+            vec![
+                Value::Integer(12345),
+                Value::String("name 12345; 12345".to_string()),
+                Value::Float(6172.5),
+                Value::Boolean(true)
+            ]
+        );
+
+        // 没有列名时按照位置对应，empty_as_null 为假时字符串列的空字段为空字符串
+        let options = CsvOptions {
+            empty_as_null: false,
+            ..Default::default()
+        };
+        let csv = "-1,,,no\n-2,x,,T\n";
+        assert_eq!(
+            session.copy_from("t", csv.as_bytes(), &options)?.inserted,
+            2
+        );
+        assert_eq!(
+            session
+                .execute("SELECT name, score, active FROM t WHERE id < 0 ORDER BY id;")?
+                .rows(),
+            vec![
+                vec![
+                    Value::String("x".to_string()),
+                    Value::Null,
+                    Value::Boolean(true)
+                ],
+                vec![
+                    Value::String(String::new()),
+                    Value::Null,
+                    Value::Boolean(false)
+                ],
+            ]
+        );
+
+        // 列名不存在，或者字段数和列数不同
+        let err = session
+            .copy_from(
+                "t",
+                "id,age\n".as_bytes(),
+                &CsvOptions {
+                    header: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UndefinedColumn);
+        let err = session
+            .copy_from("t", "-3,a\n".as_bytes(), &CsvOptions::default())
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ValueCountMismatch);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_from_bad_rows() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INT PRIMARY KEY, email STRING, score INT NULL);")?;
+        session.execute("CREATE UNIQUE INDEX idx_email ON t (email);")?;
+        session.execute("INSERT INTO t VALUES (0, 'z', NULL);")?;
+
+        // 第 2 条记录跨越两行，第 5 行的分数不是整数，第 7 行的主键和已有的行重复，第 8 行的 email 和第 2 行重复，第 9 行缺少 email
+        let csv = "id,email,score\n\
+                   1,a,10\n\
+                   2,\"multi\nline\",20\n\
+                   3,c,abc\n\
+                   4,d,\n\
+                   0,e,50\n\
+                   5,a,60\n\
+                   6,,70\n\
+                   7,g,80\n";
+        let count = |session: &mut Session<MemoryStorage>| -> Result<Value> {
+            Ok(session.execute("SELECT COUNT(*) FROM t;")?.rows()[0][0].clone())
+        };
+
+        // 停止导入时错误中包含行号，已经写入的行随事务回滚
+        let mut options = CsvOptions {
+            header: true,
+            batch_size: 2,
+            ..Default::default()
+        };
+        let err = session
+            .copy_from("t", csv.as_bytes(), &options)
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidCast);
+        assert!(err
+            .to_string()
+            .contains("while importing table 't' at line 5"));
+        assert_eq!(count(&mut session)?, Value::Integer(1));
+
+        // 跳过出错的行，其余的行全部写入
+        options.on_error = OnError::Skip;
+        let result = session.copy_from("t", csv.as_bytes(), &options)?;
+        assert_eq!(result.inserted, 4);
+        assert_eq!(
+            result
+                .skipped
+                .iter()
+                .map(|(line, e)| (*line, e.code()))
+                .collect::<Vec<_>>(),
+            vec![
+                (5, ErrorCode::InvalidCast),
+                (7, ErrorCode::UniqueViolation),
+                (8, ErrorCode::UniqueViolation),
+                (9, ErrorCode::NotNullViolation),
+            ]
+        );
+        assert_eq!(
+            session
+                .execute("SELECT id FROM t WHERE email = 'a' OR score IS NULL ORDER BY id;")?
+                .rows(),
+            vec![
+                vec![Value::Integer(0)],
+                vec![Value::Integer(1)],
+                vec![Value::Integer(4)]
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_to_round_trip() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        for table in ["t", "u"] {
+            session.execute(&format!(
+                "CREATE TABLE {table} (id INT PRIMARY KEY, name STRING NULL, score FLOAT NULL, active BOOL NULL, doc JSON NULL);"
+            ))?;
+        }
+        session.execute(
+            "INSERT INTO t VALUES \
+             (1, 'plain', 1.0 / 3.0, true, '{\"k\": \"v, \\\"x\\\"\"}'), \
+             (2, 'a,b \"quoted\"', 0.1 + 0.2, false, '[1, 2.5, null]'), \
+             (3, 'line1\nline2\r\nline3', -0.5, NULL, NULL), \
+             (4, '', 0.0, NULL, NULL), \
+             (5, '  padded  ', NULL, true, '\"\"'), \
+             (6, NULL, 100.0, NULL, NULL);",
+        )?;
+        // SQL 中不能书写的浮点数通过导入写入
+        let options = CsvOptions {
+            null: "\\N".to_string(),
+            ..Default::default()
+        };
+        let csv = "7,\\N,1e300,t,\\N\n8,x,-inf,f,\\N\n9,y,5e-324,,\\N\n";
+        session.copy_from("t", csv.as_bytes(), &options)?;
+
+        // 使用 \N 表示 NULL，空字符串和 NULL 导出后可以区分
+        let file = NamedTempFile::new()?;
+        let path = file.path().display();
+        assert_eq!(
+            session.execute(&format!(
+                "COPY (SELECT * FROM t ORDER BY id) TO '{path}' WITH (HEADER true, NULL '\\N');"
+            ))?,
+            ResultSet::modified(9)
+        );
+        let exported = std::fs::read_to_string(file.path())?;
+        assert!(exported.starts_with("id,name,score,active,doc\n"));
+        assert!(exported.contains("\n4,,0.0,\\N,\\N\n"));
+        assert!(exported.contains("\n7,\\N,1e300,true,\\N\n"));
+        session.execute(&format!(
+            "COPY u FROM '{path}' WITH (HEADER true, NULL '\\N', EMPTY_AS_NULL false);"
+        ))?;
+        let select = |session: &mut Session<MemoryStorage>, table| -> Result<Vec<Row>> {
+            Ok(session
+                .execute(&format!("SELECT * FROM {table} ORDER BY id;"))?
+                .rows()
+                .to_vec())
+        };
+        let rows = select(&mut session, "t")?;
+        assert_eq!(rows.len(), 9);
+        assert_eq!(select(&mut session, "u")?, rows);
+
+        // 默认选项下空字符串和 NULL 都导出为空字段，结果的列名为查询输出的列名
+        let mut csv = Vec::new();
+        let count = session.copy_to(
+            "SELECT id, score * 2 AS doubled FROM t WHERE id <= 2 UNION ALL SELECT id, NULL FROM u WHERE id = 4;",
+            &mut csv,
+            &CsvOptions {
+                header: true,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(count, 3);
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            format!(
+                "id,doubled\n1,{:?}\n2,{:?}\n4,\n",
+                1.0 / 3.0 * 2.0,
+                (0.1 + 0.2) * 2.0
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_to_quoting() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        session.execute("CREATE TABLE q (id INT PRIMARY KEY, s STRING NULL);")?;
+        session.execute(
+            "INSERT INTO q VALUES (1, 'a;b'), (2, 'say \"hi\"'), (3, 'x\ny'), (4, 'a,b'), (5, ''), (6, NULL);",
+        )?;
+        let export = |session: &mut Session<MemoryStorage>, query| -> Result<String> {
+            let mut csv = Vec::new();
+            let options = CsvOptions {
+                delimiter: b';',
+                ..Default::default()
+            };
+            session.copy_to(query, &mut csv, &options)?;
+            Ok(String::from_utf8(csv).unwrap())
+        };
+
+        // 包含分隔符、引号和换行的字段加上引号，字段中的引号写为两个引号，其他字段原样写入
+        assert_eq!(
+            export(&mut session, "SELECT * FROM q ORDER BY id;")?,
+            "1;\"a;b\"\n2;\"say \"\"hi\"\"\"\n3;\"x\ny\"\n4;a,b\n5;\n6;\n"
+        );
+        // 只有一个空字段的行加上引号，不会被当作空行
+        assert_eq!(
+            export(&mut session, "SELECT s FROM q WHERE id >= 4 ORDER BY id;")?,
+            "a,b\n\"\"\n\"\"\n"
+        );
+
+        let err = export(&mut session, "DELETE FROM q;").unwrap_err();
+        assert!(matches!(err, Error::Parse(_)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,806 @@
+use crate::{
+    error::{
+        Error::Internal,
+        ExecutionError::{TypeMismatch, UnboundParameter},
+        PlanError::{AmbiguousColumn, ColumnNotFound, Invalid, InvalidColumnName},
+    },
+    parser::ast::{Expression, Operation, ValueSet},
+    schema::{DataType, Row, Value},
+    Result,
+};
+
+/// 计算表达式的值
+///
+/// `columns` 和 `row` 为表达式中字段的取值来源，`columns` 中的列名为 `col_name` 或 `table_name.col_name` 的形式。
+/// 对于常量表达式，`columns` 和 `row` 可以为空。
+///
+/// 运算的类型规则和 [`infer_type`] 相同：整数和浮点数混合运算或比较时提升为浮点数，
+/// 其他不同类型之间的运算和比较返回 [`TypeMismatch`]，逻辑运算的操作数必须是布尔值，
+/// NULL 可以出现在任何位置，除了逻辑运算的短路情况外结果都为 NULL。
+pub fn evaluate(expr: &Expression, columns: &[String], row: &Row) -> Result<Value> {
+    match expr {
+        Expression::Field(col_name) => {
+            let col_idx = get_column_index_by_name(columns, col_name)?;
+            row.get(col_idx).cloned().ok_or(Internal(format!(
+                "Column {} is out of range of the row",
+                col_name
+            )))
+        }
+        Expression::Constant(_) => Ok(Value::from(expr.clone())),
+        Expression::Operation(operation) => match operation {
+            Operation::Equal(lhs, rhs) => compare(lhs, rhs, columns, row, "=", |ord| {
+                ord == Some(std::cmp::Ordering::Equal)
+            }),
+            Operation::NotEqual(lhs, rhs) => compare(lhs, rhs, columns, row, "!=", |ord| {
+                ord != Some(std::cmp::Ordering::Equal)
+            }),
+            Operation::GreaterThan(lhs, rhs) => {
+                compare_ordered(lhs, rhs, columns, row, ">", |ord| ord.is_gt())
+            }
+            Operation::GreaterThanOrEqual(lhs, rhs) => {
+                compare_ordered(lhs, rhs, columns, row, ">=", |ord| ord.is_ge())
+            }
+            Operation::LessThan(lhs, rhs) => {
+                compare_ordered(lhs, rhs, columns, row, "<", |ord| ord.is_lt())
+            }
+            Operation::LessThanOrEqual(lhs, rhs) => {
+                compare_ordered(lhs, rhs, columns, row, "<=", |ord| ord.is_le())
+            }
+            Operation::Add(lhs, rhs) => arithmetic(lhs, rhs, columns, row, "+", Value::checked_add),
+            Operation::Subtract(lhs, rhs) => {
+                arithmetic(lhs, rhs, columns, row, "-", Value::checked_sub)
+            }
+            Operation::Multiply(lhs, rhs) => {
+                arithmetic(lhs, rhs, columns, row, "*", Value::checked_mul)
+            }
+            Operation::Divide(lhs, rhs) => {
+                arithmetic(lhs, rhs, columns, row, "/", Value::checked_div)
+            }
+            // 逻辑运算采用三值逻辑：FALSE AND NULL 为 FALSE，TRUE OR NULL 为 TRUE，其余含 NULL 的情况为 NULL
+            Operation::And(lhs, rhs) => {
+                match (
+                    as_boolean(evaluate(lhs, columns, row)?, "AND")?,
+                    as_boolean(evaluate(rhs, columns, row)?, "AND")?,
+                ) {
+                    (Some(false), _) | (_, Some(false)) => Ok(Value::Boolean(false)),
+                    (Some(true), Some(true)) => Ok(Value::Boolean(true)),
+                    _ => Ok(Value::Null),
+                }
+            }
+            Operation::Or(lhs, rhs) => {
+                match (
+                    as_boolean(evaluate(lhs, columns, row)?, "OR")?,
+                    as_boolean(evaluate(rhs, columns, row)?, "OR")?,
+                ) {
+                    (Some(true), _) | (_, Some(true)) => Ok(Value::Boolean(true)),
+                    (Some(false), Some(false)) => Ok(Value::Boolean(false)),
+                    _ => Ok(Value::Null),
+                }
+            }
+            Operation::Not(expr) => Ok(match as_boolean(evaluate(expr, columns, row)?, "NOT")? {
+                Some(b) => Value::Boolean(!b),
+                None => Value::Null,
+            }),
+            Operation::IsNull(expr) => {
+                Ok(Value::Boolean(evaluate(expr, columns, row)? == Value::Null))
+            }
+        },
+        Expression::Negate(operand) => evaluate(operand, columns, row)?.neg(),
+        // 聚集函数的结果由聚集节点计算，之上的节点按照函数的名称读取
+        Expression::Function(..) => Ok(row[get_aggregate_index(columns, expr)?].clone()),
+        Expression::Coalesce(args) => evaluate_coalesce(expr, args, columns, row),
+        Expression::Scalar(function, args) => function.call(
+            args.iter()
+                .map(|arg| evaluate(arg, columns, row))
+                .collect::<Result<_>>()?,
+        ),
+        Expression::Case(operand, branches, else_result) => evaluate_case(
+            expr,
+            operand.as_deref(),
+            branches,
+            else_result.as_deref(),
+            columns,
+            row,
+        ),
+        Expression::Value(value) => Ok(value.clone()),
+        Expression::InSet(expr, set) => in_set(evaluate(expr, columns, row)?, set),
+        // 子查询在计划之前由执行器执行并替换为结果
+        Expression::Subquery(_) | Expression::InSubquery(..) => Err(Internal(format!(
+            "Subquery {} must be executed before evaluation",
+            expr
+        ))),
+        Expression::Call(..) => Err(Internal(format!(
+            "Function {} must be resolved before evaluation",
+            expr
+        ))),
+        // 占位符在执行预处理语句时替换为参数值，直接执行包含占位符的语句时没有值
+        Expression::Parameter(index) => Err(UnboundParameter(*index).into()),
+    }
+}
+
+/// 计算 `value IN set` 的值
+///
+/// 集合为空时为 FALSE；找到相等的值时为 TRUE；否则 `value` 为 NULL 或者集合中有 NULL 时为 NULL，其余情况为 FALSE。
+/// 和 `=` 一样，整数和浮点数按照数值比较，NaN 和任何值都不相等。
+fn in_set(value: Value, set: &ValueSet) -> Result<Value> {
+    check_comparable("IN", value.data_type(), set.data_type)?;
+    if set.values.is_empty() && !set.has_null {
+        return Ok(Value::Boolean(false));
+    }
+    let found = match &value {
+        Value::Null => return Ok(Value::Null),
+        Value::Float(f) if f.is_nan() => false,
+        Value::Integer(i) => {
+            set.values.contains(&value) || set.values.contains(&Value::Float(*i as f64))
+        }
+        Value::Float(f) => {
+            set.values.contains(&value)
+                || (f.fract() == 0.0
+                    && (i64::MIN as f64..i64::MAX as f64).contains(f)
+                    && set.values.contains(&Value::Integer(*f as i64)))
+        }
+        value => set.values.contains(value),
+    };
+    Ok(match found || !set.has_null {
+        true => Value::Boolean(found),
+        false => Value::Null,
+    })
+}
+
+/// 计算 CASE 表达式 `expr` 的值
+///
+/// 按顺序判断每个 `WHEN`，只计算第一个匹配的分支的结果，之后的 `WHEN` 和其余分支的结果都不会被计算，
+/// 因此 `CASE WHEN x = 0 THEN 0 ELSE 1 / x END` 不会除以 0。简单形式中操作数只计算一次，
+/// 和每个 `WHEN` 的值按照 `=` 比较，NULL 和任何值都不匹配；搜索形式中 `WHEN` 的条件为 TRUE 时匹配。
+///
+/// 结果的类型按照 [`infer_type`] 在所有分支之间统一，其中字段的类型取当前行中的值的类型，
+/// 统一为浮点数时整数结果转为浮点数。
+fn evaluate_case(
+    expr: &Expression,
+    operand: Option<&Expression>,
+    branches: &[(Expression, Expression)],
+    else_result: Option<&Expression>,
+    columns: &[String],
+    row: &Row,
+) -> Result<Value> {
+    let operand = operand
+        .map(|operand| evaluate(operand, columns, row))
+        .transpose()?;
+    let mut result = else_result;
+    for (when, then) in branches {
+        let value = evaluate(when, columns, row)?;
+        let matched = match &operand {
+            Some(operand) => {
+                check_comparable("CASE", operand.data_type(), value.data_type())?;
+                *operand != Value::Null
+                    && value != Value::Null
+                    && operand.partial_cmp(&value) == Some(std::cmp::Ordering::Equal)
+            }
+            None => as_boolean(value, "CASE WHEN")? == Some(true),
+        };
+        if matched {
+            result = Some(then);
+            break;
+        }
+    }
+    let value = match result {
+        Some(result) => evaluate(result, columns, row)?,
+        None => Value::Null,
+    };
+
+    let types = row.iter().map(Value::data_type).collect::<Vec<_>>();
+    Ok(match (value, infer_type(expr, columns, &types)?) {
+        (Value::Integer(i), Some(DataType::Float)) => Value::Float(i as f64),
+        (value, _) => value,
+    })
+}
+
+/// 计算 COALESCE 表达式 `expr` 的值，结果为第一个不为 NULL 的参数
+///
+/// 参数的类型和 CASE 的分支一样按照 [`infer_type`] 统一，类型不同且不都是数值时返回 [`TypeMismatch`]，
+/// 统一为浮点数时整数结果转为浮点数。
+fn evaluate_coalesce(
+    expr: &Expression,
+    args: &[Expression],
+    columns: &[String],
+    row: &Row,
+) -> Result<Value> {
+    let values = args
+        .iter()
+        .map(|arg| evaluate(arg, columns, row))
+        .collect::<Result<Vec<_>>>()?;
+    let types = row.iter().map(Value::data_type).collect::<Vec<_>>();
+    Ok(
+        match (Value::coalesce(&values), infer_type(expr, columns, &types)?) {
+            (Value::Integer(i), Some(DataType::Float)) => Value::Float(i as f64),
+            (value, _) => value,
+        },
+    )
+}
+
+/// 判断条件的值是否保留行，用于 `WHERE`、`JOIN ON` 和 `HAVING`
+///
+/// 只有 `TRUE` 保留行，`FALSE` 和 NULL 都不保留，其他类型的值返回 [`TypeMismatch`]。
+pub fn predicate_passes(value: Value) -> Result<bool> {
+    match value {
+        Value::Boolean(b) => Ok(b),
+        Value::Null => Ok(false),
+        value => Err(TypeMismatch(format!("Condition must be a boolean, got {:?}", value)).into()),
+    }
+}
+
+/// 推断表达式的类型，按照和 [`evaluate`] 相同的规则检查运算的类型，用于在执行前发现类型错误
+///
+/// `columns` 和 `types` 为字段和聚集函数结果的列名和类型。返回 `None` 表示表达式的值为 NULL 或者类型无法在计划时确定，
+/// 例如找不到的字段、类型未知的列，这些情况不做检查，留到执行时处理。
+pub fn infer_type(
+    expr: &Expression,
+    columns: &[String],
+    types: &[Option<DataType>],
+) -> Result<Option<DataType>> {
+    let infer = |expr: &Expression| infer_type(expr, columns, types);
+    match expr {
+        Expression::Field(col_name) => Ok(get_column_index_by_name(columns, col_name)
+            .ok()
+            .and_then(|col_idx| types.get(col_idx).copied().flatten())),
+        Expression::Constant(_) => Ok(Value::from(expr.clone()).data_type()),
+        Expression::Operation(operation) => match operation {
+            Operation::Equal(lhs, rhs)
+            | Operation::NotEqual(lhs, rhs)
+            | Operation::GreaterThan(lhs, rhs)
+            | Operation::GreaterThanOrEqual(lhs, rhs)
+            | Operation::LessThan(lhs, rhs)
+            | Operation::LessThanOrEqual(lhs, rhs) => {
+                check_comparable(operation, infer(lhs)?, infer(rhs)?)?;
+                Ok(Some(DataType::Boolean))
+            }
+            Operation::Add(lhs, rhs)
+            | Operation::Subtract(lhs, rhs)
+            | Operation::Multiply(lhs, rhs)
+            | Operation::Divide(lhs, rhs) => arithmetic_type(operation, infer(lhs)?, infer(rhs)?),
+            Operation::And(lhs, rhs) | Operation::Or(lhs, rhs) => {
+                check_boolean(operation, infer(lhs)?)?;
+                check_boolean(operation, infer(rhs)?)?;
+                Ok(Some(DataType::Boolean))
+            }
+            Operation::Not(expr) => {
+                check_boolean(operation, infer(expr)?)?;
+                Ok(Some(DataType::Boolean))
+            }
+            Operation::IsNull(expr) => {
+                infer(expr)?;
+                Ok(Some(DataType::Boolean))
+            }
+        },
+        // 负号的操作数必须是数值，结果的类型和操作数相同
+        Expression::Negate(operand) => {
+            let data_type = infer(operand)?;
+            arithmetic_type("-", data_type, data_type)
+        }
+        // 聚集函数的结果为聚集节点输出的列
+        Expression::Function(..) => Ok(get_aggregate_index(columns, expr)
+            .ok()
+            .and_then(|col_idx| types.get(col_idx).copied().flatten())),
+        // 结果为第一个不为 NULL 的参数，参数的类型按照 [`common_type`] 统一
+        Expression::Coalesce(args) => {
+            let mut data_type = None;
+            for arg in args {
+                data_type = common_type("COALESCE", data_type, infer(arg)?)?;
+            }
+            Ok(data_type)
+        }
+        // 参数的类型按照函数的签名检查，结果为签名中的类型
+        Expression::Scalar(function, args) => {
+            for (idx, arg) in args.iter().enumerate() {
+                function.check_argument(idx, infer(arg)?)?;
+            }
+            Ok(Some(function.signature().returns))
+        }
+        // 参数的类型在绑定之前未知
+        Expression::Call(..) | Expression::Parameter(_) => Ok(None),
+        // 分支结果的类型按照 [`common_type`] 统一
+        Expression::Case(operand, branches, else_result) => {
+            let operand_type = match operand {
+                Some(operand) => infer(operand)?,
+                None => None,
+            };
+            let mut data_type = None;
+            for (when, then) in branches {
+                match operand {
+                    Some(_) => check_comparable("CASE", operand_type, infer(when)?)?,
+                    None => check_boolean("CASE WHEN", infer(when)?)?,
+                }
+                data_type = common_type("CASE", data_type, infer(then)?)?;
+            }
+            if let Some(else_result) = else_result {
+                data_type = common_type("CASE", data_type, infer(else_result)?)?;
+            }
+            Ok(data_type)
+        }
+        Expression::Value(value) => Ok(value.data_type()),
+        Expression::InSet(expr, set) => {
+            check_comparable("IN", infer(expr)?, set.data_type)?;
+            Ok(Some(DataType::Boolean))
+        }
+        Expression::InSubquery(expr, _) => {
+            infer(expr)?;
+            Ok(Some(DataType::Boolean))
+        }
+        Expression::Subquery(_) => Ok(None),
+    }
+}
+
+/// 检查比较运算两侧的类型：类型相同或者都是数值时可以比较，任意一侧为 NULL 或者类型未知时不检查
+fn check_comparable(
+    op: impl std::fmt::Display,
+    lhs: Option<DataType>,
+    rhs: Option<DataType>,
+) -> Result<()> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) if lhs != rhs && !(is_numeric(lhs) && is_numeric(rhs)) => {
+            Err(TypeMismatch(format!("Cannot compare {:?} with {:?} in {}", lhs, rhs, op)).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 算术运算结果的类型：两侧都是整数时为整数，整数和浮点数混合时提升为浮点数，任意一侧为 NULL 或者类型未知时未知
+fn arithmetic_type(
+    op: impl std::fmt::Display,
+    lhs: Option<DataType>,
+    rhs: Option<DataType>,
+) -> Result<Option<DataType>> {
+    for data_type in [lhs, rhs].into_iter().flatten() {
+        if !is_numeric(data_type) {
+            return Err(TypeMismatch(format!(
+                "Cannot use {:?} as an arithmetic operand in {}",
+                data_type, op
+            ))
+            .into());
+        }
+    }
+    Ok(match (lhs, rhs) {
+        (Some(DataType::Integer), Some(DataType::Integer)) => Some(DataType::Integer),
+        (Some(_), Some(_)) => Some(DataType::Float),
+        _ => None,
+    })
+}
+
+/// 多个值作为同一个结果时统一后的类型
+///
+/// 类型相同时不变，整数和浮点数混合时提升为浮点数，其他不同的类型返回 [`TypeMismatch`]，
+/// NULL 或者类型未知的值不影响结果。
+fn common_type(
+    op: impl std::fmt::Display,
+    lhs: Option<DataType>,
+    rhs: Option<DataType>,
+) -> Result<Option<DataType>> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) if lhs == rhs => Ok(Some(lhs)),
+        (Some(lhs), Some(rhs)) if is_numeric(lhs) && is_numeric(rhs) => Ok(Some(DataType::Float)),
+        (Some(lhs), Some(rhs)) => Err(TypeMismatch(format!(
+            "Cannot use {:?} and {:?} as results of the same {}",
+            lhs, rhs, op
+        ))
+        .into()),
+        (lhs, rhs) => Ok(lhs.or(rhs)),
+    }
+}
+
+/// 检查逻辑运算的操作数是布尔值，NULL 或者类型未知时不检查
+fn check_boolean(op: impl std::fmt::Display, data_type: Option<DataType>) -> Result<()> {
+    match data_type {
+        Some(data_type) if data_type != DataType::Boolean => Err(TypeMismatch(format!(
+            "Cannot use {:?} as a boolean operand in {}",
+            data_type, op
+        ))
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+fn is_numeric(data_type: DataType) -> bool {
+    matches!(data_type, DataType::Integer | DataType::Float)
+}
+
+/// 计算算术运算的值，两侧的类型必须是数值或者 NULL
+fn arithmetic(
+    lhs: &Expression,
+    rhs: &Expression,
+    columns: &[String],
+    row: &Row,
+    op: &str,
+    f: fn(&Value, &Value) -> Result<Value>,
+) -> Result<Value> {
+    let lhs = evaluate(lhs, columns, row)?;
+    let rhs = evaluate(rhs, columns, row)?;
+    arithmetic_type(op, lhs.data_type(), rhs.data_type())?;
+    f(&lhs, &rhs)
+}
+
+/// 计算比较运算的值，两侧的类型必须可以比较，任意一侧为 NULL 时结果为 NULL
+///
+/// `f` 接收两侧值的比较结果，浮点数为 NaN 时为 `None`
+fn compare<F>(
+    lhs: &Expression,
+    rhs: &Expression,
+    columns: &[String],
+    row: &Row,
+    op: &str,
+    f: F,
+) -> Result<Value>
+where
+    F: Fn(Option<std::cmp::Ordering>) -> bool,
+{
+    let lhs = evaluate(lhs, columns, row)?;
+    let rhs = evaluate(rhs, columns, row)?;
+    check_comparable(op, lhs.data_type(), rhs.data_type())?;
+    match (&lhs, &rhs) {
+        // 与 NULL 比较的结果为 NULL
+        (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+        _ => Ok(Value::Boolean(f(lhs.partial_cmp(&rhs)))),
+    }
+}
+
+/// 计算大小比较运算的值，两侧类型不可比较或者浮点数为 NaN 时返回错误
+fn compare_ordered<F>(
+    lhs: &Expression,
+    rhs: &Expression,
+    columns: &[String],
+    row: &Row,
+    op: &str,
+    f: F,
+) -> Result<Value>
+where
+    F: Fn(std::cmp::Ordering) -> bool,
+{
+    let lhs_value = evaluate(lhs, columns, row)?;
+    let rhs_value = evaluate(rhs, columns, row)?;
+    check_comparable(op, lhs_value.data_type(), rhs_value.data_type())?;
+    match (&lhs_value, &rhs_value) {
+        (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+        _ => match lhs_value.partial_cmp(&rhs_value) {
+            Some(ord) => Ok(Value::Boolean(f(ord))),
+            None => Err(TypeMismatch(format!(
+                "Cannot compare {:?} {} {:?}",
+                lhs_value, op, rhs_value
+            ))
+            .into()),
+        },
+    }
+}
+
+/// 将逻辑运算的操作数转为布尔值，NULL 对应 `None`
+fn as_boolean(value: Value, op: &str) -> Result<Option<bool>> {
+    check_boolean(op, value.data_type())?;
+    Ok(match value {
+        Value::Boolean(b) => Some(b),
+        _ => None,
+    })
+}
+
+/// 查找聚集函数的结果所在的列索引，聚集节点输出的列名为函数本身，如 `COUNT(DISTINCT name)`
+pub fn get_aggregate_index(columns: &[String], expr: &Expression) -> Result<usize> {
+    let name = expr.to_string();
+    columns.iter().position(|col_name| *col_name == name).ok_or(
+        Invalid(format!(
+            "Aggregate function {} cannot be evaluated on a single row",
+            name
+        ))
+        .into(),
+    )
+}
+
+/// 根据列名查找列索引
+///
+/// columns 为 table_name.col_name 的形式，col_name 可能为 col_name 或 table_name.col_name
+pub fn get_column_index_by_name(columns: &[String], col_name: &str) -> Result<usize> {
+    let parts = col_name.split('.').collect::<Vec<_>>();
+    match parts.len() {
+        1 => {
+            // 仅包含 col_name，则按照最后部分匹配
+            let matches = columns
+                .iter()
+                .enumerate()
+                .filter(|(_, full_name)| full_name.split('.').next_back().unwrap() == parts[0])
+                .collect::<Vec<_>>();
+            if matches.len() == 1 {
+                Ok(matches[0].0)
+            } else if matches.is_empty() {
+                Err(ColumnNotFound(col_name.to_string()).into())
+            } else {
+                Err(AmbiguousColumn(col_name.to_string()).into())
+            }
+        }
+        2 => {
+            // 包含 table_name.col_name，则直接查找
+            columns
+                .iter()
+                .position(|full_name| full_name == col_name)
+                .ok_or(ColumnNotFound(col_name.to_string()).into())
+        }
+        _ => Err(InvalidColumnName(col_name.to_string()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::Executor,
+        parser::{
+            ast::{Constant, Statement},
+            Parser,
+        },
+        storage::MemoryStorage,
+        Engine, Error,
+    };
+
+    #[test]
+    fn test_evaluate_arithmetic() -> Result<()> {
+        let columns = vec!["t.id".to_string(), "t.price".to_string()];
+        let row = vec![Value::Integer(3), Value::Float(1.5)];
+
+        let expr = Expression::Operation(Operation::Add(
+            Box::new(Expression::Field("id".to_string())),
+            Box::new(Expression::Constant(Constant::Integer(1))),
+        ));
+        assert_eq!(evaluate(&expr, &columns, &row)?, Value::Integer(4));
+
+        let expr = Expression::Operation(Operation::Multiply(
+            Box::new(Expression::Field("t.id".to_string())),
+            Box::new(Expression::Field("price".to_string())),
+        ));
+        assert_eq!(evaluate(&expr, &columns, &row)?, Value::Float(4.5));
+
+        let expr = Expression::Operation(Operation::Divide(
+            Box::new(Expression::Field("id".to_string())),
+            Box::new(Expression::Constant(Constant::Integer(0))),
+        ));
+        assert!(evaluate(&expr, &columns, &row).is_err());
+
+        let expr = Expression::Operation(Operation::Subtract(
+            Box::new(Expression::Constant(Constant::Integer(i64::MIN))),
+            Box::new(Expression::Constant(Constant::Integer(1))),
+        ));
+        assert!(evaluate(&expr, &[], &vec![]).is_err());
+
+        let expr = Expression::Operation(Operation::Add(
+            Box::new(Expression::Field("id".to_string())),
+            Box::new(Expression::Constant(Constant::Null)),
+        ));
+        assert_eq!(evaluate(&expr, &columns, &row)?, Value::Null);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_coalesce() -> Result<()> {
+        let (int, null) = (Value::Integer, Value::Null);
+        assert_eq!(Value::coalesce(&[null.clone(), int(1), int(2)]), int(1));
+        assert_eq!(Value::coalesce(&[null.clone(), null.clone()]), null);
+        assert_eq!(Value::coalesce(&[int(1), null.clone(), int(2)]), int(1));
+
+        let columns = vec!["t.a".to_string(), "t.b".to_string()];
+        let coalesce = |args: Vec<Expression>| Expression::Coalesce(args);
+        let field = |name: &str| Expression::Field(name.to_string());
+        let constant = |c| Expression::Constant(c);
+        let expr = coalesce(vec![field("a"), field("b"), constant(Constant::Integer(0))]);
+        assert_eq!(
+            evaluate(&expr, &columns, &vec![null.clone(), int(2)])?,
+            int(2)
+        );
+        assert_eq!(
+            evaluate(&expr, &columns, &vec![int(1), null.clone()])?,
+            int(1)
+        );
+        assert_eq!(
+            evaluate(&expr, &columns, &vec![null.clone(), null.clone()])?,
+            int(0)
+        );
+        let expr = coalesce(vec![field("a"), constant(Constant::Null)]);
+        assert_eq!(
+            evaluate(&expr, &columns, &vec![null.clone(), int(2)])?,
+            null
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_case() -> Result<()> {
+        let engine = Engine::new(MemoryStorage::new());
+        let executor = Executor::from_engine(&engine)?;
+        let query = |sql: &str| -> Result<Vec<Value>> {
+            Ok(executor
+                .execute(Parser::new(sql).parse()?)?
+                .rows()
+                .iter()
+                .map(|row| row[0].clone())
+                .collect())
+        };
+        let int = Value::Integer;
+        query("CREATE TABLE t (id INT PRIMARY KEY, x INT NULL);")?;
+        query("INSERT INTO t VALUES (1, 0), (2, 5), (3, NULL), (4, 20);")?;
+
+        // 未选中的分支不会被计算，因此不会除以 0
+        assert!(query("SELECT 10 / x FROM t ORDER BY id;").is_err());
+        assert_eq!(
+            query("SELECT CASE WHEN x = 0 THEN 0 ELSE 10 / x END FROM t ORDER BY id;")?,
+            vec![int(0), int(2), Value::Null, int(0)]
+        );
+        assert_eq!(
+            query("SELECT CASE x WHEN 0 THEN 100 WHEN 10 / x THEN 1 END FROM t ORDER BY id;")?,
+            vec![int(100), Value::Null, Value::Null, Value::Null]
+        );
+        assert_eq!(
+            query("SELECT id FROM t WHERE CASE WHEN x = 0 THEN FALSE ELSE 10 / x > 1 END;")?,
+            vec![int(2)]
+        );
+
+        // 没有分支匹配且没有 ELSE 时为 NULL，简单形式中 NULL 和任何值都不匹配
+        assert_eq!(
+            query("SELECT CASE WHEN x > 100 THEN 'big' END FROM t ORDER BY id;")?,
+            vec![Value::Null; 4]
+        );
+        assert_eq!(
+            query("SELECT CASE x WHEN NULL THEN 1 ELSE 0 END FROM t WHERE id = 3;")?,
+            vec![int(0)]
+        );
+        assert_eq!(
+            query("SELECT CASE x WHEN 5 THEN 'five' WHEN 5 THEN 'again' ELSE 'other' END FROM t ORDER BY id;")?,
+            ["other", "five", "other", "other"]
+                .map(|s| Value::String(s.to_string()))
+                .to_vec()
+        );
+
+        // 整数和浮点数的分支统一为浮点数
+        assert_eq!(
+            query("SELECT CASE WHEN x > 0 THEN x ELSE 0.5 END FROM t ORDER BY id;")?,
+            vec![
+                Value::Float(0.5),
+                Value::Float(5.0),
+                Value::Float(0.5),
+                Value::Float(20.0)
+            ]
+        );
+        assert!(matches!(
+            query("SELECT CASE WHEN x > 0 THEN x ELSE 'none' END FROM t;"),
+            Err(Error::Execution(TypeMismatch(_)))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_rules() -> Result<()> {
+        let columns = ["t.i", "t.f", "t.s", "t.b", "t.n"].map(String::from);
+        let types = [
+            DataType::Integer,
+            DataType::Float,
+            DataType::String,
+            DataType::Boolean,
+            DataType::Integer,
+        ]
+        .map(Some);
+        let row = vec![
+            Value::Integer(3),
+            Value::Float(1.5),
+            Value::String("a".to_string()),
+            Value::Boolean(true),
+            Value::Null,
+        ];
+        let parse = |expr: &str| -> Result<Expression> {
+            match Parser::new(&format!("SELECT * FROM t WHERE {expr};")).parse()? {
+                Statement::Select {
+                    filter: Some(filter),
+                    ..
+                } => Ok(filter),
+                statement => panic!("unexpected statement {:?}", statement),
+            }
+        };
+
+        use DataType::{Boolean, Float, Integer};
+        let (int, float, boolean) = (Value::Integer, Value::Float, Value::Boolean);
+        let mismatch = || -> Result<Value> { Err(TypeMismatch(String::new()).into()) };
+        let division_by_zero =
+            || -> Result<Value> { Err(crate::ExecutionError::DivisionByZero.into()) };
+        // 依次为表达式、计划时推断的类型（`None` 表示计划时报错）和执行的结果
+        let cases = vec![
+            // 整数和浮点数混合运算或比较时提升为浮点数
+            ("i + 1", Some(Some(Integer)), Ok(int(4))),
+            ("i / 2", Some(Some(Integer)), Ok(int(1))),
+            ("i + f", Some(Some(Float)), Ok(float(4.5))),
+            ("f - i", Some(Some(Float)), Ok(float(-1.5))),
+            ("i * 2.0", Some(Some(Float)), Ok(float(6.0))),
+            // 负号的结果和操作数的类型相同
+            ("-i", Some(Some(Integer)), Ok(int(-3))),
+            ("-f * 2", Some(Some(Float)), Ok(float(-3.0))),
+            ("i - -i = 6", Some(Some(Boolean)), Ok(boolean(true))),
+            ("i = 3.0", Some(Some(Boolean)), Ok(boolean(true))),
+            ("f < i", Some(Some(Boolean)), Ok(boolean(true))),
+            ("i != f", Some(Some(Boolean)), Ok(boolean(true))),
+            ("s < 'b'", Some(Some(Boolean)), Ok(boolean(true))),
+            ("b = FALSE", Some(Some(Boolean)), Ok(boolean(false))),
+            ("(i > 1) = b", Some(Some(Boolean)), Ok(boolean(true))),
+            // 字符串和数值之间不做转换
+            ("s + 1", None, mismatch()),
+            ("1 - s", None, mismatch()),
+            ("s = 1", None, mismatch()),
+            ("i > 'a'", None, mismatch()),
+            ("s != 1.5", None, mismatch()),
+            // 布尔值只能用于比较和逻辑运算
+            ("b = 1", None, mismatch()),
+            ("b + 1", None, mismatch()),
+            ("-b", None, mismatch()),
+            ("-s", None, mismatch()),
+            ("i AND b", None, mismatch()),
+            ("b OR s", None, mismatch()),
+            ("NOT s", None, mismatch()),
+            ("NOT i = 3", Some(Some(Boolean)), Ok(boolean(false))),
+            // NULL 参与的运算结果为 NULL，但是不会掩盖另一侧的类型错误
+            ("n + 1", Some(Some(Integer)), Ok(Value::Null)),
+            ("-n", Some(Some(Integer)), Ok(Value::Null)),
+            ("NULL * f", Some(None), Ok(Value::Null)),
+            ("n = i", Some(Some(Boolean)), Ok(Value::Null)),
+            ("NULL = 'a'", Some(Some(Boolean)), Ok(Value::Null)),
+            ("NULL + 's'", None, mismatch()),
+            ("NULL AND s", None, mismatch()),
+            ("NULL AND FALSE", Some(Some(Boolean)), Ok(boolean(false))),
+            ("NULL OR b", Some(Some(Boolean)), Ok(boolean(true))),
+            ("NOT NULL", Some(Some(Boolean)), Ok(Value::Null)),
+            ("n IS NULL", Some(Some(Boolean)), Ok(boolean(true))),
+            ("s + 1 IS NULL", None, mismatch()),
+            // 计划时按照列的类型检查，执行时列的值为 NULL 则不会报错
+            ("n > 'a'", None, Ok(Value::Null)),
+            ("COALESCE(n, i) + 1", Some(Some(Integer)), Ok(int(4))),
+            ("COALESCE(s, 1) + 1", None, mismatch()),
+            // COALESCE 的参数统一类型，整数和浮点数混合时为浮点数
+            ("COALESCE(i, f)", Some(Some(Float)), Ok(float(3.0))),
+            ("COALESCE(n, i, f)", Some(Some(Float)), Ok(float(3.0))),
+            ("COALESCE(i, s)", None, mismatch()),
+            // CASE 的分支结果统一类型，整数和浮点数混合时为浮点数
+            (
+                "CASE WHEN b THEN i ELSE f END",
+                Some(Some(Float)),
+                Ok(float(3.0)),
+            ),
+            (
+                "CASE i WHEN 3.0 THEN 'x' END",
+                Some(Some(DataType::String)),
+                Ok(Value::String("x".to_string())),
+            ),
+            ("CASE WHEN b THEN i ELSE s END", None, mismatch()),
+            ("CASE s WHEN 1 THEN i END", None, mismatch()),
+            ("CASE WHEN i THEN 1 END", None, mismatch()),
+            // 计划时无法发现的错误
+            ("i / 0", Some(Some(Integer)), division_by_zero()),
+            ("i - 1 / (i - 3)", Some(Some(Integer)), division_by_zero()),
+        ];
+
+        let same_result = |lhs: &Result<Value>, rhs: &Result<Value>| match (lhs, rhs) {
+            (Ok(lhs), Ok(rhs)) => lhs == rhs,
+            // 类型不匹配的错误信息不参与比较
+            (Err(Error::Execution(TypeMismatch(_))), Err(Error::Execution(TypeMismatch(_)))) => {
+                true
+            }
+            (Err(lhs), Err(rhs)) => lhs == rhs,
+            _ => false,
+        };
+        for (sql, data_type, value) in cases {
+            let expr = parse(sql)?;
+            match (infer_type(&expr, &columns, &types), data_type) {
+                (Ok(actual), Some(expected)) => assert_eq!(actual, expected, "{sql}"),
+                (Err(Error::Execution(TypeMismatch(_))), None) => {}
+                (actual, expected) => panic!("{sql}: inferred {actual:?}, expected {expected:?}"),
+            }
+            let actual = evaluate(&expr, &columns, &row);
+            assert!(
+                same_result(&actual, &value),
+                "{sql}: evaluated {actual:?}, expected {value:?}"
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+use std::ops::Bound;
+
+use crate::{
+    error::{
+        ExecutionError::ForeignKeyViolation,
+        Result,
+        SchemaError::{ColumnNotFound, TableNotFound},
+    },
+    executor::Executor,
+    schema::{ForeignKey, OnDelete, Row, Table, Value},
+    storage::Storage,
+};
+
+impl<S: Storage> Executor<S> {
+    /// 检查行中外键引用的父表行是否存在，外键列为 NULL 时不检查
+    ///
+    /// 在语句写入所有行之后调用，因此同一条语句中先后写入的行可以互相引用。
+    pub(super) fn check_references(&self, table: &Table, rows: &[Row]) -> Result<()> {
+        for foreign_key in &table.foreign_keys {
+            let parent = match foreign_key.parent_table == table.name {
+                true => None,
+                false => Some(
+                    self.transaction
+                        .get_table(&foreign_key.parent_table)?
+                        .ok_or(TableNotFound(foreign_key.parent_table.clone()))?,
+                ),
+            };
+            let parent = parent.as_ref().unwrap_or(table);
+            let col_idx = Self::foreign_key_index(table, foreign_key)?;
+            for row in rows {
+                let key = &row[col_idx];
+                if *key != Value::Null && self.transaction.get_row(parent, key)?.is_none() {
+                    return Err(Self::violation(table, foreign_key, key));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 检查不再存在的主键 `keys` 没有被其他行引用，主键被同一条语句中的其他行重新使用时不检查
+    pub(super) fn check_referenced(&self, table: &Table, keys: &[Value]) -> Result<()> {
+        let mut removed = Vec::new();
+        for key in keys {
+            if self.transaction.get_row(table, key)?.is_none() {
+                removed.push(key);
+            }
+        }
+        if removed.is_empty() {
+            return Ok(());
+        }
+
+        for (child, foreign_key) in self.referencing(table)? {
+            for key in &removed {
+                if !self.referencing_rows(&child, &foreign_key, key)?.is_empty() {
+                    return Err(Self::violation(&child, &foreign_key, key));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 删除行，并按照引用它们的外键的 `ON DELETE` 处理子表中的行
+    ///
+    /// 先删除所有行再处理引用，因此同一条语句删除的行之间的引用不会违反约束。
+    /// `CASCADE` 递归删除子表中的行，已经删除的行不会再被找到，因此自引用的表也会终止。
+    pub(super) fn delete_rows(&self, table: &Table, rows: &[Row]) -> Result<()> {
+        for row in rows {
+            self.transaction
+                .delete_row(table, table.get_primary_key(row))?;
+        }
+
+        for (child, foreign_key) in self.referencing(table)? {
+            for row in rows {
+                let key = table.get_primary_key(row);
+                let children = self.referencing_rows(&child, &foreign_key, key)?;
+                if children.is_empty() {
+                    continue;
+                }
+                match foreign_key.on_delete {
+                    OnDelete::Restrict => return Err(Self::violation(&child, &foreign_key, key)),
+                    OnDelete::Cascade => self.delete_rows(&child, &children)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 所有引用 `table` 的外键，以及定义外键的子表
+    fn referencing(&self, table: &Table) -> Result<Vec<(Table, ForeignKey)>> {
+        let mut referencing = Vec::new();
+        for child in self.transaction.list_tables()? {
+            for foreign_key in &child.foreign_keys {
+                if foreign_key.parent_table == table.name {
+                    referencing.push((child.clone(), foreign_key.clone()));
+                }
+            }
+        }
+        Ok(referencing)
+    }
+
+    /// 子表中外键值为 `key` 的行，外键列上有索引时使用索引查找
+    fn referencing_rows(
+        &self,
+        child: &Table,
+        foreign_key: &ForeignKey,
+        key: &Value,
+    ) -> Result<Vec<Row>> {
+        let index = child.indexes.iter().find(|index| {
+            index.predicate.is_none() && index.columns.first() == Some(&foreign_key.column)
+        });
+        match index {
+            Some(index) => self.transaction.scan_index(
+                child,
+                index,
+                std::slice::from_ref(key),
+                (Bound::Unbounded, Bound::Unbounded),
+            ),
+            None => {
+                let col_idx = Self::foreign_key_index(child, foreign_key)?;
+                self.transaction
+                    .scan_table_iter(child, None)
+                    .filter(|row| row.as_ref().map_or(true, |row| row[col_idx] == *key))
+                    .collect()
+            }
+        }
+    }
+
+    /// 外键列在表中的位置
+    fn foreign_key_index(table: &Table, foreign_key: &ForeignKey) -> Result<usize> {
+        table.get_col_idx(&foreign_key.column).ok_or(
+            ColumnNotFound {
+                table: table.name.clone(),
+                column: foreign_key.column.clone(),
+            }
+            .into(),
+        )
+    }
+
+    fn violation(table: &Table, foreign_key: &ForeignKey, key: &Value) -> crate::Error {
+        ForeignKeyViolation {
+            constraint: foreign_key.name.clone(),
+            table: table.name.clone(),
+            key: key.clone(),
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::MemoryStorage, Database, Engine, Error};
+
+    #[test]
+    fn test_foreign_key() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        let count = |session: &mut crate::Session<MemoryStorage>, table: &str| -> Result<i64> {
+            session
+                .execute(&format!("SELECT COUNT(*) FROM {table};"))?
+                .rows()[0][0]
+                .as_i64()
+        };
+        let violation = |constraint: &str, table: &str, key: i64| -> Error {
+            ForeignKeyViolation {
+                constraint: constraint.to_string(),
+                table: table.to_string(),
+                key: Value::Integer(key),
+            }
+            .into()
+        };
+
+        // 三层级联：region <- city <- shop，另有一个 RESTRICT 引用 city 的表
+        for sql in [
+            "CREATE TABLE region (id INT PRIMARY KEY);",
+            "CREATE TABLE city (id INT PRIMARY KEY, region INT NULL REFERENCES region ON DELETE CASCADE);",
+            "CREATE TABLE shop (id INT PRIMARY KEY, city INT NULL REFERENCES city ON DELETE CASCADE);",
+            "CREATE INDEX idx_city ON shop (city);",
+            "CREATE TABLE mayor (id INT PRIMARY KEY, city INT NULL REFERENCES city);",
+            "INSERT INTO region VALUES (1), (2);",
+            "INSERT INTO city VALUES (10, 1), (11, 1), (20, 2);",
+            "INSERT INTO shop VALUES (100, 10), (101, 11), (102, 20), (103, NULL);",
+        ] {
+            session.execute(sql)?;
+        }
+
+        // 外键列的类型必须和父表主键一致，父表必须存在
+        assert!(session
+            .execute("CREATE TABLE bad (id INT PRIMARY KEY, city STRING REFERENCES city);")
+            .is_err());
+        assert!(session
+            .execute("CREATE TABLE bad (id INT PRIMARY KEY, x INT REFERENCES missing);")
+            .is_err());
+
+        // 插入或更新子表时父表的行必须存在，失败的语句整体回滚
+        assert_eq!(
+            session.execute("INSERT INTO city VALUES (30, 3);"),
+            Err(violation("fk_city_region", "city", 3))
+        );
+        assert_eq!(
+            session.execute("UPDATE shop SET city = 99 WHERE id = 100;"),
+            Err(violation("fk_shop_city", "shop", 99))
+        );
+        assert_eq!(count(&mut session, "city")?, 3);
+
+        // 更新被引用的主键违反 RESTRICT，整体平移后旧主键仍然存在则没有违反
+        assert!(matches!(
+            session.execute("UPDATE region SET id = 5 WHERE id = 2;"),
+            Err(Error::Execution(ForeignKeyViolation { .. }))
+        ));
+        session.execute("UPDATE shop SET id = id + 1;")?;
+
+        // 级联删除在显式事务中回滚
+        session.execute("BEGIN;")?;
+        session.execute("DELETE FROM region WHERE id = 1;")?;
+        assert_eq!(count(&mut session, "city")?, 1);
+        assert_eq!(count(&mut session, "shop")?, 2);
+        session.execute("ROLLBACK;")?;
+        assert_eq!(count(&mut session, "city")?, 3);
+        assert_eq!(count(&mut session, "shop")?, 4);
+
+        // 级联删除遇到 RESTRICT 引用时整条语句失败
+        session.execute("INSERT INTO mayor VALUES (1, 20);")?;
+        assert_eq!(
+            session.execute("DELETE FROM region;"),
+            Err(violation("fk_mayor_city", "mayor", 20))
+        );
+        assert_eq!(count(&mut session, "shop")?, 4);
+        session.execute("DELETE FROM mayor;")?;
+        session.execute("DELETE FROM region;")?;
+        assert_eq!(count(&mut session, "city")?, 0);
+        assert_eq!(count(&mut session, "shop")?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_self_referencing_foreign_key() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        session.execute(
+            "CREATE TABLE emp (id INT PRIMARY KEY, manager INT NULL REFERENCES emp ON DELETE CASCADE);",
+        )?;
+
+        // 同一条语句中的行可以引用之前插入的行，也可以引用自身
+        session.execute("INSERT INTO emp VALUES (1, NULL), (2, 1), (3, 2), (4, 4);")?;
+        assert!(session.execute("INSERT INTO emp VALUES (5, 6);").is_err());
+
+        // 递归删除整棵子树
+        session.execute("DELETE FROM emp WHERE id = 1;")?;
+        let result = session.execute("SELECT id FROM emp;")?;
+        assert_eq!(result.rows(), [vec![Value::Integer(4)]]);
+
+        Ok(())
+    }
+}
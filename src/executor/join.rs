@@ -1,179 +1,269 @@
 use std::collections::HashMap;
 
+use super::Rows;
 use crate::{
-    error::Error::InternalError,
-    parser::ast::{Expression, JoinType, Operation},
+    error::Error::Internal,
+    executor::expression::{evaluate, predicate_passes},
+    parser::ast::{Expression, JoinType},
     schema::{Row, Value},
     Result,
 };
 
-/// 循环连接，将左右表的行进行笛卡尔积
-pub fn loop_join(
-    left_cols: &[String],
-    right_cols: &[String],
-    left_rows: &[Row],
-    right_rows: &[Row],
-) -> Result<(Vec<String>, Vec<Row>)> {
-    let new_columns = [left_cols, right_cols].concat();
-    let new_rows: Vec<Row> = left_rows
-        .iter()
-        .flat_map(|left_row| {
-            right_rows.iter().map(move |right_row| {
-                let mut row = left_row.clone();
-                row.extend(right_row.clone());
-                row
-            })
-        })
-        .collect();
-    Ok((new_columns, new_rows))
+/// 嵌套循环连接
+///
+/// 右表的行全部读入内存，左表的行逐行读取：对左表的每一行扫描右表的所有行，
+/// 将两行拼接后在 `columns`（左右表列名的拼接）上计算 `predicate`，结果为 `TRUE` 时输出拼接后的行。
+/// `predicate` 为 `None` 时即为 CROSS JOIN。
+/// LEFT JOIN 中没有匹配的左表行，会以 `right_width` 个 NULL 填充右表的列后输出。
+pub fn nested_loop_join<'a>(
+    columns: Vec<String>,
+    left_rows: Rows<'a>,
+    right_rows: Vec<Row>,
+    right_width: usize,
+    join_type: JoinType,
+    predicate: Option<Expression>,
+) -> Result<Rows<'a>> {
+    if !matches!(
+        join_type,
+        JoinType::Cross | JoinType::Inner | JoinType::Left
+    ) {
+        return Err(Internal(format!(
+            "Unsupported join type for nested loop join: {}",
+            join_type
+        )));
+    }
+
+    // 每个左表行产生的所有结果行
+    let join_row = move |left_row: Row| -> Result<Vec<Row>> {
+        let mut new_rows = Vec::new();
+        for right_row in &right_rows {
+            let mut new_row = left_row.clone();
+            new_row.extend(right_row.iter().cloned());
+
+            // 条件为 FALSE 或 NULL 时均不匹配，因此 NULL 值不会和任何值匹配
+            let is_match = match &predicate {
+                Some(predicate) => predicate_passes(evaluate(predicate, &columns, &new_row)?)?,
+                None => true,
+            };
+            if is_match {
+                new_rows.push(new_row);
+            }
+        }
+
+        // 如果是 LEFT JOIN，则将右表的列填充为 NULL，否则忽略
+        if new_rows.is_empty() && matches!(join_type, JoinType::Left) {
+            let mut new_row = left_row;
+            new_row.extend(vec![Value::Null; right_width]);
+            new_rows.push(new_row);
+        }
+        Ok(new_rows)
+    };
+    Ok(Box::new(left_rows.flat_map(
+        move |row| match row.and_then(&join_row) {
+            Ok(new_rows) => new_rows.into_iter().map(Ok).collect::<Vec<_>>(),
+            Err(e) => vec![Err(e)],
+        },
+    )))
 }
 
-/// 哈希连接，根据 Join 条件将左右表的行合并
-pub fn hash_join(
-    left_cols: &[String],
-    right_cols: &[String],
-    left_rows: &[Row],
-    right_rows: &[Row],
-    join_type: &JoinType,
-    predicate: &Expression,
-) -> Result<(Vec<String>, Vec<Row>)> {
-    // 解析 Join 条件
-    let (left_cond, right_cond) = match predicate {
-        Expression::Operation(Operation::Equal(left, right))
-            if left.is_field() && right.is_field() =>
-        {
-            (left.as_field().unwrap(), right.as_field().unwrap())
+/// 哈希连接
+///
+/// `keys` 为等值连接条件的两个字段分别在左右表行中的下标，`left_width` 为左表的列数。
+/// 在行数较少的一侧按连接键构建哈希表，用另一侧的行探测，连接键相等且 `predicate` 为 `TRUE` 时匹配。
+/// 重复的连接键会输出所有匹配的组合，NULL 连接键不和任何行匹配。
+/// LEFT、RIGHT 和 FULL JOIN 中没有匹配的行，会以 NULL 填充另一侧的列后输出。
+///
+/// 右表的行全部读入内存，左表最多读取比右表多一行：左表行数更少时在左表上构建哈希表，
+/// 否则在右表上构建，已经读取的左表行和剩余的左表行逐行探测，左表的其余行不会读入内存。
+pub fn hash_join<'a>(
+    columns: Vec<String>,
+    mut left_rows: Rows<'a>,
+    right_rows: Vec<Row>,
+    keys: (usize, usize),
+    left_width: usize,
+    join_type: JoinType,
+    predicate: Option<Expression>,
+) -> Result<Rows<'a>> {
+    // 需要保留未匹配行的一侧
+    let (preserve_left, preserve_right) = match join_type {
+        JoinType::Inner => (false, false),
+        JoinType::Left => (true, false),
+        JoinType::Right => (false, true),
+        JoinType::Full => (true, true),
+        JoinType::Cross => {
+            return Err(Internal(format!(
+                "Unsupported join type for hash join: {}",
+                join_type
+            )))
         }
-        _ => return Err(InternalError("Unsupported join condition".to_string())),
     };
 
-    // 获取左右表的列索引
-    let left_col_idx = left_cols
-        .iter()
-        .position(|col| col == left_cond)
-        .ok_or(InternalError(format!("Column {} not found", left_cond)))?;
-    let right_col_idx = right_cols
-        .iter()
-        .position(|col| col == right_cond)
-        .ok_or(InternalError(format!("Column {} not found", right_cond)))?;
-
-    // 合并左右表的列名
-    let new_columns = [left_cols, right_cols].concat();
-
-    // 根据 join_type 不同，分别构建匹配结果
-    let new_rows = match join_type {
-        // INNER 和 LEFT JOIN：构建右表哈希表，根据左表中对应值查找匹配行
-        JoinType::Inner | JoinType::Left => {
-            // 构建右表的哈希表
-            let mut right_hash = HashMap::new();
-            for row in right_rows {
-                let rows = right_hash
-                    .entry(row[right_col_idx].clone())
-                    .or_insert(Vec::new());
-                rows.push(row);
-            }
+    // 读取左表的行，直到行数超过右表或者读完
+    let mut buffered = Vec::new();
+    for row in left_rows.by_ref() {
+        buffered.push(row?);
+        if buffered.len() > right_rows.len() {
+            break;
+        }
+    }
 
-            let mut new_rows = Vec::new();
-            for left_row in left_rows {
-                if let Some(right_rows) = right_hash.get(&left_row[left_col_idx]) {
-                    for right_row in right_rows {
-                        let mut new_row = left_row.clone();
-                        new_row.extend(right_row.iter().cloned());
-                        new_rows.push(new_row);
-                    }
-                } else if matches!(join_type, JoinType::Left) {
-                    // 如果是 LEFT JOIN，则将右表的列填充为 NULL，否则忽略
-                    let mut new_row = left_row.clone();
-                    new_row.extend(vec![Value::Null; right_cols.len()]);
-                    new_rows.push(new_row);
-                }
-            }
-            new_rows
+    // 在行数较少的一侧构建哈希表
+    let build_left = buffered.len() < right_rows.len();
+    let (build_rows, build_key, probe_rows, probe_key): (_, _, Rows<'a>, _) = if build_left {
+        (
+            buffered,
+            keys.0,
+            Box::new(right_rows.into_iter().map(Ok)),
+            keys.1,
+        )
+    } else {
+        (
+            right_rows,
+            keys.1,
+            Box::new(buffered.into_iter().map(Ok).chain(left_rows)),
+            keys.0,
+        )
+    };
+    let (preserve_build, preserve_probe) = if build_left {
+        (preserve_left, preserve_right)
+    } else {
+        (preserve_right, preserve_left)
+    };
+
+    let mut hash_table: HashMap<Value, Vec<usize>> = HashMap::new();
+    for (i, row) in build_rows.iter().enumerate() {
+        if let Some(key) = hash_key(&row[build_key]) {
+            hash_table.entry(key).or_default().push(i);
         }
-        // RIGHT JOIN：构建左表哈希表，根据右表中对应值查找匹配行
-        JoinType::Right => {
-            // 构建左表的哈希表
-            let mut left_hash = HashMap::new();
-            for row in left_rows {
-                let rows = left_hash
-                    .entry(row[left_col_idx].clone())
-                    .or_insert(Vec::new());
-                rows.push(row);
-            }
+    }
 
-            // 通过右表的列值在左表的哈希表中查找匹配的行，并将左右表的行合并
-            let mut new_rows = Vec::new();
-            for right_row in right_rows {
-                if let Some(left_rows) = left_hash.get(&right_row[right_col_idx]) {
-                    for left_row in left_rows {
-                        let mut new_row = left_row.to_vec();
-                        new_row.extend(right_row.clone());
-                        new_rows.push(new_row);
-                    }
-                } else {
-                    // 将左表的列填充为 NULL
-                    let mut new_row = vec![Value::Null; left_cols.len()];
-                    new_row.extend(right_row.iter().cloned());
-                    new_rows.push(new_row);
-                }
-            }
-            new_rows
+    Ok(Box::new(HashJoin {
+        right_width: columns.len() - left_width,
+        columns,
+        left_width,
+        predicate,
+        build_matched: vec![false; build_rows.len()],
+        build_rows,
+        hash_table,
+        probe_rows,
+        probe_key,
+        build_left,
+        preserve_build,
+        preserve_probe,
+        output: Vec::new().into_iter(),
+        unmatched: None,
+    }))
+}
+
+/// 哈希连接的探测阶段，逐行读取探测侧，并记录构建侧中匹配过的行
+struct HashJoin<'a> {
+    columns: Vec<String>,
+    left_width: usize,
+    right_width: usize,
+    predicate: Option<Expression>,
+    build_rows: Vec<Row>,
+    build_matched: Vec<bool>,
+    hash_table: HashMap<Value, Vec<usize>>,
+    probe_rows: Rows<'a>,
+    probe_key: usize,
+    build_left: bool,
+    preserve_build: bool,
+    preserve_probe: bool,
+    /// 当前探测行产生的、尚未输出的行
+    output: std::vec::IntoIter<Row>,
+    /// 探测结束后，下一个要检查是否匹配过的构建侧行的下标
+    unmatched: Option<usize>,
+}
+
+impl HashJoin<'_> {
+    /// 按照左右表的顺序拼接行，缺少的一侧以 NULL 填充
+    fn concat(&self, build_row: Option<&Row>, probe_row: Option<&Row>) -> Row {
+        let (left_row, right_row) = match self.build_left {
+            true => (build_row, probe_row),
+            false => (probe_row, build_row),
+        };
+        let mut row = Vec::with_capacity(self.columns.len());
+        match left_row {
+            Some(left_row) => row.extend(left_row.iter().cloned()),
+            None => row.extend(vec![Value::Null; self.left_width]),
         }
-        // FULL JOIN：同时处理左右未匹配的情况
-        JoinType::Full => {
-            // 构建左表的哈希表
-            let mut left_hash = HashMap::new();
-            for row in left_rows {
-                let rows = left_hash
-                    .entry(row[left_col_idx].clone())
-                    .or_insert(Vec::new());
-                rows.push(row);
+        match right_row {
+            Some(right_row) => row.extend(right_row.iter().cloned()),
+            None => row.extend(vec![Value::Null; self.right_width]),
+        }
+        row
+    }
+
+    /// 用一个探测行探测哈希表，返回产生的所有行
+    fn probe(&mut self, probe_row: &Row) -> Result<Vec<Row>> {
+        let mut new_rows = Vec::new();
+        let candidates = hash_key(&probe_row[self.probe_key])
+            .and_then(|key| self.hash_table.get(&key))
+            .cloned()
+            .unwrap_or_default();
+        for i in candidates {
+            let new_row = self.concat(Some(&self.build_rows[i]), Some(probe_row));
+            let is_match = match &self.predicate {
+                Some(predicate) => predicate_passes(evaluate(predicate, &self.columns, &new_row)?)?,
+                None => true,
+            };
+            if is_match {
+                self.build_matched[i] = true;
+                new_rows.push(new_row);
             }
+        }
 
-            // 构建右表的哈希表
-            let mut right_hash = HashMap::new();
-            for row in right_rows {
-                let rows = right_hash
-                    .entry(row[right_col_idx].clone())
-                    .or_insert(Vec::new());
-                rows.push(row);
+        if new_rows.is_empty() && self.preserve_probe {
+            new_rows.push(self.concat(None, Some(probe_row)));
+        }
+        Ok(new_rows)
+    }
+}
+
+impl Iterator for HashJoin<'_> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.output.next() {
+                return Some(Ok(row));
             }
 
-            // 通过左表的列值在右表的哈希表中查找匹配的行，并将左右表的行合并
-            let mut new_rows = Vec::new();
-            for left_row in left_rows {
-                if let Some(right_rows) = right_hash.get(&left_row[left_col_idx]) {
-                    for right_row in right_rows {
-                        let mut new_row = left_row.clone();
-                        new_row.extend(right_row.iter().cloned());
-                        new_rows.push(new_row);
+            // 探测结束后，输出构建侧中没有匹配的行
+            if let Some(i) = self.unmatched.as_mut() {
+                while *i < self.build_rows.len() {
+                    *i += 1;
+                    if !self.build_matched[*i - 1] {
+                        let row = &self.build_rows[*i - 1];
+                        return Some(Ok(self.concat(Some(row), None)));
                     }
-                } else {
-                    // 将右表的列填充为 NULL
-                    let mut new_row = left_row.clone();
-                    new_row.extend(vec![Value::Null; right_cols.len()]);
-                    new_rows.push(new_row);
                 }
+                return None;
             }
 
-            // 查找右表中未匹配的行
-            for right_row in right_rows {
-                if !left_hash.contains_key(&right_row[right_col_idx]) {
-                    // 将左表的列填充为 NULL
-                    let mut new_row = vec![Value::Null; left_cols.len()];
-                    new_row.extend(right_row.iter().cloned());
-                    new_rows.push(new_row);
-                }
+            match self.probe_rows.next() {
+                Some(Ok(probe_row)) => match self.probe(&probe_row) {
+                    Ok(rows) => self.output = rows.into_iter(),
+                    Err(e) => return Some(Err(e)),
+                },
+                Some(Err(e)) => return Some(Err(e)),
+                None if self.preserve_build => self.unmatched = Some(0),
+                None => return None,
             }
-            new_rows
         }
-        _ => {
-            return Err(InternalError(format!(
-                "Unsupported join type: {}",
-                join_type
-            )))
-        }
-    };
+    }
+}
 
-    Ok((new_columns, new_rows))
+/// 哈希连接使用的 key，和 `=` 的比较语义保持一致
+///
+/// NULL 和 NaN 不和任何值相等，返回 `None`；值为整数的浮点数转换为整数，使其和对应的整数相等。
+fn hash_key(value: &Value) -> Option<Value> {
+    match value {
+        Value::Null => None,
+        Value::Float(f) if f.is_nan() => None,
+        Value::Float(f) if f.fract() == 0.0 && *f >= i64::MIN as f64 && *f < i64::MAX as f64 => {
+            Some(Value::Integer(*f as i64))
+        }
+        value => Some(value.clone()),
+    }
 }
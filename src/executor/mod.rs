@@ -1,30 +1,82 @@
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs::File,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use aggregate::aggregate;
-use join::{hash_join, loop_join};
+use aggregate::hash_aggregate;
+use expression::{
+    evaluate, get_aggregate_index, get_column_index_by_name, infer_type, predicate_passes,
+};
+use join::{hash_join, nested_loop_join};
+use set_operation::set_operation;
+use sort::sort;
 
 use crate::{
     engine::{Engine, Transaction},
-    error::{Error::InternalError, Result},
-    parser::ast::{Expression, JoinType, Ordering, SelectFrom, Statement},
-    schema::{Row, Table, Value},
-    storage::Storage,
+    error::{
+        Error::Internal,
+        ErrorContext,
+        ExecutionError::{MissingValue, ParameterCount, ValueCount},
+        PlanError::Invalid,
+        Result, ResultExt,
+        SchemaError::{ColumnNotFound, TableNotFound},
+    },
+    function::FunctionRegistry,
+    parser::ast::{Expression, InsertSource, OrderBy, SelectFrom, Statement},
+    planner::{column_types, Node, Planner},
+    schema::{DataType, IndexDef, Row, Table, Value},
+    storage::{Storage, Version},
 };
 
 mod aggregate;
+mod analyze;
+mod cancel;
+mod copy;
+pub(crate) mod expression;
+mod foreign_key;
 mod join;
+mod result;
+mod set_operation;
+mod sort;
+mod subquery;
+mod upsert;
 
-/// SQL 执行结果
-#[derive(Debug, PartialEq)]
-pub enum ExecuteResult {
-    CreateTable,
-    Insert,
-    Scan {
-        columns: Vec<String>,
-        rows: Vec<Row>,
-    },
-    Update(usize),
-    Delete(usize),
+pub use cancel::CancellationToken;
+pub use copy::{CopyResult, CsvOptions, OnError};
+pub use result::{ColumnMeta, JsonLayout, JsonOptions, JsonResult, NonFiniteFloat, ResultSet};
+pub use upsert::{ConflictAction, ConflictTarget, UpsertOutcome};
+
+/// 执行计划节点产生的行数据的迭代器
+type Rows<'a> = Box<dyn Iterator<Item = Result<Row>> + 'a>;
+
+/// EXPLAIN ANALYZE 中计划节点的执行统计，按照先序遍历的顺序排列
+type Profile = Rc<RefCell<Vec<NodeStats>>>;
+
+/// 单个计划节点的执行统计
+#[derive(Debug, Default, Clone, Copy)]
+struct NodeStats {
+    /// 实际产生的行数
+    rows: usize,
+    /// 构建和拉取行的总耗时，包含子节点的耗时
+    elapsed: Duration,
+}
+
+/// 查询语句的计划，不依赖于计划时所在的事务，可以在之后的事务中重复执行
+///
+/// 计划中保存了表的定义，表的定义改变之后必须重新计划，`tables` 为查询读取的表。
+/// 预处理语句的计划中保留了参数占位符，每次执行时绑定参数，见 [`Planner::bind_parameters`]。
+#[derive(Debug, Clone)]
+pub(crate) struct QueryPlan {
+    root: Node,
+    /// 查询是否选择了列，为假时即 SELECT *
+    is_projected: bool,
+    /// 执行计划需要的参数个数
+    pub parameters: usize,
+    pub tables: Vec<String>,
 }
 
 /// SQL 执行器
@@ -32,14 +84,19 @@ pub enum ExecuteResult {
 /// 负责执行 SQL 语句，将 SQL 语句转换为对存储引擎的操作
 pub struct Executor<S: Storage> {
     transaction: Transaction<S>,
-    is_committed: bool,
+    /// 事务是否已经提交或回滚
+    is_finished: bool,
+    /// 正在执行的语句的取消标记
+    cancellation: Option<CancellationToken>,
+    /// 语句中的标量函数调用在计划之前从注册表中查找
+    functions: Arc<FunctionRegistry>,
 }
 
 impl<S: Storage> Drop for Executor<S> {
-    /// 在执行器销毁时，检查事务是否提交，并提交事务
+    /// 在执行器销毁时，检查事务是否结束，并提交事务
     fn drop(&mut self) {
-        // 如果事务未提交，提交事务
-        if !self.is_committed {
+        // 如果事务既没有提交也没有回滚，提交事务
+        if !self.is_finished {
             if let Err(e) = self.transaction.commit() {
                 eprintln!("Failed to commit transaction: {:?}", e);
             }
@@ -52,39 +109,85 @@ impl<S: Storage> Executor<S> {
     pub fn from_engine(eng: &Engine<S>) -> Result<Self> {
         Ok(Self {
             transaction: eng.start_txn()?,
-            is_committed: false,
+            is_finished: false,
+            cancellation: None,
+            functions: eng.functions().clone(),
         })
     }
 
-    /// 执行 SQL 语句
-    pub fn execute(&self, stmt: Statement) -> Result<ExecuteResult> {
-        match stmt {
-            Statement::CreateTable { name, columns } => {
-                let table = Table::new(&name, columns)?;
+    /// 设置之后执行的语句使用的取消标记，为 `None` 时语句不能被取消
+    pub fn set_cancellation(&mut self, token: Option<CancellationToken>) {
+        self.cancellation = token;
+    }
+
+    /// 执行 SQL 语句，语句中的子查询在计划之前执行
+    pub fn execute(&self, stmt: Statement) -> Result<ResultSet> {
+        match self.materialize_subqueries(stmt)? {
+            Statement::CreateTable {
+                name,
+                columns,
+                foreign_keys,
+            } => {
+                let mut table =
+                    Table::with_namespace(&name, columns, self.transaction.namespace())?;
+                table.foreign_keys = foreign_keys;
                 self.transaction.create_table(table)?;
 
-                Ok(ExecuteResult::CreateTable)
+                Ok(ResultSet::CreateTable { name })
+            }
+            Statement::CreateIndex {
+                name,
+                table_name,
+                columns,
+                unique,
+                predicate,
+            } => {
+                let index = IndexDef {
+                    name: name.clone(),
+                    columns,
+                    unique,
+                    predicate,
+                };
+                self.transaction.create_index(&table_name, index)?;
+
+                Ok(ResultSet::CreateIndex { name })
+            }
+            Statement::DropTable { name } => {
+                self.transaction.drop_table(&name)?;
+                Ok(ResultSet::DropTable { name })
             }
             Statement::Insert {
                 table_name,
                 columns,
-                values,
+                source,
             } => {
-                self.insert(table_name, columns.unwrap_or_default(), values)?;
-                Ok(ExecuteResult::Insert)
+                let (count, last_insert_ids) =
+                    self.insert(table_name, columns.unwrap_or_default(), source)?;
+                Ok(ResultSet::Modified {
+                    count: count as u64,
+                    last_insert_ids,
+                })
             }
             Statement::Select {
                 columns,
+                distinct,
                 from,
                 filter,
+                group_by,
+                having,
                 ordering,
                 limit,
                 offset,
             } => {
-                let (columns, rows) =
-                    self.select(columns, from, filter, ordering, limit, offset)?;
+                let (columns, rows) = self.select(
+                    columns, distinct, from, filter, group_by, having, ordering, limit, offset,
+                )?;
 
-                Ok(ExecuteResult::Scan { columns, rows })
+                Ok(ResultSet::query(columns, rows))
+            }
+            stmt @ Statement::SetOperation { .. } => {
+                let (columns, rows) = self.set_operation(stmt)?;
+                Ok(ResultSet::query(columns, rows))
             }
             Statement::Update {
                 table_name,
@@ -92,20 +195,60 @@ impl<S: Storage> Executor<S> {
                 filter,
             } => {
                 let count = self.update(table_name, columns, filter)?;
-                Ok(ExecuteResult::Update(count))
+                Ok(ResultSet::modified(count as u64))
             }
             Statement::Delete { table_name, filter } => {
                 let count = self.delete(table_name, filter)?;
-                Ok(ExecuteResult::Delete(count))
+                Ok(ResultSet::modified(count as u64))
             }
+            Statement::Copy {
+                table_name,
+                path,
+                options,
+            } => {
+                let file = File::open(&path)
+                    .with_context(|| ErrorContext::new("importing").table(&table_name))?;
+                let result = self.copy_from(&table_name, file, &options)?;
+                Ok(ResultSet::modified(result.inserted))
+            }
+            Statement::CopyTo {
+                query,
+                path,
+                options,
+            } => {
+                let file = File::create(&path).context(ErrorContext::new("exporting"))?;
+                let count = self.copy_to(*query, file, &options)?;
+                Ok(ResultSet::modified(count))
+            }
+            Statement::Analyze { table_name } => Ok(ResultSet::Analyze {
+                tables: self.analyze(table_name)?,
+            }),
+            Statement::Explain { statement, analyze } => {
+                let plan = Planner::new(&self.transaction).build_query(*statement)?;
+                match analyze {
+                    true => Ok(ResultSet::Explain(self.explain_analyze(plan)?)),
+                    false => Ok(ResultSet::Explain(plan.to_string())),
+                }
+            }
+            // 执行器本身就对应一个事务，事务控制语句由会话处理
+            Statement::Begin | Statement::Commit | Statement::Rollback => Err(Invalid(
+                "Transaction control statements must be executed in a session".to_string(),
+            )
+            .into()),
         }
     }
 
+    /// 事务的版本
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.transaction.version()
+    }
+
     /// 提交事务
     #[inline]
     pub fn commit(&mut self) -> Result<()> {
         self.transaction.commit()?;
-        self.is_committed = true;
+        self.is_finished = true;
         Ok(())
     }
 
@@ -113,436 +256,696 @@ impl<S: Storage> Executor<S> {
     #[inline]
     pub fn rollback(&mut self) -> Result<()> {
         self.transaction.rollback()?;
+        self.is_finished = true;
         Ok(())
     }
 
-    /// 扫描表
-    fn scan(
-        &self,
-        table_name: &str,
-        filter: Option<(String, Expression)>,
-    ) -> Result<(Vec<String>, Vec<Row>)> {
-        let table = self
-            .transaction
-            .get_table(table_name)?
-            .ok_or(InternalError(format!("Table {table_name} not found")))?;
-
-        let columns = table.columns.iter().map(|c| c.name.clone()).collect();
-
-        let rows = self.transaction.scan_table(&table, filter)?;
-
-        Ok((columns, rows))
-    }
-
-    /// 插入数据
+    /// 插入数据，返回插入的行数和为自增列分配的 ID
+    ///
+    /// 行的来源为 `VALUES` 或者查询语句。查询的结果逐行拉取，每一行依次按照列名对应到表的列、
+    /// 补充默认值、转换类型后写入表和索引，不会先物化查询的所有结果。
+    /// 但事务中写入的行对同一事务之后的读取可见，查询读取被插入的表时，为了不读到刚插入的行，
+    /// 需要先读取查询的全部结果再写入。
     fn insert(
         &self,
         table_name: String,
         column_names: Vec<String>,
-        values: Vec<Vec<Expression>>,
-    ) -> Result<()> {
-        let table_columns = &self
+        source: InsertSource,
+    ) -> Result<(usize, Vec<i64>)> {
+        let table = self
             .transaction
             .get_table(&table_name)?
-            .ok_or(InternalError(format!("Table {table_name} not found")))?
-            .columns;
+            .ok_or(TableNotFound(table_name.to_string()))?;
 
         // columns 为空时，表示插入所有列
         let column_names = if column_names.is_empty() {
-            table_columns.iter().map(|c| c.name.clone()).collect()
+            table.columns.iter().map(|c| c.name.clone()).collect()
         } else {
             column_names
         };
+        // 表的每一列在插入的值中的位置，重复的列名以最后一个为准
+        let positions = table
+            .columns
+            .iter()
+            .map(|column| column_names.iter().rposition(|name| *name == column.name))
+            .collect::<Vec<_>>();
 
-        for value in values {
+        // 将插入的值按照表的列排列，转换为列的数据类型
+        let build_row = |mut value: Row| -> Result<Row> {
             // 检查列数是否匹配
             if column_names.len() != value.len() {
-                return Err(InternalError(format!(
-                    "Column count {} doesn't match value count {}",
-                    column_names.len(),
-                    value.len()
-                )));
+                return Err(ValueCount {
+                    columns: column_names.len(),
+                    values: value.len(),
+                }
+                .into());
             }
 
-            // 创建一个 HashMap，方便后续根据列名查找对应的值
-            let value_map: HashMap<String, Expression> = column_names
-                .iter()
-                .cloned()
-                .zip(value.into_iter())
-                .collect();
-
-            let row = table_columns
+            table
+                .columns
                 .iter()
-                .map(|column| {
-                    if let Some(exp) = value_map.get(&column.name) {
-                        // 如果找到对应的值，将其转为 Value
-                        Ok(Value::from(exp.clone()))
-                    } else if let Some(default) = &column.default {
-                        // 如果未找到对应的值，但存在默认值，使用默认值
-                        Ok(default.clone())
-                    } else {
-                        // 如果未找到对应的值，且不存在默认值，返回错误
-                        Err(InternalError(format!(
-                            "Column {} not found in value",
-                            column.name
-                        )))
+                .zip(&positions)
+                .map(|(column, position)| match position {
+                    // 如果找到对应的值，转换为列的数据类型
+                    Some(idx) => {
+                        std::mem::replace(&mut value[*idx], Value::Null).coerce_to(column.data_type)
                     }
+                    // 自增列未给出值时之后分配
+                    None if column.auto_increment => Ok(Value::Null),
+                    // 如果未找到对应的值，但存在默认值，使用默认值
+                    None => column
+                        .default
+                        .clone()
+                        .ok_or(MissingValue(column.name.clone()).into()),
                 })
-                .collect::<Result<Vec<Value>>>()?;
+                .collect::<Result<Vec<Value>>>()
+        };
+
+        let new_rows: Rows<'_> = match source {
+            // VALUES 中的值在写入之前已经全部求出，整体检查这批行，
+            // 不合法的行和批内重复的主键在写入任何行之前返回错误
+            InsertSource::Values(values) => {
+                let new_rows = values
+                    .into_iter()
+                    .map(|value| {
+                        build_row(
+                            value
+                                .iter()
+                                .map(|exp| evaluate(exp, &[], &vec![]))
+                                .collect::<Result<Row>>()?,
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                table.validate_rows(&new_rows)?;
+                Box::new(new_rows.into_iter().map(Ok))
+            }
+            InsertSource::Query(query) => {
+                let plan = Planner::new(&self.transaction).build_query(*query)?;
+                let reads_target = plan.reads_table(&table.name);
+                let (_, rows) = self.execute_node(plan)?;
+                match reads_target {
+                    true => Box::new(rows.collect::<Result<Vec<_>>>()?.into_iter().map(build_row)),
+                    false => Box::new(rows.map(move |value| build_row(value?))),
+                }
+            }
+        };
+
+        // 外键在所有行写入之后再检查，先插入的行可以被后插入的行引用，反之亦然，因此表有外键时需要保留写入的行
+        let mut rows = Vec::new();
+        let (mut count, mut last_insert_ids) = (0, Vec::new());
+        for row in new_rows {
+            let mut row = row?;
+            // 自增列的值省略或者为 NULL 时从计数器中分配，显式写入的 ID 会推进计数器，因此分配和写入交替进行
+            last_insert_ids.extend(self.transaction.fill_auto_increment(&table, &mut row)?);
 
             // 将数据插入表中
             self.transaction.create_row(&table_name, &row)?;
+            count += 1;
+            if !table.foreign_keys.is_empty() {
+                rows.push(row);
+            }
         }
+        self.check_references(&table, &rows)?;
 
-        Ok(())
+        Ok((count, last_insert_ids))
     }
 
     /// 更新数据
+    ///
+    /// 更新分为三个阶段：
+    ///
+    /// 1. 扫描出所有满足条件的行，之后的更新只针对这些行，避免再次访问刚写入的行；
+    /// 2. 基于旧行计算赋值表达式得到新行，并检查新行是否符合表定义，值没有变化的行不会被更新和计数；
+    /// 3. 写入新行。主键不变的行原地更新；主键变化的行先全部删除旧键，再逐一插入新键并检查主键冲突。
+    ///    先删除再插入，保证了 `SET id = id + 1` 这类整体平移主键的更新不会和尚未移动的行冲突。
     fn update(
         &self,
         table_name: String,
         columns: HashMap<String, Expression>,
-        filter: Option<(String, Expression)>,
+        filter: Option<Expression>,
     ) -> Result<usize> {
         let table = self
             .transaction
             .get_table(&table_name)?
-            .ok_or(InternalError(format!("Table {table_name} not found")))?;
-        let (_, rows) = self.scan(&table_name, filter)?;
+            .ok_or(TableNotFound(table_name.to_string()))?;
 
-        let mut updated_count = 0;
+        // 解析赋值语句对应的列索引
+        let assignments = columns
+            .iter()
+            .map(|(col_name, expr)| {
+                table
+                    .get_col_idx(col_name)
+                    .map(|col_idx| (col_idx, expr))
+                    .ok_or(
+                        ColumnNotFound {
+                            table: table_name.to_string(),
+                            column: col_name.to_string(),
+                        }
+                        .into(),
+                    )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // 阶段 1：物化所有满足条件的行
+        let plan = Planner::new(&self.transaction).build_table_access(&table_name, filter)?;
+        let (column_names, rows) = self.execute_node(plan)?;
+        let rows = rows.collect::<Result<Vec<_>>>()?;
+
+        // 阶段 2：根据旧行计算新行
+        let mut changed_rows = Vec::new();
         for row in rows {
             let mut updated_row = row.clone();
-            let primary_key = table.get_primary_key(&row);
-
-            for (col_name, expr) in &columns {
-                let col_idx = table.get_col_idx(col_name).ok_or(InternalError(format!(
-                    "Column {} not found in table {}",
-                    col_name, table_name
-                )))?;
-                updated_row[col_idx] = Value::from(expr.clone());
+            for (col_idx, expr) in &assignments {
+                updated_row[*col_idx] = evaluate(expr, &column_names, &row)?
+                    .coerce_to(table.columns[*col_idx].data_type)?;
             }
+            if updated_row == row {
+                continue;
+            }
+            table.validate_row(&updated_row)?;
+            changed_rows.push((row, updated_row));
+        }
+
+        // 阶段 3：写入新行
+        let (moved_rows, in_place_rows): (Vec<_>, Vec<_>) =
+            changed_rows.iter().partition(|(row, updated_row)| {
+                table.get_primary_key(row) != table.get_primary_key(updated_row)
+            });
+        for (row, updated_row) in &in_place_rows {
+            self.transaction
+                .update_row(&table, table.get_primary_key(row), updated_row)?;
+        }
+        for (row, _) in &moved_rows {
             self.transaction
-                .update_row(&table, primary_key, &updated_row)?;
-            updated_count += 1;
+                .delete_row(&table, table.get_primary_key(row))?;
+        }
+        for (_, updated_row) in &moved_rows {
+            // create_row 会检查新主键是否已经存在
+            self.transaction.create_row(&table_name, updated_row)?;
         }
 
-        Ok(updated_count)
+        // 检查新行引用的父表行存在，以及移走的主键没有仍然引用它的行
+        let updated_rows = changed_rows
+            .iter()
+            .map(|(_, updated_row)| updated_row.clone())
+            .collect::<Vec<_>>();
+        self.check_references(&table, &updated_rows)?;
+        let moved_keys = moved_rows
+            .iter()
+            .map(|(row, _)| table.get_primary_key(row).clone())
+            .collect::<Vec<_>>();
+        self.check_referenced(&table, &moved_keys)?;
+
+        Ok(changed_rows.len())
     }
 
     /// 删除数据
-    fn delete(&self, table_name: String, filter: Option<(String, Expression)>) -> Result<usize> {
+    fn delete(&self, table_name: String, filter: Option<Expression>) -> Result<usize> {
         let table = self
             .transaction
             .get_table(&table_name)?
-            .ok_or(InternalError(format!("Table {table_name} not found")))?;
-        let (_, rows) = self.scan(&table_name, filter)?;
+            .ok_or(TableNotFound(table_name.to_string()))?;
+        let plan = Planner::new(&self.transaction).build_table_access(&table_name, filter)?;
+        // 先读取所有要删除的行，避免边扫描边删除
+        let (_, rows) = self.execute_node(plan)?;
+        let rows = rows.collect::<Result<Vec<_>>>()?;
 
-        let mut delete_count = 0;
-        for row in rows {
-            let primary_key = table.get_primary_key(&row);
-            self.transaction.delete_row(&table, primary_key)?;
-            delete_count += 1;
-        }
+        self.delete_rows(&table, &rows)?;
 
-        Ok(delete_count)
+        Ok(rows.len())
     }
 
-    /// 扫描 Join 表，返回所有的列名和行数据
-    fn scan_all_from_join(&self, from: &SelectFrom) -> Result<(Vec<String>, Vec<Row>)> {
-        match from {
-            SelectFrom::Table { name } => self.scan(name, None),
-            SelectFrom::Join {
+    /// 执行计划并输出带有执行统计的计划树
+    ///
+    /// 每个节点之后附加实际产生的行数和耗时，耗时包含子节点的耗时。
+    fn explain_analyze(&self, plan: Node) -> Result<String> {
+        let text = plan.to_string();
+        let profile = Profile::default();
+        let (_, rows) = self.execute_node_profiled(plan, Some(&profile))?;
+        rows.collect::<Result<Vec<_>>>()?;
+
+        // 计划树每行对应一个节点，和执行时记录统计的顺序相同，都是先序遍历
+        let stats = profile.borrow();
+        Ok(text
+            .lines()
+            .zip(stats.iter())
+            .map(|(line, stats)| {
+                format!(
+                    "{} [rows={}, time={:.3?}]\n",
+                    line, stats.rows, stats.elapsed
+                )
+            })
+            .collect())
+    }
+
+    /// 执行计划节点，返回所有的列名和行数据的迭代器，列名由 [`Node::columns`] 给出
+    ///
+    /// 扫描、过滤、投影、去重和 LIMIT 按需逐行产生结果，上层停止拉取时下层的扫描也随之停止；
+    /// 连接只读取右子节点的全部行，左子节点同样逐行读取；排序和聚集需要先读取子节点的全部行。
+    fn execute_node(&self, node: Node) -> Result<(Vec<String>, Rows<'_>)> {
+        self.execute_node_profiled(node, None)
+    }
+
+    /// 和 [`Executor::execute_node`] 相同，`profile` 不为空时按照先序遍历的顺序记录每个节点的执行统计
+    fn execute_node_profiled<'a>(
+        &'a self,
+        node: Node,
+        profile: Option<&Profile>,
+    ) -> Result<(Vec<String>, Rows<'a>)> {
+        let Some(profile) = profile else {
+            return self.execute_operator(node, None);
+        };
+        let id = {
+            let mut stats = profile.borrow_mut();
+            stats.push(NodeStats::default());
+            stats.len() - 1
+        };
+
+        // 构建迭代器时可能已经读取了子节点的全部行，这部分时间同样计入
+        let start = Instant::now();
+        let (columns, mut rows) = self.execute_operator(node, Some(profile))?;
+        profile.borrow_mut()[id].elapsed += start.elapsed();
+
+        let profile = profile.clone();
+        let rows = std::iter::from_fn(move || {
+            let start = Instant::now();
+            let row = rows.next();
+            let mut stats = profile.borrow_mut();
+            stats[id].elapsed += start.elapsed();
+            if let Some(Ok(_)) = row {
+                stats[id].rows += 1;
+            }
+            row
+        });
+        Ok((columns, Box::new(rows)))
+    }
+
+    /// 执行单个计划节点，子节点通过 [`Executor::execute_node_profiled`] 执行
+    ///
+    /// 设置了取消标记时，在构建节点之前以及需要读取子节点全部行的节点读取完之后检查是否取消，
+    /// 节点产生的行也定期检查。
+    fn execute_operator<'a>(
+        &'a self,
+        node: Node,
+        profile: Option<&Profile>,
+    ) -> Result<(Vec<String>, Rows<'a>)> {
+        self.check_cancelled()?;
+        let columns = node.columns();
+        let rows: Rows<'_> = match node {
+            Node::Scan {
+                table,
+                filter,
+                needed,
+                ..
+            } => Box::new(
+                self.transaction
+                    .scan_table_iter(&table, filter)
+                    .decode_only(needed),
+            ),
+            Node::KeyLookup { table, key } => {
+                Box::new(self.transaction.get_row(&table, &key)?.into_iter().map(Ok))
+            }
+            Node::KeyRangeScan { table, range, .. } => {
+                Box::new(self.transaction.scan_table_range_iter(&table, range))
+            }
+            Node::IndexScan {
+                table,
+                index,
+                prefix,
+                range,
+                ..
+            } => Box::new(
+                self.transaction
+                    .scan_index_iter(&table, &index, &prefix, range)?,
+            ),
+            Node::NestedLoopJoin {
                 left,
                 right,
                 join_type,
                 predicate,
             } => {
-                // 除了 Cross Join 外，其他 Join 类型必须有 Join 条件
-                if join_type != &JoinType::Cross && predicate.is_none() {
-                    return Err(InternalError(format!(
-                        "{} must have a predicate",
-                        join_type
-                    )));
-                }
-
-                let (mut left_columns, left_rows) = self.scan_all_from_join(left)?;
-                let (mut right_columns, right_rows) = self.scan_all_from_join(right)?;
-
-                // 对列名添加表名前缀，以便后续处理时能够识别
-                if let SelectFrom::Table { ref name } = **left {
-                    left_columns.iter_mut().for_each(|col| {
-                        *col = format!("{}.{}", name, col);
-                    });
-                }
-                if let SelectFrom::Table { ref name } = **right {
-                    right_columns.iter_mut().for_each(|col| {
-                        *col = format!("{}.{}", name, col);
-                    });
-                }
-
-                // 合并左右表
-                match join_type {
-                    JoinType::Cross => {
-                        loop_join(&left_columns, &right_columns, &left_rows, &right_rows)
+                let (left_columns, left_rows) = self.execute_node_profiled(*left, profile)?;
+                let (_, right_rows) = self.execute_node_profiled(*right, profile)?;
+                let right_rows = right_rows.collect::<Result<Vec<_>>>()?;
+                self.check_cancelled()?;
+                let right_width = columns.len() - left_columns.len();
+                nested_loop_join(
+                    columns.clone(),
+                    left_rows,
+                    right_rows,
+                    right_width,
+                    join_type,
+                    predicate,
+                )?
+            }
+            Node::HashJoin {
+                left,
+                right,
+                join_type,
+                left_key,
+                right_key,
+                predicate,
+            } => {
+                let (left_columns, left_rows) = self.execute_node_profiled(*left, profile)?;
+                let (_, right_rows) = self.execute_node_profiled(*right, profile)?;
+                let right_rows = right_rows.collect::<Result<Vec<_>>>()?;
+                self.check_cancelled()?;
+                hash_join(
+                    columns.clone(),
+                    left_rows,
+                    right_rows,
+                    (left_key, right_key),
+                    left_columns.len(),
+                    join_type,
+                    predicate,
+                )?
+            }
+            Node::Filter { source, predicate } => {
+                let (_, rows) = self.execute_node_profiled(*source, profile)?;
+                let columns = columns.clone();
+                Box::new(rows.filter_map(move |row| {
+                    let row = match row {
+                        Ok(row) => row,
+                        Err(e) => return Some(Err(e)),
+                    };
+                    match evaluate(&predicate, &columns, &row).and_then(predicate_passes) {
+                        Ok(true) => Some(Ok(row)),
+                        Ok(false) => None,
+                        Err(e) => Some(Err(e)),
                     }
-                    JoinType::Inner | JoinType::Left | JoinType::Right | JoinType::Full => {
-                        hash_join(
-                            &left_columns,
-                            &right_columns,
-                            &left_rows,
-                            &right_rows,
-                            join_type,
-                            predicate.as_ref().unwrap(),
-                        )
+                }))
+            }
+            Node::Order {
+                source,
+                ordering,
+                limit,
+            } => {
+                let (_, rows) = self.execute_node_profiled(*source, profile)?;
+                let rows = rows.collect::<Result<_>>()?;
+                self.check_cancelled()?;
+                let rows = sort(&columns, rows, &ordering, limit)?;
+                Box::new(rows.into_iter().map(Ok))
+            }
+            Node::Limit {
+                source,
+                offset,
+                limit,
+            } => {
+                let (_, rows) = self.execute_node_profiled(*source, profile)?;
+                // 错误不计入跳过的行数，保证错误能够传递给调用方
+                let mut skipped = 0;
+                let rows = rows.filter(move |row| {
+                    if row.is_ok() && skipped < offset {
+                        skipped += 1;
+                        return false;
                     }
+                    true
+                });
+                // 取满 limit 行后不再从子节点拉取
+                Box::new(rows.take(limit.unwrap_or(usize::MAX)))
+            }
+            Node::Empty { .. } => Box::new(std::iter::empty()),
+            Node::Distinct { source, sorted } => {
+                let (_, rows) = self.execute_node_profiled(*source, profile)?;
+                if sorted {
+                    // 输入已经按照所有列排序，重复的行一定相邻，只需要和上一行比较
+                    let mut last = None;
+                    Box::new(rows.filter(move |row| match row {
+                        Ok(row) if last.as_ref() == Some(row) => false,
+                        Ok(row) => {
+                            last = Some(row.clone());
+                            true
+                        }
+                        Err(_) => true,
+                    }))
+                } else {
+                    let mut seen = HashSet::new();
+                    Box::new(rows.filter(move |row| match row {
+                        Ok(row) => seen.insert(row.clone()),
+                        Err(_) => true,
+                    }))
                 }
             }
-        }
+            Node::Projection {
+                source,
+                columns: select_columns,
+            } => {
+                let types = column_types(&source);
+                let (source_columns, rows) = self.execute_node_profiled(*source, profile)?;
+                Self::select_field_columns(&select_columns, &source_columns, &types, rows)?
+            }
+            Node::Aggregate {
+                source,
+                group_by,
+                aggregates,
+            } => {
+                let (source_columns, rows) = self.execute_node_profiled(*source, profile)?;
+                let rows = hash_aggregate(&source_columns, rows, &group_by, &aggregates)?;
+                self.check_cancelled()?;
+                Box::new(rows.into_iter().map(Ok))
+            }
+            Node::SetOperation {
+                left,
+                right,
+                operator,
+                all,
+                types,
+            } => {
+                let (_, left_rows) = self.execute_node_profiled(*left, profile)?;
+                let (_, right_rows) = self.execute_node_profiled(*right, profile)?;
+                set_operation(left_rows, right_rows, operator, all, types)?
+            }
+        };
+        let rows = match &self.cancellation {
+            Some(token) => token.guard(rows),
+            None => rows,
+        };
+        Ok((columns, rows))
     }
 
-    /// 从 Join 表中扫描数据并过滤
-    fn scan_from_join(
-        &self,
-        from: &SelectFrom,
-        filter: Option<(String, Expression)>,
-    ) -> Result<(Vec<String>, Vec<Row>)> {
-        let (columns, mut rows) = self.scan_all_from_join(from)?;
-
-        // 列名称在 `scan_all_from_join` 中改为 table_name.col_name，利用这个特性进行过滤
-        if let Some((col_name, expr)) = filter {
-            let col_idx = Self::get_column_index_by_name(&columns, &col_name)?;
-            rows.retain(|row| row[col_idx] == Value::from(expr.clone()));
+    /// 设置了取消标记时检查语句是否已经被取消或者超时
+    fn check_cancelled(&self) -> Result<()> {
+        match &self.cancellation {
+            Some(token) => token.check(),
+            None => Ok(()),
         }
-
-        Ok((columns, rows))
     }
 
     /// 从 `table_name.column_name` 中提取 `column_name`
     fn extract_column_name(full_column_name: &str) -> &str {
         full_column_name
             .split('.')
-            .last()
+            .next_back()
             .unwrap_or(full_column_name)
     }
 
     /// 查询数据
+    #[allow(clippy::too_many_arguments)]
     fn select(
         &self,
         select_columns: Vec<(Expression, Option<String>)>,
+        distinct: bool,
         from: SelectFrom,
-        filter: Option<(String, Expression)>,
-        ordering: Vec<(String, Ordering)>,
+        filter: Option<Expression>,
+        group_by: Vec<Expression>,
+        having: Option<Expression>,
+        ordering: Vec<OrderBy>,
         limit: Option<Expression>,
         offset: Option<Expression>,
     ) -> Result<(Vec<String>, Vec<Row>)> {
-        let (columns, mut rows) = self.scan_from_join(&from, filter)?;
-        self.sort_rows(&mut rows, &columns, ordering)?;
-
-        // 处理 limit 和 offset
-        if !(offset.is_none() && limit.is_none()) {
-            let to_usize = |expr: Option<Expression>, default: usize, err_prefix: &str| {
-                expr.map_or(Ok(default), |e| match Value::from(e) {
-                    Value::Integer(v) if v >= 0 => Ok(v as usize),
-                    other => Err(InternalError(format!(
-                        "{} must be a non-negative integer, get {:?}",
-                        err_prefix, other
-                    ))),
-                })
-            };
-            let offset = to_usize(offset, 0, "Offset")?;
-            let limit = to_usize(limit, usize::MAX, "Limit")?;
-            rows = rows
-                .into_iter()
-                .skip(offset)
-                .take(limit)
-                .collect::<Vec<_>>();
+        let is_projected = !select_columns.is_empty();
+        let plan = Planner::new(&self.transaction).build_select(
+            select_columns,
+            distinct,
+            from,
+            filter,
+            group_by,
+            having,
+            ordering,
+            limit,
+            offset,
+        )?;
+        let (columns, rows) = self.execute_node(plan)?;
+        let rows = rows.collect::<Result<Vec<_>>>()?;
+
+        Ok((Self::output_columns(columns, is_projected), rows))
+    }
+
+    /// 执行集合运算语句，输出的列名和最左侧的 SELECT 相同
+    fn set_operation(&self, stmt: Statement) -> Result<(Vec<String>, Vec<Row>)> {
+        let mut leftmost = &stmt;
+        while let Statement::SetOperation { left, .. } = leftmost {
+            leftmost = left;
         }
+        let is_projected =
+            !matches!(leftmost, Statement::Select { columns, .. } if columns.is_empty());
 
-        // 处理不是 SELECT * 的情况
-        if !select_columns.is_empty() {
-            // column 可以全部是聚集函数，或者全部是列名，不允许出现混合的情况
-            if select_columns.iter().all(|(col, _)| col.is_function()) {
-                // 全部是聚集函数
-                let (new_columns, new_rows) =
-                    Self::select_aggregate_columns(&select_columns, &columns, &rows)?;
+        let plan = Planner::new(&self.transaction).build_query(stmt)?;
+        let (columns, rows) = self.execute_node(plan)?;
+        let rows = rows.collect::<Result<Vec<_>>>()?;
 
-                Ok((new_columns, new_rows))
-            } else if select_columns.iter().all(|(col, _)| col.is_field()) {
-                // 全是列名
-                let (new_columns, new_rows) =
-                    self.select_field_columns(&select_columns, &columns, rows)?;
+        Ok((Self::output_columns(columns, is_projected), rows))
+    }
 
-                Ok((new_columns, new_rows))
-            } else {
-                // 既有聚集函数又有列名，报错
-                Err(InternalError(
-                    "All columns must be either aggregate functions or column names".to_string(),
-                ))
-            }
+    /// 查询输出的列名，`is_projected` 为假时即 SELECT *，将列名从 table_name.col_name 改为 col_name
+    fn output_columns(columns: Vec<String>, is_projected: bool) -> Vec<String> {
+        if is_projected {
+            columns
         } else {
-            // 将列名从 table_name.col_name 改为 col_name
-            let columns = columns
+            columns
                 .into_iter()
                 .map(|full_name| Self::extract_column_name(&full_name).to_string())
-                .collect();
-
-            Ok((columns, rows))
+                .collect()
         }
     }
 
-    /// 选择列名
-    fn select_field_columns(
-        &self,
-        select_columns: &[(Expression, Option<String>)],
-        columns: &[String],
-        rows: Vec<Row>,
-    ) -> Result<(Vec<String>, Vec<Row>)> {
-        // 一次性收集新列名
-        let new_columns = select_columns
-            .iter()
-            .map(|(col_expr, alias)| match col_expr {
-                Expression::Field(col_name) => alias
-                    .clone()
-                    .unwrap_or_else(|| Self::extract_column_name(col_name).to_string()),
-                _ => unreachable!(),
-            })
-            .collect::<Vec<_>>();
+    /// 计划查询语句，返回的计划可以在之后的事务中通过 [`Executor::execute_plan`] 重复执行
+    ///
+    /// 语句必须是不包含子查询的查询语句：子查询在计划之前执行，它的结果随数据变化，计划不能被复用。
+    /// 语句中可以有参数占位符，`parameters` 为需要的参数个数。LIMIT 和 OFFSET 在计划时求值，
+    /// 其中有占位符时返回 [`UnboundParameter`](crate::error::ExecutionError::UnboundParameter)。
+    pub(crate) fn plan_query(&self, stmt: Statement, parameters: usize) -> Result<QueryPlan> {
+        if !matches!(
+            stmt,
+            Statement::Select { .. } | Statement::SetOperation { .. }
+        ) || stmt.has_subquery()
+        {
+            return Err(Internal(
+                "Only queries without subqueries can be planned for reuse".to_string(),
+            ));
+        }
 
-        // 收集需要选择的列索引
-        let col_indices = select_columns
-            .iter()
-            .map(|(col_expr, _)| match col_expr {
-                Expression::Field(col_name) => Self::get_column_index_by_name(columns, col_name),
-                _ => unreachable!(),
-            })
-            .collect::<Result<Vec<_>>>()?;
+        let stmt = self.materialize_subqueries(stmt)?;
+        let mut leftmost = &stmt;
+        while let Statement::SetOperation { left, .. } = leftmost {
+            leftmost = left;
+        }
+        let is_projected =
+            !matches!(leftmost, Statement::Select { columns, .. } if columns.is_empty());
 
-        // 选择需要的列
-        let rows = rows
-            .into_iter()
-            .map(|row| {
-                col_indices
-                    .iter()
-                    .map(|col_idx| row[*col_idx].clone())
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
-        Ok((new_columns, rows))
-    }
+        let mut tables = Vec::new();
+        Self::collect_query_tables(&stmt, &mut tables);
+        let root = Planner::new(&self.transaction).build_query(stmt)?;
 
-    /// 选择聚集函数的列
-    fn select_aggregate_columns(
-        select_columns: &[(Expression, Option<String>)],
-        columns: &[String],
-        rows: &[Row],
-    ) -> Result<(Vec<String>, Vec<Row>)> {
-        // 一次性收集新列名
-        let new_columns = select_columns
-            .iter()
-            .map(|(col, alias)| match col {
-                Expression::Function(agg, col_name) => Ok(alias
-                    .clone()
-                    .unwrap_or_else(|| format!("{}({})", agg, col_name))),
-                _ => unreachable!(),
-            })
-            .collect::<Result<Vec<String>>>()?;
+        Ok(QueryPlan {
+            root,
+            is_projected,
+            parameters,
+            tables,
+        })
+    }
 
-        // 计算聚集函数的值
-        let agg_values = select_columns
-            .iter()
-            .map(|(col, _)| match col {
-                Expression::Function(agg, col_name) => aggregate(col_name, columns, rows, *agg),
-                _ => unreachable!(),
-            })
-            .collect::<Result<Vec<Value>>>()?;
+    /// 绑定参数 `params` 并执行 [`Executor::plan_query`] 得到的计划，参数的个数必须和计划需要的相同
+    pub(crate) fn execute_plan(&self, plan: &QueryPlan, params: &[Value]) -> Result<ResultSet> {
+        if params.len() != plan.parameters {
+            return Err(ParameterCount {
+                expected: plan.parameters,
+                found: params.len(),
+            }
+            .into());
+        }
+        let root = match plan.parameters {
+            0 => plan.root.clone(),
+            _ => Planner::new(&self.transaction).bind_parameters(plan.root.clone(), params)?,
+        };
+        let (columns, rows) = self.execute_node(root)?;
+        let rows = rows.collect::<Result<Vec<_>>>()?;
 
-        Ok((new_columns, vec![agg_values]))
+        Ok(ResultSet::query(
+            Self::output_columns(columns, plan.is_projected),
+            rows,
+        ))
     }
 
-    /// 根据列名查找列索引
-    ///
-    /// columns 为 table_name.col_name 的形式，col_name 可能为 col_name 或 table_name.col_name
-    fn get_column_index_by_name(columns: &[String], col_name: &str) -> Result<usize> {
-        let parts = col_name.split('.').collect::<Vec<_>>();
-        match parts.len() {
-            1 => {
-                // 仅包含 col_name，则按照最后部分匹配
-                let matches = columns
-                    .iter()
-                    .enumerate()
-                    .filter(|(_, full_name)| full_name.split('.').last().unwrap() == parts[0])
-                    .collect::<Vec<_>>();
-                if matches.len() == 1 {
-                    Ok(matches[0].0)
-                } else if matches.is_empty() {
-                    Err(InternalError(format!(
-                        "Column {} not found in table",
-                        col_name
-                    )))
-                } else {
-                    Err(InternalError(format!(
-                        "Column {} is ambiguous in table",
-                        col_name
-                    )))
+    /// 收集查询语句读取的所有表
+    fn collect_query_tables(stmt: &Statement, tables: &mut Vec<String>) {
+        fn collect_from(from: &SelectFrom, tables: &mut Vec<String>) {
+            match from {
+                SelectFrom::Table { name } => tables.push(name.clone()),
+                SelectFrom::Join { left, right, .. } => {
+                    collect_from(left, tables);
+                    collect_from(right, tables);
                 }
             }
-            2 => {
-                // 包含 table_name.col_name，则直接查找
-                columns
-                    .iter()
-                    .position(|full_name| full_name == col_name)
-                    .ok_or(InternalError(format!(
-                        "Column {} not found in table",
-                        col_name
-                    )))
+        }
+
+        match stmt {
+            Statement::Select { from, .. } => collect_from(from, tables),
+            Statement::SetOperation { left, right, .. } => {
+                Self::collect_query_tables(left, tables);
+                Self::collect_query_tables(right, tables);
             }
-            _ => panic!(), // 不可能出现其他情况
+            _ => {}
         }
     }
 
-    /// 对行进行排序
-    fn sort_rows(
-        &self,
-        rows: &mut [Row],
+    /// 选择列，选择列可以是任意表达式，其中的聚集函数已经由聚集节点计算
+    fn select_field_columns<'a>(
+        select_columns: &[(Expression, Option<String>)],
         columns: &[String],
-        ordering: Vec<(String, Ordering)>,
-    ) -> Result<()> {
-        // columns 改为了 table_name.col_name 的形式，这里需要处理
-        let ordering = ordering
-            .into_iter()
-            .map(|(col_name, ord)| {
-                Self::get_column_index_by_name(columns, &col_name).map(|col_idx| (col_idx, ord))
-            })
-            .collect::<Result<Vec<_>>>()?;
-
-        rows.sort_by(|lhs, rhs| {
-            for (col_idx, order) in ordering.iter() {
-                match lhs[*col_idx].partial_cmp(&rhs[*col_idx]) {
-                    Some(ord) if ord != std::cmp::Ordering::Equal => {
-                        return if *order == Ordering::Asc {
-                            ord
-                        } else {
-                            ord.reverse()
-                        };
-                    }
-                    _ => continue,
-                }
+        types: &[Option<DataType>],
+        rows: Rows<'a>,
+    ) -> Result<Rows<'a>> {
+        // 提前检查引用的列是否存在，即使没有行也能发现错误
+        for (expr, _) in select_columns {
+            let mut fields = Vec::new();
+            expr.collect_fields(&mut fields);
+            for field in fields {
+                get_column_index_by_name(columns, field)?;
             }
-            std::cmp::Ordering::Equal
-        });
+            let mut functions = Vec::new();
+            expr.collect_functions(&mut functions);
+            for function in functions {
+                get_aggregate_index(columns, function)?;
+            }
+        }
 
-        Ok(())
+        // 计算每一行的选择列，计划时推断为浮点数的列中的整数转为浮点数，
+        // 例如 `COALESCE(i, f)` 在 `i` 不为 NULL 的行中的值，保证同一列中的值类型相同
+        let exprs = select_columns
+            .iter()
+            .map(|(expr, _)| {
+                let data_type = infer_type(expr, columns, types).ok().flatten();
+                (expr.clone(), data_type)
+            })
+            .collect::<Vec<_>>();
+        let columns = columns.to_vec();
+        let rows = rows.map(move |row| {
+            let row = row?;
+            exprs
+                .iter()
+                .map(|(expr, data_type)| {
+                    Ok(match (evaluate(expr, &columns, &row)?, data_type) {
+                        (Value::Integer(i), Some(DataType::Float)) => Value::Float(i as f64),
+                        (value, _) => value,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        });
+        Ok(Box::new(rows))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        ops::Bound::Unbounded,
+        sync::{
+            atomic::{AtomicUsize, Ordering as AtomicOrdering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
     use super::*;
     use crate::{
+        engine::{IndexScan, RowScan},
         error::Result,
-        parser::ast::{Aggregate, Constant, Operation},
-        schema::{Column, DataType},
+        parser::{
+            ast::{Aggregate, Constant, JoinType, Operation, Ordering},
+            Parser,
+        },
+        schema::Column,
         storage::MemoryStorage,
     };
 
@@ -563,6 +966,7 @@ mod tests {
                     nullable: false,
                     default: None,
                     primary_key: true,
+                    auto_increment: false,
                 },
                 Column {
                     name: "name".to_string(),
@@ -570,8 +974,10 @@ mod tests {
                     nullable: true,
                     default: Some(Value::String("Momo".to_string())),
                     primary_key: false,
+                    auto_increment: false,
                 },
             ],
+            foreign_keys: vec![],
         })?;
 
         // 创建 grades 表
@@ -584,6 +990,7 @@ mod tests {
                     nullable: false,
                     default: None,
                     primary_key: true,
+                    auto_increment: false,
                 },
                 Column {
                     name: "grade".to_string(),
@@ -591,8 +998,10 @@ mod tests {
                     nullable: true,
                     default: Some(Value::Integer(0)),
                     primary_key: false,
+                    auto_increment: false,
                 },
             ],
+            foreign_keys: vec![],
         })?;
 
         Ok(())
@@ -603,7 +1012,7 @@ mod tests {
         executor.execute(Statement::Insert {
             table_name: "users".to_string(),
             columns: None,
-            values: vec![
+            source: InsertSource::Values(vec![
                 vec![
                     Expression::Constant(Constant::Integer(1)),
                     Expression::Constant(Constant::String("Alice".to_string())),
@@ -612,14 +1021,14 @@ mod tests {
                     Expression::Constant(Constant::Integer(2)),
                     Expression::Constant(Constant::Null),
                 ],
-            ],
+            ]),
         })?;
 
         // 插入数据到 grades 表
         executor.execute(Statement::Insert {
             table_name: "grades".to_string(),
             columns: None,
-            values: vec![
+            source: InsertSource::Values(vec![
                 vec![
                     Expression::Constant(Constant::String("Alice".to_string())),
                     Expression::Constant(Constant::Integer(90)),
@@ -628,7 +1037,7 @@ mod tests {
                     Expression::Constant(Constant::String("Bob".to_string())),
                     Expression::Constant(Constant::Integer(80)),
                 ],
-            ],
+            ]),
         })?;
 
         Ok(())
@@ -647,7 +1056,9 @@ mod tests {
             .execute(Statement::Insert {
                 table_name: "users".to_string(),
                 columns: Some(vec!["id".to_string(), "name".to_string()]),
-                values: vec![vec![Expression::Constant(Constant::Integer(4))]],
+                source: InsertSource::Values(vec![vec![Expression::Constant(Constant::Integer(
+                    4
+                ))]]),
             })
             .is_err());
 
@@ -656,9 +1067,9 @@ mod tests {
             .execute(Statement::Insert {
                 table_name: "users".to_string(),
                 columns: Some(vec!["name".to_string()]),
-                values: vec![vec![Expression::Constant(Constant::String(
+                source: InsertSource::Values(vec![vec![Expression::Constant(Constant::String(
                     "Bob".to_string()
-                ))]],
+                ))]]),
             })
             .is_err());
 
@@ -667,10 +1078,10 @@ mod tests {
             .execute(Statement::Insert {
                 table_name: "users".to_string(),
                 columns: None,
-                values: vec![vec![
+                source: InsertSource::Values(vec![vec![
                     Expression::Constant(Constant::Integer(1)),
                     Expression::Constant(Constant::String("Bob".to_string())),
-                ]],
+                ]]),
             })
             .is_err());
 
@@ -679,10 +1090,10 @@ mod tests {
             .execute(Statement::Insert {
                 table_name: "nonexistent".to_string(),
                 columns: None,
-                values: vec![vec![
+                source: InsertSource::Values(vec![vec![
                     Expression::Constant(Constant::Integer(1)),
                     Expression::Constant(Constant::String("Bob".to_string())),
-                ]],
+                ]]),
             })
             .is_err());
 
@@ -691,13 +1102,113 @@ mod tests {
             .execute(Statement::Insert {
                 table_name: "users".to_string(),
                 columns: None,
-                values: vec![vec![
+                source: InsertSource::Values(vec![vec![
                     Expression::Constant(Constant::String("Alice".to_string())),
                     Expression::Constant(Constant::String("Bob".to_string())),
-                ]],
+                ]]),
             })
             .is_err());
 
+        // 多行插入在写入之前检查所有的行，后面的行不合法时前面的行也不会写入
+        let parse = |sql: &str| Parser::new(sql).parse();
+        for sql in [
+            "INSERT INTO users VALUES (10, 'a'), (11, 11);",
+            "INSERT INTO users VALUES (10, 'a'), (11, 'b'), (10, 'c');",
+        ] {
+            assert!(executor.execute(parse(sql)?).is_err(), "{sql}");
+            let result = executor.execute(parse("SELECT * FROM users WHERE id >= 10;")?)?;
+            assert!(result.rows().is_empty(), "{sql}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_select() -> Result<()> {
+        let engine = Engine::new(MemoryStorage::new());
+        // 记录查询计算了多少行
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        engine.functions().register(
+            "tick",
+            crate::Signature::new(vec![DataType::Integer], DataType::Integer, false),
+            move |args| {
+                counter.fetch_add(1, AtomicOrdering::SeqCst);
+                Ok(args[0].clone())
+            },
+        )?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let ids = |executor: &Executor<MemoryStorage>, sql: &str| -> Result<Vec<Value>> {
+            let result = executor.execute(parse(sql)?)?;
+            Ok(result.into_iter().map(|row| row[0].clone()).collect())
+        };
+
+        let mut executor = Executor::from_engine(&engine)?;
+        let values = (0..1000)
+            .map(|i| format!("({i}, 'n{i}')"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        for sql in [
+            "CREATE TABLE src (id INT PRIMARY KEY, name STRING);".to_string(),
+            "CREATE TABLE dst (id INT PRIMARY KEY, name STRING, note STRING DEFAULT 'copied');"
+                .to_string(),
+            "CREATE UNIQUE INDEX idx_name ON dst (name);".to_string(),
+            format!("INSERT INTO src VALUES {values};"),
+            "INSERT INTO dst VALUES (5000, 'n600', 'original');".to_string(),
+        ] {
+            executor.execute(parse(&sql)?)?;
+        }
+
+        // 按照列名插入查询的结果，未指定的列使用默认值
+        assert_eq!(
+            executor.execute(parse(
+                "INSERT INTO dst (name, id) SELECT name, id FROM src WHERE id < 300;"
+            )?)?,
+            ResultSet::modified(300)
+        );
+        let result = executor.execute(parse("SELECT * FROM dst WHERE id = 7;")?)?;
+        assert_eq!(
+            result.rows(),
+            [vec![
+                Value::Integer(7),
+                Value::String("n7".to_string()),
+                Value::String("copied".to_string()),
+            ]]
+        );
+        assert!(executor
+            .execute(parse("INSERT INTO dst SELECT id FROM src;")?)
+            .is_err());
+
+        // 源表和目标表相同时只读取插入之前的行
+        assert_eq!(
+            executor.execute(parse("INSERT INTO src SELECT id + 1000, name FROM src;")?)?,
+            ResultSet::modified(1000)
+        );
+        assert_eq!(
+            ids(&executor, "SELECT COUNT(*) FROM src;")?,
+            [Value::Integer(2000)]
+        );
+        executor.commit()?;
+
+        // 逐行读取并写入，违反唯一约束时语句立即停止，之后的行不会被读取；回滚事务撤销已经插入的行
+        let mut executor = Executor::from_engine(&engine)?;
+        let result = executor.execute(parse(
+            "INSERT INTO dst (id, name) SELECT tick(id) + 1000, name FROM src WHERE id >= 300 AND id < 1000;",
+        )?);
+        assert!(matches!(
+            result,
+            Err(crate::Error::Execution(
+                crate::ExecutionError::UniqueViolation { .. }
+            ))
+        ));
+        assert_eq!(calls.load(AtomicOrdering::SeqCst), 301);
+        executor.rollback()?;
+        let executor = Executor::from_engine(&engine)?;
+        assert_eq!(
+            ids(&executor, "SELECT COUNT(*) FROM dst;")?,
+            [Value::Integer(301)]
+        );
+
         Ok(())
     }
 
@@ -710,12 +1221,15 @@ mod tests {
         // 测试 SELECT * FROM users
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["id", "name"]);
@@ -730,12 +1244,15 @@ mod tests {
         // 测试 SELECT name FROM users
         let (columns, rows) = executor.select(
             vec![(Expression::Field("name".to_string()), None)],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["name"]);
@@ -747,10 +1264,16 @@ mod tests {
         // 测试 SELECT * FROM users WHERE id = 1
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
-            Some(("id".to_string(), Expression::Constant(Constant::Integer(1)))),
+            Some(Expression::Operation(Operation::Equal(
+                Box::new(Expression::Field("id".to_string())),
+                Box::new(Expression::Constant(Constant::Integer(1))),
+            ))),
+            vec![],
+            None,
             vec![],
             None,
             None,
@@ -764,10 +1287,15 @@ mod tests {
         // 测试 SELECT * FROM users WHERE name IS NULL
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
-            Some(("name".to_string(), Expression::Constant(Constant::Null))),
+            Some(Expression::Operation(Operation::IsNull(Box::new(
+                Expression::Field("name".to_string()),
+            )))),
+            vec![],
+            None,
             vec![],
             None,
             None,
@@ -778,11 +1306,14 @@ mod tests {
         // 测试 SELECT * FROM users ORDER BY name DESC
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
-            vec![("name".to_string(), Ordering::Desc)],
+            vec![],
+            None,
+            vec![(Expression::Field("name".to_string()), Ordering::Desc, None)],
             None,
             None,
         )?;
@@ -798,11 +1329,14 @@ mod tests {
         // 测试 SELECT * FROM users ORDER BY name ASC
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
-            vec![("name".to_string(), Ordering::Asc)],
+            vec![],
+            None,
+            vec![(Expression::Field("name".to_string()), Ordering::Asc, None)],
             None,
             None,
         )?;
@@ -818,11 +1352,14 @@ mod tests {
         // 测试 SELECT * FROM users LIMIT 1
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
+            None,
+            vec![],
             Some(Expression::Constant(Constant::Integer(1))),
             None,
         )?;
@@ -835,11 +1372,14 @@ mod tests {
         // 测试 SELECT * FROM users LIMIT 1 OFFSET 1
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
+            None,
+            vec![],
             Some(Expression::Constant(Constant::Integer(1))),
             Some(Expression::Constant(Constant::Integer(1))),
         )?;
@@ -864,17 +1404,26 @@ mod tests {
             )]
             .into_iter()
             .collect(),
-            filter: Some(("id".to_string(), Expression::Constant(Constant::Integer(1)))),
+            filter: Some(Expression::Operation(Operation::Equal(
+                Box::new(Expression::Field("id".to_string())),
+                Box::new(Expression::Constant(Constant::Integer(1))),
+            ))),
         })?;
-        assert_eq!(result, ExecuteResult::Update(1));
+        assert_eq!(result, ResultSet::modified(1));
 
         // 测试更新数据后的查询
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
-            Some(("id".to_string(), Expression::Constant(Constant::Integer(1)))),
+            Some(Expression::Operation(Operation::Equal(
+                Box::new(Expression::Field("id".to_string())),
+                Box::new(Expression::Constant(Constant::Integer(1))),
+            ))),
+            vec![],
+            None,
             vec![],
             None,
             None,
@@ -888,6 +1437,50 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_update_primary_key() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+
+        executor.execute(parse(
+            "CREATE TABLE nums (id INT PRIMARY KEY, val INT NULL);",
+        )?)?;
+        for i in 1..=10 {
+            executor.execute(parse(&format!(
+                "INSERT INTO nums VALUES ({i}, {});",
+                i * 10
+            ))?)?;
+        }
+
+        // 测试主键整体平移，不应和尚未移动的行冲突
+        let result = executor.execute(parse("UPDATE nums SET id = id + 1;")?)?;
+        assert_eq!(result, ResultSet::modified(10));
+        let ResultSet::Query { rows, .. } = executor.execute(parse("SELECT * FROM nums;")?)? else {
+            unreachable!()
+        };
+        assert_eq!(
+            rows,
+            (1..=10)
+                .map(|i| vec![Value::Integer(i + 1), Value::Integer(i * 10)])
+                .collect::<Vec<_>>()
+        );
+
+        // 测试值没有变化的行不计入更新数量
+        let result = executor.execute(parse("UPDATE nums SET val = val;")?)?;
+        assert_eq!(result, ResultSet::modified(0));
+        let result = executor.execute(parse("UPDATE nums SET val = 100 WHERE id = 11;")?)?;
+        assert_eq!(result, ResultSet::modified(0));
+        let result = executor.execute(parse("UPDATE nums SET val = val * 2 WHERE id = 11;")?)?;
+        assert_eq!(result, ResultSet::modified(1));
+
+        // 测试多行更新到同一个主键，应当返回主键冲突
+        assert!(executor
+            .execute(parse("UPDATE nums SET id = 100;")?)
+            .is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_delete() -> Result<()> {
         let executor = init_executor()?;
@@ -897,17 +1490,26 @@ mod tests {
         // 测试删除数据
         let result = executor.execute(Statement::Delete {
             table_name: "users".to_string(),
-            filter: Some(("id".to_string(), Expression::Constant(Constant::Integer(1)))),
+            filter: Some(Expression::Operation(Operation::Equal(
+                Box::new(Expression::Field("id".to_string())),
+                Box::new(Expression::Constant(Constant::Integer(1))),
+            ))),
         })?;
-        assert_eq!(result, ExecuteResult::Delete(1));
+        assert_eq!(result, ResultSet::modified(1));
 
         // 测试删除数据后的查询
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
-            Some(("id".to_string(), Expression::Constant(Constant::Integer(1)))),
+            Some(Expression::Operation(Operation::Equal(
+                Box::new(Expression::Field("id".to_string())),
+                Box::new(Expression::Constant(Constant::Integer(1))),
+            ))),
+            vec![],
+            None,
             vec![],
             None,
             None,
@@ -918,6 +1520,80 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_auto_increment_and_drop_table() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: &str| -> Result<Vec<Row>> {
+            Ok(executor.execute(parse(sql)?)?.into_iter().collect())
+        };
+        let (int, string) = (Value::Integer, |s: &str| Value::String(s.to_string()));
+
+        for sql in [
+            "CREATE TABLE parent (id INT PRIMARY KEY AUTO_INCREMENT, name VARCHAR);",
+            "CREATE TABLE child (id INT PRIMARY KEY, parent_id INT REFERENCES parent);",
+            "CREATE INDEX idx_name ON parent (name);",
+            "INSERT INTO parent (name) VALUES ('a'), ('b');",
+            "INSERT INTO parent VALUES (NULL, 'c'), (10, 'd');",
+            "INSERT INTO parent (name) VALUES ('e');",
+        ] {
+            executor.execute(parse(sql)?)?;
+        }
+        // 省略或者为 NULL 的自增列从计数器中分配，同一个事务中跳过显式写入的 ID
+        assert_eq!(
+            query("SELECT id, name FROM parent ORDER BY id;")?,
+            vec![
+                vec![int(1), string("a")],
+                vec![int(2), string("b")],
+                vec![int(3), string("c")],
+                vec![int(10), string("d")],
+                vec![int(11), string("e")],
+            ]
+        );
+
+        // 只有整数主键可以自增
+        for sql in [
+            "CREATE TABLE t (id INT PRIMARY KEY, v INT AUTO_INCREMENT);",
+            "CREATE TABLE t (id STRING PRIMARY KEY AUTO_INCREMENT);",
+        ] {
+            let e = executor.execute(parse(sql)?).unwrap_err();
+            assert_eq!(e.code(), crate::ErrorCode::InvalidDefinition);
+        }
+
+        // 被外键引用的表不能删除
+        let e = executor.execute(parse("DROP TABLE parent;")?).unwrap_err();
+        assert_eq!(e.code(), crate::ErrorCode::InvalidDefinition);
+        assert_eq!(
+            executor.execute(parse("DROP TABLE child;")?)?,
+            ResultSet::DropTable {
+                name: "child".to_string()
+            }
+        );
+        executor.execute(parse("DROP TABLE parent;")?)?;
+        assert_eq!(
+            executor
+                .execute(parse("DROP TABLE parent;")?)
+                .unwrap_err()
+                .code(),
+            crate::ErrorCode::UndefinedTable
+        );
+
+        // 重新创建的同名表中没有旧的行和索引项
+        for sql in [
+            "CREATE TABLE parent (id INT PRIMARY KEY AUTO_INCREMENT, name VARCHAR);",
+            "CREATE INDEX idx_name ON parent (name);",
+            "INSERT INTO parent VALUES (1, 'a');",
+        ] {
+            executor.execute(parse(sql)?)?;
+        }
+        assert_eq!(
+            query("SELECT id FROM parent WHERE name = 'a';")?,
+            vec![vec![int(1)]]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_cross_join() -> Result<()> {
         let executor = init_executor()?;
@@ -927,6 +1603,7 @@ mod tests {
         // 测试 CROSS JOIN
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Join {
                 left: Box::new(SelectFrom::Table {
                     name: "users".to_string(),
@@ -940,6 +1617,8 @@ mod tests {
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["id", "name", "name", "grade"]);
@@ -981,6 +1660,7 @@ mod tests {
         assert!(executor
             .select(
                 vec![],
+                false,
                 SelectFrom::Join {
                     left: Box::new(SelectFrom::Table {
                         name: "users".to_string(),
@@ -991,10 +1671,12 @@ mod tests {
                     join_type: JoinType::Cross,
                     predicate: None,
                 },
-                Some((
-                    "name".to_string(),
-                    Expression::Constant(Constant::String("Alice".to_string()))
-                )),
+                Some(Expression::Operation(Operation::Equal(
+                    Box::new(Expression::Field("name".to_string())),
+                    Box::new(Expression::Constant(Constant::String("Alice".to_string()))),
+                ))),
+                vec![],
+                None,
                 vec![],
                 None,
                 None,
@@ -1005,6 +1687,7 @@ mod tests {
         assert!(executor
             .select(
                 vec![],
+                false,
                 SelectFrom::Join {
                     left: Box::new(SelectFrom::Table {
                         name: "users".to_string(),
@@ -1016,7 +1699,9 @@ mod tests {
                     predicate: None,
                 },
                 None,
-                vec![("name".to_string(), Ordering::Asc)],
+                vec![],
+                None,
+                vec![(Expression::Field("name".to_string()), Ordering::Asc, None)],
                 None,
                 None,
             )
@@ -1025,6 +1710,7 @@ mod tests {
         // 测试 CROSS JOIN 对有指定表名的列名进行过滤和排序
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Join {
                 left: Box::new(SelectFrom::Table {
                     name: "users".to_string(),
@@ -1035,11 +1721,17 @@ mod tests {
                 join_type: JoinType::Cross,
                 predicate: None,
             },
-            Some((
-                "users.name".to_string(),
-                Expression::Constant(Constant::String("Alice".to_string())),
-            )),
-            vec![(String::from("grades.name"), Ordering::Asc)],
+            Some(Expression::Operation(Operation::Equal(
+                Box::new(Expression::Field("users.name".to_string())),
+                Box::new(Expression::Constant(Constant::String("Alice".to_string()))),
+            ))),
+            vec![],
+            None,
+            vec![(
+                Expression::Field("grades.name".to_string()),
+                Ordering::Asc,
+                None,
+            )],
             None,
             None,
         )?;
@@ -1074,6 +1766,7 @@ mod tests {
         // 测试 INNER JOIN
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Join {
                 left: Box::new(SelectFrom::Table {
                     name: "users".to_string(),
@@ -1090,6 +1783,8 @@ mod tests {
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["id", "name", "name", "grade"]);
@@ -1115,6 +1810,7 @@ mod tests {
         // 测试 LEFT JOIN
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Join {
                 left: Box::new(SelectFrom::Table {
                     name: "users".to_string(),
@@ -1129,7 +1825,13 @@ mod tests {
                 ))),
             },
             None,
-            vec![("grades.name".to_string(), Ordering::Asc)],
+            vec![],
+            None,
+            vec![(
+                Expression::Field("grades.name".to_string()),
+                Ordering::Asc,
+                None,
+            )],
             None,
             None,
         )?;
@@ -1159,6 +1861,7 @@ mod tests {
         // 测试 RIGHT JOIN
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Join {
                 left: Box::new(SelectFrom::Table {
                     name: "users".to_string(),
@@ -1173,7 +1876,13 @@ mod tests {
                 ))),
             },
             None,
-            vec![("grades.name".to_string(), Ordering::Asc)],
+            vec![],
+            None,
+            vec![(
+                Expression::Field("grades.name".to_string()),
+                Ordering::Asc,
+                None,
+            )],
             None,
             None,
         )?;
@@ -1208,6 +1917,7 @@ mod tests {
         // 测试 FULL JOIN
         let (columns, rows) = executor.select(
             vec![],
+            false,
             SelectFrom::Join {
                 left: Box::new(SelectFrom::Table {
                     name: "users".to_string(),
@@ -1222,7 +1932,13 @@ mod tests {
                 ))),
             },
             None,
-            vec![("grades.name".to_string(), Ordering::Asc)],
+            vec![],
+            None,
+            vec![(
+                Expression::Field("grades.name".to_string()),
+                Ordering::Asc,
+                None,
+            )],
             None,
             None,
         )?;
@@ -1258,15 +1974,18 @@ mod tests {
         // 测试 COUNT(*)
         let (columns, rows) = executor.select(
             vec![(
-                Expression::Function(Aggregate::Count, "*".to_string()),
+                Expression::Function(Aggregate::Count, "*".to_string(), false),
                 None,
             )],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["COUNT(*)"]);
@@ -1275,15 +1994,18 @@ mod tests {
         // 测试 COUNT(name)
         let (columns, rows) = executor.select(
             vec![(
-                Expression::Function(Aggregate::Count, "name".to_string()),
+                Expression::Function(Aggregate::Count, "name".to_string(), false),
                 None,
             )],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["COUNT(name)"]);
@@ -1292,15 +2014,18 @@ mod tests {
         // 测试 COUNT(DISTINCT name)
         let (columns, rows) = executor.select(
             vec![(
-                Expression::Function(Aggregate::Count, "name".to_string()),
+                Expression::Function(Aggregate::Count, "name".to_string(), true),
                 Some("count".to_string()),
             )],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["count"]);
@@ -1308,13 +2033,19 @@ mod tests {
 
         // 测试 SUM(id)
         let (columns, rows) = executor.select(
-            vec![(Expression::Function(Aggregate::Sum, "id".to_string()), None)],
+            vec![(
+                Expression::Function(Aggregate::Sum, "id".to_string(), false),
+                None,
+            )],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["SUM(id)"]);
@@ -1322,13 +2053,19 @@ mod tests {
 
         // 测试 AVG(id)
         let (columns, rows) = executor.select(
-            vec![(Expression::Function(Aggregate::Avg, "id".to_string()), None)],
+            vec![(
+                Expression::Function(Aggregate::Avg, "id".to_string(), false),
+                None,
+            )],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["AVG(id)"]);
@@ -1336,13 +2073,19 @@ mod tests {
 
         // 测试 MAX(id)
         let (columns, rows) = executor.select(
-            vec![(Expression::Function(Aggregate::Max, "id".to_string()), None)],
+            vec![(
+                Expression::Function(Aggregate::Max, "id".to_string(), false),
+                None,
+            )],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["MAX(id)"]);
@@ -1350,13 +2093,19 @@ mod tests {
 
         // 测试 MIN(id)
         let (columns, rows) = executor.select(
-            vec![(Expression::Function(Aggregate::Min, "id".to_string()), None)],
+            vec![(
+                Expression::Function(Aggregate::Min, "id".to_string(), false),
+                None,
+            )],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["MIN(id)"]);
@@ -1365,15 +2114,24 @@ mod tests {
         // 测试 MIN(id), MAX(id)
         let (columns, rows) = executor.select(
             vec![
-                (Expression::Function(Aggregate::Min, "id".to_string()), None),
-                (Expression::Function(Aggregate::Max, "id".to_string()), None),
+                (
+                    Expression::Function(Aggregate::Min, "id".to_string(), false),
+                    None,
+                ),
+                (
+                    Expression::Function(Aggregate::Max, "id".to_string(), false),
+                    None,
+                ),
             ],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["MIN(id)", "MAX(id)"]);
@@ -1383,15 +2141,18 @@ mod tests {
         // 测试 MIN(id)
         let (columns, rows) = executor.select(
             vec![(
-                Expression::Function(Aggregate::Min, "id".to_string()),
+                Expression::Function(Aggregate::Min, "id".to_string(), false),
                 Some("min_id".to_string()),
             )],
+            false,
             SelectFrom::Table {
                 name: "users".to_string(),
             },
             None,
             vec![],
             None,
+            vec![],
+            None,
             None,
         )?;
         assert_eq!(columns, vec!["min_id"]);
@@ -1399,4 +2160,1970 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_primary_key_access_path() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+
+        executor.execute(parse(
+            "CREATE TABLE nums (id INT PRIMARY KEY, val INT NULL);",
+        )?)?;
+        // 词法分析不支持负数常量，使用减法构造负数
+        let literal = |v: i64| match v {
+            v if v < 0 => format!("0 - {}", -v),
+            v => v.to_string(),
+        };
+        for i in -5..=10 {
+            executor.execute(parse(&format!(
+                "INSERT INTO nums VALUES ({}, {});",
+                literal(i),
+                literal(i * 10)
+            ))?)?;
+        }
+        executor.execute(parse(
+            "CREATE TABLE words (word TEXT PRIMARY KEY, len INT);",
+        )?)?;
+        for word in ["", "a", "ab", "abc", "b", "ba", "c"] {
+            executor.execute(parse(&format!(
+                "INSERT INTO words VALUES ('{word}', {});",
+                word.len()
+            ))?)?;
+        }
+
+        let query = |sql: String| -> Result<Vec<Row>> {
+            match executor.execute(parse(&sql)?)? {
+                ResultSet::Query { rows, .. } => Ok(rows),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+
+        // 主键访问路径的结果必须和全表扫描的结果一致，`NOT NOT` 会让计划器无法识别主键条件
+        let cases = [
+            ("nums", "id = 3"),
+            ("nums", "id = 100"),
+            ("nums", "3 = id"),
+            ("nums", "id > 3"),
+            ("nums", "id >= 3"),
+            ("nums", "id < 3"),
+            ("nums", "id <= 0"),
+            ("nums", "3 < id"),
+            ("nums", "3 >= id"),
+            ("nums", "id > 10"),
+            ("nums", "id >= 10"),
+            ("nums", "id BETWEEN 2 AND 5"),
+            ("nums", "id BETWEEN 5 AND 2"),
+            ("nums", "id > 3 AND id < 4"),
+            ("nums", "id >= 3 AND id <= 3"),
+            ("nums", "id > 3 AND id >= 3 AND id < 8 AND id <= 7"),
+            ("nums", "id = 3 AND id = 4"),
+            ("nums", "id > 1 AND val > 40"),
+            ("nums", "id = 2 AND val IS NULL"),
+            ("nums", "nums.id < 2 AND id > 0 - 3"),
+            ("nums", "id > 2.5"),
+            ("nums", "id > NULL"),
+            ("nums", "id > 3 OR id < 0"),
+            ("words", "word = 'ab'"),
+            ("words", "word > 'a'"),
+            ("words", "word >= 'a' AND word < 'b'"),
+            ("words", "word BETWEEN '' AND 'ab'"),
+            ("words", "word <= 'b' AND len = 1"),
+        ];
+        for (table, predicate) in cases {
+            let rows = query(format!("SELECT * FROM {table} WHERE {predicate};"))?;
+            let expected = query(format!(
+                "SELECT * FROM {table} WHERE NOT NOT ({predicate});"
+            ))?;
+            assert_eq!(rows, expected, "{predicate}");
+        }
+
+        // 测试 EXPLAIN 显示选择的访问路径
+        let explain = |sql: &str| -> Result<String> {
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Explain(plan) => Ok(plan),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        assert_eq!(
+            explain("EXPLAIN SELECT * FROM nums WHERE id = 3;")?,
+            "KeyLookup: nums (id = 3)\n"
+        );
+        assert_eq!(
+            explain("EXPLAIN SELECT val FROM nums WHERE id BETWEEN 2 AND 5 AND val > 20;")?,
+            "Projection: val\n  Filter: val > 20\n    KeyRangeScan: nums (id >= 2 AND id <= 5)\n"
+        );
+        assert_eq!(
+            explain("EXPLAIN SELECT * FROM words WHERE word > 'a' ORDER BY len DESC LIMIT 2;")?,
+            "Limit: 2 (offset 0)\n  Order: len DESC (top 2)\n    KeyRangeScan: words (word > 'a')\n"
+        );
+        assert_eq!(
+            explain("EXPLAIN SELECT * FROM nums WHERE val = 30;")?,
+            "Scan: nums (val = 30)\n"
+        );
+
+        // 测试 UPDATE 和 DELETE 同样使用主键访问路径
+        let result = executor.execute(parse("UPDATE nums SET val = 0 WHERE id >= 9;")?)?;
+        assert_eq!(result, ResultSet::modified(2));
+        let result = executor.execute(parse("DELETE FROM nums WHERE id < 0;")?)?;
+        assert_eq!(result, ResultSet::modified(5));
+        assert_eq!(query("SELECT * FROM nums;".to_string())?.len(), 11);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_scan() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: String| -> Result<Vec<Row>> {
+            match executor.execute(parse(&sql)?)? {
+                ResultSet::Query { rows, .. } => Ok(rows),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        let explain = |sql: &str| -> Result<String> {
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Explain(plan) => Ok(plan),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+
+        executor.execute(parse(
+            "CREATE TABLE people (id INT PRIMARY KEY, email TEXT NULL, city TEXT NULL, age INT NULL);",
+        )?)?;
+
+        // 使用线性同余生成器构造可复现的随机数据，部分列为 NULL
+        let mut seed = 42u64;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) % n
+        };
+        let cities = ["'a'", "'b'", "'c'", "NULL"];
+        let mut insert = |id: u64| -> Result<()> {
+            let email = match next(5) {
+                0 => "NULL".to_string(),
+                _ => format!("'user{id}@x.com'"),
+            };
+            let city = cities[next(4) as usize];
+            let age = match next(6) {
+                0 => "NULL".to_string(),
+                _ => next(10).to_string(),
+            };
+            executor.execute(parse(&format!(
+                "INSERT INTO people VALUES ({id}, {email}, {city}, {age});"
+            ))?)?;
+            Ok(())
+        };
+        // 一部分数据在创建索引前插入，另一部分在创建索引后插入
+        for id in 0..100 {
+            insert(id)?;
+        }
+        executor.execute(parse("CREATE UNIQUE INDEX idx_email ON people (email);")?)?;
+        executor.execute(parse("CREATE INDEX idx_city_age ON people (city, age);")?)?;
+        executor.execute(parse("CREATE INDEX idx_age ON people (age);")?)?;
+        for id in 100..200 {
+            insert(id)?;
+        }
+
+        // 索引访问路径的结果必须和全表扫描的结果一致
+        let cases = [
+            "email = 'user7@x.com'",
+            "email = 'nobody@x.com'",
+            "email > 'user5' AND email < 'user6'",
+            "city = 'a'",
+            "city = 'a' AND age = 3",
+            "city = 'b' AND age > 4",
+            "city = 'c' AND age <= 2 AND id > 50",
+            "age = 5",
+            "age < 3",
+            "age >= 8",
+            "age BETWEEN 2 AND 4",
+            "age > 4 AND age < 4",
+            "city = NULL",
+            "age IS NULL",
+        ];
+        for predicate in cases {
+            let rows = query(format!(
+                "SELECT * FROM people WHERE {predicate} ORDER BY id;"
+            ))?;
+            let expected = query(format!(
+                "SELECT * FROM people WHERE NOT NOT ({predicate}) ORDER BY id;"
+            ))?;
+            assert_eq!(rows, expected, "{predicate}");
+        }
+
+        // 测试 EXPLAIN 显示使用的索引
+        assert_eq!(
+            explain("EXPLAIN SELECT * FROM people WHERE email = 'user7@x.com' AND age = 1;")?,
+            "Filter: age = 1\n  IndexScan: people USING idx_email (email = 'user7@x.com')\n"
+        );
+        assert_eq!(
+            explain("EXPLAIN SELECT * FROM people WHERE age > 3 AND city = 'a';")?,
+            "IndexScan: people USING idx_city_age (city = 'a' AND age > 3)\n"
+        );
+        assert_eq!(
+            explain("EXPLAIN SELECT * FROM people WHERE age = 3;")?,
+            "IndexScan: people USING idx_age (age = 3)\n"
+        );
+        assert_eq!(
+            explain("EXPLAIN SELECT * FROM people WHERE id = 3 AND email = 'user3@x.com';")?,
+            "Filter: email = 'user3@x.com'\n  KeyLookup: people (id = 3)\n"
+        );
+        assert_eq!(
+            explain("EXPLAIN SELECT * FROM people WHERE id > 3 AND city = 'a';")?,
+            "Filter: id > 3\n  IndexScan: people USING idx_city_age (city = 'a')\n"
+        );
+
+        // 测试 NULL 值在唯一索引中不冲突，重复的非 NULL 值冲突
+        executor.execute(parse(
+            "INSERT INTO people VALUES (1000, NULL, NULL, NULL);",
+        )?)?;
+        executor.execute(parse(
+            "INSERT INTO people VALUES (1001, NULL, NULL, NULL);",
+        )?)?;
+        executor.execute(parse(
+            "INSERT INTO people VALUES (1002, 'dup@x.com', NULL, NULL);",
+        )?)?;
+        assert!(executor
+            .execute(parse(
+                "INSERT INTO people VALUES (1003, 'dup@x.com', NULL, NULL);"
+            )?)
+            .is_err());
+        assert!(executor
+            .execute(parse(
+                "UPDATE people SET email = 'dup@x.com' WHERE id = 1000;"
+            )?)
+            .is_err());
+
+        // 测试同一事务中更新和删除的行在索引中同步变化
+        executor.execute(parse(
+            "UPDATE people SET email = 'new@x.com' WHERE id = 1002;",
+        )?)?;
+        assert!(query("SELECT * FROM people WHERE email = 'dup@x.com';".to_string())?.is_empty());
+        assert_eq!(
+            query("SELECT id FROM people WHERE email = 'new@x.com';".to_string())?,
+            vec![vec![Value::Integer(1002)]]
+        );
+        executor.execute(parse("DELETE FROM people WHERE email = 'new@x.com';")?)?;
+        assert!(query("SELECT * FROM people WHERE email = 'new@x.com';".to_string())?.is_empty());
+        executor.execute(parse(
+            "UPDATE people SET id = id + 10000 WHERE city = 'a';",
+        )?)?;
+        let rows = query("SELECT * FROM people WHERE city = 'a' ORDER BY id;".to_string())?;
+        assert!(!rows.is_empty());
+        assert!(rows.iter().all(|row| row[0] >= Value::Integer(10000)));
+        assert_eq!(
+            rows,
+            query("SELECT * FROM people WHERE NOT NOT (city = 'a') ORDER BY id;".to_string())?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_loop_join() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: &str| -> Result<Vec<Row>> {
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Query { rows, .. } => Ok(rows),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        let int_pairs = |pairs: &[(Option<i64>, Option<i64>)]| -> Vec<Row> {
+            let value = |v: Option<i64>| v.map(Value::Integer).unwrap_or(Value::Null);
+            pairs
+                .iter()
+                .map(|(l, r)| vec![value(*l), value(*r)])
+                .collect()
+        };
+
+        // 两侧都有重复的连接键，并且都有 NULL 连接键
+        executor.execute(parse("CREATE TABLE l (id INT PRIMARY KEY, k INT NULL);")?)?;
+        executor.execute(parse(
+            "CREATE TABLE r (id INT PRIMARY KEY, k INT NULL, v INT);",
+        )?)?;
+        for (id, k) in [(1, "1"), (2, "1"), (3, "2"), (4, "NULL"), (5, "9")] {
+            executor.execute(parse(&format!("INSERT INTO l VALUES ({id}, {k});"))?)?;
+        }
+        for (id, k) in [(1, "1"), (2, "1"), (3, "NULL"), (4, "2"), (5, "7")] {
+            executor.execute(parse(&format!(
+                "INSERT INTO r VALUES ({id}, {k}, {});",
+                id * 10
+            ))?)?;
+        }
+
+        // INNER JOIN：重复键两两匹配，NULL 不和任何值匹配
+        let rows = query("SELECT l.id, r.id FROM l JOIN r ON l.k = r.k ORDER BY l.id, r.id;")?;
+        assert_eq!(
+            rows,
+            int_pairs(&[
+                (Some(1), Some(1)),
+                (Some(1), Some(2)),
+                (Some(2), Some(1)),
+                (Some(2), Some(2)),
+                (Some(3), Some(4)),
+            ])
+        );
+
+        // 条件两侧的顺序不影响结果
+        let rows = query("SELECT l.id, r.id FROM l JOIN r ON r.k = l.k ORDER BY l.id, r.id;")?;
+        assert_eq!(rows.len(), 5);
+
+        // LEFT JOIN：没有匹配的左表行（包括 NULL 键）以 NULL 填充右表
+        let rows = query("SELECT l.id, r.id FROM l LEFT JOIN r ON l.k = r.k ORDER BY l.id, r.id;")?;
+        assert_eq!(
+            rows,
+            int_pairs(&[
+                (Some(1), Some(1)),
+                (Some(1), Some(2)),
+                (Some(2), Some(1)),
+                (Some(2), Some(2)),
+                (Some(3), Some(4)),
+                (Some(4), None),
+                (Some(5), None),
+            ])
+        );
+
+        // 右表为空时，LEFT JOIN 按右表的列数填充 NULL
+        executor.execute(parse("CREATE TABLE e (id INT PRIMARY KEY, k INT);")?)?;
+        let rows = query("SELECT * FROM l LEFT JOIN e ON l.k = e.k ORDER BY l.id;")?;
+        assert_eq!(rows.len(), 5);
+        assert!(rows
+            .iter()
+            .all(|row| row.len() == 4 && row[2..] == [Value::Null, Value::Null]));
+
+        // 任意条件：在左右表拼接后的列上计算
+        let rows =
+            query("SELECT l.id, r.id FROM l JOIN r ON l.k < r.k AND v > 30 ORDER BY l.id, r.id;")?;
+        assert_eq!(
+            rows,
+            int_pairs(&[
+                (Some(1), Some(4)),
+                (Some(1), Some(5)),
+                (Some(2), Some(4)),
+                (Some(2), Some(5)),
+                (Some(3), Some(5)),
+            ])
+        );
+
+        // CROSS JOIN 是没有条件的嵌套循环连接
+        let rows = query("SELECT * FROM l CROSS JOIN r;")?;
+        assert_eq!(rows.len(), 25);
+
+        // RIGHT JOIN 使用哈希连接，NULL 同样不匹配
+        let rows =
+            query("SELECT l.id, r.id FROM l RIGHT JOIN r ON l.k = r.k ORDER BY r.id, l.id;")?;
+        assert_eq!(
+            rows,
+            int_pairs(&[
+                (Some(1), Some(1)),
+                (Some(2), Some(1)),
+                (Some(1), Some(2)),
+                (Some(2), Some(2)),
+                (None, Some(3)),
+                (Some(3), Some(4)),
+                (None, Some(5)),
+            ])
+        );
+
+        // 条件中的列必须在拼接后的列中唯一解析，在生成计划时检查
+        assert!(query("SELECT * FROM l JOIN r ON id = 1;").is_err());
+        assert!(query("SELECT * FROM l JOIN r ON l.x = r.k;").is_err());
+        assert!(query("SELECT * FROM l RIGHT JOIN r ON l.k < r.k;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_join() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: String| -> Result<Vec<Row>> {
+            match executor.execute(parse(&sql)?)? {
+                ResultSet::Query { mut rows, .. } => {
+                    rows.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+                    Ok(rows)
+                }
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        let explain = |sql: String| -> Result<String> {
+            match executor.execute(parse(&sql)?)? {
+                ResultSet::Explain(plan) => Ok(plan),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+
+        // 使用线性同余生成器构造可复现的随机数据，连接键取值范围较小，包含重复值和 NULL
+        let mut seed = 7u64;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) % n
+        };
+        for (table, rows) in [("a", 30), ("b", 50)] {
+            executor.execute(parse(&format!(
+                "CREATE TABLE {table} (id INT PRIMARY KEY, k INT NULL, v INT);"
+            ))?)?;
+            for id in 0..rows {
+                let k = match next(8) {
+                    0 => "NULL".to_string(),
+                    k => k.to_string(),
+                };
+                let v = next(100);
+                executor.execute(parse(&format!(
+                    "INSERT INTO {table} VALUES ({id}, {k}, {v});"
+                ))?)?;
+            }
+        }
+
+        // 哈希连接的结果必须和嵌套循环连接一致，`NOT NOT` 会让计划器无法识别等值条件
+        let select = "SELECT a.id, a.k, b.id, b.k FROM";
+        let cases = [
+            ("a", "b", "a.k = b.k", "NOT NOT (a.k = b.k)"),
+            ("b", "a", "a.k = b.k", "NOT NOT (a.k = b.k)"),
+            ("a", "b", "b.k = a.k", "NOT NOT (a.k = b.k)"),
+            (
+                "a",
+                "b",
+                "a.k = b.k AND a.v < b.v",
+                "NOT NOT (a.k = b.k) AND a.v < b.v",
+            ),
+            (
+                "b",
+                "a",
+                "a.v > 50 AND a.k = b.k",
+                "a.v > 50 AND NOT NOT (a.k = b.k)",
+            ),
+        ];
+        for (left, right, equi, nested) in cases {
+            for join in ["JOIN", "LEFT JOIN"] {
+                let sql = format!("{select} {left} {join} {right} ON {equi};");
+                assert!(
+                    explain(format!("EXPLAIN {sql}"))?.contains("HashJoin"),
+                    "{sql}"
+                );
+                let expected = query(format!("{select} {left} {join} {right} ON {nested};"))?;
+                assert!(explain(format!(
+                    "EXPLAIN {select} {left} {join} {right} ON {nested};"
+                ))?
+                .contains("NestedLoopJoin"));
+                assert_eq!(query(sql.clone())?, expected, "{sql}");
+            }
+
+            // RIGHT JOIN 等价于交换左右表的 LEFT JOIN
+            let right_join = query(format!("{select} {left} RIGHT JOIN {right} ON {equi};"))?;
+            let flipped = query(format!("{select} {right} LEFT JOIN {left} ON {nested};"))?;
+            assert_eq!(right_join, flipped, "{left} RIGHT JOIN {right} ON {equi}");
+
+            // FULL JOIN 等价于 LEFT JOIN 加上右表中没有匹配的行
+            let full_join = query(format!("{select} {left} FULL JOIN {right} ON {equi};"))?;
+            let unmatched_col = if left == "a" { 0 } else { 2 };
+            let mut expected = query(format!("{select} {left} LEFT JOIN {right} ON {nested};"))?;
+            expected.extend(
+                flipped
+                    .into_iter()
+                    .filter(|row| row[unmatched_col] == Value::Null),
+            );
+            expected.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+            assert_eq!(full_join, expected, "{left} FULL JOIN {right} ON {equi}");
+        }
+
+        // NULL 连接键不匹配任何行
+        let rows = query(format!("{select} a JOIN b ON a.k = b.k;"))?;
+        assert!(!rows.is_empty());
+        assert!(rows
+            .iter()
+            .all(|row| row[1] != Value::Null && row[1] == row[3]));
+
+        // 没有等值条件的 RIGHT 和 FULL JOIN 无法执行
+        assert!(query(format!("{select} a RIGHT JOIN b ON a.k < b.k;")).is_err());
+        assert!(query(format!("{select} a FULL JOIN b ON NOT NOT (a.k = b.k);")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_index() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let index_ids = || -> Result<Vec<Value>> {
+            let table = executor.transaction.get_table("users")?.unwrap();
+            let index = table.get_index("idx_active").unwrap();
+            Ok(executor
+                .transaction
+                .scan_index(&table, index, &[], (Unbounded, Unbounded))?
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect())
+        };
+
+        for sql in [
+            "CREATE TABLE users (id INT PRIMARY KEY, email TEXT, status TEXT);",
+            "INSERT INTO users VALUES (1, 'a', 'active'), (2, 'a', 'banned'), (3, 'b', 'active');",
+            "CREATE UNIQUE INDEX idx_active ON users (email) WHERE status = 'active';",
+        ] {
+            executor.execute(parse(sql)?)?;
+        }
+        // 条件随表定义一起保存，读取时重新解析
+        let table = executor.transaction.get_table("users")?.unwrap();
+        assert_eq!(
+            table.get_index("idx_active").unwrap().predicate,
+            Some(Parser::parse_standalone_expression("status = 'active'")?)
+        );
+
+        // 只有满足条件的行在索引中，唯一约束只在这些行之间检查
+        assert_eq!(index_ids()?, vec![Value::Integer(1), Value::Integer(3)]);
+        executor.execute(parse("INSERT INTO users VALUES (4, 'b', 'banned');")?)?;
+        assert!(executor
+            .execute(parse("INSERT INTO users VALUES (5, 'b', 'active');")?)
+            .is_err());
+
+        // 更新后不再满足条件的行从索引中删除，重新满足条件时写回
+        executor.execute(parse("UPDATE users SET status = 'banned' WHERE id = 1;")?)?;
+        assert_eq!(index_ids()?, vec![Value::Integer(3)]);
+        executor.execute(parse("UPDATE users SET status = 'active' WHERE id = 2;")?)?;
+        assert_eq!(index_ids()?, vec![Value::Integer(2), Value::Integer(3)]);
+
+        // 查询不使用部分索引，不满足条件的行仍然可以查到
+        let sql = "SELECT id FROM users WHERE email = 'a';";
+        match executor.execute(parse(&format!("EXPLAIN {sql}"))?)? {
+            ResultSet::Explain(plan) => assert!(!plan.contains("Index"), "{plan}"),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(
+            executor
+                .execute(parse(sql)?)?
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]
+        );
+
+        // 条件只能引用表中的列
+        assert!(executor
+            .execute(parse(
+                "CREATE INDEX idx_x ON users (email) WHERE missing = 1;"
+            )?)
+            .is_err());
+        assert!(executor
+            .execute(parse(
+                "CREATE INDEX idx_x ON users (email) WHERE id IN (SELECT id FROM users);"
+            )?)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query_ids = |sql: &str| -> Result<Vec<Value>> {
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Query { rows, .. } => {
+                    Ok(rows.into_iter().map(|row| row[0].clone()).collect())
+                }
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        let ids = |ids: &[i64]| ids.iter().map(|id| Value::Integer(*id)).collect::<Vec<_>>();
+
+        executor.execute(parse("CREATE TABLE t (id INT PRIMARY KEY, val INT NULL);")?)?;
+        executor.execute(parse(
+            "INSERT INTO t VALUES (1, 20), (2, NULL), (3, 10), (4, 20), (5, NULL), (6, 30);",
+        )?)?;
+
+        assert_eq!(
+            query_ids("SELECT * FROM t ORDER BY val;")?,
+            ids(&[2, 5, 3, 1, 4, 6])
+        );
+        assert_eq!(
+            query_ids("SELECT * FROM t ORDER BY val DESC NULLS FIRST, id DESC;")?,
+            ids(&[5, 2, 6, 4, 1, 3])
+        );
+        assert_eq!(
+            query_ids("SELECT * FROM t ORDER BY val NULLS LAST, 0 - id;")?,
+            ids(&[3, 4, 1, 6, 5, 2])
+        );
+
+        // 排序之上有 LIMIT 时使用有界堆，只保留前 offset + limit 行
+        let sql = "SELECT id FROM t ORDER BY val * 2 DESC LIMIT 3 OFFSET 1;";
+        assert_eq!(query_ids(sql)?, ids(&[1, 4, 3]));
+        match executor.execute(parse(&format!("EXPLAIN {sql}"))?)? {
+            ResultSet::Explain(plan) => assert_eq!(
+                plan,
+                "Projection: id\n  Limit: 3 (offset 1)\n    Order: val * 2 DESC (top 4)\n      Scan: t\n"
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by_resolution() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query_ids = |sql: &str| -> Result<Vec<i64>> {
+            executor
+                .execute(parse(sql)?)?
+                .into_iter()
+                .map(|row| row[0].as_i64())
+                .collect()
+        };
+
+        executor.execute(parse(
+            "CREATE TABLE items (id INT PRIMARY KEY, price INT, qty INT);",
+        )?)?;
+        executor.execute(parse(
+            "INSERT INTO items VALUES (1, 10, 1), (2, 3, 5), (3, 7, 2), (4, 1, 20);",
+        )?)?;
+
+        // 表达式可以引用不在选择列中的列
+        assert_eq!(
+            query_ids("SELECT id FROM items ORDER BY price * qty DESC, id;")?,
+            vec![4, 2, 3, 1]
+        );
+        // 别名替换为对应的表达式
+        assert_eq!(
+            query_ids("SELECT id, price * qty AS total FROM items ORDER BY total;")?,
+            vec![1, 3, 2, 4]
+        );
+        assert_eq!(
+            query_ids("SELECT id, qty AS amount FROM items ORDER BY amount DESC;")?,
+            vec![4, 2, 3, 1]
+        );
+        // 序号从 1 开始，SELECT * 时为表的列
+        assert_eq!(
+            query_ids("SELECT id, price FROM items ORDER BY 2;")?,
+            vec![4, 2, 3, 1]
+        );
+        assert_eq!(
+            query_ids("SELECT * FROM items ORDER BY 3 DESC;")?,
+            vec![4, 2, 3, 1]
+        );
+        assert_eq!(
+            query_ids("SELECT qty, COUNT(*) AS c FROM items GROUP BY qty ORDER BY c, 1 DESC;")?,
+            vec![20, 5, 2, 1]
+        );
+
+        // 序号超出范围在计划时报错，别名和列同名且指向不同的值时有歧义
+        for sql in [
+            "SELECT id FROM items ORDER BY 0;",
+            "SELECT id FROM items ORDER BY 2;",
+            "SELECT * FROM items ORDER BY 4;",
+            "SELECT id, price AS qty FROM items ORDER BY qty;",
+            "SELECT id AS x, price AS x FROM items ORDER BY x;",
+        ] {
+            assert!(
+                executor.execute(parse(&format!("EXPLAIN {sql}"))?).is_err(),
+                "{sql}"
+            );
+        }
+        assert_eq!(
+            query_ids("SELECT id, qty AS qty FROM items ORDER BY qty;")?,
+            vec![1, 3, 2, 4]
+        );
+
+        // DISTINCT 的排序项必须是选择列，别名和序号解析为选择列后满足要求
+        assert_eq!(
+            query_ids("SELECT DISTINCT qty * 0 + id AS n FROM items ORDER BY n DESC;")?,
+            vec![4, 3, 2, 1]
+        );
+        assert_eq!(
+            query_ids("SELECT DISTINCT id, qty FROM items ORDER BY 2;")?,
+            vec![1, 3, 2, 4]
+        );
+        assert!(executor
+            .execute(parse(
+                "SELECT DISTINCT id FROM items ORDER BY price * qty;"
+            )?)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: &str| -> Result<(Vec<String>, Vec<Row>)> {
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Query { columns, rows } => Ok((
+                    columns.into_iter().map(|column| column.name).collect(),
+                    rows,
+                )),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        let int = Value::Integer;
+        let string = |s: &str| Value::String(s.to_string());
+
+        executor.execute(parse(
+            "CREATE TABLE emp (id INT PRIMARY KEY, dept STRING NULL, salary INT NULL);",
+        )?)?;
+        executor.execute(parse(
+            "INSERT INTO emp VALUES
+                (1, 'a', 100), (2, 'b', 200), (3, 'a', 300), (4, NULL, 50),
+                (5, 'b', NULL), (6, 'c', NULL), (7, NULL, 70), (8, 'a', 100);",
+        )?)?;
+
+        // 分组列和聚集函数混合，NULL 是一个单独的分组
+        let (columns, rows) = query(
+            "SELECT dept, COUNT(*), COUNT(salary) AS cnt, SUM(DISTINCT salary), AVG(salary), MAX(salary)
+             FROM emp GROUP BY dept ORDER BY dept;",
+        )?;
+        assert_eq!(
+            columns,
+            vec![
+                "dept",
+                "COUNT(*)",
+                "cnt",
+                "SUM(DISTINCT salary)",
+                "AVG(salary)",
+                "MAX(salary)"
+            ]
+        );
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    Value::Null,
+                    int(2),
+                    int(2),
+                    int(120),
+                    Value::Float(60.0),
+                    int(70)
+                ],
+                vec![
+                    string("a"),
+                    int(3),
+                    int(3),
+                    int(400),
+                    Value::Float(500.0 / 3.0),
+                    int(300)
+                ],
+                vec![
+                    string("b"),
+                    int(2),
+                    int(1),
+                    int(200),
+                    Value::Float(200.0),
+                    int(200)
+                ],
+                vec![
+                    string("c"),
+                    int(1),
+                    int(0),
+                    Value::Null,
+                    Value::Null,
+                    Value::Null
+                ],
+            ]
+        );
+
+        // HAVING 和 ORDER BY 可以使用不在选择列中的聚集函数
+        let (_, rows) = query(
+            "SELECT dept FROM emp WHERE id > 1 GROUP BY dept HAVING COUNT(*) >= 2 AND MIN(id) > 1
+             ORDER BY SUM(salary) DESC;",
+        )?;
+        assert_eq!(
+            rows,
+            vec![vec![string("a")], vec![string("b")], vec![Value::Null]]
+        );
+
+        // 分组之后再 LIMIT
+        let (_, rows) = query(
+            "SELECT dept, COUNT(id) FROM emp GROUP BY dept ORDER BY COUNT(id) DESC, dept LIMIT 2;",
+        )?;
+        assert_eq!(
+            rows,
+            vec![vec![string("a"), int(3)], vec![Value::Null, int(2)]]
+        );
+
+        // 没有 GROUP BY 时即使没有行也输出一行
+        let (_, rows) =
+            query("SELECT COUNT(*), SUM(salary), AVG(salary) FROM emp WHERE id > 100;")?;
+        assert_eq!(rows, vec![vec![int(0), Value::Null, Value::Null]]);
+        let (_, rows) = query("SELECT dept, COUNT(*) FROM emp WHERE id > 100 GROUP BY dept;")?;
+        assert!(rows.is_empty());
+
+        // 按照表达式分组
+        let (_, rows) = query("SELECT COUNT(*) FROM emp GROUP BY id / 4 ORDER BY COUNT(*);")?;
+        assert_eq!(rows, vec![vec![int(1)], vec![int(3)], vec![int(4)]]);
+
+        // 执行计划中聚集在排序之前
+        match executor.execute(parse(
+            "EXPLAIN SELECT dept, COUNT(DISTINCT salary) FROM emp GROUP BY dept HAVING MAX(id) > 1 ORDER BY dept;",
+        )?)? {
+            ResultSet::Explain(plan) => assert_eq!(
+                plan,
+                "Projection: dept, COUNT(DISTINCT salary)\n  Order: dept ASC\n    Filter: MAX(id) > 1\n      Aggregate: COUNT(DISTINCT salary), MAX(id) GROUP BY dept\n        Scan: emp\n"
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // 选择列、HAVING 和 ORDER BY 中的字段必须出现在 GROUP BY 中
+        for sql in [
+            "SELECT id, COUNT(*) FROM emp;",
+            "SELECT dept, salary FROM emp GROUP BY dept;",
+            "SELECT dept FROM emp GROUP BY dept HAVING salary > 1;",
+            "SELECT dept FROM emp GROUP BY dept ORDER BY id;",
+            "SELECT * FROM emp GROUP BY dept;",
+            "SELECT COUNT(*) FROM emp GROUP BY COUNT(*);",
+            "SELECT SUM(name) FROM emp;",
+        ] {
+            assert!(executor.execute(parse(sql)?).is_err(), "{}", sql);
+        }
+
+        // 整数求和溢出时返回错误
+        executor.execute(parse(&format!(
+            "INSERT INTO emp VALUES (9, 'd', {}), (10, 'd', 1);",
+            i64::MAX
+        ))?)?;
+        assert!(query("SELECT SUM(salary) FROM emp GROUP BY dept;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_having() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: &str| -> Result<Vec<Row>> {
+            Ok(executor.execute(parse(sql)?)?.into_iter().collect())
+        };
+        let string = |s: &str| Value::String(s.to_string());
+        let int = Value::Integer;
+
+        executor.execute(parse(
+            "CREATE TABLE emp (id INT PRIMARY KEY, dept STRING, salary INT);",
+        )?)?;
+        executor.execute(parse(
+            "INSERT INTO emp VALUES (1, 'a', 10), (2, 'a', 20), (3, 'b', 30), (4, 'c', 5), (5, 'c', 5), (6, 'c', 50);",
+        )?)?;
+
+        // 只在 HAVING 中出现的聚集函数同样按分组计算
+        assert_eq!(
+            query("SELECT dept FROM emp GROUP BY dept HAVING COUNT(*) > 1 AND MAX(salary) < 30 ORDER BY dept;")?,
+            vec![vec![string("a")]]
+        );
+
+        // HAVING 可以引用选择列的别名，别名和列同名时优先作为列
+        assert_eq!(
+            query(
+                "SELECT dept, COUNT(*) AS n FROM emp GROUP BY dept HAVING n >= 2 ORDER BY n DESC;"
+            )?,
+            vec![vec![string("c"), int(3)], vec![string("a"), int(2)]]
+        );
+        assert_eq!(
+            query("SELECT dept, SUM(salary) / COUNT(*) AS average FROM emp GROUP BY dept HAVING average > 15 ORDER BY dept;")?,
+            vec![vec![string("b"), int(30)], vec![string("c"), int(20)]]
+        );
+        assert_eq!(
+            query("SELECT dept AS salary FROM emp GROUP BY dept, salary HAVING salary = 5;")?,
+            vec![vec![string("c")]]
+        );
+
+        // 引用没有分组的列在计划时报错，错误信息中包含列名
+        match executor.execute(parse(
+            "EXPLAIN SELECT dept FROM emp GROUP BY dept HAVING salary > 10;",
+        )?) {
+            Err(crate::Error::Plan(crate::PlanError::Invalid(message))) => {
+                assert!(message.contains("salary"), "{message}")
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(executor
+            .execute(parse(
+                "SELECT dept AS x, COUNT(*) AS x FROM emp GROUP BY dept HAVING x > 1;"
+            )?)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: &str| -> Result<Vec<Row>> {
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Query { rows, .. } => Ok(rows),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+
+        executor.execute(parse(
+            "CREATE TABLE docs (id INT PRIMARY KEY, doc JSON NULL);",
+        )?)?;
+        executor.execute(parse(
+            r#"INSERT INTO docs VALUES
+                (1, '{"name": "Alice", "address": {"city": "Paris", "zip": 75001}, "tags": ["a", "b"]}'),
+                (2, '[1, 2.5, true, null]'),
+                (3, NULL);"#,
+        )?)?;
+
+        // 插入的字符串被解析为 JSON 值，读出后与原值相同
+        let rows = query("SELECT * FROM docs;")?;
+        let doc = &rows[0][1];
+        assert_eq!(
+            doc,
+            &Value::Json(serde_json::json!({
+                "name": "Alice",
+                "address": {"city": "Paris", "zip": 75001},
+                "tags": ["a", "b"],
+            }))
+        );
+        assert_eq!(rows[2][1], Value::Null);
+
+        // 按路径提取嵌套的字段
+        assert_eq!(
+            doc.json_get("address.city")?,
+            Value::String("Paris".to_string())
+        );
+        assert_eq!(doc.json_get("address.zip")?, Value::Integer(75001));
+        assert_eq!(doc.json_get("tags.1")?, Value::String("b".to_string()));
+        assert_eq!(
+            doc.json_get("address")?,
+            Value::Json(serde_json::json!({"city": "Paris", "zip": 75001}))
+        );
+        assert_eq!(doc.json_get("address.country")?, Value::Null);
+        assert_eq!(doc.json_get("tags.5")?, Value::Null);
+        assert_eq!(doc.json_get("")?, *doc);
+        let array = &rows[1][1];
+        assert_eq!(array.json_get("1")?, Value::Float(2.5));
+        assert_eq!(array.json_get("2")?, Value::Boolean(true));
+        assert_eq!(array.json_get("3")?, Value::Null);
+        assert!(Value::Integer(1).json_get("a").is_err());
+
+        // 更新时同样会解析字符串，非法的 JSON 文本返回错误
+        executor.execute(parse(
+            r#"UPDATE docs SET doc = '{"b": 2, "a": 1}' WHERE id = 3;"#,
+        )?)?;
+        assert_eq!(
+            query("SELECT doc FROM docs WHERE id = 3;")?,
+            vec![vec![Value::Json(serde_json::json!({"a": 1, "b": 2}))]]
+        );
+        assert!(executor
+            .execute(parse("INSERT INTO docs VALUES (4, '{invalid');")?)
+            .is_err());
+        assert!(executor
+            .execute(parse("INSERT INTO docs VALUES (4, 1);")?)
+            .is_err());
+
+        // 按照规范化的序列化结果排序，对象的键按字典序排列
+        let rows = query("SELECT id FROM docs WHERE doc IS NOT NULL ORDER BY doc;")?;
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Integer(2)],
+                vec![Value::Integer(3)],
+                vec![Value::Integer(1)]
+            ]
+        );
+
+        Ok(())
+    }
+
+    /// 统计从存储引擎中读取的记录数的存储引擎，用于验证扫描的提前终止
+    struct CountingStorage {
+        inner: MemoryStorage,
+        reads: Arc<AtomicUsize>,
+    }
+
+    struct CountingIterator<'a> {
+        inner: <MemoryStorage as Storage>::Iterator<'a>,
+        reads: &'a AtomicUsize,
+    }
+
+    impl Iterator for CountingIterator<'_> {
+        type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let item = self.inner.next();
+            if item.is_some() {
+                self.reads.fetch_add(1, AtomicOrdering::SeqCst);
+            }
+            item
+        }
+    }
+
+    impl DoubleEndedIterator for CountingIterator<'_> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            let item = self.inner.next_back();
+            if item.is_some() {
+                self.reads.fetch_add(1, AtomicOrdering::SeqCst);
+            }
+            item
+        }
+    }
+
+    impl Storage for CountingStorage {
+        type Iterator<'a> = CountingIterator<'a>;
+
+        fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            self.inner.get(key)
+        }
+
+        fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+            self.inner.put(key, value)
+        }
+
+        fn delete(&mut self, key: &[u8]) -> Result<()> {
+            self.inner.delete(key)
+        }
+
+        fn scan<R>(&mut self, range: R) -> Self::Iterator<'_>
+        where
+            R: std::ops::RangeBounds<Vec<u8>>,
+        {
+            CountingIterator {
+                inner: self.inner.scan(range),
+                reads: &self.reads,
+            }
+        }
+    }
+
+    #[test]
+    fn test_limit_early_termination() -> Result<()> {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let engine = Engine::new(CountingStorage {
+            inner: MemoryStorage::new(),
+            reads: reads.clone(),
+        });
+        let executor = Executor::from_engine(&engine)?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        // 执行查询，返回第一列和执行期间从存储引擎中读取的记录数
+        let query = |sql: &str| -> Result<(Vec<Value>, usize)> {
+            reads.store(0, AtomicOrdering::SeqCst);
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Query { rows, .. } => Ok((
+                    rows.into_iter().map(|row| row[0].clone()).collect(),
+                    reads.load(AtomicOrdering::SeqCst),
+                )),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        let ids = |ids: std::ops::Range<i64>| ids.map(Value::Integer).collect::<Vec<_>>();
+
+        let count = 1000;
+        executor.execute(parse("CREATE TABLE t (id INT PRIMARY KEY, val INT);")?)?;
+        let values = (0..count)
+            .map(|id| format!("({}, {})", id, id * 2))
+            .collect::<Vec<_>>()
+            .join(", ");
+        executor.execute(parse(&format!("INSERT INTO t VALUES {values};"))?)?;
+
+        // 全表扫描读取所有的行
+        let (rows, full_reads) = query("SELECT * FROM t;")?;
+        assert_eq!(rows, ids(0..count));
+        assert!(full_reads >= count as usize);
+
+        // LIMIT 取满之后停止扫描，只读取了第一批行（另外还有读取表定义等少量读取）
+        let (rows, limit_reads) = query("SELECT id FROM t LIMIT 5 OFFSET 10;")?;
+        assert_eq!(rows, ids(10..15));
+        assert!(limit_reads < 2 * RowScan::<CountingStorage>::BATCH_SIZE);
+
+        // 过滤之后的 LIMIT 同样会提前停止
+        let (rows, filter_reads) = query("SELECT * FROM t WHERE val >= 1000 LIMIT 3;")?;
+        assert_eq!(rows, ids(500..503));
+        assert!(filter_reads < full_reads);
+
+        // 主键范围扫描
+        let (rows, range_reads) = query("SELECT * FROM t WHERE id > 900 LIMIT 2;")?;
+        assert_eq!(rows, ids(901..903));
+        assert!(range_reads < 2 * RowScan::<CountingStorage>::BATCH_SIZE);
+
+        // LIMIT 0 不会从扫描中拉取任何行，只有读取表定义等少量读取
+        let (rows, plan_reads) = query("SELECT * FROM t LIMIT 0;")?;
+        assert!(rows.is_empty());
+
+        // OFFSET 超过行数
+        let (rows, _) = query("SELECT * FROM t OFFSET 998;")?;
+        assert_eq!(rows, ids(998..1000));
+
+        // 连接只读取右表的全部行，左表逐行读取，取满之后同样停止扫描
+        executor.execute(parse("CREATE TABLE u (id INT PRIMARY KEY, val INT);")?)?;
+        let values = (0..20)
+            .map(|id| format!("({}, {})", id * 50, id))
+            .collect::<Vec<_>>()
+            .join(", ");
+        executor.execute(parse(&format!("INSERT INTO u VALUES {values};"))?)?;
+        for sql in [
+            "SELECT t.id FROM t JOIN u ON t.id = u.id LIMIT 10;",
+            "SELECT t.id FROM t JOIN u ON NOT NOT (t.id = u.id) LIMIT 10;",
+        ] {
+            let (rows, join_reads) = query(sql)?;
+            assert_eq!(
+                rows,
+                (0..10)
+                    .map(|id| Value::Integer(id * 50))
+                    .collect::<Vec<_>>()
+            );
+            assert!(join_reads < full_reads, "{sql}");
+        }
+        let (rows, join_reads) = query("SELECT t.id FROM t CROSS JOIN u LIMIT 10;")?;
+        assert_eq!(rows, vec![Value::Integer(0); 10]);
+        assert!(join_reads < 20 + 2 * RowScan::<CountingStorage>::BATCH_SIZE);
+
+        // 索引扫描只读取需要的行
+        executor.execute(parse("CREATE INDEX idx_val ON t (val);")?)?;
+        let (rows, index_reads) = query("SELECT id FROM t WHERE val >= 100 LIMIT 3;")?;
+        assert_eq!(rows, ids(50..53));
+        let (_, index_full_reads) = query("SELECT id FROM t WHERE val >= 100;")?;
+        assert!(index_reads + 900 < index_full_reads);
+        // 索引项同样分批读取，取满之后不再读取范围中剩余的索引项
+        assert!(index_reads < 2 * IndexScan::<CountingStorage>::BATCH_SIZE);
+
+        // 负数或非整数的 LIMIT 和 OFFSET 在读取任何行之前报错
+        for sql in [
+            "SELECT * FROM t LIMIT 0 - 1;",
+            "SELECT * FROM t LIMIT 'a';",
+            "SELECT * FROM t LIMIT NULL;",
+            "SELECT * FROM t LIMIT 1 OFFSET 1.5;",
+            "SELECT * FROM t LIMIT 1 OFFSET 0 - 2;",
+        ] {
+            reads.store(0, AtomicOrdering::SeqCst);
+            assert!(executor.execute(parse(sql)?).is_err(), "{}", sql);
+            assert_eq!(reads.load(AtomicOrdering::SeqCst), plan_reads, "{}", sql);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_distinct() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: &str| -> Result<(Vec<String>, Vec<Row>)> {
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Query { columns, rows } => Ok((
+                    columns.into_iter().map(|column| column.name).collect(),
+                    rows,
+                )),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        let explain = |sql: &str| -> Result<String> {
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Explain(plan) => Ok(plan),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        let int = Value::Integer;
+        let string = |s: &str| Value::String(s.to_string());
+
+        executor.execute(parse(
+            "CREATE TABLE t (id INT PRIMARY KEY, a INT NULL, b STRING NULL);",
+        )?)?;
+        executor.execute(parse(
+            "INSERT INTO t VALUES
+                (1, 2, 'x'), (2, 1, 'y'), (3, 2, 'x'), (4, NULL, 'y'),
+                (5, 3, NULL), (6, NULL, 'y'), (7, 1, 'x'), (8, 3, NULL);",
+        )?)?;
+
+        // 去重在选择列之后进行，NULL 之间视为相等，保留第一次出现的顺序
+        let (columns, rows) = query("SELECT DISTINCT a FROM t;")?;
+        assert_eq!(columns, vec!["a"]);
+        assert_eq!(
+            rows,
+            vec![vec![int(2)], vec![int(1)], vec![Value::Null], vec![int(3)]]
+        );
+        let (_, rows) = query("SELECT DISTINCT b, a FROM t;")?;
+        assert_eq!(rows.len(), 5);
+
+        // 选择列可以是表达式
+        let (columns, rows) =
+            query("SELECT DISTINCT a / 2 AS half, b FROM t WHERE a IS NOT NULL;")?;
+        assert_eq!(columns, vec!["half", "b"]);
+        assert_eq!(
+            rows,
+            vec![
+                vec![int(1), string("x")],
+                vec![int(0), string("y")],
+                vec![int(1), Value::Null],
+                vec![int(0), string("x")],
+            ]
+        );
+        let (_, rows) = query("SELECT DISTINCT a * 0 FROM t;")?;
+        assert_eq!(rows, vec![vec![int(0)], vec![Value::Null]]);
+
+        // 按照所有选择列排序时流式去重
+        let sql = "SELECT DISTINCT a, b FROM t ORDER BY t.a DESC, b;";
+        assert_eq!(
+            explain(&format!("EXPLAIN {}", sql))?,
+            "Distinct (sorted)\n  Projection: a, b\n    Order: t.a DESC, b ASC\n      Scan: t\n"
+        );
+        let (_, rows) = query(sql)?;
+        assert_eq!(
+            rows,
+            vec![
+                vec![int(3), Value::Null],
+                vec![int(2), string("x")],
+                vec![int(1), string("x")],
+                vec![int(1), string("y")],
+                vec![Value::Null, string("y")],
+            ]
+        );
+
+        // 只按照部分选择列排序时使用哈希去重
+        let sql = "SELECT DISTINCT a, b FROM t ORDER BY a LIMIT 2 OFFSET 1;";
+        assert_eq!(
+            explain(&format!("EXPLAIN {}", sql))?,
+            "Limit: 2 (offset 1)\n  Distinct\n    Projection: a, b\n      Order: a ASC\n        Scan: t\n"
+        );
+        let (_, rows) = query(sql)?;
+        assert_eq!(
+            rows,
+            vec![vec![int(1), string("y")], vec![int(1), string("x")]]
+        );
+
+        // LIMIT 作用于去重之后的行
+        let (_, rows) = query("SELECT DISTINCT b FROM t LIMIT 2;")?;
+        assert_eq!(rows, vec![vec![string("x")], vec![string("y")]]);
+        let (_, rows) = query("SELECT DISTINCT * FROM t ORDER BY id LIMIT 1 OFFSET 7;")?;
+        assert_eq!(rows, vec![vec![int(8), int(3), Value::Null]]);
+
+        // 与分组聚集一起使用
+        let (_, rows) = query("SELECT DISTINCT COUNT(*) FROM t GROUP BY a ORDER BY COUNT(*);")?;
+        assert_eq!(rows, vec![vec![int(2)]]);
+        let (_, rows) = query("SELECT DISTINCT b, COUNT(*) + 1 FROM t GROUP BY b ORDER BY b;")?;
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Null, int(3)],
+                vec![string("x"), int(4)],
+                vec![string("y"), int(4)],
+            ]
+        );
+
+        // 有 DISTINCT 时排序项必须出现在选择列中
+        for sql in [
+            "SELECT DISTINCT a FROM t ORDER BY id;",
+            "SELECT DISTINCT a FROM t ORDER BY a + 1;",
+            "SELECT DISTINCT a + 1 FROM t ORDER BY a;",
+            "SELECT DISTINCT b FROM t GROUP BY b ORDER BY COUNT(*);",
+            "SELECT DISTINCT c FROM t;",
+        ] {
+            assert!(executor.execute(parse(sql)?).is_err(), "{}", sql);
+        }
+        let (_, rows) = query("SELECT DISTINCT a + 1 FROM t ORDER BY a + 1 DESC;")?;
+        assert_eq!(
+            rows,
+            vec![vec![int(4)], vec![int(3)], vec![int(2)], vec![Value::Null]]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predicate_pushdown() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let explain = |sql: &str| -> Result<String> {
+            match executor.execute(parse(&format!("EXPLAIN {sql}"))?)? {
+                ResultSet::Explain(plan) => Ok(plan),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        // 分别使用下推和不下推的计划执行查询，结果排序后返回
+        let query = |sql: &str, pushdown: bool| -> Result<Vec<Row>> {
+            let Statement::Select {
+                columns,
+                distinct,
+                from,
+                filter,
+                group_by,
+                having,
+                ordering,
+                limit,
+                offset,
+            } = parse(sql)?
+            else {
+                panic!("unexpected statement {}", sql);
+            };
+            let planner = match pushdown {
+                true => Planner::new(&executor.transaction),
+                false => Planner::without_pushdown(&executor.transaction),
+            };
+            let plan = planner.build_select(
+                columns, distinct, from, filter, group_by, having, ordering, limit, offset,
+            )?;
+            let (_, rows) = executor.execute_node(plan)?;
+            let mut rows = rows.collect::<Result<Vec<_>>>()?;
+            rows.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+            Ok(rows)
+        };
+
+        // 使用线性同余生成器构造可复现的随机数据，包含重复值和 NULL
+        let mut seed = 13u64;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) % n
+        };
+        for (table, rows) in [("a", 20), ("b", 30), ("c", 15)] {
+            executor.execute(parse(&format!(
+                "CREATE TABLE {table} (id INT PRIMARY KEY, k INT NULL, v INT NULL);"
+            ))?)?;
+            for id in 0..rows {
+                let mut value = |n: u64| match next(n + 1) {
+                    0 => "NULL".to_string(),
+                    v => v.to_string(),
+                };
+                let (k, v) = (value(10), value(5));
+                executor.execute(parse(&format!(
+                    "INSERT INTO {table} VALUES ({id}, {k}, {v});"
+                ))?)?;
+            }
+        }
+
+        // 只引用一侧的条件下推到连接的两侧，其余的留在连接之上
+        assert_eq!(
+            explain("SELECT * FROM a JOIN b ON a.k = b.k WHERE a.v = 1 AND b.v > 2 AND a.v < b.v;")?,
+            "Filter: a.v < b.v\n  HashJoin: Inner Join (a.k = b.k)\n    Scan: a (a.v = 1)\n    Scan: b (b.v > 2)\n"
+        );
+        // 下推到单表后可以使用主键
+        assert_eq!(
+            explain("SELECT * FROM a CROSS JOIN b WHERE a.id = 3 AND b.id > 5;")?,
+            "NestedLoopJoin: Cross Join\n  KeyLookup: a (id = 3)\n  KeyRangeScan: b (id > 5)\n"
+        );
+        // LEFT JOIN 的过滤条件不能下推到右侧，连接条件中只引用右侧的条件可以下推
+        assert_eq!(
+            explain("SELECT * FROM a LEFT JOIN b ON a.k = b.k AND b.v = 1 AND a.v = 2 WHERE a.v > 0 AND b.v < 3;")?,
+            "Filter: b.v < 3\n  HashJoin: Left Join (a.k = b.k AND a.v = 2)\n    Scan: a (a.v > 0)\n    Scan: b (b.v = 1)\n"
+        );
+        // FULL JOIN 的条件都不能下推
+        assert_eq!(
+            explain("SELECT * FROM a FULL JOIN b ON a.k = b.k AND b.v = 1 WHERE a.v > 0;")?,
+            "Filter: a.v > 0\n  HashJoin: Full Join (a.k = b.k AND b.v = 1)\n    Scan: a\n    Scan: b\n"
+        );
+        // 只引用分组字段的 HAVING 条件下推到聚集之前
+        assert_eq!(
+            explain("SELECT k, COUNT(*) FROM a GROUP BY k HAVING k > 1 AND COUNT(*) > 1;")?,
+            "Projection: k, COUNT(*)\n  Filter: COUNT(*) > 1\n    Aggregate: COUNT(*) GROUP BY k\n      Scan: a (k > 1)\n"
+        );
+
+        // 随机组合连接类型和条件，下推前后的结果必须一致
+        let join_predicates = ["b.v = 1", "a.v > 2", "a.v < b.v", "b.k IS NULL", "1 = 1"];
+        let filters = [
+            "a.v = 1",
+            "b.v > 2",
+            "a.v + b.v > 4",
+            "b.k IS NULL",
+            "NOT a.k IS NULL",
+            "a.id > 5",
+            "c.v < 3",
+            "c.k = a.k",
+            "1 = 0",
+        ];
+        let joins = ["JOIN", "LEFT JOIN", "RIGHT JOIN", "FULL JOIN"];
+        for i in 0..200 {
+            let mut on = "a.k = b.k".to_string();
+            for _ in 0..next(3) {
+                on += &format!(" AND {}", join_predicates[next(5) as usize]);
+            }
+            let mut from = format!("a {} b ON {on}", joins[next(4) as usize]);
+            if i % 2 == 0 {
+                from += &format!(" {} c ON c.k = b.k", joins[next(4) as usize]);
+            } else {
+                from += " CROSS JOIN c";
+            }
+            let conjuncts = (0..next(4))
+                .map(|_| filters[next(9) as usize])
+                .collect::<Vec<_>>();
+            let sql = match conjuncts.is_empty() {
+                true => format!("SELECT * FROM {from};"),
+                false => format!("SELECT * FROM {from} WHERE {};", conjuncts.join(" AND ")),
+            };
+            assert_eq!(query(&sql, true)?, query(&sql, false)?, "{sql}");
+        }
+
+        // 分组之后的过滤同样不改变结果
+        for sql in [
+            "SELECT k, COUNT(*) FROM b GROUP BY k HAVING k > 1 AND COUNT(*) > 1;",
+            "SELECT a.k, SUM(b.v) FROM a JOIN b ON a.k = b.k GROUP BY a.k HAVING a.k < 5;",
+            "SELECT v, k FROM c GROUP BY v, k HAVING v + k > 3 OR v IS NULL;",
+        ] {
+            assert_eq!(query(sql, true)?, query(sql, false)?, "{sql}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_pruning() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let plan = |sql: &str, pruning: bool| -> Result<Node> {
+            let planner = match pruning {
+                true => Planner::new(&executor.transaction),
+                false => Planner::without_column_pruning(&executor.transaction),
+            };
+            planner.build_query(parse(sql)?)
+        };
+        // 分别使用裁剪和不裁剪的计划执行查询，结果排序后返回
+        let query = |sql: &str, pruning: bool| -> Result<Vec<Row>> {
+            let (_, rows) = executor.execute_node(plan(sql, pruning)?)?;
+            let mut rows = rows.collect::<Result<Vec<_>>>()?;
+            rows.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+            Ok(rows)
+        };
+
+        // 两张 30 列的表，包含重复值和 NULL
+        let mut seed = 7u64;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) % n
+        };
+        let columns = (1..30)
+            .map(|i| format!("c{i} INT NULL"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        for table in ["w", "x"] {
+            executor.execute(parse(&format!(
+                "CREATE TABLE {table} (id INT PRIMARY KEY, {columns});"
+            ))?)?;
+            for id in 0..40 {
+                let values = (1..30)
+                    .map(|_| match next(6) {
+                        0 => "NULL".to_string(),
+                        v => v.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                executor.execute(parse(&format!(
+                    "INSERT INTO {table} VALUES ({id}, {values});"
+                ))?)?;
+            }
+        }
+
+        // 选择列和过滤条件引用的列之外的列都不解码，SELECT * 解码所有列
+        let Node::Projection { source, .. } = plan("SELECT c2, c1 FROM w WHERE c5 > 1;", true)?
+        else {
+            panic!("expected projection");
+        };
+        assert!(matches!(
+            *source,
+            Node::Scan { needed: Some(ref needed), .. } if *needed == [1, 2, 5]
+        ));
+        assert!(matches!(
+            plan("SELECT * FROM w WHERE c5 > 1;", true)?,
+            Node::Scan { needed: None, .. }
+        ));
+        let rows = query("SELECT * FROM w;", true)?;
+        assert!(rows.iter().all(|row| row.len() == 30));
+        assert!(rows
+            .iter()
+            .any(|row| row[29] != Value::Null && row[1] != Value::Null));
+
+        // 裁剪前后的结果必须一致
+        for sql in [
+            "SELECT * FROM w WHERE c3 > 1;",
+            "SELECT c1, c2 FROM w;",
+            "SELECT c1 FROM w WHERE c5 IS NULL ORDER BY c7 DESC, id LIMIT 5;",
+            "SELECT c1 + c28 AS s FROM w ORDER BY s;",
+            "SELECT DISTINCT c1 FROM w;",
+            "SELECT DISTINCT c1, c2 FROM w ORDER BY c1 DESC;",
+            "SELECT c2, COUNT(*), SUM(c4) FROM w GROUP BY c2 HAVING MAX(c6) > 1;",
+            "SELECT COUNT(c9), AVG(c10) FROM w WHERE c11 < 3;",
+            "SELECT w.c1, x.c2 FROM w JOIN x ON w.c3 = x.c3 WHERE x.c4 > 0;",
+            "SELECT w.c1 FROM w LEFT JOIN x ON w.c3 = x.c3 AND w.c8 < x.c8;",
+            "SELECT w.c1, x.c29 FROM w CROSS JOIN x WHERE w.c12 + x.c13 = 5;",
+            "SELECT * FROM w JOIN x ON w.c3 = x.c3 WHERE x.c4 > 4;",
+            "SELECT c1 FROM w UNION SELECT c9 FROM x;",
+            "SELECT c1, c2 FROM w WHERE c3 = 1 EXCEPT ALL SELECT c1, c2 FROM x;",
+        ] {
+            assert_eq!(query(sql, true)?, query(sql, false)?, "{sql}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_constant_folding() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: &str| -> Result<Vec<Row>> {
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Query { rows, .. } => Ok(rows),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        let explain = |sql: &str| -> Result<String> {
+            match executor.execute(parse(&format!("EXPLAIN {sql}"))?)? {
+                ResultSet::Explain(plan) => Ok(plan),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+
+        executor.execute(parse(
+            "CREATE TABLE t (id INT PRIMARY KEY, v INT NULL, name STRING NULL);",
+        )?)?;
+        executor.execute(parse(
+            "CREATE TABLE e (id INT PRIMARY KEY, v INT NULL, name STRING NULL);",
+        )?)?;
+        executor.execute(parse(
+            "INSERT INTO t VALUES (1, 1, 'a'), (2, 2, 'b'), (3, NULL, NULL);",
+        )?)?;
+
+        // 恒为真的条件被去掉，常量子表达式在计划时计算
+        for (sql, plan) in [
+            ("SELECT * FROM t WHERE 1 = 1;", "Scan: t\n"),
+            (
+                "SELECT * FROM t WHERE v > 1 AND 1 = 1;",
+                "Scan: t (v > 1)\n",
+            ),
+            (
+                "SELECT * FROM t WHERE TRUE AND v > 1 + 1;",
+                "Scan: t (v > 2)\n",
+            ),
+            ("SELECT * FROM t WHERE NOT NOT v = 1;", "Scan: t (v = 1)\n"),
+            ("SELECT * FROM t WHERE v = 1 OR FALSE;", "Scan: t (v = 1)\n"),
+            ("SELECT * FROM t WHERE v = 1 OR 1 < 2;", "Scan: t\n"),
+            // 数值列上的恒等运算被去掉之后可以使用主键
+            (
+                "SELECT * FROM t WHERE id + 0 = 2 * 1;",
+                "KeyLookup: t (id = 2)\n",
+            ),
+            ("SELECT * FROM t WHERE 1 * v - 0 = 1;", "Scan: t (v = 1)\n"),
+            (
+                "SELECT * FROM t LIMIT 5 + 5;",
+                "Limit: 10 (offset 0)\n  Scan: t\n",
+            ),
+        ] {
+            assert_eq!(explain(sql)?, plan, "{sql}");
+        }
+        assert_eq!(
+            query("SELECT id FROM t WHERE id + 0 = 2 * 1;")?,
+            vec![vec![Value::Integer(2)]]
+        );
+
+        // 恒为假的条件将整个子树替换为空节点
+        for (sql, plan) in [
+            ("SELECT * FROM t WHERE 1 = 0;", "Empty\n"),
+            ("SELECT * FROM t WHERE v = 1 AND FALSE;", "Empty\n"),
+            ("SELECT * FROM t WHERE FALSE AND name IS NULL;", "Empty\n"),
+            ("SELECT * FROM t WHERE NULL;", "Empty\n"),
+            (
+                "SELECT * FROM t JOIN e ON t.id = e.id WHERE 1 > 2;",
+                "Empty\n",
+            ),
+            (
+                "SELECT COUNT(*) FROM t WHERE FALSE;",
+                "Projection: COUNT(*)\n  Aggregate: COUNT(*)\n    Empty\n",
+            ),
+            (
+                "SELECT v, COUNT(*) FROM t GROUP BY v HAVING 1 = 2 ORDER BY v;",
+                "Projection: v, COUNT(*)\n  Order: v ASC\n    Empty\n",
+            ),
+        ] {
+            assert_eq!(explain(sql)?, plan, "{sql}");
+        }
+        assert!(query("SELECT * FROM t WHERE 1 = 0;")?.is_empty());
+        assert_eq!(
+            query("SELECT COUNT(*) FROM t WHERE FALSE;")?,
+            vec![vec![Value::Integer(0)]]
+        );
+
+        // 可能出错的表达式不在计划时计算，也不会被丢弃，只有在执行时计算到才会报错
+        for (sql, plan) in [
+            (
+                "SELECT * FROM t WHERE 1 / 0 = 1;",
+                "Scan: t ((1 / 0) = 1)\n",
+            ),
+            (
+                "SELECT * FROM t WHERE FALSE AND v / 0 = 1;",
+                "Scan: t (FALSE AND ((v / 0) = 1))\n",
+            ),
+        ] {
+            assert_eq!(explain(sql)?, plan, "{sql}");
+            assert!(query(sql).is_err(), "{sql}");
+            assert!(query(&sql.replace("FROM t", "FROM e"))?.is_empty(), "{sql}");
+        }
+
+        // 类型确定的错误在计划时发现，即使表中没有行也会报错
+        for sql in [
+            "SELECT * FROM t WHERE v > 'a' AND FALSE;",
+            "SELECT * FROM t WHERE name + 0 = 1;",
+            "SELECT * FROM t WHERE TRUE AND v;",
+        ] {
+            assert!(
+                matches!(
+                    explain(sql),
+                    Err(crate::Error::Execution(
+                        crate::ExecutionError::TypeMismatch(_)
+                    ))
+                ),
+                "{sql}"
+            );
+            assert!(query(&sql.replace("FROM t", "FROM e")).is_err(), "{sql}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_parameters() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        executor.execute(parse("CREATE TABLE t (id INT PRIMARY KEY, v INT NULL);")?)?;
+        executor.execute(parse("INSERT INTO t VALUES (1, 10), (2, 20), (3, 30);")?)?;
+
+        // 计划时占位符不能作为访问路径的边界，绑定参数之后按照代入的值重新选择访问路径
+        let planner = Planner::new(&executor.transaction);
+        for (sql, params, plan) in [
+            (
+                "SELECT * FROM t WHERE id = $1;",
+                vec![Value::Integer(2)],
+                "KeyLookup: t (id = 2)\n",
+            ),
+            (
+                "SELECT v FROM t WHERE id > ? AND v < ?;",
+                vec![Value::Integer(1), Value::Integer(30)],
+                "Projection: v\n  Filter: v < 30\n    KeyRangeScan: t (id > 1)\n",
+            ),
+            (
+                "SELECT id, v + $1 FROM t WHERE v > $1;",
+                vec![Value::Integer(15)],
+                "Projection: id, v + 15\n  Scan: t (v > 15)\n",
+            ),
+        ] {
+            let generic = executor.plan_query(parse(sql)?, params.len())?;
+            assert!(generic.root.to_string().contains('$'), "{sql}");
+            let bound = planner.bind_parameters(generic.root.clone(), &params)?;
+            assert_eq!(bound.to_string(), plan, "{sql}");
+        }
+
+        let plan = executor.plan_query(parse("SELECT v FROM t WHERE id = $1;")?, 1)?;
+        assert_eq!(
+            executor.execute_plan(&plan, &[Value::Integer(3)])?.rows(),
+            &[vec![Value::Integer(30)]]
+        );
+        let e = executor.execute_plan(&plan, &[]).unwrap_err();
+        assert_eq!(e.code(), crate::ErrorCode::ValueCountMismatch);
+
+        // LIMIT 在计划时求值，其中的占位符无法在计划时确定
+        let e = executor
+            .plan_query(parse("SELECT * FROM t LIMIT $1;")?, 1)
+            .unwrap_err();
+        assert_eq!(e.code(), crate::ErrorCode::UndefinedParameter);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_float_key() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: &str| -> Result<Vec<Row>> {
+            match executor.execute(parse(sql)?)? {
+                ResultSet::Query { rows, .. } => Ok(rows),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        executor.execute(parse("CREATE TABLE f (x FLOAT PRIMARY KEY, v INT);")?)?;
+        executor.execute(parse("CREATE INDEX idx_v ON f (v);")?)?;
+        executor.execute(parse("INSERT INTO f VALUES (-0.0, 1);")?)?;
+
+        // 以 -0.0 保存的行可以通过 0.0 按主键查找，保存的值不变
+        let rows = query("SELECT * FROM f WHERE x = 0.0;")?;
+        assert_eq!(rows, vec![vec![Value::Float(0.0), Value::Integer(1)]]);
+        assert!(matches!(rows[0][0], Value::Float(x) if x.is_sign_negative()));
+
+        // 0.0 和 -0.0 是同一个主键
+        let e = executor
+            .execute(parse("INSERT INTO f VALUES (0.0, 2);")?)
+            .unwrap_err();
+        assert_eq!(e.code(), crate::ErrorCode::UniqueViolation);
+        assert_eq!(query("SELECT x FROM f WHERE v = 1;")?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_reorder() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let explain = |sql: &str| -> Result<String> {
+            match executor.execute(parse(&format!("EXPLAIN {sql}"))?)? {
+                ResultSet::Explain(plan) => Ok(plan),
+                result => panic!("unexpected result {:?}", result),
+            }
+        };
+        // 分别使用重新排序和不重新排序的计划执行查询，返回排序后的结果和执行的时间
+        let query = |sql: &str, reorder: bool| -> Result<(Vec<String>, Vec<Row>, Duration)> {
+            let Statement::Select {
+                columns,
+                distinct,
+                from,
+                filter,
+                group_by,
+                having,
+                ordering,
+                limit,
+                offset,
+            } = parse(sql)?
+            else {
+                panic!("unexpected statement {}", sql);
+            };
+            let planner = match reorder {
+                true => Planner::new(&executor.transaction),
+                false => Planner::without_join_reorder(&executor.transaction),
+            };
+            let start = Instant::now();
+            let plan = planner.build_select(
+                columns, distinct, from, filter, group_by, having, ordering, limit, offset,
+            )?;
+            let (columns, rows) = executor.execute_node(plan)?;
+            let mut rows = rows.collect::<Result<Vec<_>>>()?;
+            let elapsed = start.elapsed();
+            rows.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+            Ok((columns, rows, elapsed))
+        };
+
+        // a 和 b 各 200 行，c 和 d 分别只有 10 行和 5 行
+        for (table, rows) in [("a", 200), ("b", 200), ("c", 10), ("d", 5)] {
+            executor.execute(parse(&format!(
+                "CREATE TABLE {table} (id INT PRIMARY KEY, x INT, y INT);"
+            ))?)?;
+            for id in 0..rows {
+                executor.execute(parse(&format!(
+                    "INSERT INTO {table} VALUES ({id}, {}, {});",
+                    id % 50,
+                    id % 7
+                ))?)?;
+            }
+        }
+
+        // 从最小的 c 开始，依次连接有等值条件的 a 和 b，避免 a 和 b 的笛卡尔积
+        let skewed = "SELECT * FROM a CROSS JOIN b CROSS JOIN c WHERE a.x = c.x AND b.y = c.y;";
+        assert_eq!(
+            explain(skewed)?,
+            "Projection: a.id, a.x, a.y, b.id, b.x, b.y, c.id, c.x, c.y\n  HashJoin: Inner Join (c.y = b.y)\n    HashJoin: Inner Join (c.x = a.x)\n      Scan: c\n      Scan: a\n    Scan: b\n"
+        );
+        let (columns, reordered, fast) = query(skewed, true)?;
+        let (original_columns, original, slow) = query(skewed, false)?;
+        assert_eq!(columns, original_columns);
+        assert_eq!(reordered, original);
+        assert_eq!(reordered.len(), 1148);
+        assert!(fast * 5 < slow, "reordered {:?}, original {:?}", fast, slow);
+
+        // 外连接整体作为一个输入，内部的顺序不变
+        assert_eq!(
+            explain("SELECT * FROM a LEFT JOIN b ON a.x = b.x JOIN c ON c.y = b.y JOIN d ON d.id = c.id;")?,
+            "Projection: a.id, a.x, a.y, b.id, b.x, b.y, c.id, c.x, c.y, d.id, d.x, d.y\n  HashJoin: Inner Join (c.y = b.y)\n    HashJoin: Inner Join (d.id = c.id)\n      Scan: d\n      Scan: c\n    HashJoin: Left Join (a.x = b.x)\n      Scan: a\n      Scan: b\n"
+        );
+        assert_eq!(
+            explain("SELECT c.id, b.id FROM b JOIN a ON a.x = b.x JOIN c ON c.x = a.x WHERE c.id = 3 AND a.y = 1;")?,
+            "Projection: c.id, b.id\n  Projection: b.id, b.x, b.y, a.id, a.x, a.y, c.id, c.x, c.y\n    HashJoin: Inner Join (a.x = b.x)\n      HashJoin: Inner Join (c.x = a.x)\n        KeyLookup: c (id = 3)\n        Scan: a (a.y = 1)\n      Scan: b\n"
+        );
+
+        // 重新排序不改变结果和列的顺序
+        for sql in [
+            "SELECT * FROM a JOIN b ON a.id = b.id JOIN c ON c.id = b.x;",
+            "SELECT a.id, c.y FROM a CROSS JOIN c CROSS JOIN b WHERE a.x = b.x AND b.y < c.y AND a.id < 20;",
+            "SELECT * FROM c JOIN b ON b.y = c.y LEFT JOIN a ON a.id = b.id AND a.x = c.x WHERE b.id < 30;",
+            "SELECT c.x, COUNT(*) FROM a JOIN b ON a.x = b.x JOIN c ON c.x = b.x GROUP BY c.x;",
+        ] {
+            let (columns, rows, _) = query(sql, true)?;
+            let (original_columns, original, _) = query(sql, false)?;
+            assert_eq!(columns, original_columns, "{sql}");
+            assert_eq!(rows, original, "{sql}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_analyze() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        // 执行 EXPLAIN ANALYZE，去掉每行中不稳定的耗时，返回计划和每行的耗时
+        let explain_analyze = |sql: &str| -> Result<(String, Vec<String>)> {
+            let plan = match executor.execute(parse(&format!("EXPLAIN ANALYZE {sql}"))?)? {
+                ResultSet::Explain(plan) => plan,
+                result => panic!("unexpected result {:?}", result),
+            };
+            let mut text = String::new();
+            let mut times = Vec::new();
+            for line in plan.lines() {
+                let (line, time) = line.split_once(", time=").expect(line);
+                text += &format!("{line}]\n");
+                times.push(time.trim_end_matches(']').to_string());
+            }
+            Ok((text, times))
+        };
+
+        executor.execute(parse("CREATE TABLE a (id INT PRIMARY KEY, k INT, v INT);")?)?;
+        executor.execute(parse("CREATE TABLE b (id INT PRIMARY KEY, k INT);")?)?;
+        for id in 0..20 {
+            executor.execute(parse(&format!(
+                "INSERT INTO a VALUES ({id}, {}, {});",
+                id % 4,
+                id % 3
+            ))?)?;
+        }
+        for id in 0..6 {
+            executor.execute(parse(&format!("INSERT INTO b VALUES ({id}, {id});"))?)?;
+        }
+
+        // 每个节点附加实际产生的行数
+        let (plan, times) = explain_analyze("SELECT * FROM a WHERE v = 1;")?;
+        assert_eq!(plan, "Scan: a (v = 1) [rows=7]\n");
+        assert!(times.iter().all(|time| time.ends_with('s')), "{:?}", times);
+        assert_eq!(
+            explain_analyze("SELECT a.id, b.id FROM a JOIN b ON a.k = b.k WHERE b.id < 3 ORDER BY a.id DESC;")?.0,
+            "Projection: a.id, b.id [rows=15]\n  Order: a.id DESC [rows=15]\n    HashJoin: Inner Join (a.k = b.k) [rows=15]\n      Scan: a [rows=20]\n      KeyRangeScan: b (id < 3) [rows=3]\n"
+        );
+        assert_eq!(
+            explain_analyze("SELECT k, SUM(v) FROM a GROUP BY k HAVING SUM(v) > 4;")?.0,
+            "Projection: k, SUM(v) [rows=2]\n  Filter: SUM(v) > 4 [rows=2]\n    Aggregate: SUM(v) GROUP BY k [rows=4]\n      Scan: a [rows=20]\n"
+        );
+        // 上层停止拉取后下层不再产生行
+        assert_eq!(
+            explain_analyze("SELECT id FROM a LIMIT 3 OFFSET 2;")?.0,
+            "Projection: id [rows=3]\n  Limit: 3 (offset 2) [rows=3]\n    Scan: a [rows=5]\n"
+        );
+        assert_eq!(
+            explain_analyze("SELECT * FROM a WHERE 1 = 0;")?.0,
+            "Empty [rows=0]\n"
+        );
+
+        // 执行中的错误直接返回
+        assert!(executor
+            .execute(parse("EXPLAIN ANALYZE SELECT v / 0 FROM a;")?)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_predicates() -> Result<()> {
+        let executor = init_executor()?;
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let query = |sql: &str| -> Result<Vec<Row>> {
+            Ok(executor.execute(parse(sql)?)?.into_iter().collect())
+        };
+        let (int, null) = (Value::Integer, Value::Null);
+
+        for sql in [
+            "CREATE TABLE a (id INT PRIMARY KEY, v INT NULL, k INT NULL);",
+            "CREATE TABLE b (id INT PRIMARY KEY, k INT NULL);",
+            "INSERT INTO a VALUES (1, NULL, 1), (2, 1, NULL), (3, 2, 2), (4, NULL, NULL);",
+            "INSERT INTO b VALUES (10, 1), (11, NULL), (12, 2);",
+        ] {
+            executor.execute(parse(sql)?)?;
+        }
+
+        // WHERE 的条件为 NULL 时不保留行，NOT NULL 仍然是 NULL
+        for (predicate, ids) in [
+            ("v = 1", vec![2]),
+            ("v != 1", vec![3]),
+            ("NOT (v = 1)", vec![3]),
+            ("v = NULL", vec![]),
+            ("NOT (v = NULL)", vec![]),
+            ("NOT NULL", vec![]),
+            ("v IS NULL", vec![1, 4]),
+            ("v > 0 OR k > 0", vec![1, 2, 3]),
+            ("NOT (v > 0 OR k > 0)", vec![]),
+            ("v > 0 AND k IS NULL", vec![2]),
+        ] {
+            assert_eq!(
+                query(&format!("SELECT id FROM a WHERE {predicate} ORDER BY id;"))?,
+                ids.into_iter().map(|id| vec![int(id)]).collect::<Vec<_>>(),
+                "{predicate}"
+            );
+        }
+        assert_eq!(
+            executor.execute(parse("DELETE FROM a WHERE NOT (v = NULL);")?)?,
+            ResultSet::modified(0)
+        );
+        assert_eq!(
+            executor.execute(parse("UPDATE a SET v = 0 WHERE v != 1;")?)?,
+            ResultSet::modified(1)
+        );
+        executor.execute(parse("UPDATE a SET v = 2 WHERE id = 3;")?)?;
+
+        // JOIN ON 的条件为 NULL 时不匹配，哈希连接和嵌套循环连接的结果相同
+        let pairs = |pairs: Vec<(i64, Option<i64>)>| -> Vec<Row> {
+            pairs
+                .into_iter()
+                .map(|(a, b)| vec![int(a), b.map_or(null.clone(), int)])
+                .collect()
+        };
+        for (join, expected) in [
+            (
+                "JOIN b ON a.k = b.k",
+                pairs(vec![(1, Some(10)), (3, Some(12))]),
+            ),
+            (
+                "JOIN b ON a.k = b.k OR a.v = b.k",
+                pairs(vec![(1, Some(10)), (2, Some(10)), (3, Some(12))]),
+            ),
+            (
+                "JOIN b ON a.k = b.k AND a.v > 0",
+                pairs(vec![(3, Some(12))]),
+            ),
+            (
+                "LEFT JOIN b ON a.k = b.k",
+                pairs(vec![(1, Some(10)), (2, None), (3, Some(12)), (4, None)]),
+            ),
+            (
+                "LEFT JOIN b ON a.k = b.k OR a.v = b.k",
+                pairs(vec![(1, Some(10)), (2, Some(10)), (3, Some(12)), (4, None)]),
+            ),
+        ] {
+            assert_eq!(
+                query(&format!(
+                    "SELECT a.id, b.id FROM a {join} ORDER BY a.id, b.id;"
+                ))?,
+                expected,
+                "{join}"
+            );
+        }
+
+        // HAVING 对分组采用相同的规则，非布尔值的条件在执行时报错
+        assert_eq!(
+            query("SELECT k, COUNT(*) FROM a GROUP BY k HAVING MAX(v) > 0 ORDER BY k;")?,
+            vec![vec![null.clone(), int(2)], vec![int(2), int(1)]]
+        );
+        assert_eq!(
+            query("SELECT k FROM a GROUP BY k HAVING NOT (MAX(v) = NULL);")?,
+            Vec::<Row>::new()
+        );
+        assert!(matches!(
+            query("SELECT k FROM a GROUP BY k HAVING SUM(v);"),
+            Err(crate::Error::Execution(
+                crate::ExecutionError::TypeMismatch(_)
+            ))
+        ));
+
+        // COALESCE 的参数类型在计划时统一，整数和浮点数混合时整列都是浮点数，其他不同的类型在计划时报错
+        for sql in [
+            "CREATE TABLE c (id INT PRIMARY KEY, v INT NULL, f FLOAT NULL, s STRING NULL);",
+            "INSERT INTO c VALUES (1, 1, NULL, 'x'), (2, NULL, 2.5, NULL), (3, NULL, NULL, NULL);",
+        ] {
+            executor.execute(parse(sql)?)?;
+        }
+        let result = executor.execute(parse("SELECT COALESCE(v, f) FROM c ORDER BY id;")?)?;
+        assert_eq!(result.columns()[0].data_type, Some(DataType::Float));
+        assert_eq!(
+            result.rows(),
+            [vec![Value::Float(1.0)], vec![Value::Float(2.5)], vec![null]]
+        );
+        for sql in [
+            "SELECT COALESCE(v, s) FROM c;",
+            "SELECT id FROM c WHERE COALESCE(s, f) IS NULL;",
+        ] {
+            assert!(matches!(
+                executor.execute(parse(sql)?),
+                Err(crate::Error::Execution(
+                    crate::ExecutionError::TypeMismatch(_)
+                ))
+            ));
+        }
+
+        Ok(())
+    }
 }
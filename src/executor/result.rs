@@ -0,0 +1,542 @@
+use serde::{
+    ser::{SerializeMap, SerializeSeq, SerializeStruct},
+    Deserialize, Serialize, Serializer,
+};
+
+use crate::{
+    schema::{DataType, Row, Value},
+    ExecutionError::{ResultColumnNotFound, RowOutOfRange},
+    Result,
+};
+
+/// 查询结果中一列的元数据
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ColumnMeta {
+    /// 输出的列名
+    pub name: String,
+    /// 根据结果中的值推断的数据类型，只有 NULL 或没有行时为 `None`
+    pub data_type: Option<DataType>,
+    /// 结果中是否可能包含 NULL，推断不出数据类型时也认为可以为 NULL
+    pub nullable: bool,
+}
+
+impl ColumnMeta {
+    /// 根据第 `index` 列的值推断列的元数据，数据类型取第一个非 NULL 值的类型
+    fn infer(name: String, index: usize, rows: &[Row]) -> Self {
+        let data_type = rows.iter().find_map(|row| row[index].data_type());
+        let nullable = data_type.is_none() || rows.iter().any(|row| row[index] == Value::Null);
+        Self {
+            name,
+            data_type,
+            nullable,
+        }
+    }
+}
+
+/// 查询结果转换为 JSON 时的结构
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum JsonLayout {
+    /// 每行为一个以列名为键的对象，所有行组成数组；重复的列名依次加上 `_2`、`_3` 等后缀
+    #[default]
+    Objects,
+    /// `{"columns": [...], "rows": [[...], ...]}`，列名保持原样
+    Compact,
+}
+
+/// NaN 和无穷大不能表示为 JSON 的数字，转换为 JSON 时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NonFiniteFloat {
+    /// 转换为 `null`
+    #[default]
+    Null,
+    /// 转换为字符串 `"NaN"`、`"Infinity"` 或 `"-Infinity"`
+    String,
+}
+
+/// 查询结果转换为 JSON 的选项，见 [`ResultSet::to_json`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct JsonOptions {
+    pub layout: JsonLayout,
+    pub non_finite: NonFiniteFloat,
+}
+
+/// SQL 执行结果
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum ResultSet {
+    CreateTable {
+        name: String,
+    },
+    CreateIndex {
+        name: String,
+    },
+    DropTable {
+        name: String,
+    },
+    /// 查询的结果
+    Query {
+        columns: Vec<ColumnMeta>,
+        rows: Vec<Row>,
+    },
+    /// 插入、更新或删除的行数
+    ///
+    /// INSERT 为插入的行数；UPDATE 为值实际发生变化的行数，满足条件但赋值前后相同的行不计入；
+    /// DELETE 为满足条件而删除的行数，不包含 `ON DELETE CASCADE` 级联删除的子表中的行。
+    /// `last_insert_ids` 为 INSERT 按照插入的顺序为自增列分配的 ID，显式给出的值不包含在内，其他语句为空。
+    Modified {
+        count: u64,
+        last_insert_ids: Vec<i64>,
+    },
+    /// 收集了统计信息的表
+    Analyze {
+        tables: Vec<String>,
+    },
+    /// 执行计划的文本
+    Explain(String),
+    Begin,
+    Commit,
+    Rollback,
+}
+
+impl ResultSet {
+    /// 由列名和行构造查询结果，列的元数据根据行推断
+    pub fn query(columns: Vec<String>, rows: Vec<Row>) -> Self {
+        let columns = columns
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| ColumnMeta::infer(name, index, &rows))
+            .collect();
+        Self::Query { columns, rows }
+    }
+
+    /// 修改了 `count` 行、没有分配自增 ID 的结果
+    pub fn modified(count: u64) -> Self {
+        Self::Modified {
+            count,
+            last_insert_ids: Vec::new(),
+        }
+    }
+
+    /// INSERT 为自增列分配的 ID，不是插入的结果时为空
+    pub fn last_insert_ids(&self) -> &[i64] {
+        match self {
+            ResultSet::Modified {
+                last_insert_ids, ..
+            } => last_insert_ids,
+            _ => &[],
+        }
+    }
+
+    /// 查询结果的列，不是查询时为空
+    pub fn columns(&self) -> &[ColumnMeta] {
+        match self {
+            Self::Query { columns, .. } => columns,
+            _ => &[],
+        }
+    }
+
+    /// 查询结果的行，不是查询时为空
+    pub fn rows(&self) -> &[Row] {
+        match self {
+            Self::Query { rows, .. } => rows,
+            _ => &[],
+        }
+    }
+
+    /// 获取第 `row` 行中名为 `column` 的列的值，并转换为类型 `T`
+    ///
+    /// 行或列不存在，或者值不能转换为 `T` 时返回错误，可以为 NULL 的值使用 `Option<T>` 获取。
+    pub fn get<T: TryFrom<Value, Error = crate::Error>>(
+        &self,
+        row: usize,
+        column: &str,
+    ) -> Result<T> {
+        let index = self
+            .columns()
+            .iter()
+            .position(|meta| meta.name == column)
+            .ok_or(ResultColumnNotFound(column.to_string()))?;
+        let row = self.rows().get(row).ok_or(RowOutOfRange(row))?;
+        T::try_from(row[index].clone())
+    }
+
+    /// 获取第 `row` 行中名为 `column` 的列的值，行或列不存在时返回 `None`
+    pub fn value(&self, row: usize, column: &str) -> Option<&Value> {
+        let index = self.columns().iter().position(|meta| meta.name == column)?;
+        self.rows().get(row).map(|row| &row[index])
+    }
+}
+
+impl ResultSet {
+    /// 以 JSON 的形式序列化结果，返回的值实现了 [`Serialize`]，可以交给任意的 serde 序列化器
+    ///
+    /// 查询结果按照 `options.layout` 组织，值转换为 JSON 原生的类型：NULL 为 `null`，整数和浮点数为数字，
+    /// JSON 值原样嵌入，NaN 和无穷大按照 `options.non_finite` 处理。其他结果使用 [`ResultSet`] 本身的序列化格式，
+    /// 例如 `{"Modified":{"count":1,"last_insert_ids":[]}}`。
+    pub fn to_json(&self, options: JsonOptions) -> JsonResult<'_> {
+        JsonResult {
+            result: self,
+            options,
+        }
+    }
+
+    /// 将结果转换为 JSON 文本，见 [`ResultSet::to_json`]
+    pub fn to_json_string(&self, options: JsonOptions) -> Result<String> {
+        Ok(serde_json::to_string(&self.to_json(options))?)
+    }
+}
+
+/// [`ResultSet::to_json`] 返回的 JSON 形式的结果
+#[derive(Debug, Clone, Copy)]
+pub struct JsonResult<'a> {
+    result: &'a ResultSet,
+    options: JsonOptions,
+}
+
+impl Serialize for JsonResult<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let ResultSet::Query { columns, rows } = self.result else {
+            return self.result.serialize(serializer);
+        };
+        let non_finite = self.options.non_finite;
+        match self.options.layout {
+            JsonLayout::Objects => {
+                let names = unique_names(columns);
+                let mut seq = serializer.serialize_seq(Some(rows.len()))?;
+                for row in rows {
+                    seq.serialize_element(&JsonObject {
+                        names: &names,
+                        row,
+                        non_finite,
+                    })?;
+                }
+                seq.end()
+            }
+            JsonLayout::Compact => {
+                let names = columns.iter().map(|meta| &meta.name).collect::<Vec<_>>();
+                let rows = rows
+                    .iter()
+                    .map(|row| JsonRow { row, non_finite })
+                    .collect::<Vec<_>>();
+                let mut state = serializer.serialize_struct("ResultSet", 2)?;
+                state.serialize_field("columns", &names)?;
+                state.serialize_field("rows", &rows)?;
+                state.end()
+            }
+        }
+    }
+}
+
+/// 输出为以列名为键的对象的一行
+struct JsonObject<'a> {
+    names: &'a [String],
+    row: &'a Row,
+    non_finite: NonFiniteFloat,
+}
+
+impl Serialize for JsonObject<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.names.len()))?;
+        for (name, value) in self.names.iter().zip(self.row) {
+            map.serialize_entry(name, &JsonValue(value, self.non_finite))?;
+        }
+        map.end()
+    }
+}
+
+/// 输出为数组的一行
+struct JsonRow<'a> {
+    row: &'a Row,
+    non_finite: NonFiniteFloat,
+}
+
+impl Serialize for JsonRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.row.len()))?;
+        for value in self.row {
+            seq.serialize_element(&JsonValue(value, self.non_finite))?;
+        }
+        seq.end()
+    }
+}
+
+/// 以 JSON 原生的类型输出的值
+struct JsonValue<'a>(&'a Value, NonFiniteFloat);
+
+impl Serialize for JsonValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Null => serializer.serialize_unit(),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::Integer(i) => serializer.serialize_i64(*i),
+            Value::Float(f) if f.is_finite() => serializer.serialize_f64(*f),
+            Value::Float(f) => match self.1 {
+                NonFiniteFloat::Null => serializer.serialize_unit(),
+                NonFiniteFloat::String if f.is_nan() => serializer.serialize_str("NaN"),
+                NonFiniteFloat::String if *f > 0.0 => serializer.serialize_str("Infinity"),
+                NonFiniteFloat::String => serializer.serialize_str("-Infinity"),
+            },
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Json(json) => json.serialize(serializer),
+        }
+    }
+}
+
+/// 为重复的列名依次加上 `_2`、`_3` 等后缀，使每个列名在对象中唯一，例如连接后两个表中同名的列
+///
+/// 第一次出现的列名保持原样，加上后缀的列名也不会和结果中其他列原本的列名相同。
+fn unique_names(columns: &[ColumnMeta]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::with_capacity(columns.len());
+    for meta in columns {
+        let mut name = meta.name.clone();
+        let mut suffix = 1;
+        while names.contains(&name) || suffix > 1 && columns.iter().any(|other| other.name == name)
+        {
+            suffix += 1;
+            name = format!("{}_{}", meta.name, suffix);
+        }
+        names.push(name);
+    }
+    names
+}
+
+impl IntoIterator for ResultSet {
+    type Item = Row;
+    type IntoIter = std::vec::IntoIter<Row>;
+
+    /// 按顺序返回查询结果的行，不是查询时没有行
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            Self::Query { rows, .. } => rows.into_iter(),
+            _ => Vec::new().into_iter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        schema::{Column, Table},
+        storage::MemoryStorage,
+        Database, Engine,
+    };
+
+    #[test]
+    fn test_result_set() -> Result<()> {
+        let result = ResultSet::query(
+            vec!["id".to_string(), "score".to_string(), "note".to_string()],
+            vec![
+                vec![Value::Integer(1), Value::Null, Value::Null],
+                vec![Value::Integer(2), Value::Float(1.5), Value::Null],
+            ],
+        );
+
+        // 只有 NULL 的列推断不出类型，视为可以为 NULL
+        let meta = |name: &str, data_type, nullable| ColumnMeta {
+            name: name.to_string(),
+            data_type,
+            nullable,
+        };
+        assert_eq!(
+            result.columns(),
+            [
+                meta("id", Some(DataType::Integer), false),
+                meta("score", Some(DataType::Float), true),
+                meta("note", None, true),
+            ]
+        );
+
+        assert_eq!(result.get::<i64>(1, "id")?, 2);
+        assert_eq!(result.get::<Option<f64>>(0, "score")?, None);
+        assert_eq!(result.get::<Option<f64>>(1, "score")?, Some(1.5));
+        assert!(result.get::<f64>(0, "score").is_err());
+        assert!(result.get::<String>(0, "id").is_err());
+        assert!(result.get::<i64>(2, "id").is_err());
+        assert!(result.get::<i64>(0, "missing").is_err());
+
+        // 序列化后可以还原
+        let json = serde_json::to_string(&result)?;
+        assert_eq!(serde_json::from_str::<ResultSet>(&json)?, result);
+
+        assert_eq!(result.value(1, "score"), Some(&Value::Float(1.5)));
+        assert_eq!(result.value(0, "note"), Some(&Value::Null));
+        assert_eq!(result.value(2, "id"), None);
+        assert_eq!(result.value(0, "missing"), None);
+
+        assert_eq!(result.into_iter().count(), 2);
+        assert_eq!(ResultSet::modified(3).rows(), &[] as &[Row]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_result_set() -> Result<()> {
+        let column = |name: &str, data_type, nullable: bool| Column {
+            name: name.to_string(),
+            data_type,
+            nullable,
+            default: None,
+            primary_key: name == "id",
+            auto_increment: false,
+        };
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, false),
+                column("name", DataType::String, false),
+                column("score", DataType::Float, true),
+            ],
+        )?;
+
+        // 列的元数据来自表的定义，即使所有行中的值都是 NULL 或没有行
+        let result = table.result_set(vec![
+            vec![
+                Value::Integer(1),
+                Value::String("alice".to_string()),
+                Value::Null,
+            ],
+            vec![
+                Value::Integer(2),
+                Value::String("bob".to_string()),
+                Value::Null,
+            ],
+        ]);
+        assert_eq!(
+            result.columns()[2],
+            ColumnMeta {
+                name: "score".to_string(),
+                data_type: Some(DataType::Float),
+                nullable: true,
+            }
+        );
+        assert_eq!(
+            result.value(1, "name"),
+            Some(&Value::String("bob".to_string()))
+        );
+        assert_eq!(result.value(0, "score"), Some(&Value::Null));
+        assert_eq!(result.get::<i64>(0, "id")?, 1);
+        assert_eq!(result.value(0, "missing"), None);
+        assert_eq!(table.result_set(Vec::new()).columns().len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_json() -> Result<()> {
+        let result = ResultSet::query(
+            ["n", "b", "i", "f", "s", "j", "x"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            vec![
+                vec![
+                    Value::Null,
+                    Value::Boolean(true),
+                    Value::Integer(i64::MIN),
+                    Value::Float(0.1),
+                    Value::String("say \"hi\"".to_string()),
+                    Value::Json(serde_json::json!({"k": [1, null]})),
+                    Value::Float(f64::NAN),
+                ],
+                vec![
+                    Value::Null,
+                    Value::Boolean(false),
+                    Value::Integer(-1),
+                    Value::Float(-2.5),
+                    Value::String(String::new()),
+                    Value::Json(serde_json::json!("text")),
+                    Value::Float(f64::NEG_INFINITY),
+                ],
+            ],
+        );
+        let to_json = |options| -> Result<serde_json::Value> {
+            Ok(serde_json::from_str(&result.to_json_string(options)?)?)
+        };
+
+        // 每个值转换为 JSON 原生的类型，NaN 和无穷大默认为 null
+        assert_eq!(
+            to_json(JsonOptions::default())?,
+            serde_json::json!([
+                {"n": null, "b": true, "i": i64::MIN, "f": 0.1, "s": "say \"hi\"", "j": {"k": [1, null]}, "x": null},
+                {"n": null, "b": false, "i": -1, "f": -2.5, "s": "", "j": "text", "x": null},
+            ])
+        );
+        assert_eq!(
+            to_json(JsonOptions {
+                layout: JsonLayout::Compact,
+                non_finite: NonFiniteFloat::String,
+            })?,
+            serde_json::json!({
+                "columns": ["n", "b", "i", "f", "s", "j", "x"],
+                "rows": [
+                    [null, true, i64::MIN, 0.1, "say \"hi\"", {"k": [1, null]}, "NaN"],
+                    [null, false, -1, -2.5, "", "text", "-Infinity"],
+                ],
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(
+                ResultSet::query(
+                    vec!["x".to_string()],
+                    vec![vec![Value::Float(f64::INFINITY)]]
+                )
+                .to_json(JsonOptions {
+                    non_finite: NonFiniteFloat::String,
+                    ..Default::default()
+                })
+            )?,
+            serde_json::json!([{"x": "Infinity"}])
+        );
+
+        // 其他结果使用本身的序列化格式
+        assert_eq!(
+            ResultSet::modified(3).to_json_string(JsonOptions::default())?,
+            r#"{"Modified":{"count":3,"last_insert_ids":[]}}"#
+        );
+        assert_eq!(
+            ResultSet::Begin.to_json_string(JsonOptions::default())?,
+            r#""Begin""#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_json_column_collisions() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        session.execute("CREATE TABLE a (id INT PRIMARY KEY, name STRING);")?;
+        session.execute("CREATE TABLE b (id INT PRIMARY KEY, a_id INT, name STRING);")?;
+        session.execute("INSERT INTO a VALUES (1, 'x');")?;
+        session.execute("INSERT INTO b VALUES (10, 1, 'y');")?;
+
+        // 连接后两个表中同名的列加上后缀，紧凑格式保留原本的列名
+        let result = session.execute("SELECT * FROM a JOIN b ON a.id = b.a_id;")?;
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(
+                &result.to_json_string(JsonOptions::default())?
+            )?,
+            serde_json::json!([{"id": 1, "name": "x", "id_2": 10, "a_id": 1, "name_2": "y"}])
+        );
+        let compact = JsonOptions {
+            layout: JsonLayout::Compact,
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&result.to_json_string(compact)?)?["columns"],
+            serde_json::json!(["id", "name", "id", "a_id", "name"])
+        );
+
+        // 加上后缀的列名不会和其他列原本的列名相同
+        let result =
+            session.execute("SELECT a.id, b.id, a.name AS id_2 FROM a JOIN b ON a.id = b.a_id;")?;
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(
+                &result.to_json_string(JsonOptions::default())?
+            )?,
+            serde_json::json!([{"id": 1, "id_3": 10, "id_2": "x"}])
+        );
+
+        Ok(())
+    }
+}
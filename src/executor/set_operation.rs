@@ -0,0 +1,209 @@
+use std::collections::{HashMap, HashSet};
+
+use super::Rows;
+use crate::{
+    parser::ast::SetOperator,
+    schema::{DataType, Row, Value},
+    Result,
+};
+
+/// 集合运算
+///
+/// 两侧的行先按照统一后的列类型 `types` 转换，类型为 `Float` 的列中的整数转为浮点数，
+/// 之后按照整行比较，NULL 和 NULL 视为相同。`all` 为真时按照多重集合计算：
+///
+/// - `UNION ALL` 依次输出两侧的所有行，`UNION` 输出两侧去重后的行；
+/// - `INTERSECT ALL` 中在左侧出现 m 次、右侧出现 n 次的行输出 min(m, n) 次，`INTERSECT` 输出一次；
+/// - `EXCEPT ALL` 中的行输出 max(m - n, 0) 次，`EXCEPT` 输出只在左侧出现的行，每行一次。
+///
+/// 除 `UNION ALL` 外右侧的行全部读入内存，左侧的行逐行读取，输出的行保持在左侧中的顺序。
+pub fn set_operation<'a>(
+    left_rows: Rows<'a>,
+    right_rows: Rows<'a>,
+    operator: SetOperator,
+    all: bool,
+    types: Vec<Option<DataType>>,
+) -> Result<Rows<'a>> {
+    let coerce = move |row: Result<Row>| -> Result<Row> {
+        Ok(row?
+            .into_iter()
+            .zip(&types)
+            .map(|(value, data_type)| match (value, data_type) {
+                (Value::Integer(i), Some(DataType::Float)) => Value::Float(i as f64),
+                (value, _) => value,
+            })
+            .collect())
+    };
+    let left_rows = left_rows.map(coerce.clone());
+    let right_rows = right_rows.map(coerce);
+
+    if let SetOperator::Union = operator {
+        let rows = left_rows.chain(right_rows);
+        if all {
+            return Ok(Box::new(rows));
+        }
+        let mut seen = HashSet::new();
+        return Ok(Box::new(rows.filter(move |row| match row {
+            Ok(row) => seen.insert(row.clone()),
+            Err(_) => true,
+        })));
+    }
+
+    // 右侧每一行出现的次数
+    let mut counts = HashMap::<Row, usize>::new();
+    for row in right_rows {
+        *counts.entry(row?).or_default() += 1;
+    }
+    // 不带 ALL 时已经输出的行
+    let mut seen = HashSet::new();
+    Ok(Box::new(left_rows.filter(move |row| {
+        let Ok(row) = row else {
+            return true;
+        };
+        let count = counts.get_mut(row);
+        let matched = count.as_ref().is_some_and(|count| **count > 0);
+        let keep = match operator {
+            SetOperator::Intersect => matched,
+            _ => !matched,
+        };
+        match all {
+            true => {
+                // 每个匹配的右侧行只抵消左侧的一行
+                if let Some(count) = count.filter(|count| **count > 0) {
+                    *count -= 1;
+                }
+                keep
+            }
+            false => keep && seen.insert(row.clone()),
+        }
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        executor::Executor,
+        parser::Parser,
+        schema::{Row, Value},
+        storage::MemoryStorage,
+        Engine, Error, Result,
+    };
+
+    #[test]
+    fn test_set_operation() -> Result<()> {
+        let engine = Engine::new(MemoryStorage::new());
+        let executor = Executor::from_engine(&engine)?;
+        let execute = |sql: &str| executor.execute(Parser::new(sql).parse()?);
+        let query = |sql: &str| -> Result<Vec<Row>> { Ok(execute(sql)?.into_iter().collect()) };
+        let sorted = |sql: &str| -> Result<Vec<Row>> {
+            let mut rows = query(sql)?;
+            rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            Ok(rows)
+        };
+        let int = Value::Integer;
+        for sql in [
+            "CREATE TABLE t (id INT PRIMARY KEY, a INT NULL, b STRING NULL, f FLOAT);",
+            "INSERT INTO t VALUES (1, 1, 'x', 1.0), (2, 1, 'x', 2.5), (3, 2, 'y', 3.0), (4, NULL, NULL, 4.0), (5, NULL, NULL, 5.0), (6, 3, 'z', 1.0), (7, 2, 'y', 2.0);",
+        ] {
+            execute(sql)?;
+        }
+
+        // 不带 ALL 的集合运算和等价的谓词加 DISTINCT 结果相同，NULL 和 NULL 视为相同
+        for (set_sql, manual_sql) in [
+            (
+                "SELECT a, b FROM t WHERE id <= 3 UNION SELECT a, b FROM t WHERE id >= 3;",
+                "SELECT DISTINCT a, b FROM t WHERE id <= 3 OR id >= 3;",
+            ),
+            (
+                "SELECT a, b FROM t WHERE id <= 4 INTERSECT SELECT a, b FROM t WHERE id >= 3;",
+                "SELECT DISTINCT a, b FROM t WHERE a = 2 OR id = 4;",
+            ),
+            (
+                "SELECT a, b FROM t EXCEPT SELECT a, b FROM t WHERE id >= 3;",
+                "SELECT DISTINCT a, b FROM t WHERE a = 1;",
+            ),
+        ] {
+            assert_eq!(sorted(set_sql)?, sorted(manual_sql)?, "{set_sql}");
+        }
+
+        // 带 ALL 时按照多重集合计算
+        assert_eq!(
+            query("SELECT a FROM t UNION ALL SELECT a FROM t;")?.len(),
+            14
+        );
+        assert_eq!(
+            sorted("SELECT a FROM t INTERSECT ALL SELECT a FROM t WHERE (id <= 4 OR id = 6);")?,
+            sorted("SELECT a FROM t WHERE (id <= 4 OR id = 6);")?
+        );
+        assert_eq!(
+            sorted(
+                "SELECT a FROM t EXCEPT ALL SELECT a FROM t WHERE (id = 1 OR id = 3 OR id = 4);"
+            )?,
+            sorted("SELECT a FROM t WHERE (id = 2 OR id >= 5);")?
+        );
+        assert_eq!(
+            sorted("SELECT a FROM t WHERE id <= 2 EXCEPT ALL SELECT a FROM t WHERE id = 1 UNION ALL SELECT a FROM t WHERE id = 1;")?,
+            vec![vec![int(1)], vec![int(1)]]
+        );
+
+        // INTERSECT 的优先级高于 UNION 和 EXCEPT
+        assert_eq!(
+            sorted("SELECT a FROM t WHERE id = 6 UNION SELECT a FROM t WHERE id = 1 INTERSECT SELECT a FROM t WHERE id = 3;")?,
+            vec![vec![int(3)]]
+        );
+
+        // 整数和浮点数统一为浮点数，列名取左侧的列名，ORDER BY 和 LIMIT 作用于运算的结果
+        let result = execute(
+            "SELECT id, a AS n FROM t WHERE id <= 2 UNION SELECT f, a FROM t WHERE id >= 6 ORDER BY 1 DESC, n LIMIT 2 OFFSET 1;",
+        )?;
+        assert_eq!(
+            result
+                .columns()
+                .iter()
+                .map(|meta| meta.name.as_str())
+                .collect::<Vec<_>>(),
+            ["id", "n"]
+        );
+        assert_eq!(
+            result.rows(),
+            [
+                vec![Value::Float(2.0), int(2)],
+                vec![Value::Float(1.0), int(1)],
+            ]
+        );
+        assert_eq!(
+            query(
+                "SELECT * FROM t WHERE id = 1 UNION SELECT * FROM t WHERE id = 2 ORDER BY id DESC;"
+            )?
+            .iter()
+            .map(|row| row[0].clone())
+            .collect::<Vec<_>>(),
+            [int(2), int(1)]
+        );
+        assert_eq!(
+            query("SELECT id FROM t UNION ALL SELECT f FROM t;")?
+                .iter()
+                .filter(|row| row[0] == Value::Float(1.0))
+                .count(),
+            3
+        );
+
+        // 列数不同或者类型不兼容时在计划阶段报错
+        assert!(query("SELECT a, b FROM t UNION SELECT a FROM t;").is_err());
+        assert!(matches!(
+            query("SELECT a FROM t UNION SELECT b FROM t;"),
+            Err(Error::Execution(crate::ExecutionError::TypeMismatch(_)))
+        ));
+        assert!(query("SELECT a FROM t UNION SELECT a FROM t ORDER BY 2;").is_err());
+
+        // 集合运算可以作为子查询，也可以被 EXPLAIN
+        assert_eq!(
+            query("SELECT id FROM t WHERE a IN (SELECT a FROM t WHERE id = 1 UNION SELECT a FROM t WHERE id = 6) ORDER BY id;")?,
+            vec![vec![int(1)], vec![int(2)], vec![int(6)]]
+        );
+        let plan = execute("EXPLAIN SELECT a FROM t INTERSECT ALL SELECT a FROM t;")?;
+        assert!(format!("{plan:?}").contains("SetOperation: Intersect All"));
+
+        Ok(())
+    }
+}
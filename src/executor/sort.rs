@@ -0,0 +1,280 @@
+use std::collections::BinaryHeap;
+
+use crate::{
+    executor::expression::evaluate,
+    parser::ast::{NullsOrder, OrderBy, Ordering},
+    schema::{Row, Value},
+    Result,
+};
+
+/// 对行进行排序
+///
+/// 每一行的排序项只计算一次，之后按照排序项依次比较，排序是稳定的，排序项相等的行保持输入的顺序。
+/// `limit` 不为 `None` 时只需要前 `limit` 行，使用大小为 `limit` 的堆代替完整的排序。
+pub fn sort(
+    columns: &[String],
+    rows: Vec<Row>,
+    ordering: &[OrderBy],
+    limit: Option<usize>,
+) -> Result<Vec<Row>> {
+    // 每个排序项的排序方式，以及 NULL 是否排在最前
+    let orders = ordering
+        .iter()
+        .map(|(_, ordering, nulls_order)| {
+            let nulls_first = match nulls_order {
+                Some(NullsOrder::First) => true,
+                Some(NullsOrder::Last) => false,
+                // 未指定时 NULL 视为最小值
+                None => *ordering == Ordering::Asc,
+            };
+            (*ordering, nulls_first)
+        })
+        .collect::<Vec<_>>();
+
+    // 计算每一行的排序项
+    let mut entries = Vec::with_capacity(rows.len());
+    for (index, row) in rows.into_iter().enumerate() {
+        let keys = ordering
+            .iter()
+            .map(|(expr, _, _)| evaluate(expr, columns, &row))
+            .collect::<Result<Vec<_>>>()?;
+        entries.push(SortEntry {
+            keys,
+            index,
+            row,
+            orders: &orders,
+        });
+    }
+
+    let entries = match limit {
+        // 使用大顶堆保留最小的 limit 行，堆中的行数超过 limit 时弹出最大的行
+        Some(limit) if limit < entries.len() => {
+            let mut heap = BinaryHeap::with_capacity(limit + 1);
+            for entry in entries {
+                heap.push(entry);
+                if heap.len() > limit {
+                    heap.pop();
+                }
+            }
+            heap.into_sorted_vec()
+        }
+        // `sort` 是稳定排序，排序项相等时无需再比较下标
+        _ => {
+            entries.sort_by(|lhs, rhs| lhs.cmp_keys(rhs));
+            entries
+        }
+    };
+
+    Ok(entries.into_iter().map(|entry| entry.row).collect())
+}
+
+/// 排序中的一行，包括计算好的排序项和行在输入中的下标
+struct SortEntry<'a> {
+    keys: Vec<Value>,
+    index: usize,
+    row: Row,
+    orders: &'a [(Ordering, bool)],
+}
+
+impl SortEntry<'_> {
+    /// 按照排序项依次比较
+    fn cmp_keys(&self, other: &Self) -> std::cmp::Ordering {
+        for ((lhs, rhs), (ordering, nulls_first)) in
+            self.keys.iter().zip(&other.keys).zip(self.orders)
+        {
+            let ord = match (lhs, rhs) {
+                (Value::Null, Value::Null) => std::cmp::Ordering::Equal,
+                (Value::Null, _) if *nulls_first => std::cmp::Ordering::Less,
+                (Value::Null, _) => std::cmp::Ordering::Greater,
+                (_, Value::Null) if *nulls_first => std::cmp::Ordering::Greater,
+                (_, Value::Null) => std::cmp::Ordering::Less,
+                (lhs, rhs) if *ordering == Ordering::Asc => lhs.total_cmp(rhs),
+                (lhs, rhs) => lhs.total_cmp(rhs).reverse(),
+            };
+            if ord != std::cmp::Ordering::Equal {
+                return ord;
+            }
+        }
+        std::cmp::Ordering::Equal
+    }
+}
+
+// 堆中的比较在排序项相等时按照下标比较，以保证排序的稳定性
+impl PartialEq for SortEntry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for SortEntry<'_> {}
+
+impl PartialOrd for SortEntry<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SortEntry<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.cmp_keys(other).then(self.index.cmp(&other.index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ast::{Constant, Expression, Operation};
+
+    fn field(name: &str) -> Expression {
+        Expression::Field(name.to_string())
+    }
+
+    /// 排序后返回每一行的第一列（id）
+    fn sorted_ids(
+        columns: &[String],
+        rows: &[Row],
+        ordering: &[OrderBy],
+        limit: Option<usize>,
+    ) -> Result<Vec<i64>> {
+        sort(columns, rows.to_vec(), ordering, limit)?
+            .iter()
+            .map(|row| row[0].as_i64())
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_mixed_types() -> Result<()> {
+        let columns = vec!["t.id".to_string(), "t.v".to_string()];
+        let values = [
+            Value::String("b".to_string()),
+            Value::Integer(3),
+            Value::Null,
+            Value::Float(2.5),
+            Value::Boolean(true),
+            Value::Float(f64::NAN),
+            Value::Integer(2),
+            Value::Null,
+            Value::String("a".to_string()),
+            Value::Float(2.0),
+            Value::Boolean(false),
+        ];
+        let rows = values
+            .into_iter()
+            .enumerate()
+            .map(|(id, v)| vec![Value::Integer(id as i64), v])
+            .collect::<Vec<_>>();
+
+        // NULL < 布尔 < 数值 < 字符串，数值相等时整数在前，NaN 大于其他数值，NULL 之间保持输入顺序
+        let cases = [
+            (Ordering::Asc, None, vec![2, 7, 10, 4, 6, 9, 3, 1, 5, 8, 0]),
+            (Ordering::Desc, None, vec![0, 8, 5, 1, 3, 9, 6, 4, 10, 2, 7]),
+            (
+                Ordering::Asc,
+                Some(NullsOrder::Last),
+                vec![10, 4, 6, 9, 3, 1, 5, 8, 0, 2, 7],
+            ),
+            (
+                Ordering::Desc,
+                Some(NullsOrder::First),
+                vec![2, 7, 0, 8, 5, 1, 3, 9, 6, 4, 10],
+            ),
+        ];
+        for (ordering, nulls_order, expected) in cases {
+            let ordering = [(field("v"), ordering, nulls_order)];
+            assert_eq!(sorted_ids(&columns, &rows, &ordering, None)?, expected);
+            for limit in 0..=rows.len() + 1 {
+                assert_eq!(
+                    sorted_ids(&columns, &rows, &ordering, Some(limit))?,
+                    expected[..limit.min(expected.len())]
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_multiple_keys() -> Result<()> {
+        let columns = vec!["t.id".to_string(), "t.a".to_string(), "t.b".to_string()];
+        let rows = [
+            (0, 1, 5),
+            (1, 2, 1),
+            (2, 1, 3),
+            (3, 2, 1),
+            (4, 1, 5),
+            (5, 2, 4),
+        ]
+        .into_iter()
+        .map(|(id, a, b)| vec![Value::Integer(id), Value::Integer(a), Value::Integer(b)])
+        .collect::<Vec<_>>();
+
+        // 排序项可以是表达式，排序项都相等的行保持输入顺序
+        let ordering = [
+            (field("a"), Ordering::Desc, None),
+            (
+                Expression::Operation(Operation::Subtract(
+                    Box::new(Expression::Constant(Constant::Integer(0))),
+                    Box::new(field("t.b")),
+                )),
+                Ordering::Asc,
+                None,
+            ),
+        ];
+        assert_eq!(
+            sorted_ids(&columns, &rows, &ordering, None)?,
+            vec![5, 1, 3, 0, 4, 2]
+        );
+        assert_eq!(
+            sorted_ids(&columns, &rows, &ordering, Some(3))?,
+            vec![5, 1, 3]
+        );
+
+        // 排序项计算失败时返回错误
+        let ordering = [(field("c"), Ordering::Asc, None)];
+        assert!(sort(&columns, rows, &ordering, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_top_k() -> Result<()> {
+        // 使用线性同余生成器构造可复现的随机数据，包含大量重复值和 NULL
+        let mut seed = 11u64;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) % n
+        };
+        let columns = vec!["t.id".to_string(), "t.a".to_string(), "t.b".to_string()];
+        let mut value = |n: u64| match next(n + 1) {
+            0 => Value::Null,
+            v if v % 2 == 0 => Value::Integer(v as i64),
+            v => Value::Float(v as f64 - 0.5),
+        };
+        let rows = (0..200)
+            .map(|id| vec![Value::Integer(id), value(5), value(20)])
+            .collect::<Vec<_>>();
+
+        let orderings = [
+            vec![(field("a"), Ordering::Asc, None)],
+            vec![(field("a"), Ordering::Desc, Some(NullsOrder::First))],
+            vec![
+                (field("a"), Ordering::Asc, Some(NullsOrder::Last)),
+                (field("b"), Ordering::Desc, None),
+            ],
+        ];
+        for ordering in orderings {
+            let expected = sorted_ids(&columns, &rows, &ordering, None)?;
+            for limit in [0, 1, 2, 7, 50, 199, 200, 300] {
+                assert_eq!(
+                    sorted_ids(&columns, &rows, &ordering, Some(limit))?,
+                    expected[..limit.min(expected.len())],
+                    "{:?} limit {}",
+                    ordering,
+                    limit
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
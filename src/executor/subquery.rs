@@ -0,0 +1,349 @@
+use std::sync::Arc;
+
+use crate::{
+    error::{
+        ExecutionError::{SubqueryColumns, SubqueryRows},
+        Result,
+        SchemaError::FunctionNotFound,
+    },
+    executor::Executor,
+    parser::ast::{Expression, InsertSource, SelectFrom, Statement, ValueSet},
+    planner::Planner,
+    schema::Value,
+    storage::Storage,
+};
+
+impl<S: Storage> Executor<S> {
+    /// 执行语句中的子查询，替换为子查询的结果
+    ///
+    /// 每个子查询在计划之前只执行一次，结果被外层语句的所有行共享：标量子查询替换为它的值，
+    /// `IN` 子查询物化为集合。子查询中嵌套的子查询在执行子查询时递归处理，集合运算的每一侧分别处理。
+    /// 只支持不相关子查询，连接条件中的子查询不会被执行，在计算时报错。
+    ///
+    /// 同时将语句中的标量函数调用替换为注册表中的函数，包括连接条件中的调用。
+    pub(super) fn materialize_subqueries(&self, stmt: Statement) -> Result<Statement> {
+        match stmt {
+            Statement::Select {
+                columns,
+                distinct,
+                from,
+                filter,
+                group_by,
+                having,
+                ordering,
+                limit,
+                offset,
+            } => {
+                let from = self.resolve_join_functions(from)?;
+                let materialize = |expr| self.materialize(expr, Some(&from));
+                Ok(Statement::Select {
+                    columns: columns
+                        .into_iter()
+                        .map(|(expr, alias)| {
+                            // 子查询被替换为它的结果，没有别名时以原来的表达式作为列名，而不是结果的值
+                            let alias = match alias {
+                                None if expr.has_subquery() => Some(expr.to_string()),
+                                alias => alias,
+                            };
+                            Ok((materialize(expr)?, alias))
+                        })
+                        .collect::<Result<_>>()?,
+                    distinct,
+                    filter: filter.map(materialize).transpose()?,
+                    group_by: group_by
+                        .into_iter()
+                        .map(materialize)
+                        .collect::<Result<_>>()?,
+                    having: having.map(materialize).transpose()?,
+                    ordering: ordering
+                        .into_iter()
+                        .map(|(expr, order, nulls)| Ok((materialize(expr)?, order, nulls)))
+                        .collect::<Result<_>>()?,
+                    limit: limit.map(materialize).transpose()?,
+                    offset: offset.map(materialize).transpose()?,
+                    from,
+                })
+            }
+            Statement::Insert {
+                table_name,
+                columns,
+                source,
+            } => Ok(Statement::Insert {
+                table_name,
+                columns,
+                source: match source {
+                    InsertSource::Values(values) => InsertSource::Values(
+                        values
+                            .into_iter()
+                            .map(|row| {
+                                row.into_iter()
+                                    .map(|expr| self.materialize(expr, None))
+                                    .collect()
+                            })
+                            .collect::<Result<_>>()?,
+                    ),
+                    InsertSource::Query(query) => {
+                        InsertSource::Query(Box::new(self.materialize_subqueries(*query)?))
+                    }
+                },
+            }),
+            Statement::Update {
+                table_name,
+                columns,
+                filter,
+            } => {
+                let from = SelectFrom::Table {
+                    name: table_name.clone(),
+                };
+                let materialize = |expr| self.materialize(expr, Some(&from));
+                Ok(Statement::Update {
+                    columns: columns
+                        .into_iter()
+                        .map(|(col_name, expr)| Ok((col_name, materialize(expr)?)))
+                        .collect::<Result<_>>()?,
+                    filter: filter.map(materialize).transpose()?,
+                    table_name,
+                })
+            }
+            Statement::Delete { table_name, filter } => {
+                let from = SelectFrom::Table {
+                    name: table_name.clone(),
+                };
+                Ok(Statement::Delete {
+                    filter: filter
+                        .map(|expr| self.materialize(expr, Some(&from)))
+                        .transpose()?,
+                    table_name,
+                })
+            }
+            Statement::SetOperation {
+                operator,
+                all,
+                left,
+                right,
+                ordering,
+                limit,
+                offset,
+            } => Ok(Statement::SetOperation {
+                operator,
+                all,
+                left: Box::new(self.materialize_subqueries(*left)?),
+                right: Box::new(self.materialize_subqueries(*right)?),
+                ordering,
+                limit,
+                offset,
+            }),
+            Statement::Explain { statement, analyze } => Ok(Statement::Explain {
+                statement: Box::new(self.materialize_subqueries(*statement)?),
+                analyze,
+            }),
+            stmt => Ok(stmt),
+        }
+    }
+
+    /// 替换表达式中的函数调用并执行其中的子查询，`outer` 为外层语句的数据来源
+    fn materialize(&self, expr: Expression, outer: Option<&SelectFrom>) -> Result<Expression> {
+        let expr = self.resolve_functions(expr)?;
+        if !expr.has_subquery() {
+            return Ok(expr);
+        }
+        expr.transform(&mut |expr| match expr {
+            Expression::Subquery(subquery) => {
+                let mut values = self.subquery_values(*subquery, outer)?;
+                if values.len() > 1 {
+                    return Err(SubqueryRows(values.len()).into());
+                }
+                Ok(Expression::from(values.pop().unwrap_or(Value::Null)))
+            }
+            Expression::InSubquery(expr, subquery) => {
+                let mut set = ValueSet::default();
+                for value in self.subquery_values(*subquery, outer)? {
+                    match value {
+                        Value::Null => set.has_null = true,
+                        value => {
+                            set.data_type = set.data_type.or(value.data_type());
+                            set.values.insert(value);
+                        }
+                    }
+                }
+                Ok(Expression::InSet(expr, Arc::new(set)))
+            }
+            expr => Ok(expr),
+        })
+    }
+
+    /// 将表达式中的函数调用替换为注册表中的函数，函数不存在或者参数的个数不符时返回错误
+    fn resolve_functions(&self, expr: Expression) -> Result<Expression> {
+        expr.transform(&mut |expr| match expr {
+            Expression::Call(name, args) => {
+                let function = self.functions.get(&name)?.ok_or(FunctionNotFound(name))?;
+                function.check_arity(args.len())?;
+                Ok(Expression::Scalar(function, args))
+            }
+            expr => Ok(expr),
+        })
+    }
+
+    /// 替换连接条件中的函数调用
+    fn resolve_join_functions(&self, from: SelectFrom) -> Result<SelectFrom> {
+        match from {
+            SelectFrom::Table { .. } => Ok(from),
+            SelectFrom::Join {
+                left,
+                right,
+                join_type,
+                predicate,
+            } => Ok(SelectFrom::Join {
+                left: Box::new(self.resolve_join_functions(*left)?),
+                right: Box::new(self.resolve_join_functions(*right)?),
+                join_type,
+                predicate: predicate
+                    .map(|expr| self.resolve_functions(expr))
+                    .transpose()?,
+            }),
+        }
+    }
+
+    /// 执行子查询，返回唯一一列的所有值，子查询引用外层语句的列时返回错误
+    fn subquery_values(
+        &self,
+        subquery: Statement,
+        outer: Option<&SelectFrom>,
+    ) -> Result<Vec<Value>> {
+        let planner = Planner::new(&self.transaction);
+        let outer_columns = outer
+            .map(|from| planner.source_columns(from.clone()))
+            .transpose()?
+            .unwrap_or_default();
+        planner.check_uncorrelated(&subquery, &outer_columns)?;
+
+        let result = self.execute(subquery)?;
+        if result.columns().len() != 1 {
+            return Err(SubqueryColumns(result.columns().len()).into());
+        }
+        Ok(result
+            .into_iter()
+            .map(|mut row| row.swap_remove(0))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::ResultSet, parser::Parser, schema::Row, storage::MemoryStorage, Engine, Error,
+        ExecutionError, PlanError,
+    };
+
+    #[test]
+    fn test_subquery() -> Result<()> {
+        let engine = Engine::new(MemoryStorage::new());
+        let executor = Executor::from_engine(&engine)?;
+        let execute = |sql: &str| executor.execute(Parser::new(sql).parse()?);
+        let query = |sql: &str| -> Result<Vec<Row>> { Ok(execute(sql)?.into_iter().collect()) };
+        let int = Value::Integer;
+        for sql in [
+            "CREATE TABLE items (id INT PRIMARY KEY, kind STRING, price INT);",
+            "CREATE TABLE orders (id INT PRIMARY KEY, item INT NULL);",
+            "INSERT INTO items VALUES (1, 'a', 10), (2, 'a', 20), (3, 'b', 30), (4, 'b', 40), (5, 'c', 50);",
+            "INSERT INTO orders VALUES (1, 1), (2, 3), (3, 3);",
+        ] {
+            execute(sql)?;
+        }
+
+        // WHERE 中的标量子查询和 IN 子查询
+        assert_eq!(
+            query(
+                "SELECT id FROM items WHERE price > (SELECT AVG(price) FROM items) ORDER BY id;"
+            )?,
+            vec![vec![int(4)], vec![int(5)]]
+        );
+        assert_eq!(
+            query("SELECT id FROM items WHERE id IN (SELECT item FROM orders) ORDER BY id;")?,
+            vec![vec![int(1)], vec![int(3)]]
+        );
+
+        // 选择列中的子查询，没有行的标量子查询为 NULL
+        assert_eq!(
+            query("SELECT id, (SELECT MAX(price) FROM items) AS top, (SELECT price FROM items WHERE id = 9) AS missing, id IN (SELECT item FROM orders) AS ordered FROM items WHERE id <= 2 ORDER BY id;")?,
+            vec![
+                vec![int(1), int(50), Value::Null, Value::Boolean(true)],
+                vec![int(2), int(50), Value::Null, Value::Boolean(false)],
+            ]
+        );
+
+        // 没有别名的子查询以原来的表达式作为列名
+        let ResultSet::Query { columns, .. } = execute(
+            "SELECT (SELECT MAX(price) FROM items), id, (SELECT MIN(price) FROM items) + 1, id IN (SELECT item FROM orders) FROM items;",
+        )?
+        else {
+            unreachable!()
+        };
+        assert_eq!(
+            columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+            vec![
+                "(SELECT ...)",
+                "id",
+                "(SELECT ...) + 1",
+                "id IN (SELECT ...)"
+            ]
+        );
+
+        // HAVING 中的子查询
+        assert_eq!(
+            query("SELECT kind FROM items GROUP BY kind HAVING SUM(price) > (SELECT price FROM items WHERE id = 5) ORDER BY kind;")?,
+            vec![vec![Value::String("b".to_string())]]
+        );
+        assert_eq!(
+            query("SELECT kind, COUNT(*) AS n FROM items GROUP BY kind HAVING COUNT(*) IN (SELECT id FROM orders WHERE id = 2) ORDER BY kind;")?.len(),
+            2
+        );
+
+        // NOT IN：结果中有 NULL 时没有行满足条件，空结果时所有行都满足
+        assert_eq!(
+            query("SELECT id FROM items WHERE id NOT IN (SELECT item FROM orders) ORDER BY id;")?,
+            vec![vec![int(2)], vec![int(4)], vec![int(5)]]
+        );
+        query("INSERT INTO orders VALUES (4, NULL);")?;
+        assert_eq!(
+            query("SELECT id FROM items WHERE id NOT IN (SELECT item FROM orders);")?,
+            Vec::<Row>::new()
+        );
+        assert_eq!(
+            query("SELECT COUNT(*) FROM items WHERE id NOT IN (SELECT item FROM orders WHERE id > 9);")?,
+            vec![vec![int(5)]]
+        );
+
+        // 子查询在计划之前执行，结果可以用于选择访问路径
+        let ResultSet::Explain(plan) =
+            execute("EXPLAIN SELECT * FROM items WHERE id = (SELECT MAX(item) FROM orders);")?
+        else {
+            unreachable!()
+        };
+        assert!(plan.contains("KeyLookup: items (id = 3)"), "{plan}");
+
+        // 标量子查询返回多行或多列、IN 两侧类型不可比较、相关子查询都报错
+        assert!(matches!(
+            query("SELECT id FROM items WHERE price > (SELECT price FROM items);"),
+            Err(Error::Execution(SubqueryRows(_)))
+        ));
+        assert_eq!(
+            query("SELECT id FROM items WHERE price > (SELECT id, price FROM items WHERE id = 1);"),
+            Err(SubqueryColumns(2).into())
+        );
+        assert!(matches!(
+            query("SELECT id FROM items WHERE kind IN (SELECT item FROM orders);"),
+            Err(Error::Execution(ExecutionError::TypeMismatch(_)))
+        ));
+        let correlated = query(
+            "SELECT id FROM items WHERE price > (SELECT COUNT(*) FROM orders WHERE item = items.id);",
+        );
+        assert!(
+            matches!(&correlated, Err(Error::Plan(PlanError::Unsupported(_)))),
+            "{correlated:?}"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::{
+        ExecutionError::{DuplicateKey, RowLength},
+        Result,
+        SchemaError::{ColumnNotFound, IndexNotFound, TableNotFound},
+    },
+    executor::{expression::evaluate, Executor},
+    parser::ast::Expression,
+    schema::{Row, Table, Value},
+    storage::Storage,
+};
+
+/// 插入冲突的判断依据
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictTarget {
+    /// 主键值相同
+    PrimaryKey,
+    /// 唯一索引的索引列值相同，参数为索引名
+    UniqueIndex(String),
+}
+
+/// 插入的行和已有的行冲突时的处理方式
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictAction {
+    /// 保留已有的行，对应 `ON CONFLICT DO NOTHING`
+    Nothing,
+    /// 用插入的行替换已有的行，对应 `REPLACE`
+    Replace,
+    /// 按照赋值表达式更新已有的行，对应 `ON CONFLICT DO UPDATE SET`
+    ///
+    /// 表达式中的字段默认引用已有的行，`excluded.col_name` 引用插入的行。
+    Update(HashMap<String, Expression>),
+}
+
+/// 插入或更新的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertOutcome {
+    /// 没有冲突，插入了新行
+    Inserted,
+    /// 存在冲突，已有的行被替换或更新
+    Updated,
+    /// 存在冲突，已有的行没有变化
+    Skipped,
+}
+
+/// `ConflictAction::Update` 中引用插入的行的伪表名
+const EXCLUDED: &str = "excluded";
+
+impl<S: Storage> Executor<S> {
+    /// 插入一行，和已有的行冲突时按照 `action` 处理
+    ///
+    /// 在当前事务的快照中按照 `target` 查找冲突的行，之后写入的行如果和冲突行以外的行产生了
+    /// 主键或唯一索引上的冲突，返回错误且不写入任何数据。
+    pub fn upsert(
+        &self,
+        table_name: &str,
+        row: Row,
+        target: &ConflictTarget,
+        action: &ConflictAction,
+    ) -> Result<UpsertOutcome> {
+        let table = self
+            .transaction
+            .get_table(table_name)?
+            .ok_or(TableNotFound(table_name.to_string()))?;
+        if row.len() != table.columns.len() {
+            return Err(RowLength {
+                table: table_name.to_string(),
+                expected: table.columns.len(),
+                found: row.len(),
+            }
+            .into());
+        }
+        let mut row = row
+            .into_iter()
+            .zip(&table.columns)
+            .map(|(value, column)| value.coerce_to(column.data_type))
+            .collect::<Result<Row>>()?;
+        // 自增列为 NULL 时分配新的 ID，这样的行不会和已有的行在主键上冲突
+        self.transaction.fill_auto_increment(&table, &mut row)?;
+        table.validate_row(&row)?;
+
+        let Some(existing) = self.find_conflict(&table, &row, target)? else {
+            self.check_unique(&table, &row, None)?;
+            self.transaction.create_row(table_name, &row)?;
+            self.check_references(&table, std::slice::from_ref(&row))?;
+            return Ok(UpsertOutcome::Inserted);
+        };
+
+        let new_row = match action {
+            ConflictAction::Nothing => return Ok(UpsertOutcome::Skipped),
+            ConflictAction::Replace => row,
+            ConflictAction::Update(assignments) => {
+                Self::apply_assignments(&table, &existing, &row, assignments)?
+            }
+        };
+        if new_row == existing {
+            return Ok(UpsertOutcome::Skipped);
+        }
+
+        let pk = table.get_primary_key(&existing);
+        self.check_unique(&table, &new_row, Some(pk))?;
+        self.transaction.update_row(&table, pk, &new_row)?;
+        self.check_references(&table, std::slice::from_ref(&new_row))?;
+        self.check_referenced(&table, std::slice::from_ref(pk))?;
+        Ok(UpsertOutcome::Updated)
+    }
+
+    /// 按照 `target` 查找和 `row` 冲突的已有行
+    fn find_conflict(
+        &self,
+        table: &Table,
+        row: &Row,
+        target: &ConflictTarget,
+    ) -> Result<Option<Row>> {
+        match target {
+            ConflictTarget::PrimaryKey => {
+                self.transaction.get_row(table, table.get_primary_key(row))
+            }
+            ConflictTarget::UniqueIndex(index_name) => {
+                let index = table
+                    .get_index(index_name)
+                    .filter(|index| index.unique)
+                    .ok_or(IndexNotFound {
+                        table: table.name.clone(),
+                        index: index_name.clone(),
+                    })?;
+                self.transaction.unique_lookup(table, index, row)
+            }
+        }
+    }
+
+    /// 检查 `row` 是否和其他行在主键或唯一索引上冲突，`replacing` 为被 `row` 替换的行的主键
+    fn check_unique(&self, table: &Table, row: &Row, replacing: Option<&Value>) -> Result<()> {
+        let pk = table.get_primary_key(row);
+        if Some(pk) != replacing && self.transaction.get_row(table, pk)?.is_some() {
+            return Err(DuplicateKey {
+                table: table.name.clone(),
+                key: pk.clone(),
+            }
+            .into());
+        }
+        for index in table.indexes.iter().filter(|index| index.unique) {
+            self.transaction
+                .check_unique(table, index, row, replacing.unwrap_or(pk))?;
+        }
+        Ok(())
+    }
+
+    /// 基于已有的行计算 `DO UPDATE SET` 的赋值，得到新行
+    fn apply_assignments(
+        table: &Table,
+        existing: &Row,
+        excluded: &Row,
+        assignments: &HashMap<String, Expression>,
+    ) -> Result<Row> {
+        // 已有的行和插入的行拼接在一起，分别以表名和 excluded 作为列名的前缀
+        let columns = [table.name.as_str(), EXCLUDED]
+            .iter()
+            .flat_map(|prefix| {
+                table
+                    .columns
+                    .iter()
+                    .map(move |column| format!("{prefix}.{}", column.name))
+            })
+            .collect::<Vec<_>>();
+        let row = [existing.as_slice(), excluded.as_slice()].concat();
+
+        let mut new_row = existing.clone();
+        for (col_name, expr) in assignments {
+            let col_idx = table.get_col_idx(col_name).ok_or(ColumnNotFound {
+                table: table.name.clone(),
+                column: col_name.to_string(),
+            })?;
+            // 没有限定表名的字段引用已有的行
+            let expr = expr.clone().transform(&mut |expr| match expr {
+                Expression::Field(name) if !name.contains('.') => {
+                    Ok(Expression::Field(format!("{}.{name}", table.name)))
+                }
+                expr => Ok(expr),
+            })?;
+            new_row[col_idx] =
+                evaluate(&expr, &columns, &row)?.coerce_to(table.columns[col_idx].data_type)?;
+        }
+        table.validate_row(&new_row)?;
+        Ok(new_row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::{ast::Statement, Parser},
+        storage::MemoryStorage,
+        Engine,
+    };
+
+    #[test]
+    fn test_upsert() -> Result<()> {
+        let engine = Engine::new(MemoryStorage::new());
+        let parse = |sql: &str| Parser::new(sql).parse();
+        let rows = |executor: &Executor<MemoryStorage>| -> Result<Vec<Row>> {
+            Ok(executor
+                .execute(parse("SELECT * FROM t ORDER BY id;")?)?
+                .into_iter()
+                .collect())
+        };
+        let int = Value::Integer;
+        let string = |s: &str| Value::String(s.to_string());
+        let row = |id, email: &str, visits| vec![int(id), string(email), int(visits)];
+
+        let mut executor = Executor::from_engine(&engine)?;
+        for sql in [
+            "CREATE TABLE t (id INT PRIMARY KEY, email STRING, visits INT);",
+            "CREATE UNIQUE INDEX idx_email ON t (email);",
+            "INSERT INTO t VALUES (1, 'a', 1), (2, 'b', 1);",
+        ] {
+            executor.execute(parse(sql)?)?;
+        }
+        executor.commit()?;
+
+        let by_pk = ConflictTarget::PrimaryKey;
+        let by_email = ConflictTarget::UniqueIndex("idx_email".to_string());
+        let Statement::Update { columns, .. } =
+            parse("UPDATE t SET visits = visits + excluded.visits, email = excluded.email;")?
+        else {
+            unreachable!()
+        };
+        let merge = ConflictAction::Update(columns);
+
+        let mut executor = Executor::from_engine(&engine)?;
+        // 重复执行相同的 REPLACE 和 DO NOTHING 结果不变
+        for _ in 0..3 {
+            executor.upsert("t", row(3, "c", 1), &by_pk, &ConflictAction::Replace)?;
+            executor.upsert("t", row(1, "x", 9), &by_pk, &ConflictAction::Nothing)?;
+        }
+        assert_eq!(
+            executor.upsert("t", row(3, "c", 1), &by_pk, &ConflictAction::Replace)?,
+            UpsertOutcome::Skipped
+        );
+        assert_eq!(
+            rows(&executor)?,
+            vec![row(1, "a", 1), row(2, "b", 1), row(3, "c", 1)]
+        );
+
+        // 通过唯一索引找到冲突的行，DO UPDATE 可以同时引用已有的行和插入的行
+        assert_eq!(
+            executor.upsert("t", row(9, "b", 5), &by_email, &merge)?,
+            UpsertOutcome::Updated
+        );
+        assert_eq!(
+            executor.upsert("t", row(4, "d", 5), &by_email, &merge)?,
+            UpsertOutcome::Inserted
+        );
+        assert_eq!(rows(&executor)?[1], row(2, "b", 6));
+
+        // 更新后的行和其他行在唯一索引或主键上冲突时报错，且不写入任何数据
+        let before = rows(&executor)?;
+        assert!(executor
+            .upsert("t", row(1, "c", 1), &by_pk, &merge)
+            .is_err());
+        assert!(executor
+            .upsert("t", row(2, "a", 1), &by_email, &ConflictAction::Replace)
+            .is_err());
+        assert!(executor
+            .upsert("t", row(5, "a", 1), &by_pk, &ConflictAction::Nothing)
+            .is_err());
+        assert_eq!(rows(&executor)?, before);
+
+        // 冲突目标必须是唯一索引
+        let missing = ConflictTarget::UniqueIndex("missing".to_string());
+        assert!(executor
+            .upsert("t", row(5, "e", 1), &missing, &ConflictAction::Nothing)
+            .is_err());
+
+        executor.commit()?;
+
+        // 事务回滚后插入和更新都不可见
+        let committed = rows(&executor)?;
+        let mut executor = Executor::from_engine(&engine)?;
+        executor.upsert("t", row(1, "a", 2), &by_pk, &merge)?;
+        executor.upsert("t", row(7, "g", 1), &by_pk, &merge)?;
+        assert_ne!(rows(&executor)?, committed);
+        executor.rollback()?;
+        assert_eq!(rows(&Executor::from_engine(&engine)?)?, committed);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,373 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    error::{
+        ExecutionError::TypeMismatch,
+        PlanError::ArgumentCount,
+        SchemaError::{FunctionExists, ReservedFunctionName},
+    },
+    parser::ast::Aggregate,
+    schema::{DataType, Value},
+    Result,
+};
+
+/// 标量函数的实现，参数的个数和类型已经按照签名检查过，且都不为 NULL
+type FunctionImpl = dyn Fn(&[Value]) -> Result<Value> + Send + Sync;
+
+/// 标量函数的签名
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    /// 每个参数的类型，整数参数可以传给浮点数参数
+    pub args: Vec<DataType>,
+    /// 结果的类型
+    pub returns: DataType,
+    /// 相同的参数是否总是得到相同的结果，只有确定的函数才会在计划时对常量参数预先计算
+    pub deterministic: bool,
+}
+
+impl Signature {
+    pub fn new(args: Vec<DataType>, returns: DataType, deterministic: bool) -> Self {
+        Self {
+            args,
+            returns,
+            deterministic,
+        }
+    }
+}
+
+/// 注册的标量函数
+///
+/// 任意参数为 NULL 时结果为 NULL，不调用函数的实现；整数参数传给浮点数参数时转为浮点数，
+/// 结果的类型必须和签名相同，返回整数而签名为浮点数时转为浮点数。
+pub struct ScalarFunction {
+    name: String,
+    signature: Signature,
+    function: Box<FunctionImpl>,
+}
+
+impl ScalarFunction {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// 检查参数的个数
+    pub(crate) fn check_arity(&self, count: usize) -> Result<()> {
+        if count != self.signature.args.len() {
+            return Err(ArgumentCount {
+                function: self.name.clone(),
+                expected: self.signature.args.len(),
+                got: count,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// 检查第 `idx` 个参数的类型，类型为 `None` 表示 NULL 或者类型未知，不做检查
+    pub(crate) fn check_argument(&self, idx: usize, data_type: Option<DataType>) -> Result<()> {
+        match (data_type, self.signature.args.get(idx)) {
+            (Some(DataType::Integer), Some(DataType::Float)) | (None, _) => Ok(()),
+            (Some(data_type), Some(expected)) if data_type == *expected => Ok(()),
+            (Some(data_type), expected) => Err(TypeMismatch(format!(
+                "Function {} expects {:?} as argument {}, got {:?}",
+                self.name,
+                expected,
+                idx + 1,
+                data_type
+            ))
+            .into()),
+        }
+    }
+
+    /// 调用函数
+    pub(crate) fn call(&self, args: Vec<Value>) -> Result<Value> {
+        self.check_arity(args.len())?;
+        let mut values = Vec::with_capacity(args.len());
+        for (idx, arg) in args.into_iter().enumerate() {
+            self.check_argument(idx, arg.data_type())?;
+            values.push(match (arg, self.signature.args[idx]) {
+                (Value::Null, _) => return Ok(Value::Null),
+                (Value::Integer(i), DataType::Float) => Value::Float(i as f64),
+                (arg, _) => arg,
+            });
+        }
+        match ((self.function)(&values)?, self.signature.returns) {
+            (Value::Integer(i), DataType::Float) => Ok(Value::Float(i as f64)),
+            (value, returns) if value.data_type().is_none_or(|t| t == returns) => Ok(value),
+            (value, returns) => Err(TypeMismatch(format!(
+                "Function {} must return {:?}, got {:?}",
+                self.name, returns, value
+            ))
+            .into()),
+        }
+    }
+}
+
+impl Debug for ScalarFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScalarFunction")
+            .field("name", &self.name)
+            .field("signature", &self.signature)
+            .finish()
+    }
+}
+
+impl PartialEq for ScalarFunction {
+    /// 同一个注册表中函数名唯一，按照函数名和签名比较
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.signature == other.signature
+    }
+}
+
+/// 标量函数的注册表，内置函数在创建时注册，嵌入时可以通过 [`crate::Database::functions`] 注册自定义函数
+///
+/// 函数名不区分大小写。语句在计划之前按照函数名查找函数并检查参数的个数，计划时按照签名检查参数的类型。
+///
+/// ```
+/// use sqldb::{storage::MemoryStorage, DataType, Database, Engine, Signature, Value};
+///
+/// let db = Database::open(Engine::new(MemoryStorage::new()));
+/// db.functions().register(
+///     "twice",
+///     Signature::new(vec![DataType::Integer], DataType::Integer, true),
+///     |args| match &args[0] {
+///         Value::Integer(i) => Ok(Value::Integer(i * 2)),
+///         _ => unreachable!(),
+///     },
+/// )?;
+/// let mut session = db.session();
+/// session.execute("CREATE TABLE t (id INT PRIMARY KEY, name VARCHAR);")?;
+/// session.execute("INSERT INTO t VALUES (21, 'abc');")?;
+/// let result = session.execute("SELECT twice(id), upper(name) FROM t;")?;
+/// assert_eq!(result.rows()[0], vec![Value::Integer(42), Value::String("ABC".to_string())]);
+/// # Ok::<(), sqldb::Error>(())
+/// ```
+pub struct FunctionRegistry {
+    functions: RwLock<HashMap<String, Arc<ScalarFunction>>>,
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FunctionRegistry {
+    /// 创建只包含内置函数的注册表
+    pub fn new() -> Self {
+        let registry = Self {
+            functions: RwLock::new(HashMap::new()),
+        };
+        let string = |f: fn(&str) -> Value| {
+            move |args: &[Value]| match &args[0] {
+                Value::String(s) => Ok(f(s)),
+                arg => Err(TypeMismatch(format!("Expected a string, got {:?}", arg)).into()),
+            }
+        };
+        let builtins: [(&str, Signature, Box<FunctionImpl>); 4] = [
+            (
+                "upper",
+                Signature::new(vec![DataType::String], DataType::String, true),
+                Box::new(string(|s| Value::String(s.to_uppercase()))),
+            ),
+            (
+                "lower",
+                Signature::new(vec![DataType::String], DataType::String, true),
+                Box::new(string(|s| Value::String(s.to_lowercase()))),
+            ),
+            (
+                "length",
+                Signature::new(vec![DataType::String], DataType::Integer, true),
+                Box::new(string(|s| Value::Integer(s.chars().count() as i64))),
+            ),
+            (
+                "round",
+                Signature::new(vec![DataType::Float], DataType::Float, true),
+                Box::new(|args: &[Value]| match &args[0] {
+                    Value::Float(f) => Ok(Value::Float(f.round())),
+                    arg => Err(TypeMismatch(format!("Expected a float, got {:?}", arg)).into()),
+                }),
+            ),
+        ];
+        for (name, signature, function) in builtins {
+            registry
+                .insert(name.to_string(), signature, function)
+                .expect("built-in function names are unique");
+        }
+        registry
+    }
+
+    /// 注册函数 `name`，和内置函数或者已经注册的函数重名时返回错误
+    ///
+    /// 聚集函数和 `COALESCE` 由解析器单独处理，不能作为函数名。
+    pub fn register<F>(&self, name: &str, signature: Signature, function: F) -> Result<()>
+    where
+        F: Fn(&[Value]) -> Result<Value> + Send + Sync + 'static,
+    {
+        let name = name.to_lowercase();
+        if name == "coalesce" || Aggregate::try_from(name.clone()).is_ok() {
+            return Err(ReservedFunctionName(name).into());
+        }
+        self.insert(name, signature, Box::new(function))
+    }
+
+    /// 按照函数名查找函数
+    pub fn get(&self, name: &str) -> Result<Option<Arc<ScalarFunction>>> {
+        Ok(self.functions.read()?.get(&name.to_lowercase()).cloned())
+    }
+
+    fn insert(
+        &self,
+        name: String,
+        signature: Signature,
+        function: Box<FunctionImpl>,
+    ) -> Result<()> {
+        let mut functions = self.functions.write()?;
+        if functions.contains_key(&name) {
+            return Err(FunctionExists(name).into());
+        }
+        let function = ScalarFunction {
+            name: name.clone(),
+            signature,
+            function,
+        };
+        functions.insert(name, Arc::new(function));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    use super::*;
+    use crate::{storage::MemoryStorage, Database, Engine, Error, ExecutionError, ResultSet};
+
+    #[test]
+    fn test_scalar_function() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let float = |value: &Value| match value {
+            Value::Float(f) => *f,
+            _ => unreachable!(),
+        };
+        // 到原点的距离的平方
+        db.functions().register(
+            "dist",
+            Signature::new(
+                vec![DataType::Float, DataType::Float],
+                DataType::Float,
+                true,
+            ),
+            move |args| {
+                Ok(Value::Float(
+                    float(&args[0]).powi(2) + float(&args[1]).powi(2),
+                ))
+            },
+        )?;
+        // 每次调用返回递增的整数
+        let counter = Arc::new(AtomicI64::new(0));
+        let next = counter.clone();
+        db.functions().register(
+            "next_id",
+            Signature::new(vec![], DataType::Integer, false),
+            move |_| Ok(Value::Integer(next.fetch_add(1, Ordering::SeqCst) + 1)),
+        )?;
+
+        let mut session = db.session();
+        for sql in [
+            "CREATE TABLE points (id INT PRIMARY KEY, x FLOAT, y INT, name STRING NULL);",
+            "INSERT INTO points VALUES (1, 3.0, 4, 'a'), (2, 1.0, 1, NULL), (3, 6.0, 8, 'ccc');",
+        ] {
+            session.execute(sql)?;
+        }
+        let query = |session: &mut crate::Session<MemoryStorage>, sql: &str| {
+            Ok::<_, Error>(session.execute(sql)?.rows().to_vec())
+        };
+
+        // SELECT、WHERE 和 ORDER BY 中调用，整数参数转为浮点数，函数名不区分大小写
+        assert_eq!(
+            query(
+                &mut session,
+                "SELECT id, DIST(x, y) FROM points WHERE dist(x, y) < 50.0 ORDER BY dist(x, y) DESC;"
+            )?,
+            vec![
+                vec![Value::Integer(1), Value::Float(25.0)],
+                vec![Value::Integer(2), Value::Float(2.0)],
+            ]
+        );
+
+        // 内置函数，参数为 NULL 时结果为 NULL
+        assert_eq!(
+            query(
+                &mut session,
+                "SELECT upper(name), length(name) FROM points ORDER BY id;"
+            )?,
+            vec![
+                vec![Value::String("A".to_string()), Value::Integer(1)],
+                vec![Value::Null, Value::Null],
+                vec![Value::String("CCC".to_string()), Value::Integer(3)],
+            ]
+        );
+
+        // 确定的函数在计划时计算常量参数，因此可以使用主键查找；不确定的函数每一行都计算一次
+        let explain = |session: &mut crate::Session<MemoryStorage>, sql: &str| match session
+            .execute(&format!("EXPLAIN {sql}"))?
+        {
+            ResultSet::Explain(plan) => Ok::<_, Error>(plan),
+            result => panic!("unexpected result {result:?}"),
+        };
+        let plan = explain(
+            &mut session,
+            "SELECT * FROM points WHERE id = length('ab');",
+        )?;
+        assert!(plan.starts_with("KeyLookup"), "{plan}");
+        let plan = explain(&mut session, "SELECT * FROM points WHERE id = next_id();")?;
+        assert!(plan.contains("next_id()"), "{plan}");
+        assert_eq!(
+            query(&mut session, "SELECT id FROM points WHERE id = next_id();")?,
+            vec![
+                vec![Value::Integer(1)],
+                vec![Value::Integer(2)],
+                vec![Value::Integer(3)]
+            ]
+        );
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+
+        // 不存在的函数、参数的个数或类型不符
+        for sql in [
+            "SELECT missing(x) FROM points;",
+            "SELECT dist(x) FROM points;",
+            "SELECT * FROM points WHERE dist(name, x) > 1.0;",
+            "SELECT * FROM points WHERE upper(name) > 1;",
+        ] {
+            assert!(session.execute(sql).is_err(), "{sql}");
+        }
+
+        // 和内置函数、已经注册的函数或者保留的函数名重名
+        let signature = Signature::new(vec![], DataType::Integer, true);
+        for name in ["upper", "DIST", "count", "coalesce"] {
+            assert!(db
+                .functions()
+                .register(name, signature.clone(), |_| Ok(Value::Null))
+                .is_err());
+        }
+
+        // 函数的结果必须和签名的类型相同
+        db.functions()
+            .register("broken", signature, |_| Ok(Value::Boolean(true)))?;
+        assert!(matches!(
+            session.execute("SELECT broken() FROM points;"),
+            Err(Error::Execution(ExecutionError::TypeMismatch(_)))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+//! 保序编码，编码后的字节序与原始值的顺序一致，用于构造存储引擎中的 key
+//!
+//! - 字节串：将 `0x00` 转义为 `0x00 0xFF`，并以 `0x00 0x00` 结尾，保证编码后的字节串互不为前缀
+//! - 值：以类型标签开头（NULL 0，布尔 1，整数 2，浮点数 3，字符串 4，JSON 5），之后为值的编码
+//!   - 整数：翻转符号位后按大端序编码
+//!   - 浮点数：规范化（-0.0 为 0.0，NaN 为同一个 NaN）后，正数翻转符号位，负数翻转所有位后按大端序编码，
+//!     和 [`Value`] 的相等比较一致
+//!   - 字符串：按字节串编码
+//!   - JSON：按规范化序列化后的文本的字节串编码
+
+use crate::schema::{canonical_float_bits, Value};
+
+/// 将字节串编码追加到 `out` 中
+pub fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    for &b in bytes {
+        if b == 0x00 {
+            out.extend([0x00, 0xFF]);
+        } else {
+            out.push(b);
+        }
+    }
+    out.extend([0x00, 0x00]);
+}
+
+/// 将值编码追加到 `out` 中
+pub fn encode_value(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0),
+        Value::Boolean(b) => out.extend([1, *b as u8]),
+        Value::Integer(i) => {
+            out.push(2);
+            out.extend(((*i as u64) ^ (1 << 63)).to_be_bytes());
+        }
+        Value::Float(f) => {
+            out.push(3);
+            let bits = canonical_float_bits(*f);
+            let bits = if bits >> 63 == 0 {
+                bits ^ (1 << 63)
+            } else {
+                !bits
+            };
+            out.extend(bits.to_be_bytes());
+        }
+        Value::String(s) => {
+            out.push(4);
+            encode_bytes(s.as_bytes(), out);
+        }
+        Value::Json(json) => {
+            out.push(5);
+            encode_bytes(json.to_string().as_bytes(), out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_value(value, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_keycode_order() {
+        let values = vec![
+            Value::Null,
+            Value::Boolean(false),
+            Value::Boolean(true),
+            Value::Integer(i64::MIN),
+            Value::Integer(-1),
+            Value::Integer(0),
+            Value::Integer(1),
+            Value::Integer(256),
+            Value::Integer(i64::MAX),
+            Value::Float(f64::NEG_INFINITY),
+            Value::Float(-1.5),
+            Value::Float(0.0),
+            Value::Float(0.5),
+            Value::Float(f64::INFINITY),
+            Value::String("".to_string()),
+            Value::String("a".to_string()),
+            Value::String("a\0".to_string()),
+            Value::String("ab".to_string()),
+            Value::String("b".to_string()),
+            Value::Json(serde_json::json!([1, 2])),
+            Value::Json(serde_json::json!({"a": 1})),
+        ];
+        for pair in values.windows(2) {
+            assert!(encode(&pair[0]) < encode(&pair[1]), "{:?}", pair);
+        }
+
+        // 编码后的字节串互不为前缀
+        let mut lhs = Vec::new();
+        encode_bytes(b"a", &mut lhs);
+        let mut rhs = Vec::new();
+        encode_bytes(b"a\0b", &mut rhs);
+        assert!(!rhs.starts_with(&lhs));
+        assert!(lhs < rhs);
+    }
+
+    #[test]
+    fn test_keycode_float_canonical() {
+        // 相等的浮点数编码相同，主键和索引的 key 与值的相等比较一致
+        assert_eq!(encode(&Value::Float(-0.0)), encode(&Value::Float(0.0)));
+        assert_eq!(
+            encode(&Value::Float(f64::NAN)),
+            encode(&Value::Float(-f64::NAN))
+        );
+        assert!(encode(&Value::Float(f64::INFINITY)) < encode(&Value::Float(f64::NAN)));
+    }
+}
@@ -1,8 +1,35 @@
+#[cfg(feature = "tokio")]
+mod async_session;
+mod codec;
+mod dump;
 mod engine;
 mod error;
 pub mod executor;
+mod function;
+mod keycode;
 pub mod parser;
+mod plan_cache;
+mod planner;
 mod schema;
+#[cfg(feature = "server")]
+pub mod server;
+mod session;
+mod stats;
 pub mod storage;
 
-pub use error::{Error, Result};
+#[cfg(feature = "tokio")]
+pub use async_session::AsyncSession;
+pub use codec::{BincodeCodec, ValueCodec};
+pub use engine::{Engine, ReferenceViolation};
+pub use error::{
+    Error, ErrorCode, ErrorContext, ExecutionError, PlanError, Result, ResultExt, Retryability,
+    SchemaError, ScriptPosition, SourceError, StorageError, TransactionError,
+};
+pub use executor::{
+    CancellationToken, ColumnMeta, CopyResult, CsvOptions, JsonLayout, JsonOptions, JsonResult,
+    NonFiniteFloat, OnError, ResultSet,
+};
+pub use function::{FunctionRegistry, ScalarFunction, Signature};
+pub use plan_cache::{PlanCache, PlanCacheStats};
+pub use schema::{Column, DataType, Namespace, Row, Table, TableBuilder, Value};
+pub use session::{Database, RetryPolicy, Session};
@@ -1,6 +1,15 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    sync::Arc,
+};
 
-use crate::{error::Error::ParseError, schema::Column};
+use crate::{
+    error::{Error::Parse, ExecutionError::UnboundParameter},
+    executor::CsvOptions,
+    function::ScalarFunction,
+    schema::{Column, DataType, ForeignKey, Value},
+};
 
 /// 常量定义
 #[derive(PartialEq, Debug, Clone)]
@@ -18,7 +27,45 @@ pub enum Expression {
     Field(String),
     Constant(Constant),
     Operation(Operation),
-    Function(Aggregate, String),
+    /// 一元负号，结果为操作数的相反数
+    Negate(Box<Expression>),
+    /// 聚集函数，依次为函数类型、参数列名（`*` 表示所有行）和是否为 DISTINCT 聚集
+    Function(Aggregate, String, bool),
+    /// COALESCE 函数，结果为第一个不为 NULL 的参数
+    Coalesce(Vec<Expression>),
+    /// 标量函数调用，依次为函数名和参数，由执行器在计划之前查找函数替换为 [`Expression::Scalar`]
+    Call(String, Vec<Expression>),
+    /// 查找到的标量函数和参数
+    Scalar(Arc<ScalarFunction>, Vec<Expression>),
+    /// CASE 表达式，依次为简单形式中比较的操作数、`WHEN ... THEN ...` 分支和 `ELSE` 分支
+    ///
+    /// 没有操作数时为搜索形式，`WHEN` 为条件；没有分支匹配且没有 `ELSE` 时结果为 NULL
+    Case(
+        Option<Box<Expression>>,
+        Vec<(Expression, Expression)>,
+        Option<Box<Expression>>,
+    ),
+    /// 标量子查询，结果为子查询唯一一行唯一一列的值，没有行时为 NULL
+    Subquery(Box<Statement>),
+    /// `expr IN (SELECT ...)`，`NOT IN` 解析为 `NOT` 包裹的 `IN`
+    InSubquery(Box<Expression>, Box<Statement>),
+    /// 常量无法表示的值，由执行器将结果为 JSON 的标量子查询替换得到
+    Value(Value),
+    /// 和物化的子查询结果比较的 `IN`，由执行器替换 [`Expression::InSubquery`] 得到
+    InSet(Box<Expression>, Arc<ValueSet>),
+    /// 参数占位符，编号从 1 开始，执行预处理语句时替换为对应的参数值，见 [`Statement::bind`]
+    Parameter(usize),
+}
+
+/// `IN` 子查询物化后的结果
+#[derive(PartialEq, Debug, Default)]
+pub struct ValueSet {
+    /// 不为 NULL 的值
+    pub values: HashSet<Value>,
+    /// 结果中是否有 NULL
+    pub has_null: bool,
+    /// 第一个不为 NULL 的值的类型，用于检查 `IN` 两侧是否可以比较
+    pub data_type: Option<DataType>,
 }
 
 impl Expression {
@@ -35,7 +82,7 @@ impl Expression {
     }
 
     pub fn is_function(&self) -> bool {
-        matches!(self, Expression::Function(_, _))
+        matches!(self, Expression::Function(..))
     }
 
     pub fn as_field(&self) -> Option<&String> {
@@ -59,12 +106,215 @@ impl Expression {
         }
     }
 
-    pub fn as_function(&self) -> Option<(Aggregate, &String)> {
+    pub fn as_function(&self) -> Option<(Aggregate, &String, bool)> {
         match self {
-            Expression::Function(aggregate, alias) => Some((*aggregate, alias)),
+            Expression::Function(aggregate, col_name, distinct) => {
+                Some((*aggregate, col_name, *distinct))
+            }
             _ => None,
         }
     }
+
+    /// 收集表达式中引用的所有字段名，聚集函数的参数不包括在内
+    pub fn collect_fields<'a>(&'a self, fields: &mut Vec<&'a String>) {
+        self.walk(&mut |expr| {
+            if let Expression::Field(name) = expr {
+                fields.push(name);
+            }
+        });
+    }
+
+    /// 收集表达式中的所有聚集函数
+    pub fn collect_functions<'a>(&'a self, functions: &mut Vec<&'a Expression>) {
+        self.walk(&mut |expr| {
+            if expr.is_function() {
+                functions.push(expr);
+            }
+        });
+    }
+
+    /// 表达式中是否有尚未执行的子查询
+    pub fn has_subquery(&self) -> bool {
+        let mut found = false;
+        self.walk(&mut |expr| {
+            found |= matches!(expr, Expression::Subquery(_) | Expression::InSubquery(..));
+        });
+        found
+    }
+
+    /// 表达式中是否有参数占位符，不包括子查询中的占位符
+    pub fn has_parameter(&self) -> bool {
+        let mut found = false;
+        self.walk(&mut |expr| found |= matches!(expr, Expression::Parameter(_)));
+        found
+    }
+
+    /// 表达式的值是否只取决于一行中的字段，即只由字段、常量、运算、负号、COALESCE 和 CASE 组成
+    pub fn is_row_expression(&self) -> bool {
+        let mut row_only = true;
+        self.walk(&mut |expr| {
+            row_only &= matches!(
+                expr,
+                Expression::Field(_)
+                    | Expression::Constant(_)
+                    | Expression::Operation(_)
+                    | Expression::Negate(_)
+                    | Expression::Coalesce(_)
+                    | Expression::Case(..)
+            );
+        });
+        row_only
+    }
+
+    /// 将表达式中的参数占位符替换为 `params` 中对应的值，`$n` 对应 `params[n - 1]`，包括子查询中的占位符
+    ///
+    /// 占位符的编号超过参数的个数时返回 [`UnboundParameter`]。
+    pub fn bind(self, params: &[Value]) -> crate::Result<Expression> {
+        self.transform(&mut |expr| match expr {
+            Expression::Parameter(index) => match params.get(index - 1) {
+                Some(value) => Ok(Expression::from(value.clone())),
+                None => Err(UnboundParameter(index).into()),
+            },
+            Expression::Subquery(subquery) => {
+                Ok(Expression::Subquery(Box::new(subquery.bind(params)?)))
+            }
+            Expression::InSubquery(expr, subquery) => Ok(Expression::InSubquery(
+                expr,
+                Box::new(subquery.bind(params)?),
+            )),
+            expr => Ok(expr),
+        })
+    }
+
+    /// 后序变换表达式，先变换所有子表达式，再对变换后的表达式调用 `f`
+    ///
+    /// 子查询中的表达式属于子查询本身，不会被变换。
+    pub fn transform(
+        self,
+        f: &mut impl FnMut(Expression) -> crate::Result<Expression>,
+    ) -> crate::Result<Expression> {
+        let operation = match self {
+            Expression::Operation(operation) => operation,
+            Expression::Negate(expr) => {
+                let expr = Box::new(expr.transform(f)?);
+                return f(Expression::Negate(expr));
+            }
+            Expression::Coalesce(args) => {
+                let args = args
+                    .into_iter()
+                    .map(|arg| arg.transform(f))
+                    .collect::<crate::Result<_>>()?;
+                return f(Expression::Coalesce(args));
+            }
+            Expression::Call(name, args) => {
+                let args = args
+                    .into_iter()
+                    .map(|arg| arg.transform(f))
+                    .collect::<crate::Result<_>>()?;
+                return f(Expression::Call(name, args));
+            }
+            Expression::Scalar(function, args) => {
+                let args = args
+                    .into_iter()
+                    .map(|arg| arg.transform(f))
+                    .collect::<crate::Result<_>>()?;
+                return f(Expression::Scalar(function, args));
+            }
+            Expression::Case(operand, branches, else_result) => {
+                let operand = operand
+                    .map(|operand| operand.transform(f).map(Box::new))
+                    .transpose()?;
+                let branches = branches
+                    .into_iter()
+                    .map(|(when, then)| Ok((when.transform(f)?, then.transform(f)?)))
+                    .collect::<crate::Result<_>>()?;
+                let else_result = else_result
+                    .map(|else_result| else_result.transform(f).map(Box::new))
+                    .transpose()?;
+                return f(Expression::Case(operand, branches, else_result));
+            }
+            Expression::InSubquery(expr, subquery) => {
+                let expr = Box::new(expr.transform(f)?);
+                return f(Expression::InSubquery(expr, subquery));
+            }
+            Expression::InSet(expr, set) => {
+                let expr = Box::new(expr.transform(f)?);
+                return f(Expression::InSet(expr, set));
+            }
+            expr => return f(expr),
+        };
+        let mut map = |expr: Box<Expression>| expr.transform(f).map(Box::new);
+        let operation = match operation {
+            Operation::Equal(lhs, rhs) => Operation::Equal(map(lhs)?, map(rhs)?),
+            Operation::NotEqual(lhs, rhs) => Operation::NotEqual(map(lhs)?, map(rhs)?),
+            Operation::GreaterThan(lhs, rhs) => Operation::GreaterThan(map(lhs)?, map(rhs)?),
+            Operation::GreaterThanOrEqual(lhs, rhs) => {
+                Operation::GreaterThanOrEqual(map(lhs)?, map(rhs)?)
+            }
+            Operation::LessThan(lhs, rhs) => Operation::LessThan(map(lhs)?, map(rhs)?),
+            Operation::LessThanOrEqual(lhs, rhs) => {
+                Operation::LessThanOrEqual(map(lhs)?, map(rhs)?)
+            }
+            Operation::Add(lhs, rhs) => Operation::Add(map(lhs)?, map(rhs)?),
+            Operation::Subtract(lhs, rhs) => Operation::Subtract(map(lhs)?, map(rhs)?),
+            Operation::Multiply(lhs, rhs) => Operation::Multiply(map(lhs)?, map(rhs)?),
+            Operation::Divide(lhs, rhs) => Operation::Divide(map(lhs)?, map(rhs)?),
+            Operation::And(lhs, rhs) => Operation::And(map(lhs)?, map(rhs)?),
+            Operation::Or(lhs, rhs) => Operation::Or(map(lhs)?, map(rhs)?),
+            Operation::Not(expr) => Operation::Not(map(expr)?),
+            Operation::IsNull(expr) => Operation::IsNull(map(expr)?),
+        };
+        f(Expression::Operation(operation))
+    }
+
+    /// 先序遍历表达式及其所有子表达式，不进入子查询
+    fn walk<'a>(&'a self, visit: &mut impl FnMut(&'a Expression)) {
+        visit(self);
+        match self {
+            Expression::Field(_)
+            | Expression::Constant(_)
+            | Expression::Function(..)
+            | Expression::Subquery(_)
+            | Expression::Value(_)
+            | Expression::Parameter(_) => {}
+            Expression::Coalesce(args)
+            | Expression::Call(_, args)
+            | Expression::Scalar(_, args) => args.iter().for_each(|arg| arg.walk(visit)),
+            Expression::Case(operand, branches, else_result) => {
+                if let Some(operand) = operand {
+                    operand.walk(visit);
+                }
+                for (when, then) in branches {
+                    when.walk(visit);
+                    then.walk(visit);
+                }
+                if let Some(else_result) = else_result {
+                    else_result.walk(visit);
+                }
+            }
+            Expression::Negate(expr)
+            | Expression::InSubquery(expr, _)
+            | Expression::InSet(expr, _) => expr.walk(visit),
+            Expression::Operation(operation) => match operation {
+                Operation::Not(expr) | Operation::IsNull(expr) => expr.walk(visit),
+                Operation::Equal(lhs, rhs)
+                | Operation::NotEqual(lhs, rhs)
+                | Operation::GreaterThan(lhs, rhs)
+                | Operation::GreaterThanOrEqual(lhs, rhs)
+                | Operation::LessThan(lhs, rhs)
+                | Operation::LessThanOrEqual(lhs, rhs)
+                | Operation::Add(lhs, rhs)
+                | Operation::Subtract(lhs, rhs)
+                | Operation::Multiply(lhs, rhs)
+                | Operation::Divide(lhs, rhs)
+                | Operation::And(lhs, rhs)
+                | Operation::Or(lhs, rhs) => {
+                    lhs.walk(visit);
+                    rhs.walk(visit);
+                }
+            },
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -98,7 +348,75 @@ impl TryFrom<String> for Aggregate {
             "avg" => Ok(Aggregate::Avg),
             "max" => Ok(Aggregate::Max),
             "min" => Ok(Aggregate::Min),
-            _ => Err(ParseError(format!("Invalid aggregate function: {}", value))),
+            _ => Err(Parse(format!("Invalid aggregate function: {}", value))),
+        }
+    }
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constant::Null => write!(f, "NULL"),
+            Constant::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            Constant::Integer(i) => write!(f, "{}", i),
+            Constant::Float(v) => write!(f, "{:?}", v),
+            Constant::String(s) => write!(f, "'{}'", s.replace('\'', "''")),
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expression::Field(name) => write!(f, "{}", name),
+            Expression::Constant(constant) => write!(f, "{}", constant),
+            Expression::Operation(operation) => write!(f, "{}", operation),
+            // 操作数不是字段或者函数调用时使用括号包裹，避免 `-(a + b)` 显示为含义不同的 `-a + b`
+            Expression::Negate(expr) => match **expr {
+                Expression::Field(_)
+                | Expression::Function(..)
+                | Expression::Coalesce(_)
+                | Expression::Call(..)
+                | Expression::Scalar(..) => write!(f, "-{}", expr),
+                _ => write!(f, "-({})", expr),
+            },
+            Expression::Function(agg, col_name, false) => write!(f, "{}({})", agg, col_name),
+            Expression::Function(agg, col_name, true) => {
+                write!(f, "{}(DISTINCT {})", agg, col_name)
+            }
+            Expression::Coalesce(args) => {
+                let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+                write!(f, "COALESCE({})", args.join(", "))
+            }
+            Expression::Call(name, args) => {
+                let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expression::Scalar(function, args) => {
+                let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+                write!(f, "{}({})", function.name(), args.join(", "))
+            }
+            Expression::Case(operand, branches, else_result) => {
+                write!(f, "CASE")?;
+                if let Some(operand) = operand {
+                    write!(f, " {}", operand)?;
+                }
+                for (when, then) in branches {
+                    write!(f, " WHEN {} THEN {}", when, then)?;
+                }
+                if let Some(else_result) = else_result {
+                    write!(f, " ELSE {}", else_result)?;
+                }
+                write!(f, " END")
+            }
+            Expression::Subquery(_) => write!(f, "(SELECT ...)"),
+            Expression::InSubquery(expr, _) => write!(f, "{} IN (SELECT ...)", expr),
+            Expression::Value(value) => write!(f, "{}", value),
+            Expression::InSet(expr, set) => {
+                let count = set.values.len() + set.has_null as usize;
+                write!(f, "{} IN ({} values)", expr, count)
+            }
+            Expression::Parameter(index) => write!(f, "${}", index),
         }
     }
 }
@@ -106,17 +424,87 @@ impl TryFrom<String> for Aggregate {
 #[derive(PartialEq, Debug, Clone)]
 pub enum Operation {
     Equal(Box<Expression>, Box<Expression>),
+    NotEqual(Box<Expression>, Box<Expression>),
+    GreaterThan(Box<Expression>, Box<Expression>),
+    GreaterThanOrEqual(Box<Expression>, Box<Expression>),
+    LessThan(Box<Expression>, Box<Expression>),
+    LessThanOrEqual(Box<Expression>, Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    Divide(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    IsNull(Box<Expression>),
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // 嵌套的运算使用括号包裹，避免产生歧义
+        let operand = |expr: &Expression| match expr {
+            Expression::Operation(_) | Expression::InSubquery(..) | Expression::InSet(..) => {
+                format!("({})", expr)
+            }
+            _ => expr.to_string(),
+        };
+        let (lhs, op, rhs) = match self {
+            Operation::Not(expr) => return write!(f, "NOT {}", operand(expr)),
+            Operation::IsNull(expr) => return write!(f, "{} IS NULL", operand(expr)),
+            Operation::Equal(lhs, rhs) => (lhs, "=", rhs),
+            Operation::NotEqual(lhs, rhs) => (lhs, "!=", rhs),
+            Operation::GreaterThan(lhs, rhs) => (lhs, ">", rhs),
+            Operation::GreaterThanOrEqual(lhs, rhs) => (lhs, ">=", rhs),
+            Operation::LessThan(lhs, rhs) => (lhs, "<", rhs),
+            Operation::LessThanOrEqual(lhs, rhs) => (lhs, "<=", rhs),
+            Operation::Add(lhs, rhs) => (lhs, "+", rhs),
+            Operation::Subtract(lhs, rhs) => (lhs, "-", rhs),
+            Operation::Multiply(lhs, rhs) => (lhs, "*", rhs),
+            Operation::Divide(lhs, rhs) => (lhs, "/", rhs),
+            Operation::And(lhs, rhs) => (lhs, "AND", rhs),
+            Operation::Or(lhs, rhs) => (lhs, "OR", rhs),
+        };
+        write!(f, "{} {} {}", operand(lhs), op, operand(rhs))
+    }
 }
 
 /// 排序方式
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Ordering {
     Asc,
     Desc,
 }
 
+impl Display for Ordering {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ordering::Asc => write!(f, "ASC"),
+            Ordering::Desc => write!(f, "DESC"),
+        }
+    }
+}
+
+/// NULL 的排序位置，未指定时 NULL 视为最小值，即 ASC 时在最前，DESC 时在最后
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum NullsOrder {
+    First,
+    Last,
+}
+
+impl Display for NullsOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NullsOrder::First => write!(f, "NULLS FIRST"),
+            NullsOrder::Last => write!(f, "NULLS LAST"),
+        }
+    }
+}
+
+/// 排序项，依次为排序的表达式、排序方式和 NULL 的排序位置
+pub type OrderBy = (Expression, Ordering, Option<NullsOrder>);
+
 /// 连接方式
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum JoinType {
     Inner,
     Left,
@@ -137,8 +525,35 @@ impl Display for JoinType {
     }
 }
 
+/// 集合运算
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
+impl Display for SetOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SetOperator::Union => write!(f, "Union"),
+            SetOperator::Intersect => write!(f, "Intersect"),
+            SetOperator::Except => write!(f, "Except"),
+        }
+    }
+}
+
+/// INSERT 插入的行的来源
+#[derive(PartialEq, Debug, Clone)]
+pub enum InsertSource {
+    /// `VALUES` 中列出的每一行的值
+    Values(Vec<Vec<Expression>>),
+    /// 查询语句的结果，查询为 SELECT 语句或者集合运算
+    Query(Box<Statement>),
+}
+
 /// 查询来源
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum SelectFrom {
     Table {
         name: String,
@@ -168,32 +583,287 @@ impl Display for SelectFrom {
 }
 
 /// 抽象语法树定义
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum Statement {
     CreateTable {
         name: String,
         columns: Vec<Column>,
+        foreign_keys: Vec<ForeignKey>,
+    },
+    CreateIndex {
+        name: String,
+        table_name: String,
+        columns: Vec<String>,
+        unique: bool,
+        /// 部分索引的条件，只有满足条件的行写入索引
+        predicate: Option<Expression>,
+    },
+    DropTable {
+        name: String,
     },
     Insert {
         table_name: String,
         columns: Option<Vec<String>>,
-        values: Vec<Vec<Expression>>,
+        source: InsertSource,
     },
     Select {
         columns: Vec<(Expression, Option<String>)>,
+        distinct: bool,
         from: SelectFrom,
-        filter: Option<(String, Expression)>,
-        ordering: Vec<(String, Ordering)>,
+        filter: Option<Expression>,
+        group_by: Vec<Expression>,
+        having: Option<Expression>,
+        ordering: Vec<OrderBy>,
+        limit: Option<Expression>,
+        offset: Option<Expression>,
+    },
+    /// 集合运算，`all` 为真时保留重复的行，`ordering`、`limit` 和 `offset` 作用于运算的结果
+    SetOperation {
+        operator: SetOperator,
+        all: bool,
+        left: Box<Statement>,
+        right: Box<Statement>,
+        ordering: Vec<OrderBy>,
         limit: Option<Expression>,
         offset: Option<Expression>,
     },
     Update {
         table_name: String,
         columns: HashMap<String, Expression>,
-        filter: Option<(String, Expression)>,
+        filter: Option<Expression>,
     },
     Delete {
         table_name: String,
-        filter: Option<(String, Expression)>,
+        filter: Option<Expression>,
     },
+    /// 收集表的统计信息，`table_name` 为 `None` 时收集所有表
+    Analyze {
+        table_name: Option<String>,
+    },
+    /// `analyze` 为真时实际执行计划，并输出每个节点产生的行数和耗时
+    Explain {
+        statement: Box<Statement>,
+        analyze: bool,
+    },
+    /// 从 CSV 文件导入数据，`path` 为服务端的文件路径
+    Copy {
+        table_name: String,
+        path: String,
+        options: CsvOptions,
+    },
+    /// 将查询的结果导出为 CSV 文件，`path` 为服务端的文件路径
+    CopyTo {
+        query: Box<Statement>,
+        path: String,
+        options: CsvOptions,
+    },
+    /// 开启显式事务
+    Begin,
+    /// 提交显式事务
+    Commit,
+    /// 回滚显式事务
+    Rollback,
+}
+
+impl Statement {
+    /// 查询语句中是否有尚未执行的子查询，包括连接条件和集合运算的每一侧
+    pub fn has_subquery(&self) -> bool {
+        fn from_has_subquery(from: &SelectFrom) -> bool {
+            match from {
+                SelectFrom::Table { .. } => false,
+                SelectFrom::Join {
+                    left,
+                    right,
+                    predicate,
+                    ..
+                } => {
+                    from_has_subquery(left)
+                        || from_has_subquery(right)
+                        || predicate.as_ref().is_some_and(Expression::has_subquery)
+                }
+            }
+        }
+
+        match self {
+            Statement::Select {
+                columns,
+                from,
+                filter,
+                group_by,
+                having,
+                ordering,
+                limit,
+                offset,
+                ..
+            } => {
+                from_has_subquery(from)
+                    || columns.iter().any(|(expr, _)| expr.has_subquery())
+                    || group_by.iter().any(Expression::has_subquery)
+                    || ordering.iter().any(|(expr, _, _)| expr.has_subquery())
+                    || [filter, having, limit, offset]
+                        .into_iter()
+                        .flatten()
+                        .any(Expression::has_subquery)
+            }
+            Statement::SetOperation {
+                left,
+                right,
+                ordering,
+                limit,
+                offset,
+                ..
+            } => {
+                left.has_subquery()
+                    || right.has_subquery()
+                    || ordering.iter().any(|(expr, _, _)| expr.has_subquery())
+                    || [limit, offset]
+                        .into_iter()
+                        .flatten()
+                        .any(Expression::has_subquery)
+            }
+            _ => false,
+        }
+    }
+
+    /// 将语句中的参数占位符替换为 `params` 中对应的值，见 [`Expression::bind`]
+    pub fn bind(self, params: &[Value]) -> crate::Result<Statement> {
+        fn bind_all(exprs: Vec<Expression>, params: &[Value]) -> crate::Result<Vec<Expression>> {
+            exprs.into_iter().map(|expr| expr.bind(params)).collect()
+        }
+        fn bind_option(
+            expr: Option<Expression>,
+            params: &[Value],
+        ) -> crate::Result<Option<Expression>> {
+            expr.map(|expr| expr.bind(params)).transpose()
+        }
+        fn bind_ordering(ordering: Vec<OrderBy>, params: &[Value]) -> crate::Result<Vec<OrderBy>> {
+            ordering
+                .into_iter()
+                .map(|(expr, order, nulls)| Ok((expr.bind(params)?, order, nulls)))
+                .collect()
+        }
+        fn bind_from(from: SelectFrom, params: &[Value]) -> crate::Result<SelectFrom> {
+            match from {
+                SelectFrom::Table { .. } => Ok(from),
+                SelectFrom::Join {
+                    left,
+                    right,
+                    join_type,
+                    predicate,
+                } => Ok(SelectFrom::Join {
+                    left: Box::new(bind_from(*left, params)?),
+                    right: Box::new(bind_from(*right, params)?),
+                    join_type,
+                    predicate: bind_option(predicate, params)?,
+                }),
+            }
+        }
+
+        let stmt = match self {
+            Statement::Select {
+                columns,
+                distinct,
+                from,
+                filter,
+                group_by,
+                having,
+                ordering,
+                limit,
+                offset,
+            } => Statement::Select {
+                columns: columns
+                    .into_iter()
+                    .map(|(expr, alias)| Ok((expr.bind(params)?, alias)))
+                    .collect::<crate::Result<_>>()?,
+                distinct,
+                from: bind_from(from, params)?,
+                filter: bind_option(filter, params)?,
+                group_by: bind_all(group_by, params)?,
+                having: bind_option(having, params)?,
+                ordering: bind_ordering(ordering, params)?,
+                limit: bind_option(limit, params)?,
+                offset: bind_option(offset, params)?,
+            },
+            Statement::SetOperation {
+                operator,
+                all,
+                left,
+                right,
+                ordering,
+                limit,
+                offset,
+            } => Statement::SetOperation {
+                operator,
+                all,
+                left: Box::new(left.bind(params)?),
+                right: Box::new(right.bind(params)?),
+                ordering: bind_ordering(ordering, params)?,
+                limit: bind_option(limit, params)?,
+                offset: bind_option(offset, params)?,
+            },
+            Statement::Insert {
+                table_name,
+                columns,
+                source,
+            } => Statement::Insert {
+                table_name,
+                columns,
+                source: match source {
+                    InsertSource::Values(values) => InsertSource::Values(
+                        values
+                            .into_iter()
+                            .map(|row| bind_all(row, params))
+                            .collect::<crate::Result<_>>()?,
+                    ),
+                    InsertSource::Query(query) => {
+                        InsertSource::Query(Box::new(query.bind(params)?))
+                    }
+                },
+            },
+            Statement::Update {
+                table_name,
+                columns,
+                filter,
+            } => Statement::Update {
+                table_name,
+                columns: columns
+                    .into_iter()
+                    .map(|(col_name, expr)| Ok((col_name, expr.bind(params)?)))
+                    .collect::<crate::Result<_>>()?,
+                filter: bind_option(filter, params)?,
+            },
+            Statement::Delete { table_name, filter } => Statement::Delete {
+                table_name,
+                filter: bind_option(filter, params)?,
+            },
+            Statement::CreateIndex {
+                name,
+                table_name,
+                columns,
+                unique,
+                predicate,
+            } => Statement::CreateIndex {
+                name,
+                table_name,
+                columns,
+                unique,
+                predicate: bind_option(predicate, params)?,
+            },
+            Statement::Explain { statement, analyze } => Statement::Explain {
+                statement: Box::new(statement.bind(params)?),
+                analyze,
+            },
+            Statement::CopyTo {
+                query,
+                path,
+                options,
+            } => Statement::CopyTo {
+                query: Box::new(query.bind(params)?),
+                path,
+                options,
+            },
+            stmt => stmt,
+        };
+        Ok(stmt)
+    }
 }
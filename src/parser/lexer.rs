@@ -1,7 +1,7 @@
-use std::{fmt::Display, iter::Peekable, str::Chars};
+use std::{cell::Cell, fmt::Display, iter::Peekable, rc::Rc, str::Chars};
 
 use crate::{
-    Error::{self, ParseError},
+    Error::{self, Parse},
     Result,
 };
 
@@ -21,6 +21,13 @@ pub enum Token {
     Minus,              // 减号 -
     Slash,              // 斜杠 /
     Equal,              // 等号 =
+    NotEqual,           // 不等号 != 或 <>
+    LessThan,           // 小于号 <
+    LessThanOrEqual,    // 小于等于号 <=
+    GreaterThan,        // 大于号 >
+    GreaterThanOrEqual, // 大于等于号 >=
+    /// 参数占位符，`$n` 为编号 n 的参数，`?` 为 `None`，由解析器按照出现的顺序编号
+    Parameter(Option<usize>),
 }
 
 impl Display for Token {
@@ -39,6 +46,13 @@ impl Display for Token {
             Token::Minus => write!(f, "-"),
             Token::Slash => write!(f, "/"),
             Token::Equal => write!(f, "="),
+            Token::NotEqual => write!(f, "!="),
+            Token::LessThan => write!(f, "<"),
+            Token::LessThanOrEqual => write!(f, "<="),
+            Token::GreaterThan => write!(f, ">"),
+            Token::GreaterThanOrEqual => write!(f, ">="),
+            Token::Parameter(Some(index)) => write!(f, "${}", index),
+            Token::Parameter(None) => write!(f, "?"),
         }
     }
 }
@@ -87,6 +101,42 @@ pub enum Keyword {
     On,
     Inner,
     Full,
+    And,
+    Or,
+    Between,
+    Is,
+    In,
+    Explain,
+    Index,
+    Unique,
+    Nulls,
+    First,
+    Last,
+    Json,
+    Group,
+    Having,
+    Distinct,
+    Analyze,
+    Begin,
+    Commit,
+    Rollback,
+    References,
+    Cascade,
+    Restrict,
+    Union,
+    Intersect,
+    Except,
+    All,
+    Case,
+    When,
+    Then,
+    Else,
+    End,
+    Copy,
+    With,
+    To,
+    Drop,
+    AutoIncrement,
 }
 
 impl TryFrom<&str> for Keyword {
@@ -136,7 +186,43 @@ impl TryFrom<&str> for Keyword {
             "ON" => Keyword::On,
             "INNER" => Keyword::Inner,
             "FULL" => Keyword::Full,
-            keyword => return Err(ParseError(format!("Invalid keyword {keyword}"))),
+            "AND" => Keyword::And,
+            "OR" => Keyword::Or,
+            "BETWEEN" => Keyword::Between,
+            "IS" => Keyword::Is,
+            "IN" => Keyword::In,
+            "EXPLAIN" => Keyword::Explain,
+            "INDEX" => Keyword::Index,
+            "UNIQUE" => Keyword::Unique,
+            "NULLS" => Keyword::Nulls,
+            "FIRST" => Keyword::First,
+            "LAST" => Keyword::Last,
+            "JSON" => Keyword::Json,
+            "GROUP" => Keyword::Group,
+            "HAVING" => Keyword::Having,
+            "DISTINCT" => Keyword::Distinct,
+            "ANALYZE" => Keyword::Analyze,
+            "BEGIN" => Keyword::Begin,
+            "COMMIT" => Keyword::Commit,
+            "ROLLBACK" => Keyword::Rollback,
+            "REFERENCES" => Keyword::References,
+            "CASCADE" => Keyword::Cascade,
+            "RESTRICT" => Keyword::Restrict,
+            "UNION" => Keyword::Union,
+            "INTERSECT" => Keyword::Intersect,
+            "EXCEPT" => Keyword::Except,
+            "ALL" => Keyword::All,
+            "CASE" => Keyword::Case,
+            "WHEN" => Keyword::When,
+            "THEN" => Keyword::Then,
+            "ELSE" => Keyword::Else,
+            "END" => Keyword::End,
+            "COPY" => Keyword::Copy,
+            "WITH" => Keyword::With,
+            "TO" => Keyword::To,
+            "DROP" => Keyword::Drop,
+            "AUTO_INCREMENT" => Keyword::AutoIncrement,
+            keyword => return Err(Parse(format!("Invalid keyword {keyword}"))),
         };
         Ok(keyword)
     }
@@ -195,6 +281,42 @@ impl Display for Keyword {
             Keyword::On => "ON",
             Keyword::Inner => "INNER",
             Keyword::Full => "FULL",
+            Keyword::And => "AND",
+            Keyword::Or => "OR",
+            Keyword::Between => "BETWEEN",
+            Keyword::Is => "IS",
+            Keyword::In => "IN",
+            Keyword::Explain => "EXPLAIN",
+            Keyword::Index => "INDEX",
+            Keyword::Unique => "UNIQUE",
+            Keyword::Nulls => "NULLS",
+            Keyword::First => "FIRST",
+            Keyword::Last => "LAST",
+            Keyword::Json => "JSON",
+            Keyword::Group => "GROUP",
+            Keyword::Having => "HAVING",
+            Keyword::Distinct => "DISTINCT",
+            Keyword::Analyze => "ANALYZE",
+            Keyword::Begin => "BEGIN",
+            Keyword::Commit => "COMMIT",
+            Keyword::Rollback => "ROLLBACK",
+            Keyword::References => "REFERENCES",
+            Keyword::Cascade => "CASCADE",
+            Keyword::Restrict => "RESTRICT",
+            Keyword::Union => "UNION",
+            Keyword::Intersect => "INTERSECT",
+            Keyword::Except => "EXCEPT",
+            Keyword::All => "ALL",
+            Keyword::Case => "CASE",
+            Keyword::When => "WHEN",
+            Keyword::Then => "THEN",
+            Keyword::Else => "ELSE",
+            Keyword::End => "END",
+            Keyword::Copy => "COPY",
+            Keyword::With => "WITH",
+            Keyword::To => "TO",
+            Keyword::Drop => "DROP",
+            Keyword::AutoIncrement => "AUTO_INCREMENT",
         })
     }
 }
@@ -202,6 +324,10 @@ impl Display for Keyword {
 /// 词法分析 Lexer 结构体
 pub struct Lexer<'a> {
     iter: Peekable<Chars<'a>>,
+    /// 已经扫描的字节数
+    offset: usize,
+    /// 最近一次扫描的 Token 的起始字节偏移，没有更多 Token 时为文本的长度，和解析器共享，用于定位错误
+    token_start: Rc<Cell<usize>>,
 }
 
 impl<'a> Lexer<'a> {
@@ -209,9 +335,28 @@ impl<'a> Lexer<'a> {
     pub fn new(text: &'a str) -> Self {
         Lexer {
             iter: text.chars().peekable(),
+            offset: 0,
+            token_start: Rc::new(Cell::new(0)),
         }
     }
 
+    /// 最近一次扫描的 Token 的起始字节偏移，在之后的扫描中随之更新
+    pub fn token_start(&self) -> Rc<Cell<usize>> {
+        self.token_start.clone()
+    }
+
+    /// 已经扫描的字节数，即下一个字符的字节偏移
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// 跳转到下一个字符并返回该字符，同时记录扫描的字节数
+    fn bump(&mut self) -> Option<char> {
+        let c = self.iter.next()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
     /// 如果满足条件，则跳转到下一个字符，并返回该字符，否则返回 None
     fn next_if<F>(&mut self, predicate: F) -> Option<char>
     where
@@ -220,7 +365,7 @@ impl<'a> Lexer<'a> {
         // `peek` 返回顶端元素，如果 `filter` 结果为 None，则直接返回，不调用 `next`
         self.iter.peek().filter(|&c| predicate(*c))?;
         // 如果 `filter` 结果为 Some，则调用 `next`，迭代到下一个元素，并返回该元素
-        self.iter.next()
+        self.bump()
     }
 
     /// 跳转到下一个字符，直到不满足条件为止，并返回所有满足条件的字符。
@@ -246,25 +391,27 @@ impl<'a> Lexer<'a> {
     fn scan_string(&mut self) -> Result<Token> {
         // 如果不以单引号开头，则返回错误
         if self.next_if(|c| c == '\'').is_none() {
-            return Err(ParseError("Expect a single quote".to_string()));
+            return Err(Parse("Expect a single quote".to_string()));
         }
 
+        // 字符串中连续的两个单引号表示一个单引号
         let mut s = String::new();
-        for c in self.iter.by_ref() {
+        while let Some(c) = self.bump() {
             match c {
+                '\'' if self.next_if(|c| c == '\'').is_some() => s.push('\''),
                 '\'' => return Ok(Token::String(s)),
                 _ => s.push(c),
             }
         }
         // 如果没有找到结束的单引号，则返回错误
-        Err(ParseError("Expect a single quote".to_string()))
+        Err(Parse("Expect a single quote".to_string()))
     }
 
-    /// 扫描数字，支持 `123`、`123.456`、`456.` 格式，否则返回 `ParseError`。
+    /// 扫描数字，支持 `123`、`123.456`、`456.` 格式，否则返回 `Parse`。
     fn scan_number(&mut self) -> Result<Token> {
         // 如果不以数字开头，则返回错误
         if self.iter.peek().filter(|c| c.is_ascii_digit()).is_none() {
-            return Err(ParseError("Expect a number".to_string()));
+            return Err(Parse("Expect a number".to_string()));
         }
         let mut num = self.next_while(|c| c.is_ascii_digit());
         // 如果以 . 开头，则认为是小数，将其添加到 num 中，并添加 . 后面的数字
@@ -276,11 +423,11 @@ impl<'a> Lexer<'a> {
     }
 
     /// 扫描标识符或者关键字。如果扫描的 Token 不在关键字列表中，则认为其为标识符。
-    /// Token 必须以字母开头，否则返回 `ParseError`。
+    /// Token 必须以字母开头，否则返回 `Parse`。
     fn scan_identifier_or_keyword(&mut self) -> Result<Token> {
         let mut s = self
             .next_if(|c| c.is_alphabetic())
-            .ok_or(ParseError("Expect an identifier".to_string()))?
+            .ok_or(Parse("Expect an identifier".to_string()))?
             .to_string();
         s.push_str(&self.next_while(|c| c.is_alphanumeric() || c == '_' || c == '.'));
 
@@ -288,7 +435,7 @@ impl<'a> Lexer<'a> {
             .map_or_else(|_| Token::Identifier(s.to_lowercase()), Token::Keyword))
     }
 
-    /// 扫描符号，Token 必须为 `*(),;+-/` 中的一个，否则返回 `ParseError`。
+    /// 扫描符号，Token 必须为 `*(),;+-/=` 或者比较运算符 `!= <> < <= > >=` 中的一个，否则返回 `Parse`。
     fn scan_symbol(&mut self) -> Result<Token> {
         let sym = self
             .iter
@@ -303,24 +450,56 @@ impl<'a> Lexer<'a> {
                 '-' => Some(Token::Minus),
                 '/' => Some(Token::Slash),
                 '=' => Some(Token::Equal),
+                '<' => Some(Token::LessThan),
+                '>' => Some(Token::GreaterThan),
+                '!' => Some(Token::NotEqual),
                 _ => None,
             })
-            .ok_or(ParseError("Expect a symbol".to_string()))?;
-        self.iter.next();
+            .ok_or(Parse("Expect a symbol".to_string()))?;
+        self.bump();
+
+        // 处理由两个字符组成的比较运算符
+        let sym = match sym {
+            Token::LessThan if self.next_if(|c| c == '=').is_some() => Token::LessThanOrEqual,
+            Token::LessThan if self.next_if(|c| c == '>').is_some() => Token::NotEqual,
+            Token::GreaterThan if self.next_if(|c| c == '=').is_some() => Token::GreaterThanOrEqual,
+            // `!` 必须和 `=` 组成 `!=`
+            Token::NotEqual if self.next_if(|c| c == '=').is_none() => {
+                return Err(Parse("Expect = after !".to_string()))
+            }
+            sym => sym,
+        };
         Ok(sym)
     }
 
+    /// 扫描参数占位符 `$n` 或者 `?`，`$` 之后必须是从 1 开始的编号，否则返回 `Parse`。
+    fn scan_parameter(&mut self) -> Result<Token> {
+        if self.next_if(|c| c == '?').is_some() {
+            return Ok(Token::Parameter(None));
+        }
+        if self.next_if(|c| c == '$').is_none() {
+            return Err(Parse("Expect a parameter".to_string()));
+        }
+        let index = self.next_while(|c| c.is_ascii_digit());
+        match index.parse::<usize>() {
+            Ok(index) if index > 0 => Ok(Token::Parameter(Some(index))),
+            _ => Err(Parse(format!("Invalid parameter ${index}"))),
+        }
+    }
+
     /// 扫描下一个 Token。
-    /// 正常情况下返回 `Some(Token)`。如果全部扫描完成，返回 `None`，如果 Token 不合法，返回 `Some(ParseError)`。
+    /// 正常情况下返回 `Some(Token)`。如果全部扫描完成，返回 `None`，如果 Token 不合法，返回 `Some(Parse)`。
     fn scan_next_token(&mut self) -> Option<Result<Token>> {
         // 移除 Token 前面的空格
         self.erase_whitespace();
+        self.token_start.set(self.offset);
 
         // 对开头进行匹配
         let token = match self.iter.peek()? {
             '\'' => self.scan_string(), // 以单引号开头，认为是字符串
             c if c.is_ascii_digit() || *c == '.' => self.scan_number(), // 数字或者 . 开头，认为是数字
             c if c.is_alphabetic() => self.scan_identifier_or_keyword(), // 字母开头，认为是关键字或标识符
+            '$' | '?' => self.scan_parameter(),                          // 参数占位符
             _ => self.scan_symbol(), // 其他字符开头的情况，认为是符号
         };
         Some(token)
@@ -407,6 +586,12 @@ mod tests {
             Token::String("Hello, World!".to_string())
         );
 
+        lexer = Lexer::new("'it''s ''quoted'''");
+        assert_eq!(
+            lexer.scan_string().unwrap(),
+            Token::String("it's 'quoted'".to_string())
+        );
+
         lexer = Lexer::new("Hello, World!'");
         assert!(lexer.scan_string().is_err());
 
@@ -493,6 +678,34 @@ mod tests {
         assert_eq!(lexer.scan_symbol().unwrap(), Token::Minus);
         assert_eq!(lexer.scan_symbol().unwrap(), Token::Slash);
         assert!(lexer.scan_symbol().is_err());
+
+        let mut lexer = Lexer::new("=!=<><<=>>=!");
+        assert_eq!(lexer.scan_symbol().unwrap(), Token::Equal);
+        assert_eq!(lexer.scan_symbol().unwrap(), Token::NotEqual);
+        assert_eq!(lexer.scan_symbol().unwrap(), Token::NotEqual);
+        assert_eq!(lexer.scan_symbol().unwrap(), Token::LessThan);
+        assert_eq!(lexer.scan_symbol().unwrap(), Token::LessThanOrEqual);
+        assert_eq!(lexer.scan_symbol().unwrap(), Token::GreaterThan);
+        assert_eq!(lexer.scan_symbol().unwrap(), Token::GreaterThanOrEqual);
+        assert!(lexer.scan_symbol().is_err());
+    }
+
+    #[test]
+    fn test_scan_parameter() {
+        let tokens = Lexer::new("$1 ? $12").collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Parameter(Some(1)),
+                Token::Parameter(None),
+                Token::Parameter(Some(12)),
+            ]
+        );
+
+        // 编号从 1 开始，`$` 之后必须是编号
+        for text in ["$0", "$", "$a"] {
+            assert!(Lexer::new(text).scan_parameter().is_err());
+        }
     }
 
     #[test]
@@ -537,4 +750,18 @@ mod tests {
         assert_eq!(tokens[2], Token::Keyword(Keyword::From));
         assert_eq!(tokens[3], Token::Identifier("customers".to_string()));
     }
+
+    #[test]
+    fn test_token_offset() {
+        let mut lexer = Lexer::new("SELECT 'a\nβ' ,\n  x");
+        let token_start = lexer.token_start();
+        let mut starts = Vec::new();
+        while let Some(Ok(_)) = lexer.next() {
+            starts.push((token_start.get(), lexer.offset()));
+        }
+        // 多字节字符按照字节计算偏移
+        assert_eq!(starts, vec![(0, 6), (7, 13), (14, 15), (18, 19)]);
+        assert_eq!(lexer.next(), None);
+        assert_eq!(token_start.get(), 19);
+    }
 }
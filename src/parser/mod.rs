@@ -1,11 +1,16 @@
-use std::{collections::HashMap, iter::Peekable};
+use std::{cell::Cell, collections::HashMap, iter::Peekable, ops::Range, rc::Rc};
 
 use crate::{
-    schema::{Column, DataType},
-    Error::ParseError,
+    error::ScriptPosition,
+    executor::{CsvOptions, OnError},
+    schema::{Column, DataType, ForeignKey, OnDelete},
+    Error::{self, Parse},
     Result,
 };
-use ast::{Aggregate, Constant, Expression, JoinType, Operation, Ordering, SelectFrom, Statement};
+use ast::{
+    Aggregate, Constant, Expression, InsertSource, JoinType, NullsOrder, Operation, OrderBy,
+    Ordering, SelectFrom, SetOperator, Statement,
+};
 use lexer::{Keyword, Lexer, Token};
 
 pub mod ast;
@@ -14,16 +19,60 @@ mod lexer;
 /// SQL 解析器
 pub struct Parser<'a> {
     lexer: Peekable<Lexer<'a>>,
+    /// 最近一次扫描的 token 的起始字节偏移
+    position: Rc<Cell<usize>>,
+    /// 已经解析的 `?` 占位符的个数
+    anonymous_parameters: usize,
+    /// 已经解析的 `$n` 占位符的最大编号
+    numbered_parameters: usize,
 }
 
 impl<'a> Parser<'a> {
     /// 创建一个新的解析器
     pub fn new(input: &'a str) -> Self {
+        let lexer = Lexer::new(input);
         Parser {
-            lexer: Lexer::new(input).peekable(),
+            position: lexer.token_start(),
+            lexer: lexer.peekable(),
+            anonymous_parameters: 0,
+            numbered_parameters: 0,
         }
     }
 
+    /// 已经解析的语句需要的参数个数，即占位符的最大编号，没有占位符时为 0
+    pub fn parameter_count(&self) -> usize {
+        self.anonymous_parameters.max(self.numbered_parameters)
+    }
+
+    /// 最近一次扫描的 token 在输入中的字节偏移，输入扫描完时为输入的长度
+    ///
+    /// 解析器会预读一个 token，因此解析失败后的位置通常是导致失败的 token 的起始位置。
+    pub fn position(&self) -> usize {
+        self.position.get()
+    }
+
+    /// 解析由多条以分号结尾的语句组成的脚本，按顺序返回每条语句和它在脚本中的字节范围
+    ///
+    /// 任何一条语句解析失败时返回 [`Error::Script`]，其中的位置是出错的 token 在整个脚本中的行和列，
+    /// 而不是在单条语句中的位置。最后一条语句缺少分号时同样返回错误。
+    pub fn parse_statements(script: &str) -> Result<Vec<(Range<usize>, Statement)>> {
+        let mut statements = Vec::new();
+        for (statement_index, span) in split_statements(script).into_iter().enumerate() {
+            let mut parser = Parser::new(&script[span.clone()]);
+            match parser.parse() {
+                Ok(statement) => statements.push((span, statement)),
+                Err(e) => {
+                    let offset = span.start + parser.position();
+                    return Err(Error::Script {
+                        position: ScriptPosition::new(script, statement_index, offset),
+                        source: Box::new(e),
+                    });
+                }
+            }
+        }
+        Ok(statements)
+    }
+
     /// 解析 SQL 语句
     ///
     /// 支持的语句：
@@ -31,42 +80,204 @@ impl<'a> Parser<'a> {
     /// ```sql
     /// select [* | col_name [ [ AS ] output_name [, ...] ]] from [table_name [ cross | left | right | inner ] join ...] [where [condition]] [order by [column_name] [asc|desc]] [limit [number]] [offset [number]];
     ///
-    /// create table [table_name] ([column_name] [data_type] [nullable] [default] [primary key], ...);
+    /// [select statement] [union | intersect | except] [all] [select statement] ... [order by ...] [limit [number]] [offset [number]];
+    ///
+    /// create table [table_name] ([column_name] [data_type] [nullable] [default] [primary key] [auto_increment], ...);
+    ///
+    /// create [unique] index [index_name] on [table_name] ([column_name], ...) [where [condition]];
+    ///
+    /// drop table [table_name];
     ///
     /// insert into [table_name] ([column_name], ...) values ([value], ...);
     ///
     /// update [table_name] set [column_name] = [value], ... where [condition];
     ///
     /// delete from [table_name] where [condition];
+    ///
+    /// explain [select statement];
     /// ```
     pub fn parse(&mut self) -> Result<Statement> {
-        // 根据第一个 token 的类型选择解析方法
-        let stmt = match self
-            .lexer
-            .peek()
-            .ok_or(ParseError("Unexpected end of input".to_string()))?
-        {
-            Ok(Token::Keyword(Keyword::Select)) => self.parse_select(),
-            Ok(Token::Keyword(Keyword::Create)) => self.parse_create_table(),
-            Ok(Token::Keyword(Keyword::Insert)) => self.parse_insert(),
-            Ok(Token::Keyword(Keyword::Update)) => self.parse_update(),
-            Ok(Token::Keyword(Keyword::Delete)) => self.parse_delete(),
-            Ok(token) => Err(ParseError(format!("Unexpected token {token}"))),
-            Err(e) => Err(ParseError(format!("Lexical error: {e}"))),
-        };
+        // 先返回语句本身的错误，再检查分号，保证错误的位置指向语句中出错的 token
+        let stmt = self.parse_statement()?;
         // 解析结束后应该是一个分号，否则返回异常
         self.next_token_equal(Token::Semicolon)?;
         // 如果词法解析器的顶端不是 None，说明语句存在错误
         if let Some(result) = self.lexer.peek() {
             match result {
                 // 如果是一个 token，返回未知的 token 错误
-                Ok(token) => return Err(ParseError(format!("Unexpected token {token}"))),
+                Ok(token) => return Err(Parse(format!("Unexpected token {token}"))),
                 // 如果是一个词法解析错误，返回词法解析错误
-                Err(e) => return Err(ParseError(format!("Lexical error: {e}"))),
+                Err(e) => return Err(Parse(format!("Lexical error: {e}"))),
             }
         }
         // 返回解析结果
-        stmt
+        Ok(stmt)
+    }
+
+    /// 解析单独的一个表达式，表达式之后不能有其他 token，用于读取以文本形式保存的表达式
+    pub fn parse_standalone_expression(input: &str) -> Result<Expression> {
+        let mut parser = Parser::new(input);
+        let expr = parser.parse_expression()?;
+        match parser.lexer.next() {
+            None => Ok(expr),
+            Some(Ok(token)) => Err(Parse(format!("Unexpected token {token}"))),
+            Some(Err(e)) => Err(Parse(format!("Lexical error: {e}"))),
+        }
+    }
+
+    /// 根据第一个 token 的类型选择解析方法，解析一条不包含结尾分号的语句
+    fn parse_statement(&mut self) -> Result<Statement> {
+        match self
+            .lexer
+            .peek()
+            .ok_or(Parse("Unexpected end of input".to_string()))?
+        {
+            Ok(Token::Keyword(Keyword::Select)) => self.parse_query(),
+            Ok(Token::Keyword(Keyword::Create)) => self.parse_create(),
+            Ok(Token::Keyword(Keyword::Drop)) => self.parse_drop(),
+            Ok(Token::Keyword(Keyword::Insert)) => self.parse_insert(),
+            Ok(Token::Keyword(Keyword::Update)) => self.parse_update(),
+            Ok(Token::Keyword(Keyword::Delete)) => self.parse_delete(),
+            Ok(Token::Keyword(Keyword::Explain)) => self.parse_explain(),
+            Ok(Token::Keyword(Keyword::Analyze)) => self.parse_analyze(),
+            Ok(Token::Keyword(Keyword::Copy)) => self.parse_copy(),
+            Ok(Token::Keyword(Keyword::Begin | Keyword::Commit | Keyword::Rollback)) => {
+                self.parse_transaction()
+            }
+            Ok(token) => Err(Parse(format!("Unexpected token {token}"))),
+            Err(e) => Err(Parse(format!("Lexical error: {e}"))),
+        }
+    }
+
+    /// 解析事务控制语句
+    /// 语法：`BEGIN`、`COMMIT` 或 `ROLLBACK`
+    fn parse_transaction(&mut self) -> Result<Statement> {
+        match self.next_keyword()? {
+            Keyword::Begin => Ok(Statement::Begin),
+            Keyword::Commit => Ok(Statement::Commit),
+            Keyword::Rollback => Ok(Statement::Rollback),
+            keyword => Err(Parse(format!("Unexpected keyword {keyword}"))),
+        }
+    }
+
+    /// 解析 EXPLAIN 语句
+    /// 语法：`EXPLAIN [ANALYZE] [select statement | set operation]`
+    fn parse_explain(&mut self) -> Result<Statement> {
+        self.next_token_equal(Token::Keyword(Keyword::Explain))?;
+        let analyze = self
+            .next_token_equal(Token::Keyword(Keyword::Analyze))
+            .is_ok();
+        match self.parse_statement()? {
+            stmt @ (Statement::Select { .. } | Statement::SetOperation { .. }) => {
+                Ok(Statement::Explain {
+                    statement: Box::new(stmt),
+                    analyze,
+                })
+            }
+            _ => Err(Parse(
+                "Only SELECT and set operations can be explained".to_string(),
+            )),
+        }
+    }
+
+    /// 解析 ANALYZE 语句
+    /// 语法：`ANALYZE [table_name]`
+    fn parse_analyze(&mut self) -> Result<Statement> {
+        self.next_token_equal(Token::Keyword(Keyword::Analyze))?;
+        let table_name = match self.lexer.peek() {
+            Some(Ok(Token::Identifier(_))) => Some(self.next_identifier()?),
+            _ => None,
+        };
+        Ok(Statement::Analyze { table_name })
+    }
+
+    /// 解析 COPY 语句
+    /// 语法：`COPY table_name FROM 'path' [WITH (option value, ...)]` 或者 `COPY (query) TO 'path' [WITH (option value, ...)]`
+    ///
+    /// 选项为 `HEADER`、`DELIMITER`、`NULL`、`EMPTY_AS_NULL`、`ON_ERROR` 和 `BATCH_SIZE`，含义见 [`CsvOptions`]。
+    /// `ON_ERROR` 的值为 `abort` 或者 `skip`，`DELIMITER` 的值为单个字节的字符串，`NULL` 的值为字符串。
+    fn parse_copy(&mut self) -> Result<Statement> {
+        self.next_token_equal(Token::Keyword(Keyword::Copy))?;
+        if self.next_token_equal(Token::OpenParen).is_ok() {
+            let query = self.parse_query()?;
+            self.next_token_equal(Token::CloseParen)?;
+            self.next_token_equal(Token::Keyword(Keyword::To))?;
+            let path = self.parse_copy_path()?;
+            return Ok(Statement::CopyTo {
+                query: Box::new(query),
+                path,
+                options: self.parse_copy_options()?,
+            });
+        }
+
+        let table_name = self.next_identifier()?;
+        self.next_token_equal(Token::Keyword(Keyword::From))?;
+        let path = self.parse_copy_path()?;
+        Ok(Statement::Copy {
+            table_name,
+            path,
+            options: self.parse_copy_options()?,
+        })
+    }
+
+    /// 解析 COPY 语句中的文件路径
+    fn parse_copy_path(&mut self) -> Result<String> {
+        match self.next_token()? {
+            Token::String(path) => Ok(path),
+            token => Err(Parse(format!("Expected file path, found {token}"))),
+        }
+    }
+
+    /// 解析 COPY 语句中可选的 `WITH (option value, ...)`
+    fn parse_copy_options(&mut self) -> Result<CsvOptions> {
+        let mut options = CsvOptions::default();
+        if self
+            .next_token_equal(Token::Keyword(Keyword::With))
+            .is_err()
+        {
+            return Ok(options);
+        }
+        self.next_token_equal(Token::OpenParen)?;
+        loop {
+            // NULL 是关键字，不能作为标识符解析
+            let name = match self.next_token()? {
+                Token::Identifier(name) => name,
+                Token::Keyword(Keyword::Null) => "null".to_string(),
+                token => return Err(Parse(format!("Expected COPY option, found {token}"))),
+            };
+            let value = self.next_token()?;
+            let invalid = || Parse(format!("Invalid value {value} for COPY option {name}"));
+            match (name.as_str(), &value) {
+                ("header", Token::Keyword(Keyword::True)) => options.header = true,
+                ("header", Token::Keyword(Keyword::False)) => options.header = false,
+                ("empty_as_null", Token::Keyword(Keyword::True)) => options.empty_as_null = true,
+                ("empty_as_null", Token::Keyword(Keyword::False)) => options.empty_as_null = false,
+                ("delimiter", Token::String(s)) if s.len() == 1 => {
+                    options.delimiter = s.as_bytes()[0]
+                }
+                ("null", Token::String(s)) => options.null = s.clone(),
+                ("on_error", Token::String(s) | Token::Identifier(s)) => {
+                    options.on_error = match s.to_lowercase().as_str() {
+                        "abort" => OnError::Abort,
+                        "skip" => OnError::Skip,
+                        _ => return Err(invalid()),
+                    }
+                }
+                ("batch_size", Token::Number(n)) => {
+                    options.batch_size = n.parse().ok().filter(|&n| n > 0).ok_or_else(invalid)?
+                }
+                (
+                    "header" | "empty_as_null" | "delimiter" | "null" | "on_error" | "batch_size",
+                    _,
+                ) => return Err(invalid()),
+                _ => return Err(Parse(format!("Unknown COPY option {name}"))),
+            }
+            if self.next_token_equal(Token::Comma).is_err() {
+                break;
+            }
+        }
+        self.next_token_equal(Token::CloseParen)?;
+        Ok(options)
     }
 
     /// 在满足条件的情况下，跳转并获取下一个 token，否则不跳转，并返回错误
@@ -76,9 +287,9 @@ impl<'a> Parser<'a> {
     {
         match self.lexer.peek() {
             Some(Ok(token)) if f(token) => self.lexer.next().unwrap(),
-            Some(Ok(token)) => Err(ParseError(format!("Unexpected token {token}"))),
-            Some(Err(e)) => Err(ParseError(format!("Lexical error: {e}"))),
-            None => Err(ParseError("Unexpected end of input".to_string())),
+            Some(Ok(token)) => Err(Parse(format!("Unexpected token {token}"))),
+            Some(Err(e)) => Err(Parse(format!("Lexical error: {e}"))),
+            None => Err(Parse("Unexpected end of input".to_string())),
         }
     }
 
@@ -110,11 +321,114 @@ impl<'a> Parser<'a> {
             })
     }
 
+    /// 解析查询语句，即单个 SELECT 语句或者由集合运算连接的多个 SELECT 语句
+    /// 语法：`select_statement [UNION | INTERSECT | EXCEPT] [ALL] select_statement ... [ORDER BY ...] [LIMIT number] [OFFSET number]`
+    ///
+    /// `INTERSECT` 的优先级高于 `UNION` 和 `EXCEPT`，同一优先级从左到右结合。
+    /// 最后一个 SELECT 语句之后的 ORDER BY、LIMIT 和 OFFSET 作用于整个运算的结果，之前的 SELECT 语句不能包含它们。
+    fn parse_query(&mut self) -> Result<Statement> {
+        let mut branches = vec![self.parse_select()?];
+        let mut operators = Vec::new();
+        while let Some(operator) = self.next_set_operator() {
+            let all = self.next_token_equal(Token::Keyword(Keyword::All)).is_ok();
+            operators.push((operator, all));
+            branches.push(self.parse_select()?);
+        }
+        if operators.is_empty() {
+            return Ok(branches.remove(0));
+        }
+
+        // 最后一个 SELECT 语句解析到的 ORDER BY、LIMIT 和 OFFSET 属于整个运算
+        let Some(Statement::Select {
+            ordering,
+            limit,
+            offset,
+            ..
+        }) = branches.last_mut()
+        else {
+            unreachable!("Branches of a set operation must be SELECT statements");
+        };
+        let (ordering, limit, offset) = (std::mem::take(ordering), limit.take(), offset.take());
+        for branch in &branches {
+            if let Statement::Select {
+                ordering,
+                limit,
+                offset,
+                ..
+            } = branch
+            {
+                if !ordering.is_empty() || limit.is_some() || offset.is_some() {
+                    return Err(Parse(
+                        "ORDER BY, LIMIT and OFFSET must follow the last SELECT of a set operation"
+                            .to_string(),
+                    ));
+                }
+            }
+        }
+
+        let combine = |operator, all, left, right| Statement::SetOperation {
+            operator,
+            all,
+            left: Box::new(left),
+            right: Box::new(right),
+            ordering: Vec::new(),
+            limit: None,
+            offset: None,
+        };
+
+        // 先合并 INTERSECT 连接的语句，再从左到右合并 UNION 和 EXCEPT
+        let mut branches = branches.into_iter();
+        let mut terms = vec![branches.next().unwrap()];
+        let mut term_operators = Vec::new();
+        for ((operator, all), branch) in operators.into_iter().zip(branches) {
+            if operator == SetOperator::Intersect {
+                let left = terms.pop().unwrap();
+                terms.push(combine(operator, all, left, branch));
+            } else {
+                term_operators.push((operator, all));
+                terms.push(branch);
+            }
+        }
+        let mut terms = terms.into_iter();
+        let mut stmt = terms.next().unwrap();
+        for ((operator, all), term) in term_operators.into_iter().zip(terms) {
+            stmt = combine(operator, all, stmt, term);
+        }
+
+        if let Statement::SetOperation {
+            ordering: stmt_ordering,
+            limit: stmt_limit,
+            offset: stmt_offset,
+            ..
+        } = &mut stmt
+        {
+            (*stmt_ordering, *stmt_limit, *stmt_offset) = (ordering, limit, offset);
+        }
+        Ok(stmt)
+    }
+
+    /// 下一个 token 是集合运算的关键字时，跳转并返回对应的集合运算
+    fn next_set_operator(&mut self) -> Option<SetOperator> {
+        let operator = match self.lexer.peek() {
+            Some(Ok(Token::Keyword(Keyword::Union))) => SetOperator::Union,
+            Some(Ok(Token::Keyword(Keyword::Intersect))) => SetOperator::Intersect,
+            Some(Ok(Token::Keyword(Keyword::Except))) => SetOperator::Except,
+            _ => return None,
+        };
+        self.lexer.next();
+        Some(operator)
+    }
+
     /// 解析 SELECT 语句
-    /// 语法：`SELECT [* | col_name [ [AS] output_name [, ...] ]] FROM [table_name] WHERE [condition] ORDER BY [column_name] [ASC|DESC] LIMIT [number] OFFSET [number];`
+    /// 语法：`SELECT [DISTINCT] [* | expression [ [AS] output_name [, ...] ]] FROM [table_name] WHERE [condition] GROUP BY [expression, ...] HAVING [condition] ORDER BY [expression] [ASC|DESC] LIMIT [number] OFFSET [number];`
     fn parse_select(&mut self) -> Result<Statement> {
         self.next_token_equal(Token::Keyword(Keyword::Select))?; // 期望下一个 token 是 SELECT
 
+        // 如果有 DISTINCT，则对结果去重
+        let distinct = self
+            .next_token_equal(Token::Keyword(Keyword::Distinct))
+            .is_ok();
+
         // 获取列名，如果是 *，则表示选择所有列
         let columns = self.parse_select_columns()?;
 
@@ -127,6 +441,16 @@ impl<'a> Parser<'a> {
             .map(|_| self.parse_where_clause())
             .transpose()?;
 
+        // 如果有 GROUP BY 子句，则解析 GROUP BY 子句
+        let group_by = self.parse_group_by()?.unwrap_or_default();
+
+        // 如果有 HAVING 子句，则解析 HAVING 子句
+        let having = self
+            .next_token_equal(Token::Keyword(Keyword::Having))
+            .ok()
+            .map(|_| self.parse_expression())
+            .transpose()?;
+
         // 如果有 ORDER BY 子句，则解析 ORDER BY 子句
         let ordering = self.parse_order_by()?.unwrap_or_default();
 
@@ -143,8 +467,11 @@ impl<'a> Parser<'a> {
 
         Ok(Statement::Select {
             columns,
+            distinct,
             from,
             filter,
+            group_by,
+            having,
             ordering,
             limit,
             offset,
@@ -172,17 +499,6 @@ impl<'a> Parser<'a> {
                 _ => {
                     self.next_token_equal(Token::Keyword(Keyword::On))?; // 期望下一个 token 是 ON
                     let predicate = self.parse_expression()?;
-
-                    // JOIN 条件必须是一个字段等于另一个字段
-                    match predicate {
-                        Expression::Operation(Operation::Equal(ref left, ref right))
-                            if left.is_field() && right.is_field() => {}
-                        _ => {
-                            return Err(ParseError(
-                                "Join condition must be a field equal to a field".to_string(),
-                            ))
-                        }
-                    }
                     Some(predicate) // 解析 JOIN 条件
                 }
             };
@@ -240,17 +556,12 @@ impl<'a> Parser<'a> {
     }
 
     /// 解析 SELECT 语句的列名
-    /// 语法：`[* | col_name [ [AS] output_name [, ...] ]`
+    /// 语法：`[* | expression [ [AS] output_name [, ...] ]`
     fn parse_select_columns(&mut self) -> Result<Vec<(Expression, Option<String>)>> {
         let mut columns = Vec::new();
         if self.next_token_equal(Token::Asterisk).is_err() {
             loop {
-                let column_name = self.parse_expression()?; // 获取列名
-
-                // 列名必须是一个字段或者函数名
-                if !(column_name.is_field() || column_name.is_function()) {
-                    return Err(ParseError("Column name must be a field".to_string()));
-                }
+                let column_name = self.parse_expression()?; // 获取列的表达式
 
                 // 获取列的别名
                 let alias = self
@@ -267,16 +578,32 @@ impl<'a> Parser<'a> {
         Ok(columns)
     }
 
+    /// 解析 GROUP BY 子句，可能不存在
+    /// 语法：`GROUP BY [expression], ...`
+    fn parse_group_by(&mut self) -> Result<Option<Vec<Expression>>> {
+        self.next_token_equal(Token::Keyword(Keyword::Group))
+            .ok()
+            .map(|_| {
+                self.next_token_equal(Token::Keyword(Keyword::By))?; // 期望下一个 token 是 BY
+                let mut group_by = vec![self.parse_expression()?];
+                while self.next_token_equal(Token::Comma).is_ok() {
+                    group_by.push(self.parse_expression()?);
+                }
+                Ok(group_by)
+            })
+            .transpose()
+    }
+
     /// 解析 ORDER BY 子句，可能不存在
-    /// 语法：`ORDER BY [column_name] [ASC|DESC], ...`
-    fn parse_order_by(&mut self) -> Result<Option<Vec<(String, Ordering)>>> {
+    /// 语法：`ORDER BY [expression] [ASC|DESC] [NULLS FIRST|NULLS LAST], ...`
+    fn parse_order_by(&mut self) -> Result<Option<Vec<OrderBy>>> {
         self.next_token_equal(Token::Keyword(Keyword::Order))
             .ok()
             .map(|_| {
                 self.next_token_equal(Token::Keyword(Keyword::By))?; // 期望下一个 token 是 BY
                 let mut ordering = Vec::new();
                 loop {
-                    let column_name = self.next_identifier()?; // 获取列名
+                    let expr = self.parse_expression()?; // 获取排序的表达式
                     let ordering_type = match self.next_token_if(|token| {
                         matches!(
                             token,
@@ -288,7 +615,20 @@ impl<'a> Parser<'a> {
                         Ok(Token::Keyword(Keyword::Desc)) => Ordering::Desc,
                         _ => Ordering::Asc, // 如果不是 ASC 或 DESC，则默认为 ASC
                     };
-                    ordering.push((column_name, ordering_type));
+                    // 获取 NULL 的排序位置，可能不存在
+                    let nulls_order = if self
+                        .next_token_equal(Token::Keyword(Keyword::Nulls))
+                        .is_ok()
+                    {
+                        match self.next_token()? {
+                            Token::Keyword(Keyword::First) => Some(NullsOrder::First),
+                            Token::Keyword(Keyword::Last) => Some(NullsOrder::Last),
+                            token => return Err(Parse(format!("Unexpected token {token}"))),
+                        }
+                    } else {
+                        None
+                    };
+                    ordering.push((expr, ordering_type, nulls_order));
                     if self.next_token_equal(Token::Comma).is_err() {
                         break;
                     }
@@ -317,7 +657,7 @@ impl<'a> Parser<'a> {
             // 获取值
             let value = self.parse_expression()?;
             if columns.contains_key(&col_name) {
-                return Err(ParseError(format!("Duplicate column name {col_name}")));
+                return Err(Parse(format!("Duplicate column name {col_name}")));
             }
             columns.insert(col_name, value);
 
@@ -345,14 +685,9 @@ impl<'a> Parser<'a> {
     }
 
     /// 解析 WHERE 子句
-    /// 语法：`WHERE column_name = expression`
-    ///
-    /// 目前只支持单个表达式且仅为等于操作，不支持其他操作符和表达式组合
-    fn parse_where_clause(&mut self) -> Result<(String, Expression)> {
-        let col_name = self.next_identifier()?;
-        self.next_token_equal(Token::Equal)?;
-        let val = self.parse_expression()?;
-        Ok((col_name, val))
+    /// 语法：`WHERE expression`
+    fn parse_where_clause(&mut self) -> Result<Expression> {
+        self.parse_expression()
     }
 
     /// 解析 DELETE 语句
@@ -376,7 +711,7 @@ impl<'a> Parser<'a> {
 
     /// 解析列定义
     /// 语法：[column_name] [data_type] [nullable] [default]
-    fn parse_column(&mut self) -> Result<Column> {
+    fn parse_column(&mut self, table_name: &str) -> Result<(Column, Option<ForeignKey>)> {
         let name = self.next_identifier()?; // 获取列名
 
         // 获取数据类型
@@ -391,8 +726,10 @@ impl<'a> Parser<'a> {
             Token::Keyword(Keyword::String)
             | Token::Keyword(Keyword::Text)
             | Token::Keyword(Keyword::Varchar) => DataType::String,
+            // 如果是 JSON，则数据类型为 JSON
+            Token::Keyword(Keyword::Json) => DataType::Json,
             // 其他 token，返回未知的 token 错误
-            token => return Err(ParseError(format!("Unexpected token {token}"))),
+            token => return Err(Parse(format!("Unexpected token {token}"))),
         };
         // 初始化列结构体，设置列名和数据类型, 其他属性暂时为空
         let mut column = Column {
@@ -401,10 +738,13 @@ impl<'a> Parser<'a> {
             nullable: false,
             default: None,
             primary_key: false,
+            auto_increment: false,
         };
 
+        let mut foreign_key = None;
+
         // 解析列的其他属性
-        // 可能是 NULL, NOT NULL, DEFAULT [value]
+        // 可能是 NULL, NOT NULL, DEFAULT [value], PRIMARY KEY, AUTO_INCREMENT, REFERENCES [table] [ON DELETE CASCADE|RESTRICT]
         while let Ok(keyword) = self.next_keyword() {
             match keyword {
                 Keyword::Null => column.nullable = true, // 如果是 NULL，则设置列可空
@@ -419,80 +759,417 @@ impl<'a> Parser<'a> {
                     self.next_token_equal(Token::Keyword(Keyword::Key))?;
                     column.primary_key = true;
                 }
+                // 如果是 AUTO_INCREMENT，则设置列为自增列，是否为整数主键在创建表时检查
+                Keyword::AutoIncrement => column.auto_increment = true,
+                // 如果是 REFERENCES，则列引用父表的主键，约束名为 fk_[table_name]_[column_name]
+                Keyword::References => {
+                    let parent_table = self.next_identifier()?;
+                    let on_delete = if self.next_token_equal(Token::Keyword(Keyword::On)).is_ok() {
+                        self.next_token_equal(Token::Keyword(Keyword::Delete))?;
+                        match self.next_keyword()? {
+                            Keyword::Cascade => OnDelete::Cascade,
+                            Keyword::Restrict => OnDelete::Restrict,
+                            k => return Err(Parse(format!("Unexpected keyword {k}"))),
+                        }
+                    } else {
+                        OnDelete::default()
+                    };
+                    foreign_key = Some(ForeignKey {
+                        name: format!("fk_{table_name}_{}", column.name),
+                        column: column.name.clone(),
+                        parent_table,
+                        on_delete,
+                    });
+                }
                 // 其他关键字，返回未知的关键字错误
-                k => return Err(ParseError(format!("Unexpected keyword {k}"))),
+                k => return Err(Parse(format!("Unexpected keyword {k}"))),
             }
         }
-        Ok(column)
+        Ok((column, foreign_key))
     }
 
     /// 解析表达式
-    /// 目前支持的表达式类型：十进制整数、十进制浮点数、字符串、布尔值、NULL，**不支持函数调用、运算符等**
+    ///
+    /// 运算符优先级从低到高为：`OR`、`AND`、`NOT`、比较运算（`= != < <= > >= BETWEEN IS NULL IN`）、`+ -`、`* /`
     fn parse_expression(&mut self) -> Result<Expression> {
+        let mut left = self.parse_and_expression()?;
+        while self.next_token_equal(Token::Keyword(Keyword::Or)).is_ok() {
+            let right = self.parse_and_expression()?;
+            left = Expression::Operation(Operation::Or(Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    /// 解析 AND 表达式
+    /// 语法：`expression [AND expression ...]`
+    fn parse_and_expression(&mut self) -> Result<Expression> {
+        let mut left = self.parse_not_expression()?;
+        while self.next_token_equal(Token::Keyword(Keyword::And)).is_ok() {
+            let right = self.parse_not_expression()?;
+            left = Expression::Operation(Operation::And(Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    /// 解析 NOT 表达式
+    /// 语法：`[NOT] expression`
+    fn parse_not_expression(&mut self) -> Result<Expression> {
+        if self.next_token_equal(Token::Keyword(Keyword::Not)).is_ok() {
+            let expr = self.parse_not_expression()?;
+            return Ok(Expression::Operation(Operation::Not(Box::new(expr))));
+        }
+        self.parse_comparison_expression()
+    }
+
+    /// 解析比较表达式
+    /// 语法：`term [= | != | < | <= | > | >= term]`、`term BETWEEN term AND term`、`term IS [NOT] NULL`、
+    /// `term [NOT] IN (select statement)`
+    ///
+    /// `BETWEEN` 会被展开为 `term >= low AND term <= high`，`NOT IN` 会被展开为 `NOT (term IN (...))`
+    fn parse_comparison_expression(&mut self) -> Result<Expression> {
+        let left = self.parse_additive_expression()?;
+
+        // 解析 BETWEEN
+        if self
+            .next_token_equal(Token::Keyword(Keyword::Between))
+            .is_ok()
+        {
+            let low = self.parse_additive_expression()?;
+            self.next_token_equal(Token::Keyword(Keyword::And))?;
+            let high = self.parse_additive_expression()?;
+            return Ok(Expression::Operation(Operation::And(
+                Box::new(Expression::Operation(Operation::GreaterThanOrEqual(
+                    Box::new(left.clone()),
+                    Box::new(low),
+                ))),
+                Box::new(Expression::Operation(Operation::LessThanOrEqual(
+                    Box::new(left),
+                    Box::new(high),
+                ))),
+            )));
+        }
+
+        // 解析 IS [NOT] NULL
+        if self.next_token_equal(Token::Keyword(Keyword::Is)).is_ok() {
+            let not = self.next_token_equal(Token::Keyword(Keyword::Not)).is_ok();
+            self.next_token_equal(Token::Keyword(Keyword::Null))?;
+            let expr = Expression::Operation(Operation::IsNull(Box::new(left)));
+            return Ok(if not {
+                Expression::Operation(Operation::Not(Box::new(expr)))
+            } else {
+                expr
+            });
+        }
+
+        // 解析 [NOT] IN，NOT 只能出现在 IN 之前
+        let not = self.next_token_equal(Token::Keyword(Keyword::Not)).is_ok();
+        if not || self.next_token_equal(Token::Keyword(Keyword::In)).is_ok() {
+            if not {
+                self.next_token_equal(Token::Keyword(Keyword::In))?;
+            }
+            self.next_token_equal(Token::OpenParen)?;
+            let subquery = self.parse_query()?;
+            self.next_token_equal(Token::CloseParen)?;
+            let expr = Expression::InSubquery(Box::new(left), Box::new(subquery));
+            return Ok(if not {
+                Expression::Operation(Operation::Not(Box::new(expr)))
+            } else {
+                expr
+            });
+        }
+
+        let Ok(token) = self.next_token_if(|token| {
+            matches!(
+                token,
+                Token::Equal
+                    | Token::NotEqual
+                    | Token::LessThan
+                    | Token::LessThanOrEqual
+                    | Token::GreaterThan
+                    | Token::GreaterThanOrEqual
+            )
+        }) else {
+            return Ok(left);
+        };
+        let (left, right) = (Box::new(left), Box::new(self.parse_additive_expression()?));
+        Ok(Expression::Operation(match token {
+            Token::Equal => Operation::Equal(left, right),
+            Token::NotEqual => Operation::NotEqual(left, right),
+            Token::LessThan => Operation::LessThan(left, right),
+            Token::LessThanOrEqual => Operation::LessThanOrEqual(left, right),
+            Token::GreaterThan => Operation::GreaterThan(left, right),
+            Token::GreaterThanOrEqual => Operation::GreaterThanOrEqual(left, right),
+            _ => unreachable!(), // matches! 宏已经保证了 token 的类型
+        }))
+    }
+
+    /// 解析加减法表达式
+    /// 语法：`term [+|- term ...]`
+    fn parse_additive_expression(&mut self) -> Result<Expression> {
+        let mut left = self.parse_multiplicative_expression()?;
+        while let Ok(token) =
+            self.next_token_if(|token| matches!(token, Token::Plus | Token::Minus))
+        {
+            let right = self.parse_multiplicative_expression()?;
+            left = Expression::Operation(match token {
+                Token::Plus => Operation::Add(Box::new(left), Box::new(right)),
+                Token::Minus => Operation::Subtract(Box::new(left), Box::new(right)),
+                _ => unreachable!(), // matches! 宏已经保证了 token 的类型
+            });
+        }
+        Ok(left)
+    }
+
+    /// 解析乘除法表达式
+    /// 语法：`factor [*|/ factor ...]`
+    fn parse_multiplicative_expression(&mut self) -> Result<Expression> {
+        let mut left = self.parse_unary_expression()?;
+        while let Ok(token) =
+            self.next_token_if(|token| matches!(token, Token::Asterisk | Token::Slash))
+        {
+            let right = self.parse_unary_expression()?;
+            left = Expression::Operation(match token {
+                Token::Asterisk => Operation::Multiply(Box::new(left), Box::new(right)),
+                Token::Slash => Operation::Divide(Box::new(left), Box::new(right)),
+                _ => unreachable!(), // matches! 宏已经保证了 token 的类型
+            });
+        }
+        Ok(left)
+    }
+
+    /// 解析负号表达式
+    /// 语法：`[-] factor`
+    ///
+    /// 负号紧跟数值时直接解析为负数常量，因此 `-9223372036854775808` 可以表示 `i64::MIN`。
+    fn parse_unary_expression(&mut self) -> Result<Expression> {
+        if self.next_token_equal(Token::Minus).is_err() {
+            return self.parse_primary_expression();
+        }
+        match self.next_token_if(|token| matches!(token, Token::Number(_))) {
+            Ok(Token::Number(num_str)) => Self::parse_number(&num_str, true),
+            _ => Ok(Expression::Negate(Box::new(self.parse_unary_expression()?))),
+        }
+    }
+
+    /// 将数值解析为整数或浮点数常量，`negative` 为真时为负数
+    fn parse_number(num_str: &str, negative: bool) -> Result<Expression> {
+        let sign = if negative { "-" } else { "" };
+        if num_str.chars().all(|ch| ch.is_ascii_digit()) {
+            // 如果数字全部是 0-9，则判断为整数
+            let num = format!("{sign}{num_str}").parse::<i64>()?;
+            Ok(Expression::Constant(Constant::Integer(num)))
+        } else {
+            // 否则为浮点数
+            let num = format!("{sign}{num_str}").parse::<f64>()?;
+            Ok(Expression::Constant(Constant::Float(num)))
+        }
+    }
+
+    /// 解析基本表达式
+    /// 支持的类型：字段、聚集函数、COALESCE 函数、标量函数、CASE 表达式、十进制整数、十进制浮点数、字符串、布尔值、NULL、
+    /// 参数占位符，以及括号包裹的表达式或标量子查询
+    fn parse_primary_expression(&mut self) -> Result<Expression> {
         // 获取下一个 token
         let exp = match self.next_token()? {
+            Token::OpenParen
+                if matches!(self.lexer.peek(), Some(Ok(Token::Keyword(Keyword::Select)))) =>
+            {
+                let subquery = self.parse_query()?;
+                self.next_token_equal(Token::CloseParen)?;
+                Expression::Subquery(Box::new(subquery))
+            }
+            Token::OpenParen => {
+                let exp = self.parse_expression()?;
+                self.next_token_equal(Token::CloseParen)?;
+                exp
+            }
             Token::Identifier(ident) => {
-                if self.next_token_equal(Token::Equal).is_ok() {
-                    let right = self.parse_expression()?;
-                    Expression::Operation(Operation::Equal(
-                        Box::new(Expression::Field(ident)),
-                        Box::new(right),
-                    ))
-                } else if self.next_token_equal(Token::OpenParen).is_ok() {
+                if self.next_token_equal(Token::OpenParen).is_ok() {
+                    // COALESCE 的参数为至少一个任意表达式
+                    if ident.eq_ignore_ascii_case("coalesce") {
+                        let mut args = vec![self.parse_expression()?];
+                        while self.next_token_equal(Token::Comma).is_ok() {
+                            args.push(self.parse_expression()?);
+                        }
+                        self.next_token_equal(Token::CloseParen)?;
+                        return Ok(Expression::Coalesce(args));
+                    }
+                    // 聚集函数之外的函数为标量函数，参数为零个或多个任意表达式
+                    let Ok(aggregate) = Aggregate::try_from(ident.clone()) else {
+                        let mut args = Vec::new();
+                        if self.next_token_equal(Token::CloseParen).is_err() {
+                            args.push(self.parse_expression()?);
+                            while self.next_token_equal(Token::Comma).is_ok() {
+                                args.push(self.parse_expression()?);
+                            }
+                            self.next_token_equal(Token::CloseParen)?;
+                        }
+                        return Ok(Expression::Call(ident, args));
+                    };
+                    let distinct = self
+                        .next_token_equal(Token::Keyword(Keyword::Distinct))
+                        .is_ok();
                     let col_name = if self.next_token_equal(Token::Asterisk).is_ok() {
+                        // 只有 COUNT 可以使用 *，且不能和 DISTINCT 一起使用
+                        if aggregate != Aggregate::Count || distinct {
+                            return Err(Parse(format!(
+                                "{} cannot be applied to *",
+                                Expression::Function(aggregate, "*".to_string(), distinct)
+                            )));
+                        }
                         "*".to_string()
                     } else {
                         self.next_identifier()?
                     };
                     self.next_token_equal(Token::CloseParen)?;
-                    Expression::Function(Aggregate::try_from(ident)?, col_name)
+                    Expression::Function(aggregate, col_name, distinct)
                 } else {
                     Expression::Field(ident)
                 }
             }
-            Token::Number(num_str) => {
-                // 如果是数字，则解析为整数或浮点数
-                if num_str.chars().all(|ch| ch.is_ascii_digit()) {
-                    // 如果数字全部是 0-9，则判断为整数
-                    let num = num_str.parse::<i64>()?;
-                    Expression::Constant(Constant::Integer(num))
-                } else {
-                    // 否则为浮点数
-                    let num = num_str.parse::<f64>()?;
-                    Expression::Constant(Constant::Float(num))
-                }
-            }
-            Token::String(s) => Expression::Constant(Constant::String(s)), // 字符串
+            Token::Number(num_str) => Self::parse_number(&num_str, false)?, // 整数或浮点数
+            Token::String(s) => Expression::Constant(Constant::String(s)),  // 字符串
             Token::Keyword(Keyword::True) => Expression::Constant(Constant::Boolean(true)), // 布尔值 true
             Token::Keyword(Keyword::False) => Expression::Constant(Constant::Boolean(false)), // 布尔值 false
             Token::Keyword(Keyword::Null) => Expression::Constant(Constant::Null), // NULL
-            token => return Err(ParseError(format!("Unexpected token {token}"))), // 其他 token，返回未知的 token 错误
+            Token::Keyword(Keyword::Case) => self.parse_case()?,
+            Token::Parameter(index) => self.parse_parameter(index)?,
+            token => return Err(Parse(format!("Unexpected token {token}"))), // 其他 token，返回未知的 token 错误
         };
         Ok(exp)
     }
 
-    /// 解析 CREATE TABLE 语句
-    /// 语法：CREATE TABLE [table_name] ([column_name] [data_type] [nullable] [default], ...);
-    fn parse_create_table(&mut self) -> Result<Statement> {
+    /// 为参数占位符编号，`?` 按照在语句中出现的顺序从 1 开始编号，一条语句中不能混用 `?` 和 `$n`
+    fn parse_parameter(&mut self, index: Option<usize>) -> Result<Expression> {
+        let index = match index {
+            Some(index) if self.anonymous_parameters == 0 => {
+                self.numbered_parameters = self.numbered_parameters.max(index);
+                index
+            }
+            None if self.numbered_parameters == 0 => {
+                self.anonymous_parameters += 1;
+                self.anonymous_parameters
+            }
+            _ => return Err(Parse("Cannot mix $n and ? parameters".to_string())),
+        };
+        Ok(Expression::Parameter(index))
+    }
+
+    /// 解析 CASE 表达式，`CASE` 已经被读取
+    /// 语法：`CASE [operand] WHEN expr THEN expr [WHEN expr THEN expr ...] [ELSE expr] END`
+    fn parse_case(&mut self) -> Result<Expression> {
+        let operand = match self.lexer.peek() {
+            Some(Ok(Token::Keyword(Keyword::When))) => None,
+            _ => Some(Box::new(self.parse_expression()?)),
+        };
+        let mut branches = Vec::new();
+        while self.next_token_equal(Token::Keyword(Keyword::When)).is_ok() {
+            let when = self.parse_expression()?;
+            self.next_token_equal(Token::Keyword(Keyword::Then))?;
+            branches.push((when, self.parse_expression()?));
+        }
+        if branches.is_empty() {
+            return Err(Parse("CASE must have at least one WHEN branch".to_string()));
+        }
+        let else_result = match self.next_token_equal(Token::Keyword(Keyword::Else)) {
+            Ok(()) => Some(Box::new(self.parse_expression()?)),
+            Err(_) => None,
+        };
+        self.next_token_equal(Token::Keyword(Keyword::End))?;
+        Ok(Expression::Case(operand, branches, else_result))
+    }
+
+    /// 解析 CREATE 语句，根据 CREATE 之后的关键字选择创建表或创建索引
+    fn parse_create(&mut self) -> Result<Statement> {
         self.next_token_equal(Token::Keyword(Keyword::Create))?; // 期望下一个 token 是 CREATE
+        match self
+            .lexer
+            .peek()
+            .ok_or(Parse("Unexpected end of input".to_string()))?
+        {
+            Ok(Token::Keyword(Keyword::Table)) => self.parse_create_table(),
+            Ok(Token::Keyword(Keyword::Index)) | Ok(Token::Keyword(Keyword::Unique)) => {
+                self.parse_create_index()
+            }
+            Ok(token) => Err(Parse(format!("Unexpected token {token}"))),
+            Err(e) => Err(Parse(format!("Lexical error: {e}"))),
+        }
+    }
+
+    /// 解析 DROP TABLE 语句
+    /// 语法：`DROP TABLE [table_name]`
+    fn parse_drop(&mut self) -> Result<Statement> {
+        self.next_token_equal(Token::Keyword(Keyword::Drop))?;
+        self.next_token_equal(Token::Keyword(Keyword::Table))?;
+        let name = self.next_identifier()?;
+        Ok(Statement::DropTable { name })
+    }
+
+    /// 解析 CREATE TABLE 语句（CREATE 已被解析）
+    /// 语法：CREATE TABLE [table_name] ([column_name] [data_type] [nullable] [default] [references], ...);
+    fn parse_create_table(&mut self) -> Result<Statement> {
         self.next_token_equal(Token::Keyword(Keyword::Table))?; // 期望下一个 token 是 TABLE
 
         let table_name = self.next_identifier()?; // 获取表名
         self.next_token_equal(Token::OpenParen)?; // 期望下一个 token 是 (
 
         // 解析 ( 后面的列定义
-        let mut columns = Vec::new();
+        let (mut columns, mut foreign_keys) = (Vec::new(), Vec::new());
         loop {
-            columns.push(self.parse_column()?); // 解析列定义
+            // 解析列定义
+            let (column, foreign_key) = self.parse_column(&table_name)?;
+            columns.push(column);
+            foreign_keys.extend(foreign_key);
             match self.next_token()? {
                 Token::Comma => continue,   // 如果是逗号，继续解析下一个列定义
                 Token::CloseParen => break, // 如果是 )，则列定义解析结束
-                token => return Err(ParseError(format!("Unexpected token {token}"))), // 其他 token，返回错误
+                token => return Err(Parse(format!("Unexpected token {token}"))), // 其他 token，返回错误
             }
         }
         Ok(Statement::CreateTable {
             name: table_name,
             columns,
+            foreign_keys,
+        })
+    }
+
+    /// 解析 CREATE INDEX 语句（CREATE 已被解析）
+    /// 语法：`CREATE [UNIQUE] INDEX [index_name] ON [table_name] ([column_name], ...) [WHERE [condition]];`
+    fn parse_create_index(&mut self) -> Result<Statement> {
+        let unique = self
+            .next_token_equal(Token::Keyword(Keyword::Unique))
+            .is_ok();
+        self.next_token_equal(Token::Keyword(Keyword::Index))?;
+
+        let name = self.next_identifier()?; // 获取索引名
+        self.next_token_equal(Token::Keyword(Keyword::On))?;
+        let table_name = self.next_identifier()?; // 获取表名
+
+        // 解析索引的列名
+        self.next_token_equal(Token::OpenParen)?;
+        let mut columns = Vec::new();
+        loop {
+            columns.push(self.next_identifier()?);
+            match self.next_token()? {
+                Token::Comma => continue,
+                Token::CloseParen => break,
+                token => return Err(Parse(format!("Unexpected token {token}"))),
+            }
+        }
+
+        // 如果有 WHERE 子句，则为部分索引
+        let predicate = self
+            .next_token_equal(Token::Keyword(Keyword::Where))
+            .ok()
+            .map(|_| self.parse_where_clause())
+            .transpose()?;
+
+        Ok(Statement::CreateIndex {
+            name,
+            table_name,
+            columns,
+            unique,
+            predicate,
         })
     }
 
@@ -512,7 +1189,7 @@ impl<'a> Parser<'a> {
                 match self.next_token()? {
                     Token::Comma => continue,   // 如果是逗号，继续获取下一个列名
                     Token::CloseParen => break, // 如果是 )，则列名获取结束
-                    token => return Err(ParseError(format!("Unexpected token {token}"))), // 其他 token，返回错误
+                    token => return Err(Parse(format!("Unexpected token {token}"))), // 其他 token，返回错误
                 }
             }
             Some(columns)
@@ -520,7 +1197,17 @@ impl<'a> Parser<'a> {
             None
         };
 
-        // 期望下一个 token 是 VALUES
+        // 列名之后为查询语句时，插入查询的结果
+        if let Some(Ok(Token::Keyword(Keyword::Select))) = self.lexer.peek() {
+            let query = self.parse_query()?;
+            return Ok(Statement::Insert {
+                table_name,
+                columns,
+                source: InsertSource::Query(Box::new(query)),
+            });
+        }
+
+        // 否则期望下一个 token 是 VALUES
         self.next_token_equal(Token::Keyword(Keyword::Values))?;
 
         // 解析 VALUES 后面的值
@@ -533,7 +1220,7 @@ impl<'a> Parser<'a> {
                 match self.next_token()? {
                     Token::Comma => continue,   // 如果是逗号，继续解析下一个值
                     Token::CloseParen => break, // 如果是 )，则值解析结束
-                    token => return Err(ParseError(format!("Unexpected token {token}"))), // 其他 token，返回错误
+                    token => return Err(Parse(format!("Unexpected token {token}"))), // 其他 token，返回错误
                 }
             }
             values.push(row);
@@ -546,11 +1233,37 @@ impl<'a> Parser<'a> {
         Ok(Statement::Insert {
             table_name,
             columns,
-            values,
+            source: InsertSource::Values(values),
         })
     }
 }
 
+/// 按照分号将脚本切分为语句，返回每条语句从第一个 token 到分号（包含）的字节范围
+///
+/// 字符串中的分号不会切分语句。遇到词法错误时，剩余的部分作为一条语句，由解析时报告错误；
+/// 最后一个分号之后只有空白时忽略，否则剩余的部分作为一条缺少分号的语句。
+fn split_statements(script: &str) -> Vec<Range<usize>> {
+    let mut lexer = Lexer::new(script);
+    let token_start = lexer.token_start();
+    let mut spans = Vec::new();
+    let mut start = None;
+    while let Some(token) = lexer.next() {
+        let statement_start = *start.get_or_insert(token_start.get());
+        match token {
+            Ok(Token::Semicolon) => {
+                spans.push(statement_start..lexer.offset());
+                start = None;
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    if let Some(start) = start {
+        spans.push(start..script.len());
+    }
+    spans
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -600,8 +1313,8 @@ mod tests {
         assert_eq!(
             ordering,
             vec![
-                ("name".to_string(), Ordering::Asc),
-                ("id".to_string(), Ordering::Desc)
+                (Expression::Field("name".to_string()), Ordering::Asc, None),
+                (Expression::Field("id".to_string()), Ordering::Desc, None)
             ]
         );
 
@@ -610,14 +1323,41 @@ mod tests {
         assert_eq!(
             ordering,
             vec![
-                ("name".to_string(), Ordering::Asc),
-                ("id".to_string(), Ordering::Asc)
+                (Expression::Field("name".to_string()), Ordering::Asc, None),
+                (Expression::Field("id".to_string()), Ordering::Asc, None)
             ]
         );
 
         parser = Parser::new("ORDER BY name;");
         let ordering = parser.parse_order_by().unwrap().unwrap();
-        assert_eq!(ordering, vec![("name".to_string(), Ordering::Asc)]);
+        assert_eq!(
+            ordering,
+            vec![(Expression::Field("name".to_string()), Ordering::Asc, None)]
+        );
+
+        parser = Parser::new("ORDER BY age + 1 DESC NULLS FIRST, name NULLS LAST;");
+        let ordering = parser.parse_order_by().unwrap().unwrap();
+        assert_eq!(
+            ordering,
+            vec![
+                (
+                    Expression::Operation(Operation::Add(
+                        Box::new(Expression::Field("age".to_string())),
+                        Box::new(Expression::Constant(Constant::Integer(1))),
+                    )),
+                    Ordering::Desc,
+                    Some(NullsOrder::First)
+                ),
+                (
+                    Expression::Field("name".to_string()),
+                    Ordering::Asc,
+                    Some(NullsOrder::Last)
+                ),
+            ]
+        );
+
+        parser = Parser::new("ORDER BY name NULLS;");
+        assert!(parser.parse_order_by().is_err());
     }
 
     #[test]
@@ -743,6 +1483,7 @@ mod tests {
                         Some("user_id".to_string())
                     ),
                 ],
+                distinct: false,
                 from: SelectFrom::Join {
                     left: Box::new(SelectFrom::Table {
                         name: "table1".to_string()
@@ -756,10 +1497,15 @@ mod tests {
                         Box::new(Expression::Field("table2.name".to_string())),
                     ))),
                 },
-                filter: Some(("id".to_string(), Expression::Constant(Constant::Integer(1)))),
+                filter: Some(Expression::Operation(Operation::Equal(
+                    Box::new(Expression::Field("id".to_string())),
+                    Box::new(Expression::Constant(Constant::Integer(1))),
+                ))),
+                group_by: vec![],
+                having: None,
                 ordering: vec![
-                    ("name".to_string(), Ordering::Desc),
-                    ("id".to_string(), Ordering::Asc)
+                    (Expression::Field("name".to_string()), Ordering::Desc, None),
+                    (Expression::Field("id".to_string()), Ordering::Asc, None)
                 ],
                 limit: Some(Expression::Constant(Constant::Integer(5))),
                 offset: Some(Expression::Constant(Constant::Integer(1))),
@@ -772,10 +1518,13 @@ mod tests {
             statement,
             Statement::Select {
                 columns: vec![],
+                distinct: false,
                 from: SelectFrom::Table {
                     name: "table1".to_string()
                 },
                 filter: None,
+                group_by: vec![],
+                having: None,
                 ordering: vec![],
                 limit: None,
                 offset: None,
@@ -789,7 +1538,8 @@ mod tests {
     #[test]
     fn test_parse_column() {
         let mut parser = Parser::new("name VARCHAR NOT NULL DEFAULT 'hello' PRIMARY KEY)");
-        let column = parser.parse_column().unwrap();
+        let (column, foreign_key) = parser.parse_column("t").unwrap();
+        assert_eq!(foreign_key, None);
         assert_eq!(
             column,
             Column {
@@ -798,6 +1548,7 @@ mod tests {
                 nullable: false,
                 default: Some(Expression::Constant(Constant::String("hello".to_string())).into()),
                 primary_key: true,
+                auto_increment: false,
             }
         );
     }
@@ -828,10 +1579,278 @@ mod tests {
         assert_eq!(exp, Expression::Constant(Constant::Null));
     }
 
+    #[test]
+    fn test_parse_negate_expression() {
+        // 负号紧跟数值时为负数常量，包括 i64::MIN
+        let parse = |input: &str| Parser::new(input).parse_expression().unwrap();
+        assert_eq!(parse("-1"), Expression::Constant(Constant::Integer(-1)));
+        assert_eq!(parse("-1.5"), Expression::Constant(Constant::Float(-1.5)));
+        assert_eq!(
+            parse("-9223372036854775808"),
+            Expression::Constant(Constant::Integer(i64::MIN))
+        );
+        assert!(Parser::new("9223372036854775808")
+            .parse_expression()
+            .is_err());
+
+        // 负号的优先级高于乘除，可以连续使用
+        let negate = |expr: Expression| Expression::Negate(Box::new(expr));
+        let field = |name: &str| Box::new(Expression::Field(name.to_string()));
+        assert_eq!(
+            parse("a - -b * 2"),
+            Expression::Operation(Operation::Subtract(
+                field("a"),
+                Box::new(Expression::Operation(Operation::Multiply(
+                    Box::new(negate(*field("b"))),
+                    Box::new(Expression::Constant(Constant::Integer(2)))
+                )))
+            ))
+        );
+        let expr = parse("-(-(a + 1))");
+        assert_eq!(expr.to_string(), "-(-(a + 1))");
+        assert_eq!(
+            expr,
+            negate(negate(Expression::Operation(Operation::Add(
+                field("a"),
+                Box::new(Expression::Constant(Constant::Integer(1)))
+            ))))
+        );
+    }
+
+    #[test]
+    fn test_parse_condition_expression() {
+        // 优先级：OR < AND < NOT < 比较 < 加减 < 乘除
+        let mut parser = Parser::new("a + 1 * 2 > 2 AND NOT b IS NULL OR c BETWEEN 1 AND 3");
+        let exp = parser.parse_expression().unwrap();
+        assert_eq!(
+            exp.to_string(),
+            "(((a + (1 * 2)) > 2) AND (NOT (b IS NULL))) OR ((c >= 1) AND (c <= 3))"
+        );
+
+        parser = Parser::new("a != 1 AND b <= 2 AND c IS NOT NULL");
+        let exp = parser.parse_expression().unwrap();
+        assert_eq!(
+            exp.to_string(),
+            "((a != 1) AND (b <= 2)) AND (NOT (c IS NULL))"
+        );
+
+        parser = Parser::new("a BETWEEN 1");
+        assert!(parser.parse_expression().is_err());
+
+        parser = Parser::new("coalesce(a, b + 1, NULL) = 2");
+        let exp = parser.parse_expression().unwrap();
+        assert_eq!(exp.to_string(), "COALESCE(a, b + 1, NULL) = 2");
+
+        parser = Parser::new("COALESCE()");
+        assert!(parser.parse_expression().is_err());
+
+        // 子查询只能出现在括号中，IN 之后必须是子查询
+        parser = Parser::new("a NOT IN (SELECT b FROM t) AND c > (SELECT MAX(d) FROM t)");
+        let exp = parser.parse_expression().unwrap();
+        assert_eq!(
+            exp.to_string(),
+            "(NOT (a IN (SELECT ...))) AND (c > (SELECT ...))"
+        );
+        let Expression::Operation(Operation::And(lhs, _)) = exp else {
+            panic!("unexpected expression {:?}", exp);
+        };
+        assert!(matches!(
+            *lhs,
+            Expression::Operation(Operation::Not(ref expr))
+                if matches!(**expr, Expression::InSubquery(_, ref subquery) if matches!(**subquery, Statement::Select { .. }))
+        ));
+
+        for invalid in [
+            "a IN (1, 2)",
+            "a NOT b",
+            "a IN SELECT b FROM t",
+            "(SELECT b FROM t",
+        ] {
+            assert!(
+                Parser::new(invalid).parse_expression().is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_explain() {
+        let mut parser = Parser::new("EXPLAIN SELECT * FROM table1 WHERE id = 1;");
+        let statement = parser.parse().unwrap();
+        assert_eq!(
+            statement,
+            Statement::Explain {
+                statement: Box::new(Statement::Select {
+                    columns: vec![],
+                    distinct: false,
+                    from: SelectFrom::Table {
+                        name: "table1".to_string()
+                    },
+                    filter: Some(Expression::Operation(Operation::Equal(
+                        Box::new(Expression::Field("id".to_string())),
+                        Box::new(Expression::Constant(Constant::Integer(1))),
+                    ))),
+                    group_by: vec![],
+                    having: None,
+                    ordering: vec![],
+                    limit: None,
+                    offset: None,
+                }),
+                analyze: false,
+            }
+        );
+
+        parser = Parser::new("EXPLAIN ANALYZE SELECT * FROM table1;");
+        let Statement::Explain { statement, analyze } = parser.parse().unwrap() else {
+            panic!("EXPLAIN ANALYZE should be parsed as EXPLAIN");
+        };
+        assert!(analyze);
+        assert!(matches!(*statement, Statement::Select { .. }));
+
+        parser = Parser::new("EXPLAIN DELETE FROM table1;");
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_case() -> Result<()> {
+        let parse = |expr: &str| -> Result<Expression> {
+            match Parser::new(&format!("SELECT {expr} FROM t;")).parse()? {
+                Statement::Select { mut columns, .. } => Ok(columns.remove(0).0),
+                statement => panic!("unexpected statement {:?}", statement),
+            }
+        };
+        let field = |name: &str| Box::new(Expression::Field(name.to_string()));
+        let int = |i| Expression::Constant(Constant::Integer(i));
+
+        assert_eq!(
+            parse("CASE x WHEN 1 THEN 10 WHEN 2 THEN 20 END")?,
+            Expression::Case(
+                Some(field("x")),
+                vec![(int(1), int(10)), (int(2), int(20))],
+                None
+            )
+        );
+        let expr = parse("case when x = 0 then 0 else 1 / x end + 1")?;
+        assert_eq!(
+            expr.to_string(),
+            "CASE WHEN x = 0 THEN 0 ELSE 1 / x END + 1"
+        );
+        assert_eq!(parse(&expr.to_string())?, expr);
+
+        for invalid in [
+            "CASE END",
+            "CASE x ELSE 1 END",
+            "CASE WHEN x THEN 1",
+            "CASE WHEN x 1 END",
+        ] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_analyze() {
+        assert_eq!(
+            Parser::new("ANALYZE table1;").parse().unwrap(),
+            Statement::Analyze {
+                table_name: Some("table1".to_string())
+            }
+        );
+        assert_eq!(
+            Parser::new("analyze;").parse().unwrap(),
+            Statement::Analyze { table_name: None }
+        );
+        assert!(Parser::new("ANALYZE table1 table2;").parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_copy() {
+        assert_eq!(
+            Parser::new("COPY t FROM '/tmp/t.csv';").parse().unwrap(),
+            Statement::Copy {
+                table_name: "t".to_string(),
+                path: "/tmp/t.csv".to_string(),
+                options: CsvOptions::default(),
+            }
+        );
+        assert_eq!(
+            Parser::new(
+                "COPY t FROM 't.csv' WITH (HEADER true, DELIMITER '|', NULL '\\N', EMPTY_AS_NULL false, ON_ERROR skip, BATCH_SIZE 10);"
+            )
+            .parse()
+            .unwrap(),
+            Statement::Copy {
+                table_name: "t".to_string(),
+                path: "t.csv".to_string(),
+                options: CsvOptions {
+                    header: true,
+                    delimiter: b'|',
+                    null: "\\N".to_string(),
+                    empty_as_null: false,
+                    on_error: OnError::Skip,
+                    batch_size: 10,
+                },
+            }
+        );
+        assert!(Parser::new("COPY t FROM t.csv;").parse().is_err());
+        assert!(Parser::new("COPY t FROM 't.csv' WITH (DELIMITER ',,');")
+            .parse()
+            .is_err());
+        assert!(Parser::new("COPY t FROM 't.csv' WITH (BATCH_SIZE 0);")
+            .parse()
+            .is_err());
+        assert!(Parser::new("COPY t FROM 't.csv' WITH (FORMAT csv);")
+            .parse()
+            .is_err());
+
+        assert_eq!(
+            Parser::new(
+                "COPY (SELECT a FROM t UNION SELECT b FROM u) TO 'out.csv' WITH (HEADER true);"
+            )
+            .parse()
+            .unwrap(),
+            Statement::CopyTo {
+                query: Box::new(
+                    Parser::new("SELECT a FROM t UNION SELECT b FROM u;")
+                        .parse()
+                        .unwrap()
+                ),
+                path: "out.csv".to_string(),
+                options: CsvOptions {
+                    header: true,
+                    ..Default::default()
+                },
+            }
+        );
+        assert!(Parser::new("COPY (SELECT a FROM t) FROM 'out.csv';")
+            .parse()
+            .is_err());
+        assert!(Parser::new("COPY (DELETE FROM t) TO 'out.csv';")
+            .parse()
+            .is_err());
+        assert!(
+            Parser::new("COPY (SELECT a FROM t) TO 'out.csv' WITH (NULL 1);")
+                .parse()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_transaction() {
+        assert_eq!(Parser::new("BEGIN;").parse().unwrap(), Statement::Begin);
+        assert_eq!(Parser::new("commit;").parse().unwrap(), Statement::Commit);
+        assert_eq!(
+            Parser::new("ROLLBACK;").parse().unwrap(),
+            Statement::Rollback
+        );
+        assert!(Parser::new("BEGIN COMMIT;").parse().is_err());
+    }
+
     #[test]
     fn test_parse_create_table() {
         let mut parser = Parser::new("CREATE TABLE table1 (name VARCHAR NULL DEFAULT 'hello')");
-        let statement = parser.parse_create_table().unwrap();
+        let statement = parser.parse_create().unwrap();
         assert_eq!(
             statement,
             Statement::CreateTable {
@@ -844,12 +1863,14 @@ mod tests {
                         Expression::Constant(Constant::String("hello".to_string())).into()
                     ),
                     primary_key: false,
+                    auto_increment: false,
                 }],
+                foreign_keys: vec![],
             }
         );
 
         parser = Parser::new("CREATE TABLE table1 (id INT PRIMARY KEY, name VARCHAR)");
-        let statement = parser.parse_create_table().unwrap();
+        let statement = parser.parse_create().unwrap();
         assert_eq!(
             statement,
             Statement::CreateTable {
@@ -861,6 +1882,7 @@ mod tests {
                         nullable: false,
                         default: None,
                         primary_key: true,
+                        auto_increment: false,
                     },
                     Column {
                         name: "name".to_string(),
@@ -868,10 +1890,147 @@ mod tests {
                         nullable: false,
                         default: None,
                         primary_key: false,
+                        auto_increment: false,
                     },
                 ],
+                foreign_keys: vec![],
+            }
+        );
+
+        parser = Parser::new(
+            "CREATE TABLE t (id INT PRIMARY KEY, a INT REFERENCES p ON DELETE CASCADE, b INT REFERENCES t)",
+        );
+        let Statement::CreateTable { foreign_keys, .. } = parser.parse_create().unwrap() else {
+            panic!("CREATE TABLE should be parsed as CreateTable");
+        };
+        assert_eq!(
+            foreign_keys,
+            vec![
+                ForeignKey {
+                    name: "fk_t_a".to_string(),
+                    column: "a".to_string(),
+                    parent_table: "p".to_string(),
+                    on_delete: OnDelete::Cascade,
+                },
+                ForeignKey {
+                    name: "fk_t_b".to_string(),
+                    column: "b".to_string(),
+                    parent_table: "t".to_string(),
+                    on_delete: OnDelete::Restrict,
+                },
+            ]
+        );
+
+        parser = Parser::new("CREATE TABLE t (id INT PRIMARY KEY REFERENCES p ON DELETE)");
+        assert!(parser.parse_create().is_err());
+
+        parser = Parser::new("CREATE TABLE t (id INT AUTO_INCREMENT PRIMARY KEY)");
+        let Statement::CreateTable { columns, .. } = parser.parse_create().unwrap() else {
+            panic!("CREATE TABLE should be parsed as CreateTable");
+        };
+        assert!(columns[0].primary_key && columns[0].auto_increment);
+    }
+
+    #[test]
+    fn test_parse_parameter() {
+        let param = |index| Box::new(Expression::Parameter(index));
+        let field = |name: &str| Box::new(Expression::Field(name.to_string()));
+
+        // `$n` 可以重复出现，参数个数为最大编号
+        let mut parser = Parser::new("v > $2 AND id = $2");
+        assert_eq!(
+            parser.parse_expression().unwrap(),
+            Expression::Operation(Operation::And(
+                Box::new(Expression::Operation(Operation::GreaterThan(
+                    field("v"),
+                    param(2)
+                ))),
+                Box::new(Expression::Operation(Operation::Equal(
+                    field("id"),
+                    param(2)
+                ))),
+            ))
+        );
+        assert_eq!(parser.parameter_count(), 2);
+
+        // `?` 按照出现的顺序编号
+        let mut parser = Parser::new("INSERT INTO t VALUES (?, -?);");
+        let Statement::Insert {
+            source: InsertSource::Values(values),
+            ..
+        } = parser.parse().unwrap()
+        else {
+            panic!("INSERT should be parsed as Insert");
+        };
+        assert_eq!(values, vec![vec![*param(1), Expression::Negate(param(2))]]);
+        assert_eq!(parser.parameter_count(), 2);
+        assert_eq!(Parser::new("SELECT * FROM t;").parameter_count(), 0);
+
+        assert!(Parser::new("SELECT * FROM t WHERE a = $1 AND b = ?;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("SELECT * FROM t WHERE a = $0;")
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_drop_table() {
+        assert_eq!(
+            Parser::new("DROP TABLE t;").parse().unwrap(),
+            Statement::DropTable {
+                name: "t".to_string()
+            }
+        );
+        assert!(Parser::new("DROP t;").parse().is_err());
+        assert!(Parser::new("DROP TABLE;").parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_create_index() {
+        let mut parser = Parser::new("CREATE UNIQUE INDEX idx_email ON users (email);");
+        assert_eq!(
+            parser.parse().unwrap(),
+            Statement::CreateIndex {
+                name: "idx_email".to_string(),
+                table_name: "users".to_string(),
+                columns: vec!["email".to_string()],
+                unique: true,
+                predicate: None,
+            }
+        );
+
+        parser = Parser::new("CREATE INDEX idx_city_age ON users (city, age);");
+        assert_eq!(
+            parser.parse().unwrap(),
+            Statement::CreateIndex {
+                name: "idx_city_age".to_string(),
+                table_name: "users".to_string(),
+                columns: vec!["city".to_string(), "age".to_string()],
+                unique: false,
+                predicate: None,
             }
         );
+
+        parser = Parser::new("CREATE INDEX idx_active ON users (email) WHERE status = 'active';");
+        assert_eq!(
+            parser.parse().unwrap(),
+            Statement::CreateIndex {
+                name: "idx_active".to_string(),
+                table_name: "users".to_string(),
+                columns: vec!["email".to_string()],
+                unique: false,
+                predicate: Some(Expression::Operation(Operation::Equal(
+                    Box::new(Expression::Field("status".to_string())),
+                    Box::new(Expression::Constant(Constant::String("active".to_string()))),
+                ))),
+            }
+        );
+
+        parser = Parser::new("CREATE UNIQUE TABLE users (id INT PRIMARY KEY);");
+        assert!(parser.parse().is_err());
+        parser = Parser::new("CREATE INDEX idx ON users ();");
+        assert!(parser.parse().is_err());
     }
 
     #[test]
@@ -883,10 +2042,10 @@ mod tests {
             Statement::Insert {
                 table_name: "table1".to_string(),
                 columns: None,
-                values: vec![vec![
+                source: InsertSource::Values(vec![vec![
                     Expression::Constant(Constant::Integer(1)),
                     Expression::Constant(Constant::String("hello".to_string())),
-                ]],
+                ]]),
             }
         );
 
@@ -897,12 +2056,25 @@ mod tests {
             Statement::Insert {
                 table_name: "table1".to_string(),
                 columns: Some(vec!["id".to_string(), "name".to_string()]),
-                values: vec![vec![
+                source: InsertSource::Values(vec![vec![
                     Expression::Constant(Constant::Integer(1)),
                     Expression::Constant(Constant::String("hello".to_string())),
-                ]],
+                ]]),
             }
         );
+
+        parser = Parser::new(
+            "INSERT INTO table1 (id) SELECT id FROM table2 UNION SELECT id FROM table3",
+        );
+        let statement = parser.parse_insert().unwrap();
+        assert!(matches!(
+            statement,
+            Statement::Insert {
+                source: InsertSource::Query(query),
+                ..
+            } if matches!(*query, Statement::SetOperation { .. })
+        ));
+        assert!(Parser::new("INSERT INTO table1 UPDATE").parse().is_err());
     }
 
     #[test]
@@ -919,7 +2091,10 @@ mod tests {
                 )]
                 .into_iter()
                 .collect(),
-                filter: Some(("id".to_string(), Expression::Constant(Constant::Integer(1)))),
+                filter: Some(Expression::Operation(Operation::Equal(
+                    Box::new(Expression::Field("id".to_string())),
+                    Box::new(Expression::Constant(Constant::Integer(1))),
+                ))),
             }
         );
 
@@ -941,7 +2116,10 @@ mod tests {
                 ]
                 .into_iter()
                 .collect(),
-                filter: Some(("id".to_string(), Expression::Constant(Constant::Integer(1)))),
+                filter: Some(Expression::Operation(Operation::Equal(
+                    Box::new(Expression::Field("id".to_string())),
+                    Box::new(Expression::Constant(Constant::Integer(1))),
+                ))),
             }
         );
 
@@ -957,10 +2135,20 @@ mod tests {
                 )]
                 .into_iter()
                 .collect(),
-                filter: Some(("id".to_string(), Expression::Constant(Constant::Integer(1)))),
+                filter: Some(Expression::Operation(Operation::And(
+                    Box::new(Expression::Operation(Operation::Equal(
+                        Box::new(Expression::Field("id".to_string())),
+                        Box::new(Expression::Constant(Constant::Integer(1))),
+                    ))),
+                    Box::new(Expression::Operation(Operation::Equal(
+                        Box::new(Expression::Field("age".to_string())),
+                        Box::new(Expression::Constant(Constant::Integer(18))),
+                    ))),
+                ))),
             }
         );
 
+        // SET 中的 AND 是值表达式的一部分，而不是多个赋值的分隔符
         parser = Parser::new("UPDATE table1 SET name = 'hello' AND age = 18");
         let statement = parser.parse_update().unwrap();
         assert_eq!(
@@ -969,7 +2157,13 @@ mod tests {
                 table_name: "table1".to_string(),
                 columns: vec![(
                     "name".to_string(),
-                    Expression::Constant(Constant::String("hello".to_string()))
+                    Expression::Operation(Operation::And(
+                        Box::new(Expression::Constant(Constant::String("hello".to_string()))),
+                        Box::new(Expression::Operation(Operation::Equal(
+                            Box::new(Expression::Field("age".to_string())),
+                            Box::new(Expression::Constant(Constant::Integer(18))),
+                        ))),
+                    ))
                 )]
                 .into_iter()
                 .collect(),
@@ -986,7 +2180,10 @@ mod tests {
             statement,
             Statement::Delete {
                 table_name: "table1".to_string(),
-                filter: Some(("id".to_string(), Expression::Constant(Constant::Integer(1))),),
+                filter: Some(Expression::Operation(Operation::Equal(
+                    Box::new(Expression::Field("id".to_string())),
+                    Box::new(Expression::Constant(Constant::Integer(1))),
+                ))),
             }
         );
 
@@ -1009,13 +2206,16 @@ mod tests {
             statement,
             Statement::Select {
                 columns: vec![(
-                    Expression::Function(Aggregate::Count, "*".to_string()),
+                    Expression::Function(Aggregate::Count, "*".to_string(), false),
                     None
                 )],
+                distinct: false,
                 from: SelectFrom::Table {
                     name: "table1".to_string()
                 },
                 filter: None,
+                group_by: vec![],
+                having: None,
                 ordering: vec![],
                 limit: None,
                 offset: None,
@@ -1028,13 +2228,16 @@ mod tests {
             statement,
             Statement::Select {
                 columns: vec![(
-                    Expression::Function(Aggregate::Avg, "age".to_string()),
+                    Expression::Function(Aggregate::Avg, "age".to_string(), false),
                     None
                 )],
+                distinct: false,
                 from: SelectFrom::Table {
                     name: "table1".to_string()
                 },
                 filter: None,
+                group_by: vec![],
+                having: None,
                 ordering: vec![],
                 limit: None,
                 offset: None,
@@ -1047,13 +2250,16 @@ mod tests {
             statement,
             Statement::Select {
                 columns: vec![(
-                    Expression::Function(Aggregate::Sum, "salary".to_string()),
+                    Expression::Function(Aggregate::Sum, "salary".to_string(), false),
                     None
                 )],
+                distinct: false,
                 from: SelectFrom::Table {
                     name: "table1".to_string()
                 },
                 filter: None,
+                group_by: vec![],
+                having: None,
                 ordering: vec![],
                 limit: None,
                 offset: None,
@@ -1066,13 +2272,16 @@ mod tests {
             statement,
             Statement::Select {
                 columns: vec![(
-                    Expression::Function(Aggregate::Min, "age".to_string()),
+                    Expression::Function(Aggregate::Min, "age".to_string(), false),
                     None
                 )],
+                distinct: false,
                 from: SelectFrom::Table {
                     name: "table1".to_string()
                 },
                 filter: None,
+                group_by: vec![],
+                having: None,
                 ordering: vec![],
                 limit: None,
                 offset: None,
@@ -1085,13 +2294,16 @@ mod tests {
             statement,
             Statement::Select {
                 columns: vec![(
-                    Expression::Function(Aggregate::Max, "age".to_string()),
+                    Expression::Function(Aggregate::Max, "age".to_string(), false),
                     None
                 )],
+                distinct: false,
                 from: SelectFrom::Table {
                     name: "table1".to_string()
                 },
                 filter: None,
+                group_by: vec![],
+                having: None,
                 ordering: vec![],
                 limit: None,
                 offset: None,
@@ -1100,5 +2312,219 @@ mod tests {
 
         parser = Parser::new("SELECT INVALID_AGG(*) AS total FROM table1;");
         assert!(parser.parse_select().is_err());
+
+        // DISTINCT 聚集，只有 COUNT 可以使用 *，且不能和 DISTINCT 一起使用
+        parser = Parser::new("SELECT COUNT(DISTINCT age) FROM table1;");
+        let statement = parser.parse_select().unwrap();
+        let Statement::Select { columns, .. } = statement else {
+            panic!("unexpected statement {:?}", statement);
+        };
+        assert_eq!(
+            columns,
+            vec![(
+                Expression::Function(Aggregate::Count, "age".to_string(), true),
+                None
+            )]
+        );
+        assert_eq!(columns[0].0.to_string(), "COUNT(DISTINCT age)");
+        for sql in [
+            "SELECT SUM(*) FROM table1;",
+            "SELECT COUNT(DISTINCT *) FROM table1;",
+        ] {
+            assert!(Parser::new(sql).parse_select().is_err(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn test_parse_group_by() {
+        let mut parser = Parser::new(
+            "SELECT dept, SUM(salary) FROM table1 WHERE age > 20 GROUP BY dept, age / 10
+             HAVING COUNT(*) > 1 ORDER BY dept LIMIT 1;",
+        );
+        let statement = parser.parse_select().unwrap();
+        let Statement::Select {
+            filter,
+            group_by,
+            having,
+            ordering,
+            limit,
+            ..
+        } = statement
+        else {
+            panic!("unexpected statement {:?}", statement);
+        };
+        assert!(filter.is_some());
+        assert_eq!(
+            group_by,
+            vec![
+                Expression::Field("dept".to_string()),
+                Expression::Operation(Operation::Divide(
+                    Box::new(Expression::Field("age".to_string())),
+                    Box::new(Expression::Constant(Constant::Integer(10)))
+                )),
+            ]
+        );
+        assert_eq!(
+            having,
+            Some(Expression::Operation(Operation::GreaterThan(
+                Box::new(Expression::Function(
+                    Aggregate::Count,
+                    "*".to_string(),
+                    false
+                )),
+                Box::new(Expression::Constant(Constant::Integer(1)))
+            )))
+        );
+        assert_eq!(ordering.len(), 1);
+        assert_eq!(limit, Some(Expression::Constant(Constant::Integer(1))));
+
+        // GROUP BY 之后必须有表达式
+        for sql in [
+            "SELECT dept FROM table1 GROUP dept;",
+            "SELECT dept FROM table1 GROUP BY;",
+            "SELECT dept FROM table1 HAVING;",
+        ] {
+            assert!(Parser::new(sql).parse_select().is_err(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn test_parse_select_distinct() {
+        let mut parser = Parser::new("SELECT DISTINCT a + 1 AS b, name FROM table1;");
+        let statement = parser.parse_select().unwrap();
+        let Statement::Select {
+            columns, distinct, ..
+        } = statement
+        else {
+            panic!("unexpected statement {:?}", statement);
+        };
+        assert!(distinct);
+        assert_eq!(
+            columns,
+            vec![
+                (
+                    Expression::Operation(Operation::Add(
+                        Box::new(Expression::Field("a".to_string())),
+                        Box::new(Expression::Constant(Constant::Integer(1)))
+                    )),
+                    Some("b".to_string())
+                ),
+                (Expression::Field("name".to_string()), None),
+            ]
+        );
+
+        let statement = Parser::new("SELECT * FROM table1;").parse_select().unwrap();
+        assert!(matches!(
+            statement,
+            Statement::Select {
+                distinct: false,
+                ..
+            }
+        ));
+        assert!(Parser::new("SELECT DISTINCT FROM table1;")
+            .parse_select()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_set_operation() {
+        // 用表名区分各个 SELECT 语句
+        let table = |stmt: &Statement| match stmt {
+            Statement::Select {
+                from: SelectFrom::Table { name },
+                ..
+            } => name.clone(),
+            _ => panic!("unexpected statement {:?}", stmt),
+        };
+
+        // INTERSECT 优先结合，UNION 和 EXCEPT 从左到右结合
+        let statement = Parser::new(
+            "SELECT * FROM a UNION ALL SELECT * FROM b INTERSECT SELECT * FROM c EXCEPT SELECT * FROM d ORDER BY 1 DESC LIMIT 2;",
+        )
+        .parse()
+        .unwrap();
+        let Statement::SetOperation {
+            operator: SetOperator::Except,
+            all: false,
+            left,
+            right,
+            ordering,
+            limit,
+            offset: None,
+        } = statement
+        else {
+            panic!("unexpected statement {:?}", statement);
+        };
+        assert_eq!(table(&right), "d");
+        assert_eq!(
+            ordering,
+            vec![(
+                Expression::Constant(Constant::Integer(1)),
+                Ordering::Desc,
+                None
+            )]
+        );
+        assert_eq!(limit, Some(Expression::Constant(Constant::Integer(2))));
+        let Statement::SetOperation {
+            operator: SetOperator::Union,
+            all: true,
+            left,
+            right,
+            ordering,
+            ..
+        } = *left
+        else {
+            panic!("unexpected statement {:?}", left);
+        };
+        assert!(ordering.is_empty());
+        assert_eq!(table(&left), "a");
+        let Statement::SetOperation {
+            operator: SetOperator::Intersect,
+            all: false,
+            left,
+            right,
+            ..
+        } = *right
+        else {
+            panic!("unexpected statement {:?}", right);
+        };
+        assert_eq!(
+            (table(&left), table(&right)),
+            ("b".to_string(), "c".to_string())
+        );
+
+        // ORDER BY、LIMIT 和 OFFSET 只能出现在最后一个 SELECT 之后
+        for sql in [
+            "SELECT * FROM a ORDER BY id UNION SELECT * FROM b;",
+            "SELECT * FROM a LIMIT 1 INTERSECT SELECT * FROM b;",
+            "SELECT * FROM a UNION;",
+            "SELECT * FROM a UNION ALL ALL SELECT * FROM b;",
+        ] {
+            assert!(Parser::new(sql).parse().is_err(), "{sql}");
+        }
+
+        // 集合运算可以作为子查询和 EXPLAIN 的语句
+        let statement = Parser::new(
+            "EXPLAIN SELECT * FROM a WHERE id IN (SELECT id FROM b EXCEPT SELECT id FROM c);",
+        )
+        .parse()
+        .unwrap();
+        let Statement::Explain { statement, .. } = statement else {
+            panic!("unexpected statement {:?}", statement);
+        };
+        let Statement::Select {
+            filter: Some(Expression::InSubquery(_, subquery)),
+            ..
+        } = *statement
+        else {
+            panic!("unexpected statement {:?}", statement);
+        };
+        assert!(matches!(
+            *subquery,
+            Statement::SetOperation {
+                operator: SetOperator::Except,
+                ..
+            }
+        ));
     }
 }
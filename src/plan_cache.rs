@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{executor::QueryPlan, Result};
+
+/// 计划缓存默认保存的计划数量
+pub const DEFAULT_PLAN_CACHE_CAPACITY: usize = 128;
+
+/// 查询计划的缓存，按照规范化的 SQL 文本保存计划，由数据库的所有会话共享
+///
+/// 字面量不同的语句的计划不同，需要共享计划的语句应当预处理后以参数执行：预处理语句的计划保留占位符，
+/// 以带占位符的 SQL 文本为键，执行时绑定参数。
+/// 缓存满时淘汰最久没有被使用的计划。计划中保存了表的定义，修改表定义的语句需要通过
+/// [`PlanCache::invalidate_table`] 清除读取这张表的计划，否则计划会按照旧的定义读取数据。
+#[derive(Debug)]
+pub struct PlanCache {
+    inner: Mutex<CacheInner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// 计划缓存的命中统计
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlanCacheStats {
+    /// 直接使用缓存的计划执行的次数
+    pub hits: u64,
+    /// 查询语句没有缓存的计划，重新解析和计划的次数
+    pub misses: u64,
+    /// 当前缓存的计划数量
+    pub entries: usize,
+}
+
+#[derive(Debug)]
+struct CacheInner {
+    capacity: usize,
+    /// 每次访问递增的时钟，用于确定最久没有被使用的计划
+    clock: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    plan: Arc<QueryPlan>,
+    /// 最后一次访问时的时钟
+    last_used: u64,
+}
+
+impl PlanCache {
+    /// 创建最多保存 `capacity` 个计划的缓存，`capacity` 为 0 时不缓存任何计划
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(CacheInner {
+                capacity,
+                clock: 0,
+                entries: HashMap::new(),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// 查找 SQL 文本对应的计划，找到时计入一次命中
+    pub(crate) fn get(&self, sql: &str) -> Result<Option<Arc<QueryPlan>>> {
+        let mut inner = self.inner.lock()?;
+        inner.clock += 1;
+        let clock = inner.clock;
+        let plan = inner.entries.get_mut(&normalize(sql)).map(|entry| {
+            entry.last_used = clock;
+            entry.plan.clone()
+        });
+        if plan.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(plan)
+    }
+
+    /// 计入一次未命中，查询语句没有缓存的计划时调用，无论计划之后能否被缓存
+    pub(crate) fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 保存 SQL 文本对应的计划，缓存满时淘汰最久没有被使用的计划
+    pub(crate) fn insert(&self, sql: &str, plan: Arc<QueryPlan>) -> Result<()> {
+        let mut inner = self.inner.lock()?;
+        if inner.capacity == 0 {
+            return Ok(());
+        }
+        let key = normalize(sql);
+        if !inner.entries.contains_key(&key) && inner.entries.len() >= inner.capacity {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+            }
+        }
+        inner.clock += 1;
+        let last_used = inner.clock;
+        inner.entries.insert(key, CacheEntry { plan, last_used });
+        Ok(())
+    }
+
+    /// 清除所有读取表 `table_name` 的计划
+    pub fn invalidate_table(&self, table_name: &str) -> Result<()> {
+        self.inner
+            .lock()?
+            .entries
+            .retain(|_, entry| !entry.plan.tables.iter().any(|name| name == table_name));
+        Ok(())
+    }
+
+    /// 清除所有计划，命中统计保持不变
+    pub fn clear(&self) -> Result<()> {
+        self.inner.lock()?.entries.clear();
+        Ok(())
+    }
+
+    /// 缓存的命中统计
+    pub fn stats(&self) -> Result<PlanCacheStats> {
+        Ok(PlanCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.inner.lock()?.entries.len(),
+        })
+    }
+}
+
+/// 规范化 SQL 文本：去掉首尾的空白，将字符串字面量之外的连续空白合并为一个空格
+fn normalize(sql: &str) -> String {
+    let mut normalized = String::with_capacity(sql.len());
+    let mut in_string = false;
+    let mut pending_space = false;
+    for c in sql.trim().chars() {
+        if !in_string && c.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+        if pending_space {
+            normalized.push(' ');
+            pending_space = false;
+        }
+        if c == '\'' {
+            in_string = !in_string;
+        }
+        normalized.push(c);
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("  SELECT  *\n\tFROM t   WHERE v = 'a  b' ; "),
+            "SELECT * FROM t WHERE v = 'a  b' ;"
+        );
+        assert_eq!(normalize("SELECT 'a',  ' b ';"), "SELECT 'a', ' b ';");
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    parser::ast::{Expression, OrderBy},
+    schema::Value,
+    storage::Storage,
+    Result,
+};
+
+use super::{build_access, Node, Planner};
+
+impl<S: Storage> Planner<'_, S> {
+    /// 将计划中的参数占位符替换为 `params` 中对应的值，`$n` 对应 `params[n - 1]`
+    ///
+    /// 预处理的查询语句在绑定参数之前计划，占位符不能作为访问路径的边界，因此条件中有占位符的全表扫描
+    /// 在绑定之后按照代入的值重新选择访问路径，和直接计划代入了值的语句相同。其他节点只替换表达式。
+    pub(crate) fn bind_parameters(&self, node: Node, params: &[Value]) -> Result<Node> {
+        let bind_all = |exprs: Vec<Expression>| {
+            exprs
+                .into_iter()
+                .map(|expr| expr.bind(params))
+                .collect::<Result<Vec<_>>>()
+        };
+        let bind_option = |expr: Option<Expression>| expr.map(|expr| expr.bind(params)).transpose();
+        let bind_child = |node: Box<Node>| self.bind_parameters(*node, params).map(Box::new);
+
+        let node = match node {
+            Node::Scan {
+                table,
+                filter: Some(filter),
+                needed,
+                ..
+            } if filter.has_parameter() => {
+                let stats = self.transaction.get_stats(&table.name)?;
+                match build_access(table, stats.as_ref(), Some(filter.bind(params)?))? {
+                    // 条件引用的列没有变化，仍然只需要解码裁剪后的列
+                    Node::Scan {
+                        table,
+                        filter,
+                        estimate,
+                        ..
+                    } => Node::Scan {
+                        table,
+                        filter,
+                        needed,
+                        estimate,
+                    },
+                    node => node,
+                }
+            }
+            Node::Scan { .. }
+            | Node::KeyLookup { .. }
+            | Node::KeyRangeScan { .. }
+            | Node::IndexScan { .. }
+            | Node::Empty { .. } => node,
+            Node::NestedLoopJoin {
+                left,
+                right,
+                join_type,
+                predicate,
+            } => Node::NestedLoopJoin {
+                left: bind_child(left)?,
+                right: bind_child(right)?,
+                join_type,
+                predicate: bind_option(predicate)?,
+            },
+            Node::HashJoin {
+                left,
+                right,
+                join_type,
+                left_key,
+                right_key,
+                predicate,
+            } => Node::HashJoin {
+                left: bind_child(left)?,
+                right: bind_child(right)?,
+                join_type,
+                left_key,
+                right_key,
+                predicate: bind_option(predicate)?,
+            },
+            Node::Filter { source, predicate } => Node::Filter {
+                source: bind_child(source)?,
+                predicate: predicate.bind(params)?,
+            },
+            Node::Order {
+                source,
+                ordering,
+                limit,
+            } => Node::Order {
+                source: bind_child(source)?,
+                ordering: ordering
+                    .into_iter()
+                    .map(|(expr, order, nulls)| Ok((expr.bind(params)?, order, nulls)))
+                    .collect::<Result<Vec<OrderBy>>>()?,
+                limit,
+            },
+            Node::Limit {
+                source,
+                offset,
+                limit,
+            } => Node::Limit {
+                source: bind_child(source)?,
+                offset,
+                limit,
+            },
+            Node::Distinct { source, sorted } => Node::Distinct {
+                source: bind_child(source)?,
+                sorted,
+            },
+            Node::Projection { source, columns } => Node::Projection {
+                source: bind_child(source)?,
+                columns: columns
+                    .into_iter()
+                    .map(|(expr, alias)| Ok((expr.bind(params)?, alias)))
+                    .collect::<Result<_>>()?,
+            },
+            Node::Aggregate {
+                source,
+                group_by,
+                aggregates,
+            } => Node::Aggregate {
+                source: bind_child(source)?,
+                group_by: bind_all(group_by)?,
+                aggregates,
+            },
+            Node::SetOperation {
+                left,
+                right,
+                operator,
+                all,
+                types,
+            } => Node::SetOperation {
+                left: bind_child(left)?,
+                right: bind_child(right)?,
+                operator,
+                all,
+                types,
+            },
+        };
+        Ok(node)
+    }
+}
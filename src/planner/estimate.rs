@@ -0,0 +1,92 @@
+use std::ops::Bound;
+
+use crate::{
+    parser::ast::{Expression, Operation},
+    schema::Table,
+    stats::{TableStats, DEFAULT_SELECTIVITY},
+};
+
+use super::{column_bound, AccessPath};
+
+/// 通过二级索引读取一行的代价相对于顺序扫描一行的倍数，索引扫描需要先读取索引项，再按照主键查找行
+const INDEX_LOOKUP_COST: f64 = 4.0;
+
+/// 根据统计信息估计条件 `expr` 的选择率
+///
+/// 列和常量的比较使用列的统计信息，`AND`、`OR` 和 `NOT` 假设各个条件相互独立，其他条件使用默认的选择率。
+pub(super) fn selectivity(table: &Table, stats: &TableStats, expr: &Expression) -> f64 {
+    match expr {
+        Expression::Operation(Operation::And(lhs, rhs)) => {
+            selectivity(table, stats, lhs) * selectivity(table, stats, rhs)
+        }
+        Expression::Operation(Operation::Or(lhs, rhs)) => {
+            let lhs = selectivity(table, stats, lhs);
+            let rhs = selectivity(table, stats, rhs);
+            lhs + rhs - lhs * rhs
+        }
+        Expression::Operation(Operation::Not(expr)) => 1.0 - selectivity(table, stats, expr),
+        Expression::Operation(Operation::IsNull(expr)) => expr
+            .as_field()
+            .and_then(|name| {
+                let col_name = name
+                    .strip_prefix(&format!("{}.", table.name))
+                    .unwrap_or(name);
+                table.get_col_idx(col_name)
+            })
+            .map_or(DEFAULT_SELECTIVITY, |col_idx| {
+                stats.columns[col_idx].null_fraction
+            }),
+        expr => table
+            .columns
+            .iter()
+            .zip(&stats.columns)
+            .find_map(|(column, column_stats)| {
+                column_bound(table, &column.name, expr)
+                    .map(|range| column_stats.range_selectivity(&range))
+            })
+            .unwrap_or(DEFAULT_SELECTIVITY),
+    }
+}
+
+/// 估计访问路径读取的行占表的比例
+pub(super) fn access_selectivity(table: &Table, stats: &TableStats, path: &AccessPath) -> f64 {
+    let column_stats = |col_name: &str| {
+        table
+            .get_col_idx(col_name)
+            .map(|col_idx| &stats.columns[col_idx])
+    };
+    match path {
+        AccessPath::Key(range) => column_stats(&table.get_primary_key_column().name)
+            .map_or(DEFAULT_SELECTIVITY, |stats| stats.range_selectivity(range)),
+        AccessPath::Index(index, prefix, range) => {
+            let mut selectivity = 1.0;
+            for (col_name, value) in index.columns.iter().zip(prefix) {
+                selectivity *= column_stats(col_name)
+                    .map_or(DEFAULT_SELECTIVITY, |stats| stats.equal_selectivity(value));
+            }
+            if let Some(col_name) = index.columns.get(prefix.len()) {
+                if !matches!(range, (Bound::Unbounded, Bound::Unbounded)) {
+                    selectivity *= column_stats(col_name)
+                        .map_or(DEFAULT_SELECTIVITY, |stats| stats.range_selectivity(range));
+                }
+            }
+            selectivity
+        }
+    }
+}
+
+/// 估计访问路径的代价，以顺序扫描一行为单位，全表扫描的代价即为表的行数
+///
+/// 主键范围扫描按照主键顺序读取，代价为读取的行数；二级索引扫描每一行都需要回表，代价乘以 [`INDEX_LOOKUP_COST`]。
+pub(super) fn access_cost(table: &Table, stats: &TableStats, path: &AccessPath) -> f64 {
+    let rows = stats.row_count as f64 * access_selectivity(table, stats, path);
+    match path {
+        AccessPath::Key(_) => rows,
+        AccessPath::Index(..) => rows * INDEX_LOOKUP_COST,
+    }
+}
+
+/// 由选择率得到估计的行数
+pub(super) fn rows(stats: &TableStats, selectivity: f64) -> usize {
+    (stats.row_count as f64 * selectivity).round() as usize
+}
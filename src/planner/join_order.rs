@@ -0,0 +1,418 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{
+    executor::expression::get_column_index_by_name,
+    parser::ast::{Expression, JoinType, Operation},
+    schema::Table,
+    storage::Storage,
+    Result,
+};
+
+use super::{build_join, equi_join_keys, join_conjunction, split_conjunction, Node, Planner};
+
+/// 过滤条件的选择率的倒数，没有统计信息时假设每个条件保留三分之一的行
+const FILTER_SELECTIVITY: usize = 3;
+
+/// 参与重新排序的一个连接输入
+struct JoinInput {
+    node: Node,
+    columns: Vec<String>,
+    rows: usize,
+}
+
+impl<S: Storage> Planner<'_, S> {
+    /// 贪心地选择多表内连接的顺序
+    ///
+    /// 三张及以上的表通过 INNER 或 CROSS JOIN 连接时，将它们视为一组输入和一组连接条件，重新构建左深的连接树：
+    ///
+    /// 1. 从估计行数最少的输入开始；
+    /// 2. 每一步优先选择与已连接部分存在等值条件的输入，其次是存在其他连接条件的输入，最后才是笛卡尔积，
+    ///    同一类中选择估计行数最少的输入；
+    /// 3. 所有字段都可以在已连接部分中解析的条件作为这次连接的条件。
+    ///
+    /// 外连接不参与重新排序，只作为一个整体的输入，其内部的内连接单独排序。
+    /// 连接之上的过滤条件也可以作为连接条件。重新排序后输出列的顺序与原来相同。
+    pub(super) fn reorder_joins(&self, node: Node) -> Result<Node> {
+        let row_counts = RefCell::new(HashMap::new());
+        self.reorder(node, &row_counts)
+    }
+
+    fn reorder(&self, node: Node, row_counts: &RefCell<HashMap<String, usize>>) -> Result<Node> {
+        Ok(match node {
+            Node::Filter { source, predicate } if is_inner_join_group(&source) => {
+                self.reorder_group(*source, Some(predicate), row_counts)?
+            }
+            node if is_inner_join_group(&node) => self.reorder_group(node, None, row_counts)?,
+            node => self.reorder_children(node, row_counts)?,
+        })
+    }
+
+    /// 重新选择子节点中的连接顺序
+    fn reorder_children(
+        &self,
+        node: Node,
+        row_counts: &RefCell<HashMap<String, usize>>,
+    ) -> Result<Node> {
+        Ok(match node {
+            Node::NestedLoopJoin {
+                left,
+                right,
+                join_type,
+                predicate,
+            } => Node::NestedLoopJoin {
+                left: Box::new(self.reorder(*left, row_counts)?),
+                right: Box::new(self.reorder(*right, row_counts)?),
+                join_type,
+                predicate,
+            },
+            Node::HashJoin {
+                left,
+                right,
+                join_type,
+                left_key,
+                right_key,
+                predicate,
+            } => Node::HashJoin {
+                left: Box::new(self.reorder(*left, row_counts)?),
+                right: Box::new(self.reorder(*right, row_counts)?),
+                join_type,
+                left_key,
+                right_key,
+                predicate,
+            },
+            Node::Filter { source, predicate } => Node::Filter {
+                source: Box::new(self.reorder(*source, row_counts)?),
+                predicate,
+            },
+            Node::Order {
+                source,
+                ordering,
+                limit,
+            } => Node::Order {
+                source: Box::new(self.reorder(*source, row_counts)?),
+                ordering,
+                limit,
+            },
+            Node::Limit {
+                source,
+                offset,
+                limit,
+            } => Node::Limit {
+                source: Box::new(self.reorder(*source, row_counts)?),
+                offset,
+                limit,
+            },
+            Node::Distinct { source, sorted } => Node::Distinct {
+                source: Box::new(self.reorder(*source, row_counts)?),
+                sorted,
+            },
+            Node::Projection { source, columns } => Node::Projection {
+                source: Box::new(self.reorder(*source, row_counts)?),
+                columns,
+            },
+            Node::Aggregate {
+                source,
+                group_by,
+                aggregates,
+            } => Node::Aggregate {
+                source: Box::new(self.reorder(*source, row_counts)?),
+                group_by,
+                aggregates,
+            },
+            node @ (Node::Scan { .. }
+            | Node::KeyLookup { .. }
+            | Node::KeyRangeScan { .. }
+            | Node::IndexScan { .. }
+            | Node::Empty { .. }
+            | Node::SetOperation { .. }) => node,
+        })
+    }
+
+    /// 重新排序一组内连接，`filter` 为连接之上的过滤条件
+    fn reorder_group(
+        &self,
+        node: Node,
+        filter: Option<Expression>,
+        row_counts: &RefCell<HashMap<String, usize>>,
+    ) -> Result<Node> {
+        let columns = node.columns();
+
+        // 同一张表出现多次时完整的列名也无法区分，保持原来的顺序
+        let distinct_columns = columns.iter().collect::<HashSet<_>>();
+        if distinct_columns.len() != columns.len() {
+            let node = self.reorder_children(node, row_counts)?;
+            return Ok(match filter {
+                Some(predicate) => Node::Filter {
+                    source: Box::new(node),
+                    predicate,
+                },
+                None => node,
+            });
+        }
+
+        // 收集所有的输入和连接条件，条件中的字段替换为完整的列名，避免换了连接顺序之后解析到其他列
+        let mut inputs = Vec::new();
+        let mut predicates = Vec::new();
+        self.collect_group(node, &mut inputs, &mut predicates, row_counts)?;
+
+        // 过滤条件中无法解析的字段在执行时报错，这样的条件留在原来的位置
+        let mut residual = Vec::new();
+        if let Some(filter) = filter {
+            let mut conjuncts = Vec::new();
+            split_conjunction(filter, &mut conjuncts);
+            for expr in conjuncts {
+                match qualify(expr.clone(), &columns) {
+                    Ok(expr) => predicates.push(expr),
+                    Err(_) => residual.push(expr),
+                }
+            }
+        }
+
+        // 从估计行数最少的输入开始，行数相同时保持原来的顺序
+        let first = (0..inputs.len())
+            .min_by_key(|&i| inputs[i].rows)
+            .unwrap_or_default();
+        let mut joined = inputs.remove(first);
+        while !inputs.is_empty() {
+            // 优先级：0 为存在等值条件，1 为存在其他连接条件，2 为笛卡尔积
+            let priority = |input: &JoinInput| {
+                let connected = predicates
+                    .iter()
+                    .filter(|expr| references_both(expr, &joined.columns, &input.columns));
+                let mut priority = 2;
+                for expr in connected {
+                    if equi_join_keys(expr, &joined.columns, &input.columns).is_some() {
+                        return 0;
+                    }
+                    priority = 1;
+                }
+                priority
+            };
+            let next = (0..inputs.len())
+                .min_by_key(|&i| (priority(&inputs[i]), inputs[i].rows))
+                .unwrap_or_default();
+            let input = inputs.remove(next);
+
+            let columns = [joined.columns.as_slice(), input.columns.as_slice()].concat();
+            let (applicable, pending) = predicates
+                .into_iter()
+                .partition(|expr| resolves_in(expr, &columns));
+            predicates = pending;
+            let predicate = join_conjunction(applicable);
+            let join_type = match predicate {
+                Some(_) => JoinType::Inner,
+                None => JoinType::Cross,
+            };
+            joined = JoinInput {
+                rows: joined.rows.max(input.rows),
+                node: build_join(joined.node, input.node, join_type, predicate)?,
+                columns,
+            };
+        }
+
+        // 按照原来的顺序输出列，列名使用完整的列名，使得之上的节点仍然可以按照原来的方式解析
+        let mut node = joined.node;
+        if joined.columns != columns {
+            node = Node::Projection {
+                source: Box::new(node),
+                columns: columns
+                    .into_iter()
+                    .map(|name| (Expression::Field(name.clone()), Some(name)))
+                    .collect(),
+            };
+        }
+        residual.extend(predicates);
+        Ok(match join_conjunction(residual) {
+            Some(predicate) => Node::Filter {
+                source: Box::new(node),
+                predicate,
+            },
+            None => node,
+        })
+    }
+
+    /// 收集一组内连接的输入和连接条件
+    fn collect_group(
+        &self,
+        node: Node,
+        inputs: &mut Vec<JoinInput>,
+        predicates: &mut Vec<Expression>,
+        row_counts: &RefCell<HashMap<String, usize>>,
+    ) -> Result<()> {
+        let columns = node.columns();
+        let (left, right, predicate) = match node {
+            Node::NestedLoopJoin {
+                left,
+                right,
+                join_type: JoinType::Inner | JoinType::Cross,
+                predicate,
+            } => (left, right, predicate),
+            Node::HashJoin {
+                left,
+                right,
+                join_type: JoinType::Inner,
+                left_key,
+                right_key,
+                predicate,
+            } => {
+                let equal = Expression::Operation(Operation::Equal(
+                    Box::new(Expression::Field(columns[left_key].clone())),
+                    Box::new(Expression::Field(
+                        columns[left.columns().len() + right_key].clone(),
+                    )),
+                ));
+                predicates.push(equal);
+                (left, right, predicate)
+            }
+            node => {
+                let node = self.reorder(node, row_counts)?;
+                inputs.push(JoinInput {
+                    columns: node.columns(),
+                    rows: self.estimate_rows(&node, row_counts)?,
+                    node,
+                });
+                return Ok(());
+            }
+        };
+        if let Some(predicate) = predicate {
+            let mut conjuncts = Vec::new();
+            split_conjunction(predicate, &mut conjuncts);
+            for expr in conjuncts {
+                predicates.push(qualify(expr, &columns)?);
+            }
+        }
+        self.collect_group(*left, inputs, predicates, row_counts)?;
+        self.collect_group(*right, inputs, predicates, row_counts)
+    }
+
+    /// 估计节点输出的行数
+    ///
+    /// 访问单表的节点有根据统计信息的估计时直接使用，否则表的行数在计划时扫描得到，每个过滤条件按照固定的选择率估计，
+    /// 外连接取两侧较大的行数。
+    fn estimate_rows(
+        &self,
+        node: &Node,
+        row_counts: &RefCell<HashMap<String, usize>>,
+    ) -> Result<usize> {
+        Ok(match node {
+            Node::Scan {
+                estimate: Some(rows),
+                ..
+            }
+            | Node::KeyRangeScan {
+                estimate: Some(rows),
+                ..
+            }
+            | Node::IndexScan {
+                estimate: Some(rows),
+                ..
+            } => *rows,
+            Node::Scan { table, filter, .. } => match filter {
+                Some(_) => self.count_rows(table, row_counts)? / FILTER_SELECTIVITY,
+                None => self.count_rows(table, row_counts)?,
+            },
+            Node::KeyLookup { .. } => 1,
+            Node::KeyRangeScan { table, .. } | Node::IndexScan { table, .. } => {
+                self.count_rows(table, row_counts)? / FILTER_SELECTIVITY
+            }
+            Node::NestedLoopJoin { left, right, .. } | Node::HashJoin { left, right, .. } => self
+                .estimate_rows(left, row_counts)?
+                .max(self.estimate_rows(right, row_counts)?),
+            Node::Filter { source, .. } => {
+                self.estimate_rows(source, row_counts)? / FILTER_SELECTIVITY
+            }
+            Node::Limit {
+                source,
+                limit: Some(limit),
+                ..
+            }
+            | Node::Order {
+                source,
+                limit: Some(limit),
+                ..
+            } => self.estimate_rows(source, row_counts)?.min(*limit),
+            Node::Limit { source, .. }
+            | Node::Order { source, .. }
+            | Node::Distinct { source, .. }
+            | Node::Projection { source, .. }
+            | Node::Aggregate { source, .. } => self.estimate_rows(source, row_counts)?,
+            Node::Empty { .. } => 0,
+            Node::SetOperation { left, right, .. } => {
+                self.estimate_rows(left, row_counts)? + self.estimate_rows(right, row_counts)?
+            }
+        })
+    }
+
+    /// 扫描表得到表的行数，同一次计划中每张表只扫描一次
+    fn count_rows(
+        &self,
+        table: &Table,
+        row_counts: &RefCell<HashMap<String, usize>>,
+    ) -> Result<usize> {
+        if let Some(count) = row_counts.borrow().get(&table.name) {
+            return Ok(*count);
+        }
+        let mut count = 0;
+        for row in self.transaction.scan_table_iter(table, None) {
+            row?;
+            count += 1;
+        }
+        row_counts.borrow_mut().insert(table.name.clone(), count);
+        Ok(count)
+    }
+}
+
+/// 节点是否是三个及以上输入的内连接
+fn is_inner_join_group(node: &Node) -> bool {
+    fn count_inputs(node: &Node) -> usize {
+        match node {
+            Node::NestedLoopJoin {
+                left,
+                right,
+                join_type: JoinType::Inner | JoinType::Cross,
+                ..
+            }
+            | Node::HashJoin {
+                left,
+                right,
+                join_type: JoinType::Inner,
+                ..
+            } => count_inputs(left) + count_inputs(right),
+            _ => 1,
+        }
+    }
+    count_inputs(node) >= 3
+}
+
+/// 将表达式中的字段替换为 `columns` 中完整的列名
+fn qualify(expr: Expression, columns: &[String]) -> Result<Expression> {
+    expr.transform(&mut |expr| match expr {
+        Expression::Field(name) => Ok(Expression::Field(
+            columns[get_column_index_by_name(columns, &name)?].clone(),
+        )),
+        expr => Ok(expr),
+    })
+}
+
+/// 表达式中的字段是否都可以在 `columns` 中解析
+fn resolves_in(expr: &Expression, columns: &[String]) -> bool {
+    let mut fields = Vec::new();
+    expr.collect_fields(&mut fields);
+    fields
+        .iter()
+        .all(|field| get_column_index_by_name(columns, field).is_ok())
+}
+
+/// 表达式是否同时引用了两侧的字段，并且只引用这两侧的字段
+fn references_both(expr: &Expression, left: &[String], right: &[String]) -> bool {
+    let mut fields = Vec::new();
+    expr.collect_fields(&mut fields);
+    let in_left = |field: &String| left.contains(field);
+    let in_right = |field: &String| right.contains(field);
+    fields.iter().any(|field| in_left(field))
+        && fields.iter().any(|field| in_right(field))
+        && fields.iter().all(|field| in_left(field) || in_right(field))
+}
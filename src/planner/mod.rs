@@ -0,0 +1,1520 @@
+mod bind;
+mod estimate;
+mod join_order;
+mod prune;
+mod pushdown;
+mod simplify;
+
+use std::{fmt::Display, ops::Bound};
+
+use prune::prune_columns;
+pub(crate) use simplify::column_types;
+
+use crate::{
+    engine::Transaction,
+    error::{
+        ExecutionError::TypeMismatch,
+        PlanError::{Invalid, Unsupported},
+        Result,
+        SchemaError::TableNotFound,
+    },
+    executor::expression::{evaluate, get_column_index_by_name, infer_type},
+    parser::ast::{
+        Constant, Expression, JoinType, Operation, OrderBy, SelectFrom, SetOperator, Statement,
+    },
+    schema::{DataType, IndexDef, Table, Value},
+    stats::TableStats,
+    storage::Storage,
+};
+
+/// 值的范围，依次为下界和上界
+pub type ValueRange = (Bound<Value>, Bound<Value>);
+
+/// 执行计划节点
+///
+/// 执行器自底向上执行计划树，每个节点输出列名和行数据，列名为 `table_name.col_name` 的形式。
+/// 访问单表的节点中的 `estimate` 为根据统计信息估计的输出行数，表没有统计信息时为 `None`。
+#[derive(Debug, Clone)]
+pub enum Node {
+    /// 全表扫描，`filter` 在扫描时直接过滤
+    ///
+    /// `needed` 为需要解码的列的下标，其他列不解码而是输出 NULL，为 `None` 时解码所有列，见 [`prune_columns`]
+    Scan {
+        table: Table,
+        filter: Option<Expression>,
+        needed: Option<Vec<usize>>,
+        estimate: Option<usize>,
+    },
+    /// 根据主键查找单行
+    KeyLookup { table: Table, key: Value },
+    /// 扫描主键在范围内的行
+    KeyRangeScan {
+        table: Table,
+        range: ValueRange,
+        estimate: Option<usize>,
+    },
+    /// 通过二级索引扫描行，`prefix` 为索引前若干列的等值，`range` 为下一列的范围
+    IndexScan {
+        table: Table,
+        index: IndexDef,
+        prefix: Vec<Value>,
+        range: ValueRange,
+        estimate: Option<usize>,
+    },
+    /// 嵌套循环连接两个子节点，`predicate` 为 `None` 时即为 CROSS JOIN
+    NestedLoopJoin {
+        left: Box<Node>,
+        right: Box<Node>,
+        join_type: JoinType,
+        predicate: Option<Expression>,
+    },
+    /// 哈希连接两个子节点
+    ///
+    /// `left_key` 和 `right_key` 为等值连接条件的两个字段分别在左右子节点输出中的下标，
+    /// `predicate` 为其余的连接条件，在拼接后的行上计算
+    HashJoin {
+        left: Box<Node>,
+        right: Box<Node>,
+        join_type: JoinType,
+        left_key: usize,
+        right_key: usize,
+        predicate: Option<Expression>,
+    },
+    /// 过滤子节点的行，只保留 `predicate` 为 `TRUE` 的行
+    Filter {
+        source: Box<Node>,
+        predicate: Expression,
+    },
+    /// 排序，只需要排序后的前 `limit` 行时，使用有界堆代替完整的排序
+    Order {
+        source: Box<Node>,
+        ordering: Vec<OrderBy>,
+        limit: Option<usize>,
+    },
+    /// 跳过前 `offset` 行，最多保留 `limit` 行
+    Limit {
+        source: Box<Node>,
+        offset: usize,
+        limit: Option<usize>,
+    },
+    /// 对子节点的行去重，`sorted` 为真时子节点的输出已经按照所有列排序，重复的行一定相邻
+    Distinct { source: Box<Node>, sorted: bool },
+    /// 不输出任何行，用于替换过滤条件恒为假的子树
+    Empty { columns: Vec<String> },
+    /// 选择列
+    Projection {
+        source: Box<Node>,
+        columns: Vec<(Expression, Option<String>)>,
+    },
+    /// 按照 `group_by` 分组，计算每个分组的聚集函数 `aggregates`
+    Aggregate {
+        source: Box<Node>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<Expression>,
+    },
+    /// 集合运算，`all` 为假时对结果去重
+    ///
+    /// `types` 为两侧统一后每一列的类型，为 `Float` 的列中的整数在运算前转为浮点数
+    SetOperation {
+        left: Box<Node>,
+        right: Box<Node>,
+        operator: SetOperator,
+        all: bool,
+        types: Vec<Option<DataType>>,
+    },
+}
+
+impl Node {
+    /// 节点输出的列名
+    ///
+    /// - 访问单表的节点输出表的所有列，列名为 `table_name.col_name`；
+    /// - 连接节点输出左子节点的列，之后紧跟右子节点的列，Join 条件以及之上的节点中的列名
+    ///   （`col_name` 或 `table_name.col_name`）都在这个列布局中解析；
+    /// - `Filter`、`Order`、`Limit`、`Distinct` 与子节点相同，`Empty` 与被替换的子树相同；
+    /// - `Projection` 输出别名，没有别名时为 `col_name` 或 `agg(col_name)`；
+    /// - `Aggregate` 输出分组的列，之后紧跟聚集函数的结果。分组表达式为字段时列名与子节点相同，
+    ///   否则为表达式本身；聚集函数的列名为函数本身，如 `COUNT(DISTINCT name)`；
+    /// - `SetOperation` 与左子节点相同。
+    pub fn columns(&self) -> Vec<String> {
+        match self {
+            Node::Scan { table, .. }
+            | Node::KeyLookup { table, .. }
+            | Node::KeyRangeScan { table, .. }
+            | Node::IndexScan { table, .. } => table
+                .columns
+                .iter()
+                .map(|col| format!("{}.{}", table.name, col.name))
+                .collect(),
+            Node::NestedLoopJoin { left, right, .. } | Node::HashJoin { left, right, .. } => {
+                [left.columns(), right.columns()].concat()
+            }
+            Node::Empty { columns } => columns.clone(),
+            Node::Filter { source, .. }
+            | Node::Order { source, .. }
+            | Node::Limit { source, .. }
+            | Node::Distinct { source, .. } => source.columns(),
+            Node::SetOperation { left, .. } => left.columns(),
+            Node::Aggregate {
+                source,
+                group_by,
+                aggregates,
+            } => {
+                let source_columns = source.columns();
+                group_by
+                    .iter()
+                    .map(|expr| match expr {
+                        Expression::Field(col_name) => {
+                            get_column_index_by_name(&source_columns, col_name)
+                                .map(|idx| source_columns[idx].clone())
+                                .unwrap_or(col_name.clone())
+                        }
+                        expr => expr.to_string(),
+                    })
+                    .chain(aggregates.iter().map(|expr| expr.to_string()))
+                    .collect()
+            }
+            Node::Projection { columns, .. } => columns
+                .iter()
+                .map(|(expr, alias)| match (expr, alias) {
+                    (_, Some(alias)) => alias.clone(),
+                    (Expression::Field(col_name), None) => col_name
+                        .split('.')
+                        .next_back()
+                        .unwrap_or(col_name)
+                        .to_string(),
+                    (expr, None) => expr.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// 计划是否读取名为 `table_name` 的表
+    pub fn reads_table(&self, table_name: &str) -> bool {
+        match self {
+            Node::Scan { table, .. }
+            | Node::KeyLookup { table, .. }
+            | Node::KeyRangeScan { table, .. }
+            | Node::IndexScan { table, .. } => table.name == table_name,
+            Node::NestedLoopJoin { left, right, .. }
+            | Node::HashJoin { left, right, .. }
+            | Node::SetOperation { left, right, .. } => {
+                left.reads_table(table_name) || right.reads_table(table_name)
+            }
+            Node::Filter { source, .. }
+            | Node::Order { source, .. }
+            | Node::Limit { source, .. }
+            | Node::Distinct { source, .. }
+            | Node::Projection { source, .. }
+            | Node::Aggregate { source, .. } => source.reads_table(table_name),
+            Node::Empty { .. } => false,
+        }
+    }
+
+    /// 以 `depth` 层缩进输出节点及其子节点
+    fn fmt_indent(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        write!(f, "{}", "  ".repeat(depth))?;
+        let fmt_columns = |columns: &[(Expression, Option<String>)]| {
+            columns
+                .iter()
+                .map(|(expr, alias)| match alias {
+                    Some(alias) if *alias != expr.to_string() => format!("{} AS {}", expr, alias),
+                    _ => expr.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Node::Scan {
+                table,
+                filter,
+                estimate,
+                ..
+            } => {
+                write!(f, "Scan: {}", table.name)?;
+                if let Some(filter) = filter {
+                    write!(f, " ({})", filter)?;
+                }
+                return writeln!(f, "{}", fmt_estimate(estimate));
+            }
+            Node::KeyLookup { table, key } => {
+                let pk = &table.get_primary_key_column().name;
+                return writeln!(f, "KeyLookup: {} ({} = {})", table.name, pk, key);
+            }
+            Node::KeyRangeScan {
+                table,
+                range,
+                estimate,
+            } => {
+                let pk = &table.get_primary_key_column().name;
+                return writeln!(
+                    f,
+                    "KeyRangeScan: {} ({}){}",
+                    table.name,
+                    fmt_range(pk, range).join(" AND "),
+                    fmt_estimate(estimate)
+                );
+            }
+            Node::IndexScan {
+                table,
+                index,
+                prefix,
+                range,
+                estimate,
+            } => {
+                let mut conditions = index
+                    .columns
+                    .iter()
+                    .zip(prefix)
+                    .map(|(col_name, value)| format!("{} = {}", col_name, value))
+                    .collect::<Vec<_>>();
+                if let Some(col_name) = index.columns.get(prefix.len()) {
+                    conditions.extend(fmt_range(col_name, range));
+                }
+                return writeln!(
+                    f,
+                    "IndexScan: {} USING {} ({}){}",
+                    table.name,
+                    index.name,
+                    conditions.join(" AND "),
+                    fmt_estimate(estimate)
+                );
+            }
+            Node::NestedLoopJoin {
+                left,
+                right,
+                join_type,
+                predicate,
+            } => {
+                write!(f, "NestedLoopJoin: {}", join_type)?;
+                if let Some(predicate) = predicate {
+                    write!(f, " ({})", predicate)?;
+                }
+                writeln!(f)?;
+                left.fmt_indent(f, depth + 1)?;
+                return right.fmt_indent(f, depth + 1);
+            }
+            Node::HashJoin {
+                left,
+                right,
+                join_type,
+                left_key,
+                right_key,
+                predicate,
+            } => {
+                write!(
+                    f,
+                    "HashJoin: {} ({} = {}",
+                    join_type,
+                    left.columns()[*left_key],
+                    right.columns()[*right_key]
+                )?;
+                if let Some(predicate) = predicate {
+                    write!(f, " AND {}", predicate)?;
+                }
+                writeln!(f, ")")?;
+                left.fmt_indent(f, depth + 1)?;
+                return right.fmt_indent(f, depth + 1);
+            }
+            Node::SetOperation {
+                left,
+                right,
+                operator,
+                all,
+                ..
+            } => {
+                match all {
+                    true => writeln!(f, "SetOperation: {} All", operator)?,
+                    false => writeln!(f, "SetOperation: {}", operator)?,
+                }
+                left.fmt_indent(f, depth + 1)?;
+                return right.fmt_indent(f, depth + 1);
+            }
+            Node::Empty { .. } => return writeln!(f, "Empty"),
+            Node::Filter { predicate, .. } => writeln!(f, "Filter: {}", predicate)?,
+            Node::Order {
+                ordering, limit, ..
+            } => {
+                let ordering = ordering
+                    .iter()
+                    .map(|(expr, ord, nulls_order)| match nulls_order {
+                        Some(nulls_order) => format!("{} {} {}", expr, ord, nulls_order),
+                        None => format!("{} {}", expr, ord),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                match limit {
+                    Some(limit) => writeln!(f, "Order: {} (top {})", ordering, limit)?,
+                    None => writeln!(f, "Order: {}", ordering)?,
+                }
+            }
+            Node::Limit { offset, limit, .. } => match limit {
+                Some(limit) => writeln!(f, "Limit: {} (offset {})", limit, offset)?,
+                None => writeln!(f, "Offset: {}", offset)?,
+            },
+            Node::Distinct { sorted, .. } => match sorted {
+                true => writeln!(f, "Distinct (sorted)")?,
+                false => writeln!(f, "Distinct")?,
+            },
+            Node::Projection { columns, .. } => {
+                writeln!(f, "Projection: {}", fmt_columns(columns))?
+            }
+            Node::Aggregate {
+                group_by,
+                aggregates,
+                ..
+            } => {
+                let join = |exprs: &[Expression]| {
+                    exprs
+                        .iter()
+                        .map(|expr| expr.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                write!(f, "Aggregate: {}", join(aggregates))?;
+                if !group_by.is_empty() {
+                    write!(f, " GROUP BY {}", join(group_by))?;
+                }
+                writeln!(f)?
+            }
+        }
+        match self {
+            Node::Filter { source, .. }
+            | Node::Order { source, .. }
+            | Node::Limit { source, .. }
+            | Node::Distinct { source, .. }
+            | Node::Projection { source, .. }
+            | Node::Aggregate { source, .. } => source.fmt_indent(f, depth + 1),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 将列的范围格式化为比较条件，无界的一侧不输出
+fn fmt_range(col_name: &str, range: &ValueRange) -> Vec<String> {
+    let mut conditions = Vec::new();
+    match &range.0 {
+        Bound::Included(v) => conditions.push(format!("{} >= {}", col_name, v)),
+        Bound::Excluded(v) => conditions.push(format!("{} > {}", col_name, v)),
+        Bound::Unbounded => {}
+    }
+    match &range.1 {
+        Bound::Included(v) => conditions.push(format!("{} <= {}", col_name, v)),
+        Bound::Excluded(v) => conditions.push(format!("{} < {}", col_name, v)),
+        Bound::Unbounded => {}
+    }
+    conditions
+}
+
+/// 格式化访问节点估计的行数，没有估计时为空
+fn fmt_estimate(estimate: &Option<usize>) -> String {
+    match estimate {
+        Some(rows) => format!(" [estimated rows={}]", rows),
+        None => String::new(),
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indent(f, 0)
+    }
+}
+
+/// 查询计划器，负责将语句转换为执行计划
+pub struct Planner<'a, S: Storage> {
+    transaction: &'a Transaction<S>,
+    /// 是否进行谓词下推，关闭后过滤条件留在原来的位置，仅用于对比测试
+    pushdown: bool,
+    /// 是否重新选择多表内连接的顺序，关闭后按照 SQL 中的顺序连接，仅用于对比测试
+    join_reorder: bool,
+    /// 是否裁剪全表扫描不需要解码的列，关闭后解码所有列，仅用于对比测试
+    column_pruning: bool,
+}
+
+impl<'a, S: Storage> Planner<'a, S> {
+    /// 创建一个新的查询计划器
+    pub fn new(transaction: &'a Transaction<S>) -> Self {
+        Self {
+            transaction,
+            pushdown: true,
+            join_reorder: true,
+            column_pruning: true,
+        }
+    }
+
+    /// 创建一个不进行谓词下推的查询计划器，仅用于测试
+    #[cfg(test)]
+    pub fn without_pushdown(transaction: &'a Transaction<S>) -> Self {
+        Self {
+            transaction,
+            pushdown: false,
+            join_reorder: true,
+            column_pruning: true,
+        }
+    }
+
+    /// 创建一个不重新选择连接顺序的查询计划器，仅用于测试
+    #[cfg(test)]
+    pub fn without_join_reorder(transaction: &'a Transaction<S>) -> Self {
+        Self {
+            transaction,
+            pushdown: true,
+            join_reorder: false,
+            column_pruning: true,
+        }
+    }
+
+    /// 创建一个不裁剪列的查询计划器，仅用于测试
+    #[cfg(test)]
+    pub fn without_column_pruning(transaction: &'a Transaction<S>) -> Self {
+        Self {
+            transaction,
+            pushdown: true,
+            join_reorder: true,
+            column_pruning: false,
+        }
+    }
+
+    /// 构建查询语句的执行计划
+    ///
+    /// 计划从下到上依次为：数据来源、过滤、分组聚集、HAVING 过滤、排序、偏移和限制、选择列。
+    /// 排序在选择列之前，因此排序项可以引用不在选择列中的列，排序项的解析见 [`Planner::resolve_ordering`]。
+    /// 有 `DISTINCT` 时在选择列之上去重，偏移和限制移到去重之后。
+    /// 过滤和 HAVING 的条件在构建之后经过谓词下推，尽可能靠近数据来源，之后重新选择多表内连接的顺序，
+    /// 最后裁剪全表扫描不需要解码的列。
+    #[allow(clippy::too_many_arguments)]
+    pub fn build_select(
+        &self,
+        columns: Vec<(Expression, Option<String>)>,
+        distinct: bool,
+        from: SelectFrom,
+        filter: Option<Expression>,
+        group_by: Vec<Expression>,
+        having: Option<Expression>,
+        ordering: Vec<OrderBy>,
+        limit: Option<Expression>,
+        offset: Option<Expression>,
+    ) -> Result<Node> {
+        // 过滤条件先放在数据来源之上，之后由谓词下推移动到合适的位置
+        let source = self.build_from(from)?;
+        if let Some(filter) = &filter {
+            check_predicate(filter, &source.columns(), &simplify::column_types(&source))?;
+        }
+        let ordering = Self::resolve_ordering(&source.columns(), &columns, ordering)?;
+        let having = having
+            .map(|having| Self::resolve_having_aliases(&source.columns(), &columns, having))
+            .transpose()?;
+        let mut node = build_filter(source, filter);
+
+        // 有 GROUP BY、HAVING 或者聚集函数时，先分组聚集，之后的节点都在聚集的结果上计算
+        let is_aggregate = !group_by.is_empty()
+            || having.is_some()
+            || columns.iter().any(|(col, _)| {
+                let mut functions = Vec::new();
+                col.collect_functions(&mut functions);
+                !functions.is_empty()
+            });
+        if is_aggregate {
+            node = Self::build_aggregate(node, &columns, group_by, having, &ordering)?;
+        }
+
+        if self.pushdown {
+            node = self.push_down_predicates(node)?;
+        }
+        if self.join_reorder {
+            node = self.reorder_joins(node)?;
+        }
+
+        // 计算 limit 和 offset
+        let has_limit = !(offset.is_none() && limit.is_none());
+        let offset = to_usize(offset, "Offset")?.unwrap_or(0);
+        let limit = to_usize(limit, "Limit")?;
+
+        if columns.is_empty() && is_aggregate {
+            return Err(Invalid(
+                "SELECT * cannot be used with GROUP BY or aggregate functions".to_string(),
+            )
+            .into());
+        }
+
+        // 在计划时检查投影表达式中运算的类型，和条件一样只能发现类型确定的错误
+        let (node_columns, types) = (node.columns(), simplify::column_types(&node));
+        for (expr, _) in &columns {
+            infer_type(expr, &node_columns, &types)?;
+        }
+
+        if distinct {
+            let sorted = Self::check_distinct_ordering(&node.columns(), &columns, &ordering)?;
+            // 去重前的行数未知，排序不能只保留前 offset + limit 行
+            if !ordering.is_empty() {
+                node = Node::Order {
+                    source: Box::new(node),
+                    ordering,
+                    limit: None,
+                };
+            }
+            if !columns.is_empty() {
+                node = Node::Projection {
+                    source: Box::new(node),
+                    columns,
+                };
+            }
+            node = Node::Distinct {
+                source: Box::new(node),
+                sorted,
+            };
+            if has_limit {
+                node = Node::Limit {
+                    source: Box::new(node),
+                    offset,
+                    limit,
+                };
+            }
+            return Ok(self.prune(node));
+        }
+
+        // 排序之上有 limit 时，只需要排序后的前 offset + limit 行
+        if !ordering.is_empty() {
+            node = Node::Order {
+                source: Box::new(node),
+                ordering,
+                limit: limit.map(|limit| limit.saturating_add(offset)),
+            };
+        }
+
+        if has_limit {
+            node = Node::Limit {
+                source: Box::new(node),
+                offset,
+                limit,
+            };
+        }
+
+        if !columns.is_empty() {
+            node = Node::Projection {
+                source: Box::new(node),
+                columns,
+            };
+        }
+        Ok(self.prune(node))
+    }
+
+    /// 开启了列裁剪时裁剪计划中全表扫描不需要解码的列
+    fn prune(&self, node: Node) -> Node {
+        match self.column_pruning {
+            true => prune_columns(node),
+            false => node,
+        }
+    }
+
+    /// 构建查询语句的执行计划，查询语句为 SELECT 语句或者集合运算
+    pub fn build_query(&self, stmt: Statement) -> Result<Node> {
+        match stmt {
+            Statement::Select {
+                columns,
+                distinct,
+                from,
+                filter,
+                group_by,
+                having,
+                ordering,
+                limit,
+                offset,
+            } => self.build_select(
+                columns, distinct, from, filter, group_by, having, ordering, limit, offset,
+            ),
+            Statement::SetOperation {
+                operator,
+                all,
+                left,
+                right,
+                ordering,
+                limit,
+                offset,
+            } => self.build_set_operation(operator, all, *left, *right, ordering, limit, offset),
+            _ => Err(Invalid("Only SELECT and set operations are queries".to_string()).into()),
+        }
+    }
+
+    /// 构建集合运算的执行计划
+    ///
+    /// 两侧的列数必须相同，输出的列名取左侧的列名。同一列在两侧的类型相同时不变，整数和浮点数统一为浮点数，
+    /// 其他不同的类型在计划时返回 [`TypeMismatch`]，类型无法在计划时确定的列不检查。
+    /// 排序、偏移和限制作用于运算的结果，排序项为输出的列名或者从 1 开始的列序号。
+    #[allow(clippy::too_many_arguments)]
+    fn build_set_operation(
+        &self,
+        operator: SetOperator,
+        all: bool,
+        left: Statement,
+        right: Statement,
+        ordering: Vec<OrderBy>,
+        limit: Option<Expression>,
+        offset: Option<Expression>,
+    ) -> Result<Node> {
+        let left = self.build_query(left)?;
+        let right = self.build_query(right)?;
+        let columns = left.columns();
+        if columns.len() != right.columns().len() {
+            return Err(Invalid(format!(
+                "Each side of {} must have the same number of columns, got {} and {}",
+                operator,
+                columns.len(),
+                right.columns().len()
+            ))
+            .into());
+        }
+        let types = columns
+            .iter()
+            .zip(simplify::column_types(&left))
+            .zip(simplify::column_types(&right))
+            .map(|((col_name, lhs), rhs)| match (lhs, rhs) {
+                (Some(lhs), Some(rhs)) if lhs == rhs => Ok(Some(lhs)),
+                (
+                    Some(DataType::Integer | DataType::Float),
+                    Some(DataType::Integer | DataType::Float),
+                ) => Ok(Some(DataType::Float)),
+                (Some(lhs), Some(rhs)) => Err(TypeMismatch(format!(
+                    "Column {} has type {:?} and {:?} in {}",
+                    col_name, lhs, rhs, operator
+                ))
+                .into()),
+                (lhs, rhs) => Ok(lhs.or(rhs)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut node = Node::SetOperation {
+            left: Box::new(left),
+            right: Box::new(right),
+            operator,
+            all,
+            types,
+        };
+
+        let ordering = ordering
+            .into_iter()
+            .map(|(expr, order, nulls)| match expr {
+                Expression::Constant(Constant::Integer(ordinal)) => {
+                    let col_name = usize::try_from(ordinal)
+                        .ok()
+                        .and_then(|ordinal| columns.get(ordinal.checked_sub(1)?))
+                        .ok_or(Invalid(format!(
+                            "ORDER BY position {} is not in the select list of {} columns",
+                            ordinal,
+                            columns.len()
+                        )))?;
+                    Ok((Expression::Field(col_name.clone()), order, nulls))
+                }
+                expr => Ok((expr, order, nulls)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let has_limit = !(offset.is_none() && limit.is_none());
+        let offset = to_usize(offset, "Offset")?.unwrap_or(0);
+        let limit = to_usize(limit, "Limit")?;
+        if !ordering.is_empty() {
+            node = Node::Order {
+                source: Box::new(node),
+                ordering,
+                limit: limit.map(|limit| limit.saturating_add(offset)),
+            };
+        }
+        if has_limit {
+            node = Node::Limit {
+                source: Box::new(node),
+                offset,
+                limit,
+            };
+        }
+        Ok(node)
+    }
+
+    /// 将排序项中的别名和序号替换为对应的选择列表达式
+    ///
+    /// - 没有限定表名的字段和选择列的别名相同时，替换为别名对应的表达式。多个选择列使用同一个别名，
+    ///   或者别名和数据来源中指向不同值的列同名时有歧义，返回错误；
+    /// - 整数常量为选择列的序号，从 1 开始，`SELECT *` 时为数据来源的列，超出范围时返回错误；
+    /// - 其他排序项为表达式，在选择列之前计算。
+    fn resolve_ordering(
+        source_columns: &[String],
+        columns: &[(Expression, Option<String>)],
+        ordering: Vec<OrderBy>,
+    ) -> Result<Vec<OrderBy>> {
+        // 字段解析到的数据来源中的列
+        let resolve = |expr: &Expression| match expr {
+            Expression::Field(name) => get_column_index_by_name(source_columns, name).ok(),
+            _ => None,
+        };
+
+        ordering
+            .into_iter()
+            .map(|(expr, order, nulls_order)| {
+                let expr = match expr {
+                    Expression::Field(name) if !name.contains('.') => {
+                        let aliased = columns
+                            .iter()
+                            .filter(|(_, alias)| alias.as_deref() == Some(name.as_str()))
+                            .map(|(expr, _)| expr)
+                            .collect::<Vec<_>>();
+                        match aliased.as_slice() {
+                            [] => Expression::Field(name),
+                            [aliased] => {
+                                let column = get_column_index_by_name(source_columns, &name).ok();
+                                if column.is_some() && resolve(aliased) != column {
+                                    return Err(Invalid(format!(
+                                        "ORDER BY {} is ambiguous between an alias and a column",
+                                        name
+                                    ))
+                                    .into());
+                                }
+                                (*aliased).clone()
+                            }
+                            _ => {
+                                return Err(Invalid(format!(
+                                    "ORDER BY {} is ambiguous between multiple aliases",
+                                    name
+                                ))
+                                .into())
+                            }
+                        }
+                    }
+                    Expression::Constant(Constant::Integer(ordinal)) => {
+                        let select_len = match columns.is_empty() {
+                            true => source_columns.len(),
+                            false => columns.len(),
+                        };
+                        if ordinal < 1 || ordinal as usize > select_len {
+                            return Err(Invalid(format!(
+                                "ORDER BY position {} is not in the select list of {} columns",
+                                ordinal, select_len
+                            ))
+                            .into());
+                        }
+                        let index = ordinal as usize - 1;
+                        match columns.get(index) {
+                            Some((expr, _)) => expr.clone(),
+                            None => Expression::Field(source_columns[index].clone()),
+                        }
+                    }
+                    expr => expr,
+                };
+                Ok((expr, order, nulls_order))
+            })
+            .collect()
+    }
+
+    /// 将 `HAVING` 中的选择列别名替换为对应的表达式
+    ///
+    /// 和数据来源中的列同名的字段仍然是列，否则按照别名替换，多个选择列使用同一个别名时有歧义，返回错误。
+    fn resolve_having_aliases(
+        source_columns: &[String],
+        columns: &[(Expression, Option<String>)],
+        having: Expression,
+    ) -> Result<Expression> {
+        having.transform(&mut |expr| match expr {
+            Expression::Field(name)
+                if !name.contains('.')
+                    && get_column_index_by_name(source_columns, &name).is_err() =>
+            {
+                let aliased = columns
+                    .iter()
+                    .filter(|(_, alias)| alias.as_deref() == Some(name.as_str()))
+                    .map(|(expr, _)| expr)
+                    .collect::<Vec<_>>();
+                match aliased.as_slice() {
+                    [] => Ok(Expression::Field(name)),
+                    [aliased] => Ok((*aliased).clone()),
+                    _ => Err(Invalid(format!(
+                        "HAVING {} is ambiguous between multiple aliases",
+                        name
+                    ))
+                    .into()),
+                }
+            }
+            expr => Ok(expr),
+        })
+    }
+
+    /// 检查 `DISTINCT` 查询的排序项，返回排序后的输出是否已经按照所有选择列排序
+    ///
+    /// 去重之后的一行可能对应多个不同的排序值，因此排序项必须是选择列之一。
+    /// 字段形式的排序项与选择列解析到同一列时也视为相同。
+    fn check_distinct_ordering(
+        source_columns: &[String],
+        columns: &[(Expression, Option<String>)],
+        ordering: &[OrderBy],
+    ) -> Result<bool> {
+        // `SELECT DISTINCT *` 的选择列是数据来源的所有列
+        let select_exprs = match columns.is_empty() {
+            true => source_columns
+                .iter()
+                .map(|name| Expression::Field(name.clone()))
+                .collect::<Vec<_>>(),
+            false => columns.iter().map(|(expr, _)| expr.clone()).collect(),
+        };
+
+        // 两个表达式是否相同，字段比较其解析到的列
+        let resolve = |expr: &Expression| match expr {
+            Expression::Field(name) => get_column_index_by_name(source_columns, name).ok(),
+            _ => None,
+        };
+        let is_same = |lhs: &Expression, rhs: &Expression| match (resolve(lhs), resolve(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs == rhs,
+            _ => lhs == rhs,
+        };
+
+        for (expr, _, _) in ordering {
+            if !select_exprs.iter().any(|select| is_same(select, expr)) {
+                return Err(Invalid(format!(
+                    "ORDER BY expression {} must appear in the select list when DISTINCT is used",
+                    expr
+                ))
+                .into());
+            }
+        }
+
+        // 所有选择列都是排序项时，相同的行在排序后一定相邻
+        Ok(!ordering.is_empty()
+            && select_exprs
+                .iter()
+                .all(|select| ordering.iter().any(|(expr, _, _)| is_same(select, expr))))
+    }
+
+    /// 构建分组聚集的执行计划，`HAVING` 不为空时在聚集之上过滤
+    ///
+    /// 选择列、`HAVING` 和 `ORDER BY` 中的聚集函数都在聚集节点中计算，其中的字段必须出现在 `GROUP BY` 中。
+    fn build_aggregate(
+        source: Node,
+        columns: &[(Expression, Option<String>)],
+        group_by: Vec<Expression>,
+        having: Option<Expression>,
+        ordering: &[OrderBy],
+    ) -> Result<Node> {
+        let source_columns = source.columns();
+
+        // 分组表达式中不能有聚集函数，其中的字段必须存在
+        for expr in &group_by {
+            let mut functions = Vec::new();
+            expr.collect_functions(&mut functions);
+            if let Some(function) = functions.first() {
+                return Err(Invalid(format!(
+                    "Aggregate function {} is not allowed in GROUP BY",
+                    function
+                ))
+                .into());
+            }
+            let mut fields = Vec::new();
+            expr.collect_fields(&mut fields);
+            for field in fields {
+                get_column_index_by_name(&source_columns, field)?;
+            }
+        }
+
+        // 收集所有的聚集函数并去重，检查参数列是否存在
+        let exprs = columns
+            .iter()
+            .map(|(expr, _)| expr)
+            .chain(having.iter())
+            .chain(ordering.iter().map(|(expr, _, _)| expr))
+            .collect::<Vec<_>>();
+        let mut aggregates = Vec::new();
+        for expr in &exprs {
+            let mut functions = Vec::new();
+            expr.collect_functions(&mut functions);
+            for function in functions {
+                if let Some((_, col_name, _)) = function.as_function() {
+                    if col_name != "*" {
+                        get_column_index_by_name(&source_columns, col_name)?;
+                    }
+                }
+                if !aggregates.contains(function) {
+                    aggregates.push(function.clone());
+                }
+            }
+        }
+
+        let node = Node::Aggregate {
+            source: Box::new(source),
+            group_by,
+            aggregates,
+        };
+
+        // 聚集之上的字段只能引用分组的列
+        let output_columns = node.columns();
+        for expr in exprs {
+            let mut fields = Vec::new();
+            expr.collect_fields(&mut fields);
+            for field in fields {
+                if get_column_index_by_name(&output_columns, field).is_err() {
+                    return Err(Invalid(format!(
+                        "Column {} must appear in the GROUP BY clause or be used in an aggregate function",
+                        field
+                    )).into());
+                }
+            }
+        }
+
+        if let Some(having) = &having {
+            check_predicate(having, &output_columns, &simplify::column_types(&node))?;
+        }
+        Ok(build_filter(node, having))
+    }
+
+    /// 构建 FROM 子句的执行计划
+    fn build_from(&self, from: SelectFrom) -> Result<Node> {
+        match from {
+            SelectFrom::Table { name } => self.build_table_access(&name, None),
+            SelectFrom::Join {
+                left,
+                right,
+                join_type,
+                predicate,
+            } => {
+                // 除了 Cross Join 外，其他 Join 类型必须有 Join 条件
+                if join_type != JoinType::Cross && predicate.is_none() {
+                    return Err(Invalid(format!("{} must have a predicate", join_type)).into());
+                }
+                let left = self.build_from(*left)?;
+                let right = self.build_from(*right)?;
+                if let Some(predicate) = &predicate {
+                    let columns = [left.columns(), right.columns()].concat();
+                    let types = [
+                        simplify::column_types(&left),
+                        simplify::column_types(&right),
+                    ]
+                    .concat();
+                    check_predicate(predicate, &columns, &types)?;
+                }
+                build_join(left, right, join_type, predicate)
+            }
+        }
+    }
+
+    /// 构建单表的访问路径
+    ///
+    /// 将过滤条件按 `AND` 拆分，其中列和常量比较的条件可以用于主键或二级索引，候选访问路径按以下顺序选择：
+    ///
+    /// 1. 主键等值：使用 `KeyLookup` 查找单行；
+    /// 2. 唯一索引的所有列等值：使用 `IndexScan`，最多返回一行；
+    /// 3. 索引前缀列等值：使用 `IndexScan`，等值的列越多越优先；
+    /// 4. 主键范围：使用 `KeyRangeScan` 扫描主键范围；
+    /// 5. 索引第一列范围：使用 `IndexScan` 扫描索引范围；
+    /// 6. 否则使用 `Scan` 全表扫描，并在扫描时过滤。
+    ///
+    /// 表执行过 `ANALYZE` 时，除了前两种最多返回一行的访问路径外，根据统计信息估计每条访问路径读取的行数，
+    /// 选择代价最小的访问路径，全表扫描也作为候选，见 [`estimate::access_cost`]。
+    ///
+    /// 未被访问路径使用的条件作为 `Filter` 放在访问节点之上。
+    pub fn build_table_access(&self, table_name: &str, filter: Option<Expression>) -> Result<Node> {
+        let table = self
+            .transaction
+            .get_table(table_name)?
+            .ok_or(TableNotFound(table_name.to_string()))?;
+        if let Some(filter) = &filter {
+            let columns = table
+                .columns
+                .iter()
+                .map(|col| format!("{}.{}", table.name, col.name))
+                .collect::<Vec<_>>();
+            let types = table
+                .columns
+                .iter()
+                .map(|col| Some(col.data_type))
+                .collect::<Vec<_>>();
+            check_predicate(filter, &columns, &types)?;
+        }
+        let stats = self.transaction.get_stats(table_name)?;
+        build_access(table, stats.as_ref(), filter)
+    }
+
+    /// 数据来源输出的列名，即 WHERE 等子句中字段的解析范围
+    pub fn source_columns(&self, from: SelectFrom) -> Result<Vec<String>> {
+        Ok(self.build_from(from)?.columns())
+    }
+
+    /// 检查子查询是否为不相关子查询，目前不支持引用外层查询的列的相关子查询
+    ///
+    /// 子查询中的字段不能在自身的数据来源和选择列的别名中解析，但可以在外层的列 `outer_columns` 中解析时，
+    /// 视为对外层的引用，返回错误。集合运算的每一侧分别检查。
+    pub fn check_uncorrelated(&self, subquery: &Statement, outer_columns: &[String]) -> Result<()> {
+        if let Statement::SetOperation { left, right, .. } = subquery {
+            self.check_uncorrelated(left, outer_columns)?;
+            return self.check_uncorrelated(right, outer_columns);
+        }
+        let Statement::Select {
+            columns,
+            from,
+            filter,
+            group_by,
+            having,
+            ordering,
+            ..
+        } = subquery
+        else {
+            return Err(Invalid("Subquery must be a SELECT statement".to_string()).into());
+        };
+        let source_columns = self.source_columns(from.clone())?;
+
+        let mut exprs = columns
+            .iter()
+            .map(|(expr, _)| expr)
+            .chain(filter)
+            .chain(group_by)
+            .chain(having)
+            .chain(ordering.iter().map(|(expr, ..)| expr))
+            .collect::<Vec<_>>();
+        join_predicates(from, &mut exprs);
+        let mut fields = Vec::new();
+        for expr in exprs {
+            expr.collect_fields(&mut fields);
+        }
+
+        for field in fields {
+            let is_alias = columns
+                .iter()
+                .any(|(_, alias)| alias.as_ref() == Some(field));
+            if !is_alias
+                && get_column_index_by_name(&source_columns, field).is_err()
+                && get_column_index_by_name(outer_columns, field).is_ok()
+            {
+                return Err(Unsupported(format!(
+                    "Correlated subquery referencing outer column {}",
+                    field
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 计算 LIMIT 或 OFFSET 的值，必须是非负整数
+fn to_usize(expr: Option<Expression>, err_prefix: &str) -> Result<Option<usize>> {
+    expr.map(|e| match evaluate(&e, &[], &vec![])? {
+        Value::Integer(v) if v >= 0 => Ok(v as usize),
+        other => Err(Invalid(format!(
+            "{} must be a non-negative integer, get {:?}",
+            err_prefix, other
+        ))
+        .into()),
+    })
+    .transpose()
+}
+
+/// 收集 FROM 子句中所有的连接条件
+fn join_predicates<'a>(from: &'a SelectFrom, predicates: &mut Vec<&'a Expression>) {
+    if let SelectFrom::Join {
+        left,
+        right,
+        predicate,
+        ..
+    } = from
+    {
+        join_predicates(left, predicates);
+        join_predicates(right, predicates);
+        predicates.extend(predicate);
+    }
+}
+
+/// 在计划时检查条件表达式中运算的类型，条件的值必须是布尔值或者 NULL
+///
+/// 只能发现类型确定的错误，例如字符串列参与算术运算，类型无法确定的部分留到执行时检查。
+fn check_predicate(
+    predicate: &Expression,
+    columns: &[String],
+    types: &[Option<DataType>],
+) -> Result<()> {
+    match infer_type(predicate, columns, types)? {
+        Some(data_type) if data_type != DataType::Boolean => Err(TypeMismatch(format!(
+            "Condition {} must be a boolean, got {:?}",
+            predicate, data_type
+        ))
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// 根据过滤条件和统计信息选择表的访问路径，见 [`Planner::build_table_access`]
+fn build_access(
+    table: Table,
+    stats: Option<&TableStats>,
+    filter: Option<Expression>,
+) -> Result<Node> {
+    let Some(filter) = filter else {
+        return Ok(Node::Scan {
+            estimate: stats.map(|stats| stats.row_count),
+            table,
+            filter: None,
+            needed: None,
+        });
+    };
+
+    let mut conjuncts = Vec::new();
+    split_conjunction(filter, &mut conjuncts);
+
+    // 收集候选访问路径：(优先级, 使用的条件下标, 访问路径)，优先级越小越好
+    let mut candidates: Vec<(usize, Vec<usize>, AccessPath)> = Vec::new();
+
+    let pk_name = &table.get_primary_key_column().name;
+    if let Some((range, used)) = column_range(&table, pk_name, &conjuncts) {
+        let priority = match &range {
+            (Bound::Included(lower), Bound::Included(upper)) if lower == upper => 0,
+            _ => 3,
+        };
+        candidates.push((priority, used, AccessPath::Key(range)));
+    }
+
+    // 部分索引中没有不满足条件的行，不作为访问路径
+    for index in table
+        .indexes
+        .iter()
+        .filter(|index| index.predicate.is_none())
+    {
+        let mut prefix = Vec::new();
+        let mut used = Vec::new();
+        let mut range = (Bound::Unbounded, Bound::Unbounded);
+        for col_name in &index.columns {
+            // 优先使用等值条件扩展前缀，否则使用该列的范围条件，并停止扩展
+            let equality = conjuncts.iter().enumerate().find_map(|(i, expr)| {
+                match column_bound(&table, col_name, expr)? {
+                    (Bound::Included(lower), Bound::Included(upper)) if lower == upper => {
+                        Some((i, lower))
+                    }
+                    _ => None,
+                }
+            });
+            if let Some((i, value)) = equality {
+                prefix.push(value);
+                used.push(i);
+                continue;
+            }
+            if let Some((col_range, col_used)) = column_range(&table, col_name, &conjuncts) {
+                range = col_range;
+                used.extend(col_used);
+            }
+            break;
+        }
+        if used.is_empty() {
+            continue;
+        }
+        let priority = if index.unique && prefix.len() == index.columns.len() {
+            1
+        } else if !prefix.is_empty() {
+            2
+        } else {
+            4
+        };
+        candidates.push((
+            priority,
+            used,
+            AccessPath::Index(index.clone(), prefix, range),
+        ));
+    }
+
+    // 优先级相同时，使用条件更多的访问路径更优
+    let by_priority =
+        |(lhs_priority, lhs_used, _): &(usize, Vec<usize>, AccessPath),
+         (rhs_priority, rhs_used, _): &(usize, Vec<usize>, AccessPath)| {
+            lhs_priority
+                .cmp(rhs_priority)
+                .then(rhs_used.len().cmp(&lhs_used.len()))
+        };
+    let best = match stats {
+        // 有统计信息时，最多返回一行的访问路径之外按照估计的代价选择，代价不低于全表扫描时使用全表扫描
+        Some(stats) if !candidates.iter().any(|(priority, ..)| *priority <= 1) => candidates
+            .into_iter()
+            .map(|candidate| {
+                let cost = estimate::access_cost(&table, stats, &candidate.2);
+                (cost, candidate)
+            })
+            .filter(|(cost, _)| *cost < stats.row_count as f64)
+            .min_by(|(lhs_cost, lhs), (rhs_cost, rhs)| {
+                lhs_cost.total_cmp(rhs_cost).then(by_priority(lhs, rhs))
+            })
+            .map(|(_, candidate)| candidate),
+        _ => candidates.into_iter().min_by(by_priority),
+    };
+
+    let used = best
+        .as_ref()
+        .map(|(_, used, _)| used.clone())
+        .unwrap_or_default();
+    let residual = join_conjunction(
+        conjuncts
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !used.contains(i))
+            .map(|(_, expr)| expr)
+            .collect(),
+    );
+
+    let node = match best {
+        None => {
+            let estimate = stats.map(|stats| match &residual {
+                Some(filter) => estimate::rows(stats, estimate::selectivity(&table, stats, filter)),
+                None => stats.row_count,
+            });
+            return Ok(Node::Scan {
+                table,
+                filter: residual,
+                needed: None,
+                estimate,
+            });
+        }
+        Some((_, _, path)) => {
+            let estimate = stats.map(|stats| {
+                estimate::rows(stats, estimate::access_selectivity(&table, stats, &path))
+            });
+            match path {
+                AccessPath::Key((Bound::Included(lower), Bound::Included(upper)))
+                    if lower == upper =>
+                {
+                    Node::KeyLookup { table, key: lower }
+                }
+                AccessPath::Key(range) => Node::KeyRangeScan {
+                    table,
+                    range,
+                    estimate,
+                },
+                AccessPath::Index(index, prefix, range) => Node::IndexScan {
+                    table,
+                    index,
+                    prefix,
+                    range,
+                    estimate,
+                },
+            }
+        }
+    };
+    Ok(match residual {
+        Some(predicate) => Node::Filter {
+            source: Box::new(node),
+            predicate,
+        },
+        None => node,
+    })
+}
+
+/// 构建两个子节点的连接
+///
+/// 条件中存在左右两侧字段的等值比较时使用哈希连接，其余条件在匹配时计算，否则使用嵌套循环连接。
+fn build_join(
+    left: Node,
+    right: Node,
+    join_type: JoinType,
+    predicate: Option<Expression>,
+) -> Result<Node> {
+    let Some(predicate) = predicate else {
+        return Ok(Node::NestedLoopJoin {
+            left: Box::new(left),
+            right: Box::new(right),
+            join_type,
+            predicate: None,
+        });
+    };
+
+    // 条件中的列必须能在左右表拼接后的列中唯一解析
+    let left_columns = left.columns();
+    let right_columns = right.columns();
+    let columns = [left_columns.as_slice(), right_columns.as_slice()].concat();
+    let mut fields = Vec::new();
+    predicate.collect_fields(&mut fields);
+    for field in fields {
+        get_column_index_by_name(&columns, field)?;
+    }
+
+    let mut conjuncts = Vec::new();
+    split_conjunction(predicate.clone(), &mut conjuncts);
+    let equi_join = conjuncts.iter().enumerate().find_map(|(i, expr)| {
+        equi_join_keys(expr, &left_columns, &right_columns).map(|keys| (i, keys))
+    });
+    match equi_join {
+        Some((i, (left_key, right_key))) => {
+            conjuncts.remove(i);
+            Ok(Node::HashJoin {
+                left: Box::new(left),
+                right: Box::new(right),
+                join_type,
+                left_key,
+                right_key,
+                predicate: join_conjunction(conjuncts),
+            })
+        }
+        // 嵌套循环连接不支持 RIGHT 和 FULL JOIN
+        None if matches!(join_type, JoinType::Right | JoinType::Full) => Err(Invalid(format!(
+            "{} condition must contain a field equal to a field",
+            join_type
+        ))
+        .into()),
+        None => Ok(Node::NestedLoopJoin {
+            left: Box::new(left),
+            right: Box::new(right),
+            join_type,
+            predicate: Some(predicate),
+        }),
+    }
+}
+
+/// 在 `source` 之上过滤，过滤条件先在计划时化简
+///
+/// 条件恒为真时不需要过滤，恒为假时整个子树替换为 `Empty`。
+fn build_filter(source: Node, predicate: Option<Expression>) -> Node {
+    let Some(predicate) = predicate else {
+        return source;
+    };
+    let predicate = simplify::simplify(
+        predicate,
+        &source.columns(),
+        &simplify::column_types(&source),
+    );
+    if simplify::is_always_true(&predicate) {
+        source
+    } else if simplify::is_always_false(&predicate) {
+        Node::Empty {
+            columns: source.columns(),
+        }
+    } else {
+        Node::Filter {
+            source: Box::new(source),
+            predicate,
+        }
+    }
+}
+
+/// 单表访问路径的候选
+enum AccessPath {
+    /// 主键范围
+    Key(ValueRange),
+    /// 二级索引，依次为索引定义、前缀列的等值和下一列的范围
+    Index(IndexDef, Vec<Value>, ValueRange),
+}
+
+/// 将 `AND` 连接的表达式拆分为多个条件
+fn split_conjunction(expr: Expression, conjuncts: &mut Vec<Expression>) {
+    match expr {
+        Expression::Operation(Operation::And(lhs, rhs)) => {
+            split_conjunction(*lhs, conjuncts);
+            split_conjunction(*rhs, conjuncts);
+        }
+        expr => conjuncts.push(expr),
+    }
+}
+
+/// 将多个条件用 `AND` 连接为一个表达式，没有条件时返回 `None`
+fn join_conjunction(conjuncts: Vec<Expression>) -> Option<Expression> {
+    conjuncts
+        .into_iter()
+        .reduce(|lhs, rhs| Expression::Operation(Operation::And(Box::new(lhs), Box::new(rhs))))
+}
+
+/// 如果条件是左右两侧各一个字段的等值比较，返回两个字段分别在左右子节点输出中的下标
+fn equi_join_keys(
+    expr: &Expression,
+    left_columns: &[String],
+    right_columns: &[String],
+) -> Option<(usize, usize)> {
+    let Expression::Operation(Operation::Equal(lhs, rhs)) = expr else {
+        return None;
+    };
+    let (lhs, rhs) = (lhs.as_field()?, rhs.as_field()?);
+    let resolve = |left_field: &str, right_field: &str| {
+        Some((
+            get_column_index_by_name(left_columns, left_field).ok()?,
+            get_column_index_by_name(right_columns, right_field).ok()?,
+        ))
+    };
+    resolve(lhs, rhs).or_else(|| resolve(rhs, lhs))
+}
+
+/// 合并所有列 `col_name` 和常量比较的条件，返回列的范围和使用的条件下标，没有这样的条件时返回 `None`
+fn column_range(
+    table: &Table,
+    col_name: &str,
+    conjuncts: &[Expression],
+) -> Option<(ValueRange, Vec<usize>)> {
+    let mut range = (Bound::Unbounded, Bound::Unbounded);
+    let mut used = Vec::new();
+    for (i, expr) in conjuncts.iter().enumerate() {
+        if let Some((lower, upper)) = column_bound(table, col_name, expr) {
+            range.0 = tighter_bound(range.0, lower, std::cmp::Ordering::Greater);
+            range.1 = tighter_bound(range.1, upper, std::cmp::Ordering::Less);
+            used.push(i);
+        }
+    }
+    (!used.is_empty()).then_some((range, used))
+}
+
+/// 如果条件是列 `col_name` 和常量的比较，返回对应的列上下界
+///
+/// 常量必须和列类型相同且不为 NULL，否则比较语义和编码的顺序可能不一致，交给 `Filter` 处理。
+fn column_bound(table: &Table, col_name: &str, expr: &Expression) -> Option<ValueRange> {
+    let Expression::Operation(operation) = expr else {
+        return None;
+    };
+    let (lhs, rhs) = match operation {
+        Operation::Equal(lhs, rhs)
+        | Operation::GreaterThan(lhs, rhs)
+        | Operation::GreaterThanOrEqual(lhs, rhs)
+        | Operation::LessThan(lhs, rhs)
+        | Operation::LessThanOrEqual(lhs, rhs) => (lhs, rhs),
+        _ => return None,
+    };
+
+    let column = &table.columns[table.get_col_idx(col_name)?];
+    let is_column = |expr: &Expression| {
+        expr.as_field().is_some_and(|name| {
+            *name == column.name || *name == format!("{}.{}", table.name, column.name)
+        })
+    };
+    let as_value = |expr: &Expression| {
+        expr.as_constant()
+            .map(|_| Value::from(expr.clone()))
+            .filter(|value| value.data_type() == Some(column.data_type))
+            .filter(|value| value.partial_cmp(value).is_some()) // 排除 NaN
+    };
+
+    // 统一为 `column op value` 的形式，常量在左侧时需要翻转比较方向
+    let (value, flipped) = if is_column(lhs) {
+        (as_value(rhs)?, false)
+    } else if is_column(rhs) {
+        (as_value(lhs)?, true)
+    } else {
+        return None;
+    };
+    let bound = match (operation, flipped) {
+        (Operation::Equal(..), _) => (Bound::Included(value.clone()), Bound::Included(value)),
+        (Operation::GreaterThan(..), false) | (Operation::LessThan(..), true) => {
+            (Bound::Excluded(value), Bound::Unbounded)
+        }
+        (Operation::GreaterThanOrEqual(..), false) | (Operation::LessThanOrEqual(..), true) => {
+            (Bound::Included(value), Bound::Unbounded)
+        }
+        (Operation::LessThan(..), false) | (Operation::GreaterThan(..), true) => {
+            (Bound::Unbounded, Bound::Excluded(value))
+        }
+        (Operation::LessThanOrEqual(..), false) | (Operation::GreaterThanOrEqual(..), true) => {
+            (Bound::Unbounded, Bound::Included(value))
+        }
+        _ => unreachable!(), // 前面已经排除了其他运算
+    };
+    Some(bound)
+}
+
+/// 返回两个边界中更严格的一个
+///
+/// 对于下界 `tighter` 为 `Greater`，对于上界 `tighter` 为 `Less`；值相同时不包含的边界更严格。
+fn tighter_bound(
+    lhs: Bound<Value>,
+    rhs: Bound<Value>,
+    tighter: std::cmp::Ordering,
+) -> Bound<Value> {
+    match (&lhs, &rhs) {
+        (Bound::Unbounded, _) => rhs,
+        (_, Bound::Unbounded) => lhs,
+        (Bound::Included(l) | Bound::Excluded(l), Bound::Included(r) | Bound::Excluded(r)) => {
+            match l.partial_cmp(r) {
+                Some(ord) if ord == tighter => lhs,
+                Some(std::cmp::Ordering::Equal) if matches!(lhs, Bound::Included(_)) => rhs,
+                Some(std::cmp::Ordering::Equal) => lhs,
+                _ => rhs,
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{executor::expression::get_column_index_by_name, parser::ast::Expression};
+
+use super::Node;
+
+/// 列裁剪
+///
+/// 自顶向下计算每个节点需要子节点提供哪些列，全表扫描只解码需要的列，其余列不解码而是输出 NULL：
+///
+/// - 选择列和分组聚集只需要表达式和聚集函数的参数引用的列，与上层无关；
+/// - 过滤、排序和连接在上层需要的列之外，还需要条件、排序项和连接键引用的列；
+/// - 去重和集合运算比较整行，需要子节点的所有列；
+/// - 计划的根节点输出所有列，例如 `SELECT *`。
+///
+/// 行的布局不变，因此列名、连接键的下标和各节点中的表达式都不需要修改。
+pub(super) fn prune_columns(node: Node) -> Node {
+    prune(node, None)
+}
+
+/// 裁剪 `node` 的子树，`needed` 为上层引用的字段名，为 `None` 时需要所有列
+fn prune(node: Node, needed: Option<Vec<String>>) -> Node {
+    match node {
+        Node::Scan {
+            table,
+            filter,
+            estimate,
+            ..
+        } => {
+            let needed = needed.and_then(|mut fields| {
+                if let Some(filter) = &filter {
+                    referenced_fields(filter, &mut fields);
+                }
+                let columns = table
+                    .columns
+                    .iter()
+                    .map(|col| format!("{}.{}", table.name, col.name))
+                    .collect::<Vec<_>>();
+                let needed = (0..columns.len())
+                    .filter(|col_idx| {
+                        fields.iter().any(|field| {
+                            get_column_index_by_name(&columns, field).ok() == Some(*col_idx)
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                // 所有列都需要时和不裁剪相同
+                (needed.len() < columns.len()).then_some(needed)
+            });
+            Node::Scan {
+                table,
+                filter,
+                needed,
+                estimate,
+            }
+        }
+        Node::Projection { source, columns } => {
+            let mut fields = Vec::new();
+            for (expr, _) in &columns {
+                referenced_fields(expr, &mut fields);
+            }
+            Node::Projection {
+                source: Box::new(prune(*source, Some(fields))),
+                columns,
+            }
+        }
+        Node::Aggregate {
+            source,
+            group_by,
+            aggregates,
+        } => {
+            let mut fields = Vec::new();
+            for expr in group_by.iter().chain(&aggregates) {
+                referenced_fields(expr, &mut fields);
+            }
+            Node::Aggregate {
+                source: Box::new(prune(*source, Some(fields))),
+                group_by,
+                aggregates,
+            }
+        }
+        Node::Filter { source, predicate } => {
+            let needed = needed.map(|mut fields| {
+                referenced_fields(&predicate, &mut fields);
+                fields
+            });
+            Node::Filter {
+                source: Box::new(prune(*source, needed)),
+                predicate,
+            }
+        }
+        Node::Order {
+            source,
+            ordering,
+            limit,
+        } => {
+            let needed = needed.map(|mut fields| {
+                for (expr, ..) in &ordering {
+                    referenced_fields(expr, &mut fields);
+                }
+                fields
+            });
+            Node::Order {
+                source: Box::new(prune(*source, needed)),
+                ordering,
+                limit,
+            }
+        }
+        Node::Limit {
+            source,
+            offset,
+            limit,
+        } => Node::Limit {
+            source: Box::new(prune(*source, needed)),
+            offset,
+            limit,
+        },
+        Node::Distinct { source, sorted } => Node::Distinct {
+            source: Box::new(prune(*source, None)),
+            sorted,
+        },
+        Node::NestedLoopJoin {
+            left,
+            right,
+            join_type,
+            predicate,
+        } => {
+            let needed = needed.map(|mut fields| {
+                if let Some(predicate) = &predicate {
+                    referenced_fields(predicate, &mut fields);
+                }
+                fields
+            });
+            Node::NestedLoopJoin {
+                left: Box::new(prune(*left, needed.clone())),
+                right: Box::new(prune(*right, needed)),
+                join_type,
+                predicate,
+            }
+        }
+        Node::HashJoin {
+            left,
+            right,
+            join_type,
+            left_key,
+            right_key,
+            predicate,
+        } => {
+            let needed = needed.map(|mut fields| {
+                if let Some(predicate) = &predicate {
+                    referenced_fields(predicate, &mut fields);
+                }
+                fields.push(left.columns()[left_key].clone());
+                fields.push(right.columns()[right_key].clone());
+                fields
+            });
+            Node::HashJoin {
+                left: Box::new(prune(*left, needed.clone())),
+                right: Box::new(prune(*right, needed)),
+                join_type,
+                left_key,
+                right_key,
+                predicate,
+            }
+        }
+        // 集合运算的两侧在构建时已经各自完成了裁剪
+        node @ (Node::KeyLookup { .. }
+        | Node::KeyRangeScan { .. }
+        | Node::IndexScan { .. }
+        | Node::Empty { .. }
+        | Node::SetOperation { .. }) => node,
+    }
+}
+
+/// 收集表达式引用的字段名，包括聚集函数的参数
+fn referenced_fields(expr: &Expression, fields: &mut Vec<String>) {
+    let mut refs = Vec::new();
+    expr.collect_fields(&mut refs);
+    fields.extend(refs.into_iter().cloned());
+
+    let mut functions = Vec::new();
+    expr.collect_functions(&mut functions);
+    for function in functions {
+        if let Expression::Function(_, col_name, _) = function {
+            fields.push(col_name.clone());
+        }
+    }
+}
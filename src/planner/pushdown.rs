@@ -0,0 +1,263 @@
+use crate::{
+    executor::expression::get_column_index_by_name,
+    parser::ast::{Expression, JoinType},
+    storage::Storage,
+    Result,
+};
+
+use super::{build_access, join_conjunction, split_conjunction, Node, Planner};
+
+/// 条件引用的列属于连接的哪一侧
+enum Side {
+    Left,
+    Right,
+}
+
+impl<S: Storage> Planner<'_, S> {
+    /// 谓词下推
+    ///
+    /// 将过滤条件按 `AND` 拆分，根据每个条件引用的列将其尽可能下推到靠近数据来源的位置：
+    ///
+    /// - 到达单表的扫描时，与扫描中已有的条件合并，重新选择访问路径；
+    /// - 经过连接时，只引用一侧的条件下推到这一侧，但不能下推到会被填充 NULL 的一侧，
+    ///   连接条件中只引用会被填充 NULL 的一侧的条件同样可以下推；
+    /// - 经过分组聚集时，只引用分组字段的条件下推到聚集之前；
+    /// - 可以穿过不限制行数的排序和去重，不能穿过偏移和限制、选择列。
+    ///
+    /// 无法下推的条件作为 `Filter` 留在原来的位置。
+    pub(super) fn push_down_predicates(&self, node: Node) -> Result<Node> {
+        self.push_down(node, Vec::new())
+    }
+
+    /// 将 `predicates` 下推到 `node` 中，返回应用了这些条件的节点
+    fn push_down(&self, node: Node, mut predicates: Vec<Expression>) -> Result<Node> {
+        let node = match node {
+            Node::Filter { source, predicate } => {
+                split_conjunction(predicate, &mut predicates);
+                return self.push_down(*source, predicates);
+            }
+            Node::Scan { table, filter, .. } => {
+                let mut conjuncts = Vec::new();
+                if let Some(filter) = filter {
+                    split_conjunction(filter, &mut conjuncts);
+                }
+                conjuncts.append(&mut predicates);
+                let stats = self.transaction.get_stats(&table.name)?;
+                return build_access(table, stats.as_ref(), join_conjunction(conjuncts));
+            }
+            Node::NestedLoopJoin {
+                left,
+                right,
+                join_type,
+                predicate,
+            } => {
+                let (left, right, predicate) =
+                    self.push_down_join(*left, *right, &join_type, predicate, &mut predicates)?;
+                Node::NestedLoopJoin {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    join_type,
+                    predicate,
+                }
+            }
+            Node::HashJoin {
+                left,
+                right,
+                join_type,
+                left_key,
+                right_key,
+                predicate,
+            } => {
+                let (left, right, predicate) =
+                    self.push_down_join(*left, *right, &join_type, predicate, &mut predicates)?;
+                Node::HashJoin {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    join_type,
+                    left_key,
+                    right_key,
+                    predicate,
+                }
+            }
+            Node::Aggregate {
+                source,
+                group_by,
+                aggregates,
+            } => {
+                let source_columns = source.columns();
+                let (pushed, kept) = predicates.into_iter().partition(|expr| {
+                    references_only_group_fields(expr, &source_columns, &group_by)
+                });
+                predicates = kept;
+                Node::Aggregate {
+                    source: Box::new(self.push_down(*source, pushed)?),
+                    group_by,
+                    aggregates,
+                }
+            }
+            // 过滤和排序可以交换，但是只保留前若干行的排序不行
+            Node::Order {
+                source,
+                ordering,
+                limit: None,
+            } => {
+                return Ok(Node::Order {
+                    source: Box::new(self.push_down(*source, predicates)?),
+                    ordering,
+                    limit: None,
+                })
+            }
+            Node::Order {
+                source,
+                ordering,
+                limit,
+            } => Node::Order {
+                source: Box::new(self.push_down(*source, Vec::new())?),
+                ordering,
+                limit,
+            },
+            // 去重比较整行，过滤之后重复的行仍然重复
+            Node::Distinct { source, sorted } => {
+                return Ok(Node::Distinct {
+                    source: Box::new(self.push_down(*source, predicates)?),
+                    sorted,
+                })
+            }
+            Node::Limit {
+                source,
+                offset,
+                limit,
+            } => Node::Limit {
+                source: Box::new(self.push_down(*source, Vec::new())?),
+                offset,
+                limit,
+            },
+            Node::Projection { source, columns } => Node::Projection {
+                source: Box::new(self.push_down(*source, Vec::new())?),
+                columns,
+            },
+            // 没有行的节点不需要过滤
+            node @ Node::Empty { .. } => return Ok(node),
+            // 集合运算的两侧在构建时已经各自完成了下推
+            node @ (Node::KeyLookup { .. }
+            | Node::KeyRangeScan { .. }
+            | Node::IndexScan { .. }
+            | Node::SetOperation { .. }) => node,
+        };
+
+        Ok(match join_conjunction(predicates) {
+            Some(predicate) => Node::Filter {
+                source: Box::new(node),
+                predicate,
+            },
+            None => node,
+        })
+    }
+
+    /// 将过滤条件和连接条件下推到连接的两侧，返回新的左右子节点和剩余的连接条件
+    ///
+    /// 能够下推的过滤条件从 `predicates` 中移除，剩余的留在连接之上。
+    /// 外连接中会被填充 NULL 的一侧：过滤条件会过滤掉填充 NULL 的行，因此不能下推到这一侧；
+    /// 而连接条件只决定是否匹配，只引用这一侧的连接条件可以下推。保留的一侧正好相反。
+    fn push_down_join(
+        &self,
+        left: Node,
+        right: Node,
+        join_type: &JoinType,
+        predicate: Option<Expression>,
+        predicates: &mut Vec<Expression>,
+    ) -> Result<(Node, Node, Option<Expression>)> {
+        let left_columns = left.columns();
+        let columns = [left_columns.clone(), right.columns()].concat();
+        let (left_nullable, right_nullable) = match join_type {
+            JoinType::Cross | JoinType::Inner => (false, false),
+            JoinType::Left => (false, true),
+            JoinType::Right => (true, false),
+            JoinType::Full => (true, true),
+        };
+
+        let mut left_predicates = Vec::new();
+        let mut right_predicates = Vec::new();
+
+        let mut kept = Vec::new();
+        for expr in predicates.drain(..) {
+            match side(&expr, &columns, left_columns.len()) {
+                Some(Side::Left) if !left_nullable => left_predicates.push(expr),
+                Some(Side::Right) if !right_nullable => right_predicates.push(expr),
+                _ => kept.push(expr),
+            }
+        }
+        *predicates = kept;
+
+        let mut join_predicates = Vec::new();
+        if let Some(predicate) = predicate {
+            split_conjunction(predicate, &mut join_predicates);
+        }
+        let mut kept = Vec::new();
+        for expr in join_predicates {
+            match side(&expr, &columns, left_columns.len()) {
+                Some(Side::Left) if !right_nullable => left_predicates.push(expr),
+                Some(Side::Right) if !left_nullable => right_predicates.push(expr),
+                _ => kept.push(expr),
+            }
+        }
+
+        Ok((
+            self.push_down(left, left_predicates)?,
+            self.push_down(right, right_predicates)?,
+            join_conjunction(kept),
+        ))
+    }
+}
+
+/// 条件引用的列全部属于连接的同一侧时返回这一侧
+///
+/// 不引用任何列、含有聚集函数或者列无法唯一解析的条件返回 `None`，留在原来的位置。
+fn side(expr: &Expression, columns: &[String], left_width: usize) -> Option<Side> {
+    let mut functions = Vec::new();
+    expr.collect_functions(&mut functions);
+    if !functions.is_empty() {
+        return None;
+    }
+
+    let mut fields = Vec::new();
+    expr.collect_fields(&mut fields);
+    let mut indexes = Vec::new();
+    for field in fields {
+        indexes.push(get_column_index_by_name(columns, field).ok()?);
+    }
+    if indexes.is_empty() {
+        None
+    } else if indexes.iter().all(|&i| i < left_width) {
+        Some(Side::Left)
+    } else if indexes.iter().all(|&i| i >= left_width) {
+        Some(Side::Right)
+    } else {
+        None
+    }
+}
+
+/// 条件是否只引用分组字段，这样的条件在聚集前后过滤的结果相同
+fn references_only_group_fields(
+    expr: &Expression,
+    source_columns: &[String],
+    group_by: &[Expression],
+) -> bool {
+    let mut functions = Vec::new();
+    expr.collect_functions(&mut functions);
+    let mut fields = Vec::new();
+    expr.collect_fields(&mut fields);
+    if !functions.is_empty() || fields.is_empty() {
+        return false;
+    }
+
+    let group_indexes = group_by
+        .iter()
+        .filter_map(|expr| expr.as_field())
+        .filter_map(|field| get_column_index_by_name(source_columns, field).ok())
+        .collect::<Vec<_>>();
+    fields.iter().all(|field| {
+        get_column_index_by_name(source_columns, field)
+            .is_ok_and(|index| group_indexes.contains(&index))
+    })
+}
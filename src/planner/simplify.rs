@@ -0,0 +1,388 @@
+use crate::{
+    executor::expression::{evaluate, get_column_index_by_name, infer_type},
+    parser::ast::{Aggregate, Constant, Expression, Operation},
+    schema::{DataType, Value},
+};
+
+use super::Node;
+
+/// 节点输出的每一列的类型，无法在计划时确定时为 `None`
+pub(crate) fn column_types(node: &Node) -> Vec<Option<DataType>> {
+    match node {
+        Node::Scan { table, .. }
+        | Node::KeyLookup { table, .. }
+        | Node::KeyRangeScan { table, .. }
+        | Node::IndexScan { table, .. } => table
+            .columns
+            .iter()
+            .map(|col| Some(col.data_type))
+            .collect(),
+        Node::NestedLoopJoin { left, right, .. } | Node::HashJoin { left, right, .. } => {
+            [column_types(left), column_types(right)].concat()
+        }
+        Node::Filter { source, .. }
+        | Node::Order { source, .. }
+        | Node::Limit { source, .. }
+        | Node::Distinct { source, .. } => column_types(source),
+        Node::Projection { source, columns } => {
+            let (source_columns, types) = (source.columns(), column_types(source));
+            columns
+                .iter()
+                .map(|(expr, _)| infer_type(expr, &source_columns, &types).ok().flatten())
+                .collect()
+        }
+        // COUNT 的结果为整数，AVG 的结果为浮点数，其他聚集函数的结果和参数的类型相同
+        Node::Aggregate {
+            source,
+            group_by,
+            aggregates,
+        } => {
+            let (source_columns, types) = (source.columns(), column_types(source));
+            let group_types = group_by
+                .iter()
+                .map(|expr| infer_type(expr, &source_columns, &types).ok().flatten());
+            let aggregate_types = aggregates.iter().map(|expr| match expr {
+                Expression::Function(Aggregate::Count, ..) => Some(DataType::Integer),
+                Expression::Function(Aggregate::Avg, ..) => Some(DataType::Float),
+                Expression::Function(_, col_name, _) => infer_type(
+                    &Expression::Field(col_name.clone()),
+                    &source_columns,
+                    &types,
+                )
+                .ok()
+                .flatten(),
+                _ => None,
+            });
+            group_types.chain(aggregate_types).collect()
+        }
+        Node::SetOperation { types, .. } => types.clone(),
+        node => vec![None; node.columns().len()],
+    }
+}
+
+/// 在计划时化简表达式
+///
+/// - 计算不引用列的常量子表达式，如 `1 = 1`、`5 + 5`；
+/// - 化简逻辑运算，如 `TRUE AND p` 为 `p`，`FALSE AND p` 为 `FALSE`，`NOT NOT p` 为 `p`；
+/// - 去掉数值列上的恒等运算，如 `col + 0`、`col * 1`。
+///
+/// 化简不能改变表达式出错的行为：计算出错的常量子表达式保持原样，留到执行时再报错；
+/// 只有被丢弃的子表达式在执行时一定不会出错时，才会丢弃它。
+/// `columns` 和 `types` 为表达式所在节点的输出列和列的类型。
+pub(super) fn simplify(
+    expr: Expression,
+    columns: &[String],
+    types: &[Option<DataType>],
+) -> Expression {
+    Simplifier { columns, types }.simplify(expr)
+}
+
+/// 判断表达式的值是否恒为假，`NULL` 在过滤时和 `FALSE` 一样不保留行
+pub(super) fn is_always_false(expr: &Expression) -> bool {
+    matches!(
+        expr,
+        Expression::Constant(Constant::Boolean(false) | Constant::Null)
+    )
+}
+
+/// 判断表达式的值是否恒为真
+pub(super) fn is_always_true(expr: &Expression) -> bool {
+    matches!(expr, Expression::Constant(Constant::Boolean(true)))
+}
+
+struct Simplifier<'a> {
+    columns: &'a [String],
+    types: &'a [Option<DataType>],
+}
+
+impl Simplifier<'_> {
+    /// 自底向上化简表达式
+    fn simplify(&self, expr: Expression) -> Expression {
+        let operation = match expr {
+            Expression::Operation(operation) => operation,
+            // 操作数是常量时直接计算，溢出时保持原样
+            Expression::Negate(operand) => {
+                let expr = Expression::Negate(Box::new(self.simplify(*operand)));
+                return self.fold_constant(&expr).unwrap_or(expr);
+            }
+            // 确定的函数的参数都是常量时直接计算，出错时保持原样
+            Expression::Scalar(function, args) => {
+                let args = args.into_iter().map(|arg| self.simplify(arg)).collect();
+                let expr = Expression::Scalar(function, args);
+                return self.fold_constant(&expr).unwrap_or(expr);
+            }
+            expr => return expr,
+        };
+        let simplify = |expr: Box<Expression>| Box::new(self.simplify(*expr));
+        let operation = match operation {
+            Operation::Equal(lhs, rhs) => Operation::Equal(simplify(lhs), simplify(rhs)),
+            Operation::NotEqual(lhs, rhs) => Operation::NotEqual(simplify(lhs), simplify(rhs)),
+            Operation::GreaterThan(lhs, rhs) => {
+                Operation::GreaterThan(simplify(lhs), simplify(rhs))
+            }
+            Operation::GreaterThanOrEqual(lhs, rhs) => {
+                Operation::GreaterThanOrEqual(simplify(lhs), simplify(rhs))
+            }
+            Operation::LessThan(lhs, rhs) => Operation::LessThan(simplify(lhs), simplify(rhs)),
+            Operation::LessThanOrEqual(lhs, rhs) => {
+                Operation::LessThanOrEqual(simplify(lhs), simplify(rhs))
+            }
+            Operation::Add(lhs, rhs) => Operation::Add(simplify(lhs), simplify(rhs)),
+            Operation::Subtract(lhs, rhs) => Operation::Subtract(simplify(lhs), simplify(rhs)),
+            Operation::Multiply(lhs, rhs) => Operation::Multiply(simplify(lhs), simplify(rhs)),
+            Operation::Divide(lhs, rhs) => Operation::Divide(simplify(lhs), simplify(rhs)),
+            Operation::And(lhs, rhs) => Operation::And(simplify(lhs), simplify(rhs)),
+            Operation::Or(lhs, rhs) => Operation::Or(simplify(lhs), simplify(rhs)),
+            Operation::Not(expr) => Operation::Not(simplify(expr)),
+            Operation::IsNull(expr) => Operation::IsNull(simplify(expr)),
+        };
+
+        // 操作数都是常量时直接计算，出错时保持原样
+        let expr = Expression::Operation(operation);
+        if let Some(constant) = self.fold_constant(&expr) {
+            return constant;
+        }
+        let Expression::Operation(operation) = expr else {
+            unreachable!()
+        };
+
+        match operation {
+            // TRUE AND p、p OR FALSE 的值与 p 相同，但 p 不是布尔值时会出错，因此要求 p 为布尔值
+            Operation::And(lhs, rhs) => match (constant_bool(&lhs), constant_bool(&rhs)) {
+                (Some(false), _) if self.is_infallible_boolean(&rhs) => false_constant(),
+                (_, Some(false)) if self.is_infallible_boolean(&lhs) => false_constant(),
+                (Some(true), _) if self.is_boolean(&rhs) => *rhs,
+                (_, Some(true)) if self.is_boolean(&lhs) => *lhs,
+                _ => Expression::Operation(Operation::And(lhs, rhs)),
+            },
+            Operation::Or(lhs, rhs) => match (constant_bool(&lhs), constant_bool(&rhs)) {
+                (Some(true), _) if self.is_infallible_boolean(&rhs) => true_constant(),
+                (_, Some(true)) if self.is_infallible_boolean(&lhs) => true_constant(),
+                (Some(false), _) if self.is_boolean(&rhs) => *rhs,
+                (_, Some(false)) if self.is_boolean(&lhs) => *lhs,
+                _ => Expression::Operation(Operation::Or(lhs, rhs)),
+            },
+            Operation::Not(expr) => match *expr {
+                Expression::Operation(Operation::Not(inner)) if self.is_boolean(&inner) => *inner,
+                expr => Expression::Operation(Operation::Not(Box::new(expr))),
+            },
+            // 数值加 0、乘 1 等运算的结果与原值相同，NULL 的结果仍然是 NULL
+            Operation::Add(lhs, rhs) => match (constant_int(&lhs), constant_int(&rhs)) {
+                (Some(0), _) if self.is_numeric(&rhs) => *rhs,
+                (_, Some(0)) if self.is_numeric(&lhs) => *lhs,
+                _ => Expression::Operation(Operation::Add(lhs, rhs)),
+            },
+            Operation::Subtract(lhs, rhs) => match constant_int(&rhs) {
+                Some(0) if self.is_numeric(&lhs) => *lhs,
+                _ => Expression::Operation(Operation::Subtract(lhs, rhs)),
+            },
+            Operation::Multiply(lhs, rhs) => match (constant_int(&lhs), constant_int(&rhs)) {
+                (Some(1), _) if self.is_numeric(&rhs) => *rhs,
+                (_, Some(1)) if self.is_numeric(&lhs) => *lhs,
+                _ => Expression::Operation(Operation::Multiply(lhs, rhs)),
+            },
+            Operation::Divide(lhs, rhs) => match constant_int(&rhs) {
+                Some(1) if self.is_numeric(&lhs) => *lhs,
+                _ => Expression::Operation(Operation::Divide(lhs, rhs)),
+            },
+            operation => Expression::Operation(operation),
+        }
+    }
+
+    /// 计算操作数都是常量的运算、负号或者确定的函数，出错或者结果无法表示为常量时返回 `None`
+    fn fold_constant(&self, expr: &Expression) -> Option<Expression> {
+        let operation = match expr {
+            Expression::Operation(operation) => operation,
+            Expression::Negate(operand) => return fold_operands(expr, std::iter::once(&**operand)),
+            Expression::Scalar(function, args) if function.signature().deterministic => {
+                return fold_operands(expr, args.iter());
+            }
+            _ => return None,
+        };
+        let operands = match operation {
+            Operation::Not(expr) | Operation::IsNull(expr) => vec![expr],
+            Operation::Equal(lhs, rhs)
+            | Operation::NotEqual(lhs, rhs)
+            | Operation::GreaterThan(lhs, rhs)
+            | Operation::GreaterThanOrEqual(lhs, rhs)
+            | Operation::LessThan(lhs, rhs)
+            | Operation::LessThanOrEqual(lhs, rhs)
+            | Operation::Add(lhs, rhs)
+            | Operation::Subtract(lhs, rhs)
+            | Operation::Multiply(lhs, rhs)
+            | Operation::Divide(lhs, rhs)
+            | Operation::And(lhs, rhs)
+            | Operation::Or(lhs, rhs) => vec![lhs, rhs],
+        };
+        fold_operands(expr, operands.into_iter().map(|expr| &**expr))
+    }
+
+    /// 字段的类型，字段无法唯一解析或者类型未知时返回 `None`
+    fn field_type(&self, name: &str) -> Option<DataType> {
+        let index = get_column_index_by_name(self.columns, name).ok()?;
+        self.types.get(index).copied().flatten()
+    }
+
+    /// 常量和字段的类型，外层为 `None` 表示无法确定，内层为 `None` 表示 `NULL`
+    ///
+    /// 其他表达式只有一定是布尔值时才能确定类型，`NULL` 和任何类型都可以比较，视为布尔值不影响判断。
+    fn value_type(&self, expr: &Expression) -> Option<Option<DataType>> {
+        match expr {
+            Expression::Constant(Constant::Null) => Some(None),
+            Expression::Constant(constant) => Value::from(Expression::Constant(constant.clone()))
+                .data_type()
+                .map(Some),
+            Expression::Field(name) => self.field_type(name).map(Some),
+            Expression::Value(value) => Some(value.data_type()),
+            expr if self.is_boolean(expr) => Some(Some(DataType::Boolean)),
+            _ => None,
+        }
+    }
+
+    /// 表达式的值是否一定是布尔值或者 `NULL`，不考虑计算是否出错
+    fn is_boolean(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Constant(constant) => {
+                matches!(constant, Constant::Boolean(_) | Constant::Null)
+            }
+            Expression::Field(name) => self.field_type(name) == Some(DataType::Boolean),
+            Expression::Operation(operation) => !matches!(
+                operation,
+                Operation::Add(..)
+                    | Operation::Subtract(..)
+                    | Operation::Multiply(..)
+                    | Operation::Divide(..)
+            ),
+            Expression::Negate(_)
+            | Expression::Function(..)
+            | Expression::Subquery(_)
+            | Expression::Call(..)
+            | Expression::Parameter(_) => false,
+            Expression::Scalar(function, _) => function.signature().returns == DataType::Boolean,
+            Expression::Coalesce(args) => args.iter().all(|arg| self.is_boolean(arg)),
+            Expression::Case(_, branches, else_result) => branches
+                .iter()
+                .map(|(_, then)| then)
+                .chain(else_result.as_deref())
+                .all(|result| self.is_boolean(result)),
+            Expression::Value(value) => matches!(value, Value::Boolean(_) | Value::Null),
+            Expression::InSubquery(..) | Expression::InSet(..) => true,
+        }
+    }
+
+    /// 表达式的值是否一定是数值或者 `NULL`
+    fn is_numeric(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Field(name) => matches!(
+                self.field_type(name),
+                Some(DataType::Integer | DataType::Float)
+            ),
+            _ => false,
+        }
+    }
+
+    /// 表达式是否一定可以计算出一个值而不出错
+    fn is_infallible(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Constant(_) | Expression::Value(_) => true,
+            Expression::Field(name) => get_column_index_by_name(self.columns, name).is_ok(),
+            // 等值比较在类型不可比较时出错，因此两侧的类型必须相同或者都是数值
+            Expression::Operation(Operation::Equal(lhs, rhs))
+            | Expression::Operation(Operation::NotEqual(lhs, rhs)) => {
+                let numeric = [DataType::Integer, DataType::Float];
+                self.is_infallible(lhs)
+                    && self.is_infallible(rhs)
+                    && match (self.value_type(lhs), self.value_type(rhs)) {
+                        (Some(None), Some(_)) | (Some(_), Some(None)) => true,
+                        (Some(Some(lhs)), Some(Some(rhs))) => {
+                            lhs == rhs || (numeric.contains(&lhs) && numeric.contains(&rhs))
+                        }
+                        _ => false,
+                    }
+            }
+            Expression::Operation(Operation::IsNull(expr)) => self.is_infallible(expr),
+            // 大小比较在类型不可比较时出错，浮点数可能是 NaN，因此只允许 NULL 或者相同的非浮点类型
+            Expression::Operation(
+                Operation::GreaterThan(lhs, rhs)
+                | Operation::GreaterThanOrEqual(lhs, rhs)
+                | Operation::LessThan(lhs, rhs)
+                | Operation::LessThanOrEqual(lhs, rhs),
+            ) => {
+                self.is_infallible(lhs)
+                    && self.is_infallible(rhs)
+                    && match (self.value_type(lhs), self.value_type(rhs)) {
+                        (Some(None), Some(_)) | (Some(_), Some(None)) => true,
+                        (Some(Some(lhs)), Some(Some(rhs))) => lhs == rhs && lhs != DataType::Float,
+                        _ => false,
+                    }
+            }
+            Expression::Operation(Operation::And(lhs, rhs) | Operation::Or(lhs, rhs)) => {
+                self.is_infallible_boolean(lhs) && self.is_infallible_boolean(rhs)
+            }
+            Expression::Operation(Operation::Not(expr)) => self.is_infallible_boolean(expr),
+            // 算术运算和负号可能溢出或者除以 0
+            Expression::Operation(_) | Expression::Negate(_) => false,
+            // IN 在两侧的类型不可比较时出错
+            Expression::Function(..)
+            | Expression::Subquery(_)
+            | Expression::InSubquery(..)
+            | Expression::InSet(..) => false,
+            // 函数的实现可能出错
+            Expression::Call(..) | Expression::Scalar(..) => false,
+            // 参数的类型未知
+            Expression::Parameter(_) => false,
+            // 所有参数都会被计算
+            Expression::Coalesce(args) => args.iter().all(|arg| self.is_infallible(arg)),
+            // 比较和分支结果的类型统一都可能出错
+            Expression::Case(..) => false,
+        }
+    }
+
+    /// 表达式是否一定可以计算出一个布尔值或者 `NULL` 而不出错
+    fn is_infallible_boolean(&self, expr: &Expression) -> bool {
+        self.is_boolean(expr) && self.is_infallible(expr)
+    }
+}
+
+/// 操作数都是常量时计算表达式 `expr`，出错或者结果无法表示为常量时返回 `None`
+fn fold_operands<'a>(
+    expr: &Expression,
+    mut operands: impl Iterator<Item = &'a Expression>,
+) -> Option<Expression> {
+    if !operands.all(Expression::is_constant) {
+        return None;
+    }
+    let constant = match evaluate(expr, &[], &vec![]).ok()? {
+        Value::Null => Constant::Null,
+        Value::Boolean(b) => Constant::Boolean(b),
+        Value::Integer(i) => Constant::Integer(i),
+        Value::Float(f) => Constant::Float(f),
+        Value::String(s) => Constant::String(s),
+        Value::Json(_) => return None,
+    };
+    Some(Expression::Constant(constant))
+}
+
+/// 表达式为布尔常量时返回它的值
+fn constant_bool(expr: &Expression) -> Option<bool> {
+    match expr {
+        Expression::Constant(Constant::Boolean(b)) => Some(*b),
+        _ => None,
+    }
+}
+
+/// 表达式为整数常量时返回它的值
+fn constant_int(expr: &Expression) -> Option<i64> {
+    match expr {
+        Expression::Constant(Constant::Integer(i)) => Some(*i),
+        _ => None,
+    }
+}
+
+fn true_constant() -> Expression {
+    Expression::Constant(Constant::Boolean(true))
+}
+
+fn false_constant() -> Expression {
+    Expression::Constant(Constant::Boolean(false))
+}
@@ -1,10 +1,23 @@
-use std::{cmp::Ordering, collections::HashMap, hash::Hash};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    codec::ValueCodec,
+    error::{
+        ExecutionError::{
+            DivisionByZero, DuplicateKey, InvalidCast, InvalidColumnValue, Overflow, RowLength,
+            TypeMismatch,
+        },
+        SchemaError::{ColumnNotFound, InvalidDefinition},
+        StorageError::Decode,
+    },
+    executor::{ColumnMeta, ResultSet},
     parser::ast::{Constant, Expression},
-    Error::InternalError,
     Result,
 };
 
@@ -15,50 +28,215 @@ pub enum DataType {
     Integer,
     Float,
     String,
+    Json,
 }
 
 /// 列定义
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
 pub struct Column {
     pub name: String,
     pub data_type: DataType,
     pub nullable: bool,
     pub default: Option<Value>,
     pub primary_key: bool,
+    /// 自增列，插入时省略或者为 NULL 的值从表的计数器中分配，只能是整数类型的主键
+    pub auto_increment: bool,
 }
 
 /// 值定义
-#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+///
+/// `PartialEq`、`Hash` 和 [`Value::total_cmp`] 对浮点数采用相同的规范化：-0.0 和 0.0 相等，所有 NaN 相等，
+/// 因此可以作为哈希表的 key 用于分组和去重。SQL 中的比较运算不使用这里的相等，NaN 和任何值比较都不相等。
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Value {
     Null,
     Boolean(bool),
     Integer(i64),
     Float(f64),
     String(String),
+    /// JSON 值，序列化为规范化的文本，见 [`json_text`]
+    #[serde(with = "json_text")]
+    Json(serde_json::Value),
+}
+
+/// JSON 值的序列化方式
+///
+/// `serde_json::Value` 的反序列化依赖自描述的格式，bincode 不支持，因此以规范化的 JSON 文本存储。
+/// 未开启 `preserve_order` 时对象的键按字典序排列，同一个 JSON 值的文本是唯一的。
+mod json_text {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &serde_json::Value,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<serde_json::Value, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        serde_json::from_str(&text).map_err(serde::de::Error::custom)
+    }
 }
 
 impl Value {
     pub fn as_f64(&self) -> Result<f64> {
         match self {
             Self::Float(f) => Ok(*f),
-            other => Err(InternalError(format!("Cannot convert {:?} to f64", other))),
+            other => Err(InvalidCast {
+                value: other.clone(),
+                target: "f64",
+            }
+            .into()),
         }
     }
 
     pub fn as_i64(&self) -> Result<i64> {
         match self {
             Self::Integer(i) => Ok(*i),
-            other => Err(InternalError(format!("Cannot convert {:?} to i64", other))),
+            other => Err(InvalidCast {
+                value: other.clone(),
+                target: "i64",
+            }
+            .into()),
         }
     }
 
     pub fn as_str(&self) -> Result<&str> {
         match self {
             Self::String(s) => Ok(s),
-            other => Err(InternalError(format!(
-                "Cannot convert {:?} to string",
-                other
-            ))),
+            other => Err(InvalidCast {
+                value: other.clone(),
+                target: "string",
+            }
+            .into()),
+        }
+    }
+
+    /// 按照以 `.` 分隔的路径提取 JSON 值中的字段，数组使用下标访问，如 `a.b.0.c`
+    ///
+    /// 提取到的 null、布尔、数值和字符串转换为对应的标量值，对象和数组仍为 JSON 值；
+    /// 路径不存在时返回 `Value::Null`，空路径返回整个值。
+    pub fn json_get(&self, path: &str) -> Result<Value> {
+        let Self::Json(json) = self else {
+            return Err(TypeMismatch(format!("Cannot extract path {path} from {:?}", self)).into());
+        };
+
+        let mut current = json;
+        for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+            let next = match current {
+                serde_json::Value::Object(map) => map.get(segment),
+                serde_json::Value::Array(array) => segment
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| array.get(index)),
+                _ => None,
+            };
+            match next {
+                Some(next) => current = next,
+                None => return Ok(Self::Null),
+            }
+        }
+
+        Ok(match current {
+            serde_json::Value::Null => Self::Null,
+            serde_json::Value::Bool(b) => Self::Boolean(*b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Self::Integer(i),
+                None => Self::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(s) => Self::String(s.clone()),
+            json => Self::Json(json.clone()),
+        })
+    }
+
+    /// 将值转换为列的数据类型，目前只有 JSON 列会将字符串解析为 JSON 值，其他情况保持不变
+    pub fn coerce_to(self, data_type: DataType) -> Result<Value> {
+        match (self, data_type) {
+            (Self::String(s), DataType::Json) => Ok(Self::Json(serde_json::from_str(&s)?)),
+            (value, _) => Ok(value),
+        }
+    }
+}
+
+impl Value {
+    /// 加法运算
+    pub fn checked_add(&self, other: &Value) -> Result<Value> {
+        self.arithmetic(other, "+", i64::checked_add, |a, b| a + b)
+    }
+
+    /// 减法运算
+    pub fn checked_sub(&self, other: &Value) -> Result<Value> {
+        self.arithmetic(other, "-", i64::checked_sub, |a, b| a - b)
+    }
+
+    /// 乘法运算
+    pub fn checked_mul(&self, other: &Value) -> Result<Value> {
+        self.arithmetic(other, "*", i64::checked_mul, |a, b| a * b)
+    }
+
+    /// 除法运算，除数为 0 时返回错误
+    pub fn checked_div(&self, other: &Value) -> Result<Value> {
+        match other {
+            Self::Integer(0) => Err(DivisionByZero.into()),
+            Self::Float(f) if *f == 0.0 => Err(DivisionByZero.into()),
+            _ => self.arithmetic(other, "/", i64::checked_div, |a, b| a / b),
+        }
+    }
+
+    /// 取相反数，`Null` 的结果为 `Null`，`i64::MIN` 取相反数溢出时返回错误
+    pub fn neg(&self) -> Result<Value> {
+        self.unary_arithmetic("-", i64::checked_neg, |f| -f)
+    }
+
+    /// 取绝对值，`Null` 的结果为 `Null`，`i64::MIN` 的绝对值溢出时返回错误
+    pub fn abs(&self) -> Result<Value> {
+        self.unary_arithmetic("abs", i64::checked_abs, f64::abs)
+    }
+
+    /// 一元算术运算的内置函数，只接受整数、浮点数和 `Null`
+    fn unary_arithmetic(
+        &self,
+        op: &str,
+        int_op: fn(i64) -> Option<i64>,
+        float_op: fn(f64) -> f64,
+    ) -> Result<Value> {
+        match self {
+            Self::Null => Ok(Self::Null),
+            Self::Integer(i) => int_op(*i)
+                .map(Self::Integer)
+                .ok_or(Overflow(format!("{op}({i})")).into()),
+            Self::Float(f) => Ok(Self::Float(float_op(*f))),
+            value => Err(TypeMismatch(format!("Cannot compute {op}({:?})", value)).into()),
+        }
+    }
+
+    /// 算术运算的内置函数
+    ///
+    /// - 任意一侧为 `Null` 时，结果为 `Null`；
+    /// - 两侧均为整数时，按整数运算，溢出时返回错误；
+    /// - 整数和浮点数混合运算时，整数会提升为浮点数；
+    /// - 其他类型返回错误。
+    fn arithmetic(
+        &self,
+        other: &Value,
+        op: &str,
+        int_op: fn(i64, i64) -> Option<i64>,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Result<Value> {
+        match (self, other) {
+            (Self::Null, _) | (_, Self::Null) => Ok(Self::Null),
+            (Self::Integer(a), Self::Integer(b)) => int_op(*a, *b)
+                .map(Self::Integer)
+                .ok_or(Overflow(format!("{a} {op} {b}")).into()),
+            (Self::Integer(a), Self::Float(b)) => Ok(Self::Float(float_op(*a as f64, *b))),
+            (Self::Float(a), Self::Integer(b)) => Ok(Self::Float(float_op(*a, *b as f64))),
+            (Self::Float(a), Self::Float(b)) => Ok(Self::Float(float_op(*a, *b))),
+            (lhs, rhs) => {
+                Err(TypeMismatch(format!("Cannot compute {:?} {op} {:?}", lhs, rhs)).into())
+            }
         }
     }
 }
@@ -75,11 +253,99 @@ impl PartialOrd for Value {
             (Self::Integer(a), Self::Float(b)) => (*a as f64).partial_cmp(b),
             (Self::Float(a), Self::Integer(b)) => a.partial_cmp(&(*b as f64)),
             (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            (Self::Json(a), Self::Json(b)) => a.to_string().partial_cmp(&b.to_string()),
             _ => None,
         }
     }
 }
 
+impl Value {
+    /// 全序比较，用于排序
+    ///
+    /// 不同类型之间按照 NULL < 布尔 < 数值 < 字符串 < JSON 的顺序比较；整数和浮点数之间按照数值比较，
+    /// 数值相等时整数在前；-0.0 和 0.0 相等，NaN 大于其他所有数值，所有 NaN 之间相等；
+    /// JSON 值之间按照规范化序列化后的文本比较。
+    pub fn total_cmp(&self, other: &Self) -> Ordering {
+        // 整数和浮点数精确比较，避免整数转换为浮点数时丢失精度，导致比较结果不满足传递性
+        fn cmp_int_float(a: i64, b: f64) -> Ordering {
+            if b.is_nan() || b >= i64::MAX as f64 {
+                return Ordering::Less;
+            }
+            if b < i64::MIN as f64 {
+                return Ordering::Greater;
+            }
+            let trunc = b.trunc();
+            a.cmp(&(trunc as i64))
+                .then(trunc.partial_cmp(&b).unwrap())
+                .then(Ordering::Less)
+        }
+
+        let rank = |value: &Value| match value {
+            Self::Null => 0,
+            Self::Boolean(_) => 1,
+            Self::Integer(_) | Self::Float(_) => 2,
+            Self::String(_) => 3,
+            Self::Json(_) => 4,
+        };
+        let cmp_float = |a: f64, b: f64| match (a.is_nan(), b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => a.partial_cmp(&b).unwrap(),
+        };
+        match (self, other) {
+            (Self::Boolean(a), Self::Boolean(b)) => a.cmp(b),
+            (Self::Integer(a), Self::Integer(b)) => a.cmp(b),
+            (Self::Float(a), Self::Float(b)) => cmp_float(*a, *b),
+            (Self::Integer(a), Self::Float(b)) => cmp_int_float(*a, *b),
+            (Self::Float(a), Self::Integer(b)) => cmp_int_float(*b, *a).reverse(),
+            (Self::String(a), Self::String(b)) => a.cmp(b),
+            (Self::Json(a), Self::Json(b)) => a.to_string().cmp(&b.to_string()),
+            (lhs, rhs) => rank(lhs).cmp(&rank(rhs)),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Null => write!(f, "NULL"),
+            Self::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Float(v) => write!(f, "{:?}", v),
+            Self::String(s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Self::Json(json) => write!(f, "{}", json),
+        }
+    }
+}
+
+/// 浮点数规范化后的二进制表示，-0.0 规范化为 0.0，所有 NaN 规范化为同一个 NaN
+pub(crate) fn canonical_float_bits(f: f64) -> u64 {
+    if f.is_nan() {
+        f64::NAN.to_bits()
+    } else if f == 0.0 {
+        0.0f64.to_bits()
+    } else {
+        f.to_bits()
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Null, Self::Null) => true,
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::Integer(a), Self::Integer(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => {
+                canonical_float_bits(*a) == canonical_float_bits(*b)
+            }
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Json(a), Self::Json(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
 impl Hash for Value {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         match self {
@@ -94,12 +360,16 @@ impl Hash for Value {
             }
             Self::Float(f) => {
                 state.write_u8(3);
-                f.to_bits().hash(state)
+                canonical_float_bits(*f).hash(state)
             }
             Self::String(s) => {
                 state.write_u8(4);
                 s.hash(state)
             }
+            Self::Json(json) => {
+                state.write_u8(5);
+                json.to_string().hash(state)
+            }
         }
     }
 }
@@ -117,12 +387,37 @@ impl From<Expression> for Value {
                 Constant::String(s) => Value::String(s),
                 Constant::Null => Value::Null,
             },
+            Expression::Value(value) => value,
             _ => panic!("Cannot convert non-constant expression to value"),
         }
     }
 }
 
+impl From<Value> for Expression {
+    /// 将值转为表达式，能用常量表示时为常量，否则为 [`Expression::Value`]
+    fn from(value: Value) -> Self {
+        let constant = match value {
+            Value::Null => Constant::Null,
+            Value::Boolean(b) => Constant::Boolean(b),
+            Value::Integer(i) => Constant::Integer(i),
+            Value::Float(f) => Constant::Float(f),
+            Value::String(s) => Constant::String(s),
+            value @ Value::Json(_) => return Expression::Value(value),
+        };
+        Expression::Constant(constant)
+    }
+}
+
 impl Value {
+    /// SQL 的 COALESCE，返回第一个不为 NULL 的值，全部为 NULL 或没有值时返回 `Value::Null`
+    pub fn coalesce(values: &[Value]) -> Value {
+        values
+            .iter()
+            .find(|value| **value != Value::Null)
+            .cloned()
+            .unwrap_or(Value::Null)
+    }
+
     /// 获取数据类型
     pub fn data_type(&self) -> Option<DataType> {
         match self {
@@ -131,25 +426,174 @@ impl Value {
             Self::Integer(_) => Some(DataType::Integer),
             Self::Float(_) => Some(DataType::Float),
             Self::String(_) => Some(DataType::String),
+            Self::Json(_) => Some(DataType::Json),
+        }
+    }
+}
+
+/// 为值实现到 Rust 类型的转换，类型不匹配时返回错误
+macro_rules! impl_try_from_value {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl TryFrom<Value> for $ty {
+                type Error = crate::Error;
+
+                fn try_from(value: Value) -> Result<Self> {
+                    match value {
+                        Value::$variant(v) => Ok(v),
+                        other => Err(InvalidCast {
+                            value: other,
+                            target: stringify!($ty),
+                        }
+                        .into()),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_value! {
+    bool => Boolean,
+    i64 => Integer,
+    f64 => Float,
+    String => String,
+    serde_json::Value => Json,
+}
+
+/// 可以为 NULL 的值，`Value::Null` 转换为 `None`
+impl<T: TryFrom<Value, Error = crate::Error>> TryFrom<Value> for Option<T> {
+    type Error = crate::Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::try_from(value).map(Some),
         }
     }
 }
 
 pub type Row = Vec<Value>;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// 存储中 key 的命名空间配置
+///
+/// 表名和索引名在 key 中以 `separator` 结尾，因此表名、列名和索引名中不能包含分隔符，
+/// 否则一张表的 key 可能成为另一张表的 key 的前缀。默认的分隔符为 `0x00`。
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Namespace {
+    pub separator: u8,
+}
+
+impl Namespace {
+    /// 检查名称中是否包含分隔符，包含时返回错误
+    pub fn check_name(&self, kind: &str, name: &str) -> Result<()> {
+        if name.as_bytes().contains(&self.separator) {
+            return Err(InvalidDefinition(format!(
+                "{} name {:?} contains the key separator {:#04x}",
+                kind, name, self.separator
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// 将名称和分隔符追加到 `out` 中
+    pub(crate) fn encode_name(&self, name: &str, out: &mut Vec<u8>) {
+        out.extend_from_slice(name.as_bytes());
+        out.push(self.separator);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
     primary_key_idx: usize,
     col_idx: HashMap<String, usize>,
+    pub indexes: Vec<IndexDef>,
+    pub foreign_keys: Vec<ForeignKey>,
+    /// 表定义的版本，新建的表为 0，每次修改已有的表定义时加 1
+    schema_version: u64,
+}
+
+/// 外键定义
+///
+/// `column` 列中不为 NULL 的值必须是 `parent_table` 中已有行的主键，`parent_table` 可以是表自身。
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct ForeignKey {
+    /// 约束名
+    pub name: String,
+    pub column: String,
+    pub parent_table: String,
+    pub on_delete: OnDelete,
+}
+
+/// 删除被外键引用的行时的处理方式
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum OnDelete {
+    /// 存在引用时拒绝删除
+    #[default]
+    Restrict,
+    /// 在同一个事务中递归删除引用它的行
+    Cascade,
+}
+
+/// 二级索引定义
+///
+/// 索引按照 `columns` 的顺序组织，`unique` 为真时，索引列的值（不含 NULL）在表中不能重复。
+/// `predicate` 不为空时为部分索引，只有满足条件的行写入索引，唯一约束也只在这些行之间检查。
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct IndexDef {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+    /// 部分索引的条件，只能引用表中的字段，见 [`Expression::is_row_expression`]
+    #[serde(with = "predicate_text")]
+    pub predicate: Option<Expression>,
+}
+
+/// 部分索引的条件以 SQL 文本的形式保存，读取时重新解析
+mod predicate_text {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::parser::{ast::Expression, Parser};
+
+    pub fn serialize<S: Serializer>(
+        predicate: &Option<Expression>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match predicate {
+            Some(predicate) => serializer.serialize_some(&predicate.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Expression>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| Parser::parse_standalone_expression(&text))
+            .transpose()
+            .map_err(serde::de::Error::custom)
+    }
 }
 
 impl Table {
+    /// 在默认的命名空间中创建表定义，见 [`Table::with_namespace`]
     pub fn new(name: &str, columns: Vec<Column>) -> Result<Self> {
+        Self::with_namespace(name, columns, &Namespace::default())
+    }
+
+    /// 创建表定义，表名和列名中不能包含 `namespace` 的分隔符
+    pub fn with_namespace(name: &str, columns: Vec<Column>, namespace: &Namespace) -> Result<Self> {
+        namespace.check_name("Table", name)?;
+        for col in &columns {
+            namespace.check_name("Column", &col.name)?;
+        }
+
         // 检查表是否有列定义，如果没有则返回错误
         if columns.is_empty() {
-            return Err(InternalError(format!("Table {} has no columns", name)));
+            return Err(InvalidDefinition(format!("Table {} has no columns", name)).into());
         }
 
         // 检查是否有且仅有一个主键
@@ -159,30 +603,42 @@ impl Table {
             .filter_map(|(i, col)| if col.primary_key { Some(i) } else { None })
             .collect();
         if pk_indexes.is_empty() {
-            return Err(InternalError(format!("Table {} has no primary key", name)));
+            return Err(InvalidDefinition(format!("Table {} has no primary key", name)).into());
         } else if pk_indexes.len() > 1 {
-            return Err(InternalError(format!(
-                "Table {} has more than one primary key",
-                name
-            )));
+            return Err(
+                InvalidDefinition(format!("Table {} has more than one primary key", name)).into(),
+            );
         }
 
         // 检查主键是否为 nullable，如果是则返回错误
         if columns[pk_indexes[0]].nullable {
-            return Err(InternalError(format!(
+            return Err(InvalidDefinition(format!(
                 "Primary key {} cannot be nullable",
                 columns[pk_indexes[0]].name
-            )));
+            ))
+            .into());
+        }
+
+        // 自增列从表的计数器中分配值，每个表只有一个计数器，因此只能是整数类型的主键
+        for col in &columns {
+            if col.auto_increment && (!col.primary_key || col.data_type != DataType::Integer) {
+                return Err(InvalidDefinition(format!(
+                    "Auto-increment column {} must be an integer primary key",
+                    col.name
+                ))
+                .into());
+            }
         }
 
         // 检查默认值是否和数据类型匹配
         for col in &columns {
             if let Some(default) = &col.default {
                 if default.data_type() != Some(col.data_type) {
-                    return Err(InternalError(format!(
+                    return Err(InvalidDefinition(format!(
                         "Default value {:?} does not match column {}'s data type",
                         default, col.name
-                    )));
+                    ))
+                    .into());
                 }
             }
         }
@@ -199,18 +655,801 @@ impl Table {
             columns,
             primary_key_idx: pk_indexes[0],
             col_idx,
+            indexes: Vec::new(),
+            foreign_keys: Vec::new(),
+            schema_version: 0,
         })
     }
 
+    /// 表定义的版本，单调递增，缓存了按照表定义生成的结果时可以用来检查定义是否已经改变
+    pub fn schema_version(&self) -> u64 {
+        self.schema_version
+    }
+
+    /// 添加索引定义，表定义的版本加 1，不检查索引是否合法
+    pub fn add_index(&mut self, index: IndexDef) {
+        self.indexes.push(index);
+        self.schema_version += 1;
+    }
+
+    /// 检查行数据是否符合表定义
+    ///
+    /// 检查列数是否一致、非空列是否为空，以及数据类型是否和列定义相符，
+    /// 不符合时错误中包含列名和列的位置，例如 `column 'name' (index 1): expected String, found Integer`
+    pub fn validate_row(&self, row: &Row) -> Result<()> {
+        if row.len() != self.columns.len() {
+            return Err(RowLength {
+                table: self.name.clone(),
+                expected: self.columns.len(),
+                found: row.len(),
+            }
+            .into());
+        }
+
+        for (col_idx, value) in row.iter().enumerate() {
+            self.validate_value(col_idx, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// 按照 [`Table::validate_row`] 检查一批待插入的行，并检查这批行之间是否有重复的主键
+    ///
+    /// 只检查这批行本身，不读取存储，主键和已有的行是否冲突仍然在写入时检查。
+    /// 多行插入可以在写入之前发现客户端的错误，返回第一个不合法的行或者第一个重复的主键。
+    /// 自增列为 NULL 的行在写入时才分配 ID，只检查其他列。
+    pub fn validate_rows(&self, rows: &[Row]) -> Result<()> {
+        let auto_increment = self.auto_increment_idx();
+        let mut keys = HashSet::with_capacity(rows.len());
+        for row in rows {
+            if let Some(idx) = auto_increment.filter(|&idx| row.get(idx) == Some(&Value::Null)) {
+                if row.len() != self.columns.len() {
+                    return Err(RowLength {
+                        table: self.name.clone(),
+                        expected: self.columns.len(),
+                        found: row.len(),
+                    }
+                    .into());
+                }
+                for (col_idx, value) in row.iter().enumerate() {
+                    if col_idx != idx {
+                        self.validate_value(col_idx, value)?;
+                    }
+                }
+                continue;
+            }
+            self.validate_row(row)?;
+            let key = self.get_primary_key(row);
+            if !keys.insert(key) {
+                return Err(DuplicateKey {
+                    table: self.name.clone(),
+                    key: key.clone(),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// 检查第 `col_idx` 列的值是否符合列定义
+    fn validate_value(&self, col_idx: usize, value: &Value) -> Result<()> {
+        let column = &self.columns[col_idx];
+        match value.data_type() {
+            None if !column.nullable => Err(InvalidColumnValue {
+                column: column.name.clone(),
+                index: col_idx,
+                expected: column.data_type,
+                found: None,
+            }
+            .into()),
+            Some(data_type) if data_type != column.data_type => Err(InvalidColumnValue {
+                column: column.name.clone(),
+                index: col_idx,
+                expected: column.data_type,
+                found: Some(data_type),
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// 解码 `bincode::serialize` 编码的行，并按照 [`Table::validate_row`] 检查是否符合表定义
+    ///
+    /// 存储中的行和表定义不一致时，错误指出第一个不一致的列名和位置。
+    /// 仅用于测试，存储中的行按照引擎的编码方式解码，见 [`Table::decode_row_with`]。
+    #[cfg(test)]
+    pub fn decode_row(&self, bytes: &[u8]) -> Result<Row> {
+        self.decode_row_with(&crate::codec::BincodeCodec, bytes)
+    }
+
+    /// 使用 `codec` 解码行，并按照 [`Table::validate_row`] 检查是否符合表定义
+    pub fn decode_row_with(&self, codec: &dyn ValueCodec, bytes: &[u8]) -> Result<Row> {
+        let row = codec.decode_row(bytes)?;
+        self.validate_row(&row)?;
+        Ok(row)
+    }
+
+    /// 只解码 `bincode::serialize` 编码的行中下标在 `needed` 中的列，其他列直接跳过，在结果中为 NULL
+    ///
+    /// 结果的列数和表定义相同，因此按列的位置访问行的代码不需要修改。
+    /// 行的编码见 [`Table::extract_primary_key`]，解码的列按照 [`Table::validate_row`] 检查。
+    /// 仅用于测试，存储中的行按照引擎的编码方式解码，见 [`Table::decode_columns_with`]。
+    #[cfg(test)]
+    pub fn decode_columns(&self, bytes: &[u8], needed: &[usize]) -> Result<Row> {
+        self.decode_columns_with(&crate::codec::BincodeCodec, bytes, needed)
+    }
+
+    /// 使用 `codec` 只解码下标在 `needed` 中的列，解码的列按照 [`Table::validate_row`] 检查
+    pub fn decode_columns_with(
+        &self,
+        codec: &dyn ValueCodec,
+        bytes: &[u8],
+        needed: &[usize],
+    ) -> Result<Row> {
+        let row = codec.decode_columns(bytes, needed)?;
+        if row.len() != self.columns.len() {
+            return Err(Decode {
+                context: "decoding columns",
+                bytes: bytes.to_vec(),
+            }
+            .into());
+        }
+        for &col_idx in needed {
+            self.validate_value(col_idx, &row[col_idx])?;
+        }
+        Ok(row)
+    }
+
     /// 获取一个行的主键值
     #[inline]
     pub fn get_primary_key<'a>(&self, row: &'a Row) -> &'a Value {
         &row[self.primary_key_idx]
     }
 
+    /// 从 `bincode::serialize` 编码的行中只解码主键列，之前的列直接跳过而不反序列化
+    ///
+    /// 行的编码为：[列数 u64, 每一列的值]，值的编码为：[枚举索引 u32, 数据]，整数都是小端编码。
+    /// 主键按照 [`Table::validate_row`] 检查，为 NULL 或者类型不符时返回错误，
+    /// 避免用一个不合法的主键拼出存储中的 key。
+    pub fn extract_primary_key(&self, bytes: &[u8]) -> Result<Value> {
+        let decode_error = || Decode {
+            context: "extracting primary key",
+            bytes: bytes.to_vec(),
+        };
+
+        let mut rest = bytes;
+        if read_u64(&mut rest) != Some(self.columns.len() as u64) {
+            return Err(decode_error().into());
+        }
+        for _ in 0..self.primary_key_idx {
+            skip_value(&mut rest).ok_or_else(decode_error)?;
+        }
+        let pk = bincode::deserialize(rest).map_err(|_| decode_error())?;
+        self.validate_value(self.primary_key_idx, &pk)?;
+        Ok(pk)
+    }
+
+    /// 获取主键列的定义
+    #[inline]
+    pub fn get_primary_key_column(&self) -> &Column {
+        &self.columns[self.primary_key_idx]
+    }
+
+    /// 自增列的位置，表没有自增列时返回 `None`
+    #[inline]
+    pub fn auto_increment_idx(&self) -> Option<usize> {
+        self.get_primary_key_column()
+            .auto_increment
+            .then_some(self.primary_key_idx)
+    }
+
+    /// 根据名称获取二级索引的定义
+    pub fn get_index(&self, index_name: &str) -> Option<&IndexDef> {
+        self.indexes.iter().find(|index| index.name == index_name)
+    }
+
     /// 获取列的索引
     #[inline]
     pub fn get_col_idx(&self, col_name: &str) -> Option<usize> {
         self.col_idx.get(col_name).copied()
     }
+
+    /// 获取行中名为 `col_name` 的列的值
+    ///
+    /// 列不存在，或者行的长度不足时返回错误，而不是因为越界而 panic。
+    pub fn value_of<'a>(&self, row: &'a Row, col_name: &str) -> Result<&'a Value> {
+        let col_idx = self.get_col_idx(col_name).ok_or(ColumnNotFound {
+            table: self.name.clone(),
+            column: col_name.to_string(),
+        })?;
+        row.get(col_idx).ok_or(
+            RowLength {
+                table: self.name.clone(),
+                expected: self.columns.len(),
+                found: row.len(),
+            }
+            .into(),
+        )
+    }
+
+    /// 比较同一主键的新旧两行，返回值不同的列的 `(列名, 旧值, 新值)`，主键列不参与比较
+    ///
+    /// 两个值都为 `Null` 时视为相同，可以据此生成只更新变化列的 `UPDATE` 语句。
+    pub fn row_diff(&self, old: &Row, new: &Row) -> Result<Vec<(String, Value, Value)>> {
+        for row in [old, new] {
+            if row.len() != self.columns.len() {
+                return Err(RowLength {
+                    table: self.name.clone(),
+                    expected: self.columns.len(),
+                    found: row.len(),
+                }
+                .into());
+            }
+        }
+
+        Ok(self
+            .columns
+            .iter()
+            .zip(old.iter().zip(new.iter()))
+            .enumerate()
+            .filter(|(i, (_, (old, new)))| *i != self.primary_key_idx && old != new)
+            .map(|(_, (column, (old, new)))| (column.name.clone(), old.clone(), new.clone()))
+            .collect())
+    }
+
+    /// 由表的行构造查询结果，列名和列的元数据取自表的定义，而不是根据行推断
+    pub fn result_set(&self, rows: Vec<Row>) -> ResultSet {
+        let columns = self
+            .columns
+            .iter()
+            .map(|column| ColumnMeta {
+                name: column.name.clone(),
+                data_type: Some(column.data_type),
+                nullable: column.nullable,
+            })
+            .collect();
+        ResultSet::Query { columns, rows }
+    }
+}
+
+/// 逐列构造 [`Table`]，[`TableBuilder::build`] 时进行和 [`Table::new`] 相同的检查
+///
+/// 适合在测试和迁移脚本中构造表定义，之后通过事务的 `create_table` 创建表。
+///
+/// ```
+/// use sqldb::{Column, DataType, TableBuilder};
+///
+/// let column = |name: &str, data_type| Column {
+///     name: name.to_string(),
+///     data_type,
+///     nullable: false,
+///     default: None,
+///     primary_key: false,
+///     auto_increment: false,
+/// };
+/// let table = TableBuilder::new("users")
+///     .primary_key_column(column("id", DataType::Integer))
+///     .column(column("name", DataType::String))
+///     .build()?;
+/// assert_eq!(table.get_primary_key_column().name, "id");
+/// # Ok::<(), sqldb::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TableBuilder {
+    name: String,
+    columns: Vec<Column>,
+}
+
+impl TableBuilder {
+    /// 创建名为 `name` 的表的构造器，初始没有列
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            columns: Vec::new(),
+        }
+    }
+
+    /// 按照 `column` 自身的定义追加一列
+    pub fn column(mut self, column: Column) -> Self {
+        self.columns.push(column);
+        self
+    }
+
+    /// 追加一列并将其设为主键，其他的定义不变，因此可以为空的列在构造时仍然会报错
+    pub fn primary_key_column(mut self, column: Column) -> Self {
+        self.columns.push(Column {
+            primary_key: true,
+            ..column
+        });
+        self
+    }
+
+    /// 按照追加的顺序构造表，列的定义不合法时返回和 [`Table::new`] 相同的错误
+    pub fn build(self) -> Result<Table> {
+        Table::new(&self.name, self.columns)
+    }
+}
+
+/// 从 `bytes` 的开头取出 `n` 个字节，长度不足时返回 `None`
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if bytes.len() < n {
+        return None;
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Some(head)
+}
+
+pub(crate) fn read_u64(bytes: &mut &[u8]) -> Option<u64> {
+    take(bytes, 8)?.try_into().ok().map(u64::from_le_bytes)
+}
+
+/// 跳过一个编码后的 [`Value`]，枚举索引和 `Value` 的变体顺序一致，字符串和 JSON 文本都是 [长度 u64, 数据]
+pub(crate) fn skip_value(bytes: &mut &[u8]) -> Option<()> {
+    let tag = u32::from_le_bytes(take(bytes, 4)?.try_into().ok()?);
+    let len = match tag {
+        0 => 0,
+        1 => 1,
+        2 | 3 => 8,
+        4 | 5 => usize::try_from(read_u64(bytes)?).ok()?,
+        _ => return None,
+    };
+    take(bytes, len).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, data_type: DataType, primary_key: bool) -> Column {
+        Column {
+            name: name.to_string(),
+            data_type,
+            nullable: !primary_key,
+            default: None,
+            primary_key,
+            auto_increment: false,
+        }
+    }
+
+    #[test]
+    fn test_table_builder() -> Result<()> {
+        let built = TableBuilder::new("users")
+            .primary_key_column(column("id", DataType::Integer, false))
+            .column(column("name", DataType::String, false))
+            .build();
+        assert_eq!(
+            built.unwrap_err(),
+            InvalidDefinition("Primary key id cannot be nullable".to_string()).into()
+        );
+
+        let built = TableBuilder::new("users")
+            .primary_key_column(Column {
+                nullable: false,
+                ..column("id", DataType::Integer, false)
+            })
+            .column(column("name", DataType::String, false))
+            .build()?;
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("name", DataType::String, false),
+            ],
+        )?;
+        // 两张表的列名和列的元数据相同，同一行在两张表中的主键相同
+        let row = vec![Value::Integer(1), Value::String("alice".to_string())];
+        assert_eq!(
+            built.result_set(vec![row.clone()]),
+            table.result_set(vec![row.clone()])
+        );
+        assert_eq!(built.get_primary_key(&row), table.get_primary_key(&row));
+
+        // 和 Table::new 一样检查主键
+        assert!(TableBuilder::new("t").build().is_err());
+        assert!(TableBuilder::new("t")
+            .column(column("a", DataType::Integer, false))
+            .build()
+            .is_err());
+        assert!(TableBuilder::new("t")
+            .primary_key_column(column("a", DataType::Integer, true))
+            .primary_key_column(column("b", DataType::Integer, true))
+            .build()
+            .is_err());
+
+        // 自增列只能是整数类型的主键
+        let auto_increment = |column: Column| Column {
+            auto_increment: true,
+            ..column
+        };
+        let table = TableBuilder::new("t")
+            .column(auto_increment(column("a", DataType::Integer, true)))
+            .build()?;
+        assert_eq!(table.auto_increment_idx(), Some(0));
+        assert_eq!(built.auto_increment_idx(), None);
+        for columns in [
+            vec![
+                column("a", DataType::Integer, true),
+                auto_increment(column("b", DataType::Integer, false)),
+            ],
+            vec![auto_increment(column("a", DataType::String, true))],
+        ] {
+            assert_eq!(
+                Table::new("t", columns).unwrap_err().code(),
+                crate::ErrorCode::InvalidDefinition
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_version() -> Result<()> {
+        let mut table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("email", DataType::String, false),
+            ],
+        )?;
+        assert_eq!(table.schema_version(), 0);
+
+        for (version, name) in [(1, "idx_email"), (2, "idx_email_unique")] {
+            table.add_index(IndexDef {
+                name: name.to_string(),
+                columns: vec!["email".to_string()],
+                unique: false,
+                predicate: None,
+            });
+            assert_eq!(table.schema_version(), version);
+        }
+
+        // 版本随表定义一起保存
+        let decoded: Table = bincode::deserialize(&bincode::serialize(&table)?)?;
+        assert_eq!(decoded.schema_version(), 2);
+        assert_eq!(decoded.indexes, table.indexes);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_diff() -> Result<()> {
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("name", DataType::String, false),
+                column("score", DataType::Float, false),
+                column("note", DataType::String, false),
+            ],
+        )?;
+        let row = vec![
+            Value::Integer(1),
+            Value::String("a".to_string()),
+            Value::Float(1.5),
+            Value::Null,
+        ];
+
+        // 相同的两行没有差异，两个 Null 视为相同
+        assert_eq!(table.row_diff(&row, &row.clone())?, vec![]);
+
+        // 只返回值不同的列，主键不参与比较
+        let mut new_row = row.clone();
+        new_row[0] = Value::Integer(2);
+        new_row[2] = Value::Float(2.5);
+        assert_eq!(
+            table.row_diff(&row, &new_row)?,
+            vec![("score".to_string(), Value::Float(1.5), Value::Float(2.5))]
+        );
+        new_row[3] = Value::String("b".to_string());
+        assert_eq!(
+            table.row_diff(&new_row, &row)?,
+            vec![
+                ("score".to_string(), Value::Float(2.5), Value::Float(1.5)),
+                (
+                    "note".to_string(),
+                    Value::String("b".to_string()),
+                    Value::Null
+                ),
+            ]
+        );
+
+        // 列数与表定义不一致时返回错误
+        assert!(table.row_diff(&row, &row[..3].to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_of() -> Result<()> {
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("name", DataType::String, false),
+                column("score", DataType::Float, false),
+            ],
+        )?;
+        let row = vec![
+            Value::Integer(1),
+            Value::String("a".to_string()),
+            Value::Float(1.5),
+        ];
+
+        assert_eq!(
+            table.value_of(&row, "name")?,
+            &Value::String("a".to_string())
+        );
+        assert!(table.value_of(&row, "missing").is_err());
+
+        // 长度不足的行返回错误而不是 panic
+        let truncated = row[..1].to_vec();
+        assert_eq!(table.value_of(&truncated, "id")?, &Value::Integer(1));
+        assert!(table.value_of(&truncated, "score").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_primary_key() -> Result<()> {
+        let table = Table::new(
+            "users",
+            vec![
+                column("name", DataType::String, false),
+                column("note", DataType::String, false),
+                column("id", DataType::Integer, true),
+                column("profile", DataType::Json, false),
+            ],
+        )?;
+        let row = vec![
+            Value::String("alice".to_string()),
+            Value::Null,
+            Value::Integer(42),
+            Value::Json(serde_json::json!({"age": 30})),
+        ];
+        let bytes = bincode::serialize(&row)?;
+
+        let decoded: Row = bincode::deserialize(&bytes)?;
+        assert_eq!(
+            table.extract_primary_key(&bytes)?,
+            *table.get_primary_key(&decoded)
+        );
+
+        // 截断或列数不一致的编码返回错误
+        assert!(table.extract_primary_key(&bytes[..20]).is_err());
+        let short = bincode::serialize(&row[..3])?;
+        assert!(table.extract_primary_key(&short).is_err());
+
+        // 主键为 NULL 的编码返回错误，而不是得到一个 NULL 主键
+        let mut null_pk = row.clone();
+        null_pk[2] = Value::Null;
+        assert_eq!(
+            table.extract_primary_key(&bincode::serialize(&null_pk)?),
+            Err(InvalidColumnValue {
+                column: "id".to_string(),
+                index: 2,
+                expected: DataType::Integer,
+                found: None,
+            }
+            .into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_row() -> Result<()> {
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("name", DataType::String, false),
+                column("age", DataType::Integer, false),
+            ],
+        )?;
+        let row = vec![
+            Value::Integer(1),
+            Value::String("alice".to_string()),
+            Value::Null,
+        ];
+        assert_eq!(table.decode_row(&bincode::serialize(&row)?)?, row);
+
+        // 存储中的行和表定义不一致时，错误指出列名和位置
+        let corrupt = vec![
+            Value::Integer(1),
+            Value::String("alice".to_string()),
+            Value::String("30".to_string()),
+        ];
+        assert_eq!(
+            table.decode_row(&bincode::serialize(&corrupt)?),
+            Err(InvalidColumnValue {
+                column: "age".to_string(),
+                index: 2,
+                expected: DataType::Integer,
+                found: Some(DataType::String),
+            }
+            .into())
+        );
+        let corrupt = vec![Value::Null, Value::Null, Value::Null];
+        assert_eq!(
+            table.validate_row(&corrupt),
+            Err(InvalidColumnValue {
+                column: "id".to_string(),
+                index: 0,
+                expected: DataType::Integer,
+                found: None,
+            }
+            .into())
+        );
+
+        // 无法解码的字节返回解码错误
+        assert!(matches!(
+            table.decode_row(&[0xFF; 4]),
+            Err(crate::Error::Storage(Decode {
+                context: "decoding row",
+                ..
+            }))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_rows() -> Result<()> {
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("name", DataType::String, false),
+            ],
+        )?;
+        let row = |id: i64, name: Value| vec![Value::Integer(id), name];
+        let name = |name: &str| Value::String(name.to_string());
+
+        table.validate_rows(&[])?;
+        table.validate_rows(&[row(1, name("a")), row(2, Value::Null), row(3, name("c"))])?;
+
+        // 这批行之间的主键重复
+        assert_eq!(
+            table.validate_rows(&[row(1, name("a")), row(2, name("b")), row(1, name("c"))]),
+            Err(DuplicateKey {
+                table: "users".to_string(),
+                key: Value::Integer(1),
+            }
+            .into())
+        );
+
+        // 某一行的类型不符，错误指出列名和位置
+        assert_eq!(
+            table.validate_rows(&[row(1, name("a")), row(2, Value::Integer(2))]),
+            Err(InvalidColumnValue {
+                column: "name".to_string(),
+                index: 1,
+                expected: DataType::String,
+                found: Some(DataType::Integer),
+            }
+            .into())
+        );
+
+        // 自增列为 NULL 的行写入时才分配 ID，不参与主键的检查，其他列仍然检查
+        let table = Table::new(
+            "users",
+            vec![
+                Column {
+                    auto_increment: true,
+                    ..column("id", DataType::Integer, true)
+                },
+                column("name", DataType::String, false),
+            ],
+        )?;
+        table.validate_rows(&[
+            vec![Value::Null, name("a")],
+            vec![Value::Null, name("b")],
+            row(1, name("c")),
+        ])?;
+        assert_eq!(
+            table
+                .validate_rows(&[vec![Value::Null, Value::Integer(1)]])
+                .unwrap_err()
+                .code(),
+            crate::ErrorCode::DatatypeMismatch
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_columns() -> Result<()> {
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("name", DataType::String, false),
+                column("score", DataType::Float, false),
+                column("profile", DataType::Json, false),
+            ],
+        )?;
+        let row = vec![
+            Value::Integer(1),
+            Value::String("alice".to_string()),
+            Value::Float(1.5),
+            Value::Json(serde_json::json!({"age": 30})),
+        ];
+        let bytes = bincode::serialize(&row)?;
+
+        // 需要所有列时和完整解码相同，不需要的列为 NULL
+        assert_eq!(table.decode_columns(&bytes, &[0, 1, 2, 3])?, row);
+        assert_eq!(
+            table.decode_columns(&bytes, &[3, 0])?,
+            vec![Value::Integer(1), Value::Null, Value::Null, row[3].clone()]
+        );
+        assert_eq!(table.decode_columns(&bytes, &[])?, vec![Value::Null; 4]);
+
+        // 截断的编码返回错误，即使缺少的是不需要的列
+        assert!(table
+            .decode_columns(&bytes[..bytes.len() - 1], &[0])
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_float_normalization() {
+        let hash = |value: &Value| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            value.hash(&mut hasher);
+            std::hash::Hasher::finish(&hasher)
+        };
+        let nan = Value::Float(f64::NAN);
+        let other_nan = Value::Float(-f64::NAN);
+
+        // -0.0 和 0.0 相等，哈希值相同
+        let (zero, neg_zero) = (Value::Float(0.0), Value::Float(-0.0));
+        assert_eq!(zero, neg_zero);
+        assert_eq!(zero.total_cmp(&neg_zero), Ordering::Equal);
+        assert_eq!(hash(&zero), hash(&neg_zero));
+
+        // 所有 NaN 相等且大于其他数值，哈希值相同
+        assert_eq!(nan, other_nan);
+        assert_eq!(nan.total_cmp(&other_nan), Ordering::Equal);
+        assert_eq!(
+            nan.total_cmp(&Value::Float(f64::INFINITY)),
+            Ordering::Greater
+        );
+        assert_eq!(nan.total_cmp(&Value::Integer(i64::MAX)), Ordering::Greater);
+        assert_eq!(hash(&nan), hash(&other_nan));
+
+        // 不同类型的值不相等
+        assert_ne!(Value::Float(1.0), Value::Integer(1));
+        assert_ne!(nan, Value::Null);
+    }
+
+    #[test]
+    fn test_neg_and_abs() -> Result<()> {
+        assert_eq!(Value::Integer(5).neg()?, Value::Integer(-5));
+        assert_eq!(Value::Integer(-5).neg()?, Value::Integer(5));
+        assert_eq!(Value::Float(1.5).neg()?, Value::Float(-1.5));
+        assert_eq!(Value::Null.neg()?, Value::Null);
+        assert_eq!(Value::Float(-2.5).abs()?, Value::Float(2.5));
+        assert_eq!(Value::Integer(-3).abs()?, Value::Integer(3));
+        assert_eq!(Value::Null.abs()?, Value::Null);
+
+        // i64::MIN 的相反数和绝对值都超出了整数的范围
+        for result in [
+            Value::Integer(i64::MIN).neg(),
+            Value::Integer(i64::MIN).abs(),
+        ] {
+            assert!(matches!(result, Err(crate::Error::Execution(Overflow(_)))));
+        }
+        assert_eq!(
+            Value::Integer(i64::MIN + 1).neg()?,
+            Value::Integer(i64::MAX)
+        );
+
+        // 非数值的类型返回错误
+        assert!(Value::String("1".to_string()).neg().is_err());
+        assert!(Value::Boolean(true).abs().is_err());
+
+        Ok(())
+    }
 }
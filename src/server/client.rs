@@ -0,0 +1,517 @@
+use std::{
+    collections::HashSet,
+    io::{self, BufReader, BufWriter, ErrorKind},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use super::{read_frame, write_frame, Request, Response};
+use crate::{
+    error::ExecutionError::PreparedStatementNotFound, Error, ErrorCode, JsonOptions, Result,
+    ResultSet, RetryPolicy, Retryability, Value,
+};
+
+/// 下一个客户端的编号，用于区分预处理语句属于哪个客户端
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// [`Server`](super::Server) 的客户端，一个连接对应服务端的一个会话
+///
+/// 服务端执行失败时返回 [`Error::Remote`]，带有服务端给出的编码和分类；连接失败、请求过程中连接断开
+/// 以及无法解码的响应返回 [`Error::Connection`]。连接断开后，下一个请求会重新连接，
+/// 但是原来的会话已经结束：未提交的显式事务已经回滚，预处理的语句在下一次执行时自动重新准备。
+/// 服务端的连接数已满时，请求返回 [`ErrorCode::TooManyConnections`] 错误，之后的请求同样会重新连接。
+///
+/// ```no_run
+/// use sqldb::{server::Client, RetryPolicy};
+///
+/// let mut client = Client::connect("127.0.0.1:5432")?;
+/// client.execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")?;
+/// client.transaction(RetryPolicy::default(), |client| {
+///     client.execute("INSERT INTO t VALUES (1, 1);")?;
+///     client.execute("UPDATE t SET v = v + 1 WHERE id = 1;")
+/// })?;
+/// # Ok::<(), sqldb::Error>(())
+/// ```
+pub struct Client {
+    /// 客户端的编号，在进程中唯一
+    id: u64,
+    addr: SocketAddr,
+    /// 当前的连接，上一次请求的连接出错后为 `None`
+    connection: Option<Connection>,
+    /// 下一个预处理语句的序号，用于生成客户端中唯一的名称
+    next_statement_id: u64,
+    /// 已经在当前连接的会话中准备的语句名称，重新连接时清空
+    prepared: HashSet<String>,
+}
+
+/// 以名称保存在服务端会话中的预处理语句，由 [`Client::prepare`] 创建
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreparedStatement {
+    name: String,
+    sql: String,
+    /// 准备语句的客户端的编号
+    client: u64,
+}
+
+impl PreparedStatement {
+    /// 将 `params` 绑定到语句的占位符，通过 `client` 执行，`params[0]` 对应 `$1`
+    ///
+    /// `client` 需要是准备语句的客户端，否则返回
+    /// [`ErrorCode::UndefinedPreparedStatement`] 错误。准备之后重新连接过时，先在新的会话中重新准备语句。
+    pub fn execute(&self, client: &mut Client, params: &[Value]) -> Result<ResultSet> {
+        if self.client != client.id {
+            return Err(PreparedStatementNotFound(self.name.clone()).into());
+        }
+        // 没有连接时下一个请求会重新连接，新的会话中没有准备过的语句
+        if client.connection.is_none() || !client.prepared.contains(&self.name) {
+            client.prepare_named(&self.name, &self.sql)?;
+        }
+        client
+            .request(&Request::ExecutePrepared {
+                name: self.name.clone(),
+                params: params.to_vec(),
+            })?
+            .pop()
+            .ok_or_else(|| Error::Connection("empty response to ExecutePrepared".to_string()))
+    }
+}
+
+impl Client {
+    /// 连接到 `addr` 上的服务，`addr` 解析为多个地址时使用第一个
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let addr = addr
+            .to_socket_addrs()
+            .map_err(connection_error)?
+            .next()
+            .ok_or_else(|| Error::Connection("address resolved to nothing".to_string()))?;
+        Ok(Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            addr,
+            connection: Some(Connection::open(addr)?),
+            next_statement_id: 0,
+            prepared: HashSet::new(),
+        })
+    }
+
+    /// 执行 SQL，返回最后一条语句的结果，多条语句的执行方式见 [`Client::execute_script`]
+    pub fn execute(&mut self, sql: &str) -> Result<ResultSet> {
+        self.execute_script(sql)?
+            .pop()
+            .ok_or_else(|| Error::Parse("No statement to execute".to_string()))
+    }
+
+    /// 执行由多条以分号结尾的语句组成的脚本，返回每条语句的结果，和 [`Session::execute_script`] 相同
+    ///
+    /// [`Session::execute_script`]: crate::Session::execute_script
+    pub fn execute_script(&mut self, sql: &str) -> Result<Vec<ResultSet>> {
+        self.request(&Request::Execute(sql.to_string()))
+    }
+
+    /// 和 [`Client::execute_script`] 相同，但是服务端将结果转换为 JSON 后返回，见 [`ResultSet::to_json`]
+    ///
+    /// 返回的文本是一个数组，每条语句的结果为其中一个元素。
+    pub fn execute_json(&mut self, sql: &str, options: JsonOptions) -> Result<String> {
+        let request = Request::ExecuteJson {
+            sql: sql.to_string(),
+            options,
+        };
+        match self.send(&request)? {
+            Response::Json(json) => Ok(json),
+            _ => Err(Error::Connection("unexpected response".to_string())),
+        }
+    }
+
+    /// 在服务端的会话中解析并保存语句，语句中可以有参数占位符，执行时绑定参数
+    pub fn prepare(&mut self, sql: &str) -> Result<PreparedStatement> {
+        let name = format!("s{}", self.next_statement_id);
+        self.next_statement_id += 1;
+        self.prepare_named(&name, sql)?;
+        Ok(PreparedStatement {
+            name,
+            sql: sql.to_string(),
+            client: self.id,
+        })
+    }
+
+    /// 在当前连接的会话中以 `name` 保存语句
+    fn prepare_named(&mut self, name: &str, sql: &str) -> Result<()> {
+        self.request(&Request::Prepare {
+            name: name.to_string(),
+            sql: sql.to_string(),
+        })?;
+        self.prepared.insert(name.to_string());
+        Ok(())
+    }
+
+    /// 在显式事务中执行 `f` 并提交，失败时回滚，和 [`Session::with_retry`] 相同，
+    /// 不是 [`Retryability::Fatal`] 的错误按照 `policy` 重新执行整个事务
+    ///
+    /// 连接错误不会重试，因为无法确定事务是否已经提交。
+    ///
+    /// [`Session::with_retry`]: crate::Session::with_retry
+    pub fn transaction<T>(
+        &mut self,
+        policy: RetryPolicy,
+        mut f: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        policy.run(
+            |retryability| retryability != Retryability::Fatal,
+            || {
+                self.execute("BEGIN;")?;
+                let result = f(self).and_then(|value| {
+                    self.execute("COMMIT;")?;
+                    Ok(value)
+                });
+                if result.is_err() {
+                    self.rollback_after_error()?;
+                }
+                result
+            },
+        )
+    }
+
+    /// 事务出错后回滚：提交失败时事务已经结束，连接断开时服务端已经回滚了事务
+    fn rollback_after_error(&mut self) -> Result<()> {
+        if self.connection.is_none() {
+            return Ok(());
+        }
+        match self.execute("ROLLBACK;") {
+            Err(e) if e.code() == ErrorCode::NoActiveTransaction => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// 发送请求并等待执行结果，见 [`Client::send`]
+    fn request(&mut self, request: &Request) -> Result<Vec<ResultSet>> {
+        match self.send(request)? {
+            Response::Results(results) => Ok(results),
+            _ => Err(Error::Connection("unexpected response".to_string())),
+        }
+    }
+
+    /// 发送请求并等待响应，没有连接时先重新连接，连接出错后丢弃连接，服务端返回的错误转换为 [`Error::Remote`]
+    fn send(&mut self, request: &Request) -> Result<Response> {
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            // 新的连接对应新的会话，之前准备的语句都已经不存在
+            None => {
+                self.prepared.clear();
+                Connection::open(self.addr)?
+            }
+        };
+        match connection.round_trip(request)? {
+            // 连接数已满时服务端返回错误后关闭连接，下一个请求重新连接
+            Response::Error(err) if err.code == ErrorCode::TooManyConnections => Err(err.into()),
+            Response::Error(err) => {
+                self.connection = Some(connection);
+                Err(err.into())
+            }
+            response => {
+                self.connection = Some(connection);
+                Ok(response)
+            }
+        }
+    }
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    fn open(addr: SocketAddr) -> Result<Self> {
+        let stream = TcpStream::connect(addr).map_err(connection_error)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone().map_err(connection_error)?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    /// 写入请求并读取响应，读写和编解码的错误都是连接错误
+    fn round_trip(&mut self, request: &Request) -> Result<Response> {
+        write_frame(&mut self.writer, request).map_err(connection_error)?;
+        read_frame(&mut self.reader)
+            .map_err(connection_error)?
+            .ok_or_else(|| {
+                connection_error(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "server closed the connection",
+                ))
+            })
+    }
+}
+
+fn connection_error(err: impl ToString) -> Error {
+    Error::Connection(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, sync::Arc, thread, time::Duration};
+
+    use super::*;
+    use crate::{
+        server::{handle_request, RemoteError, Server},
+        storage::MemoryStorage,
+        Database, Engine, Value,
+    };
+
+    fn start_server() -> Result<SocketAddr> {
+        let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+        let server = Server::bind("127.0.0.1:0", db)?;
+        let addr = server.local_addr()?;
+        thread::spawn(move || server.serve());
+        Ok(addr)
+    }
+
+    #[test]
+    fn test_statements() -> Result<()> {
+        let mut client = Client::connect(start_server()?)?;
+        assert_eq!(
+            client.execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")?,
+            ResultSet::CreateTable {
+                name: "t".to_string()
+            }
+        );
+        assert_eq!(
+            client.execute("CREATE INDEX idx_v ON t (v);")?,
+            ResultSet::CreateIndex {
+                name: "idx_v".to_string()
+            }
+        );
+        assert_eq!(
+            client.execute("INSERT INTO t VALUES (1, 10), (2, 20), (3, 30);")?,
+            ResultSet::modified(3)
+        );
+        assert_eq!(
+            client.execute("UPDATE t SET v = 25 WHERE id = 2;")?,
+            ResultSet::modified(1)
+        );
+        assert_eq!(
+            client.execute("DELETE FROM t WHERE id = 3;")?,
+            ResultSet::modified(1)
+        );
+        assert_eq!(
+            client.execute("SELECT v FROM t ORDER BY id;")?.rows(),
+            &[vec![Value::Integer(10)], vec![Value::Integer(25)]]
+        );
+        assert_eq!(
+            client
+                .execute("SELECT id FROM t UNION SELECT v FROM t;")?
+                .rows()
+                .len(),
+            4
+        );
+        assert_eq!(
+            client.execute("ANALYZE t;")?,
+            ResultSet::Analyze {
+                tables: vec!["t".to_string()]
+            }
+        );
+        assert!(matches!(
+            client.execute("EXPLAIN SELECT * FROM t;")?,
+            ResultSet::Explain(_)
+        ));
+
+        assert_eq!(client.execute("BEGIN;")?, ResultSet::Begin);
+        client.execute("DELETE FROM t;")?;
+        assert_eq!(client.execute("ROLLBACK;")?, ResultSet::Rollback);
+        assert_eq!(client.execute("BEGIN;")?, ResultSet::Begin);
+        assert_eq!(client.execute("COMMIT;")?, ResultSet::Commit);
+
+        // 预处理的语句可以多次执行，能看到之后的写入
+        let count = client.prepare("SELECT COUNT(*) FROM t;")?;
+        assert_eq!(
+            count.execute(&mut client, &[])?.rows()[0][0],
+            Value::Integer(2)
+        );
+        client.execute("INSERT INTO t VALUES (4, 40);")?;
+        assert_eq!(
+            count.execute(&mut client, &[])?.rows()[0][0],
+            Value::Integer(3)
+        );
+
+        // 服务端的错误保留编码和分类
+        let err = client.execute("INSERT INTO t VALUES (4, 40);").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UniqueViolation);
+        assert_eq!(err.retryability(), Retryability::RetryableAfterReread);
+        assert_eq!(
+            client.prepare("SELEC 1;").unwrap_err().code(),
+            ErrorCode::SyntaxError
+        );
+        let mut other = Client::connect(client.addr)?;
+        assert_eq!(
+            count.execute(&mut other, &[]).unwrap_err().code(),
+            ErrorCode::UndefinedPreparedStatement
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepared_statement() -> Result<()> {
+        let mut client = Client::connect(start_server()?)?;
+        client.execute("CREATE TABLE t (id INT PRIMARY KEY, v STRING NULL);")?;
+
+        // 参数随请求发送，绑定到语句的占位符
+        let insert = client.prepare("INSERT INTO t VALUES ($1, $2);")?;
+        let select = client.prepare("SELECT v FROM t WHERE id = ?;")?;
+        for (id, v) in [(1, Value::String("a".to_string())), (2, Value::Null)] {
+            insert.execute(&mut client, &[Value::Integer(id), v])?;
+        }
+        assert_eq!(
+            select.execute(&mut client, &[Value::Integer(1)])?.rows(),
+            &[vec![Value::String("a".to_string())]]
+        );
+        assert_eq!(
+            select.execute(&mut client, &[Value::Integer(2)])?.rows(),
+            &[vec![Value::Null]]
+        );
+        let err = select.execute(&mut client, &[]).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ValueCountMismatch);
+
+        // 连接断开后服务端的会话已经结束，执行时在新的会话中重新准备语句
+        client.connection = None;
+        insert.execute(&mut client, &[Value::Integer(3), Value::Null])?;
+        assert_eq!(
+            select.execute(&mut client, &[Value::Integer(3)])?.rows(),
+            &[vec![Value::Null]]
+        );
+        assert_eq!(client.prepared.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_json() -> Result<()> {
+        let mut client = Client::connect(start_server()?)?;
+        let json = client.execute_json(
+            "CREATE TABLE t (id INT PRIMARY KEY AUTO_INCREMENT, v FLOAT NULL);
+             INSERT INTO t VALUES (NULL, 1.5), (NULL, NULL);
+             SELECT * FROM t ORDER BY id;",
+            JsonOptions::default(),
+        )?;
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json)?,
+            serde_json::json!([
+                {"CreateTable": {"name": "t"}},
+                {"Modified": {"count": 2, "last_insert_ids": [1, 2]}},
+                [{"id": 1, "v": 1.5}, {"id": 2, "v": null}]
+            ])
+        );
+
+        // 错误和 Execute 请求相同，连接仍然可以使用
+        let err = client
+            .execute_json("SELECT * FROM missing;", JsonOptions::default())
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UndefinedTable);
+        assert_eq!(client.execute("SELECT * FROM t;")?.rows().len(), 2);
+
+        // Execute 请求的结果同样带有分配的 ID
+        assert_eq!(
+            client
+                .execute("INSERT INTO t (v) VALUES (2.5);")?
+                .last_insert_ids(),
+            &[3]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction() -> Result<()> {
+        let addr = start_server()?;
+        let mut client_1 = Client::connect(addr)?;
+        let mut client_2 = Client::connect(addr)?;
+        client_1.execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")?;
+        client_1.execute("INSERT INTO t VALUES (1, 1);")?;
+
+        // 第一次执行时其他连接修改了同一行，写冲突之后整个事务重新执行
+        let policy = RetryPolicy::new(3).backoff(Duration::ZERO);
+        let mut attempts = 0;
+        let v = client_1.transaction(policy, |client| {
+            attempts += 1;
+            if attempts == 1 {
+                client.execute("SELECT v FROM t WHERE id = 1;")?;
+                client_2.execute("UPDATE t SET v = v + 10 WHERE id = 1;")?;
+            }
+            client.execute("UPDATE t SET v = v + 1 WHERE id = 1;")?;
+            client.execute("SELECT v FROM t WHERE id = 1;")
+        })?;
+        assert_eq!(attempts, 2);
+        assert_eq!(v.rows()[0][0], Value::Integer(12));
+
+        // 不能重试的错误直接返回，事务已经回滚
+        let mut attempts = 0;
+        let err = client_1
+            .transaction(policy, |client| {
+                attempts += 1;
+                client.execute("UPDATE t SET v = 0 WHERE id = 1;")?;
+                client.execute("SELECT * FROM missing;")
+            })
+            .unwrap_err();
+        assert_eq!((attempts, err.code()), (1, ErrorCode::UndefinedTable));
+        assert_eq!(
+            client_1.execute("SELECT v FROM t WHERE id = 1;")?.rows()[0][0],
+            Value::Integer(12)
+        );
+
+        // 已经在显式事务中时不会回滚外层的事务
+        client_1.execute("BEGIN;")?;
+        let err = client_1.transaction(policy, |_| Ok(())).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ActiveTransaction);
+        assert_eq!(client_1.execute("COMMIT;")?, ResultSet::Commit);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconnect() -> Result<()> {
+        let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+
+        // 只处理一个请求就退出的服务端，模拟服务端进程退出
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server = thread::spawn({
+            let db = Arc::clone(&db);
+            move || -> Result<()> {
+                let (stream, _) = listener.accept()?;
+                let mut reader = BufReader::new(stream.try_clone()?);
+                let mut writer = BufWriter::new(stream);
+                let mut session = db.session();
+                let request = read_frame(&mut reader)?.expect("one request");
+                let response = match handle_request(&mut session, request) {
+                    Ok(results) => Response::Results(results),
+                    Err(e) => Response::Error(RemoteError::from(&e)),
+                };
+                write_frame(&mut writer, &response)
+            }
+        });
+
+        let mut client = Client::connect(addr)?;
+        client.execute("CREATE TABLE t (id INT PRIMARY KEY);")?;
+        server.join().expect("server thread panicked")?;
+
+        // 请求过程中连接断开，服务端停止时重新连接也会失败
+        let err = client.execute("INSERT INTO t VALUES (1);").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ConnectionFailure);
+        assert_eq!(err.code().sqlstate(), Some("08006"));
+        assert_eq!(err.retryability(), Retryability::Fatal);
+        assert_eq!(
+            client.execute("SELECT * FROM t;").unwrap_err().code(),
+            ErrorCode::ConnectionFailure
+        );
+
+        // 服务端在同一个地址重新启动后，下一个请求自动重新连接
+        let server = Server::bind(addr, db)?;
+        thread::spawn(move || server.serve());
+        client.execute("INSERT INTO t VALUES (1);")?;
+        assert_eq!(
+            client.execute("SELECT * FROM t;")?.rows(),
+            &[vec![Value::Integer(1)]]
+        );
+
+        Ok(())
+    }
+}
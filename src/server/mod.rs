@@ -0,0 +1,611 @@
+//! 通过 TCP 对外提供 SQL 服务
+//!
+//! 每条消息是一帧：4 字节大端编码的长度，加上 bincode 编码的 [`Request`] 或者 [`Response`]。
+//! 客户端发送一个请求后等待一个响应，同一个连接上的请求按顺序执行。每个连接对应一个 [`Session`]，
+//! 因此 `BEGIN` 开启的显式事务和预处理的语句只在所在的连接中有效，连接断开时回滚尚未结束的事务。
+//! `ExecuteJson` 请求的结果以 JSON 文本返回，见 [`Client::execute_json`]。
+//!
+//! 服务端可以通过 [`Server::max_connections`] 限制同时存在的连接数，通过 [`Server::idle_timeout`]
+//! 关闭长时间没有请求的连接。`SHOW SESSIONS;` 由服务端直接处理，列出所有连接的 ID、对端地址、状态
+//! （`idle`、`active` 或 `idle in transaction`）、显式事务的版本和最近一次请求的语句。
+//!
+//! 同一个监听地址也可以通过 [`Server::serve_postgres`] 使用 PostgreSQL 的协议，见 [`postgres`]。
+//! 本 crate 的客户端见 [`Client`]。
+//!
+//! [`Session`]: crate::Session
+
+mod client;
+pub mod postgres;
+mod registry;
+
+pub use client::{Client, PreparedStatement};
+
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use registry::{Registration, Registry};
+
+use crate::{
+    storage::Storage, Database, Error, ErrorCode, JsonOptions, Result, ResultSet, Retryability,
+    ScriptPosition, Session, Value,
+};
+
+/// 一帧的最大长度，超过时认为对端不遵守协议，避免按照错误的长度分配过大的内存
+const MAX_FRAME_LEN: usize = 64 << 20;
+/// 拒绝连接时等待客户端第一个请求的最长时间，之后直接关闭连接
+const REJECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 客户端发送的请求
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    /// 执行由一条或多条以分号结尾的语句组成的 SQL，和 [`Session::execute_script`] 相同
+    Execute(String),
+    /// 解析 SQL 语句并以 `name` 保存在连接的会话中
+    Prepare { name: String, sql: String },
+    /// 将 `params` 绑定到以 `name` 保存的预处理语句的占位符并执行，见 [`Session::execute_prepared`]
+    ExecutePrepared { name: String, params: Vec<Value> },
+    /// 和 `Execute` 相同，但是结果以 [`Response::Json`] 返回
+    ExecuteJson { sql: String, options: JsonOptions },
+}
+
+/// 请求对应的语句，`SHOW SESSIONS` 中作为连接最近一次请求的语句
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::Execute(sql) | Request::ExecuteJson { sql, .. } => write!(f, "{}", sql),
+            Request::Prepare { name, sql } => write!(f, "PREPARE {} AS {}", name, sql),
+            Request::ExecutePrepared { name, params } if params.is_empty() => {
+                write!(f, "EXECUTE {}", name)
+            }
+            Request::ExecutePrepared { name, params } => {
+                let params = params.iter().map(Value::to_string).collect::<Vec<_>>();
+                write!(f, "EXECUTE {} ({})", name, params.join(", "))
+            }
+        }
+    }
+}
+
+/// 服务端对请求的响应
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    /// 每条语句的执行结果，`Prepare` 请求的结果为空
+    Results(Vec<ResultSet>),
+    /// 请求执行失败
+    Error(RemoteError),
+    /// `ExecuteJson` 请求的结果，为每条语句的结果组成的 JSON 数组，见 [`ResultSet::to_json`]
+    Json(String),
+}
+
+/// 服务端返回的错误，包含 [`Error`] 中客户端可以识别的部分
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteError {
+    pub code: ErrorCode,
+    pub sqlstate: Option<String>,
+    pub retryability: Retryability,
+    pub message: String,
+    /// `Execute` 请求中出错的语句的位置，其他请求为 `None`
+    pub position: Option<ScriptPosition>,
+}
+
+impl From<&Error> for RemoteError {
+    fn from(err: &Error) -> Self {
+        let code = err.code();
+        let position = match err {
+            Error::Script { position, .. } => Some(position.clone()),
+            _ => None,
+        };
+        Self {
+            code,
+            sqlstate: code.sqlstate().map(str::to_string),
+            retryability: err.retryability(),
+            message: err.to_string(),
+            position,
+        }
+    }
+}
+
+/// 客户端收到的错误转换为 [`Error::Remote`]，保留服务端给出的编码、分类和位置
+impl From<RemoteError> for Error {
+    fn from(err: RemoteError) -> Self {
+        Error::Remote {
+            code: err.code,
+            retryability: err.retryability,
+            message: err.message,
+            position: err.position,
+        }
+    }
+}
+
+/// SQL 服务，通过 [`Server::bind`] 监听地址，通过 [`Server::serve`] 处理连接
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use sqldb::{server::Server, storage::MemoryStorage, Database, Engine};
+///
+/// let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+/// Server::bind("127.0.0.1:5432", db)?.serve()?;
+/// # Ok::<(), sqldb::Error>(())
+/// ```
+pub struct Server<S: Storage> {
+    listener: TcpListener,
+    database: Arc<Database<S>>,
+    registry: Arc<Registry>,
+    /// 同时存在的最大连接数，`None` 表示不限制
+    max_connections: Option<usize>,
+    /// 连接等待请求的最长时间，`None` 表示不限制
+    idle_timeout: Option<Duration>,
+}
+
+/// 处理连接的方式，不同的协议各自实现
+struct Handler<S: Storage> {
+    /// 在连接的会话中处理请求
+    serve: fn(&Database<S>, &Connection, TcpStream) -> Result<()>,
+    /// 连接数已满时拒绝连接，返回 [`ErrorCode::TooManyConnections`] 错误
+    reject: fn(TcpStream) -> Result<()>,
+}
+
+/// 服务端为连接登记的信息和设置
+struct Connection {
+    registration: Registration,
+    idle_timeout: Option<Duration>,
+}
+
+impl<S: Storage + Send + 'static> Server<S> {
+    /// 监听 `addr`，端口为 0 时由系统分配，可以通过 [`Server::local_addr`] 查看
+    pub fn bind(addr: impl ToSocketAddrs, database: Arc<Database<S>>) -> Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            database,
+            registry: Arc::default(),
+            max_connections: None,
+            idle_timeout: None,
+        })
+    }
+
+    /// 限制同时存在的连接数，超出的连接在第一个请求时收到 [`ErrorCode::TooManyConnections`] 错误后被关闭
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// 连接超过 `timeout` 没有发送请求时关闭连接，回滚尚未结束的显式事务
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// 实际监听的地址
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// 接受连接并为每个连接创建一个线程处理请求，不会返回，除非监听失败
+    ///
+    /// 单个连接的错误只会关闭这个连接，不影响其他连接和之后的连接。
+    pub fn serve(self) -> Result<()> {
+        self.serve_with(Handler {
+            serve: handle_connection,
+            reject: reject_connection,
+        })
+    }
+
+    /// 接受连接并为每个连接创建一个线程，在线程中使用 `handler` 处理连接
+    fn serve_with(self, handler: Handler<S>) -> Result<()> {
+        for stream in self.listener.incoming() {
+            let result = stream.map_err(Error::from).and_then(|stream| {
+                let peer = stream.peer_addr()?;
+                let registration = self.registry.register(peer, self.max_connections)?;
+                Ok((stream, registration))
+            });
+            let (stream, registration) = match result {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    eprintln!("Failed to accept connection: {:?}", e);
+                    continue;
+                }
+            };
+            let Some(registration) = registration else {
+                thread::spawn(move || {
+                    if let Err(e) = (handler.reject)(stream) {
+                        eprintln!("Failed to reject connection: {:?}", e);
+                    }
+                });
+                continue;
+            };
+
+            let database = Arc::clone(&self.database);
+            let connection = Connection {
+                registration,
+                idle_timeout: self.idle_timeout,
+            };
+            thread::spawn(move || {
+                if let Err(e) = (handler.serve)(&database, &connection, stream) {
+                    eprintln!("Connection closed with error: {:?}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Connection {
+    /// 等待客户端发送下一个请求，超过空闲时间时返回 `false`
+    fn wait_for_request(&self, reader: &mut BufReader<TcpStream>) -> Result<bool> {
+        reader.get_ref().set_read_timeout(self.idle_timeout)?;
+        match reader.fill_buf() {
+            Ok(_) => Ok(true),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// 连接数已满时服务端返回的错误
+fn too_many_connections() -> RemoteError {
+    let code = ErrorCode::TooManyConnections;
+    RemoteError {
+        code,
+        sqlstate: code.sqlstate().map(str::to_string),
+        retryability: Retryability::Retryable,
+        message: "Too many connections".to_string(),
+        position: None,
+    }
+}
+
+/// 在新的会话中依次处理一个连接上的请求，直到客户端关闭连接或者超过空闲时间
+fn handle_connection<S: Storage>(
+    database: &Database<S>,
+    connection: &Connection,
+    stream: TcpStream,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let mut session = database.session();
+    while connection.wait_for_request(&mut reader)? {
+        let Some(request) = read_frame::<Request>(&mut reader)? else {
+            break;
+        };
+        let registration = &connection.registration;
+        registration.begin(&request.to_string())?;
+        let result = match &request {
+            Request::Execute(sql) | Request::ExecuteJson { sql, .. } => registration
+                .show_sessions(sql)?
+                .map(|result| Ok(vec![result])),
+            _ => None,
+        };
+        let json = match &request {
+            Request::ExecuteJson { options, .. } => Some(*options),
+            _ => None,
+        };
+        let result = result.unwrap_or_else(|| handle_request(&mut session, request));
+        registration.finish(&session)?;
+
+        let response = result
+            .and_then(|results| match json {
+                Some(options) => Ok(Response::Json(serde_json::to_string(
+                    &results
+                        .iter()
+                        .map(|result| result.to_json(options))
+                        .collect::<Vec<_>>(),
+                )?)),
+                None => Ok(Response::Results(results)),
+            })
+            .unwrap_or_else(|e| Response::Error(RemoteError::from(&e)));
+        write_frame(&mut writer, &response)?;
+    }
+    Ok(())
+}
+
+/// 读取客户端的第一个请求，返回连接数已满的错误后关闭连接
+///
+/// 先读取请求再返回错误，避免关闭连接时接收缓冲区中还有未读取的请求，导致客户端收到重置而不是错误。
+fn reject_connection(stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(REJECT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    if read_frame::<Request>(&mut reader)?.is_some() {
+        write_frame(
+            &mut BufWriter::new(stream),
+            &Response::Error(too_many_connections()),
+        )?;
+    }
+    Ok(())
+}
+
+fn handle_request<S: Storage>(
+    session: &mut Session<'_, S>,
+    request: Request,
+) -> Result<Vec<ResultSet>> {
+    match request {
+        Request::Execute(sql) | Request::ExecuteJson { sql, .. } => session.execute_script(&sql),
+        Request::Prepare { name, sql } => session.prepare(&name, &sql).map(|_| Vec::new()),
+        Request::ExecutePrepared { name, params } => {
+            session.execute_prepared(&name, &params).map(|r| vec![r])
+        }
+    }
+}
+
+/// 读取一帧并解码，对端在两帧之间关闭连接时返回 `None`
+fn read_frame<T: DeserializeOwned>(reader: &mut impl Read) -> Result<Option<T>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "frame of {} bytes exceeds the limit of {}",
+                len, MAX_FRAME_LEN
+            ),
+        )
+        .into());
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some(bincode::deserialize(&payload)?))
+}
+
+/// 编码并写入一帧，写入后立即发送
+fn write_frame<T: Serialize>(writer: &mut impl Write, message: &T) -> Result<()> {
+    let payload = bincode::serialize(message)?;
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|len| *len as usize <= MAX_FRAME_LEN)
+        .ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("message of {} bytes exceeds the frame limit", payload.len()),
+            )
+        })?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::MemoryStorage, Engine};
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+        let server = Server::bind("127.0.0.1:0", db)?;
+        let addr = server.local_addr()?;
+        thread::spawn(move || server.serve());
+
+        let mut client_1 = Client::connect(addr)?;
+        assert_eq!(
+            client_1.execute_script(
+                "CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR);
+                 INSERT INTO users VALUES (1, 'Alice'), (2, 'Bob');"
+            )?,
+            vec![
+                ResultSet::CreateTable {
+                    name: "users".to_string()
+                },
+                ResultSet::modified(2),
+            ]
+        );
+        let result = client_1.execute("SELECT name FROM users;")?;
+        assert_eq!(result.rows().len(), 2);
+        assert_eq!(result.get::<String>(1, "name")?, "Bob");
+
+        // 显式事务属于连接，其他连接看不到未提交的写入
+        let mut client_2 = Client::connect(addr)?;
+        client_1.execute_script("BEGIN; INSERT INTO users VALUES (3, 'Carol');")?;
+        let count = |client: &mut Client| -> Result<Value> {
+            Ok(client.execute("SELECT COUNT(*) FROM users;")?.rows()[0][0].clone())
+        };
+        assert_eq!(count(&mut client_2)?, Value::Integer(2));
+        assert_eq!(count(&mut client_1)?, Value::Integer(3));
+        client_1.execute("COMMIT;")?;
+        assert_eq!(count(&mut client_2)?, Value::Integer(3));
+
+        // 错误包含编码和出错的位置
+        let err = client_2
+            .execute("SELECT name FROM users;\nSELECT * FROM missing;")
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UndefinedTable);
+        assert_eq!(err.code().sqlstate(), Some("42P01"));
+        assert_eq!(err.retryability(), Retryability::Fatal);
+        let Error::Remote { position, .. } = err else {
+            panic!("expected a remote error");
+        };
+        let position = position.expect("script errors have a position");
+        assert_eq!((position.statement_index, position.line), (1, 2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_prepared() -> Result<()> {
+        let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+        let server = Server::bind("127.0.0.1:0", db)?;
+        let addr = server.local_addr()?;
+        thread::spawn(move || server.serve());
+
+        // 直接按照协议收发帧，检查参数经过编码后绑定到语句
+        let stream = TcpStream::connect(addr)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        let mut request = |request: Request| -> Result<Response> {
+            write_frame(&mut writer, &request)?;
+            Ok(read_frame(&mut reader)?.expect("server closed the connection"))
+        };
+        let prepare = |name: &str, sql: &str| Request::Prepare {
+            name: name.to_string(),
+            sql: sql.to_string(),
+        };
+        let execute = |name: &str, params: Vec<Value>| Request::ExecutePrepared {
+            name: name.to_string(),
+            params,
+        };
+
+        request(Request::Execute(
+            "CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR);".to_string(),
+        ))?;
+        assert_eq!(
+            request(prepare("insert", "INSERT INTO users VALUES ($1, $2);"))?,
+            Response::Results(Vec::new())
+        );
+        assert_eq!(
+            request(prepare("select", "SELECT name FROM users WHERE id = ?;"))?,
+            Response::Results(Vec::new())
+        );
+        for (id, name) in [(1, "Alice"), (2, "Bob")] {
+            let params = vec![Value::Integer(id), Value::String(name.to_string())];
+            assert_eq!(
+                request(execute("insert", params))?,
+                Response::Results(vec![ResultSet::modified(1)])
+            );
+        }
+        for (id, name) in [(2, "Bob"), (1, "Alice")] {
+            let Response::Results(results) = request(execute("select", vec![Value::Integer(id)]))?
+            else {
+                panic!("expected results");
+            };
+            assert_eq!(results[0].get::<String>(0, "name")?, name);
+        }
+
+        // 参数的个数和占位符不符时返回错误，连接仍然可用
+        let Response::Error(err) = request(execute("select", Vec::new()))? else {
+            panic!("expected an error");
+        };
+        assert_eq!(err.code, ErrorCode::ValueCountMismatch);
+        assert!(matches!(
+            request(execute("select", vec![Value::Integer(3)]))?,
+            Response::Results(results) if results[0].rows().is_empty()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_connections() -> Result<()> {
+        let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+        let server = Server::bind("127.0.0.1:0", db)?.max_connections(2);
+        let addr = server.local_addr()?;
+        thread::spawn(move || server.serve());
+
+        let mut client_1 = Client::connect(addr)?;
+        client_1.execute("CREATE TABLE t (id INT PRIMARY KEY);")?;
+        let mut client_2 = Client::connect(addr)?;
+        client_2.execute("SELECT * FROM t;")?;
+
+        // 超出的连接收到错误，已有的连接不受影响
+        let mut client_3 = Client::connect(addr)?;
+        let err = client_3.execute("SELECT * FROM t;").unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TooManyConnections);
+        assert_eq!(err.code().sqlstate(), Some("53300"));
+        assert_eq!(err.retryability(), Retryability::Retryable);
+        let sessions = client_1.execute("SHOW SESSIONS;")?;
+        assert_eq!(sessions.rows().len(), 2);
+
+        // 其他连接关闭后可以重新连接
+        drop(client_2);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while let Err(e) = client_3.execute("SELECT * FROM t;") {
+            assert_eq!(e.code(), ErrorCode::TooManyConnections);
+            assert!(
+                std::time::Instant::now() < deadline,
+                "connection slot not released"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_idle_timeout() -> Result<()> {
+        let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+        let server = Server::bind("127.0.0.1:0", db)?.idle_timeout(Duration::from_millis(300));
+        let addr = server.local_addr()?;
+        thread::spawn(move || server.serve());
+
+        let mut client_1 = Client::connect(addr)?;
+        client_1.execute_script("CREATE TABLE t (id INT PRIMARY KEY); BEGIN;")?;
+        client_1.execute("INSERT INTO t VALUES (1);")?;
+
+        // 列出连接的状态、显式事务的版本和最近一次请求的语句
+        let mut client_2 = Client::connect(addr)?;
+        let sessions = client_2.execute("SHOW SESSIONS;")?;
+        assert_eq!(
+            sessions.get::<String>(0, "state")?,
+            "idle in transaction".to_string()
+        );
+        assert!(sessions
+            .get::<Option<i64>>(0, "transaction_version")?
+            .is_some());
+        assert_eq!(
+            sessions.get::<String>(0, "last_statement")?,
+            "INSERT INTO t VALUES (1);".to_string()
+        );
+        assert_eq!(sessions.get::<String>(1, "state")?, "active".to_string());
+        assert_eq!(sessions.get::<Option<i64>>(1, "transaction_version")?, None);
+
+        // 超过空闲时间后连接被关闭，未提交的事务回滚
+        thread::sleep(Duration::from_millis(700));
+        let mut client_3 = Client::connect(addr)?;
+        assert_eq!(
+            client_3.execute("SELECT COUNT(*) FROM t;")?.rows()[0][0],
+            Value::Integer(0)
+        );
+        assert_eq!(client_3.execute("SHOW SESSIONS;")?.rows().len(), 1);
+        assert_eq!(
+            client_1.execute("COMMIT;").unwrap_err().code(),
+            ErrorCode::ConnectionFailure
+        );
+        assert_eq!(
+            client_1.execute("COMMIT;").unwrap_err().code(),
+            ErrorCode::NoActiveTransaction
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_frame() -> Result<()> {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, &Request::Execute("SELECT 1;".to_string()))?;
+        assert_eq!(&buf[..4], &(buf.len() as u32 - 4).to_be_bytes());
+        assert_eq!(
+            read_frame::<Request>(&mut buf.as_slice())?,
+            Some(Request::Execute("SELECT 1;".to_string()))
+        );
+        assert_eq!(read_frame::<Request>(&mut [].as_slice())?, None);
+
+        // 参数值和结果中的值一样编码，包括 JSON 值
+        let request = Request::ExecutePrepared {
+            name: "s0".to_string(),
+            params: vec![
+                Value::Integer(1),
+                Value::Json(serde_json::json!({"a": [1]})),
+            ],
+        };
+        assert_eq!(request.to_string(), r#"EXECUTE s0 (1, {"a":[1]})"#);
+        let mut buf = Vec::new();
+        write_frame(&mut buf, &request)?;
+        assert_eq!(read_frame::<Request>(&mut buf.as_slice())?, Some(request));
+
+        // 长度超过限制或者内容不完整都是错误
+        let oversized = (MAX_FRAME_LEN as u32 + 1).to_be_bytes();
+        assert!(read_frame::<Request>(&mut oversized.as_slice()).is_err());
+        assert!(read_frame::<Request>(&mut &buf[..buf.len() - 1]).is_err());
+
+        Ok(())
+    }
+}
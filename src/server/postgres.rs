@@ -0,0 +1,633 @@
+//! PostgreSQL 前后端协议（v3）的简单查询部分，使得 `psql` 等客户端可以直接连接
+//!
+//! 支持的部分：
+//!
+//! - 启动：不使用 TLS（拒绝 `SSLRequest`），不需要认证，忽略连接参数中的用户和数据库；
+//! - 简单查询：`Query` 中的每条语句依次返回 `RowDescription`、`DataRow` 和 `CommandComplete`，
+//!   出错时返回带有 SQLSTATE 的 `ErrorResponse` 并跳过之后的语句，最后返回 `ReadyForQuery`；
+//!   `SHOW SESSIONS` 单独作为一个 `Query` 时由服务端处理，见 [`super`]；
+//! - 连接数已满时在启动消息之后返回 SQLSTATE 为 53300 的 `FATAL` 错误，超过空闲时间时返回 57P05 的
+//!   `FATAL` 错误，然后关闭连接；
+//! - 所有的值都使用文本格式，类型按照 [`DataType`] 对应到 `bool`、`int8`、`float8`、`text` 和 `json`。
+//!
+//! 扩展查询协议和 `COPY` 不支持：扩展查询的消息返回错误，直到 `Sync` 之后恢复。
+//! 和 PostgreSQL 不同，一个 `Query` 中的多条语句不会放在同一个隐式事务中，每条语句各自自动提交。
+
+use std::{
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+use super::{Connection, Handler, Server, MAX_FRAME_LEN, REJECT_TIMEOUT};
+use crate::{
+    parser::{ast::Statement, Parser},
+    storage::Storage,
+    DataType, Database, Error, Result, ResultSet, ScriptPosition, Session, Value,
+};
+
+/// 协议版本 3.0
+const PROTOCOL_VERSION: i32 = 196608;
+/// 请求使用 TLS 的启动消息
+const SSL_REQUEST: i32 = 80877103;
+/// 请求使用 GSSAPI 加密的启动消息
+const GSSENC_REQUEST: i32 = 80877104;
+/// 取消请求，不支持
+const CANCEL_REQUEST: i32 = 80877102;
+
+/// 不支持的功能，例如扩展查询协议
+const FEATURE_NOT_SUPPORTED: &str = "0A000";
+/// 违反协议，例如未知的消息类型
+const PROTOCOL_VIOLATION: &str = "08P01";
+/// 没有对应的 SQLSTATE 的错误
+const INTERNAL_ERROR: &str = "XX000";
+/// 超过空闲时间后关闭连接
+const IDLE_SESSION_TIMEOUT: &str = "57P05";
+
+impl<S: Storage + Send + 'static> Server<S> {
+    /// 和 [`Server::serve`] 相同，但是使用 PostgreSQL 的协议处理连接
+    ///
+    /// ```no_run
+    /// use std::sync::Arc;
+    /// use sqldb::{server::Server, storage::MemoryStorage, Database, Engine};
+    ///
+    /// let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+    /// // 之后可以通过 `psql -h localhost -p 5433` 连接
+    /// Server::bind("127.0.0.1:5433", db)?.serve_postgres()?;
+    /// # Ok::<(), sqldb::Error>(())
+    /// ```
+    pub fn serve_postgres(self) -> Result<()> {
+        self.serve_with(Handler {
+            serve: handle_connection,
+            reject: reject_connection,
+        })
+    }
+}
+
+/// 完成启动过程后在新的会话中依次处理消息，直到客户端发送 `Terminate`、关闭连接或者超过空闲时间
+fn handle_connection<S: Storage>(
+    database: &Database<S>,
+    connection: &Connection,
+    stream: TcpStream,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    if !startup(&mut reader, &mut writer)? {
+        return Ok(());
+    }
+    authentication_ok(&mut writer)?;
+
+    let mut session = database.session();
+    ready_for_query(&mut writer, &session)?;
+    // 扩展查询的消息出错后，忽略之后的消息直到 `Sync`
+    let mut skip_until_sync = false;
+    loop {
+        if !connection.wait_for_request(&mut reader)? {
+            let message = "terminating connection due to idle-session timeout";
+            let body = error_body("FATAL", IDLE_SESSION_TIMEOUT, message, None);
+            send(&mut writer, b'E', &body)?;
+            writer.flush()?;
+            break;
+        }
+        let Some((tag, body)) = read_message(&mut reader)? else {
+            break;
+        };
+        match tag {
+            b'Q' => {
+                let sql = read_cstr(&body)?;
+                let registration = &connection.registration;
+                registration.begin(sql)?;
+                match registration.show_sessions(sql)? {
+                    Some(result) => {
+                        let columns = result
+                            .columns()
+                            .iter()
+                            .map(|column| (column.name.clone(), column.data_type))
+                            .collect::<Vec<_>>();
+                        send_rows(&mut writer, &columns, result.rows())?;
+                        send_command_complete(&mut writer, "SHOW")?;
+                    }
+                    None => simple_query(&mut writer, &mut session, sql)?,
+                }
+                registration.finish(&session)?;
+                ready_for_query(&mut writer, &session)?;
+            }
+            b'X' => break,
+            b'S' => {
+                skip_until_sync = false;
+                ready_for_query(&mut writer, &session)?;
+            }
+            b'P' | b'B' | b'D' | b'E' | b'C' | b'H' => {
+                if !skip_until_sync {
+                    skip_until_sync = true;
+                    error_response(
+                        &mut writer,
+                        FEATURE_NOT_SUPPORTED,
+                        "extended query protocol is not supported",
+                        None,
+                    )?;
+                    writer.flush()?;
+                }
+            }
+            _ => {
+                error_response(
+                    &mut writer,
+                    PROTOCOL_VIOLATION,
+                    &format!("unsupported message type '{}'", tag as char),
+                    None,
+                )?;
+                writer.flush()?;
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unsupported message type {:#04x}", tag),
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 读取启动消息后返回连接数已满的 `FATAL` 错误，然后关闭连接
+fn reject_connection(stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(REJECT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    if startup(&mut reader, &mut writer)? {
+        let code = crate::ErrorCode::TooManyConnections;
+        let sqlstate = code.sqlstate().unwrap_or(INTERNAL_ERROR);
+        let body = error_body("FATAL", sqlstate, "sorry, too many clients already", None);
+        send(&mut writer, b'E', &body)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// 读取启动消息，返回是否继续处理连接，取消请求不需要继续处理
+fn startup(reader: &mut impl Read, writer: &mut impl Write) -> Result<bool> {
+    loop {
+        let body = read_body(reader)?;
+        let code = body
+            .get(..4)
+            .map(|code| i32::from_be_bytes([code[0], code[1], code[2], code[3]]))
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "startup message too short"))?;
+        match code {
+            PROTOCOL_VERSION => break,
+            SSL_REQUEST | GSSENC_REQUEST => {
+                // 不支持加密，客户端可以继续以明文发送启动消息
+                writer.write_all(b"N")?;
+                writer.flush()?;
+            }
+            CANCEL_REQUEST => return Ok(false),
+            _ => {
+                let message = format!(
+                    "unsupported frontend protocol {}.{}",
+                    code >> 16,
+                    code & 0xFFFF
+                );
+                let body = error_body("FATAL", FEATURE_NOT_SUPPORTED, &message, None);
+                send(writer, b'E', &body)?;
+                writer.flush()?;
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// 不需要认证，返回 `AuthenticationOk` 和服务端的参数
+fn authentication_ok(writer: &mut impl Write) -> Result<()> {
+    send(writer, b'R', &0i32.to_be_bytes())?;
+    for (name, value) in [
+        ("server_version", "16.0"),
+        ("server_encoding", "UTF8"),
+        ("client_encoding", "UTF8"),
+        ("DateStyle", "ISO, MDY"),
+        ("integer_datetimes", "on"),
+        ("standard_conforming_strings", "on"),
+    ] {
+        let mut body = Vec::new();
+        put_cstr(&mut body, name);
+        put_cstr(&mut body, value);
+        send(writer, b'S', &body)?;
+    }
+    Ok(())
+}
+
+/// 执行 `Query` 消息中的 SQL，为每条语句返回结果，遇到错误时停止
+fn simple_query<S: Storage>(
+    writer: &mut impl Write,
+    session: &mut Session<'_, S>,
+    sql: &str,
+) -> Result<()> {
+    // psql 等客户端会去掉最后一条语句的分号
+    let mut sql = sql.trim_end().to_string();
+    if sql.is_empty() {
+        return send(writer, b'I', &[]);
+    }
+    if !sql.ends_with(';') {
+        sql.push(';');
+    }
+
+    let statements = match Parser::parse_statements(&sql) {
+        Ok(statements) => statements,
+        Err(e) => return query_error(writer, &sql, &e),
+    };
+    for (span, statement) in statements {
+        match session.execute(&sql[span]) {
+            Ok(result) => send_result(writer, &statement, result)?,
+            Err(e) => return query_error(writer, &sql, &e),
+        }
+    }
+    Ok(())
+}
+
+/// 返回一条语句的结果，查询和执行计划返回结果的每一行
+fn send_result(writer: &mut impl Write, statement: &Statement, result: ResultSet) -> Result<()> {
+    let tag = match result {
+        ResultSet::Query { columns, rows } => {
+            let columns = columns
+                .into_iter()
+                .map(|column| (column.name, column.data_type))
+                .collect::<Vec<_>>();
+            send_rows(writer, &columns, &rows)?;
+            format!("SELECT {}", rows.len())
+        }
+        ResultSet::Explain(plan) => {
+            let rows = plan
+                .lines()
+                .map(|line| vec![Value::String(line.to_string())])
+                .collect::<Vec<_>>();
+            let columns = [("QUERY PLAN".to_string(), Some(DataType::String))];
+            send_rows(writer, &columns, &rows)?;
+            "EXPLAIN".to_string()
+        }
+        // 命令标签中没有位置放自增 ID，PostgreSQL 协议的客户端只能得到行数
+        ResultSet::Modified { count, .. } => match statement {
+            Statement::Insert { .. } => format!("INSERT 0 {}", count),
+            Statement::Update { .. } => format!("UPDATE {}", count),
+            Statement::Copy { .. } | Statement::CopyTo { .. } => format!("COPY {}", count),
+            _ => format!("DELETE {}", count),
+        },
+        ResultSet::CreateTable { .. } => "CREATE TABLE".to_string(),
+        ResultSet::CreateIndex { .. } => "CREATE INDEX".to_string(),
+        ResultSet::DropTable { .. } => "DROP TABLE".to_string(),
+        ResultSet::Analyze { .. } => "ANALYZE".to_string(),
+        ResultSet::Begin => "BEGIN".to_string(),
+        ResultSet::Commit => "COMMIT".to_string(),
+        ResultSet::Rollback => "ROLLBACK".to_string(),
+    };
+    send_command_complete(writer, &tag)
+}
+
+/// 返回 `CommandComplete`，`tag` 为命令的类型和影响的行数
+fn send_command_complete(writer: &mut impl Write, tag: &str) -> Result<()> {
+    let mut body = Vec::new();
+    put_cstr(&mut body, tag);
+    send(writer, b'C', &body)
+}
+
+/// 返回 `RowDescription` 和每一行的 `DataRow`
+fn send_rows(
+    writer: &mut impl Write,
+    columns: &[(String, Option<DataType>)],
+    rows: &[Vec<Value>],
+) -> Result<()> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&(columns.len() as i16).to_be_bytes());
+    for (name, data_type) in columns {
+        let (oid, size) = type_info(*data_type);
+        put_cstr(&mut body, name);
+        body.extend_from_slice(&0i32.to_be_bytes()); // 表的 OID
+        body.extend_from_slice(&0i16.to_be_bytes()); // 列在表中的序号
+        body.extend_from_slice(&oid.to_be_bytes());
+        body.extend_from_slice(&size.to_be_bytes());
+        body.extend_from_slice(&(-1i32).to_be_bytes()); // 类型修饰符
+        body.extend_from_slice(&0i16.to_be_bytes()); // 文本格式
+    }
+    send(writer, b'T', &body)?;
+
+    for row in rows {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(row.len() as i16).to_be_bytes());
+        for value in row {
+            match text_value(value) {
+                Some(text) => {
+                    body.extend_from_slice(&(text.len() as i32).to_be_bytes());
+                    body.extend_from_slice(text.as_bytes());
+                }
+                None => body.extend_from_slice(&(-1i32).to_be_bytes()),
+            }
+        }
+        send(writer, b'D', &body)?;
+    }
+    Ok(())
+}
+
+/// 数据类型对应的 PostgreSQL 类型的 OID 和长度，长度为 -1 表示变长，推断不出类型的列作为 `text`
+fn type_info(data_type: Option<DataType>) -> (i32, i16) {
+    match data_type {
+        Some(DataType::Boolean) => (16, 1),
+        Some(DataType::Integer) => (20, 8),
+        Some(DataType::Float) => (701, 8),
+        Some(DataType::Json) => (114, -1),
+        Some(DataType::String) | None => (25, -1),
+    }
+}
+
+/// 值的文本格式，NULL 返回 `None`
+fn text_value(value: &Value) -> Option<String> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Boolean(b) => if *b { "t" } else { "f" }.to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) if f.is_nan() => "NaN".to_string(),
+        Value::Float(f) if f.is_infinite() => {
+            if *f > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+        }
+        Value::Float(f) => f.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Json(json) => json.to_string(),
+    })
+}
+
+/// 返回 SQL 出错的 `ErrorResponse`，解析错误附带出错的位置
+fn query_error(writer: &mut impl Write, sql: &str, err: &Error) -> Result<()> {
+    let (message, position) = match err {
+        Error::Script { position, source } => {
+            (source.to_string(), Some(char_position(sql, position)))
+        }
+        err => (err.to_string(), None),
+    };
+    let sqlstate = err.code().sqlstate().unwrap_or(INTERNAL_ERROR);
+    error_response(writer, sqlstate, &message, position)
+}
+
+/// 出错的位置在整个 SQL 中的字符序号，从 1 开始
+fn char_position(sql: &str, position: &ScriptPosition) -> usize {
+    let preceding: usize = sql
+        .split('\n')
+        .take(position.line - 1)
+        .map(|line| line.chars().count() + 1)
+        .sum();
+    preceding + position.column
+}
+
+fn error_response(
+    writer: &mut impl Write,
+    sqlstate: &str,
+    message: &str,
+    position: Option<usize>,
+) -> Result<()> {
+    send(
+        writer,
+        b'E',
+        &error_body("ERROR", sqlstate, message, position),
+    )
+}
+
+/// `ErrorResponse` 的内容：每个字段是类型和以 0 结尾的值，最后以 0 结尾
+fn error_body(severity: &str, sqlstate: &str, message: &str, position: Option<usize>) -> Vec<u8> {
+    let mut body = Vec::new();
+    // `V` 是不会被翻译的严重程度
+    for (field, value) in [
+        (b'S', severity),
+        (b'V', severity),
+        (b'C', sqlstate),
+        (b'M', message),
+    ] {
+        body.push(field);
+        put_cstr(&mut body, value);
+    }
+    if let Some(position) = position {
+        body.push(b'P');
+        put_cstr(&mut body, &position.to_string());
+    }
+    body.push(0);
+    body
+}
+
+/// 返回 `ReadyForQuery`，带有会话是否处于显式事务中，并发送缓冲的所有消息
+fn ready_for_query<S: Storage>(writer: &mut impl Write, session: &Session<'_, S>) -> Result<()> {
+    let status = if session.in_transaction() { b'T' } else { b'I' };
+    send(writer, b'Z', &[status])?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// 写入一条消息：类型、包含自身的 4 字节长度和内容
+fn send(writer: &mut impl Write, tag: u8, body: &[u8]) -> Result<()> {
+    writer.write_all(&[tag])?;
+    writer.write_all(&(body.len() as i32 + 4).to_be_bytes())?;
+    writer.write_all(body)?;
+    Ok(())
+}
+
+/// 读取一条消息的类型和内容，客户端在两条消息之间关闭连接时返回 `None`
+fn read_message(reader: &mut impl Read) -> Result<Option<(u8, Vec<u8>)>> {
+    let mut tag = [0];
+    match reader.read_exact(&mut tag) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    Ok(Some((tag[0], read_body(reader)?)))
+}
+
+/// 读取包含自身的 4 字节长度和之后的内容
+fn read_body(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = i32::from_be_bytes(len);
+    let len = usize::try_from(len)
+        .ok()
+        .and_then(|len| len.checked_sub(4))
+        .filter(|len| *len <= MAX_FRAME_LEN)
+        .ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid message length {}", len),
+            )
+        })?;
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+/// 读取以 0 结尾的 UTF-8 字符串
+fn read_cstr(body: &[u8]) -> Result<&str> {
+    let end = body.iter().position(|b| *b == 0).unwrap_or(body.len());
+    std::str::from_utf8(&body[..end]).map_err(|e| io::Error::new(ErrorKind::InvalidData, e).into())
+}
+
+fn put_cstr(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+    use crate::{storage::MemoryStorage, Engine};
+
+    /// 只支持简单查询的测试客户端，返回收到的每条消息的类型和内容
+    struct TestClient {
+        stream: TcpStream,
+    }
+
+    impl TestClient {
+        fn connect(addr: std::net::SocketAddr) -> Result<Self> {
+            let mut stream = TcpStream::connect(addr)?;
+
+            // 先请求 TLS，服务端拒绝后以明文继续
+            stream.write_all(&8i32.to_be_bytes())?;
+            stream.write_all(&SSL_REQUEST.to_be_bytes())?;
+            let mut answer = [0];
+            stream.read_exact(&mut answer)?;
+            assert_eq!(&answer, b"N");
+
+            let mut body = PROTOCOL_VERSION.to_be_bytes().to_vec();
+            put_cstr(&mut body, "user");
+            put_cstr(&mut body, "test");
+            body.push(0);
+            stream.write_all(&(body.len() as i32 + 4).to_be_bytes())?;
+            stream.write_all(&body)?;
+
+            let mut client = Self { stream };
+            let messages = client.read_until_ready()?;
+            assert_eq!(messages[0], (b'R', 0i32.to_be_bytes().to_vec()));
+            Ok(client)
+        }
+
+        fn query(&mut self, sql: &str) -> Result<Vec<(u8, Vec<u8>)>> {
+            let mut body = Vec::new();
+            put_cstr(&mut body, sql);
+            send(&mut self.stream, b'Q', &body)?;
+            self.read_until_ready()
+        }
+
+        fn read_until_ready(&mut self) -> Result<Vec<(u8, Vec<u8>)>> {
+            let mut messages = Vec::new();
+            loop {
+                let message = read_message(&mut self.stream)?.expect("connection closed");
+                let ready = message.0 == b'Z';
+                messages.push(message);
+                if ready {
+                    return Ok(messages);
+                }
+            }
+        }
+    }
+
+    fn command_complete(tag: &str) -> (u8, Vec<u8>) {
+        let mut body = Vec::new();
+        put_cstr(&mut body, tag);
+        (b'C', body)
+    }
+
+    /// `ErrorResponse` 中类型为 `field` 的字段
+    fn error_field(body: &[u8], field: u8) -> Option<String> {
+        body.split(|b| *b == 0)
+            .find(|f| f.first() == Some(&field))
+            .map(|f| String::from_utf8_lossy(&f[1..]).into_owned())
+    }
+
+    #[test]
+    fn test_simple_query() -> Result<()> {
+        let db = Arc::new(Database::open(Engine::new(MemoryStorage::new())));
+        let server = Server::bind("127.0.0.1:0", db)?;
+        let addr = server.local_addr()?;
+        thread::spawn(move || server.serve_postgres());
+
+        let mut client = TestClient::connect(addr)?;
+        assert_eq!(
+            client.query("CREATE TABLE t (id INT PRIMARY KEY, name VARCHAR NULL, score FLOAT)")?,
+            vec![command_complete("CREATE TABLE"), (b'Z', b"I".to_vec())]
+        );
+        assert_eq!(
+            client.query("INSERT INTO t VALUES (1, 'a', 1.5), (2, NULL, 2.0);")?,
+            vec![command_complete("INSERT 0 2"), (b'Z', b"I".to_vec())]
+        );
+
+        let messages = client.query("SELECT id, name, score FROM t ORDER BY id;")?;
+        let tags = messages.iter().map(|(tag, _)| *tag).collect::<Vec<_>>();
+        assert_eq!(tags, b"TDDCZ");
+        // 第一列 id 的类型为 int8
+        let description = &messages[0].1;
+        assert_eq!(&description[..5], &[0, 3, b'i', b'd', 0]);
+        assert_eq!(&description[11..15], &20i32.to_be_bytes());
+        // 第二行的 name 为 NULL，score 为 "2"
+        let mut expected = vec![0, 3, 0, 0, 0, 1, b'2', 255, 255, 255, 255, 0, 0, 0, 1];
+        expected.push(b'2');
+        assert_eq!(messages[2].1, expected);
+        assert_eq!(messages[3], command_complete("SELECT 2"));
+
+        // 显式事务的状态在 ReadyForQuery 中
+        let messages = client.query("BEGIN; UPDATE t SET score = 3.0 WHERE id = 1")?;
+        assert_eq!(
+            messages,
+            vec![
+                command_complete("BEGIN"),
+                command_complete("UPDATE 1"),
+                (b'Z', b"T".to_vec())
+            ]
+        );
+        assert_eq!(
+            client.query("DELETE FROM t WHERE id = 2; COMMIT;")?,
+            vec![
+                command_complete("DELETE 1"),
+                command_complete("COMMIT"),
+                (b'Z', b"I".to_vec())
+            ]
+        );
+
+        // SHOW SESSIONS 由服务端处理，返回每个连接一行
+        let messages = client.query("show sessions")?;
+        let tags = messages.iter().map(|(tag, _)| *tag).collect::<Vec<_>>();
+        assert_eq!(tags, b"TDCZ");
+        assert_eq!(messages[2], command_complete("SHOW"));
+
+        // 出错后跳过之后的语句，解析错误带有位置
+        let messages = client.query("SELECT * FROM missing; SELECT * FROM t;")?;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].0, b'E');
+        assert_eq!(error_field(&messages[0].1, b'C').as_deref(), Some("42P01"));
+        let messages = client.query("SELECT id FROM t;\nSELECT * FROM t WHERE")?;
+        assert_eq!(messages[0].0, b'E');
+        assert_eq!(error_field(&messages[0].1, b'C').as_deref(), Some("42601"));
+        assert_eq!(error_field(&messages[0].1, b'P').as_deref(), Some("40"));
+
+        assert_eq!(
+            client.query("  ")?,
+            vec![(b'I', vec![]), (b'Z', b"I".to_vec())]
+        );
+
+        // 扩展查询返回错误，Sync 之后恢复
+        send(&mut client.stream, b'P', &[0, 0, 0, 0])?;
+        send(&mut client.stream, b'S', &[])?;
+        let messages = client.read_until_ready()?;
+        assert_eq!(messages[0].0, b'E');
+        assert_eq!(error_field(&messages[0].1, b'C').as_deref(), Some("0A000"));
+        assert_eq!(client.query("SELECT id FROM t;")?.len(), 4);
+
+        send(&mut client.stream, b'X', &[])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_value() {
+        assert_eq!(text_value(&Value::Null), None);
+        assert_eq!(text_value(&Value::Boolean(true)).as_deref(), Some("t"));
+        assert_eq!(text_value(&Value::Float(0.5)).as_deref(), Some("0.5"));
+        assert_eq!(
+            text_value(&Value::Float(f64::NEG_INFINITY)).as_deref(),
+            Some("-Infinity")
+        );
+        assert_eq!(
+            text_value(&Value::String("a'b".to_string())).as_deref(),
+            Some("a'b")
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use crate::{storage::Storage, Result, ResultSet, Session, Value};
+
+/// 连接当前的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// 等待请求，不在显式事务中
+    Idle,
+    /// 正在执行请求
+    Active,
+    /// 等待请求，显式事务尚未结束
+    IdleInTransaction,
+}
+
+impl State {
+    fn as_str(&self) -> &'static str {
+        match self {
+            State::Idle => "idle",
+            State::Active => "active",
+            State::IdleInTransaction => "idle in transaction",
+        }
+    }
+}
+
+/// 一个连接的信息，由 `SHOW SESSIONS` 返回
+#[derive(Debug, Clone)]
+struct SessionInfo {
+    peer: SocketAddr,
+    state: State,
+    /// 显式事务的版本，不在显式事务中时为 `None`
+    transaction_version: Option<u64>,
+    /// 最近一次请求的语句，正在执行时为当前的语句
+    last_statement: Option<String>,
+}
+
+/// 服务端所有连接的登记表，用于限制连接数和列出连接
+#[derive(Debug, Default)]
+pub(super) struct Registry {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    sessions: BTreeMap<u64, SessionInfo>,
+    /// 下一个连接的 ID，从 1 开始，不会重复使用
+    next_id: u64,
+}
+
+impl Registry {
+    /// 登记一个新的连接，已有 `max_connections` 个连接时返回 `None`
+    ///
+    /// 返回的 [`Registration`] 销毁时取消登记。
+    pub(super) fn register(
+        self: &Arc<Self>,
+        peer: SocketAddr,
+        max_connections: Option<usize>,
+    ) -> Result<Option<Registration>> {
+        let mut inner = self.inner.lock()?;
+        if max_connections.is_some_and(|max| inner.sessions.len() >= max) {
+            return Ok(None);
+        }
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.sessions.insert(
+            id,
+            SessionInfo {
+                peer,
+                state: State::Idle,
+                transaction_version: None,
+                last_statement: None,
+            },
+        );
+        Ok(Some(Registration {
+            registry: Arc::clone(self),
+            id,
+        }))
+    }
+
+    /// 以查询结果的形式列出所有连接，按照连接 ID 排序
+    fn show_sessions(&self) -> Result<ResultSet> {
+        let rows = self
+            .inner
+            .lock()?
+            .sessions
+            .iter()
+            .map(|(id, info)| {
+                vec![
+                    Value::Integer(*id as i64),
+                    Value::String(info.peer.to_string()),
+                    Value::String(info.state.as_str().to_string()),
+                    info.transaction_version
+                        .map_or(Value::Null, |version| Value::Integer(version as i64)),
+                    info.last_statement
+                        .clone()
+                        .map_or(Value::Null, Value::String),
+                ]
+            })
+            .collect();
+        let columns = [
+            "id",
+            "peer",
+            "state",
+            "transaction_version",
+            "last_statement",
+        ];
+        Ok(ResultSet::query(
+            columns.into_iter().map(str::to_string).collect(),
+            rows,
+        ))
+    }
+}
+
+/// 已经登记的连接，销毁时从 [`Registry`] 中移除
+pub(super) struct Registration {
+    registry: Arc<Registry>,
+    id: u64,
+}
+
+impl Registration {
+    /// 开始执行请求中的语句 `statement`
+    pub(super) fn begin(&self, statement: &str) -> Result<()> {
+        self.update(|info| {
+            info.state = State::Active;
+            info.last_statement = Some(statement.to_string());
+        })
+    }
+
+    /// 请求执行结束，根据会话是否处于显式事务中更新状态
+    pub(super) fn finish<S: Storage>(&self, session: &Session<'_, S>) -> Result<()> {
+        let version = session.transaction_version().map(u64::from);
+        self.update(|info| {
+            info.state = match version {
+                Some(_) => State::IdleInTransaction,
+                None => State::Idle,
+            };
+            info.transaction_version = version;
+        })
+    }
+
+    /// `sql` 是 `SHOW SESSIONS` 时返回所有连接，否则返回 `None`，由会话执行
+    pub(super) fn show_sessions(&self, sql: &str) -> Result<Option<ResultSet>> {
+        let sql = sql.trim().trim_end_matches(';');
+        let words = sql.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [show, sessions]
+                if show.eq_ignore_ascii_case("SHOW")
+                    && sessions.eq_ignore_ascii_case("SESSIONS") =>
+            {
+                self.registry.show_sessions().map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut SessionInfo)) -> Result<()> {
+        if let Some(info) = self.registry.inner.lock()?.sessions.get_mut(&self.id) {
+            f(info);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Ok(mut inner) = self.registry.inner.lock() {
+            inner.sessions.remove(&self.id);
+        }
+    }
+}
@@ -0,0 +1,1327 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    dump,
+    error::{
+        ExecutionError::{
+            Cancelled, ParameterCount, PreparedStatementNotFound, Timeout, UnboundParameter,
+        },
+        ScriptPosition,
+        TransactionError::{AlreadyStarted, Closed},
+    },
+    executor::{CancellationToken, CopyResult, CsvOptions, Executor, ResultSet},
+    function::FunctionRegistry,
+    parser::{ast::Statement, Parser},
+    plan_cache::{PlanCache, DEFAULT_PLAN_CACHE_CAPACITY},
+    schema::Value,
+    storage::{Storage, Version},
+    Engine, Error, Result, Retryability,
+};
+
+/// 数据库，嵌入时的入口，通过 [`Database::session`] 创建会话执行 SQL
+///
+/// ```
+/// use sqldb::{storage::MemoryStorage, DataType, Database, Engine, ResultSet};
+///
+/// let db = Database::open(Engine::new(MemoryStorage::new()));
+/// let mut session = db.session();
+/// session.execute("CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR);")?;
+/// let result = session.execute("INSERT INTO users VALUES (1, 'Alice'), (2, 'Bob');")?;
+/// assert_eq!(result, ResultSet::modified(2));
+///
+/// let result = session.execute("SELECT name FROM users WHERE id = 2;")?;
+/// assert_eq!(result.columns()[0].data_type, Some(DataType::String));
+/// assert_eq!(result.get::<String>(0, "name")?, "Bob");
+/// # Ok::<(), sqldb::Error>(())
+/// ```
+pub struct Database<S: Storage> {
+    engine: Engine<S>,
+    /// 所有会话共享的查询计划缓存
+    plan_cache: PlanCache,
+}
+
+impl<S: Storage> Database<S> {
+    /// 在存储引擎之上打开数据库
+    pub fn open(engine: Engine<S>) -> Self {
+        Self::with_plan_cache_capacity(engine, DEFAULT_PLAN_CACHE_CAPACITY)
+    }
+
+    /// 在存储引擎之上打开数据库，查询计划缓存最多保存 `capacity` 个计划
+    pub fn with_plan_cache_capacity(engine: Engine<S>, capacity: usize) -> Self {
+        Self {
+            engine,
+            plan_cache: PlanCache::new(capacity),
+        }
+    }
+
+    /// 查询计划缓存，可以查看命中统计
+    pub fn plan_cache(&self) -> &PlanCache {
+        &self.plan_cache
+    }
+
+    /// 标量函数的注册表，注册的函数对之后执行的所有语句可见
+    pub fn functions(&self) -> &FunctionRegistry {
+        self.engine.functions()
+    }
+
+    /// 创建一个新的会话
+    pub fn session(&self) -> Session<'_, S> {
+        Session {
+            engine: &self.engine,
+            plan_cache: &self.plan_cache,
+            transaction: None,
+            prepared: HashMap::new(),
+            altered_tables: Vec::new(),
+            auto_retry: None,
+        }
+    }
+
+    /// 将所有表的定义、数据和索引导出为 SQL 脚本，写入 `writer`
+    ///
+    /// 所有内容在同一个事务中读取，导出的是开始时的一致快照，导出期间其他会话的写入不影响结果。
+    /// 脚本包含在 `BEGIN` 和 `COMMIT` 之间，通过 [`Database::restore`] 在空数据库中执行即可恢复。
+    ///
+    /// ```
+    /// use sqldb::{storage::MemoryStorage, Database, Engine, Value};
+    ///
+    /// let db = Database::open(Engine::new(MemoryStorage::new()));
+    /// db.session().execute_script(
+    ///     "CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR);
+    ///      INSERT INTO users VALUES (1, 'O''Brien');",
+    /// )?;
+    /// let mut script = Vec::new();
+    /// db.dump(&mut script)?;
+    ///
+    /// let restored = Database::open(Engine::new(MemoryStorage::new()));
+    /// restored.restore(script.as_slice())?;
+    /// let result = restored.session().execute("SELECT name FROM users;")?;
+    /// assert_eq!(result.rows(), [vec![Value::String("O'Brien".to_string())]]);
+    /// # Ok::<(), sqldb::Error>(())
+    /// ```
+    pub fn dump(&self, mut writer: impl Write) -> Result<()> {
+        let txn = self.engine.start_txn()?;
+        let result = dump::dump(&txn, &mut writer);
+        // 导出只读取数据，无论成功与否都回滚事务
+        txn.rollback()?;
+        result
+    }
+
+    /// 执行 [`Database::dump`] 导出的脚本，恢复其中的表、数据和索引
+    ///
+    /// 脚本在一个事务中执行，任意语句失败时整个脚本回滚，返回的错误中包含失败语句的位置。
+    pub fn restore(&self, mut reader: impl Read) -> Result<()> {
+        let mut script = String::new();
+        reader.read_to_string(&mut script)?;
+        self.session().execute_script(&script)?;
+        Ok(())
+    }
+}
+
+/// 重试的策略：最多执行的次数，以及两次执行之间等待的时间
+///
+/// ```
+/// use std::time::Duration;
+/// use sqldb::RetryPolicy;
+///
+/// let policy = RetryPolicy::new(5).backoff(Duration::from_millis(10));
+/// assert_eq!(policy.max_attempts(), 5);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// 最多执行 3 次，两次执行之间等待 10 毫秒
+    fn default() -> Self {
+        Self::new(3).backoff(Duration::from_millis(10))
+    }
+}
+
+impl RetryPolicy {
+    /// 最多执行 `max_attempts` 次（包括第一次），两次执行之间不等待，`max_attempts` 为 0 时按照 1 处理
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff: Duration::ZERO,
+        }
+    }
+
+    /// 第 n 次重试前等待 `backoff * n`，给冲突的事务留出结束的时间
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// 最多执行的次数
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// 执行 `f`，失败并且 `should_retry` 返回 true 时按照策略重新执行，返回最后一次执行的结果
+    pub(crate) fn run<T>(
+        &self,
+        should_retry: impl Fn(Retryability) -> bool,
+        mut f: impl FnMut() -> Result<T>,
+    ) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(e) if attempt < self.max_attempts && should_retry(e.retryability()) => {
+                    thread::sleep(self.backoff * attempt);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// 会话，负责解析、计划和执行 SQL 语句
+///
+/// `BEGIN` 开启显式事务，之后的语句都在这个事务中执行，直到 `COMMIT` 或 `ROLLBACK`；
+/// 没有显式事务时，每条语句在单独的事务中执行，成功时自动提交，失败时回滚。
+/// 会话销毁时回滚尚未结束的显式事务。
+///
+/// 通过 [`Session::set_auto_retry`] 开启自动重试后，自动提交的语句遇到写冲突等可以原样重试的错误时
+/// 按照策略重新执行；显式事务中的语句不会自动重试，整个事务的重试由 [`Session::with_retry`] 完成。
+///
+/// 不包含子查询的查询语句的计划按照 SQL 文本缓存在数据库中，再次执行相同的语句时跳过解析和计划；
+/// 创建表或者索引时清除读取这张表的计划。
+///
+/// ```
+/// use sqldb::{storage::MemoryStorage, Database, Engine, ResultSet, Value};
+///
+/// let db = Database::open(Engine::new(MemoryStorage::new()));
+/// let mut session = db.session();
+/// session.execute("CREATE TABLE t (id INT PRIMARY KEY);")?;
+///
+/// // 回滚的写入对之后的语句不可见
+/// session.execute("BEGIN;")?;
+/// assert!(session.in_transaction());
+/// session.execute("INSERT INTO t VALUES (1);")?;
+/// session.execute("ROLLBACK;")?;
+/// assert!(!session.in_transaction());
+///
+/// // 预处理的语句解析一次，可以绑定不同的参数多次执行
+/// session.prepare("count", "SELECT COUNT(*) FROM t WHERE id > $1;")?;
+/// let count = |result: ResultSet| result.rows()[0][0].clone();
+/// let zero = [Value::Integer(0)];
+/// assert_eq!(count(session.execute_prepared("count", &zero)?), Value::Integer(0));
+///
+/// session.execute("BEGIN;")?;
+/// session.execute("INSERT INTO t VALUES (1), (2);")?;
+/// session.execute("COMMIT;")?;
+/// assert_eq!(count(session.execute_prepared("count", &zero)?), Value::Integer(2));
+/// let one = [Value::Integer(1)];
+/// assert_eq!(count(session.execute_prepared("count", &one)?), Value::Integer(1));
+/// # Ok::<(), sqldb::Error>(())
+/// ```
+pub struct Session<'a, S: Storage> {
+    engine: &'a Engine<S>,
+    plan_cache: &'a PlanCache,
+    /// `BEGIN` 开启的显式事务
+    transaction: Option<Executor<S>>,
+    /// 预处理的语句，按照名称保存
+    prepared: HashMap<String, Prepared>,
+    /// 显式事务中修改了定义的表，事务结束时清除读取这些表的计划
+    altered_tables: Vec<String>,
+    /// 自动提交的语句的重试策略，为 `None` 时不重试
+    auto_retry: Option<RetryPolicy>,
+}
+
+/// 会话中以名称保存的预处理语句
+struct Prepared {
+    /// 语句的 SQL 文本，作为缓存计划的键
+    sql: String,
+    statement: Statement,
+    /// 语句需要的参数个数
+    parameters: usize,
+}
+
+impl<S: Storage> Drop for Session<'_, S> {
+    /// 会话销毁时回滚尚未结束的显式事务
+    fn drop(&mut self) {
+        if let Some(mut executor) = self.transaction.take() {
+            if let Err(e) = executor.rollback() {
+                eprintln!("Failed to rollback transaction: {:?}", e);
+            }
+        }
+        if let Err(e) = self.invalidate_altered_tables() {
+            eprintln!("Failed to invalidate cached plans: {:?}", e);
+        }
+    }
+}
+
+impl<S: Storage> Session<'_, S> {
+    /// 解析并执行一条 SQL 语句
+    pub fn execute(&mut self, sql: &str) -> Result<ResultSet> {
+        self.execute_sql(sql, None)
+    }
+
+    /// 解析并依次执行由多条以分号结尾的语句组成的脚本，返回每条语句的结果
+    ///
+    /// 先解析整个脚本，任何一条语句解析失败时不执行任何语句；执行时遇到第一个失败的语句就停止，
+    /// 之前的语句的执行结果按照各自所在的事务保留。两种错误都是 [`Error::Script`]，
+    /// 包含出错的语句的下标和在整个脚本中的行列号：解析错误指向出错的 token，执行错误指向语句的开头。
+    ///
+    /// ```
+    /// use sqldb::{storage::MemoryStorage, Database, Engine, Error};
+    ///
+    /// let db = Database::open(Engine::new(MemoryStorage::new()));
+    /// let mut session = db.session();
+    /// let err = session
+    ///     .execute_script("CREATE TABLE t (id INT PRIMARY KEY);\nSELECT * FROM t WHERE;")
+    ///     .unwrap_err();
+    /// let Error::Script { position, .. } = &err else { unreachable!() };
+    /// assert_eq!((position.statement_index, position.line, position.column), (1, 2, 22));
+    /// assert_eq!(position.snippet, format!("SELECT * FROM t WHERE;\n{}^", " ".repeat(21)));
+    /// ```
+    pub fn execute_script(&mut self, script: &str) -> Result<Vec<ResultSet>> {
+        let statements = Parser::parse_statements(script)?;
+        let mut results = Vec::with_capacity(statements.len());
+        for (statement_index, (span, statement)) in statements.into_iter().enumerate() {
+            let result =
+                self.auto_retry(|session| session.execute_statement(statement.clone(), None));
+            match result {
+                Ok(result) => results.push(result),
+                Err(e) => {
+                    return Err(Error::Script {
+                        position: ScriptPosition::new(script, statement_index, span.start),
+                        source: Box::new(e),
+                    })
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// 解析并执行一条 SQL 语句，`token` 被取消时语句返回 [`Cancelled`]
+    ///
+    /// 语句被取消或者超时时回滚所在的事务，包括 `BEGIN` 开启的显式事务，因为已经写入的部分无法单独撤销。
+    pub fn execute_with_token(
+        &mut self,
+        sql: &str,
+        token: &CancellationToken,
+    ) -> Result<ResultSet> {
+        self.execute_sql(sql, Some(token.clone()))
+    }
+
+    /// 解析并执行一条 SQL 语句，超过 `deadline` 时语句返回 [`Timeout`]，事务的处理和
+    /// [`Session::execute_with_token`] 相同
+    pub fn execute_with_deadline(&mut self, sql: &str, deadline: Instant) -> Result<ResultSet> {
+        self.execute_sql(sql, Some(CancellationToken::with_deadline(deadline)))
+    }
+
+    /// 解析 SQL 语句并以 `name` 保存，同名的语句会被替换
+    ///
+    /// 语句中可以使用参数占位符 `$1`、`$2` 等，或者按照出现顺序编号的 `?`，执行时绑定参数值。
+    pub fn prepare(&mut self, name: &str, sql: &str) -> Result<()> {
+        let mut parser = Parser::new(sql);
+        let statement = parser.parse()?;
+        let prepared = Prepared {
+            sql: sql.to_string(),
+            statement,
+            parameters: parser.parameter_count(),
+        };
+        self.prepared.insert(name.to_string(), prepared);
+        Ok(())
+    }
+
+    /// 将 `params` 绑定到以 `name` 保存的预处理语句的占位符并执行，`params[0]` 对应 `$1`
+    ///
+    /// 参数的个数必须和语句中占位符的最大编号相同，否则返回 [`ParameterCount`]。
+    /// 查询语句的计划保留占位符，以语句的 SQL 文本缓存，参数不同的执行共享同一个计划。
+    pub fn execute_prepared(&mut self, name: &str, params: &[Value]) -> Result<ResultSet> {
+        let prepared = self
+            .prepared
+            .get(name)
+            .ok_or(PreparedStatementNotFound(name.to_string()))?;
+        if params.len() != prepared.parameters {
+            return Err(ParameterCount {
+                expected: prepared.parameters,
+                found: params.len(),
+            }
+            .into());
+        }
+        let (sql, statement) = (prepared.sql.clone(), prepared.statement.clone());
+        if is_reusable_query(&statement) {
+            return self.auto_retry(|session| {
+                if session.altered_tables.is_empty() {
+                    if let Some(plan) = session.plan_cache.get(&sql)? {
+                        return session.run(None, |executor| executor.execute_plan(&plan, params));
+                    }
+                }
+                session.plan_and_execute(&sql, statement.clone(), params, None)
+            });
+        }
+        let statement = statement.bind(params)?;
+        self.auto_retry(|session| session.execute_statement(statement.clone(), None))
+    }
+
+    /// 从 `reader` 中读取 CSV 格式的行写入表 `table_name`，和 `COPY ... FROM` 语句相同，见 [`Executor::copy_from`]
+    ///
+    /// 在显式事务中导入时和事务一起提交，否则在自动提交的事务中导入，出错时不写入任何行。
+    /// `reader` 只能读取一次，因此导入不会自动重试。
+    ///
+    /// ```
+    /// use sqldb::{storage::MemoryStorage, CsvOptions, Database, Engine, OnError};
+    ///
+    /// let db = Database::open(Engine::new(MemoryStorage::new()));
+    /// let mut session = db.session();
+    /// session.execute("CREATE TABLE t (id INT PRIMARY KEY, name STRING NULL);")?;
+    /// let options = CsvOptions {
+    ///     header: true,
+    ///     on_error: OnError::Skip,
+    ///     ..Default::default()
+    /// };
+    /// let csv = "name,id\nalice,1\nbob,x\n,3\n";
+    /// let result = session.copy_from("t", csv.as_bytes(), &options)?;
+    /// assert_eq!(result.inserted, 2);
+    /// assert_eq!(result.skipped[0].0, 3);
+    /// # Ok::<(), sqldb::Error>(())
+    /// ```
+    pub fn copy_from(
+        &mut self,
+        table_name: &str,
+        reader: impl Read,
+        options: &CsvOptions,
+    ) -> Result<CopyResult> {
+        self.run(None, |executor| {
+            executor.copy_from(table_name, reader, options)
+        })
+    }
+
+    /// 执行查询 `query`，将结果以 CSV 格式写入 `writer`，返回写入的行数，和 `COPY (...) TO` 语句相同，见 [`Executor::copy_to`]
+    ///
+    /// `query` 必须是 SELECT 或者集合运算语句。结果逐行写入 `writer`，出错时已经写入的内容不会被撤销。
+    ///
+    /// ```
+    /// use sqldb::{storage::MemoryStorage, CsvOptions, Database, Engine};
+    ///
+    /// let db = Database::open(Engine::new(MemoryStorage::new()));
+    /// let mut session = db.session();
+    /// session.execute("CREATE TABLE t (id INT PRIMARY KEY, name STRING NULL);")?;
+    /// session.execute("INSERT INTO t VALUES (1, 'a, b'), (2, NULL);")?;
+    /// let options = CsvOptions {
+    ///     header: true,
+    ///     ..Default::default()
+    /// };
+    /// let mut csv = Vec::new();
+    /// let count = session.copy_to("SELECT * FROM t ORDER BY id;", &mut csv, &options)?;
+    /// assert_eq!(count, 2);
+    /// assert_eq!(String::from_utf8(csv).unwrap(), "id,name\n1,\"a, b\"\n2,\n");
+    /// # Ok::<(), sqldb::Error>(())
+    /// ```
+    pub fn copy_to(
+        &mut self,
+        query: &str,
+        writer: impl Write,
+        options: &CsvOptions,
+    ) -> Result<u64> {
+        let query = Parser::new(query).parse()?;
+        if !matches!(
+            query,
+            Statement::Select { .. } | Statement::SetOperation { .. }
+        ) {
+            return Err(Error::Parse(
+                "Only SELECT and set operations can be exported".to_string(),
+            ));
+        }
+        self.run(None, |executor| executor.copy_to(query, writer, options))
+    }
+
+    /// 当前是否处于 `BEGIN` 开启的显式事务中
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// 显式事务的版本，不在显式事务中时为 `None`
+    pub fn transaction_version(&self) -> Option<Version> {
+        self.transaction.as_ref().map(Executor::version)
+    }
+
+    /// 设置自动提交的语句的重试策略，`None` 表示不重试
+    ///
+    /// 只有 [`Retryability::Retryable`] 的错误会被重试：自动提交的语句在新的事务中重新执行，
+    /// 失败的事务已经回滚，重试对调用方不可见。其他错误以及显式事务中的错误直接返回。
+    pub fn set_auto_retry(&mut self, policy: Option<RetryPolicy>) {
+        self.auto_retry = policy;
+    }
+
+    /// 在显式事务中执行 `f` 并提交，失败时回滚事务，错误可以重试时按照 `policy` 重新执行整个事务
+    ///
+    /// 除了 [`Retryability::Retryable`] 的错误，[`Retryability::RetryableAfterReread`] 的错误也会被重试，
+    /// 因为每次执行 `f` 都会在新的事务中重新读取数据。`f` 需要能够重复执行，不应该有事务之外的副作用。
+    /// 会话已经处于显式事务中时返回 [`AlreadyStarted`]。
+    ///
+    /// ```
+    /// use sqldb::{storage::MemoryStorage, Database, Engine, RetryPolicy, Value};
+    ///
+    /// let db = Database::open(Engine::new(MemoryStorage::new()));
+    /// let mut session = db.session();
+    /// session.execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")?;
+    /// session.execute("INSERT INTO t VALUES (1, 1);")?;
+    ///
+    /// session.with_retry(RetryPolicy::default(), |session| {
+    ///     let result = session.execute("SELECT v FROM t WHERE id = 1;")?;
+    ///     let v = result.get::<i64>(0, "v")?;
+    ///     session.execute(&format!("UPDATE t SET v = {} WHERE id = 1;", v + 1))?;
+    ///     Ok(())
+    /// })?;
+    /// let result = session.execute("SELECT v FROM t;")?;
+    /// assert_eq!(result.rows()[0][0], Value::Integer(2));
+    /// # Ok::<(), sqldb::Error>(())
+    /// ```
+    pub fn with_retry<T>(
+        &mut self,
+        policy: RetryPolicy,
+        mut f: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        if self.transaction.is_some() {
+            return Err(AlreadyStarted.into());
+        }
+        policy.run(
+            |retryability| retryability != Retryability::Fatal,
+            || {
+                self.execute("BEGIN;")?;
+                let result = f(self).and_then(|value| {
+                    self.execute("COMMIT;")?;
+                    Ok(value)
+                });
+                // 提交失败时事务已经结束，`f` 失败时事务可能仍在进行
+                if result.is_err() && self.transaction.is_some() {
+                    self.execute("ROLLBACK;")?;
+                }
+                result
+            },
+        )
+    }
+
+    /// 调用 `f` 执行语句，不在显式事务中并且设置了重试策略时，可以原样重试的错误按照策略重新执行
+    fn auto_retry(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> Result<ResultSet>,
+    ) -> Result<ResultSet> {
+        match self.auto_retry {
+            Some(policy) if self.transaction.is_none() => policy.run(
+                |retryability| retryability == Retryability::Retryable,
+                || f(self),
+            ),
+            _ => f(self),
+        }
+    }
+
+    /// 执行一条 SQL 语句，查询语句优先使用缓存的计划，`token` 为语句的取消标记
+    fn execute_sql(&mut self, sql: &str, token: Option<CancellationToken>) -> Result<ResultSet> {
+        self.auto_retry(|session| session.execute_sql_once(sql, token.clone()))
+    }
+
+    /// 执行一条 SQL 语句，不重试，参数和 [`Session::execute_sql`] 相同
+    fn execute_sql_once(
+        &mut self,
+        sql: &str,
+        token: Option<CancellationToken>,
+    ) -> Result<ResultSet> {
+        if self.altered_tables.is_empty() {
+            if let Some(plan) = self.plan_cache.get(sql)? {
+                // 计划可能来自文本相同的预处理语句，直接执行时占位符没有绑定参数值，和下面的检查相同
+                if plan.parameters > 0 {
+                    return Err(UnboundParameter(plan.parameters).into());
+                }
+                return self.run(token, |executor| executor.execute_plan(&plan, &[]));
+            }
+        }
+
+        let mut parser = Parser::new(sql);
+        let statement = parser.parse()?;
+        // 占位符只能在预处理语句中绑定参数值，和脚本中的语句执行到占位符时的错误相同
+        if parser.parameter_count() > 0 {
+            return Err(UnboundParameter(parser.parameter_count()).into());
+        }
+        if !is_reusable_query(&statement) {
+            return self.execute_statement(statement, token);
+        }
+        self.plan_and_execute(sql, statement, &[], token)
+    }
+
+    /// 计划并执行没有缓存计划的查询语句，`params` 为绑定到占位符的参数，计划以 `sql` 缓存
+    fn plan_and_execute(
+        &mut self,
+        sql: &str,
+        statement: Statement,
+        params: &[Value],
+        token: Option<CancellationToken>,
+    ) -> Result<ResultSet> {
+        // 显式事务修改了表定义时，缓存的计划可能不符合事务中的定义，事务中的计划也不能被其他会话使用
+        let use_cache = self.altered_tables.is_empty();
+        self.plan_cache.record_miss();
+        let plan_cache = self.plan_cache;
+        self.run(token, |executor| {
+            let plan = match executor.plan_query(statement.clone(), params.len()) {
+                // LIMIT 和 OFFSET 中有占位符时只能在绑定参数之后计划，计划依赖参数的值，不缓存
+                Err(Error::Execution(UnboundParameter(_))) => {
+                    let plan = executor.plan_query(statement.bind(params)?, 0)?;
+                    return executor.execute_plan(&plan, &[]);
+                }
+                plan => plan?,
+            };
+            let result = executor.execute_plan(&plan, params)?;
+            if use_cache {
+                plan_cache.insert(sql, Arc::new(plan))?;
+            }
+            Ok(result)
+        })
+    }
+
+    /// 执行一条已经解析的语句，`token` 为语句的取消标记
+    fn execute_statement(
+        &mut self,
+        statement: Statement,
+        token: Option<CancellationToken>,
+    ) -> Result<ResultSet> {
+        let altered_table = match &statement {
+            Statement::CreateTable { name, .. } | Statement::DropTable { name } => {
+                Some(name.clone())
+            }
+            Statement::CreateIndex { table_name, .. } => Some(table_name.clone()),
+            _ => None,
+        };
+        if let Some(table_name) = altered_table {
+            // 事务结束时再次清除计划，因为其他会话在事务提交之前仍然按照旧的定义计划和缓存查询
+            self.plan_cache.invalidate_table(&table_name)?;
+            let in_transaction = self.transaction.is_some();
+            self.altered_tables.push(table_name);
+            let result = self.run(token, |executor| executor.execute(statement));
+            if !in_transaction {
+                self.invalidate_altered_tables()?;
+            }
+            return result;
+        }
+
+        match statement {
+            Statement::Begin => {
+                if self.transaction.is_some() {
+                    return Err(AlreadyStarted.into());
+                }
+                self.transaction = Some(Executor::from_engine(self.engine)?);
+                Ok(ResultSet::Begin)
+            }
+            Statement::Commit => {
+                let result = self.take_transaction()?.commit();
+                self.invalidate_altered_tables()?;
+                result.map(|_| ResultSet::Commit)
+            }
+            Statement::Rollback => {
+                let result = self.take_transaction()?.rollback();
+                self.invalidate_altered_tables()?;
+                result.map(|_| ResultSet::Rollback)
+            }
+            statement => self.run(token, |executor| executor.execute(statement)),
+        }
+    }
+
+    /// 在显式事务或者自动提交的事务中调用 `f` 执行语句，`token` 为语句的取消标记
+    ///
+    /// 开始执行之前已经取消的语句不会执行，自动提交的语句在提交之前再检查一次，取消后回滚而不是提交。
+    fn run<T>(
+        &mut self,
+        token: Option<CancellationToken>,
+        f: impl FnOnce(&Executor<S>) -> Result<T>,
+    ) -> Result<T> {
+        let check = |token: &Option<CancellationToken>| match token {
+            Some(token) => token.check(),
+            None => Ok(()),
+        };
+        match &mut self.transaction {
+            Some(executor) => {
+                let result = check(&token).and_then(|_| {
+                    executor.set_cancellation(token);
+                    f(executor)
+                });
+                executor.set_cancellation(None);
+                if let Err(Error::Execution(Cancelled | Timeout)) = result {
+                    self.take_transaction()?.rollback()?;
+                    self.invalidate_altered_tables()?;
+                }
+                result
+            }
+            None => {
+                let mut executor = Executor::from_engine(self.engine)?;
+                executor.set_cancellation(token.clone());
+                match check(&token)
+                    .and_then(|_| f(&executor))
+                    .and_then(|result| check(&token).map(|_| result))
+                {
+                    Ok(result) => {
+                        executor.commit()?;
+                        Ok(result)
+                    }
+                    Err(e) => {
+                        executor.rollback()?;
+                        Err(e)
+                    }
+                }
+            }
+        }
+    }
+
+    /// 取出显式事务，没有显式事务时返回错误
+    fn take_transaction(&mut self) -> Result<Executor<S>> {
+        self.transaction.take().ok_or(Closed.into())
+    }
+
+    /// 显式事务结束后，清除读取事务中修改了定义的表的计划
+    fn invalidate_altered_tables(&mut self) -> Result<()> {
+        for table_name in self.altered_tables.drain(..) {
+            self.plan_cache.invalidate_table(&table_name)?;
+        }
+        Ok(())
+    }
+}
+
+/// 语句是否为可以缓存计划的查询语句，子查询在计划之前执行，结果随数据变化，有子查询的计划不能被复用
+fn is_reusable_query(statement: &Statement) -> bool {
+    matches!(
+        statement,
+        Statement::Select { .. } | Statement::SetOperation { .. }
+    ) && !statement.has_subquery()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::MemoryStorage, ErrorCode, PlanCacheStats, Value};
+
+    /// 查询 `t` 表中的所有 id
+    fn ids(session: &mut Session<MemoryStorage>) -> Result<Vec<Value>> {
+        Ok(session
+            .execute("SELECT id FROM t ORDER BY id;")?
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect())
+    }
+
+    #[test]
+    fn test_session() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INT PRIMARY KEY);")?;
+
+        // 自动提交的语句失败时整条语句回滚，已经插入的行也不可见
+        assert!(session
+            .execute("INSERT INTO t VALUES (1), (2), (1);")
+            .is_err());
+        assert_eq!(ids(&mut session)?, vec![]);
+
+        // 没有显式事务时不能提交或回滚，也不能重复开启事务
+        assert!(session.execute("COMMIT;").is_err());
+        assert!(session.execute("ROLLBACK;").is_err());
+        assert_eq!(session.execute("BEGIN;")?, ResultSet::Begin);
+        assert!(session.execute("BEGIN;").is_err());
+        assert!(session.in_transaction());
+
+        // 显式事务中的写入在提交之前对其他会话不可见
+        session.execute("INSERT INTO t VALUES (1);")?;
+        let mut other = db.session();
+        assert_eq!(ids(&mut other)?, vec![]);
+        assert_eq!(ids(&mut session)?, vec![Value::Integer(1)]);
+        assert_eq!(session.execute("COMMIT;")?, ResultSet::Commit);
+        assert_eq!(ids(&mut other)?, vec![Value::Integer(1)]);
+
+        // 会话销毁时回滚尚未结束的事务
+        session.execute("BEGIN;")?;
+        session.execute("INSERT INTO t VALUES (2);")?;
+        drop(session);
+        assert_eq!(ids(&mut other)?, vec![Value::Integer(1)]);
+
+        // 执行不存在的预处理语句报错，语法错误在预处理时报错
+        assert!(other.execute_prepared("missing", &[]).is_err());
+        assert!(other.prepare("invalid", "SELECT FROM;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepared_parameters() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INT PRIMARY KEY, name VARCHAR NULL, score FLOAT);")?;
+
+        // `?` 按照出现的顺序编号
+        session.prepare("insert", "INSERT INTO t VALUES (?, ?, ?);")?;
+        for (id, name) in [(1, "a"), (2, "b"), (3, "c")] {
+            let params = [
+                Value::Integer(id),
+                Value::String(name.to_string()),
+                Value::Float(id as f64 * 10.0),
+            ];
+            assert_eq!(
+                session.execute_prepared("insert", &params)?,
+                ResultSet::modified(1)
+            );
+        }
+        session.execute_prepared(
+            "insert",
+            &[Value::Integer(4), Value::Null, Value::Float(0.5)],
+        )?;
+
+        // 同一个参数可以出现多次，子查询中的占位符同样绑定
+        session.prepare(
+            "select",
+            "SELECT id, name FROM t WHERE id >= $1 AND score > (SELECT MIN(score) FROM t WHERE id >= $1) ORDER BY id;",
+        )?;
+        let result = session.execute_prepared("select", &[Value::Integer(3)])?;
+        assert_eq!(
+            result.rows(),
+            &[vec![Value::Integer(3), Value::String("c".to_string())]]
+        );
+        let result = session.execute_prepared("select", &[Value::Integer(1)])?;
+        assert_eq!(result.rows().len(), 3);
+
+        session.prepare("update", "UPDATE t SET name = $2 WHERE id = $1;")?;
+        session.execute_prepared(
+            "update",
+            &[Value::Integer(4), Value::String("d".to_string())],
+        )?;
+        let result = session.execute("SELECT name FROM t WHERE id = 4;")?;
+        assert_eq!(result.get::<String>(0, "name")?, "d");
+
+        // 参数的个数必须和占位符相符，直接执行包含占位符的语句时没有参数
+        let err = session
+            .execute_prepared("update", &[Value::Integer(4)])
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ValueCountMismatch);
+        let err = session
+            .execute("SELECT * FROM t WHERE id = $1;")
+            .unwrap_err();
+        assert_eq!(err, UnboundParameter(1).into());
+        let err = session
+            .execute_script("DELETE FROM t WHERE id = $1;")
+            .unwrap_err();
+        let Error::Script { source, .. } = err else {
+            panic!("expected a script error");
+        };
+        assert_eq!(*source, UnboundParameter(1).into());
+        assert!(session.prepare("mixed", "SELECT $1, ?;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_cache() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        let stats = |hits, misses, entries| PlanCacheStats {
+            hits,
+            misses,
+            entries,
+        };
+        session.execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")?;
+        session.execute("CREATE TABLE u (id INT PRIMARY KEY);")?;
+        session.execute("INSERT INTO t VALUES (1, 10), (2, 20);")?;
+
+        // 只有空白不同的语句共享计划，缓存的计划读取执行时的数据
+        assert_eq!(
+            ids(&mut session)?,
+            vec![Value::Integer(1), Value::Integer(2)]
+        );
+        session.execute("INSERT INTO t VALUES (3, 30);")?;
+        session.execute("SELECT * FROM u;")?;
+        assert_eq!(
+            session
+                .execute("  SELECT id  FROM t\nORDER BY id;")?
+                .rows()
+                .len(),
+            3
+        );
+        assert_eq!(db.plan_cache().stats()?, stats(1, 2, 2));
+
+        // 包含子查询的语句不缓存
+        session.execute("SELECT id FROM t WHERE v = (SELECT MAX(v) FROM t);")?;
+        assert_eq!(db.plan_cache().stats()?, stats(1, 2, 2));
+
+        // 创建索引清除读取这张表的计划，重新计划后使用新的索引
+        session.execute("CREATE INDEX idx_v ON t (v);")?;
+        assert_eq!(db.plan_cache().stats()?.entries, 1);
+        let result = session.execute("SELECT id FROM t WHERE v = 20;")?;
+        assert_eq!(result.rows(), &[vec![Value::Integer(2)]]);
+        let ResultSet::Explain(plan) = session.execute("EXPLAIN SELECT id FROM t WHERE v = 20;")?
+        else {
+            panic!("expected explain result");
+        };
+        assert!(plan.contains("idx_v"));
+
+        // 显式事务中修改表定义之后不使用缓存，其他会话在提交之前缓存的计划在事务结束时被清除
+        let mut other = db.session();
+        session.execute("BEGIN;")?;
+        session.execute("CREATE TABLE w (id INT PRIMARY KEY);")?;
+        session.execute("SELECT * FROM w;")?;
+        ids(&mut session)?;
+        assert!(other.execute("SELECT * FROM w;").is_err());
+        assert_eq!(db.plan_cache().stats()?.entries, 2);
+        ids(&mut other)?;
+        assert_eq!(db.plan_cache().stats()?.entries, 3);
+        session.execute("ROLLBACK;")?;
+        assert_eq!(db.plan_cache().stats()?.entries, 3);
+        assert!(other.execute("SELECT * FROM w;").is_err());
+
+        session.execute("BEGIN;")?;
+        session.execute("CREATE INDEX idx_id_v ON t (id, v);")?;
+        ids(&mut other)?;
+        assert_eq!(db.plan_cache().stats()?.entries, 2);
+        session.execute("COMMIT;")?;
+        assert_eq!(db.plan_cache().stats()?.entries, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_cache_eviction() -> Result<()> {
+        let db = Database::with_plan_cache_capacity(Engine::new(MemoryStorage::new()), 2);
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INT PRIMARY KEY);")?;
+
+        // 缓存满时淘汰最久没有被使用的计划
+        for sql in [
+            "SELECT * FROM t WHERE id = 1;",
+            "SELECT * FROM t WHERE id = 2;",
+            "SELECT * FROM t WHERE id = 1;",
+            "SELECT * FROM t WHERE id = 3;",
+        ] {
+            session.execute(sql)?;
+        }
+        let stats = db.plan_cache().stats()?;
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 3, 2));
+        session.execute("SELECT * FROM t WHERE id = 1;")?;
+        session.execute("SELECT * FROM t WHERE id = 2;")?;
+        let stats = db.plan_cache().stats()?;
+        assert_eq!((stats.hits, stats.misses), (2, 4));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_cache_prepared() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let (mut session, mut other) = (db.session(), db.session());
+        session.execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")?;
+        session.execute("INSERT INTO t VALUES (1, 10), (2, 20), (3, 30);")?;
+        let row = |id, v| vec![Value::Integer(id), Value::Integer(v)];
+
+        // 预处理的查询以占位符的形式缓存，参数不同的执行和其他会话中相同的语句共享计划
+        session.prepare("q", "SELECT * FROM t WHERE id = $1;")?;
+        other.prepare("q", "SELECT * FROM t WHERE id = $1;")?;
+        let result = session.execute_prepared("q", &[Value::Integer(1)])?;
+        assert_eq!(result.rows(), &[row(1, 10)]);
+        let result = session.execute_prepared("q", &[Value::Integer(2)])?;
+        assert_eq!(result.rows(), &[row(2, 20)]);
+        let result = other.execute_prepared("q", &[Value::Integer(3)])?;
+        assert_eq!(result.rows(), &[row(3, 30)]);
+        let stats = db.plan_cache().stats()?;
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 1, 1));
+
+        // 绑定参数之后重新选择访问路径，缓存的计划读取执行时的数据
+        session.execute("INSERT INTO t VALUES (4, 40);")?;
+        session.prepare("range", "SELECT id FROM t WHERE id >= ? AND v < ?;")?;
+        for _ in 0..2 {
+            let result =
+                session.execute_prepared("range", &[Value::Integer(2), Value::Integer(40)])?;
+            assert_eq!(
+                result.rows(),
+                &[vec![Value::Integer(2)], vec![Value::Integer(3)]]
+            );
+        }
+        let stats = db.plan_cache().stats()?;
+        assert_eq!((stats.hits, stats.misses, stats.entries), (3, 2, 2));
+
+        // LIMIT 中的占位符在计划时求值，这样的计划不缓存
+        session.prepare("limit", "SELECT id FROM t ORDER BY id LIMIT $1;")?;
+        for n in [1, 3] {
+            let result = session.execute_prepared("limit", &[Value::Integer(n)])?;
+            assert_eq!(result.rows().len(), n as usize);
+        }
+        assert_eq!(db.plan_cache().stats()?.entries, 2);
+
+        // 直接执行带占位符的语句时，即使命中缓存的计划也需要绑定参数
+        let e = session
+            .execute("SELECT * FROM t WHERE id = $1;")
+            .unwrap_err();
+        assert_eq!(e, UnboundParameter(1).into());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_modified_count() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        let modified = ResultSet::modified;
+        session.execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")?;
+
+        assert_eq!(
+            session.execute("INSERT INTO t VALUES (1, 0), (2, 0), (3, 1);")?,
+            modified(3)
+        );
+
+        // 赋值前后相同的行不计入更新的行数
+        assert_eq!(session.execute("UPDATE t SET v = 0;")?, modified(1));
+        assert_eq!(session.execute("UPDATE t SET v = 0;")?, modified(0));
+        assert_eq!(
+            session.execute("UPDATE t SET v = v + 1 WHERE id > 9;")?,
+            modified(0)
+        );
+
+        assert_eq!(session.execute("DELETE FROM t WHERE id > 9;")?, modified(0));
+        assert_eq!(
+            session.execute("DELETE FROM t WHERE id >= 2;")?,
+            modified(2)
+        );
+        assert_eq!(ids(&mut session)?, vec![Value::Integer(1)]);
+
+        // INSERT 返回按照插入顺序为自增列分配的 ID，显式给出的值不包含在内
+        session.execute("CREATE TABLE a (id INT PRIMARY KEY AUTO_INCREMENT, v INT);")?;
+        assert_eq!(
+            session.execute("INSERT INTO a (v) VALUES (1), (2), (3);")?,
+            ResultSet::Modified {
+                count: 3,
+                last_insert_ids: vec![1, 2, 3],
+            }
+        );
+        let result = session.execute("INSERT INTO a VALUES (10, 4), (NULL, 5);")?;
+        assert_eq!(result.last_insert_ids(), &[11]);
+        assert_eq!(
+            session.execute("INSERT INTO a SELECT NULL, v FROM a WHERE v < 3;")?,
+            ResultSet::Modified {
+                count: 2,
+                last_insert_ids: vec![12, 13],
+            }
+        );
+        assert_eq!(session.execute("UPDATE a SET v = 0;")?, modified(7));
+        assert!(session
+            .execute("SELECT * FROM a;")?
+            .last_insert_ids()
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_retry() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session_1 = db.session();
+        let mut session_2 = db.session();
+        session_1.execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")?;
+        session_1.execute("INSERT INTO t VALUES (1, 1);")?;
+        let v = |session: &mut Session<MemoryStorage>| -> Result<i64> {
+            session
+                .execute("SELECT v FROM t WHERE id = 1;")?
+                .get(0, "v")
+        };
+
+        // 第一次执行与 session_1 的事务冲突，session_1 提交后重新执行整个事务，读取到新的值
+        session_1.execute("BEGIN;")?;
+        session_1.execute("UPDATE t SET v = 10 WHERE id = 1;")?;
+        let mut attempts = 0;
+        session_2.with_retry(RetryPolicy::new(3), |session| {
+            attempts += 1;
+            let old = v(session)?;
+            let result = session.execute(&format!("UPDATE t SET v = {} WHERE id = 1;", old + 1));
+            if attempts == 1 {
+                assert!(result.as_ref().unwrap_err().is_retryable());
+                session_1.execute("COMMIT;")?;
+            }
+            result
+        })?;
+        assert_eq!(attempts, 2);
+        assert!(!session_2.in_transaction());
+        assert_eq!(v(&mut session_2)?, 11);
+
+        // 不能重试的错误直接返回，事务已经回滚
+        let mut attempts = 0;
+        let err = session_2
+            .with_retry(RetryPolicy::new(3), |session| {
+                attempts += 1;
+                session.execute("UPDATE t SET v = v / 0;")
+            })
+            .unwrap_err();
+        assert_eq!(err.retryability(), Retryability::Fatal);
+        assert_eq!(attempts, 1);
+        assert!(!session_2.in_transaction());
+
+        // 次数用尽时返回最后一次的错误
+        let mut attempts = 0;
+        let err = session_2
+            .with_retry(RetryPolicy::new(2), |session| {
+                attempts += 1;
+                session.execute("INSERT INTO t VALUES (1, 0);")
+            })
+            .unwrap_err();
+        assert_eq!(err.retryability(), Retryability::RetryableAfterReread);
+        assert_eq!(attempts, 2);
+
+        session_2.execute("BEGIN;")?;
+        assert_eq!(
+            session_2.with_retry(RetryPolicy::new(2), |_| Ok(())),
+            Err(AlreadyStarted.into())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_retry() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session_1 = db.session();
+        let mut session_2 = db.session();
+        session_1.execute("CREATE TABLE t (id INT PRIMARY KEY, v INT);")?;
+        session_1.execute("INSERT INTO t VALUES (1, 1);")?;
+
+        // 没有开启自动重试时直接返回写冲突
+        session_1.execute("BEGIN;")?;
+        session_1.execute("UPDATE t SET v = 2 WHERE id = 1;")?;
+        let err = session_2
+            .execute("UPDATE t SET v = v + 1 WHERE id = 1;")
+            .unwrap_err();
+        assert!(err.is_retryable());
+
+        // 开启自动重试后，session_1 的事务在另一个线程中提交，冲突被重试吸收
+        session_2.set_auto_retry(Some(
+            RetryPolicy::new(100).backoff(Duration::from_millis(1)),
+        ));
+        thread::scope(|scope| -> Result<()> {
+            scope.spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                session_1.execute("COMMIT;").unwrap();
+            });
+            session_2.execute("UPDATE t SET v = v + 1 WHERE id = 1;")?;
+            Ok(())
+        })?;
+        let result = session_2.execute("SELECT v FROM t WHERE id = 1;")?;
+        assert_eq!(result.get::<i64>(0, "v")?, 3);
+
+        // 不能原样重试的错误不会被重试
+        let err = session_2
+            .execute("INSERT INTO t VALUES (1, 0);")
+            .unwrap_err();
+        assert_eq!(err.retryability(), Retryability::RetryableAfterReread);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_script() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        let mut session = db.session();
+        let position = |err: Error| match err {
+            Error::Script { position, source } => (
+                position.statement_index,
+                position.line,
+                position.column,
+                position.snippet,
+                source.code(),
+            ),
+            err => panic!("expected a script error, got {err:?}"),
+        };
+
+        let results = session.execute_script(
+            "CREATE TABLE t (id INT PRIMARY KEY, v VARCHAR);\n\
+             INSERT INTO t VALUES (1, 'a;b'), (2, 'c');\n\
+             SELECT v FROM t ORDER BY id;\n",
+        )?;
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1], ResultSet::modified(2));
+        assert_eq!(results[2].get::<String>(0, "v")?, "a;b");
+
+        // 第一条语句解析失败，不执行任何语句
+        let err = session
+            .execute_script("SELEC 1;\nINSERT INTO t VALUES (3, 'd');")
+            .unwrap_err();
+        assert_eq!(
+            position(err),
+            (0, 1, 1, "SELEC 1;\n^".to_string(), ErrorCode::SyntaxError)
+        );
+        assert_eq!(
+            ids(&mut session)?,
+            vec![Value::Integer(1), Value::Integer(2)]
+        );
+
+        // 中间的语句解析失败，位置是出错的 token 在整个脚本中的行列号
+        let err = session
+            .execute_script(
+                "INSERT INTO t VALUES (3, 'd');\n  SELECT * FROM t WHERE id = = 1;\nSELECT * FROM t;",
+            )
+            .unwrap_err();
+        assert_eq!(
+            position(err),
+            (
+                1,
+                2,
+                30,
+                format!("  SELECT * FROM t WHERE id = = 1;\n{}^", " ".repeat(29)),
+                ErrorCode::SyntaxError
+            )
+        );
+
+        // 之前的语句包含跨行的字符串，行号按照整个脚本计算
+        let err = session
+            .execute_script(
+                "INSERT INTO t VALUES (3, 'line 1\nline 2;\nline 3');\nSELECT * FROM t LIMIT;",
+            )
+            .unwrap_err();
+        assert_eq!(
+            position(err),
+            (
+                1,
+                4,
+                22,
+                format!("SELECT * FROM t LIMIT;\n{}^", " ".repeat(21)),
+                ErrorCode::SyntaxError
+            )
+        );
+
+        // 最后一条语句执行失败，之前的语句已经提交，位置指向语句的开头
+        let err = session
+            .execute_script(
+                "INSERT INTO t VALUES (3, 'x\ny');\nINSERT INTO t VALUES (4, 'z'); INSERT INTO t VALUES (1, 'dup');",
+            )
+            .unwrap_err();
+        assert_eq!(
+            position(err),
+            (
+                2,
+                3,
+                32,
+                format!(
+                    "INSERT INTO t VALUES (4, 'z'); INSERT INTO t VALUES (1, 'dup');\n{}^",
+                    " ".repeat(31)
+                ),
+                ErrorCode::UniqueViolation
+            )
+        );
+        assert_eq!(
+            ids(&mut session)?,
+            (1..=4).map(Value::Integer).collect::<Vec<_>>()
+        );
+
+        // 最后一条语句缺少分号，位置指向脚本的结尾
+        let err = session
+            .execute_script("SELECT * FROM t;\nSELECT * FROM t")
+            .unwrap_err();
+        assert_eq!(
+            position(err),
+            (
+                1,
+                2,
+                16,
+                format!("SELECT * FROM t\n{}^", " ".repeat(15)),
+                ErrorCode::SyntaxError
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_restore() -> Result<()> {
+        let db = Database::open(Engine::new(MemoryStorage::new()));
+        db.session().execute_script(
+            "CREATE TABLE users (
+                 id INT PRIMARY KEY,
+                 name VARCHAR,
+                 bio TEXT NULL,
+                 score FLOAT DEFAULT 1.5,
+                 active BOOL NULL DEFAULT TRUE,
+                 profile JSON NULL
+             );
+             CREATE TABLE posts (
+                 id INT PRIMARY KEY AUTO_INCREMENT,
+                 author INT REFERENCES users ON DELETE CASCADE,
+                 parent INT NULL REFERENCES posts,
+                 title STRING DEFAULT 'it''s new'
+             );
+             CREATE UNIQUE INDEX idx_users_name ON users (name);
+             CREATE INDEX idx_posts_author ON posts (author, title) WHERE parent IS NULL;
+             CREATE TABLE empty (id STRING PRIMARY KEY);
+             INSERT INTO users VALUES
+                 (1, 'O''Brien', 'line 1
+line 2; ''quoted''', -0.125, NULL, '{\"tags\": [\"a''b\", null]}'),
+                 (2, 'Bob', NULL, 100000000000000000000.0, FALSE, NULL),
+                 (3, '', '', 1.5, TRUE, '\"\"');
+             INSERT INTO posts VALUES (1, 1, NULL, 'first'), (2, 2, NULL, 'second');
+             INSERT INTO posts VALUES (4, 1, 5, 'cycle'), (5, 2, 4, 'cycle');
+             INSERT INTO posts VALUES (3, 2, 1, 'reply');
+             INSERT INTO posts VALUES (6, 3, 6, 'self');",
+        )?;
+
+        let mut script = Vec::new();
+        db.dump(&mut script)?;
+        let restored = Database::open(Engine::new(MemoryStorage::new()));
+        restored.restore(script.as_slice())?;
+
+        // 表定义和所有行都相同
+        let (txn, restored_txn) = (db.engine.start_txn()?, restored.engine.start_txn()?);
+        let (tables, restored_tables) = (txn.list_tables()?, restored_txn.list_tables()?);
+        assert_eq!(tables.len(), 3);
+        assert_eq!(restored_tables.len(), tables.len());
+        for (table, restored_table) in tables.iter().zip(&restored_tables) {
+            assert_eq!(restored_table.name, table.name);
+            assert_eq!(restored_table.columns, table.columns);
+            assert_eq!(restored_table.indexes, table.indexes);
+            assert_eq!(restored_table.foreign_keys, table.foreign_keys);
+            assert_eq!(restored_table.schema_version(), table.schema_version());
+
+            let sql = format!("SELECT * FROM {} ORDER BY id;", table.name);
+            let rows = db.session().execute(&sql)?;
+            assert_eq!(restored.session().execute(&sql)?, rows);
+        }
+        txn.rollback()?;
+        restored_txn.rollback()?;
+        let bio = restored
+            .session()
+            .execute("SELECT bio FROM users WHERE id = 1;")?;
+        assert_eq!(bio.get::<String>(0, "bio")?, "line 1\nline 2; 'quoted'");
+
+        // 恢复的索引和外键仍然生效
+        let mut session = restored.session();
+        let e = session
+            .execute("INSERT INTO users VALUES (4, 'Bob', NULL, 0.0, NULL, NULL);")
+            .unwrap_err();
+        assert_eq!(e.code(), ErrorCode::UniqueViolation);
+        let e = session
+            .execute("INSERT INTO posts VALUES (7, 99, NULL, 'orphan');")
+            .unwrap_err();
+        assert_eq!(e.code(), ErrorCode::ForeignKeyViolation);
+
+        // 恢复时显式插入的 ID 推进了自增计数器
+        session.execute("INSERT INTO posts (author, parent, title) VALUES (1, NULL, 'new');")?;
+        let id = session.execute("SELECT id FROM posts WHERE title = 'new';")?;
+        assert_eq!(id.get::<i64>(0, "id")?, 7);
+        drop(session);
+
+        // 脚本中的语句失败时整个脚本回滚
+        let target = Database::open(Engine::new(MemoryStorage::new()));
+        target
+            .session()
+            .execute("CREATE TABLE posts (id INT PRIMARY KEY);")?;
+        assert!(target.restore(script.as_slice()).is_err());
+        assert!(target.session().execute("SELECT * FROM users;").is_err());
+
+        // NaN 没有对应的字面量，导出失败
+        db.session()
+            .execute("CREATE TABLE floats (id INT PRIMARY KEY, f FLOAT NULL);")?;
+        let txn = db.engine.start_txn()?;
+        txn.create_row("floats", &vec![Value::Integer(1), Value::Float(f64::NAN)])?;
+        txn.commit()?;
+        let e = db.dump(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.code(), ErrorCode::InvalidCast);
+        Ok(())
+    }
+}
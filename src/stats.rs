@@ -0,0 +1,320 @@
+use std::{
+    cmp::Ordering,
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Bound,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    planner::ValueRange,
+    schema::{Row, Table, Value},
+};
+
+/// 没有统计信息可用时条件的选择率，假设每个条件保留三分之一的行
+pub(crate) const DEFAULT_SELECTIVITY: f64 = 1.0 / 3.0;
+
+/// HyperLogLog 寄存器个数的以 2 为底的对数，标准误差约为 1.04 / sqrt(2^10) ≈ 3%
+const HLL_PRECISION: u32 = 10;
+
+/// 表的统计信息，由 `ANALYZE` 扫描表得到，和表信息一样在事务中读写
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableStats {
+    /// 表的行数
+    pub row_count: usize,
+    /// 每一列的统计信息，和表的列一一对应
+    pub columns: Vec<ColumnStats>,
+}
+
+/// 列的统计信息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnStats {
+    /// 不为 NULL 的不同值的个数，由 HyperLogLog 估计
+    pub distinct: usize,
+    /// 不为 NULL 的最小值，所有值都是 NULL 时为 NULL
+    pub min: Value,
+    /// 不为 NULL 的最大值，所有值都是 NULL 时为 NULL
+    pub max: Value,
+    /// NULL 占所有行的比例
+    pub null_fraction: f64,
+}
+
+impl ColumnStats {
+    /// 列等于 `value` 的行的比例，假设不为 NULL 的值均匀分布在不同值上
+    pub fn equal_selectivity(&self, value: &Value) -> f64 {
+        if self.min == Value::Null
+            || value.total_cmp(&self.min) == Ordering::Less
+            || value.total_cmp(&self.max) == Ordering::Greater
+        {
+            return 0.0;
+        }
+        (1.0 - self.null_fraction) / self.distinct.max(1) as f64
+    }
+
+    /// 列在范围 `range` 内的行的比例
+    ///
+    /// 数值列假设值在最小值和最大值之间均匀分布，按照范围和 `[min, max]` 重叠的长度估计；
+    /// 其他类型的列只能判断范围是否和 `[min, max]` 重叠，重叠时使用 [`DEFAULT_SELECTIVITY`]。
+    pub fn range_selectivity(&self, range: &ValueRange) -> f64 {
+        if let (Bound::Included(lower), Bound::Included(upper)) = range {
+            if lower == upper {
+                return self.equal_selectivity(lower);
+            }
+        }
+        if self.min == Value::Null {
+            return 0.0;
+        }
+        let below_min = |bound: &Bound<Value>| match bound {
+            Bound::Included(v) => v.total_cmp(&self.min) == Ordering::Less,
+            Bound::Excluded(v) => v.total_cmp(&self.min) != Ordering::Greater,
+            Bound::Unbounded => false,
+        };
+        let above_max = |bound: &Bound<Value>| match bound {
+            Bound::Included(v) => v.total_cmp(&self.max) == Ordering::Greater,
+            Bound::Excluded(v) => v.total_cmp(&self.max) != Ordering::Less,
+            Bound::Unbounded => false,
+        };
+        if below_min(&range.1) || above_max(&range.0) {
+            return 0.0;
+        }
+
+        let as_number = |value: &Value| match value {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Float(f) if f.is_finite() => Some(*f),
+            _ => None,
+        };
+        let fraction = match (as_number(&self.min), as_number(&self.max)) {
+            (Some(min), Some(max)) if max > min => {
+                let bound_value = |bound: &Bound<Value>, default: f64| match bound {
+                    Bound::Included(v) | Bound::Excluded(v) => as_number(v),
+                    Bound::Unbounded => Some(default),
+                };
+                match (bound_value(&range.0, min), bound_value(&range.1, max)) {
+                    (Some(lower), Some(upper)) => {
+                        ((upper.min(max) - lower.max(min)) / (max - min)).clamp(0.0, 1.0)
+                    }
+                    _ => DEFAULT_SELECTIVITY,
+                }
+            }
+            // 所有值都相同，且没有被上面排除
+            (Some(_), Some(_)) => 1.0,
+            _ => DEFAULT_SELECTIVITY,
+        };
+        fraction * (1.0 - self.null_fraction)
+    }
+}
+
+/// 逐行收集表的统计信息
+pub struct StatsCollector {
+    row_count: usize,
+    columns: Vec<ColumnCollector>,
+}
+
+/// 单列统计信息的收集状态
+struct ColumnCollector {
+    sketch: HyperLogLog,
+    nulls: usize,
+    min: Option<Value>,
+    max: Option<Value>,
+}
+
+impl StatsCollector {
+    /// 为表 `table` 创建收集器
+    pub fn new(table: &Table) -> Self {
+        Self {
+            row_count: 0,
+            columns: table
+                .columns
+                .iter()
+                .map(|_| ColumnCollector {
+                    sketch: HyperLogLog::new(),
+                    nulls: 0,
+                    min: None,
+                    max: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// 加入一行
+    pub fn add(&mut self, row: &Row) {
+        self.row_count += 1;
+        for (column, value) in self.columns.iter_mut().zip(row) {
+            if *value == Value::Null {
+                column.nulls += 1;
+                continue;
+            }
+            column.sketch.add(value);
+            if column
+                .min
+                .as_ref()
+                .is_none_or(|min| value.total_cmp(min) == Ordering::Less)
+            {
+                column.min = Some(value.clone());
+            }
+            if column
+                .max
+                .as_ref()
+                .is_none_or(|max| value.total_cmp(max) == Ordering::Greater)
+            {
+                column.max = Some(value.clone());
+            }
+        }
+    }
+
+    /// 结束收集，得到统计信息
+    pub fn finish(self) -> TableStats {
+        let row_count = self.row_count;
+        let columns = self
+            .columns
+            .into_iter()
+            .map(|column| ColumnStats {
+                // 不同值的个数不会超过不为 NULL 的行数
+                distinct: column.sketch.estimate().min(row_count - column.nulls),
+                min: column.min.unwrap_or(Value::Null),
+                max: column.max.unwrap_or(Value::Null),
+                null_fraction: match row_count {
+                    0 => 0.0,
+                    _ => column.nulls as f64 / row_count as f64,
+                },
+            })
+            .collect();
+        TableStats { row_count, columns }
+    }
+}
+
+/// 估计不同值个数的 HyperLogLog
+///
+/// 值的哈希的前 [`HLL_PRECISION`] 位选择寄存器，寄存器记录其余位中第一个 1 出现的最大位置，
+/// 不同值越多，这个位置越靠后。哈希使用固定的密钥，因此相同的数据得到相同的估计。
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << HLL_PRECISION],
+        }
+    }
+
+    fn add(&mut self, value: &Value) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let idx = (hash >> (64 - HLL_PRECISION)) as usize;
+        // 最低位之后补 1，保证剩余位全为 0 时位置也有上限
+        let rank = ((hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1))).leading_zeros() + 1;
+        self.registers[idx] = self.registers[idx].max(rank as u8);
+    }
+
+    fn estimate(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum = self
+            .registers
+            .iter()
+            .map(|rank| 2f64.powi(-(*rank as i32)))
+            .sum::<f64>();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|rank| **rank == 0).count();
+        // 基数较小时使用线性计数修正
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{Column, DataType};
+
+    #[test]
+    fn test_collect_stats() -> crate::Result<()> {
+        let column = |name: &str, data_type, primary_key: bool| Column {
+            name: name.to_string(),
+            data_type,
+            nullable: !primary_key,
+            default: None,
+            primary_key,
+            auto_increment: false,
+        };
+        let table = Table::new(
+            "t",
+            vec![
+                column("id", DataType::Integer, true),
+                column("category", DataType::String, false),
+                column("score", DataType::Float, false),
+            ],
+        )?;
+        let mut collector = StatsCollector::new(&table);
+        for id in 0..10000 {
+            collector.add(&vec![
+                Value::Integer(id),
+                Value::String(format!("c{}", id % 100)),
+                match id % 4 {
+                    0 => Value::Null,
+                    _ => Value::Float(id as f64 / 10.0),
+                },
+            ]);
+        }
+        let stats = collector.finish();
+        assert_eq!(stats.row_count, 10000);
+
+        // 不同值个数的估计误差在 10% 以内
+        let [id, category, score] = &stats.columns[..] else {
+            unreachable!()
+        };
+        assert!(id.distinct.abs_diff(10000) < 1000, "{}", id.distinct);
+        assert!(
+            category.distinct.abs_diff(100) < 10,
+            "{}",
+            category.distinct
+        );
+        assert!(score.distinct.abs_diff(7500) < 750, "{}", score.distinct);
+        assert_eq!(
+            (&id.min, &id.max),
+            (&Value::Integer(0), &Value::Integer(9999))
+        );
+        assert_eq!(category.min, Value::String("c0".to_string()));
+        assert_eq!((id.null_fraction, score.null_fraction), (0.0, 0.25));
+
+        // 选择率
+        let range = |lower, upper| (lower, upper);
+        let int = Value::Integer;
+        assert!((id.equal_selectivity(&int(5)) - 1e-4).abs() < 1e-5);
+        assert_eq!(id.equal_selectivity(&int(-1)), 0.0);
+        let selectivity =
+            id.range_selectivity(&range(Bound::Included(int(1000)), Bound::Unbounded));
+        assert!((selectivity - 0.9).abs() < 0.01);
+        assert_eq!(
+            id.range_selectivity(&range(Bound::Excluded(int(9999)), Bound::Unbounded)),
+            0.0
+        );
+        let selectivity = score.range_selectivity(&range(
+            Bound::Unbounded,
+            Bound::Excluded(Value::Float(500.0)),
+        ));
+        assert!((selectivity - 0.375).abs() < 0.01);
+        assert_eq!(
+            category.range_selectivity(&range(
+                Bound::Included(Value::String("d".to_string())),
+                Bound::Unbounded
+            )),
+            0.0
+        );
+
+        // 空表
+        let stats = StatsCollector::new(&table).finish();
+        assert_eq!(stats.columns[0].distinct, 0);
+        assert_eq!(
+            stats.columns[0].range_selectivity(&range(Bound::Unbounded, Bound::Unbounded)),
+            0.0
+        );
+
+        Ok(())
+    }
+}
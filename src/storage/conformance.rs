@@ -0,0 +1,353 @@
+//! 存储引擎的一致性检查
+//!
+//! [`Mvcc`](super::Mvcc) 依赖 [`Storage`] 的有序扫描、反向扫描和前缀扫描等语义，自定义的存储引擎可以通过
+//! [`run_storage_conformance`] 检查是否满足这些语义。
+
+use std::ops::Bound;
+
+use super::Storage;
+
+type Pairs = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// 对 `make` 创建的存储引擎执行 [`Storage`] 接口的一致性检查，违反约定时 panic 并给出违反的约定
+///
+/// 每一项检查都会调用 `make` 创建新的存储引擎，因此 `make` 每次都需要返回空的存储引擎。
+///
+/// # Example
+/// ```
+/// use sqldb::storage::{conformance::run_storage_conformance, MemoryStorage};
+///
+/// run_storage_conformance(MemoryStorage::new);
+/// ```
+pub fn run_storage_conformance<S: Storage>(make: impl Fn() -> S) {
+    check_get_put(make());
+    check_overwrite(make());
+    check_delete(make());
+    check_ordered_scan(make());
+    check_reverse_scan(make());
+    check_scan_bounds(make());
+    check_empty_range(make());
+    check_prefix_scan(make());
+}
+
+/// 将迭代器中的 key-value 全部取出，迭代器返回错误时 panic
+fn collect(check: &str, iter: impl Iterator<Item = crate::Result<(Vec<u8>, Vec<u8>)>>) -> Pairs {
+    iter.map(|item| item.unwrap_or_else(|err| panic!("{check}: scan failed: {err}")))
+        .collect()
+}
+
+fn put<S: Storage>(check: &str, storage: &mut S, key: &[u8], value: &[u8]) {
+    storage
+        .put(key, value)
+        .unwrap_or_else(|err| panic!("{check}: put {key:?} failed: {err}"));
+}
+
+fn get<S: Storage>(check: &str, storage: &mut S, key: &[u8]) -> Option<Vec<u8>> {
+    storage
+        .get(key)
+        .unwrap_or_else(|err| panic!("{check}: get {key:?} failed: {err}"))
+}
+
+fn delete<S: Storage>(check: &str, storage: &mut S, key: &[u8]) {
+    storage
+        .delete(key)
+        .unwrap_or_else(|err| panic!("{check}: delete {key:?} failed: {err}"));
+}
+
+/// 写入 `keys`，value 为 key 加上后缀 `-v`
+fn put_keys<S: Storage>(check: &str, storage: &mut S, keys: &[&[u8]]) {
+    for key in keys {
+        put(check, storage, key, &value_of(key));
+    }
+}
+
+fn value_of(key: &[u8]) -> Vec<u8> {
+    [key, b"-v"].concat()
+}
+
+fn pairs(keys: &[&[u8]]) -> Pairs {
+    keys.iter()
+        .map(|key| (key.to_vec(), value_of(key)))
+        .collect()
+}
+
+/// 读取写入的值，包括空值和任意字节，不存在的 key 返回 `None`
+fn check_get_put<S: Storage>(mut storage: S) {
+    const CHECK: &str = "get/put";
+    assert_eq!(
+        get(CHECK, &mut storage, b"missing"),
+        None,
+        "{CHECK}: get on an empty storage must return None"
+    );
+
+    let binary: Vec<u8> = (0..=u8::MAX).collect();
+    let large = vec![0xab; 64 * 1024];
+    put(CHECK, &mut storage, b"empty", b"");
+    put(CHECK, &mut storage, b"binary", &binary);
+    put(CHECK, &mut storage, b"large", &large);
+    put(CHECK, &mut storage, &binary, b"binary key");
+
+    assert_eq!(
+        get(CHECK, &mut storage, b"empty"),
+        Some(Vec::new()),
+        "{CHECK}: an empty value must be stored, not treated as missing"
+    );
+    assert_eq!(
+        get(CHECK, &mut storage, b"binary"),
+        Some(binary.clone()),
+        "{CHECK}: binary values must round-trip unchanged"
+    );
+    assert_eq!(
+        get(CHECK, &mut storage, b"large"),
+        Some(large),
+        "{CHECK}: large values must round-trip unchanged"
+    );
+    assert_eq!(
+        get(CHECK, &mut storage, &binary),
+        Some(b"binary key".to_vec()),
+        "{CHECK}: binary keys must round-trip unchanged"
+    );
+    assert_eq!(
+        get(CHECK, &mut storage, b"missing"),
+        None,
+        "{CHECK}: get on a key never written must return None"
+    );
+}
+
+/// 重复写入同一个 key 时保留最后一次写入的值，扫描时只出现一次
+fn check_overwrite<S: Storage>(mut storage: S) {
+    const CHECK: &str = "overwrite";
+    put(CHECK, &mut storage, b"k", b"old value");
+    put(CHECK, &mut storage, b"k", b"new");
+    assert_eq!(
+        get(CHECK, &mut storage, b"k"),
+        Some(b"new".to_vec()),
+        "{CHECK}: get must return the last value written"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan(..)),
+        vec![(b"k".to_vec(), b"new".to_vec())],
+        "{CHECK}: an overwritten key must appear exactly once with its last value"
+    );
+}
+
+/// 删除后读取不到 key，扫描时跳过被删除的 key，删除不存在的 key 不报错，删除后可以再次写入
+fn check_delete<S: Storage>(mut storage: S) {
+    const CHECK: &str = "delete";
+    delete(CHECK, &mut storage, b"missing");
+    put_keys(CHECK, &mut storage, &[b"a", b"b", b"c"]);
+
+    delete(CHECK, &mut storage, b"b");
+    assert_eq!(
+        get(CHECK, &mut storage, b"b"),
+        None,
+        "{CHECK}: get must return None after delete"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan(..)),
+        pairs(&[b"a", b"c"]),
+        "{CHECK}: scan must skip deleted keys"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan(..).rev()),
+        pairs(&[b"c", b"a"]),
+        "{CHECK}: reverse scan must skip deleted keys"
+    );
+
+    delete(CHECK, &mut storage, b"b");
+    put(CHECK, &mut storage, b"b", b"again");
+    assert_eq!(
+        get(CHECK, &mut storage, b"b"),
+        Some(b"again".to_vec()),
+        "{CHECK}: a deleted key must be writable again"
+    );
+}
+
+/// 按照 key 的字节序升序扫描，与写入顺序无关
+fn check_ordered_scan<S: Storage>(mut storage: S) {
+    const CHECK: &str = "ordered scan";
+    put_keys(
+        CHECK,
+        &mut storage,
+        &[b"b", b"a\xff", b"", b"ab", b"a", b"\x00", b"b\x00"],
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan(..)),
+        pairs(&[b"", b"\x00", b"a", b"ab", b"a\xff", b"b", b"b\x00"]),
+        "{CHECK}: keys must be returned in ascending byte order"
+    );
+}
+
+/// 反向扫描按照降序返回，并且可以和正向迭代交替进行，两端相遇后结束
+fn check_reverse_scan<S: Storage>(mut storage: S) {
+    const CHECK: &str = "reverse scan";
+    put_keys(CHECK, &mut storage, &[b"c", b"a", b"d", b"b"]);
+    assert_eq!(
+        collect(CHECK, storage.scan(..).rev()),
+        pairs(&[b"d", b"c", b"b", b"a"]),
+        "{CHECK}: keys must be returned in descending byte order"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan(b"b".to_vec()..).rev()),
+        pairs(&[b"d", b"c", b"b"]),
+        "{CHECK}: reverse scan must respect the start bound"
+    );
+
+    let mut iter = storage.scan(..);
+    let mut mixed = Vec::new();
+    while let (Some(front), back) = (iter.next(), iter.next_back()) {
+        mixed.push(front);
+        mixed.extend(back);
+    }
+    drop(iter);
+    assert_eq!(
+        collect(CHECK, mixed.into_iter()),
+        pairs(&[b"a", b"d", b"b", b"c"]),
+        "{CHECK}: alternating next and next_back must yield each key exactly once"
+    );
+}
+
+/// 扫描的边界：包含的边界返回对应的 key，不包含的边界跳过对应的 key，边界不必是已有的 key
+fn check_scan_bounds<S: Storage>(mut storage: S) {
+    const CHECK: &str = "scan bounds";
+    put_keys(CHECK, &mut storage, &[b"a", b"b", b"c", b"d"]);
+    assert_eq!(
+        collect(CHECK, storage.scan(b"b".to_vec()..b"d".to_vec())),
+        pairs(&[b"b", b"c"]),
+        "{CHECK}: `start..end` must include start and exclude end"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan(b"b".to_vec()..=b"d".to_vec())),
+        pairs(&[b"b", b"c", b"d"]),
+        "{CHECK}: `start..=end` must include end"
+    );
+    assert_eq!(
+        collect(
+            CHECK,
+            storage.scan((Bound::Excluded(b"b".to_vec()), Bound::Unbounded))
+        ),
+        pairs(&[b"c", b"d"]),
+        "{CHECK}: an excluded start bound must skip the start key"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan(..b"c".to_vec())),
+        pairs(&[b"a", b"b"]),
+        "{CHECK}: `..end` must start from the smallest key"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan(b"a\x00".to_vec()..b"c\x00".to_vec())),
+        pairs(&[b"b", b"c"]),
+        "{CHECK}: bounds that are not stored keys must still filter by byte order"
+    );
+}
+
+/// 空的存储和不包含任何 key 的范围返回空的迭代器
+fn check_empty_range<S: Storage>(mut storage: S) {
+    const CHECK: &str = "empty range";
+    assert_eq!(
+        collect(CHECK, storage.scan(..)),
+        Vec::new(),
+        "{CHECK}: scanning an empty storage must yield nothing"
+    );
+    assert!(
+        storage.scan(..).next_back().is_none(),
+        "{CHECK}: reverse scanning an empty storage must yield nothing"
+    );
+
+    put_keys(CHECK, &mut storage, &[b"a", b"c"]);
+    assert_eq!(
+        collect(CHECK, storage.scan(b"b".to_vec()..b"c".to_vec())),
+        Vec::new(),
+        "{CHECK}: a range between stored keys must yield nothing"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan(b"a".to_vec()..b"a".to_vec())),
+        Vec::new(),
+        "{CHECK}: `k..k` must yield nothing even if k is stored"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan(b"d".to_vec()..)),
+        Vec::new(),
+        "{CHECK}: a range after the largest key must yield nothing"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan_prefix(b"b")),
+        Vec::new(),
+        "{CHECK}: a prefix matching no key must yield nothing"
+    );
+}
+
+/// 前缀扫描返回且只返回以前缀开头的 key，包括前缀本身
+fn check_prefix_scan<S: Storage>(mut storage: S) {
+    const CHECK: &str = "prefix scan";
+    put_keys(
+        CHECK,
+        &mut storage,
+        &[
+            b"ab",
+            b"abc",
+            b"abc\x00",
+            b"abcd",
+            b"abc\xff",
+            b"abc\xff\xff",
+            b"abd",
+            b"abb\xff",
+            b"b",
+        ],
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan_prefix(b"abc")),
+        pairs(&[b"abc", b"abc\x00", b"abcd", b"abc\xff", b"abc\xff\xff"]),
+        "{CHECK}: must yield exactly the keys starting with the prefix, in order"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan_prefix(b"abc").rev()),
+        pairs(&[b"abc\xff\xff", b"abc\xff", b"abcd", b"abc\x00", b"abc"]),
+        "{CHECK}: reverse prefix scan must yield the same keys in descending order"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan_prefix(b"a")),
+        pairs(&[
+            b"ab",
+            b"abb\xff",
+            b"abc",
+            b"abc\x00",
+            b"abcd",
+            b"abc\xff",
+            b"abc\xff\xff",
+            b"abd"
+        ]),
+        "{CHECK}: a shorter prefix must include every longer match"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan_prefix(b"abc\xff")),
+        pairs(&[b"abc\xff", b"abc\xff\xff"]),
+        "{CHECK}: a prefix ending in 0xFF must still be bounded correctly"
+    );
+    assert_eq!(
+        collect(CHECK, storage.scan_prefix(b"")).len(),
+        9,
+        "{CHECK}: an empty prefix must yield every key"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{DiskStorage, MemoryStorage};
+
+    #[test]
+    fn test_memory_storage_conformance() {
+        run_storage_conformance(MemoryStorage::new);
+    }
+
+    #[test]
+    fn test_disk_storage_conformance() {
+        let dir = tempfile::tempdir().unwrap();
+        let count = std::cell::Cell::new(0);
+        run_storage_conformance(|| {
+            count.set(count.get() + 1);
+            DiskStorage::new(dir.path().join(format!("{}.log", count.get()))).unwrap()
+        });
+    }
+}
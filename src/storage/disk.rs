@@ -8,7 +8,7 @@ use std::{
 };
 
 use super::Storage;
-use crate::Result;
+use crate::{error::StorageError::Decode, Result};
 
 /// 基于 Bitcast 的磁盘存储，参考论文 [Bitcask: A Log-Structured Hash Table for Key/Value Data](https://riak.com/assets/bitcask-intro.pdf)。
 ///
@@ -99,6 +99,17 @@ impl DiskStorage {
             file_reader.read_exact(&mut len_buf)?;
             let val_len = u64::from_le_bytes(len_buf);
 
+            // 长度超过文件剩余的大小说明日志已经损坏，不能按照这个长度分配内存
+            let remaining = file_sz.saturating_sub(offset + u64::BITS as u64 / 8 * 2);
+            let entry_len = key_len.checked_add(val_len & !(1 << (u64::BITS - 1)));
+            if entry_len.is_none_or(|len| len > remaining) {
+                return Err(Decode {
+                    context: "reading log entry header",
+                    bytes: [key_len.to_le_bytes(), val_len.to_le_bytes()].concat(),
+                }
+                .into());
+            }
+
             // 读取 key
             let mut key = vec![0u8; key_len as usize];
             file_reader.read_exact(&mut key)?;
@@ -281,6 +292,17 @@ mod tests {
         assert_eq!(*storage.keydir.get(&b"key3"[..]).unwrap(), (72, 6));
     }
 
+    #[test]
+    fn test_corrupt_entry_length() {
+        // key 的长度超过了文件剩余的大小，返回错误而不是按照这个长度分配内存
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            b"\xff\xff\xff\xff\xff\xff\xff\x7f\x06\x00\x00\x00\x00\x00\x00\x00key1value1",
+        )
+        .unwrap();
+        assert!(DiskStorage::new(file.path()).is_err());
+    }
+
     #[test]
     fn test_compact() {
         let mut file = NamedTempFile::new().unwrap();
@@ -0,0 +1,160 @@
+use std::ops::RangeBounds;
+
+use super::Storage;
+use crate::Result;
+
+/// 注入的故障
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// 每次调用都 panic，调用方持有锁时会使锁中毒
+    Panic,
+    /// 读取和扫描返回的 value 被替换为无法解码的字节
+    CorruptValues,
+    /// 扫描返回的 key 的第一个字节被替换，无法按照原来的类型解码
+    CorruptKeys,
+}
+
+/// 损坏的 value，也用于替换 key 的第一个字节
+const CORRUPT_BYTE: u8 = 0xFF;
+
+/// 可以注入故障的存储引擎
+///
+/// 没有设置故障时将调用原样转发给内部的存储引擎 `S`，设置故障后按照 [`Fault`] 修改返回的数据或者直接 panic，
+/// 用于在测试中检查上层如何对存储的故障分类。写入的数据不受故障影响，清除故障后可以正常读取。
+pub struct FaultyStorage<S: Storage> {
+    inner: S,
+    fault: Option<Fault>,
+}
+
+impl<S: Storage> FaultyStorage<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner, fault: None }
+    }
+
+    /// 设置之后的调用注入的故障，`None` 表示不注入故障
+    pub fn set_fault(&mut self, fault: Option<Fault>) {
+        self.fault = fault;
+    }
+
+    /// 返回内部的存储引擎
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn check_panic(&self) {
+        if self.fault == Some(Fault::Panic) {
+            panic!("injected storage fault");
+        }
+    }
+}
+
+impl<S: Storage> Storage for FaultyStorage<S> {
+    type Iterator<'a>
+        = FaultyIter<S::Iterator<'a>>
+    where
+        Self: 'a;
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.check_panic();
+        let value = self.inner.get(key)?;
+        Ok(match self.fault {
+            Some(Fault::CorruptValues) => value.map(|_| vec![CORRUPT_BYTE]),
+            _ => value,
+        })
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.check_panic();
+        self.inner.put(key, value)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.check_panic();
+        self.inner.delete(key)
+    }
+
+    fn scan<R>(&mut self, range: R) -> Self::Iterator<'_>
+    where
+        R: RangeBounds<Vec<u8>>,
+    {
+        self.check_panic();
+        FaultyIter {
+            inner: self.inner.scan(range),
+            fault: self.fault,
+        }
+    }
+}
+
+/// [`FaultyStorage`] 的扫描结果，按照创建时的故障修改每一项
+pub struct FaultyIter<I> {
+    inner: I,
+    fault: Option<Fault>,
+}
+
+impl<I> FaultyIter<I> {
+    fn corrupt(&self, item: Result<(Vec<u8>, Vec<u8>)>) -> Result<(Vec<u8>, Vec<u8>)> {
+        let (mut key, value) = item?;
+        match self.fault {
+            Some(Fault::CorruptValues) => Ok((key, vec![CORRUPT_BYTE])),
+            Some(Fault::CorruptKeys) => {
+                if let Some(first) = key.first_mut() {
+                    *first = CORRUPT_BYTE;
+                }
+                Ok((key, value))
+            }
+            _ => Ok((key, value)),
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<(Vec<u8>, Vec<u8>)>>> Iterator for FaultyIter<I> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next()?;
+        Some(self.corrupt(item))
+    }
+}
+
+impl<I: DoubleEndedIterator<Item = Result<(Vec<u8>, Vec<u8>)>>> DoubleEndedIterator
+    for FaultyIter<I>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next_back()?;
+        Some(self.corrupt(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_faulty_storage() -> Result<()> {
+        let mut storage = FaultyStorage::new(MemoryStorage::new());
+        storage.put(b"a", b"1")?;
+        assert_eq!(storage.get(b"a")?, Some(b"1".to_vec()));
+
+        storage.set_fault(Some(Fault::CorruptValues));
+        assert_eq!(storage.get(b"a")?, Some(vec![CORRUPT_BYTE]));
+        assert_eq!(storage.get(b"b")?, None);
+        storage.set_fault(Some(Fault::CorruptKeys));
+        assert_eq!(
+            storage.scan_prefix(b"").rev().collect::<Result<Vec<_>>>()?,
+            vec![(vec![CORRUPT_BYTE], b"1".to_vec())]
+        );
+
+        storage.set_fault(Some(Fault::Panic));
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = storage.get(b"a");
+        }))
+        .is_err());
+
+        // 故障不影响已经写入的数据
+        storage.set_fault(None);
+        assert_eq!(storage.into_inner().get(b"a")?, Some(b"1".to_vec()));
+
+        Ok(())
+    }
+}
@@ -1,15 +1,21 @@
-use std::ops::RangeBounds;
+use std::ops::{Bound, RangeBounds};
 
 use crate::Result;
 
+pub mod conformance;
 mod disk;
+pub mod faulty;
 mod memory;
 mod mvcc;
+pub mod recording;
 
 pub use {
     disk::DiskStorage,
     memory::MemoryStorage,
-    mvcc::{Mvcc, MvccTxn},
+    mvcc::{
+        ActiveTxn, ChunkedScan, FrozenSnapshot, Isolation, LenientScan, Mvcc, MvccTxn, Operation,
+        TxnBuilder, TxnOptions, Version, VersionClock, VersionStats,
+    },
 };
 
 pub trait Storage {
@@ -39,15 +45,25 @@ pub trait Storage {
     /// # 注意
     /// 迭代器存活期间，禁止对存储进行写入或删除操作。
     fn scan_prefix(&mut self, prefix: &[u8]) -> Self::Iterator<'_> {
-        let start = prefix.to_vec();
-        let mut end = prefix.to_vec();
-        // 需要将 end 的最后一个字节加 1，构造一个区间满足前缀要求
-        // 比如 prefix 为 "abc"，则 start 为 "abc"，end 为 "abd"
-        // 这样构造的区间包含了所有以 "abc" 为前缀的 key
-        if let Some(last) = end.last_mut() {
+        self.scan((Bound::Included(prefix.to_vec()), prefix_end(prefix)))
+    }
+}
+
+/// 所有以 `prefix` 开头的 key 的上界
+///
+/// 将前缀的最后一个字节加 1 作为开区间的终点，比如 prefix 为 "abc" 时终点为 "abd"。
+/// 末尾的 0xFF 无法加 1，需要先去掉，前缀为空或全为 0xFF 时没有上界。
+pub(crate) fn prefix_end(prefix: &[u8]) -> Bound<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while end.last() == Some(&0xFF) {
+        end.pop();
+    }
+    match end.last_mut() {
+        Some(last) => {
             *last += 1;
+            Bound::Excluded(end)
         }
-        self.scan(start..end) // 开区间
+        None => Bound::Unbounded,
     }
 }
 
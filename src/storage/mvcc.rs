@@ -1,27 +1,93 @@
 use std::{
     collections::{BTreeMap, HashSet},
-    ops::Add,
+    ops::{Bound, RangeBounds},
     sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use bincode::Options;
 use serde::{Deserialize, Serialize};
 
-use super::Storage;
+use super::{prefix_end, Storage};
 use crate::{
-    Error::{self, InternalError, WriteConflict},
+    codec::ValueCodec,
+    engine::row_key,
+    error::{
+        ErrorContext,
+        ExecutionError::{Overflow, TypeMismatch},
+        ResultExt,
+        SchemaError::ColumnNotFound,
+        StorageError::{
+            Decode, InvalidArgument, KeyExists, KeyNotFound, SequenceOverflow, VersionExhausted,
+        },
+        TransactionError::{Expired, Inactive, WriteConflict},
+    },
+    executor::expression::{evaluate, predicate_passes},
+    parser::ast::Expression,
+    schema::{DataType, Namespace, Row, Table, Value},
+    Error::Internal,
     Result,
 };
 
+/// 提交和回滚时每批读取的 TxnWrite 记录的最大数量
+const TXN_WRITE_BATCH_SIZE: usize = 1024;
+
+/// 编码 MVCC 自己的 key，这些类型的编码不会失败，失败说明存在 bug，返回 [`Internal`]
+fn encode_key<T: Serialize>(key: &T, what: &str) -> Result<Vec<u8>> {
+    key_options()
+        .serialize(key)
+        .map_err(|e| Internal(format!("failed to encode {what}: {e}")))
+}
+
+/// 解码 MVCC 自己写入的值，例如版本记录的值和序列的当前值
+///
+/// 这些值由 MVCC 编码后写入，解码失败说明存储中的数据已经损坏，返回携带原始字节的 [`Decode`]，
+/// 而不是表示用户数据无法编码的 [`Encoding`](crate::StorageError::Encoding)。
+fn decode_stored<'a, T: Deserialize<'a>>(bytes: &'a [u8], context: &'static str) -> Result<T> {
+    bincode::deserialize(bytes).map_err(|_| {
+        Decode {
+            context,
+            bytes: bytes.to_vec(),
+        }
+        .into()
+    })
+}
+
+/// 当前时间距离 UNIX 纪元的毫秒数，用于记录事务开始的时间
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// `MvccKey`、`MvccKeyPrefix` 和 `Version` 编码使用的 bincode 配置
+///
+/// 整数使用定长大端编码：定长保证同一类 key 中各部分的位置固定，大端保证编码的字节序和数值顺序一致，
+/// 这样同一个 key 的不同版本在存储引擎中按照版本号从小到大排列，范围扫描的结果才是有序的。
+/// bincode 的默认配置是变长编码，`bincode::serialize` 则是小端编码，两者都不满足要求。
+pub fn key_options() -> impl Options + Copy {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_big_endian()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Version(u64);
 
 impl Version {
     pub fn encode(&self) -> Result<Vec<u8>> {
-        bincode::serialize(&self).map_err(|e| e.into())
+        encode_key(self, "version")
     }
 
+    /// 解码版本号，失败时返回携带原始字节的 [`Decode`] 错误
     pub fn decode(bytes: &[u8]) -> Result<Self> {
-        bincode::deserialize(bytes).map_err(|e| e.into())
+        key_options().deserialize(bytes).map_err(|_| {
+            Decode {
+                context: "decoding version",
+                bytes: bytes.to_vec(),
+            }
+            .into()
+        })
     }
 
     pub fn max() -> Self {
@@ -31,13 +97,13 @@ impl Version {
     pub fn min() -> Self {
         Self(0)
     }
-}
-
-impl Add<u64> for Version {
-    type Output = Self;
 
-    fn add(self, rhs: u64) -> Self::Output {
-        Self(self.0.checked_add(rhs).expect("version overflow"))
+    /// 版本号加上 `rhs`，超过 `u64::MAX` 时返回 [`VersionExhausted`] 错误
+    pub fn checked_add(self, rhs: u64) -> Result<Self> {
+        self.0
+            .checked_add(rhs)
+            .map(Self)
+            .ok_or(VersionExhausted.into())
     }
 }
 
@@ -47,26 +113,55 @@ impl From<u64> for Version {
     }
 }
 
+impl From<Version> for u64 {
+    fn from(version: Version) -> Self {
+        version.0
+    }
+}
+
+/// 外部提供的版本号来源，例如外部的时间戳服务，或者测试中确定的版本号序列
+///
+/// 事务完全信任时钟返回的版本号，不会检查它是否单调递增。
+pub trait VersionClock: Send + Sync {
+    /// 分配下一个版本号
+    fn next(&self) -> Version;
+}
+
 type Key = Vec<u8>;
 
+/// 事务提交的一次写操作，用于将提交的事务重放到另一个 MVCC 存储引擎中
+///
+/// 由 [`MvccTxn::commit_with_log`] 记录，通过 [`Mvcc::apply_operations`] 重放。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operation {
+    Set(Key, Vec<u8>),
+    Delete(Key),
+}
+
 /// MVCC 存储引擎的 key
 ///
 /// - `NextVersion`: 下一个版本号
 /// - `TxnActive`: 活跃事务
 /// - `TxnWrite`: 事务写入记录，用于回滚事务
 /// - `Version`: 版本记录，用于事务的可见性判断
+/// - `Sequence`: 序列的当前值，不属于任何事务的快照，见 [`MvccTxn::next_sequence`]
+/// - `Latest`: 不保留历史版本的事务写入的 key 的唯一记录，见 [`MvccTxn::begin_non_versioned`]
+/// - `ClockVersion`: [`VersionClock`] 分配过的最大版本号，读已提交的事务刷新快照时读取
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 enum MvccKey {
     NextVersion,
     TxnActive(Version),
     TxnWrite(Version, Key),
     Version(Key, Version),
+    Sequence(Key),
+    Latest(Key),
+    ClockVersion,
 }
 
 impl MvccKey {
     /// 编码 key
     pub fn encode(&self) -> Result<Vec<u8>> {
-        let mut bytes = bincode::serialize(&self).map_err(Error::from)?;
+        let mut bytes = encode_key(self, "mvcc key")?;
         // 由于 bincode 的编码方式，需要对 Version 的编码进行特殊处理以适应前缀扫描
         //
         // bincode 对枚举的编码方式为：[索引, 数据]
@@ -78,41 +173,70 @@ impl MvccKey {
         // 对于 Version，前缀数据部分是 Key (Vec<u8>)
         // bincode 对 Vec 的编码方式是：[长度, 数据]，长度是 u64 类型，占 8 字节
         // 由于 Version 前缀中 Key 长度和 Version 的不一定相等，所以会导致前缀扫描失败
-        // 比如，`MvccKey::Version("key".to_vec(), 42)` 的编码为：[3, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 107, 101, 121, 82, 191, 1, 0, 0, 0, 0, 0]
-        // 而 `MvccKeyPrefix::Version("ke".to_vec())` 的编码为：[3, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 107, 101]
+        // 比如，`MvccKey::Version("key".to_vec(), 42)` 的编码为：[0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 3, 107, 101, 121, 0, 0, 0, 0, 0, 0, 0, 42]
+        // 而 `MvccKeyPrefix::Version("ke".to_vec())` 的编码为：[0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 2, 107, 101]
         //
         // 我们将长度去除，只保留数据，上面的例子的编码就变为：
-        // `MvccKey::Version("key".to_vec(), 42)`：[3, 0, 0, 0, 107, 101, 121, 82, 191, 1, 0, 0, 0, 0, 0]
-        // `MvccKeyPrefix::Version("ke".to_vec())`：[3, 0, 0, 0, 107, 101]
-        if let MvccKey::Version(_, _) = self {
-            bytes.drain(4..12); // 前 4 个字节是 Version 枚举对应的索引编码
+        // `MvccKey::Version("key".to_vec(), 42)`：[0, 0, 0, 3, 107, 101, 121, 0, 0, 0, 0, 0, 0, 0, 42]
+        // `MvccKeyPrefix::Version("ke".to_vec())`：[0, 0, 0, 3, 107, 101]
+        //
+        // Latest 的数据部分只有 Key，同样去掉长度，使得可以按照用户 key 的前缀扫描
+        if let MvccKey::Version(_, _) | MvccKey::Latest(_) = self {
+            bytes.drain(4..12); // 前 4 个字节是枚举对应的索引编码
         }
 
         Ok(bytes)
     }
 
-    /// 解码 key
+    /// 解码 key，失败时返回携带原始字节的 [`Decode`] 错误，错误中包含按照枚举索引判断的 key 的种类
     pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let context = match bytes.get(0..4) {
+            Some([0, 0, 0, 0]) => "decoding mvcc NextVersion key",
+            Some([0, 0, 0, 1]) => "decoding mvcc TxnActive key",
+            Some([0, 0, 0, 2]) => "decoding mvcc TxnWrite key",
+            Some([0, 0, 0, 3]) => "decoding mvcc Version key",
+            Some([0, 0, 0, 4]) => "decoding mvcc Sequence key",
+            Some([0, 0, 0, 5]) => "decoding mvcc Latest key",
+            Some([0, 0, 0, 6]) => "decoding mvcc ClockVersion key",
+            _ => "decoding mvcc key with unknown tag",
+        };
+        let decode_error = || Decode {
+            context,
+            bytes: bytes.to_vec(),
+        };
+
         // 由于编码时对 Version 进行了特殊处理，解码时也需要进行特殊处理
         //
         // 如果前缀是 Version，则需要在前面加上长度
         // 长度为编码后的长度 - 4（前 4 个字节是 Version 枚举对应的索引编码）- 8（Version u64 的版本号的长度）
-        let mut bytes = bytes.to_vec();
-        if bytes.len() > 4 && bytes[0..4] == [3, 0, 0, 0] {
-            let len = (bytes.len() - 4 - 8) as u64;
-            bytes.splice(4..4, len.to_le_bytes().iter().copied());
+        let mut raw = bytes.to_vec();
+        if raw.len() > 4 && raw[0..4] == [0, 0, 0, 3] {
+            let len = raw.len().checked_sub(4 + 8).ok_or_else(decode_error)? as u64;
+            raw.splice(4..4, len.to_be_bytes().iter().copied());
+        }
+        // Latest 的数据部分只有 Key，长度为编码后的长度 - 4
+        if raw.len() >= 4 && raw[0..4] == [0, 0, 0, 5] {
+            let len = (raw.len() - 4) as u64;
+            raw.splice(4..4, len.to_be_bytes().iter().copied());
         }
-        bincode::deserialize(&bytes).map_err(Error::from)
+        key_options()
+            .deserialize(&raw)
+            .map_err(|_| decode_error().into())
     }
 }
 
 /// MVCC 存储引擎的 key 前缀，用于扫描一个范围使用
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+///
+/// 变体的顺序需要和 [`MvccKey`] 一致，编码的枚举索引才相同，`Sequence` 和 `ClockVersion` 只用于占位。
 enum MvccKeyPrefix {
     NextVersion,
     TxnActive,
     TxnWrite(Version),
     Version(Key),
+    Sequence,
+    Latest(Key),
+    ClockVersion,
 }
 
 impl MvccKeyPrefix {
@@ -120,8 +244,8 @@ impl MvccKeyPrefix {
     pub fn encode(&self) -> Result<Vec<u8>> {
         // 需要和编码 MvccKey 相同的处理方式
         // 具体参考 MvccKey 的 encode 方法
-        let mut bytes = bincode::serialize(&self).map_err(Error::from)?;
-        if let MvccKeyPrefix::Version(_) = self {
+        let mut bytes = encode_key(self, "mvcc key prefix")?;
+        if let MvccKeyPrefix::Version(_) | MvccKeyPrefix::Latest(_) = self {
             bytes.drain(4..12);
         }
 
@@ -142,373 +266,2314 @@ impl<S: Storage> Mvcc<S> {
         }
     }
 
-    /// 开启一个新事务
+    /// 开启一个新事务，使用快照隔离
     pub fn start_txn(&self) -> Result<MvccTxn<S>> {
         MvccTxn::begin(self.storage.clone())
     }
-}
 
-/// MVCC 事务
-pub struct MvccTxn<S: Storage> {
-    storage: Arc<Mutex<S>>,
-    version: Version,
-    active_versions: HashSet<Version>,
-}
-
-impl<S: Storage> MvccTxn<S> {
-    /// 开启一个新事务
-    pub fn begin(s: Arc<Mutex<S>>) -> Result<Self> {
-        // 获取当前存储引擎的锁
-        let mut storage = s.lock()?;
+    /// 以指定的隔离级别开启一个新事务
+    pub fn start_txn_with_isolation(&self, isolation: Isolation) -> Result<MvccTxn<S>> {
+        MvccTxn::begin_with_isolation(self.storage.clone(), isolation)
+    }
 
-        // 获取下一个版本号，如果不存在则从 1 开始
-        let version = if let Some(value) = storage.get(&MvccKey::NextVersion.encode()?)? {
-            Version::decode(&value)?
-        } else {
-            Version(1)
-        };
+    /// 以指定的选项开启一个新事务，使用快照隔离
+    pub fn start_txn_with_options(&self, options: TxnOptions) -> Result<MvccTxn<S>> {
+        MvccTxn::begin_with_options(self.storage.clone(), options)
+    }
 
-        // 将下一个版本号加 1，写入存储引擎
-        storage.put(&MvccKey::NextVersion.encode()?, &(version + 1).encode()?)?;
+    /// 开启一个带有标签的新事务，见 [`MvccTxn::begin_labeled`]
+    pub fn start_txn_labeled(&self, label: impl Into<String>) -> Result<MvccTxn<S>> {
+        MvccTxn::begin_labeled(self.storage.clone(), label)
+    }
 
-        // 扫描所有活跃事务
-        let active_versions = Self::scan_active_txn(&mut storage)?;
+    /// 开启一个不保留历史版本的事务，见 [`MvccTxn::begin_non_versioned`]
+    pub fn start_txn_non_versioned(&self) -> Result<MvccTxn<S>> {
+        MvccTxn::begin_non_versioned(self.storage.clone())
+    }
 
-        // 将新事务加入活跃事务列表
-        // 在扫描之后加入，否则会将自己加入活跃事务列表从而导致自己不可见
-        storage.put(&MvccKey::TxnActive(version).encode()?, &[])?;
+    /// 开启一个新事务，版本号由 `clock` 分配
+    pub fn start_txn_with_clock(&self, clock: Arc<dyn VersionClock>) -> Result<MvccTxn<S>> {
+        MvccTxn::begin_with_clock(self.storage.clone(), clock)
+    }
 
-        Ok(Self {
-            storage: s.clone(),
-            version,
-            active_versions,
-        })
+    /// 创建开启事务的 [`TxnBuilder`]，见 [`MvccTxn::builder`]
+    pub fn txn_builder(&self) -> TxnBuilder<S> {
+        MvccTxn::builder(self.storage.clone())
     }
 
-    /// 查找所有活跃事务
-    fn scan_active_txn(storage: &mut MutexGuard<S>) -> Result<HashSet<Version>> {
-        let mut active_versions = HashSet::new();
+    /// 删除 `prefix` 开头的所有可见行中满足 `predicate` 的行，返回删除的行数
+    ///
+    /// 在一个新事务中扫描 `prefix` 下的行并使用 `codec` 解码，`predicate` 为 `TRUE` 时写入删除标记。
+    /// 写入前先检查所有匹配的 key，任何一个存在写冲突时回滚整个事务并返回 [`WriteConflict`]，
+    /// 因此不会只删除一部分行。只删除行本身，不维护二级索引。
+    pub fn delete_where(
+        &self,
+        prefix: Key,
+        table: &Table,
+        predicate: &Expression,
+        codec: &dyn ValueCodec,
+    ) -> Result<usize> {
+        let txn = self.start_txn()?;
+        let result = (|| {
+            let columns = table
+                .columns
+                .iter()
+                .map(|col| format!("{}.{}", table.name, col.name))
+                .collect::<Vec<_>>();
+            let mut keys = Vec::new();
+            for (key, value) in txn.scan_prefix(&prefix)? {
+                let row = table
+                    .decode_row_with(codec, &value)
+                    .with_context(|| ErrorContext::new("scanning").table(&table.name).key(&key))?;
+                if predicate_passes(evaluate(predicate, &columns, &row)?)? {
+                    keys.push(key);
+                }
+            }
 
-        // 扫描前缀为 TxnActive 的 key
-        let mut iter = storage.scan_prefix(&MvccKeyPrefix::TxnActive.encode()?);
-        while let Some((key, _)) = iter.next().transpose()? {
-            // 解码 key，获取事务版本，并加入活跃事务列表
-            if let MvccKey::TxnActive(version) = MvccKey::decode(&key)? {
-                active_versions.insert(version);
-            } else {
-                return Err(InternalError(format!(
-                    "unexpected key {} when scanning active transactions",
-                    String::from_utf8_lossy(key.as_slice())
-                )));
+            if !txn.precheck_conflicts(&keys)?.is_empty() {
+                return Err(WriteConflict.into());
+            }
+            for key in &keys {
+                txn.delete(key)?;
+            }
+            Ok(keys.len())
+        })();
+
+        match result {
+            Ok(count) => {
+                txn.commit()?;
+                Ok(count)
+            }
+            Err(e) => {
+                txn.rollback()?;
+                Err(e)
             }
         }
-        Ok(active_versions)
     }
 
-    /// 版本是否可见
+    /// 在一个新事务中重放 [`MvccTxn::commit_with_log`] 记录的操作日志
     ///
-    /// 版本可见的条件是：
-    ///
-    /// - 版本小于等于当前版本；
-    /// - 版本不在活跃事务列表中。
-    #[inline]
-    fn is_version_visible(&self, version: Version) -> bool {
-        version <= self.version && !self.active_versions.contains(&version)
+    /// 写入前先检查所有 key，任何一个存在写冲突时回滚并返回 [`WriteConflict`]，不会只重放一部分操作。
+    pub fn apply_operations(&self, ops: &[Operation]) -> Result<()> {
+        let txn = self.start_txn()?;
+        let result = (|| {
+            let keys = ops
+                .iter()
+                .map(|op| match op {
+                    Operation::Set(key, _) | Operation::Delete(key) => key.clone(),
+                })
+                .collect::<Vec<_>>();
+            if !txn.precheck_conflicts(&keys)?.is_empty() {
+                return Err(WriteConflict.into());
+            }
+            for op in ops {
+                match op {
+                    Operation::Set(key, value) => txn.set(key, value)?,
+                    Operation::Delete(key) => txn.delete(key)?,
+                }
+            }
+            Ok(())
+        })();
+
+        match result {
+            Ok(()) => txn.commit(),
+            Err(e) => {
+                txn.rollback()?;
+                Err(e)
+            }
+        }
     }
 
-    /// 更新/删除数据的内置函数
+    /// 在一个新事务中批量写入 `table` 的行，返回写入的行数，用于向空表导入按主键排好序的数据
     ///
-    /// - 如果 `value` 为 `None`，则删除 `key` 对应的数据
-    /// - 否则更新 `key` 对应的数据
-    fn write_inner(&self, key: &[u8], value: Option<Vec<u8>>) -> Result<()> {
-        // 获取当前存储引擎的锁
-        let mut storage = self.storage.lock()?;
-
-        // 活跃事务和大于当前版本的事务都不可见
-        // 取活跃事务的最小值到可能存在的版本最大值，构成一个范围，其中会包括所有不可见的事务
-        let begin = self
-            .active_versions
-            .iter()
-            .min()
-            .copied()
-            .unwrap_or(self.version + 1);
-        let begin_key = MvccKey::Version(key.to_vec(), begin).encode()?;
-        let end_key = MvccKey::Version(key.to_vec(), Version::max()).encode()?;
+    /// 行使用 `codec` 编码，key 在命名空间 `namespace` 中编码，两者应该和读取这张表的引擎一致，
+    /// 通常通过 [`Engine::bulk_insert`](crate::Engine::bulk_insert) 调用。
+    /// 假设行的主键范围中没有已有的数据，因此不检查写冲突、主键是否已经存在和外键，只对每一行编码 key 和 value，
+    /// 在一次获取存储引擎的锁的过程中写入事务写入记录和版本记录。`verify` 为真时检查行按照主键严格升序排列，
+    /// 主键重复或者顺序错误时回滚整个事务并返回错误。不维护二级索引，表有二级索引时不写入任何行并返回错误。
+    pub fn bulk_insert(
+        &self,
+        table: &Table,
+        rows: impl Iterator<Item = Row>,
+        codec: &dyn ValueCodec,
+        namespace: &Namespace,
+        verify: bool,
+    ) -> Result<usize> {
+        if let Some(index) = table.indexes.first() {
+            return Err(InvalidArgument(format!(
+                "Table {} has secondary index {}, which bulk insert does not maintain",
+                table.name, index.name
+            ))
+            .into());
+        }
 
-        // 检查是否有不可见的版本写入了 key
-        // 首先根据活跃事务和大于当前版本的事务的范围，找到最后一个可能不可见的事务
-        // 如果这个事务不可见，则说明有不可见的事务写入了 key，返回写冲突
-        //
-        // 为什么只需检查最后一个可能不可见的版本即可：
-        // 若最后版本不可见：直接判定存在写冲突，无需检查更早的版本，因为该版本是当前事务可能冲突的最高版本。
-        // 若最后版本可见：所有更早的版本要么已被提交（可见），要么会发生写冲突。
-        if let Some((key, _)) = storage.scan(begin_key..=end_key).last().transpose()? {
-            if let MvccKey::Version(_, version) = MvccKey::decode(&key)? {
-                if !self.is_version_visible(version) {
-                    return Err(WriteConflict);
+        let txn = self.start_txn()?;
+        let result = (|| {
+            let mut storage = txn.storage.lock()?;
+            let mut count = 0;
+            let mut last_key: Option<Key> = None;
+            for row in rows {
+                table.validate_row(&row)?;
+                let pk = table.get_primary_key(&row);
+                let key = row_key(namespace, &table.name, pk);
+                if verify && last_key.as_ref().is_some_and(|last_key| *last_key >= key) {
+                    return Err(InvalidArgument(format!(
+                        "Primary key {:?} in table {} is duplicate or out of order",
+                        pk, table.name
+                    ))
+                    .into());
                 }
-            } else {
-                return Err(InternalError(format!(
-                    "unexpected key {} when scanning versions",
-                    String::from_utf8_lossy(key.as_slice())
-                )));
+                storage.put(&MvccKey::TxnWrite(txn.version, key.clone()).encode()?, &[])?;
+                storage.put(
+                    &MvccKey::Version(key.clone(), txn.version).encode()?,
+                    &bincode::serialize(&Some(codec.encode_row(&row)?))?,
+                )?;
+                last_key = Some(key);
+                count += 1;
+            }
+            Ok(count)
+        })();
+
+        match result {
+            Ok(count) => {
+                txn.commit()?;
+                Ok(count)
+            }
+            Err(e) => {
+                txn.rollback()?;
+                Err(e)
             }
         }
+    }
 
-        // 记录新版本写入了哪些 key，用于回滚事务
-        storage.put(
-            &MvccKey::TxnWrite(self.version, key.to_vec()).encode()?,
-            &[],
-        )?;
+    /// 在一个新事务中扫描一次 `prefix` 开头的所有可见行，返回行数和整数列 `col` 的和，
+    /// 相当于 `SELECT COUNT(*), SUM(col)`，两个结果来自同一个快照
+    ///
+    /// 行使用 `codec` 解码，每一行只解码 `col` 这一列。值为 NULL 的行计入行数但不计入和，列不存在、
+    /// 列的类型不是整数或者求和溢出时返回错误。
+    pub fn aggregate_integer(
+        &self,
+        prefix: Key,
+        table: &Table,
+        col: &str,
+        codec: &dyn ValueCodec,
+    ) -> Result<(usize, i64)> {
+        let col_idx = table.get_col_idx(col).ok_or(ColumnNotFound {
+            table: table.name.clone(),
+            column: col.to_string(),
+        })?;
+        let data_type = table.columns[col_idx].data_type;
+        if data_type != DataType::Integer {
+            return Err(TypeMismatch(format!(
+                "cannot sum column {col} of type {data_type:?} in table {}",
+                table.name
+            ))
+            .into());
+        }
 
-        // 如果 value 不为 None，则写入新的数据，否则删除数据
-        storage.put(
-            &MvccKey::Version(key.to_vec(), self.version).encode()?,
-            &bincode::serialize(&value)?,
-        )?;
+        let txn = self.start_txn()?;
+        let result = (|| {
+            let mut count = 0;
+            let mut sum: i64 = 0;
+            for (key, value) in txn.scan_prefix(&prefix)? {
+                let row = table
+                    .decode_columns_with(codec, &value, &[col_idx])
+                    .with_context(|| ErrorContext::new("scanning").table(&table.name).key(&key))?;
+                count += 1;
+                if let Value::Integer(value) = row[col_idx] {
+                    sum = sum
+                        .checked_add(value)
+                        .ok_or(Overflow(format!("SUM({col})")))?;
+                }
+            }
+            Ok((count, sum))
+        })();
 
-        Ok(())
+        match result {
+            Ok(result) => {
+                txn.commit()?;
+                Ok(result)
+            }
+            Err(e) => {
+                txn.rollback()?;
+                Err(e)
+            }
+        }
     }
 
-    /// 更新 `key` 对应的值
-    #[inline]
-    pub fn set(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        self.write_inner(key, Some(value.to_vec()))
+    /// 分批扫描 `prefix` 开头的所有可见 key，每批最多 `chunk` 个
+    ///
+    /// 快照在调用时固定，之后每次调用 [`ChunkedScan::next_chunk`] 才获取存储引擎的锁，读完一批后立即释放，
+    /// 因此调用方可以在两批之间长时间停留而不阻塞其他事务。和 [`MvccTxn::scan_range`] 相同，
+    /// 要求 key 之间互不为前缀。
+    pub fn scan_visible_chunked(&self, prefix: Key, chunk: usize) -> Result<ChunkedScan<S>> {
+        if chunk == 0 {
+            return Err(InvalidArgument("Chunk size must be positive".to_string()).into());
+        }
+
+        let end = prefix_end(&prefix);
+
+        Ok(ChunkedScan {
+            txn: Some(self.start_txn()?),
+            start: Bound::Included(prefix),
+            end,
+            chunk,
+        })
     }
 
-    /// 删除 `key` 对应的值
-    #[inline]
-    pub fn delete(&self, key: &[u8]) -> Result<()> {
-        self.write_inner(key, None)
+    /// 在一个新事务中返回 `prefix` 开头的所有可见 key，不包含值，见 [`MvccTxn::scan_prefix_keys`]
+    ///
+    /// 用于只需要判断 key 是否存在的场景，例如构建只有 key 的索引，或者对大量 key 做存在性检查。
+    pub fn scan_visible_keys(&self, prefix: Key) -> Result<Vec<Key>> {
+        let txn = self.start_txn()?;
+        match txn.scan_prefix_keys(&prefix) {
+            Ok(keys) => {
+                txn.commit()?;
+                Ok(keys)
+            }
+            Err(e) => {
+                txn.rollback()?;
+                Err(e)
+            }
+        }
     }
 
-    /// 获取 `key` 对应的值
-    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+    /// 按照版本从小到大返回 `key` 存储的所有版本，删除的版本对应的值为 `None`
+    ///
+    /// 不考虑可见性，未提交事务写入的版本也会返回，用于审计和查看历史。
+    pub fn history(s: Arc<Mutex<S>>, key: Key) -> Result<Vec<(Version, Option<Vec<u8>>)>> {
         // 获取当前存储引擎的锁
-        let mut storage = self.storage.lock()?;
-
-        // 设置范围为 0 到当前版本，因为大于当前版本的事务一定不可见
-        let begin = MvccKey::Version(key.to_vec(), Version::min()).encode()?;
-        let end = MvccKey::Version(key.to_vec(), self.version).encode()?;
+        let mut storage = s.lock()?;
 
-        // 从范围中找到最新的可见版本
-        let mut iter = storage.scan(begin..=end).rev(); // 新版本在后面
-        while let Some((key, value)) = iter.next().transpose()? {
-            if let MvccKey::Version(_, version) = MvccKey::decode(&key)? {
-                // 判断是否可见，此处指的是不在活跃事务中，因为范围已经排除了大于当前版本的事务
-                if self.is_version_visible(version) {
-                    // 存储的数据为 Option<Vec<u8>>，Option 为 None 表示删除，需要解析
-                    return Ok(bincode::deserialize(&value)?);
+        // 编码中去掉了 key 的长度，范围内还可能有以 key 为前缀的其他 key 的版本，需要跳过
+        let begin = MvccKey::Version(key.clone(), Version::min()).encode()?;
+        let end = MvccKey::Version(key.clone(), Version::max()).encode()?;
+        let mut history = Vec::new();
+        let mut iter = storage.scan(begin..=end);
+        while let Some((raw_key, value)) = iter.next().transpose()? {
+            match MvccKey::decode(&raw_key)? {
+                MvccKey::Version(k, version) if k == key => {
+                    history.push((version, decode_stored(&value, "decoding version value")?))
+                }
+                MvccKey::Version(..) => {}
+                _ => {
+                    return Err(Decode {
+                        context: "scanning versions, expected a Version key",
+                        bytes: raw_key.to_vec(),
+                    }
+                    .into())
                 }
-            } else {
-                return Err(InternalError(format!(
-                    "unexpected key {} when scanning versions",
-                    String::from_utf8_lossy(key.as_slice())
-                )));
             }
         }
-
-        // 没有找到可见版本，返回 None
-        Ok(None)
+        Ok(history)
     }
 
-    /// 扫描 `prefix` 开头的所有可见的事务记录
-    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<(Key, Vec<u8>)>> {
+    /// 按照版本从小到大返回所有活跃事务的版本、标签和开始时间
+    ///
+    /// 用于排查长时间不结束的事务：标签由 [`MvccTxn::begin_labeled`] 设置，标识开启事务的模块。
+    /// 开始时间只在设置了 [`TxnOptions::max_age`] 时记录。
+    pub fn active_transactions(s: Arc<Mutex<S>>) -> Result<Vec<ActiveTxn>> {
         // 获取当前存储引擎的锁
-        let mut storage = self.storage.lock()?;
-
-        let prefix = MvccKeyPrefix::Version(prefix.to_vec()).encode()?;
+        let mut storage = s.lock()?;
 
-        let mut result = BTreeMap::new();
-        let mut iter = storage.scan_prefix(&prefix);
+        let mut active = Vec::new();
+        let mut iter = storage.scan_prefix(&MvccKeyPrefix::TxnActive.encode()?);
         while let Some((key, value)) = iter.next().transpose()? {
-            match MvccKey::decode(&key)? {
-                // 如果版本可见，则返回 key-value，之后的过滤中被保留
-                // 如果版本可见但 value 为 None，表示删除，返回 None，并且删除前面的版本中已经存在的 key-value
-                MvccKey::Version(k, version) => {
-                    if !self.is_version_visible(version) {
-                        continue;
-                    }
-                    let value: Option<Vec<u8>> = bincode::deserialize(&value)?;
-                    if let Some(value) = &value {
-                        result.insert(k, value.clone());
-                    } else {
-                        result.remove(&k);
-                    }
+            let MvccKey::TxnActive(version) = MvccKey::decode(&key)? else {
+                return Err(Decode {
+                    context: "scanning active transactions, expected a TxnActive key",
+                    bytes: key.to_vec(),
                 }
-                // 如果解析不是 Version，则返回错误
-                _ => {
-                    return Err(InternalError(format!(
-                        "unexpected key {} when scanning versions",
-                        String::from_utf8_lossy(&key)
-                    )))?
+                .into());
+            };
+            let meta = TxnMeta::decode(&value)?;
+            active.push(ActiveTxn {
+                version,
+                label: meta.label,
+                started_at: meta
+                    .started_at
+                    .map(|millis| UNIX_EPOCH + Duration::from_millis(millis)),
+            });
+        }
+        Ok(active)
+    }
+
+    /// 扫描 `Version` 和 `TxnWrite` 记录，统计存储中累积的版本数量，用于判断何时需要清理历史版本
+    ///
+    /// 不考虑可见性，未提交事务写入的版本同样计入。
+    pub fn version_stats(s: Arc<Mutex<S>>) -> Result<VersionStats> {
+        // 获取当前存储引擎的锁
+        let mut storage = s.lock()?;
+        let mut stats = VersionStats::default();
+
+        // 编码中去掉了 key 的长度，不同 key 的版本可能交错，因此用集合统计 key 的数量
+        let mut keys = HashSet::new();
+        let mut iter = storage.scan_prefix(&MvccKeyPrefix::Version(Vec::new()).encode()?);
+        while let Some((raw_key, value)) = iter.next().transpose()? {
+            let MvccKey::Version(key, _) = MvccKey::decode(&raw_key)? else {
+                return Err(Decode {
+                    context: "scanning versions, expected a Version key",
+                    bytes: raw_key.to_vec(),
                 }
+                .into());
+            };
+            stats.total_versions += 1;
+            if decode_stored::<Option<Vec<u8>>>(&value, "decoding version value")?.is_none() {
+                stats.tombstones += 1;
             }
+            keys.insert(key);
         }
-
-        Ok(result.into_iter().collect())
+        stats.distinct_keys = keys.len();
+        drop(iter);
+
+        // 所有事务的 TxnWrite 记录位于版本号最小的 TxnWrite 前缀和 Version 前缀之间
+        let begin = MvccKeyPrefix::TxnWrite(Version::min()).encode()?;
+        let end = MvccKeyPrefix::Version(Vec::new()).encode()?;
+        let mut iter = storage.scan(begin..end);
+        while let Some((raw_key, _)) = iter.next().transpose()? {
+            let MvccKey::TxnWrite(..) = MvccKey::decode(&raw_key)? else {
+                return Err(Decode {
+                    context: "scanning txn writes, expected a TxnWrite key",
+                    bytes: raw_key.to_vec(),
+                }
+                .into());
+            };
+            stats.txn_write_markers += 1;
+        }
+        Ok(stats)
     }
 
-    /// 提交事务
+    /// 扫描 `Version` 记录，返回所有 key 的版本中最小和最大的版本，用于确定清理历史版本的安全水位
     ///
-    /// 对于提交事务，实际上是让这个事务的修改对后续新开启的事务是可见的。
-    /// 因此，只需要将当前事务对应的所有 TxnWrite 记录，以及当前事务在活跃事务列表中的记录删除即可。
-    pub fn commit(&self) -> Result<()> {
+    /// 不考虑可见性，未提交事务写入的版本同样计入。没有任何版本记录时返回 `(Version(0), Version(0))`，
+    /// 版本号从 1 开始分配，因此不会和实际的版本混淆。
+    pub fn version_bounds(s: Arc<Mutex<S>>) -> Result<(Version, Version)> {
         // 获取当前存储引擎的锁
-        let mut storage = self.storage.lock()?;
+        let mut storage = s.lock()?;
 
-        // 找到当前事务对应的所有 TxnWrite 记录
-        let txn_keys = storage
-            .scan_prefix(&MvccKeyPrefix::TxnWrite(self.version).encode()?)
-            .map(|item| {
-                let (key, _) = item?;
-                if let MvccKey::TxnWrite(_, key) = MvccKey::decode(&key)? {
-                    Ok(key)
-                } else {
-                    Err(InternalError(format!(
-                        "unexpected key {} when scanning txn writes",
-                        String::from_utf8_lossy(&key)
-                    )))
+        // 版本记录按照 key 排序，同一个 key 的版本才有序，因此需要遍历所有记录
+        let mut bounds: Option<(Version, Version)> = None;
+        let mut iter = storage.scan_prefix(&MvccKeyPrefix::Version(Vec::new()).encode()?);
+        while let Some((raw_key, _)) = iter.next().transpose()? {
+            let MvccKey::Version(_, version) = MvccKey::decode(&raw_key)? else {
+                return Err(Decode {
+                    context: "scanning versions, expected a Version key",
+                    bytes: raw_key.to_vec(),
                 }
-            })
-            .collect::<Result<Vec<_>>>()?;
-
-        // 将当前事务对应的所有 TxnWrite 记录从存储引擎中删除
-        for key in txn_keys {
-            storage.delete(&key)?;
+                .into());
+            };
+            bounds = Some(match bounds {
+                Some((min, max)) => (min.min(version), max.max(version)),
+                None => (version, version),
+            });
         }
+        Ok(bounds.unwrap_or((Version::min(), Version::min())))
+    }
 
-        // 将当前事务从活跃事务列表中移除
-        storage.delete(&MvccKey::TxnActive(self.version).encode()?)?;
-
-        Ok(())
+    /// 将当前所有已提交的可见 key 复制到内存中，得到一个不可变的快照
+    ///
+    /// 复制时在一个只读事务中读取，读取完成后回滚该事务，之后快照的读取都不再访问存储引擎，
+    /// 也就不需要获取它的锁，适合大量并发的只读请求共享一个一致的视图，代价是复制所有数据的内存。
+    pub fn freeze(&self) -> Result<FrozenSnapshot> {
+        let txn = self.start_txn()?;
+        let data = match txn.scan_prefix(&[]) {
+            Ok(pairs) => pairs.into_iter().collect(),
+            Err(e) => {
+                txn.rollback()?;
+                return Err(e);
+            }
+        };
+        txn.rollback()?;
+        Ok(FrozenSnapshot {
+            version: txn.version(),
+            data,
+        })
     }
+}
 
-    /// 回滚事务
-    pub fn rollback(&self) -> Result<()> {
-        // 获取当前存储引擎的锁
-        let mut storage = self.storage.lock()?;
+/// 由 [`Mvcc::freeze`] 创建的不可变快照，持有复制时所有可见的 key-value
+///
+/// 之后的写入对快照不可见，读取不获取存储引擎的锁，可以在多个线程之间共享。
+#[derive(Debug, Clone)]
+pub struct FrozenSnapshot {
+    /// 复制时使用的只读事务的版本
+    version: Version,
+    data: BTreeMap<Key, Vec<u8>>,
+}
 
-        // 找到当前事务对应的所有 TxnWrite 记录，并转换为 Version 记录
-        // 之后将 TxnWrite 记录和 Version 记录都添加到删除列表中
-        let txn_keys = storage
-            .scan_prefix(&MvccKeyPrefix::TxnWrite(self.version).encode()?)
-            .map(|item| {
-                let (tx_write_key, _) = item?;
-                if let MvccKey::TxnWrite(_, raw_version_key) = MvccKey::decode(&tx_write_key)? {
-                    let version_key = MvccKey::Version(raw_version_key, self.version).encode()?;
-                    Ok((tx_write_key, version_key))
-                } else {
-                    Err(InternalError(format!(
-                        "unexpected key {} when scanning txn writes",
-                        String::from_utf8_lossy(&tx_write_key)
-                    )))
-                }
-            })
-            .collect::<Result<Vec<_>>>()?;
+impl FrozenSnapshot {
+    /// 复制时使用的只读事务的版本，小于它且在复制时已经提交的版本都可见
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.version
+    }
 
-        // 将当前事务对应的所有 TxnWrite 记录和 Version 记录从存储引擎中删除
-        for (tx_write_key, version_key) in txn_keys {
-            storage.delete(&tx_write_key)?;
-            storage.delete(&version_key)?;
-        }
+    /// 获取 key 对应的 value
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.data.get(key).map(Vec::as_slice)
+    }
 
-        // 将当前事务从活跃事务列表中移除
-        storage.delete(&MvccKey::TxnActive(self.version).encode()?)?;
+    /// 按 key 升序遍历 `range` 范围内的 key-value
+    pub fn scan<R>(&self, range: R) -> impl DoubleEndedIterator<Item = (&Key, &[u8])>
+    where
+        R: RangeBounds<Key>,
+    {
+        self.data
+            .range(range)
+            .map(|(key, value)| (key, value.as_slice()))
+    }
 
-        Ok(())
+    /// 快照中 key 的数量
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// 快照中是否没有任何 key
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        storage::{disk::DiskStorage, memory::MemoryStorage},
-        Result,
-    };
+/// [`Mvcc::version_stats`] 统计的版本数量
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VersionStats {
+    /// 所有 key 的版本总数，包括删除标记
+    pub total_versions: usize,
+    /// 至少有一个版本的 key 的数量
+    pub distinct_keys: usize,
+    /// 删除标记的数量
+    pub tombstones: usize,
+    /// 事务写入记录的数量，提交或回滚时删除，因此只属于尚未结束的事务
+    pub txn_write_markers: usize,
+}
 
-    use super::*;
-    use tempfile::NamedTempFile;
+/// [`Mvcc::active_transactions`] 返回的活跃事务
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveTxn {
+    pub version: Version,
+    /// 开启事务时设置的标签，没有设置时为 `None`
+    pub label: Option<String>,
+    /// 事务开始的时间，只在设置了 [`TxnOptions::max_age`] 时记录
+    pub started_at: Option<SystemTime>,
+}
 
-    #[test]
-    fn test_mvcckey() -> Result<()> {
-        let key_1 = MvccKey::NextVersion;
-        let encoded_1 = key_1.encode()?;
-        let decoded_1 = MvccKey::decode(&encoded_1)?;
-        assert_eq!(key_1, decoded_1);
+/// 活跃事务保存在 `TxnActive` 记录中的信息
+///
+/// 没有任何信息时记录的值为空，而不是编码后的结构，大多数事务因此不需要额外的存储。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct TxnMeta {
+    /// 事务开始的时间，距离 UNIX 纪元的毫秒数
+    started_at: Option<u64>,
+    label: Option<String>,
+}
 
-        let key_2 = MvccKey::TxnActive(1.into());
-        let encoded_2 = key_2.encode()?;
-        let decoded_2 = MvccKey::decode(&encoded_2)?;
-        assert_eq!(key_2, decoded_2);
+impl TxnMeta {
+    fn encode(&self) -> Result<Vec<u8>> {
+        match *self == Self::default() {
+            true => Ok(Vec::new()),
+            false => Ok(bincode::serialize(self)?),
+        }
+    }
 
-        let key_3 = MvccKey::TxnWrite(1.into(), b"key".to_vec());
-        let encoded_3 = key_3.encode()?;
-        let decoded_3 = MvccKey::decode(&encoded_3)?;
-        assert_eq!(key_3, decoded_3);
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        match bytes.is_empty() {
+            true => Ok(Self::default()),
+            false => decode_stored(bytes, "decoding active transaction"),
+        }
+    }
+}
 
-        let key_4 = MvccKey::Version(b"key".to_vec(), 1.into());
+/// 事务的隔离级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Isolation {
+    /// 快照隔离，快照在事务开始时确定，之后的读取都基于这个快照
+    #[default]
+    Snapshot,
+    /// 读已提交，每次读取前都会重新获取快照，因此可以读取到其他事务已经提交的数据
+    ReadCommitted,
+}
+
+/// 事务的选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxnOptions {
+    /// 写入时是否检测写冲突，默认为 `true`
+    ///
+    /// 关闭后写入不再扫描 key 已有的版本，直接写入事务写入记录和版本记录，只适用于 key 不会被重复写入的
+    /// 追加场景，例如日志表。调用方需要保证 key 的唯一性：如果并发的事务写入了同一个 key，两个事务都会提交成功，
+    /// 后提交的写入可能覆盖或者被先提交的写入覆盖，破坏快照隔离，造成数据损坏。
+    pub detect_conflicts: bool,
+    /// 事务的最大存活时间，默认为 `None`，即不限制
+    ///
+    /// 长时间不结束的事务一直留在活跃事务列表中，使得其他事务无法清理它开始之后被覆盖的版本。
+    /// 设置后事务开始的时间记录在 `TxnActive` 的值中，超过时间后写入和提交都返回 [`Expired`]，
+    /// 提交时同时回滚事务，调用方需要重新开始事务。
+    pub max_age: Option<Duration>,
+}
+
+impl Default for TxnOptions {
+    fn default() -> Self {
+        Self {
+            detect_conflicts: true,
+            max_age: None,
+        }
+    }
+}
+
+/// 事务用于判断版本可见性的快照
+///
+/// - `version`: 快照中最新的版本，大于它的版本都不可见
+/// - `active_versions`: 获取快照时的活跃事务（不包含当前事务），它们的版本都不可见
+struct Snapshot {
+    version: Version,
+    active_versions: HashSet<Version>,
+}
+
+/// [`MvccTxn::scan_prefix_lenient`] 的结果
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LenientScan {
+    /// 可见的 key-value，按 key 升序排列
+    pub entries: Vec<(Key, Vec<u8>)>,
+    /// 无法解码而被跳过的记录在存储中的 key
+    pub skipped: Vec<Vec<u8>>,
+}
+
+/// 分批扫描可见 key 的游标，由 [`Mvcc::scan_visible_chunked`] 创建
+///
+/// 内部持有一个只读事务固定快照，扫描结束或游标销毁时回滚该事务。
+pub struct ChunkedScan<S: Storage> {
+    txn: Option<MvccTxn<S>>,
+    /// 下一批的起点，读完一批后移动到这一批的最后一个 key 之后
+    start: Bound<Key>,
+    end: Bound<Key>,
+    chunk: usize,
+}
+
+impl<S: Storage> ChunkedScan<S> {
+    /// 读取下一批可见的 key-value，结果按 key 升序排列，扫描结束后返回空
+    pub fn next_chunk(&mut self) -> Result<Vec<(Key, Vec<u8>)>> {
+        let Some(txn) = &self.txn else {
+            return Ok(Vec::new());
+        };
+        let chunk = txn.scan_range_limit((self.start.clone(), self.end.clone()), self.chunk)?;
+        match chunk.last() {
+            // 不足一批说明已经读完
+            Some((key, _)) if chunk.len() == self.chunk => {
+                self.start = Bound::Excluded(key.clone());
+            }
+            _ => self.finish()?,
+        }
+        Ok(chunk)
+    }
+
+    /// 结束扫描，回滚只读事务
+    fn finish(&mut self) -> Result<()> {
+        match self.txn.take() {
+            Some(txn) => txn.rollback(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S: Storage> Drop for ChunkedScan<S> {
+    /// 销毁时结束尚未读完的扫描
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            eprintln!("Failed to finish chunked scan: {:?}", e);
+        }
+    }
+}
+
+/// MVCC 事务
+pub struct MvccTxn<S: Storage> {
+    storage: Arc<Mutex<S>>,
+    version: Version,
+    isolation: Isolation,
+    options: TxnOptions,
+    snapshot: Mutex<Snapshot>,
+    /// 是否保留历史版本，为 `false` 时写入覆盖 key 唯一的 `Latest` 记录
+    versioned: bool,
+}
+
+/// 开启事务的选项，可以任意组合，由 [`MvccTxn::builder`] 或者 [`Mvcc::txn_builder`] 创建
+///
+/// 默认使用快照隔离、默认的 [`TxnOptions`]、存储引擎中的 `NextVersion` 分配版本号、没有标签、保留历史版本。
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use sqldb::storage::{Isolation, MemoryStorage, MvccTxn, TxnOptions};
+///
+/// let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+/// let txn = MvccTxn::builder(storage)
+///     .isolation(Isolation::ReadCommitted)
+///     .options(TxnOptions {
+///         detect_conflicts: false,
+///         ..TxnOptions::default()
+///     })
+///     .label("import")
+///     .begin()?;
+/// txn.set(b"key", b"value")?;
+/// txn.commit()?;
+/// # Ok::<(), sqldb::Error>(())
+/// ```
+pub struct TxnBuilder<S: Storage> {
+    storage: Arc<Mutex<S>>,
+    isolation: Isolation,
+    options: TxnOptions,
+    clock: Option<Arc<dyn VersionClock>>,
+    label: Option<String>,
+    versioned: bool,
+}
+
+impl<S: Storage> TxnBuilder<S> {
+    fn new(storage: Arc<Mutex<S>>) -> Self {
+        Self {
+            storage,
+            isolation: Isolation::default(),
+            options: TxnOptions::default(),
+            clock: None,
+            label: None,
+            versioned: true,
+        }
+    }
+
+    /// 设置隔离级别
+    pub fn isolation(mut self, isolation: Isolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// 设置事务的选项
+    pub fn options(mut self, options: TxnOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// 版本号由 `clock` 分配，不读写存储引擎中的 `NextVersion`
+    ///
+    /// 同一个存储引擎上的所有事务都应该使用同一个时钟，否则版本号可能重复。
+    pub fn clock(mut self, clock: Arc<dyn VersionClock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// 设置事务的标签，见 [`MvccTxn::begin_labeled`]
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// 不保留历史版本，见 [`MvccTxn::begin_non_versioned`]
+    pub fn non_versioned(mut self) -> Self {
+        self.versioned = false;
+        self
+    }
+
+    /// 按照设置的选项开启事务
+    pub fn begin(self) -> Result<MvccTxn<S>> {
+        MvccTxn::begin_inner(self)
+    }
+}
+
+impl<S: Storage> MvccTxn<S> {
+    /// 创建开启事务的 [`TxnBuilder`]，用于组合隔离级别、选项、时钟和标签
+    pub fn builder(s: Arc<Mutex<S>>) -> TxnBuilder<S> {
+        TxnBuilder::new(s)
+    }
+
+    /// 开启一个新事务，使用快照隔离
+    #[inline]
+    pub fn begin(s: Arc<Mutex<S>>) -> Result<Self> {
+        Self::builder(s).begin()
+    }
+
+    /// 以指定的隔离级别开启一个新事务
+    pub fn begin_with_isolation(s: Arc<Mutex<S>>, isolation: Isolation) -> Result<Self> {
+        Self::builder(s).isolation(isolation).begin()
+    }
+
+    /// 以指定的选项开启一个新事务，使用快照隔离
+    pub fn begin_with_options(s: Arc<Mutex<S>>, options: TxnOptions) -> Result<Self> {
+        Self::builder(s).options(options).begin()
+    }
+
+    /// 开启一个带有标签的新事务，使用快照隔离
+    ///
+    /// 标签保存在事务的 `TxnActive` 记录中，可以通过 [`Mvcc::active_transactions`] 查看，
+    /// 用于在日志中关联事务，或者找出长时间不结束的事务是由哪个模块开启的。
+    pub fn begin_labeled(s: Arc<Mutex<S>>, label: impl Into<String>) -> Result<Self> {
+        Self::builder(s).label(label).begin()
+    }
+
+    /// 开启一个不保留历史版本的事务，适用于不需要快照隔离的嵌入场景
+    ///
+    /// 写入直接覆盖 key 唯一的 `Latest` 记录，而不是追加一个版本记录，因此每个 key 只占用一条记录。
+    /// 事务仍然在 `TxnActive` 中登记，并通过 `TxnWrite` 记录每个 key 写入前的值，提交或回滚仍然是原子的：
+    /// 回滚时恢复写入前的值，提交时清理删除的 key。
+    ///
+    /// 代价是失去快照隔离：读取总是返回最新写入的值，包括其他尚未提交的事务写入的值（脏读）。
+    /// 写入一个被其他活跃事务写入过的 key 返回 [`WriteConflict`]，避免回滚时恢复的值覆盖其他事务的写入。
+    /// `Latest` 记录和版本记录互不可见，同一个存储引擎上的数据应该只用其中一种方式读写。
+    /// 不支持 [`MvccTxn::adopt_writes`]。
+    pub fn begin_non_versioned(s: Arc<Mutex<S>>) -> Result<Self> {
+        Self::builder(s).non_versioned().begin()
+    }
+
+    /// 开启一个新事务，版本号由 `clock` 分配，见 [`TxnBuilder::clock`]
+    pub fn begin_with_clock(s: Arc<Mutex<S>>, clock: Arc<dyn VersionClock>) -> Result<Self> {
+        Self::builder(s).clock(clock).begin()
+    }
+
+    fn begin_inner(builder: TxnBuilder<S>) -> Result<Self> {
+        let TxnBuilder {
+            storage: s,
+            isolation,
+            options,
+            clock,
+            label,
+            versioned,
+        } = builder;
+
+        // 获取当前存储引擎的锁
+        let mut storage = s.lock()?;
+
+        let version = match clock {
+            // 时钟分配的版本号不经过 NextVersion，记录分配过的最大版本号，读已提交的事务刷新快照时才能看到这些版本
+            Some(clock) => {
+                let version = clock.next();
+                let key = MvccKey::ClockVersion.encode()?;
+                let latest = match storage.get(&key)? {
+                    Some(value) => Version::decode(&value)?,
+                    None => Version(0),
+                };
+                if version > latest {
+                    storage.put(&key, &version.encode()?)?;
+                }
+                version
+            }
+            None => {
+                // 获取下一个版本号，如果不存在则从 1 开始
+                let version = if let Some(value) = storage.get(&MvccKey::NextVersion.encode()?)? {
+                    Version::decode(&value)?
+                } else {
+                    Version(1)
+                };
+
+                // 将下一个版本号加 1，写入存储引擎
+                storage.put(
+                    &MvccKey::NextVersion.encode()?,
+                    &version.checked_add(1)?.encode()?,
+                )?;
+                version
+            }
+        };
+
+        // 扫描所有活跃事务
+        let active_versions = Self::scan_active_txn(&mut storage)?;
+
+        // 将新事务加入活跃事务列表，值为开始的时间（只在限制存活时间时记录）和标签
+        // 在扫描之后加入，否则会将自己加入活跃事务列表从而导致自己不可见
+        let meta = TxnMeta {
+            started_at: options.max_age.map(|_| unix_millis()),
+            label,
+        };
+        storage.put(&MvccKey::TxnActive(version).encode()?, &meta.encode()?)?;
+
+        Ok(Self {
+            storage: s.clone(),
+            version,
+            isolation,
+            options,
+            snapshot: Mutex::new(Snapshot {
+                version,
+                active_versions,
+            }),
+            versioned,
+        })
+    }
+
+    /// 事务的版本
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// 获取用于判断可见性的快照
+    ///
+    /// 快照隔离直接返回事务开始时的快照；读已提交则先从存储引擎中重新读取最新的版本号和活跃事务，
+    /// 使得其他事务已经提交的数据对当前事务可见
+    ///
+    /// 最新的版本号取 `NextVersion` 减 1 和时钟分配过的最大版本号中较大的一个，因此同时能看到两种方式分配版本号的事务；
+    /// 快照的版本只增不减，不会看不到之前已经看到的版本。
+    fn snapshot(&self, storage: &mut MutexGuard<S>) -> Result<MutexGuard<'_, Snapshot>> {
+        let mut snapshot = self.snapshot.lock()?;
+        if self.isolation == Isolation::ReadCommitted {
+            if let Some(value) = storage.get(&MvccKey::NextVersion.encode()?)? {
+                let allocated = Version(Version::decode(&value)?.0 - 1);
+                snapshot.version = snapshot.version.max(allocated);
+            }
+            if let Some(value) = storage.get(&MvccKey::ClockVersion.encode()?)? {
+                snapshot.version = snapshot.version.max(Version::decode(&value)?);
+            }
+            snapshot.active_versions = Self::scan_active_txn(storage)?;
+            snapshot.active_versions.remove(&self.version);
+        }
+        Ok(snapshot)
+    }
+
+    /// 事务开始之后的时间超过 [`TxnOptions::max_age`] 时返回 [`Expired`]
+    ///
+    /// 开始的时间从事务的 `TxnActive` 记录中读取，记录已经不存在时不检查。
+    fn check_age(&self, storage: &mut MutexGuard<S>) -> Result<()> {
+        let Some(max_age) = self.options.max_age else {
+            return Ok(());
+        };
+        let Some(value) = storage.get(&MvccKey::TxnActive(self.version).encode()?)? else {
+            return Ok(());
+        };
+        let Some(started_at) = TxnMeta::decode(&value)?.started_at else {
+            return Ok(());
+        };
+        let age = Duration::from_millis(unix_millis().saturating_sub(started_at));
+        match age > max_age {
+            true => Err(Expired { max_age }.into()),
+            false => Ok(()),
+        }
+    }
+
+    /// 查找所有活跃事务
+    fn scan_active_txn(storage: &mut MutexGuard<S>) -> Result<HashSet<Version>> {
+        let mut active_versions = HashSet::new();
+
+        // 扫描前缀为 TxnActive 的 key
+        let mut iter = storage.scan_prefix(&MvccKeyPrefix::TxnActive.encode()?);
+        while let Some((key, _)) = iter.next().transpose()? {
+            // 解码 key，获取事务版本，并加入活跃事务列表
+            if let MvccKey::TxnActive(version) = MvccKey::decode(&key)? {
+                active_versions.insert(version);
+            } else {
+                return Err(Decode {
+                    context: "scanning active transactions, expected a TxnActive key",
+                    bytes: key.to_vec(),
+                }
+                .into());
+            }
+        }
+        Ok(active_versions)
+    }
+
+    /// 将 `versions` 加入快照的活跃事务列表，仅用于测试
+    ///
+    /// 直接修改内存中的快照而不经过 `begin`，用于构造特定的可见性场景。
+    /// 读已提交的事务每次读取都会刷新快照，因此只对快照隔离的事务有意义。
+    #[cfg(test)]
+    fn inject_active(&mut self, versions: &[Version]) {
+        let snapshot = self.snapshot.get_mut().unwrap();
+        snapshot.active_versions.extend(versions.iter().copied());
+    }
+
+    /// 设置快照的版本，大于它的版本都不可见，仅用于测试
+    #[cfg(test)]
+    fn set_current_version(&mut self, version: Version) {
+        self.snapshot.get_mut().unwrap().version = version;
+    }
+
+    /// 版本在快照中是否可见
+    ///
+    /// 当前事务自己写入的版本总是可见，其他版本可见的条件是：
+    ///
+    /// - 版本小于等于快照的版本；
+    /// - 版本不在快照的活跃事务列表中。
+    #[inline]
+    fn is_version_visible(&self, snapshot: &Snapshot, version: Version) -> bool {
+        version == self.version
+            || (version <= snapshot.version && !snapshot.active_versions.contains(&version))
+    }
+
+    /// 检查 `key` 是否存在写冲突
+    ///
+    /// 活跃事务和大于当前版本的事务都不可见
+    /// 取活跃事务的最小值和当前版本加 1 中较小的一个，到可能存在的版本最大值，构成一个范围，其中会包括所有不可见的事务
+    ///
+    /// 首先根据活跃事务和大于当前版本的事务的范围，找到最后一个可能不可见的事务
+    /// 如果这个事务不可见，则说明有不可见的事务写入了 key，存在写冲突
+    ///
+    /// 为什么只需检查最后一个可能不可见的版本即可：
+    /// 若最后版本不可见：直接判定存在写冲突，无需检查更早的版本，因为该版本是当前事务可能冲突的最高版本。
+    /// 若最后版本可见：所有更早的版本要么已被提交（可见），要么会发生写冲突。
+    ///
+    /// 无论隔离级别如何，大于当前版本的事务都视为不可见，否则当前事务写入的版本会被更新的版本覆盖；
+    /// 读已提交时 `snapshot` 是最新的快照，已经提交的事务不会再产生冲突。
+    ///
+    /// 最后一个版本是当前事务自己写入的版本时不算冲突，因此同一个事务可以多次写入同一个 key。
+    /// `ignored` 不为 `None` 时跳过该版本写入的记录，用于合并其他事务的写入。
+    fn has_conflict(
+        &self,
+        storage: &mut MutexGuard<S>,
+        snapshot: &Snapshot,
+        key: &[u8],
+        ignored: Option<Version>,
+    ) -> Result<bool> {
+        if !self.versioned {
+            return self.has_latest_conflict(storage, key);
+        }
+
+        // 范围的起点不能大于当前版本加 1：没有活跃事务时，不大于当前版本的事务都已提交且可见；
+        // 读已提交刷新快照后，活跃事务可能都比当前事务新，它们之前开启并且先提交的事务仍然会冲突
+        let next = self.version.checked_add(1)?;
+        let begin = snapshot
+            .active_versions
+            .iter()
+            .min()
+            .map_or(next, |min| (*min).min(next));
+        let begin_key = MvccKey::Version(key.to_vec(), begin).encode()?;
+        let end_key = MvccKey::Version(key.to_vec(), Version::max()).encode()?;
+
+        // 和 `history` 相同，范围内可能有以 key 为前缀的其他 key 的版本，需要跳过
+        let mut iter = storage.scan(begin_key..=end_key).rev();
+        while let Some((raw_key, _)) = iter.next().transpose()? {
+            if let MvccKey::Version(user_key, version) = MvccKey::decode(&raw_key)? {
+                if user_key != key || Some(version) == ignored {
+                    continue;
+                }
+                if version == self.version {
+                    return Ok(false);
+                }
+                return Ok(version > self.version || !self.is_version_visible(snapshot, version));
+            } else {
+                return Err(Decode {
+                    context: "scanning versions, expected a Version key",
+                    bytes: raw_key.to_vec(),
+                }
+                .into());
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// 不保留历史版本时，`key` 的 `Latest` 记录是否由其他仍然活跃的事务写入
+    fn has_latest_conflict(&self, storage: &mut MutexGuard<S>, key: &[u8]) -> Result<bool> {
+        let Some((writer, _)) = Self::read_latest(storage, key)? else {
+            return Ok(false);
+        };
+        Ok(writer != self.version
+            && storage
+                .get(&MvccKey::TxnActive(writer).encode()?)?
+                .is_some())
+    }
+
+    /// 读取 `key` 的 `Latest` 记录，返回写入的事务和值，值为 `None` 表示删除
+    fn read_latest(
+        storage: &mut MutexGuard<S>,
+        key: &[u8],
+    ) -> Result<Option<(Version, Option<Vec<u8>>)>> {
+        storage
+            .get(&MvccKey::Latest(key.to_vec()).encode()?)?
+            .map(|value| {
+                decode_stored(&value, "decoding latest value")
+                    .with_context(|| ErrorContext::new("reading latest value of").key(key))
+            })
+            .transpose()
+    }
+
+    /// 用户 key 的前缀 `prefix` 在存储中对应的前缀，保留历史版本时为版本记录，否则为 `Latest` 记录
+    fn data_prefix(&self, prefix: &[u8]) -> Result<Vec<u8>> {
+        match self.versioned {
+            true => MvccKeyPrefix::Version(prefix.to_vec()).encode(),
+            false => MvccKeyPrefix::Latest(prefix.to_vec()).encode(),
+        }
+    }
+
+    /// 更新/删除数据的内置函数
+    ///
+    /// - 如果 `value` 为 `None`，则删除 `key` 对应的数据
+    /// - 否则更新 `key` 对应的数据
+    fn write_inner(&self, key: &[u8], value: Option<Vec<u8>>) -> Result<()> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+        self.check_age(&mut storage)?;
+
+        // 检查是否有不可见的版本写入了 key，读已提交时基于最新的快照检查
+        // 关闭冲突检测时信任调用方写入的 key 不会冲突，不做检查
+        if self.options.detect_conflicts {
+            let snapshot = self.snapshot(&mut storage)?;
+            if self.has_conflict(&mut storage, &snapshot, key, None)? {
+                return Err(WriteConflict.into());
+            }
+        }
+
+        self.put_version(&mut storage, key, value)
+    }
+
+    /// 写入 `key` 在当前事务中的版本，不检查写冲突
+    fn put_version(
+        &self,
+        storage: &mut MutexGuard<S>,
+        key: &[u8],
+        value: Option<Vec<u8>>,
+    ) -> Result<()> {
+        if !self.versioned {
+            return self.put_latest(storage, key, value);
+        }
+
+        // 记录新版本写入了哪些 key，用于回滚事务
+        storage.put(
+            &MvccKey::TxnWrite(self.version, key.to_vec()).encode()?,
+            &[],
+        )?;
+
+        // 如果 value 不为 None，则写入新的数据，否则删除数据
+        storage.put(
+            &MvccKey::Version(key.to_vec(), self.version).encode()?,
+            &bincode::serialize(&value)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// 不保留历史版本时，覆盖 `key` 的 `Latest` 记录，不检查写冲突
+    ///
+    /// 当前事务第一次写入 `key` 时，在 TxnWrite 记录中保存写入前的 `Latest` 记录，用于回滚时恢复。
+    fn put_latest(
+        &self,
+        storage: &mut MutexGuard<S>,
+        key: &[u8],
+        value: Option<Vec<u8>>,
+    ) -> Result<()> {
+        let latest_key = MvccKey::Latest(key.to_vec()).encode()?;
+        let txn_write_key = MvccKey::TxnWrite(self.version, key.to_vec()).encode()?;
+        if storage.get(&txn_write_key)?.is_none() {
+            let before = storage.get(&latest_key)?;
+            storage.put(&txn_write_key, &bincode::serialize(&before)?)?;
+        }
+        storage.put(&latest_key, &bincode::serialize(&(self.version, value))?)
+    }
+
+    /// 预先检查一组 key 的写冲突，返回其中当前会发生写冲突的 key，不进行任何写入
+    ///
+    /// 使用和写入时相同的冲突检测逻辑，调用方可以据此在写入前决定继续还是中止事务。
+    pub fn precheck_conflicts(&self, keys: &[Key]) -> Result<Vec<Key>> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        let snapshot = self.snapshot(&mut storage)?;
+        let mut conflicts = Vec::new();
+        for key in keys {
+            if self.has_conflict(&mut storage, &snapshot, key, None)? {
+                conflicts.push(key.clone());
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// 更新 `key` 对应的值
+    #[inline]
+    pub fn set(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.write_inner(key, Some(value.to_vec()))
+    }
+
+    /// 删除 `key` 对应的值
+    #[inline]
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        self.write_inner(key, None)
+    }
+
+    /// 将 `from` 的值移动到 `to`，并删除 `from`，用于修改行的主键
+    ///
+    /// 读取、冲突检查和写入在同一次加锁中完成，两个 key 中任何一个存在写冲突时不做任何写入。
+    /// `from` 没有可见的值时返回 [`KeyNotFound`]；`to` 已经有可见的值时返回 [`KeyExists`]，
+    /// `overwrite` 为真时覆盖 `to` 的值。`from` 和 `to` 相同时不做任何修改。
+    pub fn rename(&self, from: &[u8], to: &[u8], overwrite: bool) -> Result<()> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+        self.check_age(&mut storage)?;
+
+        let snapshot = self.snapshot(&mut storage)?;
+        let value = self
+            .get_visible(&mut storage, &snapshot, from)?
+            .ok_or_else(|| KeyNotFound(from.to_vec()))?;
+        if from == to {
+            return Ok(());
+        }
+        if !overwrite && self.get_visible(&mut storage, &snapshot, to)?.is_some() {
+            return Err(KeyExists(to.to_vec()).into());
+        }
+
+        // 先检查两个 key，避免只写入其中一个
+        if self.options.detect_conflicts {
+            for key in [from, to] {
+                if self.has_conflict(&mut storage, &snapshot, key, None)? {
+                    return Err(WriteConflict.into());
+                }
+            }
+        }
+
+        self.put_version(&mut storage, to, Some(value))?;
+        self.put_version(&mut storage, from, None)
+    }
+
+    /// 获取 `key` 对应的值
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        let snapshot = self.snapshot(&mut storage)?;
+        self.get_visible(&mut storage, &snapshot, key)
+    }
+
+    /// 获取 `key` 在快照 `snapshot` 中可见的值
+    fn get_visible(
+        &self,
+        storage: &mut MutexGuard<S>,
+        snapshot: &Snapshot,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        if !self.versioned {
+            return Ok(Self::read_latest(storage, key)?.and_then(|(_, value)| value));
+        }
+
+        // 设置范围为 0 到快照的版本，因为大于快照版本的事务一定不可见，但当前事务自己的版本总是可见
+        let begin = MvccKey::Version(key.to_vec(), Version::min()).encode()?;
+        let end = MvccKey::Version(key.to_vec(), snapshot.version.max(self.version)).encode()?;
+
+        // 从范围中找到最新的可见版本
+        //
+        // 编码中去掉了 key 的长度，版本号的高位字节通常为 0，所以以 key 加上 0 字节开头的其他 key 的版本
+        // 也可能落在范围内，例如 `("k\0", 5)` 排在 `("k", 5)` 和 `("k", 10)` 之间，需要跳过
+        let mut iter = storage.scan(begin..=end).rev(); // 新版本在后面
+        while let Some((raw_key, value)) = iter.next().transpose()? {
+            if let MvccKey::Version(user_key, version) = MvccKey::decode(&raw_key)? {
+                if user_key != key {
+                    continue;
+                }
+                // 判断是否可见，此处指的是不在活跃事务中，因为范围已经排除了大于当前版本的事务
+                if self.is_version_visible(snapshot, version) {
+                    // 存储的数据为 Option<Vec<u8>>，Option 为 None 表示删除，需要解析
+                    return decode_stored(&value, "decoding version value")
+                        .with_context(|| ErrorContext::new("reading version of").key(&user_key));
+                }
+            } else {
+                return Err(Decode {
+                    context: "scanning versions, expected a Version key",
+                    bytes: raw_key.to_vec(),
+                }
+                .into());
+            }
+        }
+
+        // 没有找到可见版本，返回 None
+        Ok(None)
+    }
+
+    /// 分配序列 `name` 的下一个值，序列不存在时从 1 开始
+    ///
+    /// 序列直接读写存储引擎，不属于事务的快照：分配立即对其他事务生效，事务回滚时也不会归还，
+    /// 因此并发分配的值不会重复，也不会产生写冲突，但是分配的值可能不连续。
+    pub fn next_sequence(&self, name: &[u8]) -> Result<i64> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        let key = MvccKey::Sequence(name.to_vec()).encode()?;
+        let current: i64 = match storage.get(&key)? {
+            Some(value) => decode_stored(&value, "decoding sequence value")?,
+            None => 0,
+        };
+        let next = current.checked_add(1).ok_or(SequenceOverflow)?;
+        storage.put(&key, &bincode::serialize(&next)?)?;
+        Ok(next)
+    }
+
+    /// 将序列 `name` 推进到至少 `value`，之后分配的值都大于 `value`
+    ///
+    /// 和 [`MvccTxn::next_sequence`] 相同，不受事务回滚的影响。
+    pub fn advance_sequence(&self, name: &[u8], value: i64) -> Result<()> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        let key = MvccKey::Sequence(name.to_vec()).encode()?;
+        let current: i64 = match storage.get(&key)? {
+            Some(value) => decode_stored(&value, "decoding sequence value")?,
+            None => 0,
+        };
+        if value > current {
+            storage.put(&key, &bincode::serialize(&value)?)?;
+        }
+        Ok(())
+    }
+
+    /// 删除序列 `name`，之后分配的值重新从 1 开始
+    ///
+    /// 和 [`MvccTxn::next_sequence`] 相同，立即生效，不受事务回滚的影响。
+    pub fn reset_sequence(&self, name: &[u8]) -> Result<()> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        storage.delete(&MvccKey::Sequence(name.to_vec()).encode()?)
+    }
+
+    /// 扫描 `prefix` 开头的所有可见的事务记录
+    ///
+    /// 版本记录的前缀编码去掉了 key 的长度，空前缀只剩下 `Version` 的枚举索引，
+    /// 因此匹配所有用户 key，而不会匹配 `NextVersion`、`TxnActive` 和 `TxnWrite` 记录。
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<(Key, Vec<u8>)>> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        let snapshot = self.snapshot(&mut storage)?;
+        let prefix = self.data_prefix(prefix)?;
+        self.collect_visible(
+            &snapshot,
+            storage.scan_prefix(&prefix),
+            |_| true,
+            usize::MAX,
+            None,
+        )
+    }
+
+    /// 和 [`MvccTxn::scan_prefix`] 相同，但只返回 key，结果按升序排列
+    ///
+    /// 存储引擎的扫描总是同时返回 key 和 value，这里只借用 value 判断版本是否为删除，不会复制或者返回值的内容。
+    pub fn scan_prefix_keys(&self, prefix: &[u8]) -> Result<Vec<Key>> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        let snapshot = self.snapshot(&mut storage)?;
+        let prefix = self.data_prefix(prefix)?;
+        // 和 collect_visible 相同，后面的可见版本覆盖前面的版本，最后为删除的 key 不出现在结果中
+        let mut keys = BTreeMap::new();
+        for item in storage.scan_prefix(&prefix) {
+            let (key, value) = item?;
+            let (k, version) = self.decode_scanned_key(&key)?;
+            if !self.is_version_visible(&snapshot, version) {
+                continue;
+            }
+            let exists = match self.versioned {
+                true => decode_stored::<Option<&[u8]>>(&value, "decoding version value")
+                    .map(|value| value.is_some()),
+                false => decode_stored::<(Version, Option<&[u8]>)>(&value, "decoding latest value")
+                    .map(|(_, value)| value.is_some()),
+            }
+            .with_context(|| ErrorContext::new("reading version of").key(&k))?;
+            keys.insert(k, exists);
+        }
+
+        Ok(keys
+            .into_iter()
+            .filter_map(|(key, exists)| exists.then_some(key))
+            .collect())
+    }
+
+    /// 和 [`MvccTxn::scan_prefix`] 相同，但跳过无法解码的版本记录而不是返回错误
+    ///
+    /// 单个损坏的记录不会导致整个前缀无法读取，跳过的记录在 [`LenientScan::skipped`] 中报告。
+    pub fn scan_prefix_lenient(&self, prefix: &[u8]) -> Result<LenientScan> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        let snapshot = self.snapshot(&mut storage)?;
+        let prefix = self.data_prefix(prefix)?;
+        let mut skipped = Vec::new();
+        let entries = self.collect_visible(
+            &snapshot,
+            storage.scan_prefix(&prefix),
+            |_| true,
+            usize::MAX,
+            Some(&mut skipped),
+        )?;
+        Ok(LenientScan { entries, skipped })
+    }
+
+    /// 返回 `prefix_a` 下存在而 `prefix_b` 下不存在的 key，结果去掉了前缀并按升序排列
+    ///
+    /// 两个前缀在同一个快照下读取，去掉前缀后比较，用于实现 EXCEPT 这样的反连接。
+    pub fn visible_key_diff(&self, prefix_a: &[u8], prefix_b: &[u8]) -> Result<Vec<Key>> {
+        // 获取当前存储引擎的锁，两次扫描期间一直持有，保证读取的是同一个快照
+        let mut storage = self.storage.lock()?;
+
+        let snapshot = self.snapshot(&mut storage)?;
+        let mut scan = |prefix: &[u8]| -> Result<Vec<Key>> {
+            let encoded = self.data_prefix(prefix)?;
+            let visible = self.collect_visible(
+                &snapshot,
+                storage.scan_prefix(&encoded),
+                |_| true,
+                usize::MAX,
+                None,
+            )?;
+            Ok(visible
+                .into_iter()
+                .map(|(key, _)| key[prefix.len()..].to_vec())
+                .collect())
+        };
+        let keys_b: HashSet<Key> = scan(prefix_b)?.into_iter().collect();
+        Ok(scan(prefix_a)?
+            .into_iter()
+            .filter(|key| !keys_b.contains(key))
+            .collect())
+    }
+
+    /// 扫描 key 在 `range` 范围内的所有可见的事务记录，结果按 key 升序排列
+    ///
+    /// 版本记录的编码为 `[Version 索引, key, version]`，其中 key 没有长度信息，
+    /// 因此要求调用方使用的 key 之间互不为前缀（例如 `keycode` 编码的 key），
+    /// 否则一个 key 的版本记录可能落在另一个 key 的版本记录之间，导致范围边界不准确。
+    pub fn scan_range<R>(&self, range: R) -> Result<Vec<(Key, Vec<u8>)>>
+    where
+        R: RangeBounds<Key>,
+    {
+        self.scan_range_limit(range, usize::MAX)
+    }
+
+    /// 和 [`MvccTxn::scan_range`] 相同，但最多返回 `limit` 个 key
+    ///
+    /// 凑满 `limit` 个 key 后立即停止读取存储引擎，调用方可以从最后一个 key 之后继续扫描，实现分批读取。
+    pub fn scan_range_limit<R>(&self, range: R, limit: usize) -> Result<Vec<(Key, Vec<u8>)>>
+    where
+        R: RangeBounds<Key>,
+    {
+        let index = self.data_prefix(&[])?;
+        let with_index = |key: &Key| [index.as_slice(), key].concat();
+
+        // 将用户 key 的范围转换为版本记录的范围
+        // 起始边界总是包含 key 自身的所有版本，排除的情况在解码后过滤
+        let start = match range.start_bound() {
+            Bound::Included(key) | Bound::Excluded(key) => with_index(key),
+            Bound::Unbounded => index.clone(),
+        };
+        let end = match range.end_bound() {
+            // 版本号编码固定为 8 字节，在 key 后追加 8 个 0xFF 即可包含 key 的所有版本
+            Bound::Included(key) => Bound::Included([with_index(key), vec![0xFF; 8]].concat()),
+            Bound::Excluded(key) => Bound::Excluded(with_index(key)),
+            Bound::Unbounded => prefix_end(&index),
+        };
+
+        // 空范围直接返回，同时避免存储引擎在起点大于终点时 panic
+        let is_empty = match &end {
+            Bound::Included(end) => start > *end,
+            Bound::Excluded(end) => start >= *end,
+            Bound::Unbounded => false,
+        };
+        if is_empty {
+            return Ok(Vec::new());
+        }
+
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        let snapshot = self.snapshot(&mut storage)?;
+        self.collect_visible(
+            &snapshot,
+            storage.scan((Bound::Included(start), end)),
+            |key| range.contains(key),
+            limit,
+            None,
+        )
+    }
+
+    /// 从版本记录中收集所有满足 `filter` 的 key 的最新可见值，删除的 key 不会出现在结果中
+    ///
+    /// 同一个 key 的版本记录是连续的，因此遇到新的 key 时前面的 key 都已经确定，
+    /// 此时如果已经收集了 `limit` 个 key，就不再继续读取。
+    ///
+    /// `skipped` 为 `None` 时遇到无法解码的记录返回错误，否则跳过该记录并将存储中的 key 加入 `skipped`。
+    /// 值无法解码的 key 不会出现在结果中，而不是退回到更早的版本。
+    fn collect_visible<I, F>(
+        &self,
+        snapshot: &Snapshot,
+        mut iter: I,
+        filter: F,
+        limit: usize,
+        mut skipped: Option<&mut Vec<Vec<u8>>>,
+    ) -> Result<Vec<(Key, Vec<u8>)>>
+    where
+        I: Iterator<Item = Result<(Vec<u8>, Vec<u8>)>>,
+        F: Fn(&Key) -> bool,
+    {
+        let mut result = BTreeMap::new();
+        let mut last_key: Option<Key> = None;
+        while let Some((key, value)) = iter.next().transpose()? {
+            let (k, version) = match (self.decode_scanned_key(&key), skipped.as_deref_mut()) {
+                (Ok(decoded), _) => decoded,
+                (Err(_), Some(skipped)) => {
+                    skipped.push(key);
+                    continue;
+                }
+                (Err(err), None) => return Err(err),
+            };
+
+            // 如果版本可见，则返回 key-value，之后的过滤中被保留
+            // 如果版本可见但 value 为 None，表示删除，返回 None，并且删除前面的版本中已经存在的 key-value
+            if !self.is_version_visible(snapshot, version) || !filter(&k) {
+                continue;
+            }
+            if last_key.as_ref() != Some(&k) {
+                if result.len() >= limit {
+                    break;
+                }
+                last_key = Some(k.clone());
+            }
+            let value = match self.versioned {
+                true => decode_stored(&value, "decoding version value"),
+                false => decode_stored(&value, "decoding latest value")
+                    .map(|(_, value): (Version, Option<Vec<u8>>)| value),
+            };
+            let value = match (value, skipped.as_deref_mut()) {
+                (Ok(value), _) => value,
+                (Err(_), Some(skipped)) => {
+                    skipped.push(key);
+                    None
+                }
+                (Err(err), None) => {
+                    return Err(err)
+                        .with_context(|| ErrorContext::new("reading version of").key(&k))
+                }
+            };
+            if let Some(value) = value {
+                result.insert(k, value);
+            } else {
+                result.remove(&k);
+            }
+        }
+
+        Ok(result.into_iter().collect())
+    }
+
+    /// 解码扫描数据得到的存储 key，返回用户 key 和版本
+    ///
+    /// 不保留历史版本时扫描的是 Latest 记录，每个 key 只有一条，总是可见，版本视为当前事务的版本。
+    /// 记录的类型和事务不符时返回错误。
+    fn decode_scanned_key(&self, key: &[u8]) -> Result<(Key, Version)> {
+        match MvccKey::decode(key)? {
+            MvccKey::Version(k, version) if self.versioned => Ok((k, version)),
+            MvccKey::Latest(k) if !self.versioned => Ok((k, self.version)),
+            _ if self.versioned => Err(Decode {
+                context: "scanning versions, expected a Version key",
+                bytes: key.to_vec(),
+            }
+            .into()),
+            _ => Err(Decode {
+                context: "scanning latest values, expected a Latest key",
+                bytes: key.to_vec(),
+            }
+            .into()),
+        }
+    }
+
+    /// 将另一个未提交事务 `other` 的写入合并到当前事务中
+    ///
+    /// `other` 的所有 TxnWrite 和 Version 记录都改为当前事务的版本，之后将 `other` 从活跃事务列表中移除，
+    /// 它的写入随当前事务一起提交或回滚，合并之后不能再使用 `other` 对应的事务。
+    ///
+    /// `other` 必须是当前事务之外仍然活跃的事务，已经提交或回滚的事务不能合并。
+    /// 两个事务写入了相同的 key 时无法确定保留哪个值，当前事务写入这些 key 会发生写冲突时同样不能合并，
+    /// 这两种情况都返回写冲突，并且不做任何修改。
+    pub fn adopt_writes(&self, other: Version) -> Result<()> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        if !self.versioned {
+            return Err(InvalidArgument(
+                "Non-versioned transactions cannot adopt writes".to_string(),
+            )
+            .into());
+        }
+        if other == self.version {
+            return Err(InvalidArgument(format!(
+                "Transaction {:?} cannot adopt its own writes",
+                other
+            ))
+            .into());
+        }
+        if storage.get(&MvccKey::TxnActive(other).encode()?)?.is_none() {
+            return Err(Inactive(other).into());
+        }
+
+        // 找到 other 对应的所有 TxnWrite 记录
+        let keys = storage
+            .scan_prefix(&MvccKeyPrefix::TxnWrite(other).encode()?)
+            .map(|item| {
+                let (key, _) = item?;
+                if let MvccKey::TxnWrite(_, key) = MvccKey::decode(&key)? {
+                    Ok(key)
+                } else {
+                    Err(Decode {
+                        context: "scanning txn writes, expected a TxnWrite key",
+                        bytes: key.to_vec(),
+                    }
+                    .into())
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // 先检查所有的 key，避免合并到一半时失败
+        let snapshot = self.snapshot(&mut storage)?;
+        for key in &keys {
+            let written = storage
+                .get(&MvccKey::TxnWrite(self.version, key.clone()).encode()?)?
+                .is_some();
+            if written || self.has_conflict(&mut storage, &snapshot, key, Some(other))? {
+                return Err(WriteConflict.into());
+            }
+        }
+
+        // 将 other 的记录改为当前事务的版本
+        for key in keys {
+            let other_version_key = MvccKey::Version(key.clone(), other).encode()?;
+            let value = storage.get(&other_version_key)?.ok_or_else(|| {
+                Internal(format!(
+                    "Transaction {:?} has no version record for a written key",
+                    other
+                ))
+            })?;
+            storage.put(&MvccKey::TxnWrite(self.version, key.clone()).encode()?, &[])?;
+            storage.put(
+                &MvccKey::Version(key.clone(), self.version).encode()?,
+                &value,
+            )?;
+            storage.delete(&MvccKey::TxnWrite(other, key).encode()?)?;
+            storage.delete(&other_version_key)?;
+        }
+
+        // 将 other 从活跃事务列表中移除
+        storage.delete(&MvccKey::TxnActive(other).encode()?)?;
+
+        Ok(())
+    }
+
+    /// 提交事务
+    ///
+    /// 对于提交事务，实际上是让这个事务的修改对后续新开启的事务是可见的。
+    /// 因此，只需要将当前事务对应的所有 TxnWrite 记录，以及当前事务在活跃事务列表中的记录删除即可。
+    pub fn commit(&self) -> Result<()> {
+        self.commit_inner(false).map(|_| ())
+    }
+
+    /// 提交事务，并返回事务写入的操作日志，按照 key 升序排列
+    ///
+    /// 每个 key 只记录事务最后一次写入的值，日志可以通过 [`Mvcc::apply_operations`] 重放到另一个存储引擎中。
+    pub fn commit_with_log(&self) -> Result<Vec<Operation>> {
+        self.commit_inner(true)
+    }
+
+    fn commit_inner(&self, log: bool) -> Result<Vec<Operation>> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+
+        // 超过存活时间的事务不能提交，回滚后返回错误，不会继续留在活跃事务列表中
+        if let Err(e) = self.check_age(&mut storage) {
+            self.rollback_inner(&mut storage)?;
+            return Err(e);
+        }
+
+        // 分批删除当前事务对应的所有 TxnWrite 记录，需要日志时从当前事务写入的版本记录中读取
+        let mut operations = Vec::new();
+        self.drain_txn_writes(&mut storage, |storage, key, _| {
+            let value = if self.versioned {
+                if !log {
+                    return Ok(());
+                }
+                storage
+                    .get(&MvccKey::Version(key.clone(), self.version).encode()?)?
+                    .map(|value| decode_stored::<Option<Vec<u8>>>(&value, "decoding version value"))
+                    .transpose()?
+                    .flatten()
+            } else {
+                // 不保留历史版本时，删除的 key 在提交后不再需要保留记录
+                let latest_key = MvccKey::Latest(key.clone()).encode()?;
+                let value = storage
+                    .get(&latest_key)?
+                    .map(|value| {
+                        decode_stored::<(Version, Option<Vec<u8>>)>(&value, "decoding latest value")
+                    })
+                    .transpose()?
+                    .and_then(|(_, value)| value);
+                if value.is_none() {
+                    storage.delete(&latest_key)?;
+                }
+                value
+            };
+            if log {
+                operations.push(match value {
+                    Some(value) => Operation::Set(key, value),
+                    None => Operation::Delete(key),
+                });
+            }
+            Ok(())
+        })?;
+
+        // 将当前事务从活跃事务列表中移除
+        storage.delete(&MvccKey::TxnActive(self.version).encode()?)?;
+
+        Ok(operations)
+    }
+
+    /// 回滚事务
+    pub fn rollback(&self) -> Result<()> {
+        // 获取当前存储引擎的锁
+        let mut storage = self.storage.lock()?;
+        self.rollback_inner(&mut storage)
+    }
+
+    fn rollback_inner(&self, storage: &mut MutexGuard<S>) -> Result<()> {
+        // 分批删除当前事务对应的所有 TxnWrite 记录，以及其中记录的 key 对应的 Version 记录；
+        // 不保留历史版本时，用 TxnWrite 记录中保存的写入前的值恢复 Latest 记录
+        self.drain_txn_writes(storage, |storage, key, before| {
+            if self.versioned {
+                return storage.delete(&MvccKey::Version(key, self.version).encode()?);
+            }
+            let latest_key = MvccKey::Latest(key).encode()?;
+            match decode_stored::<Option<Vec<u8>>>(&before, "decoding latest value before write")? {
+                Some(before) => storage.put(&latest_key, &before),
+                None => storage.delete(&latest_key),
+            }
+        })?;
+
+        // 将当前事务从活跃事务列表中移除
+        storage.delete(&MvccKey::TxnActive(self.version).encode()?)?;
+
+        Ok(())
+    }
+
+    /// 按照 key 升序分批删除当前事务的 TxnWrite 记录，每删除一条记录以其中记录的 key 和记录的值调用 `f`
+    ///
+    /// 迭代器存活期间不能修改存储引擎，因此每次最多读取 [`TXN_WRITE_BATCH_SIZE`] 条记录后释放迭代器，
+    /// 删除这一批后再从最后一条记录之后继续扫描，内存占用和事务写入的 key 的数量无关。
+    fn drain_txn_writes<F>(&self, storage: &mut S, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut S, Key, Vec<u8>) -> Result<()>,
+    {
+        let prefix = MvccKeyPrefix::TxnWrite(self.version).encode()?;
+        // 版本号的最后一个字节可能是 0xFF，不能直接加 1
+        let end = prefix_end(&prefix);
+        let mut start = Bound::Included(prefix);
+        loop {
+            let batch = storage
+                .scan((start.clone(), end.clone()))
+                .take(TXN_WRITE_BATCH_SIZE)
+                .map(|item| {
+                    let (txn_key, value) = item?;
+                    if let MvccKey::TxnWrite(_, key) = MvccKey::decode(&txn_key)? {
+                        Ok((txn_key, key, value))
+                    } else {
+                        Err(Decode {
+                            context: "scanning txn writes, expected a TxnWrite key",
+                            bytes: txn_key.to_vec(),
+                        }
+                        .into())
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let len = batch.len();
+            for (txn_key, key, value) in batch {
+                storage.delete(&txn_key)?;
+                f(storage, key, value)?;
+                start = Bound::Excluded(txn_key);
+            }
+            // 不足一批说明已经删除完
+            if len < TXN_WRITE_BATCH_SIZE {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        codec::{BincodeCodec, JsonCodec},
+        parser::ast::{Constant, Operation},
+        schema::{Column, DataType, IndexDef, Value},
+        storage::{
+            disk::DiskStorage,
+            faulty::{Fault, FaultyStorage},
+            memory::MemoryStorage,
+            recording::{self, RecordingStorage},
+        },
+        Error, ErrorCode, Result, StorageError,
+    };
+
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_mvcckey() -> Result<()> {
+        let key_1 = MvccKey::NextVersion;
+        let encoded_1 = key_1.encode()?;
+        let decoded_1 = MvccKey::decode(&encoded_1)?;
+        assert_eq!(key_1, decoded_1);
+
+        let key_2 = MvccKey::TxnActive(1.into());
+        let encoded_2 = key_2.encode()?;
+        let decoded_2 = MvccKey::decode(&encoded_2)?;
+        assert_eq!(key_2, decoded_2);
+
+        let key_3 = MvccKey::TxnWrite(1.into(), b"key".to_vec());
+        let encoded_3 = key_3.encode()?;
+        let decoded_3 = MvccKey::decode(&encoded_3)?;
+        assert_eq!(key_3, decoded_3);
+
+        let key_4 = MvccKey::Version(b"key".to_vec(), 1.into());
         let encoded_4 = key_4.encode()?;
         let decoded_4 = MvccKey::decode(&encoded_4)?;
         assert_eq!(key_4, decoded_4);
 
-        assert_ne!(encoded_1, encoded_2);
-        assert_ne!(encoded_1, encoded_3);
-        assert_ne!(encoded_1, encoded_4);
-        assert_ne!(encoded_2, encoded_3);
-        assert_ne!(encoded_2, encoded_4);
-        assert_ne!(encoded_3, encoded_4);
+        let key_5 = MvccKey::Sequence(b"key".to_vec());
+        let encoded_5 = key_5.encode()?;
+        let decoded_5 = MvccKey::decode(&encoded_5)?;
+        assert_eq!(key_5, decoded_5);
+
+        assert_ne!(encoded_1, encoded_2);
+        assert_ne!(encoded_1, encoded_3);
+        assert_ne!(encoded_1, encoded_4);
+        assert_ne!(encoded_2, encoded_3);
+        assert_ne!(encoded_2, encoded_4);
+        assert_ne!(encoded_3, encoded_4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mvcckey_prefix() -> Result<()> {
+        let key_prefix_1 = MvccKeyPrefix::TxnActive;
+        let encoded_prefix_1 = key_prefix_1.encode()?;
+
+        let key_1 = MvccKey::TxnActive(114514.into());
+        let encoded_1 = key_1.encode()?;
+        assert!(encoded_1.starts_with(&encoded_prefix_1));
+
+        let key_prefix_2 = MvccKeyPrefix::Version(b"ke".to_vec());
+        let encoded_prefix_2 = key_prefix_2.encode()?;
+
+        let key_2 = MvccKey::Version(b"key".to_vec(), 114514.into());
+        let encoded_2 = key_2.encode()?;
+
+        assert!(encoded_2.starts_with(&encoded_prefix_2));
+        assert!(!encoded_2.starts_with(&encoded_prefix_1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_encoding() -> Result<()> {
+        // 版本号使用定长大端编码，编码的字节序和版本号的顺序一致
+        assert_eq!(Version(42).encode()?, vec![0, 0, 0, 0, 0, 0, 0, 42]);
+        let versions = [0, 1, 255, 256, 65535, 1 << 32, u64::MAX];
+        for pair in versions.windows(2) {
+            let lhs = MvccKey::Version(b"key".to_vec(), pair[0].into()).encode()?;
+            let rhs = MvccKey::Version(b"key".to_vec(), pair[1].into()).encode()?;
+            assert!(lhs < rhs, "{} < {}", pair[0], pair[1]);
+        }
+
+        // 两个独立的存储引擎中，相同的操作写入的 key 的编码完全相同
+        let raw_keys = |storage: MemoryStorage| -> Result<Vec<Vec<u8>>> {
+            let mvcc = Mvcc::new(storage);
+            let tx_1 = mvcc.start_txn()?;
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"key1", b"val1")?;
+            tx_1.set(b"key2", b"val2")?;
+            tx_2.commit()?;
+
+            let mut storage = mvcc.storage.lock()?;
+            let keys = storage
+                .scan(..)
+                .map(|item| item.map(|(key, _)| key))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(keys)
+        };
+        let keys = raw_keys(MemoryStorage::new())?;
+        assert_eq!(keys, raw_keys(MemoryStorage::new())?);
+        assert!(keys.contains(&MvccKey::Version(b"key1".to_vec(), 2.into()).encode()?));
+        assert!(keys.contains(&MvccKey::TxnWrite(1.into(), b"key2".to_vec()).encode()?));
+        assert!(keys.contains(&MvccKey::TxnActive(1.into()).encode()?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_error() -> Result<()> {
+        let mvcc = Mvcc::new(MemoryStorage::new());
+        let tx = mvcc.start_txn()?;
+        tx.set(b"key", b"value")?;
+
+        // 在当前事务的 TxnWrite 前缀下写入一个无法解码的 key
+        let mut bad_key = MvccKeyPrefix::TxnWrite(tx.version).encode()?;
+        bad_key.push(0xFF);
+        mvcc.storage.lock()?.put(&bad_key, &[])?;
+
+        assert_eq!(
+            tx.commit(),
+            Err(Decode {
+                context: "decoding mvcc TxnWrite key",
+                bytes: bad_key,
+            }
+            .into())
+        );
+
+        // 无法解码的版本值在错误中带上用户 key
+        mvcc.storage.lock()?.put(
+            &MvccKey::Version(b"bad".to_vec(), Version::min()).encode()?,
+            &[2],
+        )?;
+        let tx = mvcc.start_txn()?;
+        for err in [
+            tx.get(b"bad").unwrap_err(),
+            tx.scan_prefix(b"").unwrap_err(),
+        ] {
+            assert_eq!(
+                err.to_string(),
+                "while reading version of key 0x626164: Decode error when decoding version value: 0x02"
+            );
+            assert!(matches!(err.root(), Error::Storage(Decode { .. })));
+        }
+
+        // 过短的 Version key 不应导致 panic
+        assert!(matches!(
+            MvccKey::decode(&[3, 0, 0, 0, 1]),
+            Err(Error::Storage(Decode { .. }))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fault_classification() -> Result<()> {
+        let mvcc = Mvcc::new(FaultyStorage::new(MemoryStorage::new()));
+        let set_fault = |fault| {
+            mvcc.storage
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .set_fault(fault)
+        };
+        let tx = mvcc.start_txn()?;
+        tx.set(b"key", b"value")?;
+        tx.next_sequence(b"seq")?;
+        tx.commit()?;
+
+        // 存储中损坏的版本值和序列值是数据损坏，而不是用户数据的编码错误
+        let tx = mvcc.start_txn()?;
+        set_fault(Some(Fault::CorruptValues));
+        for (err, context) in [
+            (tx.get(b"key").unwrap_err(), "decoding version value"),
+            (tx.scan_prefix(b"").unwrap_err(), "decoding version value"),
+            (
+                tx.next_sequence(b"seq").unwrap_err(),
+                "decoding sequence value",
+            ),
+        ] {
+            assert!(
+                matches!(err.root(), Error::Storage(Decode { context: c, .. }) if *c == context),
+                "{err:?}"
+            );
+            assert_eq!(err.code(), ErrorCode::DataCorrupted);
+        }
+
+        // 存储中损坏的 key 同样是数据损坏
+        set_fault(Some(Fault::CorruptKeys));
+        assert!(matches!(
+            tx.scan_prefix(b"").unwrap_err().root(),
+            Error::Storage(Decode { .. })
+        ));
+        set_fault(None);
+        assert_eq!(tx.get(b"key")?, Some(b"value".to_vec()));
+        tx.commit()?;
+
+        // 持有锁时 panic 使锁中毒，之后的操作返回 EnginePanicked，而不是内部错误
+        let tx = mvcc.start_txn()?;
+        set_fault(Some(Fault::Panic));
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| tx.set(b"key", b"v")));
+        assert!(result.is_err());
+        set_fault(None);
+        for err in [tx.get(b"key").unwrap_err(), mvcc.start_txn().err().unwrap()] {
+            assert!(matches!(err, Error::EnginePanicked(_)), "{err:?}");
+            assert_eq!(err.code(), ErrorCode::EnginePanicked);
+            assert!(err.to_string().contains("reopen the database"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_fuzz() {
+        // 固定种子的 xorshift 伪随机数，保证失败时可以复现
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..20_000 {
+            let len = (next() % 48) as usize;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            // 一半的输入使用合法的枚举索引，覆盖各个变体的解码逻辑
+            if len >= 4 && next() % 2 == 0 {
+                bytes[..4].copy_from_slice(&[0, 0, 0, (next() % 7) as u8]);
+            }
+            // 只要求返回结果而不 panic，能够解码的 key 重新编码后可以再次解码
+            if let Ok(key) = MvccKey::decode(&bytes) {
+                assert_eq!(MvccKey::decode(&key.encode().unwrap()).unwrap(), key);
+            }
+            if let Ok(version) = Version::decode(&bytes) {
+                assert_eq!(version.encode().unwrap(), bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_version_exhausted() -> Result<()> {
+        assert_eq!(Version(41).checked_add(1)?, Version(42));
+        assert_eq!(Version::max().checked_add(1), Err(VersionExhausted.into()));
+
+        // 解码错误中包含 key 的种类和十六进制的原始字节
+        assert_eq!(
+            MvccKey::decode(&[0, 0, 0, 2, 0xFF])
+                .unwrap_err()
+                .to_string(),
+            "Decode error when decoding mvcc TxnWrite key: 0x00000002ff"
+        );
+        assert_eq!(
+            Version::decode(&[1, 2]).unwrap_err().to_string(),
+            "Decode error when decoding version: 0x0102"
+        );
+
+        // 下一个版本号已经用尽时无法开启事务
+        let mvcc = Mvcc::new(MemoryStorage::new());
+        mvcc.storage
+            .lock()?
+            .put(&MvccKey::NextVersion.encode()?, &Version::max().encode()?)?;
+        assert!(matches!(
+            mvcc.start_txn(),
+            Err(Error::Storage(StorageError::VersionExhausted))
+        ));
+
+        // 版本号的最后一个字节为 0xFF 时，提交仍然只处理自己的写入记录
+        let mvcc = Mvcc::new(MemoryStorage::new());
+        mvcc.storage
+            .lock()?
+            .put(&MvccKey::NextVersion.encode()?, &Version(0xFF).encode()?)?;
+        let tx_1 = mvcc.start_txn()?;
+        let tx_2 = mvcc.start_txn()?;
+        assert_eq!(
+            (tx_1.version(), tx_2.version()),
+            (Version(0xFF), Version(0x100))
+        );
+        tx_1.set(b"a", b"1")?;
+        tx_2.set(b"b", b"2")?;
+        tx_1.commit()?;
+        tx_2.rollback()?;
+        let tx_3 = mvcc.start_txn()?;
+        assert_eq!(tx_3.scan_prefix(b"")?, vec![(b"a".to_vec(), b"1".to_vec())]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_prefix_lenient() -> Result<()> {
+        let mvcc = Mvcc::new(MemoryStorage::new());
+        let tx_1 = mvcc.start_txn()?;
+        tx_1.set(b"users/1", b"alice")?;
+        tx_1.set(b"users/2", b"bob")?;
+        tx_1.set(b"users/3", b"carol")?;
+        tx_1.commit()?;
+
+        // 在前缀下写入一个过短而无法解码的 key，以及一个值无法解码的新版本
+        let mut bad_key = MvccKeyPrefix::Version(b"users/".to_vec()).encode()?;
+        bad_key.push(b'x');
+        let bad_value =
+            MvccKey::Version(b"users/2".to_vec(), tx_1.version().checked_add(1)?).encode()?;
+        {
+            let mut storage = mvcc.storage.lock()?;
+            storage.put(&bad_key, b"")?;
+            storage.put(&bad_value, &[2])?;
+        }
+
+        let tx_2 = mvcc.start_txn()?;
+        assert!(tx_2.scan_prefix(b"users/").is_err());
+
+        // 跳过损坏的记录，值无法解码的 key 不会退回到更早的版本
+        let scan = tx_2.scan_prefix_lenient(b"users/")?;
+        assert_eq!(
+            scan.entries,
+            vec![
+                (b"users/1".to_vec(), b"alice".to_vec()),
+                (b"users/3".to_vec(), b"carol".to_vec()),
+            ]
+        );
+        assert_eq!(scan.skipped, vec![bad_value, bad_key]);
+
+        // 没有损坏的记录时和 scan_prefix 相同
+        assert_eq!(
+            tx_2.scan_prefix_lenient(b"users/3")?,
+            LenientScan {
+                entries: tx_2.scan_prefix(b"users/3")?,
+                skipped: Vec::new(),
+            }
+        );
+
+        Ok(())
+    }
+
+    macro_rules! test_all_storage {
+        ($code:expr) => {
+            let file = NamedTempFile::new().unwrap();
+            let storage = DiskStorage::new(file.path()).unwrap();
+            $code(&Mvcc::new(storage))?;
+
+            let storage = MemoryStorage::new();
+            $code(&Mvcc::new(storage))?;
+        };
+    }
+
+    #[test]
+    fn test_version_visibility() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"key", b"val1")?;
+            tx_1.commit()?;
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"key", b"val2")?;
+            tx_2.commit()?;
+
+            let mut tx = mvcc.start_txn()?;
+            let visible = |tx: &MvccTxn<_>, version: u64| {
+                tx.is_version_visible(&tx.snapshot.lock().unwrap(), version.into())
+            };
+            assert_eq!(tx.version, 3.into());
+            assert!(visible(&tx, 1) && visible(&tx, 2) && visible(&tx, 3));
+            assert!(!visible(&tx, 4));
+
+            // 活跃事务不可见，读取时跳过它的版本，退回到更早的可见版本
+            tx.inject_active(&[2.into(), 5.into()]);
+            assert!(visible(&tx, 1) && !visible(&tx, 2) && !visible(&tx, 5));
+            assert_eq!(tx.get(b"key")?, Some(b"val1".to_vec()));
+            assert_eq!(
+                tx.scan_prefix(b"k")?,
+                vec![(b"key".to_vec(), b"val1".to_vec())]
+            );
+
+            // 活跃事务写入的 key 会导致写冲突，即使它的版本已经提交
+            assert_eq!(tx.set(b"key", b"val3"), Err(WriteConflict.into()));
+
+            // 快照版本之后的版本不可见，所有版本都不可见时 key 不存在
+            tx.set_current_version(0.into());
+            assert!(!visible(&tx, 1) && !visible(&tx, 2));
+            assert_eq!(tx.get(b"key")?, None);
+            assert!(tx.scan_prefix(b"k")?.is_empty());
+
+            // 当前事务自己的版本总是可见，即使它大于快照版本或者在活跃事务列表中
+            tx.inject_active(&[3.into()]);
+            assert!(visible(&tx, 3));
+            tx.set(b"other", b"val")?;
+            assert_eq!(tx.get(b"other")?, Some(b"val".to_vec()));
+
+            // 快照版本可以大于当前事务的版本，之后提交的事务变为可见
+            let tx_4 = mvcc.start_txn()?;
+            tx_4.set(b"key", b"val4")?;
+            tx_4.commit()?;
+            tx.set_current_version(4.into());
+            assert!(visible(&tx, 4));
+            assert_eq!(tx.get(b"key")?, Some(b"val4".to_vec()));
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx0 = mvcc.start_txn()?;
+            tx0.set(b"key1", b"val1")?;
+            tx0.set(b"key2", b"val2")?;
+            tx0.set(b"key2", b"val3")?;
+            tx0.set(b"key3", b"val4")?;
+            tx0.delete(b"key3")?;
+            tx0.commit()?;
+
+            let tx1 = mvcc.start_txn()?;
+            assert_eq!(tx1.get(b"key1")?, Some(b"val1".to_vec()));
+            assert_eq!(tx1.get(b"key2")?, Some(b"val3".to_vec()));
+            assert_eq!(tx1.get(b"key3")?, None);
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_isolation() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"key1", b"val1")?;
+            tx_1.set(b"key2", b"val2")?;
+            tx_1.set(b"key2", b"val3")?;
+            tx_1.set(b"key3", b"val4")?;
+            tx_1.commit()?;
+
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"key1", b"val2")?;
+
+            let tx_3 = mvcc.start_txn()?;
+
+            let tx_4 = mvcc.start_txn()?;
+            tx_4.set(b"key2", b"val4")?;
+            tx_4.delete(b"key3")?;
+            tx_4.commit()?;
+
+            assert_eq!(tx_3.get(b"key1")?, Some(b"val1".to_vec()));
+            assert_eq!(tx_3.get(b"key2")?, Some(b"val3".to_vec()));
+            assert_eq!(tx_3.get(b"key3")?, Some(b"val4".to_vec()));
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"key1", b"val1")?;
+            tx_1.set(b"key2", b"val2")?;
+            tx_1.set(b"key2", b"val3")?;
+            tx_1.set(b"key3", b"val4")?;
+            tx_1.set(b"key4", b"val5")?;
+            tx_1.commit()?;
+
+            let tx_2 = mvcc.start_txn()?;
+            let tx_3 = mvcc.start_txn()?;
+
+            tx_2.set(b"key1", b"val1-1")?;
+            tx_2.set(b"key2", b"val3-1")?;
+            tx_2.set(b"key2", b"val3-2")?;
+
+            tx_3.set(b"key3", b"val4-1")?;
+            tx_3.set(b"key4", b"val5-1")?;
+
+            tx_2.commit()?;
+            tx_3.commit()?;
+
+            let tx_4 = mvcc.start_txn()?;
+            assert_eq!(tx_4.get(b"key1")?, Some(b"val1-1".to_vec()));
+            assert_eq!(tx_4.get(b"key2")?, Some(b"val3-2".to_vec()));
+            assert_eq!(tx_4.get(b"key3")?, Some(b"val4-1".to_vec()));
+            assert_eq!(tx_4.get(b"key4")?, Some(b"val5-1".to_vec()));
+
+            Ok(())
+        });
 
         Ok(())
     }
 
     #[test]
-    fn test_mvcckey_prefix() -> Result<()> {
-        let key_prefix_1 = MvccKeyPrefix::TxnActive;
-        let encoded_prefix_1 = key_prefix_1.encode()?;
+    fn test_write_conflict() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"key1", b"val1")?;
+            tx_1.set(b"key2", b"val2")?;
+            tx_1.set(b"key2", b"val3")?;
+            tx_1.set(b"key3", b"val4")?;
+            tx_1.set(b"key4", b"val5")?;
+            tx_1.commit()?;
 
-        let key_1 = MvccKey::TxnActive(114514.into());
-        let encoded_1 = key_1.encode()?;
-        assert!(encoded_1.starts_with(&encoded_prefix_1));
+            let tx_2 = mvcc.start_txn()?;
+            let tx_3 = mvcc.start_txn()?;
 
-        let key_prefix_2 = MvccKeyPrefix::Version(b"ke".to_vec());
-        let encoded_prefix_2 = key_prefix_2.encode()?;
+            tx_2.set(b"key1", b"val1-1")?;
+            tx_2.set(b"key1", b"val1-2")?;
 
-        let key_2 = MvccKey::Version(b"key".to_vec(), 114514.into());
-        let encoded_2 = key_2.encode()?;
+            assert_eq!(tx_3.set(b"key1", b"val1-3"), Err(WriteConflict.into()));
 
-        assert!(encoded_2.starts_with(&encoded_prefix_2));
-        assert!(!encoded_2.starts_with(&encoded_prefix_1));
+            let tx_4 = mvcc.start_txn()?;
+            tx_4.set(b"key5", b"val6")?;
+            tx_4.commit()?;
+
+            assert_eq!(tx_1.set(b"key5", b"val6-1"), Err(WriteConflict.into()));
+
+            Ok(())
+        });
 
         Ok(())
     }
 
-    macro_rules! test_all_storage {
-        ($code:expr) => {
-            let file = NamedTempFile::new().unwrap();
-            let storage = DiskStorage::new(file.path()).unwrap();
-            $code(&Mvcc::new(storage))?;
+    #[test]
+    fn test_conflict_without_active_txns() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_0 = mvcc.start_txn()?;
+            tx_0.set(b"key1", b"val1")?;
+            tx_0.commit()?;
 
-            let storage = MemoryStorage::new();
-            $code(&Mvcc::new(storage))?;
-        };
+            // 开启时没有活跃事务，更早提交的事务不冲突
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"key1", b"val1-1")?;
+
+            // 之后开启并且先提交的事务写入的 key 冲突
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"key2", b"val2")?;
+            tx_2.commit()?;
+            assert_eq!(tx_1.set(b"key2", b"val2-1"), Err(WriteConflict.into()));
+            tx_1.commit()?;
+
+            // 读已提交时快照中的活跃事务都比当前事务新，先提交的更新的事务仍然冲突
+            let tx_rc = mvcc.start_txn_with_isolation(Isolation::ReadCommitted)?;
+            let tx_3 = mvcc.start_txn()?;
+            tx_3.set(b"key3", b"val3")?;
+            tx_3.commit()?;
+            let tx_4 = mvcc.start_txn()?;
+            assert_eq!(tx_rc.set(b"key3", b"val3-1"), Err(WriteConflict.into()));
+            tx_rc.set(b"key1", b"val1-2")?;
+            tx_4.rollback()?;
+            tx_rc.commit()?;
+
+            let tx_5 = mvcc.start_txn()?;
+            assert_eq!(tx_5.get(b"key1")?, Some(b"val1-2".to_vec()));
+            assert_eq!(tx_5.get(b"key2")?, Some(b"val2".to_vec()));
+            assert_eq!(tx_5.get(b"key3")?, Some(b"val3".to_vec()));
+
+            Ok(())
+        });
+
+        Ok(())
     }
 
     #[test]
-    fn test_read() -> Result<()> {
+    fn test_write_same_key_twice() -> Result<()> {
         test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
-            let tx0 = mvcc.start_txn()?;
-            tx0.set(b"key1", b"val1")?;
-            tx0.set(b"key2", b"val2")?;
-            tx0.set(b"key2", b"val3")?;
-            tx0.set(b"key3", b"val4")?;
-            tx0.delete(b"key3")?;
-            tx0.commit()?;
+            // 存在更早的活跃事务时，冲突检查的范围包含当前事务自己的版本
+            let tx_1 = mvcc.start_txn()?;
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"key1", b"val1")?;
+            tx_2.set(b"key1", b"val2")?;
+            tx_2.delete(b"key1")?;
+            tx_2.set(b"key1", b"val3")?;
+            assert_eq!(
+                tx_2.precheck_conflicts(&[b"key1".to_vec()])?,
+                Vec::<Key>::new()
+            );
+            assert_eq!(tx_2.get(b"key1")?, Some(b"val3".to_vec()));
 
-            let tx1 = mvcc.start_txn()?;
-            assert_eq!(tx1.get(b"key1")?, Some(b"val1".to_vec()));
-            assert_eq!(tx1.get(b"key2")?, Some(b"val3".to_vec()));
-            assert_eq!(tx1.get(b"key3")?, None);
+            // 其他事务写入同一个 key 仍然冲突
+            assert_eq!(tx_1.set(b"key1", b"val1-1"), Err(WriteConflict.into()));
+            let tx_3 = mvcc.start_txn()?;
+            assert_eq!(tx_3.set(b"key1", b"val1-2"), Err(WriteConflict.into()));
+            tx_2.commit()?;
+
+            // 读已提交的事务同样可以多次写入同一个 key
+            let tx_4 = mvcc.start_txn_with_isolation(Isolation::ReadCommitted)?;
+            tx_4.set(b"key1", b"val4")?;
+            tx_4.set(b"key1", b"val5")?;
+            tx_4.commit()?;
+
+            let tx_5 = mvcc.start_txn()?;
+            assert_eq!(tx_5.get(b"key1")?, Some(b"val5".to_vec()));
 
             Ok(())
         });
@@ -517,28 +2582,235 @@ mod tests {
     }
 
     #[test]
-    fn test_isolation() -> Result<()> {
+    fn test_adjacent_keys() -> Result<()> {
         test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            // 以 "k" 加上 0 字节开头的 key 的版本编码后落在 "k" 的版本范围内
             let tx_1 = mvcc.start_txn()?;
-            tx_1.set(b"key1", b"val1")?;
-            tx_1.set(b"key2", b"val2")?;
-            tx_1.set(b"key2", b"val3")?;
-            tx_1.set(b"key3", b"val4")?;
+            tx_1.set(b"k\0", b"1")?;
+            tx_1.set(b"k\0\0", b"2")?;
             tx_1.commit()?;
 
             let tx_2 = mvcc.start_txn()?;
-            tx_2.set(b"key1", b"val2")?;
+            let tx_3 = mvcc.start_txn()?;
+            assert_eq!(tx_2.get(b"k")?, None);
+
+            // 其他事务写入相邻的 key 不是冲突
+            tx_3.set(b"k\0", b"3")?;
+            tx_3.delete(b"k\0\0")?;
+            tx_2.set(b"k", b"4")?;
+            assert_eq!(tx_2.get(b"k")?, Some(b"4".to_vec()));
+            assert_eq!(tx_2.get(b"k\0")?, Some(b"1".to_vec()));
+            tx_3.commit()?;
+            tx_2.commit()?;
+
+            let tx_4 = mvcc.start_txn()?;
+            assert_eq!(tx_4.get(b"k")?, Some(b"4".to_vec()));
+            assert_eq!(tx_4.get(b"k\0")?, Some(b"3".to_vec()));
+            assert_eq!(tx_4.get(b"k\0\0")?, None);
+            tx_4.delete(b"k")?;
+            assert_eq!(tx_4.get(b"k")?, None);
+            assert_eq!(tx_4.get(b"k\0")?, Some(b"3".to_vec()));
+            tx_4.commit()?;
+
+            Ok(())
+        });
+
+        Ok(())
+    }
 
+    #[test]
+    fn test_adopt_writes() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_0 = mvcc.start_txn()?;
+            tx_0.set(b"key1", b"val1")?;
+            tx_0.set(b"key3", b"val3")?;
+            tx_0.commit()?;
+
+            // tx_2 的写入合并到 tx_1 中
+            let tx_1 = mvcc.start_txn()?;
+            let tx_2 = mvcc.start_txn()?;
+            tx_1.set(b"key1", b"val1-1")?;
+            tx_2.set(b"key2", b"val2-2")?;
+            tx_2.delete(b"key3")?;
             let tx_3 = mvcc.start_txn()?;
+            tx_1.adopt_writes(tx_2.version())?;
+            assert_eq!(tx_1.get(b"key2")?, Some(b"val2-2".to_vec()));
+            assert_eq!(tx_1.get(b"key3")?, None);
+
+            // 合并之后的写入属于 tx_1，其他事务写入这些 key 仍然冲突
+            assert_eq!(tx_3.set(b"key2", b"val2-3"), Err(WriteConflict.into()));
+            assert_eq!(tx_3.get(b"key3")?, Some(b"val3".to_vec()));
+            tx_1.set(b"key2", b"val2-1")?;
+            tx_1.commit()?;
 
             let tx_4 = mvcc.start_txn()?;
-            tx_4.set(b"key2", b"val4")?;
-            tx_4.delete(b"key3")?;
+            assert_eq!(
+                tx_4.scan_prefix(b"key")?,
+                vec![
+                    (b"key1".to_vec(), b"val1-1".to_vec()),
+                    (b"key2".to_vec(), b"val2-1".to_vec()),
+                ]
+            );
+            assert_eq!(tx_3.get(b"key2")?, None);
+
+            // 合并之后随当前事务一起回滚
+            let tx_5 = mvcc.start_txn()?;
+            let tx_6 = mvcc.start_txn()?;
+            tx_6.set(b"key4", b"val4")?;
+            tx_5.adopt_writes(tx_6.version())?;
+            tx_5.rollback()?;
+            let tx_7 = mvcc.start_txn()?;
+            assert_eq!(tx_7.get(b"key4")?, None);
+            tx_7.set(b"key4", b"val4-7")?;
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_adopt_writes_error() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            // 不能合并自己、已经提交的事务和不存在的事务
+            let tx_1 = mvcc.start_txn()?;
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"key1", b"val1")?;
+            tx_2.commit()?;
+            assert!(tx_1.adopt_writes(tx_1.version()).is_err());
+            assert!(tx_1.adopt_writes(tx_2.version()).is_err());
+            assert!(tx_1.adopt_writes(Version::max()).is_err());
+
+            // tx_3 提交的写入对 tx_1 不可见，tx_1 写入 tx_4 写过的 key 会冲突，合并失败时不做任何修改
+            let tx_3 = mvcc.start_txn()?;
+            tx_3.set(b"key2", b"val2-3")?;
+            tx_3.commit()?;
+            let tx_4 = mvcc.start_txn()?;
+            tx_4.set(b"key2", b"val2-4")?;
+            tx_4.set(b"key3", b"val3-4")?;
+            assert_eq!(tx_1.adopt_writes(tx_4.version()), Err(WriteConflict.into()));
+            assert_eq!(tx_1.get(b"key3")?, None);
             tx_4.commit()?;
+            let tx_5 = mvcc.start_txn()?;
+            assert_eq!(tx_5.get(b"key2")?, Some(b"val2-4".to_vec()));
+            assert_eq!(tx_5.get(b"key3")?, Some(b"val3-4".to_vec()));
 
-            assert_eq!(tx_3.get(b"key1")?, Some(b"val1".to_vec()));
-            assert_eq!(tx_3.get(b"key2")?, Some(b"val3".to_vec()));
-            assert_eq!(tx_3.get(b"key3")?, Some(b"val4".to_vec()));
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_versioned() -> Result<()> {
+        let mvcc = Mvcc::new(MemoryStorage::new());
+        // 存储中的 Latest 记录
+        let latest_records = || -> Result<Vec<Vec<u8>>> {
+            let prefix = MvccKeyPrefix::Latest(Vec::new()).encode()?;
+            let mut storage = mvcc.storage.lock()?;
+            let records = storage
+                .scan_prefix(&prefix)
+                .map(|item| Ok(item?.0))
+                .collect::<Result<Vec<_>>>();
+            records
+        };
+
+        let tx_1 = mvcc.start_txn_non_versioned()?;
+        tx_1.set(b"a", b"1")?;
+        tx_1.set(b"b", b"2")?;
+        tx_1.set(b"a", b"3")?;
+        tx_1.commit()?;
+        // 写入覆盖同一条记录，不产生版本记录
+        assert_eq!(latest_records()?.len(), 2);
+        let stats = Mvcc::version_stats(mvcc.storage.clone())?;
+        assert_eq!((stats.total_versions, stats.txn_write_markers), (0, 0));
+
+        // 读取返回唯一的最新值，多次写入后仍然只有一条记录
+        let tx_2 = mvcc.start_txn_non_versioned()?;
+        assert_eq!(tx_2.get(b"a")?, Some(b"3".to_vec()));
+        tx_2.set(b"a", b"4")?;
+        tx_2.set(b"a", b"5")?;
+        tx_2.set(b"c", b"6")?;
+        assert_eq!(tx_2.get(b"a")?, Some(b"5".to_vec()));
+        assert_eq!(latest_records()?.len(), 3);
+
+        // 没有快照隔离：其他事务可以读到未提交的写入，但不能写入同一个 key
+        let tx_3 = mvcc.start_txn_non_versioned()?;
+        assert_eq!(tx_3.get(b"a")?, Some(b"5".to_vec()));
+        assert_eq!(tx_3.set(b"a", b"7"), Err(WriteConflict.into()));
+        tx_3.set(b"b", b"8")?;
+        tx_3.commit()?;
+
+        // 回滚恢复写入前的值，删除事务中新建的 key
+        tx_2.rollback()?;
+        let tx_4 = mvcc.start_txn_non_versioned()?;
+        assert_eq!(
+            tx_4.scan_prefix(b"")?,
+            vec![
+                (b"a".to_vec(), b"3".to_vec()),
+                (b"b".to_vec(), b"8".to_vec()),
+            ]
+        );
+
+        // 删除的 key 在提交后不再保留记录
+        tx_4.delete(b"a")?;
+        assert_eq!(tx_4.get(b"a")?, None);
+        assert_eq!(
+            tx_4.scan_range(b"a".to_vec()..)?,
+            vec![(b"b".to_vec(), b"8".to_vec())]
+        );
+        tx_4.commit()?;
+        assert_eq!(latest_records()?.len(), 1);
+
+        // 版本记录和 Latest 记录互不可见
+        let tx_5 = mvcc.start_txn()?;
+        assert_eq!(tx_5.get(b"b")?, None);
+        tx_5.set(b"b", b"9")?;
+        tx_5.commit()?;
+        let tx_6 = mvcc.start_txn_non_versioned()?;
+        assert_eq!(tx_6.get(b"b")?, Some(b"8".to_vec()));
+        assert!(tx_6.adopt_writes(tx_5.version()).is_err());
+        tx_6.commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"key", b"val1")?;
+            tx_1.set(b"key1", b"other")?;
+            tx_1.commit()?;
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"key", b"val2")?;
+            tx_2.commit()?;
+            let tx_3 = mvcc.start_txn()?;
+            tx_3.delete(b"key")?;
+            tx_3.commit()?;
+            let tx_4 = mvcc.start_txn()?;
+            tx_4.set(b"key", b"val4")?;
+            tx_4.commit()?;
+
+            // 回滚的版本被删除，未提交的版本同样会返回
+            let tx_5 = mvcc.start_txn()?;
+            tx_5.set(b"key", b"val5")?;
+            tx_5.rollback()?;
+            let tx_6 = mvcc.start_txn()?;
+            tx_6.set(b"key", b"val6")?;
+
+            // 以 key 为前缀的 key1 的版本不包括在内
+            assert_eq!(
+                Mvcc::history(mvcc.storage.clone(), b"key".to_vec())?,
+                vec![
+                    (1.into(), Some(b"val1".to_vec())),
+                    (2.into(), Some(b"val2".to_vec())),
+                    (3.into(), None),
+                    (4.into(), Some(b"val4".to_vec())),
+                    (6.into(), Some(b"val6".to_vec())),
+                ]
+            );
+            assert_eq!(Mvcc::history(mvcc.storage.clone(), b"ke".to_vec())?, vec![]);
 
             Ok(())
         });
@@ -547,34 +2819,197 @@ mod tests {
     }
 
     #[test]
-    fn test_write() -> Result<()> {
+    fn test_version_stats() -> Result<()> {
         test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            assert_eq!(
+                Mvcc::version_stats(mvcc.storage.clone())?,
+                VersionStats::default()
+            );
+
             let tx_1 = mvcc.start_txn()?;
-            tx_1.set(b"key1", b"val1")?;
-            tx_1.set(b"key2", b"val2")?;
-            tx_1.set(b"key2", b"val3")?;
-            tx_1.set(b"key3", b"val4")?;
-            tx_1.set(b"key4", b"val5")?;
+            tx_1.set(b"a", b"1")?;
+            tx_1.set(b"ab", b"1")?;
+            tx_1.set(b"b", b"1")?;
+            tx_1.commit()?;
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"a", b"2")?;
+            tx_2.delete(b"b")?;
+            tx_2.commit()?;
+
+            // 回滚的版本不计入，序列不属于版本记录
+            let tx_3 = mvcc.start_txn()?;
+            tx_3.set(b"c", b"3")?;
+            tx_3.rollback()?;
+            tx_2.next_sequence(b"seq")?;
+
+            // 未提交的事务的版本和写入记录都计入
+            let tx_4 = mvcc.start_txn()?;
+            tx_4.set(b"a", b"4")?;
+            tx_4.delete(b"ab")?;
+            tx_4.set(b"d", b"4")?;
+
+            assert_eq!(
+                Mvcc::version_stats(mvcc.storage.clone())?,
+                VersionStats {
+                    total_versions: 8,
+                    distinct_keys: 4,
+                    tombstones: 2,
+                    txn_write_markers: 3,
+                }
+            );
+
+            tx_4.commit()?;
+            assert_eq!(
+                Mvcc::version_stats(mvcc.storage.clone())?.txn_write_markers,
+                0
+            );
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"a", b"1")?;
+            tx_1.set(b"b", b"2")?;
             tx_1.commit()?;
 
+            // 移动到没有值的 key
             let tx_2 = mvcc.start_txn()?;
+            tx_2.rename(b"a", b"c", false)?;
+            assert_eq!(tx_2.get(b"a")?, None);
+            assert_eq!(tx_2.get(b"c")?, Some(b"1".to_vec()));
+
+            // 目标 key 已经有值时报错，不做任何修改，允许覆盖时覆盖目标 key
+            assert_eq!(
+                tx_2.rename(b"c", b"b", false),
+                Err(KeyExists(b"b".to_vec()).into())
+            );
+            assert_eq!(tx_2.get(b"c")?, Some(b"1".to_vec()));
+            tx_2.rename(b"c", b"b", true)?;
+            assert_eq!(tx_2.get(b"b")?, Some(b"1".to_vec()));
+
+            // 源 key 不存在或者已经被删除时报错
+            assert_eq!(
+                tx_2.rename(b"a", b"d", false),
+                Err(KeyNotFound(b"a".to_vec()).into())
+            );
+            assert_eq!(
+                tx_2.rename(b"x", b"d", false),
+                Err(KeyNotFound(b"x".to_vec()).into())
+            );
+
+            // 提交之前其他事务看不到移动，和移动并发写入任何一个 key 都会冲突
             let tx_3 = mvcc.start_txn()?;
+            assert_eq!(tx_3.get(b"a")?, Some(b"1".to_vec()));
+            assert_eq!(tx_3.get(b"b")?, Some(b"2".to_vec()));
+            assert_eq!(tx_3.rename(b"a", b"e", false), Err(WriteConflict.into()));
+            assert_eq!(tx_3.get(b"e")?, None);
+            tx_3.rollback()?;
+            tx_2.commit()?;
 
-            tx_2.set(b"key1", b"val1-1")?;
-            tx_2.set(b"key2", b"val3-1")?;
-            tx_2.set(b"key2", b"val3-2")?;
+            let tx_4 = mvcc.start_txn()?;
+            assert_eq!(tx_4.scan_prefix(b"")?, vec![(b"b".to_vec(), b"1".to_vec())]);
 
-            tx_3.set(b"key3", b"val4-1")?;
-            tx_3.set(b"key4", b"val5-1")?;
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_bounds() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            assert_eq!(
+                Mvcc::version_bounds(mvcc.storage.clone())?,
+                (Version(0), Version(0))
+            );
+
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"b", b"1")?;
+            tx_1.commit()?;
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"a", b"2")?;
+            tx_2.set(b"c", b"2")?;
+            tx_2.commit()?;
+            let tx_3 = mvcc.start_txn()?;
+            tx_3.delete(b"a")?;
+            tx_3.rollback()?;
+
+            // 最小和最大的版本分布在不同的 key 上
+            assert_eq!(
+                Mvcc::version_bounds(mvcc.storage.clone())?,
+                (Version(1), Version(2))
+            );
+
+            // 未提交事务写入的版本同样计入
+            let tx_4 = mvcc.start_txn()?;
+            tx_4.delete(b"a")?;
+            assert_eq!(
+                Mvcc::version_bounds(mvcc.storage.clone())?,
+                (Version(1), Version(4))
+            );
+            tx_4.commit()?;
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_freeze() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            assert!(mvcc.freeze()?.is_empty());
+
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"a", b"1")?;
+            tx_1.set(b"b", b"1")?;
+            tx_1.set(b"c", b"1")?;
+            tx_1.commit()?;
 
+            // 未提交的写入不可见
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"d", b"2")?;
+            let frozen = mvcc.freeze()?;
             tx_2.commit()?;
+
+            // 之后的写入不影响快照
+            let tx_3 = mvcc.start_txn()?;
+            tx_3.set(b"a", b"3")?;
+            tx_3.delete(b"b")?;
             tx_3.commit()?;
 
-            let tx_4 = mvcc.start_txn()?;
-            assert_eq!(tx_4.get(b"key1")?, Some(b"val1-1".to_vec()));
-            assert_eq!(tx_4.get(b"key2")?, Some(b"val3-2".to_vec()));
-            assert_eq!(tx_4.get(b"key3")?, Some(b"val4-1".to_vec()));
-            assert_eq!(tx_4.get(b"key4")?, Some(b"val5-1".to_vec()));
+            // 持有存储引擎的锁时仍然可以读取快照
+            let storage = mvcc.storage.lock()?;
+            assert_eq!(frozen.get(b"a"), Some(b"1".as_slice()));
+            assert_eq!(frozen.get(b"b"), Some(b"1".as_slice()));
+            assert_eq!(frozen.get(b"d"), None);
+            assert_eq!(
+                frozen
+                    .scan(b"b".to_vec()..)
+                    .map(|(key, _)| key.clone())
+                    .collect::<Vec<_>>(),
+                vec![b"b".to_vec(), b"c".to_vec()]
+            );
+            assert_eq!(frozen.len(), 3);
+            drop(storage);
+
+            // 复制使用的只读事务已经结束，新的快照能看到所有已提交的写入
+            let frozen = mvcc.freeze()?;
+            assert!(frozen.version() > tx_1.version());
+            assert_eq!(frozen.get(b"a"), Some(b"3".as_slice()));
+            assert_eq!(
+                frozen
+                    .scan(..)
+                    .map(|(key, _)| key.clone())
+                    .collect::<Vec<_>>(),
+                vec![b"a".to_vec(), b"c".to_vec(), b"d".to_vec()]
+            );
 
             Ok(())
         });
@@ -583,29 +3018,27 @@ mod tests {
     }
 
     #[test]
-    fn test_write_conflict() -> Result<()> {
+    fn test_precheck_conflicts() -> Result<()> {
         test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
             let tx_1 = mvcc.start_txn()?;
             tx_1.set(b"key1", b"val1")?;
             tx_1.set(b"key2", b"val2")?;
-            tx_1.set(b"key2", b"val3")?;
-            tx_1.set(b"key3", b"val4")?;
-            tx_1.set(b"key4", b"val5")?;
+            tx_1.set(b"key3", b"val3")?;
             tx_1.commit()?;
 
             let tx_2 = mvcc.start_txn()?;
             let tx_3 = mvcc.start_txn()?;
+            tx_3.set(b"key2", b"val2-1")?;
+            tx_3.commit()?;
 
-            tx_2.set(b"key1", b"val1-1")?;
-            tx_2.set(b"key1", b"val1-2")?;
-
-            assert_eq!(tx_3.set(b"key1", b"val1-3"), Err(WriteConflict));
-
-            let tx_4 = mvcc.start_txn()?;
-            tx_4.set(b"key5", b"val6")?;
-            tx_4.commit()?;
+            let keys = vec![b"key1".to_vec(), b"key2".to_vec(), b"key3".to_vec()];
+            assert_eq!(tx_2.precheck_conflicts(&keys)?, vec![b"key2".to_vec()]);
 
-            assert_eq!(tx_1.set(b"key5", b"val6-1"), Err(WriteConflict));
+            // 预检查不会写入任何数据
+            assert_eq!(tx_2.get(b"key1")?, Some(b"val1".to_vec()));
+            assert_eq!(tx_2.get(b"key2")?, Some(b"val2".to_vec()));
+            tx_2.set(b"key1", b"val1-1")?;
+            assert_eq!(tx_2.set(b"key2", b"val2-2"), Err(WriteConflict.into()));
 
             Ok(())
         });
@@ -657,6 +3090,99 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_visible_key_diff() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"a/1", b"val1")?;
+            tx_1.set(b"a/2", b"val2")?;
+            tx_1.set(b"a/3", b"val3")?;
+            tx_1.set(b"a/4", b"val4")?;
+            tx_1.set(b"b/2", b"other")?;
+            tx_1.set(b"b/4", b"other")?;
+            tx_1.set(b"b/5", b"other")?;
+            tx_1.set(b"c/1", b"val1")?;
+            tx_1.commit()?;
+
+            // 重叠的 key 被去掉，只在 prefix_b 下的 key 不出现在结果中
+            let tx_2 = mvcc.start_txn()?;
+            assert_eq!(
+                tx_2.visible_key_diff(b"a/", b"b/")?,
+                vec![b"1".to_vec(), b"3".to_vec()]
+            );
+            assert_eq!(tx_2.visible_key_diff(b"b/", b"a/")?, vec![b"5".to_vec()]);
+
+            // 没有交集时返回 prefix_a 下的所有 key，prefix_b 下没有 key 时同样如此
+            assert_eq!(tx_2.visible_key_diff(b"c/", b"b/")?, vec![b"1".to_vec()]);
+            assert_eq!(
+                tx_2.visible_key_diff(b"b/", b"d/")?,
+                vec![b"2".to_vec(), b"4".to_vec(), b"5".to_vec()]
+            );
+            assert!(tx_2.visible_key_diff(b"d/", b"a/")?.is_empty());
+
+            // 使用当前事务的快照，删除的 key 不可见，未提交的写入只对自身可见
+            tx_2.delete(b"b/2")?;
+            tx_2.set(b"b/3", b"other")?;
+            assert_eq!(
+                tx_2.visible_key_diff(b"a/", b"b/")?,
+                vec![b"1".to_vec(), b"2".to_vec()]
+            );
+            let tx_3 = mvcc.start_txn()?;
+            assert_eq!(
+                tx_3.visible_key_diff(b"a/", b"b/")?,
+                vec![b"1".to_vec(), b"3".to_vec()]
+            );
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_range() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"a", b"val1")?;
+            tx_1.set(b"b", b"val2")?;
+            tx_1.set(b"c", b"val3")?;
+            tx_1.set(b"d", b"val4")?;
+            tx_1.commit()?;
+
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.delete(b"c")?;
+            assert_eq!(
+                tx_2.scan_range(b"b".to_vec()..=b"d".to_vec())?,
+                vec![
+                    (b"b".to_vec(), b"val2".to_vec()),
+                    (b"d".to_vec(), b"val4".to_vec()),
+                ]
+            );
+            assert_eq!(
+                tx_2.scan_range((
+                    Bound::Excluded(b"a".to_vec()),
+                    Bound::Excluded(b"d".to_vec())
+                ))?,
+                vec![(b"b".to_vec(), b"val2".to_vec())]
+            );
+            assert_eq!(
+                tx_2.scan_range(..b"b".to_vec())?,
+                vec![(b"a".to_vec(), b"val1".to_vec())]
+            );
+            assert_eq!(tx_2.scan_range(b"c".to_vec()..)?.len(), 1);
+            assert!(tx_2.scan_range(b"d".to_vec()..b"a".to_vec())?.is_empty());
+            assert!(tx_2.scan_range(b"b".to_vec()..b"b".to_vec())?.is_empty());
+
+            // 其他事务看不到未提交的删除
+            let tx_3 = mvcc.start_txn()?;
+            assert_eq!(tx_3.scan_range(..)?.len(), 4);
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
     #[test]
     fn test_delete() -> Result<()> {
         test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
@@ -734,6 +3260,61 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_read_committed() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let tx_1 = mvcc.start_txn()?;
+            tx_1.set(b"key1", b"val1")?;
+            tx_1.commit()?;
+
+            let tx_2 = mvcc.start_txn()?;
+            tx_2.set(b"key1", b"val1-1")?;
+            tx_2.set(b"key2", b"val2")?;
+
+            let tx_rc = mvcc.start_txn_with_isolation(Isolation::ReadCommitted)?;
+            let tx_si = mvcc.start_txn()?;
+
+            // 其他事务未提交的修改不可见，并且会导致写冲突
+            assert_eq!(tx_rc.get(b"key1")?, Some(b"val1".to_vec()));
+            assert_eq!(tx_rc.scan_prefix(b"key")?.len(), 1);
+            assert_eq!(tx_rc.set(b"key1", b"val1-2"), Err(WriteConflict.into()));
+
+            // 提交之后，读已提交的事务可以读取到，快照隔离的事务仍然读取不到
+            tx_2.commit()?;
+            assert_eq!(tx_rc.get(b"key1")?, Some(b"val1-1".to_vec()));
+            assert_eq!(
+                tx_rc.scan_prefix(b"key")?,
+                vec![
+                    (b"key1".to_vec(), b"val1-1".to_vec()),
+                    (b"key2".to_vec(), b"val2".to_vec()),
+                ]
+            );
+            assert_eq!(tx_si.get(b"key1")?, Some(b"val1".to_vec()));
+            assert_eq!(tx_si.get(b"key2")?, None);
+
+            // 写入基于最新的状态检查冲突，已提交的事务不再冲突
+            tx_rc.set(b"key1", b"val1-2")?;
+            assert_eq!(tx_rc.get(b"key1")?, Some(b"val1-2".to_vec()));
+            assert_eq!(tx_si.set(b"key2", b"val2-1"), Err(WriteConflict.into()));
+
+            // 版本更新的事务提交后同样可见，但是写入它修改过的 key 仍然冲突
+            let tx_3 = mvcc.start_txn()?;
+            tx_3.set(b"key3", b"val3")?;
+            tx_3.commit()?;
+            assert_eq!(tx_rc.get(b"key3")?, Some(b"val3".to_vec()));
+            assert_eq!(tx_rc.set(b"key3", b"val3-1"), Err(WriteConflict.into()));
+            tx_rc.commit()?;
+
+            let tx_4 = mvcc.start_txn()?;
+            assert_eq!(tx_4.get(b"key1")?, Some(b"val1-2".to_vec()));
+            assert_eq!(tx_4.get(b"key3")?, Some(b"val3".to_vec()));
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
     #[test]
     fn test_phantom_read() -> Result<()> {
         test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
@@ -804,4 +3385,843 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_bulk_insert() -> Result<()> {
+        let column = |name: &str, data_type, primary_key| Column {
+            name: name.to_string(),
+            data_type,
+            nullable: false,
+            default: None,
+            primary_key,
+            auto_increment: false,
+        };
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("name", DataType::String, false),
+            ],
+        )?;
+        let row = |id: i64| vec![Value::Integer(id), Value::String(format!("user{id}"))];
+        let (codec, namespace) = (&BincodeCodec, &Namespace::default());
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let get = |id: i64| -> Result<Option<Row>> {
+                let txn = mvcc.start_txn()?;
+                let value = txn.get(&row_key(namespace, "users", &Value::Integer(id)))?;
+                txn.commit()?;
+                value
+                    .map(|value| Ok(bincode::deserialize(&value)?))
+                    .transpose()
+            };
+
+            // 负数主键的编码同样有序
+            assert_eq!(
+                mvcc.bulk_insert(&table, (-50..50).map(row), codec, namespace, true)?,
+                100
+            );
+            assert_eq!(get(-50)?, Some(row(-50)));
+            assert_eq!(get(49)?, Some(row(49)));
+            assert_eq!(get(50)?, None);
+            // 提交后没有遗留的事务写入记录
+            assert_eq!(
+                Mvcc::version_stats(mvcc.storage.clone())?.txn_write_markers,
+                0
+            );
+
+            // 主键重复或者顺序错误时整体回滚
+            for ids in [vec![100, 101, 101, 102], vec![200, 202, 201]] {
+                let first = ids[0];
+                assert!(mvcc
+                    .bulk_insert(&table, ids.into_iter().map(row), codec, namespace, true)
+                    .is_err());
+                assert_eq!(get(first)?, None);
+            }
+            // 不符合表定义的行同样回滚
+            let invalid = vec![Value::Integer(300), Value::Integer(1)];
+            assert!(mvcc
+                .bulk_insert(
+                    &table,
+                    [row(299), invalid].into_iter(),
+                    codec,
+                    namespace,
+                    false
+                )
+                .is_err());
+            assert_eq!(get(299)?, None);
+
+            // 不检查顺序时接受乱序的行
+            assert_eq!(
+                mvcc.bulk_insert(
+                    &table,
+                    [402, 400, 401].into_iter().map(row),
+                    codec,
+                    namespace,
+                    false
+                )?,
+                3
+            );
+            assert_eq!(get(400)?, Some(row(400)));
+
+            // 不维护二级索引，表有二级索引时不写入任何行
+            let mut indexed = table.clone();
+            indexed.add_index(IndexDef {
+                name: "idx_name".to_string(),
+                columns: vec!["name".to_string()],
+                unique: false,
+                predicate: None,
+            });
+            let e = mvcc
+                .bulk_insert(&indexed, [row(500)].into_iter(), codec, namespace, true)
+                .unwrap_err();
+            assert_eq!(e.code(), ErrorCode::InvalidArgument);
+            assert_eq!(get(500)?, None);
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_integer() -> Result<()> {
+        let column = |name: &str, data_type, primary_key: bool| Column {
+            name: name.to_string(),
+            data_type,
+            nullable: !primary_key,
+            default: None,
+            primary_key,
+            auto_increment: false,
+        };
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("age", DataType::Integer, false),
+                column("name", DataType::String, false),
+            ],
+        )?;
+        // 行使用传入的编码解码，不限于默认的 bincode
+        let codecs: [&dyn ValueCodec; 2] = [&BincodeCodec, &JsonCodec];
+        for codec in codecs {
+            test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+                let insert = |txn: &MvccTxn<_>, id: i64, age: Value| -> Result<()> {
+                    let row = vec![Value::Integer(id), age, Value::Null];
+                    txn.set(format!("users/{id}").as_bytes(), &codec.encode_row(&row)?)
+                };
+                let aggregate =
+                    |col: &str| mvcc.aggregate_integer(b"users/".to_vec(), &table, col, codec);
+
+                assert_eq!(aggregate("age")?, (0, 0));
+
+                // NULL 计入行数，不计入和
+                let tx_1 = mvcc.start_txn()?;
+                insert(&tx_1, 1, Value::Integer(12))?;
+                insert(&tx_1, 2, Value::Null)?;
+                insert(&tx_1, 3, Value::Integer(-2))?;
+                tx_1.commit()?;
+                assert_eq!(aggregate("age")?, (3, 10));
+                assert_eq!(aggregate("id")?, (3, 6));
+
+                // 未提交的写入不可见
+                let tx_2 = mvcc.start_txn()?;
+                insert(&tx_2, 4, Value::Integer(100))?;
+                assert_eq!(aggregate("age")?, (3, 10));
+                tx_2.rollback()?;
+
+                // 列不存在或者不是整数
+                assert!(matches!(
+                    aggregate("missing"),
+                    Err(Error::Schema(ColumnNotFound { .. }))
+                ));
+                assert!(matches!(
+                    aggregate("name"),
+                    Err(Error::Execution(TypeMismatch(_)))
+                ));
+
+                // 求和溢出
+                let tx_3 = mvcc.start_txn()?;
+                insert(&tx_3, 5, Value::Integer(i64::MAX))?;
+                tx_3.commit()?;
+                assert_eq!(
+                    aggregate("age"),
+                    Err(Overflow("SUM(age)".to_string()).into())
+                );
+
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_where() -> Result<()> {
+        let column = |name: &str, data_type, primary_key| Column {
+            name: name.to_string(),
+            data_type,
+            nullable: false,
+            default: None,
+            primary_key,
+            auto_increment: false,
+        };
+        let table = Table::new(
+            "users",
+            vec![
+                column("id", DataType::Integer, true),
+                column("age", DataType::Integer, false),
+            ],
+        )?;
+        // age < 18
+        let predicate = Expression::Operation(Operation::LessThan(
+            Box::new(Expression::Field("age".to_string())),
+            Box::new(Expression::Constant(Constant::Integer(18))),
+        ));
+        // 行使用传入的编码解码，不限于默认的 bincode
+        let codecs: [&dyn ValueCodec; 2] = [&BincodeCodec, &JsonCodec];
+        for codec in codecs {
+            test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+                let insert = |txn: &MvccTxn<_>, id: i64, age: i64| -> Result<()> {
+                    let row = vec![Value::Integer(id), Value::Integer(age)];
+                    txn.set(format!("users/{id}").as_bytes(), &codec.encode_row(&row)?)
+                };
+                let ids = || -> Result<Vec<Value>> {
+                    let txn = mvcc.start_txn()?;
+                    let rows = txn.scan_prefix(b"users/")?;
+                    txn.commit()?;
+                    rows.into_iter()
+                        .map(|(_, value)| Ok(codec.decode_row(&value)?.remove(0)))
+                        .collect()
+                };
+
+                let tx_1 = mvcc.start_txn()?;
+                for (id, age) in [(1, 12), (2, 30), (3, 17), (4, 18), (5, 45)] {
+                    insert(&tx_1, id, age)?;
+                }
+                // 前缀之外的 key 不受影响
+                tx_1.set(b"other", b"value")?;
+                tx_1.commit()?;
+
+                assert_eq!(
+                    mvcc.delete_where(b"users/".to_vec(), &table, &predicate, codec)?,
+                    2
+                );
+                assert_eq!(
+                    ids()?,
+                    vec![Value::Integer(2), Value::Integer(4), Value::Integer(5)]
+                );
+                let tx_2 = mvcc.start_txn()?;
+                assert_eq!(tx_2.get(b"other")?, Some(b"value".to_vec()));
+                tx_2.commit()?;
+
+                // 没有匹配的行
+                assert_eq!(
+                    mvcc.delete_where(b"users/".to_vec(), &table, &predicate, codec)?,
+                    0
+                );
+
+                // 其中一个匹配的 key 被未提交的事务写入，整个删除中止，其他匹配的行也保留
+                let tx_3 = mvcc.start_txn()?;
+                insert(&tx_3, 6, 10)?;
+                insert(&tx_3, 7, 11)?;
+                tx_3.commit()?;
+                let tx_4 = mvcc.start_txn()?;
+                insert(&tx_4, 7, 16)?;
+                assert!(matches!(
+                    mvcc.delete_where(b"users/".to_vec(), &table, &predicate, codec),
+                    Err(Error::Transaction(WriteConflict))
+                ));
+                tx_4.rollback()?;
+                assert_eq!(
+                    ids()?,
+                    vec![
+                        Value::Integer(2),
+                        Value::Integer(4),
+                        Value::Integer(5),
+                        Value::Integer(6),
+                        Value::Integer(7)
+                    ]
+                );
+                assert_eq!(
+                    mvcc.delete_where(b"users/".to_vec(), &table, &predicate, codec)?,
+                    2
+                );
+
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recorded_operations() -> Result<()> {
+        use recording::Operation;
+
+        let storage = Arc::new(Mutex::new(RecordingStorage::new(MemoryStorage::new())));
+        let prefix_scan = |prefix: MvccKeyPrefix| -> Result<Operation> {
+            let start = prefix.encode()?;
+            let mut end = start.clone();
+            *end.last_mut().unwrap() += 1;
+            Ok(Operation::Scan(
+                Bound::Included(start),
+                Bound::Excluded(end),
+            ))
+        };
+
+        // 开启事务：分配版本号，读取活跃事务，再将自己加入活跃事务
+        let txn = MvccTxn::begin(storage.clone())?;
+        assert_eq!(
+            storage.lock()?.operations(),
+            [
+                Operation::Get(MvccKey::NextVersion.encode()?),
+                Operation::Put(MvccKey::NextVersion.encode()?, Version(2).encode()?),
+                prefix_scan(MvccKeyPrefix::TxnActive)?,
+                Operation::Put(MvccKey::TxnActive(Version(1)).encode()?, vec![]),
+            ]
+        );
+        storage.lock()?.clear_operations();
+
+        // 写入：检查冲突，之后写入事务写入记录和版本记录
+        txn.set(b"key", b"val")?;
+        // 提交：删除事务写入记录和活跃事务记录，版本记录保留
+        txn.commit()?;
+        assert_eq!(
+            storage.lock()?.operations(),
+            [
+                Operation::Scan(
+                    Bound::Included(MvccKey::Version(b"key".to_vec(), Version(2)).encode()?),
+                    Bound::Included(MvccKey::Version(b"key".to_vec(), Version::max()).encode()?),
+                ),
+                Operation::Put(
+                    MvccKey::TxnWrite(Version(1), b"key".to_vec()).encode()?,
+                    vec![]
+                ),
+                Operation::Put(
+                    MvccKey::Version(b"key".to_vec(), Version(1)).encode()?,
+                    bincode::serialize(&Some(b"val".to_vec()))?,
+                ),
+                prefix_scan(MvccKeyPrefix::TxnWrite(Version(1)))?,
+                Operation::Delete(MvccKey::TxnWrite(Version(1), b"key".to_vec()).encode()?),
+                Operation::Delete(MvccKey::TxnActive(Version(1)).encode()?),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_only_txn() -> Result<()> {
+        use recording::Operation;
+
+        let storage = Arc::new(Mutex::new(RecordingStorage::new(MemoryStorage::new())));
+        let options = TxnOptions {
+            detect_conflicts: false,
+            ..Default::default()
+        };
+
+        // 写入时不扫描已有的版本，只写入事务写入记录和版本记录
+        let txn = MvccTxn::begin_with_options(storage.clone(), options)?;
+        storage.lock()?.clear_operations();
+        txn.set(b"log1", b"a")?;
+        assert_eq!(
+            storage.lock()?.operations(),
+            [
+                Operation::Put(
+                    MvccKey::TxnWrite(Version(1), b"log1".to_vec()).encode()?,
+                    vec![]
+                ),
+                Operation::Put(
+                    MvccKey::Version(b"log1".to_vec(), Version(1)).encode()?,
+                    bincode::serialize(&Some(b"a".to_vec()))?,
+                ),
+            ]
+        );
+
+        // 和检测冲突的事务并发写入不同的 key，提交后都可见，回滚同样撤销写入
+        let other = MvccTxn::begin(storage.clone())?;
+        other.set(b"log2", b"b")?;
+        txn.set(b"log3", b"c")?;
+        other.commit()?;
+        txn.commit()?;
+        let txn = MvccTxn::begin_with_options(storage.clone(), options)?;
+        txn.set(b"log4", b"d")?;
+        txn.rollback()?;
+
+        let txn = MvccTxn::begin(storage.clone())?;
+        assert_eq!(
+            txn.scan_prefix(b"log")?,
+            vec![
+                (b"log1".to_vec(), b"a".to_vec()),
+                (b"log2".to_vec(), b"b".to_vec()),
+                (b"log3".to_vec(), b"c".to_vec()),
+            ]
+        );
+        txn.commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_age() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let options = TxnOptions {
+                max_age: Some(Duration::from_millis(100)),
+                ..Default::default()
+            };
+
+            // 存活时间之内可以正常写入和提交
+            let txn = mvcc.start_txn_with_options(options)?;
+            txn.set(b"a", b"1")?;
+            txn.commit()?;
+
+            // 超过存活时间后写入返回错误，提交时回滚事务
+            let txn = mvcc.start_txn_with_options(options)?;
+            txn.set(b"b", b"2")?;
+            std::thread::sleep(Duration::from_millis(150));
+            let err = txn.set(b"c", b"3").unwrap_err();
+            assert_eq!(
+                err,
+                Expired {
+                    max_age: options.max_age.unwrap()
+                }
+                .into()
+            );
+            assert_eq!(err.code(), ErrorCode::TransactionExpired);
+            // 移动 key 同样是写入
+            assert_eq!(
+                txn.rename(b"b", b"d", false).unwrap_err().code(),
+                ErrorCode::TransactionExpired
+            );
+            assert_eq!(
+                txn.commit().unwrap_err().code(),
+                ErrorCode::TransactionExpired
+            );
+
+            // 过期的事务已经不在活跃事务列表中，写入的数据也被撤销
+            let txn = mvcc.start_txn()?;
+            assert!(txn.snapshot.lock()?.active_versions.is_empty());
+            assert_eq!(txn.get(b"a")?, Some(b"1".to_vec()));
+            assert_eq!(txn.get(b"b")?, None);
+            assert_eq!(txn.get(b"d")?, None);
+            txn.commit()?;
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_active_transactions() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let active = || Mvcc::active_transactions(mvcc.storage.clone());
+            assert!(active()?.is_empty());
+
+            let plain = mvcc.start_txn()?;
+            let labeled = mvcc.start_txn_labeled("vacuum")?;
+            let timed = mvcc.start_txn_with_options(TxnOptions {
+                max_age: Some(Duration::from_secs(60)),
+                ..Default::default()
+            })?;
+            labeled.set(b"a", b"1")?;
+
+            // 标签和版本一起返回，开始时间只在限制存活时间时记录
+            let transactions = active()?;
+            assert_eq!(
+                transactions
+                    .iter()
+                    .map(|txn| (txn.version, txn.label.as_deref()))
+                    .collect::<Vec<_>>(),
+                [
+                    (plain.version(), None),
+                    (labeled.version(), Some("vacuum")),
+                    (timed.version(), None),
+                ]
+            );
+            assert!(transactions[..2].iter().all(|txn| txn.started_at.is_none()));
+            let started_at = transactions[2].started_at.unwrap();
+            assert!(started_at <= SystemTime::now());
+            assert!(started_at + Duration::from_secs(60) > SystemTime::now());
+
+            // 结束的事务不再出现
+            labeled.commit()?;
+            plain.rollback()?;
+            assert_eq!(
+                active()?
+                    .into_iter()
+                    .map(|txn| txn.version)
+                    .collect::<Vec<_>>(),
+                [timed.version()]
+            );
+            timed.commit()?;
+            assert!(active()?.is_empty());
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_in_batches() -> Result<()> {
+        use recording::Operation;
+
+        let storage = Arc::new(Mutex::new(RecordingStorage::new(MemoryStorage::new())));
+        let count = TXN_WRITE_BATCH_SIZE * 2 + 10;
+        let write = |txn: &MvccTxn<_>| -> Result<()> {
+            for i in 0..count {
+                txn.set(format!("key{i:05}").as_bytes(), b"val")?;
+            }
+            Ok(())
+        };
+        // 每次扫描之后、下一次扫描之前删除的 TxnWrite 记录数量
+        let batches = |operations: &[Operation]| {
+            let mut batches = Vec::new();
+            for op in operations {
+                match op {
+                    Operation::Scan(..) => batches.push(0),
+                    Operation::Delete(key)
+                        if matches!(MvccKey::decode(key), Ok(MvccKey::TxnWrite(..))) =>
+                    {
+                        *batches.last_mut().unwrap() += 1
+                    }
+                    _ => {}
+                }
+            }
+            batches
+        };
+
+        // 提交时分批扫描，每批不超过上限，下一批从上一批最后一个 key 之后开始
+        let txn = MvccTxn::begin(storage.clone())?;
+        write(&txn)?;
+        storage.lock()?.clear_operations();
+        assert_eq!(txn.commit_with_log()?.len(), count);
+        let operations = storage.lock()?.operations().to_vec();
+        assert_eq!(
+            batches(&operations),
+            [TXN_WRITE_BATCH_SIZE, TXN_WRITE_BATCH_SIZE, 10]
+        );
+        let last = MvccKey::TxnWrite(
+            Version(1),
+            format!("key{:05}", TXN_WRITE_BATCH_SIZE - 1).into_bytes(),
+        )
+        .encode()?;
+        assert!(operations
+            .iter()
+            .any(|op| matches!(op, Operation::Scan(Bound::Excluded(start), _) if *start == last)));
+        assert_eq!(Mvcc::version_stats(storage.clone())?.txn_write_markers, 0);
+
+        // 回滚同样分批删除 TxnWrite 记录和版本记录
+        let txn = MvccTxn::begin(storage.clone())?;
+        write(&txn)?;
+        storage.lock()?.clear_operations();
+        txn.rollback()?;
+        assert_eq!(
+            batches(storage.lock()?.operations()),
+            [TXN_WRITE_BATCH_SIZE, TXN_WRITE_BATCH_SIZE, 10]
+        );
+        assert_eq!(Mvcc::version_stats(storage.clone())?.txn_write_markers, 0);
+        let txn = MvccTxn::begin(storage.clone())?;
+        assert_eq!(txn.scan_prefix(b"key")?.len(), count);
+        txn.commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_clock() -> Result<()> {
+        use recording::Operation;
+
+        /// 按顺序返回固定版本号的时钟
+        struct FixedClock(Mutex<std::vec::IntoIter<u64>>);
+
+        impl VersionClock for FixedClock {
+            fn next(&self) -> Version {
+                Version(self.0.lock().unwrap().next().expect("clock exhausted"))
+            }
+        }
+
+        let storage = Arc::new(Mutex::new(RecordingStorage::new(MemoryStorage::new())));
+        let clock: Arc<dyn VersionClock> =
+            Arc::new(FixedClock(Mutex::new(vec![5, 9, 12, 15, 20].into_iter())));
+
+        // 版本号来自时钟，不读写 NextVersion
+        let txn = MvccTxn::begin_with_clock(storage.clone(), clock.clone())?;
+        assert_eq!(txn.version(), Version(5));
+        let next_version = MvccKey::NextVersion.encode()?;
+        assert!(!storage.lock()?.operations().iter().any(|op| matches!(
+            op,
+            Operation::Get(key) | Operation::Put(key, _) if *key == next_version
+        )));
+        txn.set(b"key", b"val1")?;
+        txn.commit()?;
+
+        // 之后的事务按照时钟的版本号判断可见性
+        let txn1 = MvccTxn::begin_with_clock(storage.clone(), clock.clone())?;
+        let txn2 = MvccTxn::begin_with_clock(storage.clone(), clock.clone())?;
+        assert_eq!((txn1.version(), txn2.version()), (Version(9), Version(12)));
+        assert_eq!(txn1.get(b"key")?, Some(b"val1".to_vec()));
+        txn1.set(b"key", b"val2")?;
+        txn1.commit()?;
+        assert_eq!(txn2.get(b"key")?, Some(b"val1".to_vec()));
+        assert_eq!(
+            Mvcc::history(storage.clone(), b"key".to_vec())?,
+            vec![
+                (Version(5), Some(b"val1".to_vec())),
+                (Version(9), Some(b"val2".to_vec())),
+            ]
+        );
+        assert_eq!(storage.lock()?.get(&next_version)?, None);
+        txn2.rollback()?;
+
+        // 读已提交的事务刷新快照时能看到时钟分配版本号的事务的提交，无论它自己的版本号来自哪里
+        let rc_counter = MvccTxn::builder(storage.clone())
+            .isolation(Isolation::ReadCommitted)
+            .begin()?;
+        let rc_clock = MvccTxn::builder(storage.clone())
+            .isolation(Isolation::ReadCommitted)
+            .clock(clock.clone())
+            .label("reader")
+            .begin()?;
+        assert_eq!(
+            (rc_counter.version(), rc_clock.version()),
+            (Version(1), Version(15))
+        );
+        let active = Mvcc::active_transactions(storage.clone())?;
+        assert!(active
+            .iter()
+            .any(|txn| txn.version == Version(15) && txn.label.as_deref() == Some("reader")));
+
+        let writer = MvccTxn::begin_with_clock(storage.clone(), clock.clone())?;
+        writer.set(b"key", b"val3")?;
+        assert_eq!(rc_clock.get(b"key")?, Some(b"val2".to_vec()));
+        writer.commit()?;
+        assert_eq!(rc_counter.get(b"key")?, Some(b"val3".to_vec()));
+        assert_eq!(rc_clock.get(b"key")?, Some(b"val3".to_vec()));
+        rc_counter.commit()?;
+        rc_clock.commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_visible_chunked() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let txn = mvcc.start_txn()?;
+            for i in 0..1000 {
+                txn.set(format!("key{i:04}").as_bytes(), &i.to_string().into_bytes())?;
+            }
+            // 前缀之外的 key 和已经删除的 key 不出现在结果中
+            txn.set(b"other", b"value")?;
+            txn.delete(b"key0500")?;
+            txn.commit()?;
+
+            let full = mvcc.start_txn()?;
+            let expected = full.scan_prefix(b"key")?;
+            assert_eq!(expected.len(), 999);
+
+            let mut scan = mvcc.scan_visible_chunked(b"key".to_vec(), 64)?;
+            let mut chunks = Vec::new();
+            loop {
+                let chunk = scan.next_chunk()?;
+                if chunk.is_empty() {
+                    break;
+                }
+                assert!(chunk.len() <= 64);
+                chunks.push(chunk);
+
+                // 两批之间没有持有锁，其他事务可以写入，但快照已经固定，写入不可见
+                let writer = mvcc.start_txn()?;
+                writer.set(format!("key{:04}", chunks.len()).as_bytes(), b"new")?;
+                writer.set(format!("key9{:03}", chunks.len()).as_bytes(), b"new")?;
+                writer.commit()?;
+            }
+            assert_eq!(chunks.len(), 16);
+            assert_eq!(chunks.concat(), expected);
+            assert!(scan.next_chunk()?.is_empty());
+            full.commit()?;
+
+            assert!(mvcc.scan_visible_chunked(b"key".to_vec(), 0).is_err());
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_visible_keys() -> Result<()> {
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let txn = mvcc.start_txn()?;
+            for i in 0..100 {
+                txn.set(format!("key{i:02}").as_bytes(), &vec![i as u8; 1024])?;
+            }
+            txn.set(b"other", b"value")?;
+            txn.commit()?;
+
+            // 删除的 key、重新写入的 key 和其他事务未提交的写入
+            let txn = mvcc.start_txn()?;
+            txn.delete(b"key10")?;
+            txn.delete(b"key20")?;
+            txn.set(b"key20", b"again")?;
+            txn.commit()?;
+            let active = mvcc.start_txn()?;
+            active.set(b"key50x", b"uncommitted")?;
+            active.delete(b"key51")?;
+
+            let keys = mvcc.scan_visible_keys(b"key".to_vec())?;
+            let full = mvcc.start_txn()?;
+            let expected = full
+                .scan_prefix(b"key")?
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>();
+            assert_eq!(keys.len(), 99);
+            assert_eq!(keys, expected);
+            assert_eq!(full.scan_prefix_keys(b"key")?, expected);
+
+            // 事务自己的写入可见
+            assert_eq!(active.scan_prefix_keys(b"key5")?.len(), 10);
+            full.commit()?;
+            active.rollback()?;
+
+            Ok(())
+        });
+
+        // 只扫描一次数据，不读取任何 key 的值
+        let mvcc = Mvcc::new(RecordingStorage::new(MemoryStorage::new()));
+        let txn = mvcc.start_txn()?;
+        txn.set(b"a", &[1; 4096])?;
+        txn.set(b"b", &[2; 4096])?;
+        txn.commit()?;
+        mvcc.storage.lock()?.clear_operations();
+        assert_eq!(
+            mvcc.scan_visible_keys(Vec::new())?,
+            [b"a".to_vec(), b"b".to_vec()]
+        );
+        let storage = mvcc.storage.lock()?;
+        let data_scan = recording::Operation::Scan(
+            Bound::Included(MvccKeyPrefix::Version(Vec::new()).encode()?),
+            prefix_end(&MvccKeyPrefix::Version(Vec::new()).encode()?),
+        );
+        assert_eq!(
+            storage
+                .operations()
+                .iter()
+                .filter(|op| **op == data_scan)
+                .count(),
+            1
+        );
+        assert!(storage.operations().iter().all(|op| !matches!(
+            op,
+            recording::Operation::Get(key) if key != &MvccKey::NextVersion.encode().unwrap()
+        )));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_empty_prefix() -> Result<()> {
+        // 空前缀的编码只有 Version 的枚举索引，和其他类型的 key 不重叠
+        assert_eq!(MvccKeyPrefix::Version(Vec::new()).encode()?, [0, 0, 0, 3]);
+
+        test_all_storage!(|mvcc: &Mvcc<_>| -> Result<()> {
+            let txn = mvcc.start_txn()?;
+            txn.set(b"a", b"1")?;
+            txn.set(b"b", b"2")?;
+            txn.commit()?;
+
+            // 存储引擎中同时存在 NextVersion、TxnActive 和 TxnWrite 记录
+            let active = mvcc.start_txn()?;
+            active.set(b"c", b"3")?;
+
+            let txn = mvcc.start_txn()?;
+            let expected = vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"b".to_vec(), b"2".to_vec()),
+            ];
+            assert_eq!(txn.scan_prefix(b"")?, expected);
+            assert_eq!(txn.scan_range(..)?, expected);
+            let mut scan = mvcc.scan_visible_chunked(Vec::new(), 1)?;
+            assert_eq!(
+                [scan.next_chunk()?, scan.next_chunk()?, scan.next_chunk()?].concat(),
+                expected
+            );
+            txn.commit()?;
+            active.rollback()?;
+
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_operations() -> Result<()> {
+        use super::Operation as Op;
+
+        let primary = Mvcc::new(MemoryStorage::new());
+        let replica = Mvcc::new(MemoryStorage::new());
+        let mut log = Vec::new();
+
+        let txn = primary.start_txn()?;
+        txn.set(b"a", b"1")?;
+        txn.set(b"b", b"2")?;
+        txn.set(b"a", b"3")?;
+        txn.delete(b"c")?;
+        let ops = txn.commit_with_log()?;
+        // 每个 key 只记录最后一次写入，删除不存在的 key 同样记录
+        assert_eq!(
+            ops,
+            vec![
+                Op::Set(b"a".to_vec(), b"3".to_vec()),
+                Op::Set(b"b".to_vec(), b"2".to_vec()),
+                Op::Delete(b"c".to_vec()),
+            ]
+        );
+        log.push(ops);
+
+        // 回滚的事务没有日志
+        let txn = primary.start_txn()?;
+        txn.set(b"d", b"4")?;
+        txn.rollback()?;
+
+        let txn = primary.start_txn()?;
+        txn.delete(b"a")?;
+        txn.set(b"e", b"5")?;
+        log.push(txn.commit_with_log()?);
+
+        for ops in &log {
+            replica.apply_operations(ops)?;
+        }
+        let visible = |mvcc: &Mvcc<MemoryStorage>| -> Result<Vec<(Key, Vec<u8>)>> {
+            let txn = mvcc.start_txn()?;
+            let result = txn.scan_prefix(b"")?;
+            txn.commit()?;
+            Ok(result)
+        };
+        assert_eq!(visible(&replica)?, visible(&primary)?);
+
+        // 和副本上的活跃事务冲突时整体不重放
+        let txn = replica.start_txn()?;
+        txn.set(b"e", b"6")?;
+        let ops = vec![
+            Op::Set(b"f".to_vec(), b"7".to_vec()),
+            Op::Delete(b"e".to_vec()),
+        ];
+        assert_eq!(replica.apply_operations(&ops), Err(WriteConflict.into()));
+        txn.rollback()?;
+        assert_eq!(visible(&replica)?, visible(&primary)?);
+        replica.apply_operations(&ops)?;
+        assert_eq!(
+            visible(&replica)?,
+            vec![
+                (b"b".to_vec(), b"2".to_vec()),
+                (b"f".to_vec(), b"7".to_vec()),
+            ]
+        );
+
+        Ok(())
+    }
 }
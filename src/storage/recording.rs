@@ -0,0 +1,116 @@
+use std::ops::{Bound, RangeBounds};
+
+use super::Storage;
+use crate::Result;
+
+/// 对存储引擎的一次调用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Get(Vec<u8>),
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    /// 扫描的范围，`scan_prefix` 同样记录为对应的范围
+    Scan(Bound<Vec<u8>>, Bound<Vec<u8>>),
+}
+
+/// 记录所有操作的存储引擎
+///
+/// 将调用转发给内部的存储引擎 `S`，同时按照调用顺序记录每次操作，用于在测试中断言上层实际执行的操作。
+pub struct RecordingStorage<S: Storage> {
+    inner: S,
+    operations: Vec<Operation>,
+}
+
+impl<S: Storage> RecordingStorage<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            operations: Vec::new(),
+        }
+    }
+
+    /// 按照调用顺序返回记录的所有操作
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// 清空记录的操作，之后只记录新的操作
+    pub fn clear_operations(&mut self) {
+        self.operations.clear();
+    }
+
+    /// 返回内部的存储引擎
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Storage> Storage for RecordingStorage<S> {
+    type Iterator<'a>
+        = S::Iterator<'a>
+    where
+        Self: 'a;
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.operations.push(Operation::Get(key.to_vec()));
+        self.inner.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.operations
+            .push(Operation::Put(key.to_vec(), value.to_vec()));
+        self.inner.put(key, value)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.operations.push(Operation::Delete(key.to_vec()));
+        self.inner.delete(key)
+    }
+
+    fn scan<R>(&mut self, range: R) -> Self::Iterator<'_>
+    where
+        R: RangeBounds<Vec<u8>>,
+    {
+        self.operations.push(Operation::Scan(
+            range.start_bound().cloned(),
+            range.end_bound().cloned(),
+        ));
+        self.inner.scan(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn test_recording_storage() -> Result<()> {
+        let mut storage = RecordingStorage::new(MemoryStorage::new());
+        storage.put(b"a", b"1")?;
+        storage.put(b"ab", b"2")?;
+        assert_eq!(storage.get(b"a")?, Some(b"1".to_vec()));
+        assert_eq!(storage.scan_prefix(b"a").count(), 2);
+        storage.delete(b"a")?;
+
+        assert_eq!(
+            storage.operations(),
+            [
+                Operation::Put(b"a".to_vec(), b"1".to_vec()),
+                Operation::Put(b"ab".to_vec(), b"2".to_vec()),
+                Operation::Get(b"a".to_vec()),
+                Operation::Scan(
+                    Bound::Included(b"a".to_vec()),
+                    Bound::Excluded(b"b".to_vec())
+                ),
+                Operation::Delete(b"a".to_vec()),
+            ]
+        );
+
+        storage.clear_operations();
+        assert!(storage.operations().is_empty());
+        assert_eq!(storage.into_inner().get(b"ab")?, Some(b"2".to_vec()));
+
+        Ok(())
+    }
+}